@@ -29,6 +29,7 @@ use tracing::{debug, error};
 use darkfi::{
     impl_p2p_message,
     net::{
+        bandwidth::TrafficPriority,
         metering::MeteringConfiguration,
         protocol::protocol_generic::{
             ProtocolGenericAction, ProtocolGenericHandler, ProtocolGenericHandlerPtr,
@@ -66,7 +67,8 @@ impl_p2p_message!(
         threshold: 50,
         sleep_step: 500,
         expiry_time: NanoTimestamp::from_secs(5),
-    }
+    },
+    TrafficPriority::High
 );
 
 /// Atomic pointer to the `ProtocolProposal` handler.
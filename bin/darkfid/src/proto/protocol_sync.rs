@@ -25,6 +25,7 @@ use darkfi::{
     blockchain::{BlockInfo, Header, HeaderHash},
     impl_p2p_message,
     net::{
+        bandwidth::TrafficPriority,
         metering::MeteringConfiguration,
         protocol::protocol_generic::{
             ProtocolGenericAction, ProtocolGenericHandler, ProtocolGenericHandlerPtr,
@@ -54,6 +55,10 @@ const PROTOCOL_SYNC_METERING_CONFIGURATION: MeteringConfiguration = MeteringConf
     expiry_time: NanoTimestamp::from_secs(5),
 };
 
+// Requests and small control responses are sent with high priority, so
+// they are never delayed by the upload limit. Responses carrying block
+// or header batches use normal priority and respect the limit.
+
 /// Structure represening a request to ask a node for their current
 /// canonical(confirmed) tip block hash, if they are synced. We also
 /// include our own tip, so they can verify we follow the same sequence.
@@ -63,7 +68,14 @@ pub struct TipRequest {
     pub tip: HeaderHash,
 }
 
-impl_p2p_message!(TipRequest, "tiprequest", 32, 1, PROTOCOL_SYNC_METERING_CONFIGURATION);
+impl_p2p_message!(
+    TipRequest,
+    "tiprequest",
+    32,
+    1,
+    PROTOCOL_SYNC_METERING_CONFIGURATION,
+    TrafficPriority::High
+);
 
 /// Structure representing the response to `TipRequest`,
 /// containing a boolean flag to indicate if we are synced,
@@ -78,7 +90,14 @@ pub struct TipResponse {
     pub hash: Option<HeaderHash>,
 }
 
-impl_p2p_message!(TipResponse, "tipresponse", 39, 1, PROTOCOL_SYNC_METERING_CONFIGURATION);
+impl_p2p_message!(
+    TipResponse,
+    "tipresponse",
+    39,
+    1,
+    PROTOCOL_SYNC_METERING_CONFIGURATION,
+    TrafficPriority::High
+);
 
 /// Structure represening a request to ask a node for up to `BATCH` headers before
 /// the provided header height.
@@ -93,7 +112,8 @@ impl_p2p_message!(
     "headersyncrequest",
     4,
     1,
-    PROTOCOL_SYNC_METERING_CONFIGURATION
+    PROTOCOL_SYNC_METERING_CONFIGURATION,
+    TrafficPriority::High
 );
 
 /// Structure representing the response to `HeaderSyncRequest`,
@@ -109,7 +129,8 @@ impl_p2p_message!(
     "headersyncresponse",
     72121, // We leave some headroom for merge mining data
     1,
-    PROTOCOL_SYNC_METERING_CONFIGURATION
+    PROTOCOL_SYNC_METERING_CONFIGURATION,
+    TrafficPriority::Normal
 );
 
 /// Structure represening a request to ask a node for up to`BATCH` blocks
@@ -120,7 +141,14 @@ pub struct SyncRequest {
     pub headers: Vec<HeaderHash>,
 }
 
-impl_p2p_message!(SyncRequest, "syncrequest", 641, 1, PROTOCOL_SYNC_METERING_CONFIGURATION);
+impl_p2p_message!(
+    SyncRequest,
+    "syncrequest",
+    641,
+    1,
+    PROTOCOL_SYNC_METERING_CONFIGURATION,
+    TrafficPriority::High
+);
 
 /// Structure representing the response to `SyncRequest`,
 /// containing up to `BATCH` blocks after the requested block height.
@@ -130,7 +158,14 @@ pub struct SyncResponse {
     pub blocks: Vec<BlockInfo>,
 }
 
-impl_p2p_message!(SyncResponse, "syncresponse", 0, 1, PROTOCOL_SYNC_METERING_CONFIGURATION);
+impl_p2p_message!(
+    SyncResponse,
+    "syncresponse",
+    0,
+    1,
+    PROTOCOL_SYNC_METERING_CONFIGURATION,
+    TrafficPriority::Normal
+);
 
 /// Structure represening a request to ask a node a fork sequence.
 /// If we include a specific fork tip, they have to return its sequence,
@@ -145,7 +180,14 @@ pub struct ForkSyncRequest {
     pub fork_tip: Option<HeaderHash>,
 }
 
-impl_p2p_message!(ForkSyncRequest, "forksyncrequest", 65, 1, PROTOCOL_SYNC_METERING_CONFIGURATION);
+impl_p2p_message!(
+    ForkSyncRequest,
+    "forksyncrequest",
+    65,
+    1,
+    PROTOCOL_SYNC_METERING_CONFIGURATION,
+    TrafficPriority::High
+);
 
 /// Structure representing the response to `ForkSyncRequest`,
 /// containing the requested fork sequence, up to `BATCH` proposals.
@@ -155,7 +197,14 @@ pub struct ForkSyncResponse {
     pub proposals: Vec<Proposal>,
 }
 
-impl_p2p_message!(ForkSyncResponse, "forksyncresponse", 0, 1, PROTOCOL_SYNC_METERING_CONFIGURATION);
+impl_p2p_message!(
+    ForkSyncResponse,
+    "forksyncresponse",
+    0,
+    1,
+    PROTOCOL_SYNC_METERING_CONFIGURATION,
+    TrafficPriority::Normal
+);
 
 /// Structure represening a request to ask a node a fork header for the
 /// requested height. The fork is identified by the provided header hash.
@@ -172,7 +221,8 @@ impl_p2p_message!(
     "forkheaderhashrequest",
     36,
     1,
-    PROTOCOL_SYNC_METERING_CONFIGURATION
+    PROTOCOL_SYNC_METERING_CONFIGURATION,
+    TrafficPriority::High
 );
 
 /// Structure representing the response to `ForkHeaderHashRequest`,
@@ -188,7 +238,8 @@ impl_p2p_message!(
     "forkheaderhashresponse",
     33,
     1,
-    PROTOCOL_SYNC_METERING_CONFIGURATION
+    PROTOCOL_SYNC_METERING_CONFIGURATION,
+    TrafficPriority::High
 );
 
 /// Structure represening a request to ask a node for up to `BATCH`
@@ -207,7 +258,8 @@ impl_p2p_message!(
     "forkheadersrequest",
     673,
     1,
-    PROTOCOL_SYNC_METERING_CONFIGURATION
+    PROTOCOL_SYNC_METERING_CONFIGURATION,
+    TrafficPriority::High
 );
 
 /// Structure representing the response to `ForkHeadersRequest`,
@@ -223,7 +275,8 @@ impl_p2p_message!(
     "forkheadersresponse",
     72121, // We leave some headroom for merge mining data
     1,
-    PROTOCOL_SYNC_METERING_CONFIGURATION
+    PROTOCOL_SYNC_METERING_CONFIGURATION,
+    TrafficPriority::Normal
 );
 
 /// Structure represening a request to ask a node for up to `BATCH`
@@ -242,7 +295,8 @@ impl_p2p_message!(
     "forkproposalsrequest",
     673,
     1,
-    PROTOCOL_SYNC_METERING_CONFIGURATION
+    PROTOCOL_SYNC_METERING_CONFIGURATION,
+    TrafficPriority::High
 );
 
 /// Structure representing the response to `ForkProposalsRequest`,
@@ -258,7 +312,8 @@ impl_p2p_message!(
     "forkproposalsresponse",
     0,
     1,
-    PROTOCOL_SYNC_METERING_CONFIGURATION
+    PROTOCOL_SYNC_METERING_CONFIGURATION,
+    TrafficPriority::Normal
 );
 
 /// Atomic pointer to the `ProtocolSync` handler.
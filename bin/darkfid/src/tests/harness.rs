@@ -112,7 +112,7 @@ impl Harness {
 
         // Generate validators
         let mut settings = Settings {
-            active_profiles: vec!["tcp+tls".to_string()],
+            active_profiles: vec!["mem".to_string()],
             localnet: true,
             inbound_connections: 3,
             ..Default::default()
@@ -54,8 +54,8 @@ async fn sync_blocks_real(ex: Arc<Executor<'static>>) -> Result<()> {
         pow_fixed_difficulty: pow_fixed_difficulty.clone(),
         confirmation_threshold: 3,
         max_forks: 8,
-        alice_url: "tcp+tls://127.0.0.1:18340".to_string(),
-        bob_url: "tcp+tls://127.0.0.1:18341".to_string(),
+        alice_url: "mem://alice.darkfid:18340".to_string(),
+        bob_url: "mem://bob.darkfid:18341".to_string(),
     };
     let th = Harness::new(config, true, &ex).await?;
 
@@ -111,12 +111,12 @@ async fn sync_blocks_real(ex: Arc<Executor<'static>>) -> Result<()> {
 
     // We are going to create a third node and try to sync from Bob
     let mut settings = Settings {
-        active_profiles: vec!["tcp+tls".to_string()],
+        active_profiles: vec!["mem".to_string()],
         localnet: true,
         inbound_connections: 3,
         ..Default::default()
     };
-    let charlie_url = Url::parse("tcp+tls://127.0.0.1:18342")?;
+    let charlie_url = Url::parse("mem://charlie.darkfid:18342")?;
     settings.inbound_addrs = vec![charlie_url];
     let bob_url = th.bob.p2p_handler.p2p.settings().read().await.inbound_addrs[0].clone();
     settings.peers = vec![bob_url];
@@ -38,8 +38,8 @@ async fn sync_forks_real(ex: Arc<Executor<'static>>) -> Result<()> {
         pow_fixed_difficulty: pow_fixed_difficulty.clone(),
         confirmation_threshold: 6,
         max_forks: 8,
-        alice_url: "tcp+tls://127.0.0.1:18440".to_string(),
-        bob_url: "tcp+tls://127.0.0.1:18441".to_string(),
+        alice_url: "mem://alice.darkfid:18440".to_string(),
+        bob_url: "mem://bob.darkfid:18441".to_string(),
     };
     let th = Harness::new(config, true, &ex).await?;
 
@@ -67,13 +67,13 @@ async fn sync_forks_real(ex: Arc<Executor<'static>>) -> Result<()> {
 
     // We are going to create a third node and try to sync from Bob
     let mut settings = Settings {
-        active_profiles: vec!["tcp+tls".to_string()],
+        active_profiles: vec!["mem".to_string()],
         localnet: true,
         inbound_connections: 3,
         ..Default::default()
     };
 
-    let charlie_url = Url::parse("tcp+tls://127.0.0.1:18442")?;
+    let charlie_url = Url::parse("mem://charlie.darkfid:18442")?;
     settings.inbound_addrs = vec![charlie_url];
     let bob_url = th.bob.p2p_handler.p2p.settings().read().await.inbound_addrs[0].clone();
     settings.peers = vec![bob_url];
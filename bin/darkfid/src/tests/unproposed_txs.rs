@@ -112,8 +112,8 @@ fn test_unproposed_txs_within_gas_limit() -> Result<()> {
                 // Receive number of unproposed txs within gas limit
                 let (num_unproposed_txs, _) = simulate_unproposed_txs(
                     5,
-                    "tcp+tls://127.0.0.1:18540".to_string(),
-                    "tcp+tls://127.0.0.1:18541".to_string(),
+                    "mem://alice.darkfid:18540".to_string(),
+                    "mem://bob.darkfid:18541".to_string(),
                     ex.clone(),
                 )
                 .await
@@ -151,8 +151,8 @@ fn test_unproposed_txs_exceeding_gas_limit() -> Result<()> {
                 // Receive total gas used by simulating a number of transactions that will exceed gas limit
                 let (num_unproposed_txs, total_gas_used) = simulate_unproposed_txs(
                     135,
-                    "tcp+tls://127.0.0.1:18640".to_string(),
-                    "tcp+tls://127.0.0.1:18641".to_string(),
+                    "mem://alice.darkfid:18640".to_string(),
+                    "mem://bob.darkfid:18641".to_string(),
                     ex.clone(),
                 )
                 .await
@@ -21,6 +21,9 @@
 /// ChaCha box, used for channel encryption, and optionally DM encryption.
 pub mod saltbox;
 
+/// Double ratchet, used for forward-secret DM encryption.
+pub mod ratchet;
+
 /// bcrypt utilities
 pub mod bcrypt;
 
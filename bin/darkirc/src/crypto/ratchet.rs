@@ -0,0 +1,429 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2026 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! X3DH-style key agreement and the double ratchet, as described in
+//! <https://signal.org/docs/specifications/x3dh/> and
+//! <https://signal.org/docs/specifications/doubleratchet/>.
+//!
+//! The identity keys are the static X25519 keys configured per contact.
+//! Prekey bundles are only ever exchanged inside the static `ChaChaBox`
+//! of a contact pair, which authenticates them, so no prekey signatures
+//! are needed. For the same reason there are no one-time prekeys: a
+//! bundle can only be used by the one contact it was encrypted for.
+//!
+//! Blake3 is used as the KDF, and ChaCha20-Poly1305 for message encryption.
+
+use chacha20poly1305::{
+    aead::{Aead, KeyInit, Payload},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use darkfi_serial::{async_trait, serialize, SerialDecodable, SerialEncodable};
+use rand::rngs::OsRng;
+use x25519_dalek::{PublicKey, StaticSecret};
+
+/// Maximum number of message keys skipped in a single chain
+pub const MAX_SKIP: u32 = 1000;
+
+/// Maximum number of skipped message keys kept around for late messages
+const MAX_SKIPPED_KEYS: usize = 2000;
+
+const KDF_X3DH: &str = "darkirc 2024 x3dh shared secret";
+const KDF_ROOT: &str = "darkirc 2024 ratchet root key";
+const KDF_CHAIN: &str = "darkirc 2024 ratchet chain key";
+const KDF_MESSAGE_KEY: &str = "darkirc 2024 ratchet message key";
+const KDF_MESSAGE_NONCE: &str = "darkirc 2024 ratchet message nonce";
+
+/// X25519 Diffie-Hellman. Returns `None` for non-contributory results,
+/// i.e. when the public key is a low order point.
+fn dh(secret: &[u8; 32], public: &[u8; 32]) -> Option<[u8; 32]> {
+    let shared = StaticSecret::from(*secret).diffie_hellman(&PublicKey::from(*public));
+    shared.was_contributory().then(|| shared.to_bytes())
+}
+
+/// Root KDF, returns the next root key and a new chain key
+fn kdf_rk(root_key: &[u8; 32], dh_out: &[u8; 32]) -> ([u8; 32], [u8; 32]) {
+    let input = [root_key.as_slice(), dh_out.as_slice()].concat();
+    (blake3::derive_key(KDF_ROOT, &input), blake3::derive_key(KDF_CHAIN, &input))
+}
+
+/// Chain KDF, returns the next chain key and a message key
+fn kdf_ck(chain_key: &[u8; 32]) -> ([u8; 32], [u8; 32]) {
+    let next = blake3::keyed_hash(chain_key, &[0x02]);
+    let message_key = blake3::keyed_hash(chain_key, &[0x01]);
+    (*next.as_bytes(), *message_key.as_bytes())
+}
+
+/// Every message key is only used once, so the nonce can be derived
+/// from it along with the encryption key.
+fn message_cipher(message_key: &[u8; 32]) -> (ChaCha20Poly1305, [u8; 12]) {
+    let key = blake3::derive_key(KDF_MESSAGE_KEY, message_key);
+    let nonce = blake3::derive_key(KDF_MESSAGE_NONCE, message_key);
+    let mut nonce_bytes = [0u8; 12];
+    nonce_bytes.copy_from_slice(&nonce[..12]);
+    (ChaCha20Poly1305::new(Key::from_slice(&key)), nonce_bytes)
+}
+
+fn seal(message_key: &[u8; 32], ad: &[u8], plaintext: &[u8]) -> Vec<u8> {
+    let (cipher, nonce) = message_cipher(message_key);
+    cipher.encrypt(Nonce::from_slice(&nonce), Payload { msg: plaintext, aad: ad }).unwrap()
+}
+
+fn open(message_key: &[u8; 32], ad: &[u8], ciphertext: &[u8]) -> Option<Vec<u8>> {
+    let (cipher, nonce) = message_cipher(message_key);
+    cipher.decrypt(Nonce::from_slice(&nonce), Payload { msg: ciphertext, aad: ad }).ok()
+}
+
+/// X25519 keypair
+#[derive(Clone, SerialEncodable, SerialDecodable)]
+pub struct KeyPair {
+    pub secret: [u8; 32],
+    pub public: [u8; 32],
+}
+
+impl KeyPair {
+    pub fn generate() -> Self {
+        Self::from_secret(StaticSecret::random_from_rng(OsRng).to_bytes())
+    }
+
+    pub fn from_secret(secret: [u8; 32]) -> Self {
+        let public = PublicKey::from(&StaticSecret::from(secret)).to_bytes();
+        Self { secret, public }
+    }
+}
+
+/// Prekey bundle published to a contact
+#[derive(Clone, Debug, SerialEncodable, SerialDecodable)]
+pub struct PrekeyBundle {
+    /// Public prekey
+    pub prekey: [u8; 32],
+    /// Creation time in seconds, newer bundles replace older ones
+    pub timestamp: u64,
+}
+
+/// Key agreement data sent along with messages until the initiator
+/// gets a reply, so the responder can set up the session.
+#[derive(Clone, Debug, PartialEq, Eq, SerialEncodable, SerialDecodable)]
+pub struct InitHeader {
+    /// Initiator's ephemeral public key
+    pub ephemeral: [u8; 32],
+    /// Responder's public prekey used for the key agreement
+    pub prekey: [u8; 32],
+}
+
+impl InitHeader {
+    /// Identifier of the session created from this key agreement
+    pub fn session_id(&self) -> [u8; 32] {
+        *blake3::hash(&[self.ephemeral.as_slice(), self.prekey.as_slice()].concat()).as_bytes()
+    }
+}
+
+/// Double ratchet message header
+#[derive(Clone, Debug, SerialEncodable, SerialDecodable)]
+pub struct MessageHeader {
+    /// Sender's current ratchet public key
+    pub dh: [u8; 32],
+    /// Number of messages in the sender's previous sending chain
+    pub prev_chain_len: u32,
+    /// Message number in the current sending chain
+    pub n: u32,
+    /// Key agreement data, if the session isn't confirmed yet
+    pub init: Option<InitHeader>,
+}
+
+/// Double ratchet encrypted message
+#[derive(Clone, Debug, SerialEncodable, SerialDecodable)]
+pub struct RatchetMessage {
+    pub header: MessageHeader,
+    pub ciphertext: Vec<u8>,
+}
+
+/// Double ratchet session with a single contact
+#[derive(Clone, SerialEncodable, SerialDecodable)]
+pub struct Session {
+    /// Session identifier, see [`InitHeader::session_id`]
+    pub id: [u8; 32],
+    /// Set on the initiator's side until the first reply arrives
+    pending_init: Option<InitHeader>,
+    /// Initiator and responder identity public keys
+    associated_data: [u8; 64],
+    /// Our current ratchet keypair
+    dh_self: KeyPair,
+    /// Contact's current ratchet public key
+    dh_remote: Option<[u8; 32]>,
+    root_key: [u8; 32],
+    send_chain: Option<[u8; 32]>,
+    recv_chain: Option<[u8; 32]>,
+    send_n: u32,
+    recv_n: u32,
+    prev_send_n: u32,
+    /// Message keys of skipped messages, by ratchet public key and number
+    skipped: Vec<([u8; 32], u32, [u8; 32])>,
+}
+
+impl Session {
+    /// Start a session as the initiator, using the contact's prekey.
+    pub fn initiate(
+        identity: &KeyPair,
+        remote_identity: &[u8; 32],
+        remote_prekey: &[u8; 32],
+    ) -> Option<Self> {
+        let ephemeral = KeyPair::generate();
+
+        let dh1 = dh(&identity.secret, remote_prekey)?;
+        let dh2 = dh(&ephemeral.secret, remote_identity)?;
+        let dh3 = dh(&ephemeral.secret, remote_prekey)?;
+        let shared = blake3::derive_key(KDF_X3DH, &[dh1, dh2, dh3].concat());
+
+        let dh_self = KeyPair::generate();
+        let (root_key, send_chain) = kdf_rk(&shared, &dh(&dh_self.secret, remote_prekey)?);
+
+        let init = InitHeader { ephemeral: ephemeral.public, prekey: *remote_prekey };
+        let mut associated_data = [0u8; 64];
+        associated_data[..32].copy_from_slice(&identity.public);
+        associated_data[32..].copy_from_slice(remote_identity);
+
+        Some(Self {
+            id: init.session_id(),
+            pending_init: Some(init),
+            associated_data,
+            dh_self,
+            dh_remote: Some(*remote_prekey),
+            root_key,
+            send_chain: Some(send_chain),
+            recv_chain: None,
+            send_n: 0,
+            recv_n: 0,
+            prev_send_n: 0,
+            skipped: vec![],
+        })
+    }
+
+    /// Set up a session as the responder, from the key agreement data of
+    /// a received message and our prekey it refers to.
+    pub fn respond(
+        identity: &KeyPair,
+        remote_identity: &[u8; 32],
+        prekey: &KeyPair,
+        init: &InitHeader,
+    ) -> Option<Self> {
+        let dh1 = dh(&prekey.secret, remote_identity)?;
+        let dh2 = dh(&identity.secret, &init.ephemeral)?;
+        let dh3 = dh(&prekey.secret, &init.ephemeral)?;
+        let shared = blake3::derive_key(KDF_X3DH, &[dh1, dh2, dh3].concat());
+
+        let mut associated_data = [0u8; 64];
+        associated_data[..32].copy_from_slice(remote_identity);
+        associated_data[32..].copy_from_slice(&identity.public);
+
+        Some(Self {
+            id: init.session_id(),
+            pending_init: None,
+            associated_data,
+            dh_self: prekey.clone(),
+            dh_remote: None,
+            root_key: shared,
+            send_chain: None,
+            recv_chain: None,
+            send_n: 0,
+            recv_n: 0,
+            prev_send_n: 0,
+            skipped: vec![],
+        })
+    }
+
+    /// Returns true if the session has a sending chain. A responder can
+    /// only send after it has received the initiator's first message.
+    pub fn can_send(&self) -> bool {
+        self.send_chain.is_some()
+    }
+
+    fn header_ad(&self, header: &MessageHeader) -> Vec<u8> {
+        [self.associated_data.as_slice(), &serialize(header)].concat()
+    }
+
+    /// Encrypt a message and advance the sending chain.
+    pub fn encrypt(&mut self, plaintext: &[u8]) -> Option<RatchetMessage> {
+        let (chain_key, message_key) = kdf_ck(&self.send_chain?);
+
+        let header = MessageHeader {
+            dh: self.dh_self.public,
+            prev_chain_len: self.prev_send_n,
+            n: self.send_n,
+            init: self.pending_init.clone(),
+        };
+
+        self.send_chain = Some(chain_key);
+        self.send_n += 1;
+
+        let ciphertext = seal(&message_key, &self.header_ad(&header), plaintext);
+        Some(RatchetMessage { header, ciphertext })
+    }
+
+    /// Decrypt a message. The session is left untouched if decryption
+    /// fails, so garbage can't corrupt its state.
+    pub fn decrypt(&mut self, message: &RatchetMessage) -> Option<Vec<u8>> {
+        let header = &message.header;
+        let ad = self.header_ad(header);
+
+        if let Some(idx) =
+            self.skipped.iter().position(|(dh, n, _)| *dh == header.dh && *n == header.n)
+        {
+            let plaintext = open(&self.skipped[idx].2, &ad, &message.ciphertext)?;
+            self.skipped.remove(idx);
+            return Some(plaintext)
+        }
+
+        let mut state = self.clone();
+        if state.dh_remote != Some(header.dh) {
+            state.skip_message_keys(header.prev_chain_len)?;
+            state.dh_ratchet(&header.dh)?;
+        }
+        state.skip_message_keys(header.n)?;
+
+        let (chain_key, message_key) = kdf_ck(&state.recv_chain?);
+        let plaintext = open(&message_key, &ad, &message.ciphertext)?;
+
+        state.recv_chain = Some(chain_key);
+        state.recv_n += 1;
+        // The contact replied, so they have the session now.
+        state.pending_init = None;
+
+        *self = state;
+        Some(plaintext)
+    }
+
+    fn skip_message_keys(&mut self, until: u32) -> Option<()> {
+        let Some(mut chain_key) = self.recv_chain else { return Some(()) };
+        let remote = self.dh_remote?;
+
+        if until > self.recv_n.saturating_add(MAX_SKIP) {
+            return None
+        }
+
+        while self.recv_n < until {
+            let (next, message_key) = kdf_ck(&chain_key);
+            self.skipped.push((remote, self.recv_n, message_key));
+            chain_key = next;
+            self.recv_n += 1;
+        }
+        self.recv_chain = Some(chain_key);
+
+        if self.skipped.len() > MAX_SKIPPED_KEYS {
+            let excess = self.skipped.len() - MAX_SKIPPED_KEYS;
+            self.skipped.drain(..excess);
+        }
+
+        Some(())
+    }
+
+    fn dh_ratchet(&mut self, remote: &[u8; 32]) -> Option<()> {
+        self.prev_send_n = self.send_n;
+        self.send_n = 0;
+        self.recv_n = 0;
+        self.dh_remote = Some(*remote);
+
+        let (root_key, recv_chain) = kdf_rk(&self.root_key, &dh(&self.dh_self.secret, remote)?);
+        self.dh_self = KeyPair::generate();
+        let (root_key, send_chain) = kdf_rk(&root_key, &dh(&self.dh_self.secret, remote)?);
+
+        self.root_key = root_key;
+        self.recv_chain = Some(recv_chain);
+        self.send_chain = Some(send_chain);
+        Some(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{KeyPair, RatchetMessage, Session};
+
+    fn sessions() -> (Session, Session, RatchetMessage) {
+        let alice = KeyPair::generate();
+        let bob = KeyPair::generate();
+        let bob_prekey = KeyPair::generate();
+
+        let mut alice_session = Session::initiate(&alice, &bob.public, &bob_prekey.public).unwrap();
+        let first = alice_session.encrypt(b"hello bob").unwrap();
+
+        let init = first.header.init.clone().unwrap();
+        let mut bob_session = Session::respond(&bob, &alice.public, &bob_prekey, &init).unwrap();
+        assert_eq!(bob_session.id, alice_session.id);
+        assert!(!bob_session.can_send());
+        assert_eq!(bob_session.decrypt(&first).unwrap(), b"hello bob");
+        assert!(bob_session.can_send());
+
+        (alice_session, bob_session, first)
+    }
+
+    #[test]
+    fn ratchet_roundtrip() {
+        let (mut alice, mut bob, _) = sessions();
+
+        for i in 0..3 {
+            let msg = format!("from bob {i}");
+            let ct = bob.encrypt(msg.as_bytes()).unwrap();
+            assert!(ct.header.init.is_none());
+            assert_eq!(alice.decrypt(&ct).unwrap(), msg.as_bytes());
+
+            let msg = format!("from alice {i}");
+            let ct = alice.encrypt(msg.as_bytes()).unwrap();
+            // Alice got a reply, so she stops sending the init header.
+            assert!(ct.header.init.is_none());
+            assert_eq!(bob.decrypt(&ct).unwrap(), msg.as_bytes());
+        }
+    }
+
+    #[test]
+    fn ratchet_handles_out_of_order_and_replayed_messages() {
+        let (mut alice, mut bob, first) = sessions();
+
+        let m1 = alice.encrypt(b"one").unwrap();
+        let m2 = alice.encrypt(b"two").unwrap();
+        let m3 = alice.encrypt(b"three").unwrap();
+
+        assert_eq!(bob.decrypt(&m3).unwrap(), b"three");
+        assert_eq!(bob.decrypt(&m1).unwrap(), b"one");
+        assert_eq!(bob.decrypt(&m2).unwrap(), b"two");
+
+        // Message keys are deleted once used
+        assert!(bob.decrypt(&first).is_none());
+        assert!(bob.decrypt(&m2).is_none());
+
+        // Tampered messages are rejected without breaking the session
+        let mut m4 = alice.encrypt(b"four").unwrap();
+        m4.ciphertext[0] ^= 1;
+        assert!(bob.decrypt(&m4).is_none());
+        let m5 = alice.encrypt(b"five").unwrap();
+        assert_eq!(bob.decrypt(&m5).unwrap(), b"five");
+    }
+
+    #[test]
+    fn ratchet_rejects_wrong_identity() {
+        let alice = KeyPair::generate();
+        let bob = KeyPair::generate();
+        let mallory = KeyPair::generate();
+        let bob_prekey = KeyPair::generate();
+
+        let mut alice_session = Session::initiate(&alice, &bob.public, &bob_prekey.public).unwrap();
+        let first = alice_session.encrypt(b"hello bob").unwrap();
+
+        let init = first.header.init.clone().unwrap();
+        let mut bob_session = Session::respond(&bob, &mallory.public, &bob_prekey, &init).unwrap();
+        assert!(bob_session.decrypt(&first).is_none());
+    }
+}
@@ -0,0 +1,352 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2026 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Channel key rotation for encrypted channels.
+//!
+//! An encrypted channel starts at epoch 0 with the `secret` set in the
+//! config. A channel can also have an admin keypair. The admin rotates
+//! the channel key by publishing a rekey event, signed with the admin
+//! key, containing the new channel secret sealed for every member with
+//! the contact box the admin shares with them. Members who aren't in
+//! the rekey event can't learn the new key, which is how someone is
+//! removed from a channel. The channel name is sealed along with the
+//! key and covered by the signature, so members find the channel by
+//! name and a rekey can't be replayed onto another channel with the
+//! same admin.
+//!
+//! The epoch of the key used is encrypted along with each channel
+//! message, in the dummy channel field. Messages from before key
+//! rotation existed carry zeroes there, which is epoch 0. Once a newer
+//! epoch is known, messages under older ones are dropped after a grace
+//! period, so removed members can't keep writing with an old key.
+//! Old keys are kept to decrypt history.
+
+use std::{collections::HashMap, sync::Arc};
+
+use crypto_box::ChaChaBox;
+use darkfi::Result;
+use darkfi_sdk::crypto::{
+    schnorr::{SchnorrPublic, SchnorrSecret, Signature},
+    PublicKey, SecretKey,
+};
+use darkfi_serial::{
+    async_trait, deserialize_async, serialize, serialize_async, SerialDecodable, SerialEncodable,
+};
+use sled_overlay::sled;
+use smol::lock::RwLock;
+
+use super::server::MAX_NICK_LEN;
+use crate::crypto::saltbox;
+
+/// `Privmsg::msg_type` of channel rekey events
+pub const MSG_TYPE_CHANNEL_REKEY: u8 = 1;
+
+/// Messages under a superseded epoch are still accepted for this many
+/// milliseconds after the rekey, while it propagates.
+pub const REKEY_GRACE_MS: u64 = 10 * 60 * 1000;
+
+/// Maximum number of members a rekey event is sealed for
+pub const MAX_CHANNEL_MEMBERS: usize = 256;
+
+/// Domain separator of rekey event signatures
+const REKEY_SIGNATURE_DOMAIN: &[u8] = b"darkirc-channel-rekey-v2";
+
+const CHANNEL_KEYS_TREE: &str = "darkirc_channel_keys";
+const CHANNEL_MEMBERS_TREE: &str = "darkirc_channel_members";
+
+/// Build the saltbox of a channel secret
+pub fn channel_saltbox(secret: [u8; 32]) -> Arc<ChaChaBox> {
+    let secret = crypto_box::SecretKey::from(secret);
+    Arc::new(ChaChaBox::new(&secret.public_key(), &secret))
+}
+
+/// Dummy channel field plaintext carrying the key epoch
+pub fn channel_tag(epoch: u32) -> [u8; MAX_NICK_LEN] {
+    let mut tag = [0u8; MAX_NICK_LEN];
+    tag[..4].copy_from_slice(&epoch.to_be_bytes());
+    tag
+}
+
+/// Parse the key epoch out of a decrypted dummy channel field
+pub fn parse_channel_tag(tag: &[u8]) -> Option<u32> {
+    if tag.len() != MAX_NICK_LEN || tag[4..].iter().any(|b| *b != 0) {
+        return None
+    }
+
+    Some(u32::from_be_bytes(tag[..4].try_into().unwrap()))
+}
+
+/// A channel key persisted in sled
+#[derive(Clone, SerialEncodable, SerialDecodable)]
+struct ChannelEpoch {
+    epoch: u32,
+    /// Timestamp of the rekey event, in milliseconds
+    activated: u64,
+    secret: [u8; 32],
+}
+
+/// A channel key, newer than the configured one
+#[derive(Clone)]
+pub struct ChannelKey {
+    pub epoch: u32,
+    /// Timestamp of the rekey event, in milliseconds
+    pub activated: u64,
+    pub saltbox: Arc<ChaChaBox>,
+}
+
+/// Signed channel rekey event, carried in the `msg` field of a `Privmsg`
+#[derive(Clone, SerialEncodable, SerialDecodable)]
+pub struct RekeyEvent {
+    /// Epoch of the new key
+    pub epoch: u32,
+    /// Epoch, new key and channel name sealed for each member, with the
+    /// contact box the admin shares with them
+    pub keys: Vec<String>,
+    /// Admin signature over the above and the channel name
+    pub signature: Signature,
+}
+
+impl RekeyEvent {
+    /// Create a rekey event of `channel` for `epoch`, sealing `secret`
+    /// for each of the given member contact boxes.
+    pub fn new(
+        admin: &SecretKey,
+        channel: &str,
+        epoch: u32,
+        secret: &[u8; 32],
+        members: &[Arc<ChaChaBox>],
+    ) -> Self {
+        let payload = [&epoch.to_be_bytes()[..], secret, channel.as_bytes()].concat();
+        let keys: Vec<String> = members.iter().map(|m| saltbox::encrypt(m, &payload)).collect();
+        let signature = admin.sign(&Self::signed_message(channel, epoch, &keys));
+        Self { epoch, keys, signature }
+    }
+
+    fn signed_message(channel: &str, epoch: u32, keys: &[String]) -> Vec<u8> {
+        [
+            REKEY_SIGNATURE_DOMAIN,
+            &serialize(&channel.to_string()),
+            &epoch.to_be_bytes(),
+            &serialize(&keys),
+        ]
+        .concat()
+    }
+
+    /// Verify the event was signed by the given admin for `channel`
+    pub fn verify(&self, channel: &str, admin: &PublicKey) -> bool {
+        admin.verify(&Self::signed_message(channel, self.epoch, &self.keys), &self.signature)
+    }
+
+    /// Find the new key sealed for us, trying each of our contact boxes.
+    /// Returns the channel name along with the key.
+    pub fn open(&self, contacts: &[Arc<ChaChaBox>]) -> Option<(String, [u8; 32])> {
+        for sealed in &self.keys {
+            let Ok(sealed) = bs58::decode(sealed).into_vec() else { continue };
+            for contact in contacts {
+                let Some(payload) = saltbox::try_decrypt(contact, &sealed) else { continue };
+                if payload.len() < 36 || payload[..4] != self.epoch.to_be_bytes() {
+                    continue
+                }
+                let Ok(channel) = String::from_utf8(payload[36..].to_vec()) else { continue };
+
+                return Some((channel, payload[4..36].try_into().unwrap()))
+            }
+        }
+
+        None
+    }
+}
+
+/// Keys and members of encrypted channels, persisted in sled.
+/// Channels are identified by their configured name.
+pub struct ChannelKeys {
+    keys: sled::Tree,
+    members: sled::Tree,
+    /// Known keys of each channel, newest first
+    cache: RwLock<HashMap<String, Vec<ChannelKey>>>,
+}
+
+impl ChannelKeys {
+    pub async fn new(sled_db: &sled::Db) -> Result<Self> {
+        let keys = sled_db.open_tree(CHANNEL_KEYS_TREE)?;
+        let members = sled_db.open_tree(CHANNEL_MEMBERS_TREE)?;
+
+        let mut cache = HashMap::new();
+        for item in keys.iter() {
+            let (channel, epochs) = item?;
+            let epochs: Vec<ChannelEpoch> = deserialize_async(&epochs).await?;
+            cache.insert(String::from_utf8_lossy(&channel).to_string(), Self::build_keys(epochs));
+        }
+
+        Ok(Self { keys, members, cache: RwLock::new(cache) })
+    }
+
+    fn build_keys(mut epochs: Vec<ChannelEpoch>) -> Vec<ChannelKey> {
+        epochs.sort_by(|a, b| b.epoch.cmp(&a.epoch));
+        epochs
+            .into_iter()
+            .map(|e| ChannelKey {
+                epoch: e.epoch,
+                activated: e.activated,
+                saltbox: channel_saltbox(e.secret),
+            })
+            .collect()
+    }
+
+    /// Known rotated keys of a channel, newest first
+    pub async fn keys(&self, channel: &str) -> Vec<ChannelKey> {
+        self.cache.read().await.get(channel).cloned().unwrap_or_default()
+    }
+
+    /// Current rotated key of a channel, if it was ever rotated
+    pub async fn current(&self, channel: &str) -> Option<ChannelKey> {
+        self.cache.read().await.get(channel).and_then(|keys| keys.first().cloned())
+    }
+
+    /// Store the key of a channel epoch. Returns `false` if it was
+    /// already known.
+    pub async fn insert(
+        &self,
+        channel: &str,
+        epoch: u32,
+        activated: u64,
+        secret: [u8; 32],
+    ) -> Result<bool> {
+        let mut cache = self.cache.write().await;
+
+        let mut epochs: Vec<ChannelEpoch> = match self.keys.get(channel)? {
+            Some(bytes) => deserialize_async(&bytes).await?,
+            None => vec![],
+        };
+
+        if epochs.iter().any(|e| e.epoch == epoch) {
+            return Ok(false)
+        }
+
+        epochs.push(ChannelEpoch { epoch, activated, secret });
+        self.keys.insert(channel, serialize_async(&epochs).await)?;
+        cache.insert(channel.to_string(), Self::build_keys(epochs));
+        Ok(true)
+    }
+
+    /// Returns true if a message under `epoch` with the given timestamp
+    /// should be accepted for the channel. Messages under a superseded
+    /// epoch are only accepted until the grace period of the next
+    /// epoch's rekey is over.
+    pub async fn epoch_accepted(&self, channel: &str, epoch: u32, timestamp: u64) -> bool {
+        let keys = self.keys(channel).await;
+        match keys.iter().filter(|k| k.epoch > epoch).min_by_key(|k| k.epoch) {
+            Some(next) => timestamp < next.activated.saturating_add(REKEY_GRACE_MS),
+            None => true,
+        }
+    }
+
+    /// Contact names of the channel members, as managed by its admin
+    pub async fn members(&self, channel: &str) -> Result<Vec<String>> {
+        match self.members.get(channel)? {
+            Some(bytes) => Ok(deserialize_async(&bytes).await?),
+            None => Ok(vec![]),
+        }
+    }
+
+    /// Replace the contact names of the channel members
+    pub async fn set_members(&self, channel: &str, members: Vec<String>) -> Result<()> {
+        self.members.insert(channel, serialize_async(&members).await)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crypto_box::{ChaChaBox, SecretKey as BoxSecretKey};
+    use darkfi_sdk::crypto::{PublicKey, SecretKey};
+    use rand::rngs::OsRng;
+    use sled_overlay::sled;
+
+    use super::{channel_tag, parse_channel_tag, ChannelKeys, RekeyEvent, REKEY_GRACE_MS};
+
+    fn contact_box(mine: &BoxSecretKey, theirs: &BoxSecretKey) -> Arc<ChaChaBox> {
+        Arc::new(ChaChaBox::new(&theirs.public_key(), mine))
+    }
+
+    #[test]
+    fn channel_tag_roundtrip() {
+        assert_eq!(parse_channel_tag(&channel_tag(0)), Some(0));
+        assert_eq!(parse_channel_tag(&channel_tag(42)), Some(42));
+        // Messages from before key rotation carry zeroes
+        assert_eq!(parse_channel_tag(&[0u8; 24]), Some(0));
+        assert_eq!(parse_channel_tag(&[1u8; 24]), None);
+        assert_eq!(parse_channel_tag(&[0u8; 23]), None);
+    }
+
+    #[test]
+    fn rekey_reaches_only_remaining_members() {
+        let admin = SecretKey::random(&mut OsRng);
+        let admin_box = BoxSecretKey::generate(&mut OsRng);
+        let alice = BoxSecretKey::generate(&mut OsRng);
+        let mallory = BoxSecretKey::generate(&mut OsRng);
+
+        let secret = [7u8; 32];
+        let event = RekeyEvent::new(&admin, "#dev", 3, &secret, &[contact_box(&admin_box, &alice)]);
+
+        assert!(event.verify("#dev", &PublicKey::from_secret(admin)));
+        assert!(!event.verify("#dev", &PublicKey::from_secret(SecretKey::random(&mut OsRng))));
+
+        assert_eq!(
+            event.open(&[contact_box(&alice, &admin_box)]),
+            Some(("#dev".to_string(), secret))
+        );
+        assert_eq!(event.open(&[contact_box(&mallory, &admin_box)]), None);
+
+        // Epoch can't be changed without breaking the signature
+        let mut forged = event.clone();
+        forged.epoch = 4;
+        assert!(!forged.verify("#dev", &PublicKey::from_secret(admin)));
+
+        // Nor can the rekey be replayed onto another channel of the admin
+        assert!(!event.verify("#ops", &PublicKey::from_secret(admin)));
+    }
+
+    #[test]
+    fn superseded_epochs_expire_after_grace_period() {
+        smol::block_on(async {
+            let sled_db = sled::Config::new().temporary(true).open().unwrap();
+            let keys = ChannelKeys::new(&sled_db).await.unwrap();
+
+            assert!(keys.epoch_accepted("#dev", 0, u64::MAX).await);
+
+            assert!(keys.insert("#dev", 1, 1000, [1u8; 32]).await.unwrap());
+            assert!(!keys.insert("#dev", 1, 2000, [1u8; 32]).await.unwrap());
+            assert!(keys.insert("#dev", 2, 5000, [2u8; 32]).await.unwrap());
+            assert_eq!(keys.current("#dev").await.unwrap().epoch, 2);
+
+            assert!(keys.epoch_accepted("#dev", 0, 1000 + REKEY_GRACE_MS - 1).await);
+            assert!(!keys.epoch_accepted("#dev", 0, 1000 + REKEY_GRACE_MS).await);
+            assert!(keys.epoch_accepted("#dev", 1, 5000 + REKEY_GRACE_MS - 1).await);
+            assert!(!keys.epoch_accepted("#dev", 1, 5000 + REKEY_GRACE_MS).await);
+            assert!(keys.epoch_accepted("#dev", 2, u64::MAX).await);
+
+            // Keys survive restarts
+            let keys = ChannelKeys::new(&sled_db).await.unwrap();
+            let epochs: Vec<u32> = keys.keys("#dev").await.iter().map(|k| k.epoch).collect();
+            assert_eq!(epochs, vec![2, 1]);
+        });
+    }
+}
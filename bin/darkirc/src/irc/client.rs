@@ -26,8 +26,9 @@ use std::{
 };
 
 use darkfi::{
-    event_graph::{proto::EventPut, Event, NULL_ID},
+    event_graph::{proto::EventPut, redaction::Redaction, Event, NULL_ID},
     system::Subscription,
+    util::time::DateTime,
     Error, Result,
 };
 use darkfi_serial::{deserialize_async_partial, serialize_async};
@@ -43,8 +44,9 @@ use smol::{
 use tracing::{debug, error, warn};
 
 use super::{
+    files::FileOffer,
     server::{IrcServer, RlnMessageReservation, MAX_MSG_LEN},
-    NickServ, SERVER_NAME,
+    ChanServ, FileServ, NickServ, SearchServ, SERVER_NAME,
 };
 use crate::Privmsg;
 
@@ -104,27 +106,94 @@ pub(super) fn message_lines(message: &str) -> Vec<&str> {
     message.split(['\r', '\n']).filter(|line| !line.is_empty()).collect()
 }
 
+/// Format a millisecond timestamp for the IRCv3 `server-time` tag,
+/// e.g. `2019-01-04T14:33:26.123Z`.
+pub(super) fn format_server_time(timestamp: u64) -> String {
+    let dt = DateTime::from_timestamp(timestamp / 1000, 0);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        dt.year,
+        dt.month,
+        dt.day,
+        dt.hour,
+        dt.min,
+        dt.sec,
+        timestamp % 1000
+    )
+}
+
+/// Parse an IRCv3 `server-time` formatted timestamp into milliseconds.
+pub(super) fn parse_server_time(time: &str) -> Option<u64> {
+    let time = time.strip_suffix('Z')?;
+    let (datetime, fraction) = time.split_once('.').unwrap_or((time, "0"));
+    if fraction.is_empty() || fraction.len() > 9 || !fraction.bytes().all(|b| b.is_ascii_digit()) {
+        return None
+    }
+
+    let dt = DateTime::from_timestamp_str(datetime).ok()?;
+
+    // Days since the Unix epoch, from the proleptic Gregorian calendar
+    let (month, day) = (dt.month as i64, dt.day as i64);
+    let year = if month <= 2 { dt.year as i64 - 1 } else { dt.year as i64 };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = u64::try_from(era * 146097 + day_of_era - 719468).ok()?;
+
+    let secs = days * 86400 + dt.hour as u64 * 3600 + dt.min as u64 * 60 + dt.sec as u64;
+    let millis: u64 = format!("{fraction:0<3}")[..3].parse().ok()?;
+    Some(secs * 1000 + millis)
+}
+
+/// Event metadata of a message, sent to the client as IRCv3 tags
+#[derive(Clone, Debug)]
+pub struct EventTags {
+    /// Event timestamp in milliseconds, sent as `time`
+    pub timestamp: u64,
+    /// Event ID, sent as `msgid`
+    pub id: blake3::Hash,
+    /// Reference of the batch the message is part of
+    pub batch: Option<String>,
+}
+
+impl EventTags {
+    pub fn new(event: &Event) -> Self {
+        Self { timestamp: event.header.timestamp, id: event.header.id(), batch: None }
+    }
+}
+
 /// Reply types, we can either send server replies, or client replies.
 pub enum ReplyType {
     /// Server reply, we have to use numerics
     Server((u16, String)),
     /// Client reply, message from someone to some{one,where}
     Client((String, String)),
+    /// Client reply carrying a message from the Event Graph
+    EventClient((String, String, EventTags)),
     /// Historical client reply. The event is marked seen after its final line
-    /// has been written successfully.
-    HistoricalClient((String, String, Option<blake3::Hash>)),
+    /// (flagged by the last field) has been written successfully.
+    HistoricalClient((String, String, EventTags, bool)),
     /// Pong reply, we just use server origin
     Pong(String),
     /// CAP reply
     Cap(String),
     /// NOTICE reply (from, to, what)
     Notice((String, String, String)),
+    /// BATCH start (`+<ref> <type> [params]`) or end (`-<ref>`)
+    Batch(String),
+    /// Standard FAIL reply (command, code, description)
+    Fail((String, String, String)),
+    /// Read marker of a target, `None` if there is none
+    MarkRead((String, Option<u64>)),
 }
 
 fn format_reply(reply: &ReplyType) -> String {
     match reply {
         ReplyType::Server((rpl, msg)) => format!(":{SERVER_NAME} {rpl:03} {msg}"),
-        ReplyType::Client((nick, msg)) | ReplyType::HistoricalClient((nick, msg, _)) => {
+        ReplyType::Client((nick, msg)) |
+        ReplyType::EventClient((nick, msg, _)) |
+        ReplyType::HistoricalClient((nick, msg, _, _)) => {
             format!(":{nick}!~anon@darkirc {msg}")
         }
         ReplyType::Pong(origin) => format!(":{SERVER_NAME} PONG :{origin}"),
@@ -132,7 +201,43 @@ fn format_reply(reply: &ReplyType) -> String {
         ReplyType::Notice((src, dst, msg)) => {
             format!(":{src}!~anon@darkirc NOTICE {dst} :{msg}")
         }
+        ReplyType::Batch(msg) => format!(":{SERVER_NAME} BATCH {msg}"),
+        ReplyType::Fail((cmd, code, desc)) => format!(":{SERVER_NAME} FAIL {cmd} {code} :{desc}"),
+        ReplyType::MarkRead((target, timestamp)) => {
+            let timestamp = timestamp.map_or("*".to_string(), format_server_time);
+            format!(":{SERVER_NAME} MARKREAD {target} timestamp={timestamp}")
+        }
+    }
+}
+
+/// Build the IRCv3 tags prefix of a reply, depending on the caps the
+/// client has enabled. Returns an empty string if there is nothing to tag.
+fn format_tags(reply: &ReplyType, caps: &HashMap<String, bool>) -> String {
+    let tags = match reply {
+        ReplyType::EventClient((_, _, tags)) | ReplyType::HistoricalClient((_, _, tags, _)) => tags,
+        _ => return String::new(),
+    };
+
+    let enabled = |cap: &str| caps.get(cap).copied().unwrap_or(false);
+    let mut out = vec![];
+
+    if enabled("server-time") {
+        out.push(format!("time={}", format_server_time(tags.timestamp)));
+    }
+
+    if enabled("message-tags") {
+        out.push(format!("msgid={}", tags.id));
     }
+
+    if let (true, Some(batch)) = (enabled("batch"), &tags.batch) {
+        out.push(format!("batch={batch}"));
+    }
+
+    if out.is_empty() {
+        return String::new()
+    }
+
+    format!("@{} ", out.join(";"))
 }
 
 async fn write_irc_frame<W>(writer: &mut W, mut frame: String) -> Result<()>
@@ -177,11 +282,26 @@ pub struct Client {
     pub realname: RwLock<String>,
     /// Client caps
     pub caps: RwLock<HashMap<String, bool>>,
-    /// Set of seen messages for the user
+    /// Counter used to create unique batch references
+    pub batch_counter: AtomicUsize,
+    /// Client identifier sent with `PASS`, used to track seen
+    /// messages separately for each client of the same user
+    pub client_id: RwLock<Option<String>>,
+    /// Set of seen messages for the client
     /// TODO: It grows indefinitely, needs to be pruned.
     pub seen: OnceCell<sled::Tree>,
+    /// Subscription for read marker updates
+    pub marker_updates: Subscription<(String, u64)>,
     /// NickServ instance
     pub nickserv: Arc<NickServ>,
+    /// ChanServ instance
+    pub chanserv: Arc<ChanServ>,
+    /// FileServ instance
+    pub fileserv: Arc<FileServ>,
+    /// Subscription for the results of FileServ downloads
+    pub file_results: Subscription<String>,
+    /// SearchServ instance
+    pub searchserv: Arc<SearchServ>,
 }
 
 impl Client {
@@ -192,11 +312,22 @@ impl Client {
         incoming_st: Subscription<Event>,
         addr: SocketAddr,
     ) -> Result<Self> {
-        let caps =
-            HashMap::from([("no-history".to_string(), false), ("no-autojoin".to_string(), false)]);
+        let caps = HashMap::from([
+            ("no-history".to_string(), false),
+            ("no-autojoin".to_string(), false),
+            ("server-time".to_string(), false),
+            ("message-tags".to_string(), false),
+            ("batch".to_string(), false),
+            ("echo-message".to_string(), false),
+            ("draft/chathistory".to_string(), false),
+            ("draft/read-marker".to_string(), false),
+        ]);
 
         let username = Arc::new(RwLock::new(String::from("*")));
         let nickname = Arc::new(RwLock::new(String::from("*")));
+        let fileserv = Arc::new(FileServ::new(nickname.clone(), server.clone()));
+        let file_results = fileserv.subscribe_results().await;
+        let marker_updates = server.read_markers.subscribe().await;
 
         Ok(Self {
             server: server.clone(),
@@ -214,10 +345,17 @@ impl Client {
             nickname: nickname.clone(),
             realname: RwLock::new(String::from("*")),
             caps: RwLock::new(caps),
+            batch_counter: AtomicUsize::new(0),
+            client_id: RwLock::new(None),
             seen: OnceCell::new(),
+            marker_updates,
             nickserv: Arc::new(
                 NickServ::new(username.clone(), nickname.clone(), server.clone()).await?,
             ),
+            chanserv: Arc::new(ChanServ::new(nickname.clone(), server.clone())),
+            fileserv,
+            file_results,
+            searchserv: Arc::new(SearchServ::new(nickname.clone(), server.clone())),
         })
     }
 
@@ -269,7 +407,7 @@ impl Client {
                     // that just arrived.
                     if self.server.darkirc.event_graph.is_synced() && !args_queue.is_empty() {
                         let pending = self.pending_privmsgs_to_events(&args_queue).await?;
-                        self.publish_events(pending).await?;
+                        self.publish_events(pending, &mut writer).await?;
                         args_queue.clear();
                     }
 
@@ -280,7 +418,7 @@ impl Client {
                         // This means we add it to our DAG, and the DAG will
                         // handle the rest of the propagation.
                         Ok(Some(events)) => {
-                            self.publish_events(events).await?;
+                            self.publish_events(events, &mut writer).await?;
                         }
 
                         // If we got nothing, we just pass.
@@ -303,11 +441,32 @@ impl Client {
                 _ = queue_timer.next().fuse() => {
                     if self.server.darkirc.event_graph.is_synced() && !args_queue.is_empty() {
                         let pending = self.pending_privmsgs_to_events(&args_queue).await?;
-                        self.publish_events(pending).await?;
+                        self.publish_events(pending, &mut writer).await?;
                         args_queue.clear();
                     }
                 }
 
+                // Relay read marker updates, possibly made by other clients
+                (target, timestamp) = self.marker_updates.receive().fuse() => {
+                    if !self.cap_enabled("draft/read-marker").await {
+                        continue
+                    }
+
+                    let is_target = self.channels.read().await.contains(&target) ||
+                        self.server.contacts.read().await.contains_key(&target);
+                    if is_target {
+                        let reply = ReplyType::MarkRead((target, Some(timestamp)));
+                        self.reply(&mut writer, &reply).await?;
+                    }
+                }
+
+                // Report finished FileServ downloads
+                result = self.file_results.receive().fuse() => {
+                    let nick = self.nickname.read().await.to_string();
+                    let reply = ReplyType::Notice(("FileServ".to_string(), nick, result));
+                    self.reply(&mut writer, &reply).await?;
+                }
+
                 // Process message from the network. These should only be PRIVMSG.
                 //
                 // N.b. handling "historical messages", i.e. outstanding messages
@@ -331,6 +490,11 @@ impl Client {
                         }
                     }
 
+                    // Redactions are applied by the event graph
+                    if Redaction::from_event(&r).is_some() {
+                        continue
+                    }
+
                     // Try to deserialize the `Event`'s content into a `Privmsg`
                     let mut privmsg = match deserialize_async_partial(r.content()).await {
                         Ok((v, _)) => v,
@@ -340,11 +504,15 @@ impl Client {
                         }
                     };
 
-                    // If successful, potentially decrypt it:
-                    self.server.try_decrypt(&mut privmsg, self.nickname.read().await.as_ref()).await;
+                    // If successful, potentially decrypt it. Direct message
+                    // handshakes and channel rekeys are consumed internally.
+                    let nick = self.nickname.read().await.to_string();
+                    if !self.server.try_decrypt(&mut privmsg, &nick, r.header.timestamp).await {
+                        continue
+                    }
 
                     // We should skip any attempts to contact services from the network.
-                    if ["nickserv", "chanserv"].contains(&privmsg.nick.to_lowercase().as_str()) {
+                    if ["nickserv", "chanserv", "fileserv", "searchserv"].contains(&privmsg.nick.to_lowercase().as_str()) {
                         continue
                     }
 
@@ -375,12 +543,13 @@ impl Client {
                     drop(chans_lock);
 
                     // Handle message lines individually
+                    let tags = EventTags::new(&r);
                     for line in lines {
                         // Format the message
                         let msg = format!("PRIVMSG {} :{line}", privmsg.channel);
 
                         // Send it to the client
-                        let reply = ReplyType::Client((privmsg.nick.clone(), msg));
+                        let reply = ReplyType::EventClient((privmsg.nick.clone(), msg, tags.clone()));
                         self.reply(&mut writer, &reply).await?;
                     }
 
@@ -429,16 +598,23 @@ impl Client {
     async fn pending_privmsgs_to_events(
         &self,
         args_queue: &VecDeque<Privmsg>,
-    ) -> Result<Vec<Event>> {
+    ) -> Result<Vec<(Event, Privmsg)>> {
         let mut events = Vec::with_capacity(args_queue.len());
         for privmsg in args_queue.iter().cloned() {
-            events.push(self.privmsg_to_event(privmsg).await?);
+            events.extend(self.privmsg_to_events(privmsg).await?);
         }
         Ok(events)
     }
 
-    async fn publish_events(&self, events: Vec<Event>) -> Result<()> {
-        for event in events {
+    /// Insert our events into the DAG and broadcast them. The plaintext
+    /// `Privmsg` of each event is echoed back if `echo-message` is enabled.
+    async fn publish_events<W>(&self, events: Vec<(Event, Privmsg)>, writer: &mut W) -> Result<()>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        let echo = self.cap_enabled("echo-message").await;
+
+        for (event, privmsg) in events {
             // Update the last sent event.
             let event_id = event.header.id();
             *self.last_sent.write().await = event_id;
@@ -460,6 +636,7 @@ impl Client {
                                 "[IRC CLIENT] No RLN identity registered; refusing to send. Use \
                                  `/msg NickServ REGISTER ...` to register."
                             );
+                            self.server.event_published(&event_id, false).await?;
                             continue
                         }
                         RlnMessageReservation::BudgetExhausted => {
@@ -467,6 +644,7 @@ impl Client {
                                 "[IRC CLIENT] RLN message budget exhausted for this epoch; \
                                  dropping message to avoid slash"
                             );
+                            self.server.event_published(&event_id, false).await?;
                             continue
                         }
                     };
@@ -487,13 +665,14 @@ impl Client {
             // Commit our outbound signal through the safe public API. It inserts
             // the header, verifies and stores the RLN blob, then commits the event
             // body.
-            if let Err(e) = self
+            let inserted = self
                 .server
                 .darkirc
                 .event_graph
                 .insert_signal_with_blob(&event, &blob, &dag_name)
-                .await
-            {
+                .await;
+            self.server.event_published(&event_id, inserted.is_ok()).await?;
+            if let Err(e) = inserted {
                 error!("[IRC CLIENT] Failed inserting verified signal event: {e}");
                 continue
             }
@@ -506,27 +685,53 @@ impl Client {
                 return Err(e)
             }
 
+            // Writing to a target means everything before was read
+            if !message_lines(&privmsg.msg).is_empty() {
+                self.server.read_markers.advance(&privmsg.channel, event.header.timestamp).await?;
+            }
+
+            let tags = EventTags::new(&event);
             if let Err(e) = self.server.darkirc.p2p.broadcast(&EventPut(event, blob)).await {
                 error!("[IRC CLIENT] Event broadcast was not admitted: {e}");
             }
+
+            // Echo the message back, so the client shows it with the time
+            // and ID it has on the network.
+            if echo {
+                for line in message_lines(&privmsg.msg) {
+                    let msg = format!("PRIVMSG {} :{line}", privmsg.channel);
+                    let reply = ReplyType::EventClient((privmsg.nick.clone(), msg, tags.clone()));
+                    self.reply(writer, &reply).await?;
+                }
+            }
         }
 
         Ok(())
     }
 
+    /// Check if the client has enabled the given cap
+    pub async fn cap_enabled(&self, cap: &str) -> bool {
+        self.caps.read().await.get(cap).copied().unwrap_or(false)
+    }
+
+    /// Create a batch reference unique to this connection
+    pub fn next_batch_ref(&self) -> String {
+        format!("darkirc{}", self.batch_counter.fetch_add(1, SeqCst))
+    }
+
     /// Send a reply to the IRC client. Matches on the reply type.
     async fn reply<W>(&self, writer: &mut W, reply: &ReplyType) -> Result<()>
     where
         W: AsyncWrite + Unpin,
     {
-        let r = format_reply(reply);
+        let r = format!("{}{}", format_tags(reply, &*self.caps.read().await), format_reply(reply));
 
         debug!("[{}] <-- {r}", self.addr);
 
         write_irc_frame(writer, r).await?;
 
-        if let ReplyType::HistoricalClient((_, _, Some(event_id))) = reply {
-            self.mark_seen(event_id).await?;
+        if let ReplyType::HistoricalClient((_, _, tags, true)) = reply {
+            self.mark_seen(&tags.id).await?;
         }
 
         Ok(())
@@ -538,7 +743,7 @@ impl Client {
         line: &str,
         writer: &mut W,
         args_queue: &mut VecDeque<Privmsg>,
-    ) -> Result<Option<Vec<Event>>>
+    ) -> Result<Option<Vec<(Event, Privmsg)>>>
     where
         W: AsyncWrite + Unpin,
     {
@@ -559,6 +764,14 @@ impl Client {
             return Err(Error::ParseFailed("Line doesn't end with CR/LF"))
         }
 
+        // Drop IRCv3 client tags, we don't make use of any of them.
+        if line.starts_with('@') {
+            line = match line.split_once(' ') {
+                Some((_, rest)) => rest.trim_start().to_string(),
+                None => return Ok(None),
+            };
+        }
+
         // Prefix the message part of PRIVMSG with ':' if is not already.
         // Or realname part of USER command.
         if let Some(index) = match line.split_whitespace().next() {
@@ -588,9 +801,11 @@ impl Client {
         let replies: Vec<ReplyType> = match cmd.as_str() {
             "ADMIN" => self.handle_cmd_admin(&args).await?,
             "CAP" => self.handle_cmd_cap(&args).await?,
+            "CHATHISTORY" => self.handle_cmd_chathistory(&args).await?,
             "INFO" => self.handle_cmd_info(&args).await?,
             "JOIN" => self.handle_cmd_join(&args, true).await?,
             "LIST" => self.handle_cmd_list(&args).await?,
+            "MARKREAD" => self.handle_cmd_markread(&args).await?,
             "MODE" => self.handle_cmd_mode(&args).await?,
             "MOTD" => self.handle_cmd_motd(&args).await?,
             "NAMES" => self.handle_cmd_names(&args).await?,
@@ -603,6 +818,8 @@ impl Client {
             "TOPIC" => self.handle_cmd_topic(&args).await?,
             "USER" => self.handle_cmd_user(&args).await?,
             "VERSION" => self.handle_cmd_version(&args).await?,
+            // Client-only tags (e.g. typing notifications) are not relayed.
+            "TAGMSG" => vec![],
             "QUIT" => return Err(Error::ChannelStopped),
             _ => {
                 warn!("[IRC CLIENT] Unimplemented \"{cmd}\" command");
@@ -615,6 +832,28 @@ impl Client {
             self.reply(writer, reply).await?;
         }
 
+        // Publish the channel rekeys created by ChanServ commands
+        let rekeys = self.chanserv.take_outgoing().await;
+        if !rekeys.is_empty() {
+            return Ok(Some(rekeys))
+        }
+
+        // Publish the file offers created by FileServ commands. They are
+        // echoed as the readable line recipients get.
+        let offers = self.fileserv.take_outgoing().await;
+        if !offers.is_empty() {
+            let mut events = vec![];
+            for privmsg in offers {
+                for (event, mut plaintext) in self.privmsg_to_events(privmsg).await? {
+                    if let Some(offer) = FileOffer::parse(&plaintext.msg) {
+                        plaintext.msg = offer.describe();
+                    }
+                    events.push((event, plaintext));
+                }
+            }
+            return Ok(Some(events))
+        }
+
         // If the command was a PRIVMSG the client sent, we need to encrypt it and
         // create an Event to broadcast and return it from this function. So let's try.
         // We also do not allow sending unencrypted DMs. In that case, we send a notice
@@ -652,9 +891,7 @@ impl Client {
                 self.penalty.fetch_add(1, SeqCst);
                 return Ok(None)
             };
-            let event = self.privmsg_to_event(privmsg).await?;
-
-            return Ok(Some(vec![event]))
+            return Ok(Some(self.privmsg_to_events(privmsg).await?))
         }
 
         Ok(None)
@@ -672,24 +909,50 @@ impl Client {
         Some(Privmsg { version: 0, msg_type: 0, channel, nick, msg: msg.to_string() })
     }
 
-    // Internal helper function that creates an Event from PRIVMSG arguments
-    async fn privmsg_to_event(&self, mut privmsg: Privmsg) -> Result<Event> {
+    // Internal helper function that creates the Events to publish for a
+    // PRIVMSG, paired with its plaintext.
+    async fn privmsg_to_events(&self, privmsg: Privmsg) -> Result<Vec<(Event, Privmsg)>> {
+        let event_graph = &self.server.darkirc.event_graph;
+        let mut events = vec![];
+
+        // Direct messages are preceded by our prekey for the contact when
+        // it wasn't published yet, so they can reply forward-secretly.
+        // It carries no plaintext, so it is never echoed.
+        if let Some((handshake, contact, created)) =
+            self.server.contact_handshake(&privmsg.channel).await?
+        {
+            let event = Event::new(serialize_async(&handshake).await, event_graph).await?;
+            self.server.handshake_event(event.id(), contact, created).await;
+            let plaintext = Privmsg { msg: String::new(), ..privmsg.clone() };
+            events.push((event, plaintext));
+        }
+
         // Encrypt the Privmsg if an encryption method is available.
-        self.server.try_encrypt(&mut privmsg).await;
+        let mut encrypted = privmsg.clone();
+        self.server.try_encrypt(&mut encrypted).await;
 
         // Build a DAG event and return it.
-        Event::new(serialize_async(&privmsg).await, &self.server.darkirc.event_graph).await
+        events.push((Event::new(serialize_async(&encrypted).await, event_graph).await?, privmsg));
+        Ok(events)
     }
 
-    /// Atomically mark a message as seen for this client.
-    pub async fn mark_seen(&self, event_id: &blake3::Hash) -> Result<()> {
-        let db = self
-            .seen
+    /// Get the tree of seen messages. Clients which identified with a
+    /// client ID have their own, others share the one of their username.
+    async fn seen_tree(&self) -> &sled::Tree {
+        self.seen
             .get_or_init(|| async {
-                let u = self.username.read().await.to_string();
-                self.server.darkirc.sled.open_tree(format!("darkirc_user_{u}")).unwrap()
+                let name = match self.client_id.read().await.as_ref() {
+                    Some(client_id) => format!("darkirc_client_{client_id}"),
+                    None => format!("darkirc_user_{}", self.username.read().await),
+                };
+                self.server.darkirc.sled.open_tree(name).unwrap()
             })
-            .await;
+            .await
+    }
+
+    /// Atomically mark a message as seen for this client.
+    pub async fn mark_seen(&self, event_id: &blake3::Hash) -> Result<()> {
+        let db = self.seen_tree().await;
 
         debug!("Marking event {event_id} as seen");
         let mut batch = sled::Batch::default();
@@ -699,13 +962,7 @@ impl Client {
 
     /// Check if a message was already marked seen for this client.
     pub async fn is_seen(&self, event_id: &blake3::Hash) -> Result<bool> {
-        let db = self
-            .seen
-            .get_or_init(|| async {
-                let u = self.username.read().await.to_string();
-                self.server.darkirc.sled.open_tree(format!("darkirc_user_{u}")).unwrap()
-            })
-            .await;
+        let db = self.seen_tree().await;
 
         Ok(db.contains_key(event_id.as_bytes())?)
     }
@@ -714,7 +971,7 @@ impl Client {
 #[cfg(test)]
 mod tests {
     use std::{
-        collections::VecDeque,
+        collections::{HashMap, VecDeque},
         pin::Pin,
         task::{Context, Poll},
     };
@@ -722,8 +979,9 @@ mod tests {
     use smol::io::{AsyncWrite, BufReader, Cursor};
 
     use super::{
-        enqueue_pending_privmsg, format_reply, message_lines, read_bounded_line, write_irc_frame,
-        ReplyType, MAX_IRC_LINE_LEN, MAX_PENDING_PRIVMSGS,
+        enqueue_pending_privmsg, format_reply, format_server_time, format_tags, message_lines,
+        parse_server_time, read_bounded_line, write_irc_frame, EventTags, ReplyType,
+        MAX_IRC_LINE_LEN, MAX_PENDING_PRIVMSGS,
     };
     use crate::Privmsg;
 
@@ -808,6 +1066,56 @@ mod tests {
         assert!(message_lines("\r\n").is_empty());
     }
 
+    #[test]
+    fn server_time_roundtrip() {
+        assert_eq!(format_server_time(1546612406123), "2019-01-04T14:33:26.123Z");
+        assert_eq!(parse_server_time("2019-01-04T14:33:26.123Z"), Some(1546612406123));
+        assert_eq!(parse_server_time("2019-01-04T14:33:26Z"), Some(1546612406000));
+        assert_eq!(parse_server_time("2024-02-29T00:00:00.5Z"), Some(1709164800500));
+        assert_eq!(parse_server_time("1970-01-01T00:00:00.000Z"), Some(0));
+
+        assert_eq!(parse_server_time("2019-01-04T14:33:26.123"), None);
+        assert_eq!(parse_server_time("2019-02-30T14:33:26.123Z"), None);
+        assert_eq!(parse_server_time("2019-01-04T14:33:26.Z"), None);
+        assert_eq!(parse_server_time("1969-12-31T23:59:59.000Z"), None);
+    }
+
+    #[test]
+    fn event_replies_are_tagged_by_enabled_caps() {
+        let tags = EventTags {
+            timestamp: 1546612406123,
+            id: blake3::hash(b"event"),
+            batch: Some("darkirc0".to_string()),
+        };
+        let reply = ReplyType::HistoricalClient((
+            "alice".to_string(),
+            "PRIVMSG #dev :hello".to_string(),
+            tags.clone(),
+            true,
+        ));
+
+        let mut caps = HashMap::from([
+            ("server-time".to_string(), false),
+            ("message-tags".to_string(), false),
+            ("batch".to_string(), false),
+        ]);
+        assert_eq!(format_tags(&reply, &caps), "");
+
+        caps.insert("server-time".to_string(), true);
+        assert_eq!(format_tags(&reply, &caps), "@time=2019-01-04T14:33:26.123Z ");
+
+        caps.insert("message-tags".to_string(), true);
+        caps.insert("batch".to_string(), true);
+        assert_eq!(
+            format_tags(&reply, &caps),
+            format!("@time=2019-01-04T14:33:26.123Z;msgid={};batch=darkirc0 ", tags.id)
+        );
+
+        // Non-event replies never carry tags
+        let reply = ReplyType::Client(("alice".to_string(), "JOIN :#dev".to_string()));
+        assert_eq!(format_tags(&reply, &caps), "");
+    }
+
     fn privmsg() -> Privmsg {
         Privmsg {
             version: 0,
@@ -49,22 +49,43 @@
 //! Some of the above commands could actually be implemented and could
 //! work in respect to the P2P network.
 
-use std::{collections::HashSet, sync::atomic::Ordering::SeqCst};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::atomic::Ordering::SeqCst,
+};
 
-use darkfi::Result;
+use darkfi::{
+    event_graph::{
+        proto::{RangeCursor, SyncDirection, MAX_RANGE_PAGE_SIZE},
+        redaction::Redaction,
+        Event, NULL_PARENTS,
+    },
+    Result,
+};
 use darkfi_serial::deserialize_async_partial;
 use tracing::{error, info, warn};
 
 use super::{
-    client::{Client, ReplyType},
+    client::{format_server_time, message_lines, parse_server_time, Client, EventTags, ReplyType},
+    read_markers::{is_mention, is_valid_client_id, split_client_id},
     rpl::*,
     server::{MAX_MSG_LEN, MAX_NICK_LEN},
     IrcChannel, SERVER_NAME,
 };
-use crate::crypto::bcrypt::bcrypt_hash_password;
+use crate::{crypto::bcrypt::bcrypt_hash_password, Privmsg};
 
 const MAX_TOPIC_LEN: usize = MAX_MSG_LEN;
 
+/// Maximum number of messages returned by a single `CHATHISTORY` request
+const CHATHISTORY_MAX_LIMIT: usize = 100;
+
+/// Maximum number of DAG pages scanned for a single `CHATHISTORY` request,
+/// so sparse targets can't turn a request into a walk over the whole DAG.
+const CHATHISTORY_MAX_PAGES: usize = 50;
+
+/// Maximum number of missed mentions sent as notices on (re)connection
+const MAX_MISSED_MENTIONS: usize = 10;
+
 fn is_identifier_char(byte: u8) -> bool {
     byte.is_ascii_alphanumeric() ||
         matches!(byte, b'-' | b'_' | b'[' | b']' | b'\\' | b'`' | b'^' | b'{' | b'}' | b'|')
@@ -100,6 +121,34 @@ fn invalid_syntax(nick: &str, command: &str) -> Vec<ReplyType> {
     vec![ReplyType::Server((ERR_NEEDMOREPARAMS, format!("{nick} {command} :{INVALID_SYNTAX}")))]
 }
 
+/// Build the notices summarizing the mentions missed while away. Only
+/// the latest `MAX_MISSED_MENTIONS` are listed.
+fn missed_mention_notices(nick: &str, mentions: &[(Privmsg, u64)]) -> Vec<ReplyType> {
+    if mentions.is_empty() {
+        return vec![]
+    }
+
+    let notice = |msg: String| ReplyType::Notice((SERVER_NAME.to_string(), nick.to_string(), msg));
+    let mut replies = vec![notice(format!("You were mentioned {} time(s):", mentions.len()))];
+
+    let skipped = mentions.len().saturating_sub(MAX_MISSED_MENTIONS);
+    if skipped > 0 {
+        replies.push(notice(format!("({skipped} earlier mention(s) not shown)")));
+    }
+
+    for (privmsg, timestamp) in &mentions[skipped..] {
+        let line = message_lines(&privmsg.msg).join(" ");
+        replies.push(notice(format!(
+            "[{}] {} <{}> {line}",
+            format_server_time(*timestamp),
+            privmsg.channel,
+            privmsg.nick
+        )));
+    }
+
+    replies
+}
+
 #[derive(Debug, PartialEq, Eq)]
 enum TopicRequest<'a> {
     Get { channel: &'a str },
@@ -132,6 +181,74 @@ fn parse_topic_request(args: &str) -> Option<TopicRequest<'_>> {
     Some(TopicRequest::Set { channel, topic })
 }
 
+/// Message reference of a `CHATHISTORY` request
+#[derive(Debug, PartialEq, Eq)]
+enum HistoryRef {
+    /// `timestamp=<server-time>`, in milliseconds
+    Timestamp(u64),
+    /// `msgid=<event id>`
+    MsgId(blake3::Hash),
+}
+
+fn parse_history_ref(reference: &str) -> Option<HistoryRef> {
+    if let Some(time) = reference.strip_prefix("timestamp=") {
+        return parse_server_time(time).map(HistoryRef::Timestamp)
+    }
+
+    if let Some(id) = reference.strip_prefix("msgid=") {
+        return blake3::Hash::from_hex(id).ok().map(HistoryRef::MsgId)
+    }
+
+    None
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum ChathistoryRequest<'a> {
+    Latest { target: &'a str, after: Option<HistoryRef>, limit: usize },
+    Before { target: &'a str, before: HistoryRef, limit: usize },
+    After { target: &'a str, after: HistoryRef, limit: usize },
+    Around { target: &'a str, around: HistoryRef, limit: usize },
+    Between { target: &'a str, start: HistoryRef, end: HistoryRef, limit: usize },
+}
+
+/// Parse a `CHATHISTORY` command. On failure, returns the standard
+/// reply code to send to the client.
+fn parse_chathistory_request(args: &str) -> std::result::Result<ChathistoryRequest<'_>, &str> {
+    let tokens: Vec<&str> = args.split_ascii_whitespace().collect();
+    let Some(subcommand) = tokens.first() else { return Err("NEED_MORE_PARAMS") };
+
+    let expected_len = match subcommand.to_uppercase().as_str() {
+        "LATEST" | "BEFORE" | "AFTER" | "AROUND" => 4,
+        "BETWEEN" => 5,
+        _ => return Err("UNKNOWN_COMMAND"),
+    };
+
+    if tokens.len() < expected_len {
+        return Err("NEED_MORE_PARAMS")
+    }
+
+    let target = tokens[1];
+    let limit = match tokens[expected_len - 1].parse::<usize>() {
+        Ok(0) | Err(_) => return Err("INVALID_PARAMS"),
+        Ok(limit) => limit.min(CHATHISTORY_MAX_LIMIT),
+    };
+
+    let reference = |idx: usize| parse_history_ref(tokens[idx]).ok_or("INVALID_PARAMS");
+
+    let request = match subcommand.to_uppercase().as_str() {
+        "LATEST" if tokens[2] == "*" => ChathistoryRequest::Latest { target, after: None, limit },
+        "LATEST" => ChathistoryRequest::Latest { target, after: Some(reference(2)?), limit },
+        "BEFORE" => ChathistoryRequest::Before { target, before: reference(2)?, limit },
+        "AFTER" => ChathistoryRequest::After { target, after: reference(2)?, limit },
+        "AROUND" => ChathistoryRequest::Around { target, around: reference(2)?, limit },
+        _ => {
+            ChathistoryRequest::Between { target, start: reference(2)?, end: reference(3)?, limit }
+        }
+    };
+
+    Ok(request)
+}
+
 impl Client {
     /// `ADMIN [<server>]`
     ///
@@ -262,6 +379,152 @@ impl Client {
         Ok(vec![ReplyType::Server((ERR_NEEDMOREPARAMS, format!("{nick} CAP :{INVALID_SYNTAX}")))])
     }
 
+    /// `CHATHISTORY <subcommand> <target> <reference> [<reference>] <limit>`
+    ///
+    /// Returns a page of the message history of `<target>` from the Event
+    /// Graph, following the IRCv3 `draft/chathistory` specification.
+    /// Supported subcommands are `LATEST`, `BEFORE`, `AFTER`, `AROUND`
+    /// and `BETWEEN`.
+    pub async fn handle_cmd_chathistory(&self, args: &str) -> Result<Vec<ReplyType>> {
+        if !self.registered.load(SeqCst) {
+            self.penalty.fetch_add(1, SeqCst);
+            return Ok(vec![ReplyType::Server((ERR_NOTREGISTERED, format!("* :{NOT_REGISTERED}")))])
+        }
+
+        let fail = |code: &str, desc: &str| -> Result<Vec<ReplyType>> {
+            Ok(vec![ReplyType::Fail((
+                "CHATHISTORY".to_string(),
+                code.to_string(),
+                desc.to_string(),
+            ))])
+        };
+
+        let request = match parse_chathistory_request(args) {
+            Ok(request) => request,
+            Err(code) => return fail(code, "Invalid CHATHISTORY request"),
+        };
+
+        let target = match &request {
+            ChathistoryRequest::Latest { target, .. } |
+            ChathistoryRequest::Before { target, .. } |
+            ChathistoryRequest::After { target, .. } |
+            ChathistoryRequest::Around { target, .. } |
+            ChathistoryRequest::Between { target, .. } => target.to_string(),
+        };
+
+        if !is_valid_channel_name(&target) &&
+            !self.server.contacts.read().await.contains_key(&target)
+        {
+            return fail(&format!("INVALID_TARGET {target}"), "Unknown target")
+        }
+
+        let messages = match request {
+            ChathistoryRequest::Latest { after, limit, .. } => {
+                let bound = match after {
+                    Some(r) => match self.history_ref_cursor(&r, SyncDirection::Forward).await? {
+                        Some(cursor) => Some(cursor),
+                        None => return fail("INVALID_PARAMS", "Unknown message reference"),
+                    },
+                    None => None,
+                };
+                self.collect_history(
+                    &target,
+                    RangeCursor::newest(),
+                    SyncDirection::Backward,
+                    bound,
+                    limit,
+                )
+                .await?
+            }
+
+            ChathistoryRequest::Before { before, limit, .. } => {
+                let dir = SyncDirection::Backward;
+                let Some(cursor) = self.history_ref_cursor(&before, dir.clone()).await? else {
+                    return fail("INVALID_PARAMS", "Unknown message reference")
+                };
+                self.collect_history(&target, cursor, dir, None, limit).await?
+            }
+
+            ChathistoryRequest::After { after, limit, .. } => {
+                let dir = SyncDirection::Forward;
+                let Some(cursor) = self.history_ref_cursor(&after, dir.clone()).await? else {
+                    return fail("INVALID_PARAMS", "Unknown message reference")
+                };
+                self.collect_history(&target, cursor, dir, None, limit).await?
+            }
+
+            ChathistoryRequest::Around { around, limit, .. } => {
+                let Some(cursor) = self.history_ref_cursor(&around, SyncDirection::Forward).await?
+                else {
+                    return fail("INVALID_PARAMS", "Unknown message reference")
+                };
+                // The referenced message itself is part of the older half.
+                let before_limit = limit - limit / 2;
+                let mut messages = self
+                    .collect_history(
+                        &target,
+                        cursor.successor(),
+                        SyncDirection::Backward,
+                        None,
+                        before_limit,
+                    )
+                    .await?;
+                if limit > before_limit {
+                    messages.extend(
+                        self.collect_history(
+                            &target,
+                            cursor,
+                            SyncDirection::Forward,
+                            None,
+                            limit - before_limit,
+                        )
+                        .await?,
+                    );
+                }
+                messages
+            }
+
+            ChathistoryRequest::Between { start, end, limit, .. } => {
+                let (Some(start_cursor), Some(end_cursor)) = (
+                    self.history_ref_cursor(&start, SyncDirection::Forward).await?,
+                    self.history_ref_cursor(&end, SyncDirection::Forward).await?,
+                ) else {
+                    return fail("INVALID_PARAMS", "Unknown message reference")
+                };
+
+                // Both ends are exclusive, so the lower end of the range
+                // has to exclude its whole timestamp going backwards.
+                let lower = |reference: &HistoryRef, cursor: RangeCursor| match reference {
+                    HistoryRef::Timestamp(ts) => RangeCursor::timestamp_start(*ts),
+                    HistoryRef::MsgId(_) => cursor,
+                };
+                if start_cursor <= end_cursor {
+                    let end_cursor = lower(&end, end_cursor);
+                    self.collect_history(
+                        &target,
+                        start_cursor,
+                        SyncDirection::Forward,
+                        Some(end_cursor),
+                        limit,
+                    )
+                    .await?
+                } else {
+                    let start_cursor = lower(&start, start_cursor);
+                    self.collect_history(
+                        &target,
+                        start_cursor,
+                        SyncDirection::Backward,
+                        Some(end_cursor),
+                        limit,
+                    )
+                    .await?
+                }
+            }
+        };
+
+        Ok(self.history_replies(&target, messages, true).await)
+    }
+
     /// `INFO [<target>]`
     ///
     /// Gives information about the `<target>` server, or the current server if
@@ -337,6 +600,8 @@ impl Client {
                     topic: String::new(),
                     nicks: HashSet::from([nick.clone()]),
                     saltbox: None,
+                    admin_public: None,
+                    admin_secret: None,
                 };
                 server_channels.insert(channel.clone(), chan);
             }
@@ -352,6 +617,11 @@ impl Client {
                     )));
                 }
             }
+
+            if self.cap_enabled("draft/read-marker").await {
+                let marker = self.server.read_markers.get(channel)?;
+                replies.push(ReplyType::MarkRead((channel.clone(), marker)));
+            }
         }
 
         // Drop the locks as they're used in get_history()
@@ -394,6 +664,50 @@ impl Client {
         Ok(replies)
     }
 
+    /// `MARKREAD <target> [timestamp=<timestamp>]`
+    ///
+    /// IRCv3 `draft/read-marker`. Without a timestamp, returns the read
+    /// marker of `<target>`. Otherwise moves it forward, which is relayed
+    /// to every client supporting read markers.
+    pub async fn handle_cmd_markread(&self, args: &str) -> Result<Vec<ReplyType>> {
+        if !self.registered.load(SeqCst) {
+            self.penalty.fetch_add(1, SeqCst);
+            return Ok(vec![ReplyType::Server((ERR_NOTREGISTERED, format!("* :{NOT_REGISTERED}")))])
+        }
+
+        let fail = |code: &str, desc: &str| -> Result<Vec<ReplyType>> {
+            Ok(vec![ReplyType::Fail(("MARKREAD".to_string(), code.to_string(), desc.to_string()))])
+        };
+
+        let mut tokens = args.split_ascii_whitespace();
+        let Some(target) = tokens.next() else { return fail("NEED_MORE_PARAMS", "Missing target") };
+
+        if !is_valid_channel_name(target) && !self.server.contacts.read().await.contains_key(target)
+        {
+            return fail("INVALID_PARAMS", "Invalid target")
+        }
+
+        let Some(timestamp) = tokens.next() else {
+            let marker = self.server.read_markers.get(target)?;
+            return Ok(vec![ReplyType::MarkRead((target.to_string(), marker))])
+        };
+
+        let Some(timestamp) = timestamp
+            .trim_start_matches(':')
+            .strip_prefix("timestamp=")
+            .and_then(parse_server_time)
+        else {
+            return fail("INVALID_PARAMS", "Invalid timestamp")
+        };
+
+        // If the marker moved, the update is relayed to all clients,
+        // including this one. Otherwise, reply with the newer marker.
+        match self.server.read_markers.advance(target, timestamp).await? {
+            (_, true) => Ok(vec![]),
+            (marker, false) => Ok(vec![ReplyType::MarkRead((target.to_string(), Some(marker)))]),
+        }
+    }
+
     /// `MODE <nickname> <flags>`
     /// `MODE <channel> <flags>`
     ///
@@ -610,13 +924,24 @@ impl Client {
         Ok(replies)
     }
 
-    /// `PASS <password>`
+    /// `PASS [<client_id>:]<password>`
     ///
     /// Used to set a connection `<password>`. If set, the password must
-    /// be set before USER/NICK commands.
+    /// be set before USER/NICK commands. The optional `<client_id>`
+    /// identifies the client, so each client of the same user gets the
+    /// messages it didn't see yet. Without a server password, `PASS
+    /// <client_id>` only sets the client ID.
     pub async fn handle_cmd_pass(&self, args: &str) -> Result<Vec<ReplyType>> {
         let nick = self.nickname.read().await.to_string();
 
+        if self.registered.load(SeqCst) {
+            self.penalty.fetch_add(1, SeqCst);
+            return Ok(vec![ReplyType::Server((
+                ERR_ALREADYREGISTERED,
+                format!("{nick} :{ALREADY_REGISTERED}"),
+            ))])
+        }
+
         // "the final parameter can be prepended with a (':', 0x3A) character"
         // <https://modern.ircdocs.horse/#parameters>
         let Some(i) = args.find(' ') else {
@@ -631,16 +956,42 @@ impl Client {
             password = &password[1..];
         }
 
-        if self.server.password == bcrypt_hash_password(password) {
+        // Without a server password, the parameter is the client ID alone
+        if self.server.password.is_empty() {
+            let client_id = password.strip_suffix(':').unwrap_or(password);
+            if !is_valid_client_id(client_id) {
+                return Ok(invalid_syntax(&nick, "PASS"))
+            }
+
+            *self.client_id.write().await = Some(client_id.to_string());
             self.is_pass_set.store(true, SeqCst);
-        } else {
-            error!("[IRC CLIENT] Password is not correct!");
-            return Ok(vec![ReplyType::Server((
-                ERR_PASSWDMISMATCH,
-                format!("{nick} PASS :{PASSWORD_MISMATCH}"),
-            ))])
+            return Ok(vec![])
         }
 
+        // The whole parameter is tried as the password first, since
+        // passwords may contain colons too.
+        let client_id = if self.server.password == bcrypt_hash_password(password) {
+            None
+        } else {
+            match split_client_id(password) {
+                Some((client_id, password))
+                    if self.server.password == bcrypt_hash_password(password) =>
+                {
+                    Some(client_id)
+                }
+                _ => {
+                    error!("[IRC CLIENT] Password is not correct!");
+                    return Ok(vec![ReplyType::Server((
+                        ERR_PASSWDMISMATCH,
+                        format!("{nick} PASS :{PASSWORD_MISMATCH}"),
+                    ))])
+                }
+            }
+        };
+
+        *self.client_id.write().await = client_id.map(str::to_string);
+        self.is_pass_set.store(true, SeqCst);
+
         Ok(vec![])
     }
 
@@ -708,7 +1059,12 @@ impl Client {
                     format!("{nick} :Invalid recipient given (PRIVMSG)"),
                 ))])
             }
-        } else if !target.eq_ignore_ascii_case("nickserv") && !is_valid_nickname(target) {
+        } else if !target.eq_ignore_ascii_case("nickserv") &&
+            !target.eq_ignore_ascii_case("chanserv") &&
+            !target.eq_ignore_ascii_case("fileserv") &&
+            !target.eq_ignore_ascii_case("searchserv") &&
+            !is_valid_nickname(target)
+        {
             self.penalty.fetch_add(1, SeqCst);
             return Ok(vec![ReplyType::Server((ERR_NOSUCHNICK, format!("{nick} :{target}")))])
         }
@@ -729,6 +1085,35 @@ impl Client {
             return self.nickserv.handle_query(args).await
         }
 
+        // Handle queries to ChanServ
+        if target.to_lowercase().as_str() == "chanserv" {
+            return self.chanserv.handle_query(args).await
+        }
+
+        // Handle queries to FileServ
+        if target.to_lowercase().as_str() == "fileserv" {
+            return self.fileserv.handle_query(args).await
+        }
+
+        // Handle queries to SearchServ
+        if target.to_lowercase().as_str() == "searchserv" {
+            return self.searchserv.handle_query(args).await
+        }
+
+        // An admin managed channel without a known key can't be written
+        // to, since we must not send plaintext to it.
+        if let Some(channel) = self.server.channels.read().await.get(target) {
+            if channel.saltbox.is_none() &&
+                channel.admin_public.is_some() &&
+                self.server.channel_keys.current(target).await.is_none()
+            {
+                return Ok(vec![ReplyType::Server((
+                    ERR_CANNOTSENDTOCHAN,
+                    format!("{nick} {target} :Channel key not received yet"),
+                ))])
+            }
+        }
+
         // If it's a DM and we don't have an encryption key, we will
         // refuse to send it. Send ERR_NORECIPIENT to the client.
         if !target.starts_with('#') && !self.server.contacts.read().await.contains_key(target) {
@@ -944,6 +1329,13 @@ impl Client {
                     env!("CARGO_PKG_VERSION")
                 ),
             )),
+            ReplyType::Server((
+                RPL_ISUPPORT,
+                format!(
+                    "{nick} CHATHISTORY={CHATHISTORY_MAX_LIMIT} MSGREFTYPES=msgid,timestamp \
+                     :are supported by this server"
+                ),
+            )),
         ];
 
         // Append the MOTD
@@ -985,24 +1377,39 @@ impl Client {
         Ok(replies)
     }
 
-    /// Internal function that scans the DAG and returns events for
-    /// given channels. Will return empty if no_history CAP is requested.
+    /// Internal function that scans the DAG and returns the unseen and
+    /// unread events for given channels, followed by notices for the
+    /// mentions of our nick among them. Only the notices are returned if
+    /// no_history CAP is requested, or if the client fetches history on
+    /// demand with `draft/chathistory`. The read markers of such clients
+    /// are then moved past the scanned messages, so the same mentions
+    /// aren't notified again on the next connection or JOIN.
     // N.b. the handling of "live messages" is implemented
     // <file:./client.rs::r = self.incoming.receive().fuse() => {>
     // for which the logic for delivery should be kept in sync
     async fn get_history(&self, channels: &HashSet<String>) -> Result<Vec<ReplyType>> {
         let has_contacts = !self.server.contacts.read().await.is_empty();
-        if (channels.is_empty() && !has_contacts) ||
-            *self.caps.read().await.get("no-history").unwrap()
-        {
+        if channels.is_empty() && !has_contacts {
             return Ok(vec![])
         }
 
+        let replay =
+            !self.cap_enabled("no-history").await && !self.cap_enabled("draft/chathistory").await;
+        let nick = self.nickname.read().await.to_string();
+
         // Fetch and order all the events from the DAG
         let dag_events = self.server.darkirc.event_graph.order_events().await?;
 
-        // Here we'll hold the events in order we'll push to the client
-        let mut replies = vec![];
+        // Here we'll hold the messages of each target, in the order
+        // we'll push them to the client
+        let mut targets: Vec<(String, Vec<(Privmsg, EventTags)>)> = vec![];
+        // Messages mentioning us, along with their timestamp
+        let mut mentions: Vec<(Privmsg, u64)> = vec![];
+        // Read markers of the targets, fetched once per target
+        let mut read_markers: HashMap<String, Option<u64>> = HashMap::new();
+        // Timestamp of the newest scanned message of each target, when
+        // not replaying
+        let mut scanned: HashMap<String, u64> = HashMap::new();
 
         for event in dag_events.iter() {
             let event_id = event.header.id();
@@ -1016,19 +1423,8 @@ impl Client {
                 }
             }
 
-            // Try to deserialize it. (Here we skip errors)
-            let mut privmsg = match deserialize_async_partial(event.content()).await {
-                Ok((v, _)) => v,
-                Err(_) => continue,
-            };
-
-            // Potentially decrypt the privmsg
-            self.server.try_decrypt(&mut privmsg, self.nickname.read().await.as_ref()).await;
-
-            // We should skip any attempts to contact services from the network.
-            if ["nickserv", "chanserv"].contains(&privmsg.nick.to_lowercase().as_str()) {
-                continue
-            }
+            // Try to deserialize and decrypt it. (Here we skip errors)
+            let Some(privmsg) = self.event_privmsg(event).await else { continue };
 
             // If the PRIVMSG is intended for any of the given
             // channels or contacts, add it as a reply and
@@ -1039,8 +1435,7 @@ impl Client {
                 continue
             }
 
-            let lines = super::client::message_lines(&privmsg.msg);
-            if lines.is_empty() {
+            if message_lines(&privmsg.msg).is_empty() {
                 warn!(
                     "[IRC CLIENT] Refusing to mark historical event {event_id} as seen: \
                      PRIVMSG has no deliverable lines"
@@ -1048,33 +1443,228 @@ impl Client {
                 continue
             }
 
+            // Messages up to the read marker were read on another client
+            let read_marker = match read_markers.get(&privmsg.channel) {
+                Some(marker) => *marker,
+                None => {
+                    let marker = self.server.read_markers.get(&privmsg.channel)?;
+                    read_markers.insert(privmsg.channel.clone(), marker);
+                    marker
+                }
+            };
+            if read_marker.is_some_and(|marker| event.header.timestamp <= marker) {
+                continue
+            }
+
             // Insert nicks into channels
             if let Some(chan) = self.server.channels.write().await.get_mut(&privmsg.channel) {
                 chan.nicks.insert(privmsg.nick.clone());
             }
 
+            if privmsg.nick != nick && is_mention(&privmsg.msg, &nick) {
+                mentions.push((privmsg.clone(), event.header.timestamp));
+            }
+
+            if !replay {
+                let newest = scanned.entry(privmsg.channel.clone()).or_default();
+                *newest = (*newest).max(event.header.timestamp);
+                continue
+            }
+
+            let tags = EventTags::new(event);
+            match targets.iter_mut().find(|(target, _)| *target == privmsg.channel) {
+                Some((_, messages)) => messages.push((privmsg, tags)),
+                None => targets.push((privmsg.channel.clone(), vec![(privmsg, tags)])),
+            }
+        }
+
+        let mut replies = vec![];
+        for (target, messages) in targets {
+            replies.extend(self.history_replies(&target, messages, false).await);
+        }
+
+        replies.extend(missed_mention_notices(&nick, &mentions));
+
+        // Once notified, the scanned messages don't need to be scanned
+        // again, the client loads them on demand.
+        for (target, timestamp) in scanned {
+            self.server.read_markers.advance(&target, timestamp).await?;
+        }
+
+        Ok(replies)
+    }
+
+    /// Internal helper that deserializes and decrypts the `Privmsg` of an
+    /// event. Returns `None` for anything that isn't deliverable to the client.
+    async fn event_privmsg(&self, event: &Event) -> Option<Privmsg> {
+        if Redaction::from_event(event).is_some() {
+            return None
+        }
+
+        let (mut privmsg, _): (Privmsg, _) =
+            deserialize_async_partial(event.content()).await.ok()?;
+
+        // Potentially decrypt the privmsg. Direct message handshakes
+        // and channel rekeys are consumed internally.
+        let nick = self.nickname.read().await.to_string();
+        if !self.server.try_decrypt(&mut privmsg, &nick, event.header.timestamp).await {
+            return None
+        }
+
+        // We should skip any attempts to contact services from the network.
+        if ["nickserv", "chanserv", "fileserv", "searchserv"]
+            .contains(&privmsg.nick.to_lowercase().as_str())
+        {
+            return None
+        }
+
+        Some(privmsg)
+    }
+
+    /// Internal helper that turns a `CHATHISTORY` reference into an
+    /// exclusive pagination cursor. A message id points at the message
+    /// itself, while a timestamp excludes every message sent at that
+    /// timestamp when loading in the direction `dir`.
+    /// Returns `None` if the referenced event is unknown.
+    async fn history_ref_cursor(
+        &self,
+        reference: &HistoryRef,
+        dir: SyncDirection,
+    ) -> Result<Option<RangeCursor>> {
+        match reference {
+            HistoryRef::Timestamp(ts) => Ok(Some(match dir {
+                SyncDirection::Forward => RangeCursor::timestamp_end(*ts),
+                SyncDirection::Backward => RangeCursor::timestamp_start(*ts),
+            })),
+            HistoryRef::MsgId(id) => Ok(self
+                .server
+                .darkirc
+                .event_graph
+                .fetch_event_from_dags(id)
+                .await?
+                .map(|event| RangeCursor { timestamp: event.header.timestamp, event_id: *id })),
+        }
+    }
+
+    /// Internal helper that pages through the DAGs starting at `cursor`
+    /// (exclusive) and collects up to `limit` messages sent to `target`,
+    /// stopping at `bound` (exclusive) if given. The returned messages
+    /// are in chronological order.
+    async fn collect_history(
+        &self,
+        target: &str,
+        mut cursor: RangeCursor,
+        dir: SyncDirection,
+        bound: Option<RangeCursor>,
+        limit: usize,
+    ) -> Result<Vec<(Privmsg, EventTags)>> {
+        let backward = matches!(dir, SyncDirection::Backward);
+        let mut messages = vec![];
+
+        'pages: for _ in 0..CHATHISTORY_MAX_PAGES {
+            let (page, next_cursor) = self
+                .server
+                .darkirc
+                .event_graph
+                .fetch_page_at(cursor, dir.clone(), MAX_RANGE_PAGE_SIZE)
+                .await?;
+
+            if page.is_empty() {
+                break
+            }
+
+            for event in page {
+                let position =
+                    RangeCursor { timestamp: event.header.timestamp, event_id: event.id() };
+                let past_bound = match bound {
+                    Some(bound) if backward => position <= bound,
+                    Some(bound) => position >= bound,
+                    None => false,
+                };
+                if past_bound {
+                    break 'pages
+                }
+
+                if event.header.parents == NULL_PARENTS {
+                    continue
+                }
+
+                let Some(privmsg) = self.event_privmsg(&event).await else { continue };
+                if privmsg.channel != target || message_lines(&privmsg.msg).is_empty() {
+                    continue
+                }
+
+                messages.push((privmsg, EventTags::new(&event)));
+                if messages.len() >= limit {
+                    break 'pages
+                }
+            }
+
+            cursor = next_cursor;
+        }
+
+        if backward {
+            messages.reverse();
+        }
+
+        Ok(messages)
+    }
+
+    /// Internal helper that turns the messages of a single target into
+    /// historical replies. They are wrapped in a `chathistory` batch if the
+    /// client enabled `batch`. Empty batches are only sent when `always`
+    /// is set, as `CHATHISTORY` requests expect a batch in any case.
+    async fn history_replies(
+        &self,
+        target: &str,
+        messages: Vec<(Privmsg, EventTags)>,
+        always: bool,
+    ) -> Vec<ReplyType> {
+        if messages.is_empty() && !always {
+            return vec![]
+        }
+
+        let batch = self.cap_enabled("batch").await.then(|| self.next_batch_ref());
+        let mut replies = vec![];
+
+        if let Some(ref batch) = batch {
+            replies.push(ReplyType::Batch(format!("+{batch} chathistory {target}")));
+        }
+
+        for (privmsg, mut tags) in messages {
+            tags.batch = batch.clone();
+
             // Handle message lines individually
+            let lines = message_lines(&privmsg.msg);
             for (index, line) in lines.iter().enumerate() {
                 // Format the message
                 let msg = format!("PRIVMSG {} :{line}", privmsg.channel);
 
                 // Mark the event seen only after its final line is written.
-                let delivered_event = (index + 1 == lines.len()).then_some(event_id);
                 replies.push(ReplyType::HistoricalClient((
                     privmsg.nick.clone(),
                     msg,
-                    delivered_event,
+                    tags.clone(),
+                    index + 1 == lines.len(),
                 )));
             }
         }
 
-        Ok(replies)
+        if let Some(batch) = batch {
+            replies.push(ReplyType::Batch(format!("-{batch}")));
+        }
+
+        replies
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{parse_topic_request, TopicRequest};
+    use super::{
+        missed_mention_notices, parse_chathistory_request, parse_topic_request, ChathistoryRequest,
+        HistoryRef, ReplyType, TopicRequest, CHATHISTORY_MAX_LIMIT, MAX_MISSED_MENTIONS,
+    };
+    use crate::Privmsg;
 
     #[test]
     fn parse_topic_request_gets_current_topic() {
@@ -1124,4 +1714,90 @@ mod tests {
         assert!(!super::is_valid_topic(&"a".repeat(super::MAX_TOPIC_LEN + 1)));
         assert!(!super::is_valid_topic("bad\nline"));
     }
+
+    #[test]
+    fn parse_chathistory_request_accepts_references() {
+        assert_eq!(
+            parse_chathistory_request(" LATEST #dev * 50"),
+            Ok(ChathistoryRequest::Latest { target: "#dev", after: None, limit: 50 })
+        );
+
+        assert_eq!(
+            parse_chathistory_request(" BEFORE #dev timestamp=2019-01-04T14:33:26.123Z 10"),
+            Ok(ChathistoryRequest::Before {
+                target: "#dev",
+                before: HistoryRef::Timestamp(1546612406123),
+                limit: 10,
+            })
+        );
+
+        let id = blake3::hash(b"event");
+        assert_eq!(
+            parse_chathistory_request(&format!(" after alice msgid={id} 10")),
+            Ok(ChathistoryRequest::After {
+                target: "alice",
+                after: HistoryRef::MsgId(id),
+                limit: 10
+            })
+        );
+
+        assert_eq!(
+            parse_chathistory_request(&format!(
+                " BETWEEN #dev msgid={id} timestamp=2019-01-04T14:33:26.123Z 1000"
+            )),
+            Ok(ChathistoryRequest::Between {
+                target: "#dev",
+                start: HistoryRef::MsgId(id),
+                end: HistoryRef::Timestamp(1546612406123),
+                limit: CHATHISTORY_MAX_LIMIT,
+            })
+        );
+    }
+
+    #[test]
+    fn parse_chathistory_request_rejects_invalid_requests() {
+        assert_eq!(parse_chathistory_request(""), Err("NEED_MORE_PARAMS"));
+        assert_eq!(parse_chathistory_request(" TARGETS * * 10"), Err("UNKNOWN_COMMAND"));
+        assert_eq!(parse_chathistory_request(" BEFORE #dev 10"), Err("NEED_MORE_PARAMS"));
+        assert_eq!(parse_chathistory_request(" LATEST #dev * 0"), Err("INVALID_PARAMS"));
+        assert_eq!(parse_chathistory_request(" BEFORE #dev * 10"), Err("INVALID_PARAMS"));
+        assert_eq!(
+            parse_chathistory_request(" AROUND #dev msgid=nothex 10"),
+            Err("INVALID_PARAMS")
+        );
+    }
+
+    #[test]
+    fn missed_mention_notices_list_the_latest_mentions() {
+        assert!(missed_mention_notices("alice", &[]).is_empty());
+
+        let mentions: Vec<(Privmsg, u64)> = (0..MAX_MISSED_MENTIONS as u64 + 2)
+            .map(|i| {
+                let privmsg = Privmsg {
+                    version: 0,
+                    msg_type: 0,
+                    channel: "#dev".to_string(),
+                    nick: "bob".to_string(),
+                    msg: format!("alice: ping {i}"),
+                };
+                (privmsg, 1546612406123 + i)
+            })
+            .collect();
+
+        let notices: Vec<String> = missed_mention_notices("alice", &mentions)
+            .into_iter()
+            .map(|reply| match reply {
+                ReplyType::Notice((_, dst, msg)) => {
+                    assert_eq!(dst, "alice");
+                    msg
+                }
+                _ => panic!("expected a notice"),
+            })
+            .collect();
+
+        assert_eq!(notices.len(), MAX_MISSED_MENTIONS + 2);
+        assert_eq!(notices[0], format!("You were mentioned {} time(s):", mentions.len()));
+        assert_eq!(notices[1], "(2 earlier mention(s) not shown)");
+        assert_eq!(notices[2], "[2019-01-04T14:33:26.125Z] #dev <bob> alice: ping 2");
+    }
 }
@@ -0,0 +1,565 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2026 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Forward-secret direct messages.
+//!
+//! Direct messages are always wrapped in the static `ChaChaBox` of the
+//! contact, so the message layout on the network stays the same. Inside
+//! of it, the payload is either:
+//!
+//! * plain text, for contacts which don't support forward secrecy, or
+//! * a prekey bundle, telling the contact which prekey to use to start
+//!   a session with us, or
+//! * a double ratchet message.
+//!
+//! Prekey bundles and ratchet messages start with a NUL byte, which
+//! can't appear in IRC text, so they never collide with plain text.
+//!
+//! Our prekey bundle is published as its own event right before the
+//! first direct message sent to a contact, and again whenever the
+//! prekey is rotated. It's only marked as published once its event
+//! made it into our DAG. Once we know a contact's prekey, messages to
+//! them go through a double ratchet session. Until then, and for
+//! contacts running older versions, the static box alone is used.
+//!
+//! Ratchet message keys are deleted once used, so the plaintext of
+//! every ratchet message is stored locally, in order to be able to
+//! replay history to IRC clients. Stored plaintexts are encrypted with
+//! a key derived from our static secret for the contact, which lives in
+//! the config rather than in the database, and are deleted after
+//! [`PLAINTEXT_RETENTION_SECS`].
+
+use darkfi::{util::time::Timestamp, Result};
+use darkfi_serial::{
+    async_trait, deserialize_async, serialize_async, SerialDecodable, SerialEncodable,
+};
+use sled_overlay::sled;
+use smol::lock::Mutex;
+use tracing::{debug, info, warn};
+
+use super::{
+    files::{decrypt_file, encrypt_file},
+    IrcContact,
+};
+use crate::crypto::ratchet::{KeyPair, PrekeyBundle, RatchetMessage, Session};
+
+/// Prefix of prekey bundle payloads
+const PREKEY_PAYLOAD_PREFIX: &[u8] = b"\x00darkirc-prekey-v1\x00";
+
+/// Prefix of double ratchet message payloads
+const RATCHET_PAYLOAD_PREFIX: &[u8] = b"\x00darkirc-ratchet-v1\x00";
+
+/// Our prekey for a contact is rotated after this many seconds
+pub const PREKEY_ROTATION_SECS: u64 = 7 * 24 * 60 * 60;
+
+/// Old prekeys are kept for this many seconds after rotation, so
+/// sessions started with them while the new bundle propagates can
+/// still be set up. They are deleted afterwards.
+pub const PREKEY_RETENTION_SECS: u64 = 30 * 24 * 60 * 60;
+
+/// Stored plaintexts of ratchet messages are deleted after this many
+/// seconds. History older than this is replayed as undecryptable.
+pub const PLAINTEXT_RETENTION_SECS: u64 = 30 * 24 * 60 * 60;
+
+/// Maximum number of sessions kept per contact. More than one session
+/// exists when both sides start one at the same time.
+const MAX_SESSIONS_PER_CONTACT: usize = 4;
+
+/// Text delivered in place of ratchet messages that can't be decrypted
+pub const UNDECRYPTABLE_MESSAGE: &str = "[unable to decrypt forward-secret message]";
+
+const DM_PREKEYS_TREE: &str = "darkirc_dm_prekeys";
+const DM_BUNDLES_TREE: &str = "darkirc_dm_bundles";
+const DM_SESSIONS_TREE: &str = "darkirc_dm_sessions";
+const DM_PLAINTEXTS_TREE: &str = "darkirc_dm_plaintexts_v2";
+
+/// Tree of the previous versions, holding plaintexts in the clear
+const DM_LEGACY_PLAINTEXTS_TREE: &str = "darkirc_dm_plaintexts";
+
+/// Key encrypting the stored plaintexts of the messages with a contact
+fn plaintext_key(contact: &IrcContact) -> [u8; 32] {
+    blake3::derive_key("darkirc dm plaintext store v1", &contact.secret_key.to_bytes())
+}
+
+/// Our prekeys for a single contact
+#[derive(Clone, SerialEncodable, SerialDecodable)]
+struct OwnPrekeys {
+    /// Current prekey
+    current: KeyPair,
+    /// Creation time of the current prekey, in seconds
+    created: u64,
+    /// Whether the current prekey was published to the contact
+    published: bool,
+    /// Rotated prekeys, along with their rotation time
+    previous: Vec<(KeyPair, u64)>,
+}
+
+/// Decrypted payload of a direct message
+#[derive(Debug, PartialEq, Eq)]
+pub enum DmPayload {
+    /// Text to deliver to the IRC client
+    Text(String),
+    /// Handshake message consumed internally, not to be delivered
+    Handshake,
+}
+
+/// Double ratchet state of all contacts, persisted in sled.
+/// Contacts are identified by their public key, so renaming a contact
+/// in the config keeps its sessions.
+pub struct DmSessions {
+    prekeys: sled::Tree,
+    bundles: sled::Tree,
+    sessions: sled::Tree,
+    plaintexts: sled::Tree,
+    /// Serializes state updates, as several IRC clients can try to
+    /// decrypt the same message concurrently.
+    lock: Mutex<()>,
+}
+
+impl DmSessions {
+    pub fn new(sled_db: &sled::Db) -> Result<Self> {
+        if sled_db.drop_tree(DM_LEGACY_PLAINTEXTS_TREE)? {
+            info!(target: "darkirc::irc::dm", "Deleted cleartext direct message store");
+        }
+
+        let self_ = Self {
+            prekeys: sled_db.open_tree(DM_PREKEYS_TREE)?,
+            bundles: sled_db.open_tree(DM_BUNDLES_TREE)?,
+            sessions: sled_db.open_tree(DM_SESSIONS_TREE)?,
+            plaintexts: sled_db.open_tree(DM_PLAINTEXTS_TREE)?,
+            lock: Mutex::new(()),
+        };
+        self_.prune_plaintexts()?;
+        Ok(self_)
+    }
+
+    /// Returns the prekey bundle payload to publish before messaging the
+    /// given contact, along with the prekey creation time, if our current
+    /// prekey for them wasn't published yet. Rotates the prekey when it is
+    /// due and drops expired ones. Call [`Self::mark_bundle_published()`]
+    /// once the bundle was sent.
+    pub async fn bundle_to_publish(&self, contact: &IrcContact) -> Result<Option<(Vec<u8>, u64)>> {
+        let _lock = self.lock.lock().await;
+        let now = Timestamp::current_time().inner();
+        let key = contact.public_key.as_bytes();
+
+        let mut prekeys = match self.prekeys.get(key)? {
+            Some(bytes) => deserialize_async::<OwnPrekeys>(&bytes).await?,
+            None => OwnPrekeys {
+                current: KeyPair::generate(),
+                created: now,
+                published: false,
+                previous: vec![],
+            },
+        };
+
+        if now.saturating_sub(prekeys.created) >= PREKEY_ROTATION_SECS {
+            let rotated = std::mem::replace(&mut prekeys.current, KeyPair::generate());
+            prekeys.previous.push((rotated, now));
+            prekeys.created = now;
+            prekeys.published = false;
+            debug!(target: "darkirc::irc::dm", "Rotated prekey for contact");
+        }
+        prekeys
+            .previous
+            .retain(|(_, rotated)| now.saturating_sub(*rotated) < PREKEY_RETENTION_SECS);
+
+        let payload = if prekeys.published {
+            None
+        } else {
+            let bundle =
+                PrekeyBundle { prekey: prekeys.current.public, timestamp: prekeys.created };
+            let payload = [PREKEY_PAYLOAD_PREFIX, &serialize_async(&bundle).await].concat();
+            Some((payload, prekeys.created))
+        };
+
+        self.prekeys.insert(key, serialize_async(&prekeys).await)?;
+        Ok(payload)
+    }
+
+    /// Mark the bundle of the prekey created at `created` as published,
+    /// unless it was rotated in the meantime.
+    pub async fn mark_bundle_published(&self, contact: &IrcContact, created: u64) -> Result<()> {
+        let _lock = self.lock.lock().await;
+        let key = contact.public_key.as_bytes();
+
+        let Some(bytes) = self.prekeys.get(key)? else { return Ok(()) };
+        let mut prekeys: OwnPrekeys = deserialize_async(&bytes).await?;
+        if prekeys.created != created || prekeys.published {
+            return Ok(())
+        }
+
+        prekeys.published = true;
+        self.prekeys.insert(key, serialize_async(&prekeys).await)?;
+        Ok(())
+    }
+
+    /// Encrypt a message for the given contact with a double ratchet
+    /// session, starting one if we know their prekey. Returns `None` if
+    /// the contact doesn't support forward secrecy yet.
+    pub async fn encrypt(&self, contact: &IrcContact, plaintext: &str) -> Result<Option<Vec<u8>>> {
+        let _lock = self.lock.lock().await;
+        let key = contact.public_key.as_bytes();
+        let mut sessions = self.load_sessions(key).await?;
+
+        // Use the most recently active session able to send
+        let mut session = match sessions.iter().position(|s| s.can_send()) {
+            Some(idx) => sessions.remove(idx),
+            None => {
+                let Some(bundle) = self.bundles.get(key)? else { return Ok(None) };
+                let bundle: PrekeyBundle = deserialize_async(&bundle).await?;
+                let identity = KeyPair::from_secret(contact.secret_key.to_bytes());
+                let Some(session) = Session::initiate(&identity, key, &bundle.prekey) else {
+                    warn!(target: "darkirc::irc::dm", "Contact prekey is invalid, not using it");
+                    return Ok(None)
+                };
+                info!(target: "darkirc::irc::dm", "Started forward-secret session with contact");
+                session
+            }
+        };
+
+        let Some(message) = session.encrypt(plaintext.as_bytes()) else { return Ok(None) };
+        sessions.insert(0, session);
+        self.save_sessions(key, sessions).await?;
+
+        Ok(Some([RATCHET_PAYLOAD_PREFIX, &serialize_async(&message).await].concat()))
+    }
+
+    /// Remember the plaintext of a message we sent, identified by its
+    /// encrypted `msg` field, since we can't decrypt it ourselves.
+    pub fn remember_plaintext(
+        &self,
+        contact: &IrcContact,
+        ciphertext: &str,
+        plaintext: &str,
+    ) -> Result<()> {
+        self.store_plaintext(contact, &blake3::hash(ciphertext.as_bytes()), plaintext)
+    }
+
+    /// Store the plaintext of a ratchet message, encrypted with the
+    /// plaintext key of the contact and prefixed with the storage time.
+    fn store_plaintext(
+        &self,
+        contact: &IrcContact,
+        cache_key: &blake3::Hash,
+        plaintext: &str,
+    ) -> Result<()> {
+        let now = Timestamp::current_time().inner();
+        let sealed = encrypt_file(&plaintext_key(contact), plaintext.as_bytes())?;
+        self.plaintexts.insert(cache_key.as_bytes(), [&now.to_be_bytes()[..], &sealed].concat())?;
+        Ok(())
+    }
+
+    /// Load a stored plaintext, unless it expired.
+    fn load_plaintext(
+        &self,
+        contact: &IrcContact,
+        cache_key: &blake3::Hash,
+    ) -> Result<Option<String>> {
+        let Some(value) = self.plaintexts.get(cache_key.as_bytes())? else { return Ok(None) };
+        if plaintext_expired(&value) {
+            self.plaintexts.remove(cache_key.as_bytes())?;
+            return Ok(None)
+        }
+
+        Ok(decrypt_file(&plaintext_key(contact), &value[8..])
+            .map(|plaintext| String::from_utf8_lossy(&plaintext).into()))
+    }
+
+    /// Delete the stored plaintexts older than [`PLAINTEXT_RETENTION_SECS`]
+    pub fn prune_plaintexts(&self) -> Result<usize> {
+        let mut batch = sled::Batch::default();
+        let mut pruned = 0;
+        for entry in self.plaintexts.iter() {
+            let (key, value) = entry?;
+            if plaintext_expired(&value) {
+                batch.remove(key);
+                pruned += 1;
+            }
+        }
+
+        if pruned > 0 {
+            self.plaintexts.apply_batch(batch)?;
+            debug!(target: "darkirc::irc::dm", "Deleted {pruned} expired plaintexts");
+        }
+        Ok(pruned)
+    }
+
+    /// Handle the payload of a direct message decrypted with the static
+    /// box of `contact`. `ciphertext` is the encrypted `msg` field, used
+    /// to find messages which were already decrypted. `own` is set for
+    /// messages we sent ourselves.
+    pub async fn open_payload(
+        &self,
+        contact: &IrcContact,
+        ciphertext: &str,
+        payload: &[u8],
+        own: bool,
+    ) -> Result<DmPayload> {
+        if let Some(bundle) = payload.strip_prefix(PREKEY_PAYLOAD_PREFIX) {
+            if !own {
+                self.store_bundle(contact, bundle).await?;
+            }
+            return Ok(DmPayload::Handshake)
+        }
+
+        let Some(message) = payload.strip_prefix(RATCHET_PAYLOAD_PREFIX) else {
+            return Ok(DmPayload::Text(String::from_utf8_lossy(payload).into()))
+        };
+
+        let _lock = self.lock.lock().await;
+        let cache_key = blake3::hash(ciphertext.as_bytes());
+        if let Some(plaintext) = self.load_plaintext(contact, &cache_key)? {
+            return Ok(DmPayload::Text(plaintext))
+        }
+
+        if own {
+            return Ok(DmPayload::Text(UNDECRYPTABLE_MESSAGE.to_string()))
+        }
+
+        let Ok(message) = deserialize_async::<RatchetMessage>(message).await else {
+            return Ok(DmPayload::Text(UNDECRYPTABLE_MESSAGE.to_string()))
+        };
+
+        let Some(plaintext) = self.ratchet_decrypt(contact, &message).await? else {
+            warn!(target: "darkirc::irc::dm", "Failed decrypting forward-secret message");
+            return Ok(DmPayload::Text(UNDECRYPTABLE_MESSAGE.to_string()))
+        };
+
+        let plaintext = String::from_utf8_lossy(&plaintext).to_string();
+        self.store_plaintext(contact, &cache_key, &plaintext)?;
+        Ok(DmPayload::Text(plaintext))
+    }
+
+    async fn store_bundle(&self, contact: &IrcContact, bundle: &[u8]) -> Result<()> {
+        let Ok(bundle) = deserialize_async::<PrekeyBundle>(bundle).await else {
+            warn!(target: "darkirc::irc::dm", "Received invalid prekey bundle");
+            return Ok(())
+        };
+
+        let _lock = self.lock.lock().await;
+        let key = contact.public_key.as_bytes();
+        if let Some(known) = self.bundles.get(key)? {
+            let known: PrekeyBundle = deserialize_async(&known).await?;
+            if known.timestamp >= bundle.timestamp {
+                return Ok(())
+            }
+        }
+
+        debug!(target: "darkirc::irc::dm", "Stored new prekey bundle of contact");
+        self.bundles.insert(key, serialize_async(&bundle).await)?;
+        Ok(())
+    }
+
+    /// Try decrypting a ratchet message with the known sessions of the
+    /// contact, or set up a new session from its key agreement data.
+    async fn ratchet_decrypt(
+        &self,
+        contact: &IrcContact,
+        message: &RatchetMessage,
+    ) -> Result<Option<Vec<u8>>> {
+        let key = contact.public_key.as_bytes();
+        let mut sessions = self.load_sessions(key).await?;
+
+        for idx in 0..sessions.len() {
+            if let Some(plaintext) = sessions[idx].decrypt(message) {
+                // The session the contact last used becomes the active one
+                let session = sessions.remove(idx);
+                sessions.insert(0, session);
+                self.save_sessions(key, sessions).await?;
+                return Ok(Some(plaintext))
+            }
+        }
+
+        let Some(init) = &message.header.init else { return Ok(None) };
+        if sessions.iter().any(|s| s.id == init.session_id()) {
+            return Ok(None)
+        }
+
+        let Some(prekey) = self.find_prekey(key, &init.prekey).await? else {
+            warn!(target: "darkirc::irc::dm", "Forward-secret message uses an unknown prekey");
+            return Ok(None)
+        };
+
+        let identity = KeyPair::from_secret(contact.secret_key.to_bytes());
+        let Some(mut session) = Session::respond(&identity, key, &prekey, init) else {
+            return Ok(None)
+        };
+        let Some(plaintext) = session.decrypt(message) else { return Ok(None) };
+
+        info!(target: "darkirc::irc::dm", "Contact started a forward-secret session");
+        sessions.insert(0, session);
+        self.save_sessions(key, sessions).await?;
+        Ok(Some(plaintext))
+    }
+
+    async fn find_prekey(&self, key: &[u8], public: &[u8; 32]) -> Result<Option<KeyPair>> {
+        let Some(prekeys) = self.prekeys.get(key)? else { return Ok(None) };
+        let prekeys: OwnPrekeys = deserialize_async(&prekeys).await?;
+
+        if prekeys.current.public == *public {
+            return Ok(Some(prekeys.current))
+        }
+
+        Ok(prekeys.previous.into_iter().map(|(p, _)| p).find(|p| p.public == *public))
+    }
+
+    async fn load_sessions(&self, key: &[u8]) -> Result<Vec<Session>> {
+        match self.sessions.get(key)? {
+            Some(bytes) => Ok(deserialize_async(&bytes).await?),
+            None => Ok(vec![]),
+        }
+    }
+
+    async fn save_sessions(&self, key: &[u8], mut sessions: Vec<Session>) -> Result<()> {
+        sessions.truncate(MAX_SESSIONS_PER_CONTACT);
+        self.sessions.insert(key, serialize_async(&sessions).await)?;
+        Ok(())
+    }
+}
+
+/// Whether a stored plaintext is older than [`PLAINTEXT_RETENTION_SECS`]
+fn plaintext_expired(value: &[u8]) -> bool {
+    let Some(stored) = value.get(..8) else { return true };
+    let stored = u64::from_be_bytes(stored.try_into().unwrap());
+    Timestamp::current_time().inner().saturating_sub(stored) >= PLAINTEXT_RETENTION_SECS
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crypto_box::{ChaChaBox, SecretKey};
+    use rand::rngs::OsRng;
+    use sled_overlay::sled;
+
+    use super::{DmPayload, DmSessions, PLAINTEXT_RETENTION_SECS, UNDECRYPTABLE_MESSAGE};
+    use crate::irc::IrcContact;
+
+    fn contact(my_secret: &SecretKey, public: &SecretKey) -> IrcContact {
+        let public_key = public.public_key();
+        IrcContact {
+            saltbox: Arc::new(ChaChaBox::new(&public_key, my_secret)),
+            self_saltbox: Arc::new(ChaChaBox::new(&my_secret.public_key(), my_secret)),
+            public_key,
+            secret_key: my_secret.clone(),
+        }
+    }
+
+    fn dm_sessions() -> DmSessions {
+        DmSessions::new(&sled::Config::new().temporary(true).open().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn dm_sessions_upgrade_to_forward_secrecy() {
+        smol::block_on(async {
+            let alice_secret = SecretKey::generate(&mut OsRng);
+            let bob_secret = SecretKey::generate(&mut OsRng);
+            let bob_at_alice = contact(&alice_secret, &bob_secret);
+            let alice_at_bob = contact(&bob_secret, &alice_secret);
+            let alice = dm_sessions();
+            let bob = dm_sessions();
+
+            // Without Bob's prekey, Alice falls back to the static box
+            assert!(alice.encrypt(&bob_at_alice, "hi").await.unwrap().is_none());
+
+            // Bob publishes his prekey until it was sent once, then until
+            // it's rotated
+            let (bundle, created) = bob.bundle_to_publish(&alice_at_bob).await.unwrap().unwrap();
+            assert!(bob.bundle_to_publish(&alice_at_bob).await.unwrap().is_some());
+            bob.mark_bundle_published(&alice_at_bob, created).await.unwrap();
+            assert!(bob.bundle_to_publish(&alice_at_bob).await.unwrap().is_none());
+            assert_eq!(
+                alice.open_payload(&bob_at_alice, "bundle", &bundle, false).await.unwrap(),
+                DmPayload::Handshake
+            );
+
+            let payload = alice.encrypt(&bob_at_alice, "hello bob").await.unwrap().unwrap();
+            alice.remember_plaintext(&bob_at_alice, "m1", "hello bob").unwrap();
+            assert_eq!(
+                bob.open_payload(&alice_at_bob, "m1", &payload, false).await.unwrap(),
+                DmPayload::Text("hello bob".to_string())
+            );
+
+            // Decrypting the same message again hits the plaintext store
+            assert_eq!(
+                bob.open_payload(&alice_at_bob, "m1", &payload, false).await.unwrap(),
+                DmPayload::Text("hello bob".to_string())
+            );
+            assert_eq!(
+                alice.open_payload(&bob_at_alice, "m1", &payload, true).await.unwrap(),
+                DmPayload::Text("hello bob".to_string())
+            );
+
+            let payload = bob.encrypt(&alice_at_bob, "hello alice").await.unwrap().unwrap();
+            assert_eq!(
+                alice.open_payload(&bob_at_alice, "m2", &payload, false).await.unwrap(),
+                DmPayload::Text("hello alice".to_string())
+            );
+
+            // Plain text of older clients is passed through
+            assert_eq!(
+                alice.open_payload(&bob_at_alice, "m3", b"legacy", false).await.unwrap(),
+                DmPayload::Text("legacy".to_string())
+            );
+
+            // Garbage doesn't break the session
+            let mut payload = alice.encrypt(&bob_at_alice, "again").await.unwrap().unwrap();
+            let last = payload.len() - 1;
+            payload[last] ^= 1;
+            assert_eq!(
+                bob.open_payload(&alice_at_bob, "m4", &payload, false).await.unwrap(),
+                DmPayload::Text(UNDECRYPTABLE_MESSAGE.to_string())
+            );
+            let payload = alice.encrypt(&bob_at_alice, "again").await.unwrap().unwrap();
+            assert_eq!(
+                bob.open_payload(&alice_at_bob, "m5", &payload, false).await.unwrap(),
+                DmPayload::Text("again".to_string())
+            );
+        });
+    }
+
+    #[test]
+    fn dm_plaintexts_are_encrypted_and_expire() {
+        let alice_secret = SecretKey::generate(&mut OsRng);
+        let bob_secret = SecretKey::generate(&mut OsRng);
+        let bob_at_alice = contact(&alice_secret, &bob_secret);
+        let alice = dm_sessions();
+
+        alice.remember_plaintext(&bob_at_alice, "m1", "secret words").unwrap();
+        let key = blake3::hash(b"m1");
+        let stored = alice.plaintexts.get(key.as_bytes()).unwrap().unwrap();
+        assert!(!stored.windows(12).any(|w| w == b"secret words"));
+        assert_eq!(
+            alice.load_plaintext(&bob_at_alice, &key).unwrap(),
+            Some("secret words".to_string())
+        );
+
+        // Another contact's key can't read it
+        let carol_at_alice = contact(&alice_secret, &SecretKey::generate(&mut OsRng));
+        assert_eq!(alice.load_plaintext(&carol_at_alice, &key).unwrap(), None);
+
+        // Expired plaintexts are pruned
+        let mut expired = stored.to_vec();
+        let stored_at = u64::from_be_bytes(expired[..8].try_into().unwrap());
+        expired[..8].copy_from_slice(&(stored_at - PLAINTEXT_RETENTION_SECS).to_be_bytes());
+        alice.plaintexts.insert(key.as_bytes(), expired).unwrap();
+        assert_eq!(alice.prune_plaintexts().unwrap(), 1);
+        assert_eq!(alice.load_plaintext(&bob_at_alice, &key).unwrap(), None);
+    }
+}
@@ -0,0 +1,476 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2026 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! File sharing through fud.
+//!
+//! Shared files are encrypted with a random key, stored in the shares
+//! directory and handed to the local fud daemon over JSON-RPC, which
+//! chunks and seeds them to the fud network. The hash announced for a
+//! file is the one fud gives its resource, as it depends on how fud is
+//! configured to chunk files.
+//!
+//! The file is announced with a PRIVMSG carrying its hash, size, key
+//! and name. Such offers are only sent to encrypted channels and
+//! contacts, so the file key is protected by the channel or contact
+//! key, like any other message.
+//!
+//! On the receiving side, offers are remembered and replaced with a
+//! readable line. The file is fetched on request: fud downloads the
+//! chunks peer-to-peer and verifies them against the hash, and we
+//! decrypt the result into the downloads directory. Decryption also
+//! authenticates the file, so a wrong file is never written out.
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use chacha20poly1305::{
+    aead::{Aead, KeyInit},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use darkfi::{
+    geode::hash_to_string,
+    rpc::{client::RpcClient, jsonrpc::JsonRequest, util::JsonValue},
+    system::ExecutorPtr,
+    Error, Result,
+};
+use rand::{rngs::OsRng, RngCore};
+use sled_overlay::sled;
+use smol::{fs, Timer};
+use tracing::{debug, info, warn};
+use url::Url;
+
+use crate::Privmsg;
+
+/// Prefix of file offer messages
+const FILE_OFFER_PREFIX: &str = "\x01DARKIRC-FILE ";
+
+/// Suffix of file offer messages
+const FILE_OFFER_SUFFIX: &str = "\x01";
+
+/// Maximum length of a shared file name
+pub const MAX_FILE_NAME_LEN: usize = 128;
+
+/// Size of the nonce prepended to encrypted files
+const FILE_NONCE_LEN: usize = 12;
+
+/// Size of the authentication tag appended to encrypted files
+const FILE_TAG_LEN: usize = 16;
+
+/// How often fud is polled while a file downloads
+const FETCH_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// A download which didn't complete after this long is abandoned
+const FETCH_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+
+/// A shared file fud didn't insert after this long is abandoned
+const SHARE_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+const FILE_OFFERS_TREE: &str = "darkirc_file_offers";
+
+/// A shared file, as announced in a PRIVMSG
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FileOffer {
+    /// fud hash of the encrypted file
+    pub hash: blake3::Hash,
+    /// Size of the plaintext file in bytes
+    pub size: u64,
+    /// Key the file is encrypted with
+    pub key: [u8; 32],
+    /// File name, without any directory
+    pub name: String,
+}
+
+impl FileOffer {
+    /// Encode the offer into a PRIVMSG body
+    pub fn to_message(&self) -> String {
+        format!(
+            "{FILE_OFFER_PREFIX}{} {} {} {}{FILE_OFFER_SUFFIX}",
+            hash_to_string(&self.hash),
+            self.size,
+            bs58::encode(self.key).into_string(),
+            self.name
+        )
+    }
+
+    /// Parse a PRIVMSG body into an offer, if it is one
+    pub fn parse(msg: &str) -> Option<Self> {
+        let body = msg.strip_prefix(FILE_OFFER_PREFIX)?.strip_suffix(FILE_OFFER_SUFFIX)?;
+        let mut fields = body.splitn(4, ' ');
+
+        let mut hash = [0u8; 32];
+        if bs58::decode(fields.next()?).onto(&mut hash).ok()? != 32 {
+            return None
+        }
+
+        let size = fields.next()?.parse().ok()?;
+
+        let mut key = [0u8; 32];
+        if bs58::decode(fields.next()?).onto(&mut key).ok()? != 32 {
+            return None
+        }
+
+        let name = sanitize_file_name(fields.next()?)?;
+
+        Some(Self { hash: blake3::Hash::from_bytes(hash), size, key, name })
+    }
+
+    /// Readable line replacing the offer in the IRC client
+    pub fn describe(&self) -> String {
+        format!(
+            "\x01ACTION shared {} ({} bytes), download it with /msg FileServ FETCH {}\x01",
+            self.name,
+            self.size,
+            hash_to_string(&self.hash)
+        )
+    }
+}
+
+/// Reduce a file name to something safe to display and to write in
+/// the downloads directory. Returns `None` if nothing usable is left.
+pub fn sanitize_file_name(name: &str) -> Option<String> {
+    let name = name.rsplit(['/', '\\']).next()?;
+    let name: String = name.chars().filter(|c| !c.is_control()).take(MAX_FILE_NAME_LEN).collect();
+    let name = name.trim();
+
+    if name.is_empty() || name.chars().all(|c| c == '.') {
+        return None
+    }
+
+    Some(name.to_string())
+}
+
+/// Encrypt file contents with the given key. The output is the random
+/// nonce followed by the ciphertext.
+pub fn encrypt_file(key: &[u8; 32], plaintext: &[u8]) -> Result<Vec<u8>> {
+    let mut nonce = [0u8; FILE_NONCE_LEN];
+    OsRng.fill_bytes(&mut nonce);
+
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(key));
+    let Ok(ciphertext) = cipher.encrypt(Nonce::from_slice(&nonce), plaintext) else {
+        return Err(Error::Custom("Failed encrypting file".to_string()))
+    };
+
+    let mut out = nonce.to_vec();
+    out.extend_from_slice(&ciphertext);
+    Ok(out)
+}
+
+/// Decrypt file contents created by [`encrypt_file`]
+pub fn decrypt_file(key: &[u8; 32], data: &[u8]) -> Option<Vec<u8>> {
+    if data.len() < FILE_NONCE_LEN + FILE_TAG_LEN {
+        return None
+    }
+
+    let (nonce, ciphertext) = data.split_at(FILE_NONCE_LEN);
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(key));
+    cipher.decrypt(Nonce::from_slice(nonce), ciphertext).ok()
+}
+
+/// Shared and received files, and the connection to fud
+pub struct FileShares {
+    /// Encrypted files we share
+    outgoing_path: PathBuf,
+    /// Encrypted files downloaded by fud
+    incoming_path: PathBuf,
+    /// Decrypted downloaded files
+    pub downloads_path: PathBuf,
+    /// fud JSON-RPC endpoint
+    fud_endpoint: Url,
+    /// Maximum size of shared and downloaded files, in bytes
+    pub max_size: u64,
+    /// Received offers, by hash
+    offers: sled::Tree,
+    /// Executor used for the fud RPC clients
+    ex: ExecutorPtr,
+}
+
+impl FileShares {
+    pub async fn new(
+        sled_db: &sled::Db,
+        base_path: &Path,
+        fud_endpoint: Url,
+        max_size: u64,
+        ex: ExecutorPtr,
+    ) -> Result<Self> {
+        let outgoing_path = base_path.join("outgoing");
+        let incoming_path = base_path.join("incoming");
+        let downloads_path = base_path.join("downloads");
+        for path in [&outgoing_path, &incoming_path, &downloads_path] {
+            fs::create_dir_all(path).await?;
+        }
+
+        Ok(Self {
+            outgoing_path,
+            incoming_path,
+            downloads_path,
+            fud_endpoint,
+            max_size,
+            offers: sled_db.open_tree(FILE_OFFERS_TREE)?,
+            ex,
+        })
+    }
+
+    /// Encrypt the file at `path` and have fud seed it. Returns the
+    /// offer to announce.
+    pub async fn share(&self, path: &Path) -> Result<FileOffer> {
+        let metadata = fs::metadata(path).await?;
+        if !metadata.is_file() {
+            return Err(Error::Custom(format!("{} is not a file", path.display())))
+        }
+
+        if metadata.len() > self.max_size {
+            return Err(Error::Custom(format!(
+                "{} is larger than the limit of {} bytes",
+                path.display(),
+                self.max_size
+            )))
+        }
+
+        let Some(name) = path.file_name().and_then(|n| sanitize_file_name(&n.to_string_lossy()))
+        else {
+            return Err(Error::Custom(format!("{} has no usable file name", path.display())))
+        };
+
+        let plaintext = fs::read(path).await?;
+        let mut key = [0u8; 32];
+        OsRng.fill_bytes(&mut key);
+        let ciphertext = encrypt_file(&key, &plaintext)?;
+
+        // The content hash is only known once fud chunked the file,
+        // so it's stored under a name derived from the key.
+        let file_path = self.outgoing_path.join(hash_to_string(&blake3::hash(&key)));
+        fs::write(&file_path, &ciphertext).await?;
+
+        let file_path_str = file_path.to_string_lossy().to_string();
+        let hash = match self.put(&file_path_str).await {
+            Ok(hash) => hash,
+            Err(e) => {
+                let _ = fs::remove_file(&file_path).await;
+                return Err(e)
+            }
+        };
+
+        info!(target: "darkirc::irc::files", "Shared {name} as {}", hash_to_string(&hash));
+        Ok(FileOffer { hash, size: metadata.len(), key, name })
+    }
+
+    /// Executor used for background downloads
+    pub fn executor(&self) -> ExecutorPtr {
+        self.ex.clone()
+    }
+
+    /// Have fud insert the file at `path`, and wait until it seeds it.
+    /// fud inserts files in the background, so its hash is found in the
+    /// resource list by path.
+    async fn put(&self, path: &str) -> Result<blake3::Hash> {
+        self.fud_request("put", vec![JsonValue::String(path.to_string())]).await?;
+
+        let started = Instant::now();
+        while started.elapsed() < SHARE_TIMEOUT {
+            Timer::after(FETCH_POLL_INTERVAL).await;
+
+            let resource = self.resources().await?.into_iter().find(|resource| {
+                resource.get("path").and_then(|p| p.get::<String>()).is_some_and(|p| p == path)
+            });
+            let Some(resource) = resource else { continue };
+
+            if resource.get("status").and_then(|s| s.get::<String>()).map(String::as_str) !=
+                Some("seeding")
+            {
+                continue
+            }
+
+            let Some(hash) = resource.get("hash").and_then(|h| h.get::<String>()) else {
+                return Err(Error::Custom("Invalid fud list_resources reply".to_string()))
+            };
+
+            let mut bytes = [0u8; 32];
+            if !matches!(bs58::decode(hash).onto(&mut bytes), Ok(32)) {
+                return Err(Error::Custom("Invalid fud list_resources reply".to_string()))
+            }
+
+            return Ok(blake3::Hash::from_bytes(bytes))
+        }
+
+        Err(Error::Custom("fud did not insert the file, check its logs".to_string()))
+    }
+
+    /// If `privmsg` is a file offer, remember it and replace the message
+    /// with a readable line. Only called for decrypted messages.
+    pub fn receive_offer(&self, privmsg: &mut Privmsg) {
+        let Some(offer) = FileOffer::parse(&privmsg.msg) else { return };
+
+        if let Err(e) = self.offers.insert(offer.hash.as_bytes(), offer.to_message().as_bytes()) {
+            warn!(target: "darkirc::irc::files", "Failed storing file offer: {e}");
+        }
+
+        privmsg.msg = offer.describe();
+    }
+
+    /// Look up a received offer by its hash
+    pub fn offer(&self, hash: &blake3::Hash) -> Result<Option<FileOffer>> {
+        let Some(msg) = self.offers.get(hash.as_bytes())? else { return Ok(None) };
+        Ok(FileOffer::parse(&String::from_utf8_lossy(&msg)))
+    }
+
+    /// Download an offered file with fud, then verify and decrypt it
+    /// into the downloads directory. Returns the path of the file.
+    pub async fn fetch(&self, offer: &FileOffer) -> Result<PathBuf> {
+        if offer.size > self.max_size {
+            return Err(Error::Custom(format!(
+                "{} is larger than the limit of {} bytes",
+                offer.name, self.max_size
+            )))
+        }
+
+        let hash_str = hash_to_string(&offer.hash);
+        let encrypted_path = self.incoming_path.join(&hash_str);
+        self.fud_request(
+            "get",
+            vec![
+                JsonValue::String(hash_str.clone()),
+                JsonValue::String(encrypted_path.to_string_lossy().to_string()),
+                JsonValue::Null,
+            ],
+        )
+        .await?;
+
+        self.wait_for_download(&hash_str).await?;
+
+        // The declared size is not authenticated until decryption,
+        // so check the actual one before reading the file.
+        let size = fs::metadata(&encrypted_path).await?.len();
+        if size > self.max_size + (FILE_NONCE_LEN + FILE_TAG_LEN) as u64 {
+            return Err(Error::Custom(format!("{} is larger than announced", offer.name)))
+        }
+
+        let ciphertext = fs::read(&encrypted_path).await?;
+        let Some(plaintext) = decrypt_file(&offer.key, &ciphertext) else {
+            return Err(Error::Custom(format!("Failed decrypting {}", offer.name)))
+        };
+
+        // Don't overwrite other downloads with the same name
+        let mut path = self.downloads_path.join(&offer.name);
+        if fs::metadata(&path).await.is_ok() {
+            path = self.downloads_path.join(format!("{}-{}", &hash_str[..8], offer.name));
+        }
+
+        fs::write(&path, plaintext).await?;
+        info!(target: "darkirc::irc::files", "Downloaded {} to {}", offer.name, path.display());
+        Ok(path)
+    }
+
+    /// Poll fud until the resource with the given hash is complete
+    async fn wait_for_download(&self, hash_str: &str) -> Result<()> {
+        let started = Instant::now();
+        let mut downloading = false;
+
+        while started.elapsed() < FETCH_TIMEOUT {
+            Timer::after(FETCH_POLL_INTERVAL).await;
+
+            let status = self.resources().await?.into_iter().find_map(|resource| {
+                if resource.get("hash")?.get::<String>()? != hash_str {
+                    return None
+                }
+                resource.get("status")?.get::<String>().cloned()
+            });
+
+            debug!(target: "darkirc::irc::files", "Download of {hash_str}: {status:?}");
+            match status.as_deref() {
+                Some("seeding") => return Ok(()),
+                // A previous attempt might still be marked incomplete
+                // until fud picks up the new one.
+                Some("incomplete") if downloading => {
+                    return Err(Error::Custom("Download did not complete".to_string()))
+                }
+                Some("incomplete") | None => {}
+                Some(_) => downloading = true,
+            }
+        }
+
+        Err(Error::Custom("Download timed out".to_string()))
+    }
+
+    /// Fetch the list of fud resources
+    async fn resources(&self) -> Result<Vec<HashMap<String, JsonValue>>> {
+        let resources = self.fud_request("list_resources", vec![]).await?;
+        let Some(resources) = resources.get::<Vec<JsonValue>>() else {
+            return Err(Error::Custom("Invalid fud list_resources reply".to_string()))
+        };
+
+        Ok(resources
+            .iter()
+            .filter_map(|r| r.get::<HashMap<String, JsonValue>>().cloned())
+            .collect())
+    }
+
+    /// Send a single request to fud
+    async fn fud_request(&self, method: &str, params: Vec<JsonValue>) -> Result<JsonValue> {
+        let rpc_client = RpcClient::new(self.fud_endpoint.clone(), self.ex.clone()).await?;
+        let rep = rpc_client.request(JsonRequest::new(method, JsonValue::Array(params))).await;
+        rpc_client.stop().await;
+        rep
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn offer() -> FileOffer {
+        FileOffer {
+            hash: blake3::hash(b"file"),
+            size: 1234,
+            key: [7u8; 32],
+            name: "screen shot.png".to_string(),
+        }
+    }
+
+    #[test]
+    fn file_offer_roundtrip() {
+        let offer = offer();
+        assert_eq!(FileOffer::parse(&offer.to_message()), Some(offer));
+        assert_eq!(FileOffer::parse("hello"), None);
+        assert_eq!(FileOffer::parse("\x01DARKIRC-FILE abc 12 def name\x01"), None);
+    }
+
+    #[test]
+    fn file_offer_names_are_sanitized() {
+        let mut offer = offer();
+        offer.name = "../../.ssh/authorized_keys".to_string();
+        assert_eq!(FileOffer::parse(&offer.to_message()).unwrap().name, "authorized_keys");
+
+        offer.name = "..".to_string();
+        assert_eq!(FileOffer::parse(&offer.to_message()), None);
+
+        assert_eq!(sanitize_file_name("a\r\nb.txt"), Some("ab.txt".to_string()));
+        assert_eq!(sanitize_file_name(&"x".repeat(500)).unwrap().len(), MAX_FILE_NAME_LEN);
+    }
+
+    #[test]
+    fn file_encryption_roundtrip() {
+        let key = [1u8; 32];
+        let ciphertext = encrypt_file(&key, b"log contents").unwrap();
+        assert_eq!(decrypt_file(&key, &ciphertext), Some(b"log contents".to_vec()));
+        assert_eq!(decrypt_file(&[2u8; 32], &ciphertext), None);
+        assert_eq!(decrypt_file(&key, &ciphertext[..10]), None);
+    }
+}
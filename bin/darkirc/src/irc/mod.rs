@@ -18,7 +18,7 @@
 
 use std::{collections::HashSet, sync::Arc};
 
-use crypto_box::ChaChaBox;
+use crypto_box::{ChaChaBox, PublicKey, SecretKey};
 
 /// IRC client state
 pub mod client;
@@ -29,9 +29,29 @@ pub mod server;
 /// IRC command handler
 pub mod command;
 
+/// Forward-secret direct messages
+pub mod dm;
+
+/// Key rotation of encrypted channels
+pub mod channel_keys;
+
+/// File sharing through fud
+pub mod files;
+
+/// Read markers shared by the connected clients
+pub mod read_markers;
+
+/// Redaction of channel messages by channel admins
+pub mod redaction;
+
+/// Local full-text search over the message history
+pub mod search;
+
 /// Services implementations
 pub mod services;
-pub use services::nickserv::NickServ;
+pub use services::{
+    chanserv::ChanServ, fileserv::FileServ, nickserv::NickServ, searchserv::SearchServ,
+};
 
 /// IRC numerics and server replies
 pub mod rpl;
@@ -45,6 +65,10 @@ pub struct IrcChannel {
     pub topic: String,
     pub nicks: HashSet<String>,
     pub saltbox: Option<Arc<ChaChaBox>>,
+    /// Public key of the channel admin, allowed to rotate its key
+    pub admin_public: Option<darkfi_sdk::crypto::PublicKey>,
+    /// Admin secret key, if we are the channel admin
+    pub admin_secret: Option<darkfi_sdk::crypto::SecretKey>,
 }
 
 /// IRC contact definition
@@ -55,4 +79,8 @@ pub struct IrcContact {
     /// Saltbox used to encrypt our nick in direct messages,
     /// created for our own public key.
     pub self_saltbox: Arc<ChaChaBox>,
+    /// Contact public key, used as their ratchet identity key
+    pub public_key: PublicKey,
+    /// Our secret key for this contact, used as our ratchet identity key
+    pub secret_key: SecretKey,
 }
@@ -0,0 +1,154 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2026 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Read markers shared by all the IRC clients connected to this node.
+//!
+//! A read marker is the timestamp, in milliseconds, of the last message
+//! of a target (channel or contact) the user has read on any of their
+//! clients. It is set by clients with the IRCv3 `draft/read-marker`
+//! extension, and moved forward whenever the user sends a message to
+//! the target. Messages up to the marker are not replayed to clients,
+//! as they were read elsewhere.
+//!
+//! Markers only ever move forward, and every update is published to
+//! the connected clients.
+
+use darkfi::{
+    system::{Publisher, PublisherPtr, Subscription},
+    Result,
+};
+use sled_overlay::sled;
+
+const READ_MARKERS_TREE: &str = "darkirc_read_markers";
+
+/// Client identifiers are at most this long
+pub const MAX_CLIENT_ID_LEN: usize = 32;
+
+/// Persistent read markers, by target
+pub struct ReadMarkers {
+    tree: sled::Tree,
+    /// Marker updates, as `(target, timestamp)`
+    updates: PublisherPtr<(String, u64)>,
+}
+
+impl ReadMarkers {
+    pub fn new(sled_db: &sled::Db) -> Result<Self> {
+        Ok(Self { tree: sled_db.open_tree(READ_MARKERS_TREE)?, updates: Publisher::new() })
+    }
+
+    /// Get the read marker of `target`, if any
+    pub fn get(&self, target: &str) -> Result<Option<u64>> {
+        Ok(self.tree.get(target.as_bytes())?.and_then(|v| decode_marker(&v)))
+    }
+
+    /// Move the read marker of `target` forward to `timestamp`. Returns
+    /// the resulting marker, and whether it moved. Updates are published
+    /// to the subscribers.
+    pub async fn advance(&self, target: &str, timestamp: u64) -> Result<(u64, bool)> {
+        let previous = self.tree.fetch_and_update(target.as_bytes(), |old| {
+            match old.and_then(decode_marker) {
+                Some(marker) if marker >= timestamp => Some(marker.to_be_bytes().to_vec()),
+                _ => Some(timestamp.to_be_bytes().to_vec()),
+            }
+        })?;
+
+        match previous.and_then(|v| decode_marker(&v)) {
+            Some(marker) if marker >= timestamp => Ok((marker, false)),
+            _ => {
+                self.updates.notify((target.to_string(), timestamp)).await;
+                Ok((timestamp, true))
+            }
+        }
+    }
+
+    /// Subscribe to marker updates
+    pub async fn subscribe(&self) -> Subscription<(String, u64)> {
+        self.updates.clone().subscribe().await
+    }
+}
+
+fn decode_marker(bytes: &[u8]) -> Option<u64> {
+    Some(u64::from_be_bytes(bytes.try_into().ok()?))
+}
+
+/// Client identifiers, sent with `PASS`, are short alphanumeric strings
+pub fn is_valid_client_id(client_id: &str) -> bool {
+    !client_id.is_empty() &&
+        client_id.len() <= MAX_CLIENT_ID_LEN &&
+        client_id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Split a `PASS` parameter of the form `<client_id>:<password>`.
+/// Returns `None` if it doesn't start with a valid client identifier.
+pub fn split_client_id(param: &str) -> Option<(&str, &str)> {
+    let (client_id, password) = param.split_once(':')?;
+    is_valid_client_id(client_id).then_some((client_id, password))
+}
+
+/// Whether the message mentions `nick`, as a whole word, ignoring case
+pub fn is_mention(msg: &str, nick: &str) -> bool {
+    if nick.is_empty() || nick == "*" {
+        return false
+    }
+
+    let is_nick_char = |c: char| c.is_ascii_alphanumeric() || "-_[]\\`^{}|".contains(c);
+    msg.split(|c: char| !is_nick_char(c)).any(|word| word.eq_ignore_ascii_case(nick))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_markers_only_move_forward() {
+        smol::block_on(async {
+            let sled_db = sled::Config::new().temporary(true).open().unwrap();
+            let markers = ReadMarkers::new(&sled_db).unwrap();
+            let updates = markers.subscribe().await;
+
+            assert_eq!(markers.get("#dev").unwrap(), None);
+            assert_eq!(markers.advance("#dev", 200).await.unwrap(), (200, true));
+            assert_eq!(markers.advance("#dev", 100).await.unwrap(), (200, false));
+            assert_eq!(markers.advance("#dev", 200).await.unwrap(), (200, false));
+            assert_eq!(markers.get("#dev").unwrap(), Some(200));
+            assert_eq!(markers.get("#random").unwrap(), None);
+
+            assert_eq!(updates.receive().await, ("#dev".to_string(), 200));
+        })
+    }
+
+    #[test]
+    fn client_ids_are_split_from_passwords() {
+        assert_eq!(split_client_id("phone:hunter2"), Some(("phone", "hunter2")));
+        assert_eq!(split_client_id("laptop:pass:word"), Some(("laptop", "pass:word")));
+        assert_eq!(split_client_id("phone:"), Some(("phone", "")));
+        assert_eq!(split_client_id("hunter2"), None);
+        assert_eq!(split_client_id("my phone:hunter2"), None);
+        assert_eq!(split_client_id(":hunter2"), None);
+        assert!(!is_valid_client_id(&"a".repeat(MAX_CLIENT_ID_LEN + 1)));
+    }
+
+    #[test]
+    fn mentions_match_whole_nicks() {
+        assert!(is_mention("alice: ping", "alice"));
+        assert!(is_mention("ping @Alice!", "alice"));
+        assert!(!is_mention("malice aforethought", "alice"));
+        assert!(!is_mention("alice_ is here", "alice"));
+        assert!(!is_mention("anything", "*"));
+    }
+}
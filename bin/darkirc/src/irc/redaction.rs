@@ -0,0 +1,128 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2026 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Redaction of channel messages by channel admins.
+//!
+//! The authorization of a redaction event is an [`AdminRedaction`]: the
+//! name of a channel and a signature over it and the redacted event id,
+//! made with the channel `admin_secret`. A node applies the redaction if
+//! it has the channel configured with the matching `admin_public`.
+//!
+//! Only the event id is signed, so redactions are checked from the
+//! event header and nodes syncing a redacted message accept its
+//! placeholder. The admin is trusted to only redact messages of their
+//! channel, which other nodes can't check for encrypted channels.
+
+use std::{collections::HashMap, sync::RwLock};
+
+use darkfi::event_graph::{
+    redaction::{Redaction, RedactionPolicy},
+    Event, Header,
+};
+use darkfi_sdk::crypto::{
+    schnorr::{SchnorrPublic, SchnorrSecret, Signature},
+    PublicKey, SecretKey,
+};
+use darkfi_serial::{deserialize, serialize, SerialDecodable, SerialEncodable};
+
+/// Domain separator of redaction signatures
+const REDACTION_SIGNATURE_DOMAIN: &[u8] = b"darkirc-redaction-v1";
+
+/// Authorization of a redaction by a channel admin
+#[derive(Clone, Debug, SerialEncodable, SerialDecodable)]
+pub struct AdminRedaction {
+    /// Channel the redacted message was sent to
+    pub channel: String,
+    /// Admin signature over the channel and the redacted event id
+    pub signature: Signature,
+}
+
+impl AdminRedaction {
+    /// Authorize the redaction of `target` in `channel`
+    pub fn new(admin: &SecretKey, channel: &str, target: &blake3::Hash) -> Self {
+        let signature = admin.sign(&Self::signed_message(channel, target));
+        Self { channel: channel.to_string(), signature }
+    }
+
+    fn signed_message(channel: &str, target: &blake3::Hash) -> Vec<u8> {
+        [REDACTION_SIGNATURE_DOMAIN, &serialize(&channel.to_string()), target.as_bytes()].concat()
+    }
+
+    /// Verify the redaction of `target` was signed by the given admin
+    pub fn verify(&self, admin: &PublicKey, target: &blake3::Hash) -> bool {
+        admin.verify(&Self::signed_message(&self.channel, target), &self.signature)
+    }
+}
+
+/// Redaction policy applying the redactions of the admins of the
+/// configured channels
+#[derive(Default)]
+pub struct ChannelAdminPolicy {
+    /// Admin public keys, by channel
+    admins: RwLock<HashMap<String, PublicKey>>,
+}
+
+impl ChannelAdminPolicy {
+    /// Replace the channel admins, on (re)configuration
+    pub fn set_admins(&self, admins: HashMap<String, PublicKey>) {
+        *self.admins.write().unwrap() = admins;
+    }
+}
+
+impl RedactionPolicy for ChannelAdminPolicy {
+    fn authorize(&self, target: &Event, redaction_event: &Event, redaction: &Redaction) -> bool {
+        self.authorize_header(&target.header, redaction_event, redaction)
+    }
+
+    fn authorize_header(
+        &self,
+        target: &Header,
+        _redaction_event: &Event,
+        redaction: &Redaction,
+    ) -> bool {
+        let Ok(auth) = deserialize::<AdminRedaction>(&redaction.auth) else { return false };
+        let Some(admin) = self.admins.read().unwrap().get(&auth.channel).copied() else {
+            return false
+        };
+
+        auth.verify(&admin, &target.id())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::OsRng;
+
+    use super::*;
+
+    #[test]
+    fn admin_redactions_are_bound_to_channel_and_target() {
+        let admin = SecretKey::random(&mut OsRng);
+        let target = blake3::hash(b"target");
+        let auth = AdminRedaction::new(&admin, "#dev", &target);
+
+        assert!(auth.verify(&PublicKey::from_secret(admin), &target));
+        assert!(!auth.verify(&PublicKey::from_secret(admin), &blake3::hash(b"other")));
+        assert!(!auth.verify(&PublicKey::from_secret(SecretKey::random(&mut OsRng)), &target));
+
+        // The channel can't be swapped for another one of the admin
+        let mut forged = auth.clone();
+        forged.channel = "#ops".to_string();
+        assert!(!forged.verify(&PublicKey::from_secret(admin), &target));
+    }
+}
@@ -39,6 +39,11 @@ pub const RPL_WELCOME: u16 = 001;
 /// Part of the post-registration greeting.
 pub const RPL_YOURHOST: u16 = 002;
 
+/// `<client> <1-13 tokens> :are supported by this server`
+///
+/// Advertises the features supported by the server.
+pub const RPL_ISUPPORT: u16 = 005;
+
 /// `<client> <user modes>`
 ///
 /// Sent to a client to inform that client of their currently-set user modes.
@@ -163,6 +168,11 @@ pub const ERR_NOSUCHNICK: u16 = 401;
 /// Indicates that no channel can be found for the supplied channel name.
 pub const ERR_NOSUCHCHANNEL: u16 = 403;
 
+/// `<client> <channel> :Cannot send to channel`
+///
+/// Indicates that the PRIVMSG could not be delivered to `<channel>`.
+pub const ERR_CANNOTSENDTOCHAN: u16 = 404;
+
 /// `<client> :No origin specified`
 ///
 /// Indicates a PING or PONG message missing the originator parameter
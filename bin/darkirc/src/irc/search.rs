@@ -0,0 +1,436 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2026 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Local full-text search over the message history.
+//!
+//! Messages of public channels are indexed as their events are inserted
+//! in the event graph. Encrypted channels and direct messages are not:
+//! the index is stored in the clear, and their plaintext must not end
+//! up on disk. The index is kept in two sled trees: the indexed
+//! messages, keyed by `(timestamp, event_id)`, and an inverted index of
+//! their words, keyed by `(word, 0x00, timestamp, event_id)`. Big-endian
+//! timestamps make both iterate chronologically.
+//!
+//! Messages leave the index along with the rotating DAG holding them,
+//! so search never returns more than what the node retains. In archive
+//! mode nothing is pruned. Redacted messages leave it as soon as their
+//! redaction is applied.
+//!
+//! A query is a list of words which must all appear in a message. A
+//! word ending with `*` matches any word starting with it, `in:<channel>`
+//! restricts the results to a channel and `from:<nick>` to a sender.
+
+use std::collections::BTreeSet;
+
+use darkfi::{event_graph::EventGraph, Result};
+use darkfi_serial::{async_trait, deserialize, serialize, SerialDecodable, SerialEncodable};
+use sled_overlay::sled;
+
+const SEARCH_DOCS_TREE: &str = "darkirc_search_docs_v2";
+const SEARCH_TERMS_TREE: &str = "darkirc_search_terms_v2";
+
+/// Trees of the index which also held decrypted messages
+const LEGACY_SEARCH_TREES: [&str; 2] = ["darkirc_search_docs", "darkirc_search_terms"];
+
+/// Words shorter than this are not indexed
+const MIN_TERM_LEN: usize = 2;
+/// Words longer than this are not indexed
+const MAX_TERM_LEN: usize = 64;
+
+/// Maximum number of results returned by a search
+pub const MAX_SEARCH_RESULTS: usize = 100;
+
+/// An indexed message
+#[derive(Clone, Debug, SerialEncodable, SerialDecodable)]
+struct SearchDoc {
+    channel: String,
+    nick: String,
+    msg: String,
+}
+
+/// A message matching a search
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SearchHit {
+    pub event_id: blake3::Hash,
+    pub timestamp: u64,
+    pub channel: String,
+    pub nick: String,
+    pub msg: String,
+}
+
+/// A parsed search query
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SearchQuery {
+    /// Words which must appear in the message
+    pub terms: Vec<String>,
+    /// Prefixes of words which must appear in the message
+    pub prefixes: Vec<String>,
+    /// Channel the message was sent to
+    pub target: Option<String>,
+    /// Sender of the message
+    pub nick: Option<String>,
+}
+
+impl SearchQuery {
+    /// Parse a query string
+    pub fn parse(query: &str) -> Self {
+        let mut parsed = Self::default();
+
+        for word in query.split_whitespace() {
+            if let Some(target) = word.strip_prefix("in:") {
+                parsed.target = Some(target.to_string());
+                continue
+            }
+
+            if let Some(nick) = word.strip_prefix("from:") {
+                parsed.nick = Some(nick.to_string());
+                continue
+            }
+
+            // Only the last word of `foo-ba*` is a prefix
+            let (word, is_prefix) = match word.strip_suffix('*') {
+                Some(word) => (word, true),
+                None => (word, false),
+            };
+
+            let mut words: Vec<String> = words(word).collect();
+            if is_prefix {
+                if let Some(prefix) = words.pop() {
+                    parsed.prefixes.push(prefix);
+                }
+            }
+            parsed.terms.extend(words);
+        }
+
+        parsed.terms.sort();
+        parsed.terms.dedup();
+        parsed.prefixes.sort();
+        parsed.prefixes.dedup();
+        parsed
+    }
+
+    /// Whether the query matches any message
+    pub fn is_empty(&self) -> bool {
+        self.terms.is_empty() &&
+            self.prefixes.is_empty() &&
+            self.target.is_none() &&
+            self.nick.is_none()
+    }
+
+    /// Whether the message matches the filters and prefixes. Words are
+    /// matched with the posting lists instead.
+    fn matches(&self, doc: &SearchDoc) -> bool {
+        if let Some(target) = &self.target {
+            if !doc.channel.eq_ignore_ascii_case(target) {
+                return false
+            }
+        }
+
+        if let Some(nick) = &self.nick {
+            if !doc.nick.eq_ignore_ascii_case(nick) {
+                return false
+            }
+        }
+
+        if !self.prefixes.is_empty() {
+            let words: Vec<String> = words(&doc.msg).collect();
+            if !self.prefixes.iter().all(|p| words.iter().any(|w| w.starts_with(p.as_str()))) {
+                return false
+            }
+        }
+
+        true
+    }
+}
+
+/// Split a text into the lowercase words to index
+fn words(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| (MIN_TERM_LEN..=MAX_TERM_LEN).contains(&w.chars().count()))
+        .map(str::to_lowercase)
+}
+
+/// Key of a message in the messages tree
+fn doc_key(timestamp: u64, event_id: &blake3::Hash) -> [u8; 40] {
+    let mut key = [0u8; 40];
+    key[..8].copy_from_slice(&timestamp.to_be_bytes());
+    key[8..].copy_from_slice(event_id.as_bytes());
+    key
+}
+
+/// Key of a message in the posting list of `term`
+fn term_key(term: &str, doc_key: &[u8; 40]) -> Vec<u8> {
+    let mut key = Vec::with_capacity(term.len() + 1 + doc_key.len());
+    key.extend_from_slice(term.as_bytes());
+    key.push(0x00);
+    key.extend_from_slice(doc_key);
+    key
+}
+
+/// Persistent full-text index of the message history
+pub struct SearchIndex {
+    /// Indexed messages, by `(timestamp, event_id)`
+    docs: sled::Tree,
+    /// Posting lists, by `(term, 0x00, timestamp, event_id)`
+    terms: sled::Tree,
+}
+
+impl SearchIndex {
+    pub fn new(sled_db: &sled::Db) -> Result<Self> {
+        // The index is rebuilt from the DAGs on startup
+        for tree in LEGACY_SEARCH_TREES {
+            sled_db.drop_tree(tree)?;
+        }
+
+        Ok(Self {
+            docs: sled_db.open_tree(SEARCH_DOCS_TREE)?,
+            terms: sled_db.open_tree(SEARCH_TERMS_TREE)?,
+        })
+    }
+
+    /// Whether the message of the given event is indexed
+    pub fn contains(&self, event_id: &blake3::Hash, timestamp: u64) -> Result<bool> {
+        Ok(self.docs.contains_key(doc_key(timestamp, event_id))?)
+    }
+
+    /// Index the message of the given event. Returns `false` if it was
+    /// already indexed.
+    pub fn insert(
+        &self,
+        event_id: &blake3::Hash,
+        timestamp: u64,
+        channel: &str,
+        nick: &str,
+        msg: &str,
+    ) -> Result<bool> {
+        let key = doc_key(timestamp, event_id);
+        if self.docs.contains_key(key)? {
+            return Ok(false)
+        }
+
+        let mut batch = sled::Batch::default();
+        for term in words(msg).collect::<BTreeSet<_>>() {
+            batch.insert(term_key(&term, &key), &[]);
+        }
+        self.terms.apply_batch(batch)?;
+
+        let doc =
+            SearchDoc { channel: channel.to_string(), nick: nick.to_string(), msg: msg.into() };
+        self.docs.insert(key, serialize(&doc))?;
+        Ok(true)
+    }
+
+    /// Drop the message of the given event from the index. Returns
+    /// `false` if it wasn't indexed.
+    pub fn remove(&self, event_id: &blake3::Hash, timestamp: u64) -> Result<bool> {
+        let key = doc_key(timestamp, event_id);
+        let Some(value) = self.docs.get(key)? else { return Ok(false) };
+        self.remove_doc(&key, &value)?;
+        Ok(true)
+    }
+
+    /// Drop the messages older than `cutoff` from the index. Returns the
+    /// number of dropped messages.
+    pub fn prune(&self, cutoff: u64) -> Result<usize> {
+        let mut pruned = 0;
+        for item in self.docs.range(..cutoff.to_be_bytes()) {
+            let (key, value) = item?;
+            let Ok(key) = <[u8; 40]>::try_from(key.as_ref()) else { continue };
+            self.remove_doc(&key, &value)?;
+            pruned += 1;
+        }
+
+        Ok(pruned)
+    }
+
+    /// Drop an indexed message and its postings
+    fn remove_doc(&self, key: &[u8; 40], value: &[u8]) -> Result<()> {
+        if let Ok(doc) = deserialize::<SearchDoc>(value) {
+            let mut batch = sled::Batch::default();
+            for term in words(&doc.msg).collect::<BTreeSet<_>>() {
+                batch.remove(term_key(&term, key));
+            }
+            self.terms.apply_batch(batch)?;
+        }
+
+        self.docs.remove(key)?;
+        Ok(())
+    }
+
+    /// Find the messages matching `query`, newest first. Messages older
+    /// than `cutoff` are ignored, as they may wait to be pruned.
+    pub fn search(
+        &self,
+        query: &SearchQuery,
+        cutoff: Option<u64>,
+        limit: usize,
+    ) -> Result<Vec<SearchHit>> {
+        let limit = limit.min(MAX_SEARCH_RESULTS);
+        let cutoff = cutoff.unwrap_or(0);
+
+        // The posting list of the first word, or all the messages when
+        // there is none, is walked newest first. The other words are
+        // looked up for each of its messages, so no list is loaded.
+        let candidates: Box<dyn Iterator<Item = sled::Result<sled::IVec>>> =
+            match query.terms.first() {
+                Some(term) => {
+                    let mut prefix = term.as_bytes().to_vec();
+                    prefix.push(0x00);
+                    Box::new(self.terms.scan_prefix(prefix).keys().rev())
+                }
+                None => Box::new(self.docs.range(cutoff.to_be_bytes()..).keys().rev()),
+            };
+
+        let mut hits = vec![];
+        'candidates: for key in candidates {
+            if hits.len() >= limit {
+                break
+            }
+
+            // Postings end with the message key
+            let key = key?;
+            let Some(key) =
+                key.len().checked_sub(40).and_then(|i| <[u8; 40]>::try_from(&key[i..]).ok())
+            else {
+                continue
+            };
+
+            let timestamp = u64::from_be_bytes(key[..8].try_into().unwrap());
+            if timestamp < cutoff {
+                break
+            }
+
+            for term in query.terms.iter().skip(1) {
+                if !self.terms.contains_key(term_key(term, &key))? {
+                    continue 'candidates
+                }
+            }
+
+            let Some(value) = self.docs.get(key)? else { continue };
+            let Ok(doc) = deserialize::<SearchDoc>(&value) else { continue };
+            if !query.matches(&doc) {
+                continue
+            }
+
+            let event_id = blake3::Hash::from_bytes(key[8..].try_into().unwrap());
+            hits.push(SearchHit {
+                event_id,
+                timestamp,
+                channel: doc.channel,
+                nick: doc.nick,
+                msg: doc.msg,
+            });
+        }
+
+        Ok(hits)
+    }
+}
+
+/// Timestamp of the oldest DAG retained by the event graph. Older
+/// messages were pruned along with their DAG. `None` in archive mode.
+pub async fn retention_cutoff(event_graph: &EventGraph) -> Option<u64> {
+    let max_dags = event_graph.config.max_dags? as u64;
+    let rotation_ms = event_graph.config.hours_rotation.saturating_mul(3_600_000);
+    let genesis = event_graph.current_genesis.read().await.header.timestamp;
+    Some(genesis.saturating_sub(max_dags.saturating_sub(1).saturating_mul(rotation_ms)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn index() -> SearchIndex {
+        let sled_db = sled::Config::new().temporary(true).open().unwrap();
+        SearchIndex::new(&sled_db).unwrap()
+    }
+
+    fn insert(index: &SearchIndex, timestamp: u64, channel: &str, nick: &str, msg: &str) {
+        let event_id = blake3::hash(&timestamp.to_be_bytes());
+        assert!(index.insert(&event_id, timestamp, channel, nick, msg).unwrap());
+    }
+
+    fn timestamps(hits: Vec<SearchHit>) -> Vec<u64> {
+        hits.into_iter().map(|hit| hit.timestamp).collect()
+    }
+
+    #[test]
+    fn search_queries_are_parsed() {
+        let query = SearchQuery::parse("in:#dev from:Alice Zk-proof rln* a");
+        assert_eq!(query.terms, vec!["proof", "zk"]);
+        assert_eq!(query.prefixes, vec!["rln"]);
+        assert_eq!(query.target.as_deref(), Some("#dev"));
+        assert_eq!(query.nick.as_deref(), Some("Alice"));
+        assert!(SearchQuery::parse("  ").is_empty());
+    }
+
+    #[test]
+    fn search_matches_all_words_newest_first() {
+        let index = index();
+        insert(&index, 100, "#dev", "alice", "The RLN proof is slow");
+        insert(&index, 200, "#dev", "bob", "rln proofs got faster");
+        insert(&index, 300, "#random", "alice", "Proof of work is dead");
+        insert(&index, 400, "#random", "carol", "did you see the rln change?");
+
+        let search =
+            |query: &str| timestamps(index.search(&SearchQuery::parse(query), None, 10).unwrap());
+
+        assert_eq!(search("proof"), vec![300, 100]);
+        assert_eq!(search("rln proof"), vec![100]);
+        assert_eq!(search("proof*"), vec![300, 200, 100]);
+        assert_eq!(search("rln from:ALICE"), vec![100]);
+        assert_eq!(search("in:#random"), vec![400, 300]);
+        assert_eq!(search("in:#random rln"), vec![400]);
+        assert_eq!(search("rln missing"), Vec::<u64>::new());
+        assert_eq!(search("missing"), Vec::<u64>::new());
+        assert_eq!(search("rln proof*").len(), 2);
+
+        let hits = index.search(&SearchQuery::parse("rln"), Some(150), 1).unwrap();
+        assert_eq!(timestamps(hits), vec![400]);
+    }
+
+    #[test]
+    fn removed_messages_leave_the_index() {
+        let index = index();
+        insert(&index, 100, "#dev", "alice", "leaked password");
+        insert(&index, 200, "#dev", "bob", "password rotated");
+
+        let leaked = blake3::hash(&100u64.to_be_bytes());
+        assert!(index.remove(&leaked, 100).unwrap());
+        assert!(!index.remove(&leaked, 100).unwrap());
+
+        let query = SearchQuery::parse("password");
+        assert_eq!(timestamps(index.search(&query, None, 10).unwrap()), vec![200]);
+        assert!(index.terms.scan_prefix(b"leaked\x00").next().is_none());
+    }
+
+    #[test]
+    fn pruned_messages_leave_the_index() {
+        let index = index();
+        insert(&index, 100, "#dev", "alice", "old news");
+        insert(&index, 200, "#dev", "bob", "fresh news");
+
+        assert_eq!(index.prune(150).unwrap(), 1);
+        assert_eq!(index.prune(150).unwrap(), 0);
+        assert!(!index.contains(&blake3::hash(&100u64.to_be_bytes()), 100).unwrap());
+
+        let query = SearchQuery::parse("news");
+        assert_eq!(timestamps(index.search(&query, None, 10).unwrap()), vec![200]);
+        assert!(index.search(&SearchQuery::parse("old"), None, 10).unwrap().is_empty());
+        assert!(index.terms.scan_prefix(b"old\x00").next().is_none());
+    }
+}
@@ -24,13 +24,14 @@ use std::{
     sync::Arc,
 };
 
+use crypto_box::ChaChaBox;
 use darkfi::{
-    event_graph::Event,
+    event_graph::{redaction::Redaction, Event},
     system::{StoppableTask, StoppableTaskPtr, Subscription},
     util::path::expand_path,
     Error, Result,
 };
-use darkfi_serial::{deserialize_async, serialize_async};
+use darkfi_serial::{deserialize_async, deserialize_async_partial, serialize_async};
 use futures_rustls::{
     rustls::{
         self,
@@ -50,7 +51,15 @@ use tracing::{debug, error, info, warn};
 use url::Url;
 
 use super::{
+    channel_keys::{
+        channel_tag, parse_channel_tag, ChannelKeys, RekeyEvent, MSG_TYPE_CHANNEL_REKEY,
+    },
     client::Client,
+    dm::{DmPayload, DmSessions, UNDECRYPTABLE_MESSAGE},
+    files::FileShares,
+    read_markers::ReadMarkers,
+    redaction::ChannelAdminPolicy,
+    search::retention_cutoff,
     services::nickserv::{ACCOUNTS_DB_PREFIX, ACCOUNTS_DEFAULT_TREE, ACCOUNTS_KEY_RLN_IDENTITY},
     IrcChannel, IrcContact,
 };
@@ -217,11 +226,24 @@ pub struct IrcServer {
     pub channels: RwLock<HashMap<String, IrcChannel>>,
     /// Configured IRC contacts
     pub contacts: RwLock<HashMap<String, IrcContact>>,
+    /// Rotated keys and members of encrypted channels
+    pub channel_keys: ChannelKeys,
+    /// Forward-secret direct message sessions
+    pub dm: DmSessions,
+    /// Files shared through fud
+    pub files: Arc<FileShares>,
+    /// Read markers shared by the connected clients
+    pub read_markers: ReadMarkers,
+    /// Redactions applied to the event graph, by channel admins
+    redaction_policy: Arc<ChannelAdminPolicy>,
     /// Configured RLN identity
     pub rln_identity: RwLock<Option<RlnIdentity>>,
     /// Static-DAG events whose broadcast is deferred until the
     /// EventGraph is synced.
     pub pending_static_broadcasts: Mutex<Vec<(Event, Vec<u8>)>>,
+    /// Events carrying a prekey handshake that weren't published yet, with
+    /// the contact and the creation time of the prekey
+    pending_handshakes: Mutex<HashMap<blake3::Hash, (IrcContact, u64)>>,
     /// Active client connections
     clients: Mutex<HashMap<u16, StoppableTaskPtr>>,
     /// IRC server Password
@@ -245,6 +267,7 @@ impl IrcServer {
         tls_secret: Option<String>,
         config_path: PathBuf,
         password: String,
+        files: Arc<FileShares>,
     ) -> Result<Arc<Self>> {
         let scheme = listen.scheme();
         if scheme != "tcp" && scheme != "tcp+tls" {
@@ -288,6 +311,12 @@ impl IrcServer {
             None
         };
 
+        let channel_keys = ChannelKeys::new(&darkirc.sled).await?;
+        let dm = DmSessions::new(&darkirc.sled)?;
+        let read_markers = ReadMarkers::new(&darkirc.sled)?;
+        let redaction_policy = Arc::new(ChannelAdminPolicy::default());
+        darkirc.event_graph.set_redaction_policy(redaction_policy.clone()).await;
+
         let self_ = Arc::new(Self {
             darkirc,
             config_path,
@@ -296,8 +325,14 @@ impl IrcServer {
             autojoin: RwLock::new(Vec::new()),
             channels: RwLock::new(HashMap::new()),
             contacts: RwLock::new(HashMap::new()),
+            channel_keys,
+            dm,
+            files,
+            read_markers,
+            redaction_policy,
             rln_identity: RwLock::new(rln_identity),
             pending_static_broadcasts: Mutex::new(Vec::new()),
+            pending_handshakes: Mutex::new(HashMap::new()),
             clients: Mutex::new(HashMap::new()),
             password,
         });
@@ -321,6 +356,73 @@ impl IrcServer {
         Ok(n)
     }
 
+    /// Index the messages of the event graph for local search. Messages
+    /// already in the DAGs are indexed first, and then new ones as their
+    /// events are inserted.
+    pub async fn index_events(self: Arc<Self>) -> Result<()> {
+        let incoming = self.darkirc.event_graph.event_subscribe().await;
+
+        for event in self.darkirc.event_graph.order_events().await? {
+            self.index_event(&event).await?;
+        }
+
+        loop {
+            let event = incoming.receive().await;
+            self.index_event(&event).await?;
+        }
+    }
+
+    /// Index a single event, if it holds a public channel message, and
+    /// drop the messages of the DAGs rotated out since. Redactions drop
+    /// the message they apply to.
+    async fn index_event(&self, event: &Event) -> Result<()> {
+        let search = &self.darkirc.search;
+        let event_graph = &self.darkirc.event_graph;
+
+        // Redactions are applied before their event is published
+        if let Some(redaction) = Redaction::from_event(event) {
+            if event_graph.is_redacted(&redaction.target)? {
+                if let Some(target) = event_graph.fetch_event_from_dags(&redaction.target).await? {
+                    search.remove(&redaction.target, target.header.timestamp)?;
+                }
+            }
+            return Ok(())
+        }
+
+        let event_id = event.id();
+        let timestamp = event.header.timestamp;
+        if search.contains(&event_id, timestamp)? {
+            return Ok(())
+        }
+
+        let Ok((privmsg, _)) = deserialize_async_partial::<Privmsg>(event.content()).await else {
+            return Ok(())
+        };
+
+        // The index is stored in the clear, so the messages of encrypted
+        // channels and contacts are not indexed. Their channel field is
+        // a base58 ciphertext. Skip attempts to contact services too.
+        if privmsg.msg_type == MSG_TYPE_CHANNEL_REKEY || !privmsg.channel.starts_with('#') {
+            return Ok(())
+        }
+        if ["nickserv", "chanserv", "fileserv", "searchserv"]
+            .contains(&privmsg.nick.to_lowercase().as_str())
+        {
+            return Ok(())
+        }
+
+        search.insert(&event_id, timestamp, &privmsg.channel, &privmsg.nick, &privmsg.msg)?;
+
+        if let Some(cutoff) = retention_cutoff(event_graph).await {
+            let pruned = search.prune(cutoff)?;
+            if pruned > 0 {
+                debug!(target: "darkirc::irc::server::index_event", "Pruned {pruned} messages from the search index");
+            }
+        }
+
+        Ok(())
+    }
+
     /// Reload the darkirc configuration file and reconfigure channels and contacts.
     pub async fn rehash(&self) -> Result<()> {
         let contents = fs::read_to_string(&self.config_path).await?;
@@ -342,7 +444,7 @@ impl IrcServer {
         let contacts = parse_configured_contacts(&contents)?;
 
         // Persist unconfigured channels (joined from client, or autojoined without config)
-        let channels = {
+        let channels: HashMap<String, IrcChannel> = {
             let old_channels = self.channels.read().await.clone();
             let unconfigured_channels: HashMap<String, IrcChannel> = old_channels
                 .into_iter()
@@ -351,10 +453,16 @@ impl IrcServer {
             configured_channels.into_iter().chain(unconfigured_channels).collect()
         };
 
+        let admins = channels
+            .iter()
+            .filter_map(|(name, channel)| channel.admin_public.map(|admin| (name.clone(), admin)))
+            .collect();
+
         // Only if everything is fine, replace.
         *self.autojoin.write().await = autojoin;
         *self.channels.write().await = channels;
         *self.contacts.write().await = contacts;
+        self.redaction_policy.set_admins(admins);
 
         Ok(())
     }
@@ -462,75 +570,189 @@ impl IrcServer {
     /// Try encrypting a given `Privmsg` if there is such a channel/contact.
     pub async fn try_encrypt(&self, privmsg: &mut Privmsg) {
         if let Some((name, channel)) = self.channels.read().await.get_key_value(&privmsg.channel) {
-            if let Some(saltbox) = &channel.saltbox {
-                // We will use a dummy channel value of MAX_NICK_LEN,
-                // since its not used, so all encrypted messages look the same.
-                privmsg.channel = saltbox::encrypt(saltbox, &[0x00; MAX_NICK_LEN]);
+            // Use the latest rotated key of the channel, if any
+            let key = match self.channel_keys.current(name).await {
+                Some(key) => Some((key.epoch, key.saltbox)),
+                None => channel.saltbox.clone().map(|saltbox| (0, saltbox)),
+            };
+
+            if let Some((epoch, saltbox)) = key {
+                // We will use a dummy channel value of MAX_NICK_LEN, only
+                // carrying the key epoch, so all encrypted messages look the same.
+                privmsg.channel = saltbox::encrypt(&saltbox, &channel_tag(epoch));
                 // We will pad the name to MAX_NICK_LEN so they all look the same
-                privmsg.nick = saltbox::encrypt(saltbox, &pad(&privmsg.nick));
-                privmsg.msg = saltbox::encrypt(saltbox, privmsg.msg.as_bytes());
+                privmsg.nick = saltbox::encrypt(&saltbox, &pad(&privmsg.nick));
+                privmsg.msg = saltbox::encrypt(&saltbox, privmsg.msg.as_bytes());
                 debug!("Successfully encrypted message for {name}");
                 return
             }
         };
 
         if let Some((name, contact)) = self.contacts.read().await.get_key_value(&privmsg.channel) {
-            // We will use dummy channel and nick values of MAX_NICK_LEN,
-            // since they are not used, so all encrypted messages look the same.
-            privmsg.channel = saltbox::encrypt(&contact.saltbox, &[0x00; MAX_NICK_LEN]);
-            // We will encrypt the dummy nick value using our own self saltbox,
-            // so we can identify our messages.
-            privmsg.nick = saltbox::encrypt(&contact.self_saltbox, &[0x00; MAX_NICK_LEN]);
-            privmsg.msg = saltbox::encrypt(&contact.saltbox, privmsg.msg.as_bytes());
+            // Use the forward-secret session with the contact if possible,
+            // otherwise fall back to the contact static box alone.
+            let ratchet_payload = match self.dm.encrypt(contact, &privmsg.msg).await {
+                Ok(v) => v,
+                Err(e) => {
+                    error!("Failed ratchet encrypting message for {name}: {e}");
+                    None
+                }
+            };
+
+            let plaintext = std::mem::take(&mut privmsg.msg);
+            match ratchet_payload {
+                Some(payload) => {
+                    self.seal_for_contact(contact, privmsg, &payload);
+                    // We can't decrypt our own ratchet messages, so keep their plaintext
+                    if let Err(e) = self.dm.remember_plaintext(contact, &privmsg.msg, &plaintext) {
+                        error!("Failed storing plaintext of message for {name}: {e}");
+                    }
+                }
+                None => self.seal_for_contact(contact, privmsg, plaintext.as_bytes()),
+            }
             debug!("Successfully encrypted message for {name}");
         };
     }
 
-    /// Try decrypting a given potentially encrypted `Privmsg` object.
-    pub async fn try_decrypt(&self, privmsg: &mut Privmsg, self_nickname: &str) {
+    /// Seal a direct message payload for the given contact into `privmsg`.
+    pub fn seal_for_contact(&self, contact: &IrcContact, privmsg: &mut Privmsg, payload: &[u8]) {
+        // We will use dummy channel and nick values of MAX_NICK_LEN,
+        // since they are not used, so all encrypted messages look the same.
+        privmsg.channel = saltbox::encrypt(&contact.saltbox, &[0x00; MAX_NICK_LEN]);
+        // We will encrypt the dummy nick value using our own self saltbox,
+        // so we can identify our messages.
+        privmsg.nick = saltbox::encrypt(&contact.self_saltbox, &[0x00; MAX_NICK_LEN]);
+        privmsg.msg = saltbox::encrypt(&contact.saltbox, payload);
+    }
+
+    /// Build the `Privmsg` announcing our prekey to the given contact, if
+    /// our current prekey for them wasn't published yet. Once its event is
+    /// built, pass it to [`Self::handshake_event()`].
+    pub async fn contact_handshake(
+        &self,
+        contact_name: &str,
+    ) -> Result<Option<(Privmsg, IrcContact, u64)>> {
+        let Some(contact) = self.contacts.read().await.get(contact_name).cloned() else {
+            return Ok(None)
+        };
+
+        let Some((payload, created)) = self.dm.bundle_to_publish(&contact).await? else {
+            return Ok(None)
+        };
+
+        let mut privmsg = Privmsg {
+            version: 0,
+            msg_type: 0,
+            channel: String::new(),
+            nick: String::new(),
+            msg: String::new(),
+        };
+        self.seal_for_contact(&contact, &mut privmsg, &payload);
+        Ok(Some((privmsg, contact, created)))
+    }
+
+    /// Remember the event carrying a prekey handshake, so the prekey gets
+    /// marked as published once the event is inserted.
+    pub async fn handshake_event(&self, event_id: blake3::Hash, contact: IrcContact, created: u64) {
+        self.pending_handshakes.lock().await.insert(event_id, (contact, created));
+    }
+
+    /// Called with the outcome of publishing an event. If it carried a
+    /// prekey handshake, the prekey is marked as published on success.
+    pub async fn event_published(&self, event_id: &blake3::Hash, success: bool) -> Result<()> {
+        let Some((contact, created)) = self.pending_handshakes.lock().await.remove(event_id) else {
+            return Ok(())
+        };
+
+        if success {
+            self.dm.mark_bundle_published(&contact, created).await?;
+        }
+        Ok(())
+    }
+
+    /// Try decrypting a given potentially encrypted `Privmsg` object,
+    /// from an event with the given timestamp. Returns `false` if the
+    /// message must not be delivered to the client, like direct message
+    /// handshakes, channel rekeys, or messages under an expired channel key.
+    pub async fn try_decrypt(
+        &self,
+        privmsg: &mut Privmsg,
+        self_nickname: &str,
+        timestamp: u64,
+    ) -> bool {
+        if privmsg.msg_type == MSG_TYPE_CHANNEL_REKEY {
+            if let Err(e) = self.handle_channel_rekey(privmsg, timestamp).await {
+                error!(target: "darkirc::irc::server::try_decrypt", "Failed handling channel rekey: {e}");
+            }
+            return false
+        }
+
         // If all fields have base58, then we can consider decrypting.
         let channel_ciphertext = match bs58::decode(&privmsg.channel).into_vec() {
             Ok(v) => v,
-            Err(_) => return,
+            Err(_) => return true,
         };
 
         let nick_ciphertext = match bs58::decode(&privmsg.nick).into_vec() {
             Ok(v) => v,
-            Err(_) => return,
+            Err(_) => return true,
         };
 
         let msg_ciphertext = match bs58::decode(&privmsg.msg).into_vec() {
             Ok(v) => v,
-            Err(_) => return,
+            Err(_) => return true,
         };
 
         // Now go through all 3 ciphertexts. We'll use intermediate buffers
         // for decryption, iff all passes, we will return a modified
         // (i.e. decrypted) privmsg, otherwise we return the original.
         for (name, channel) in self.channels.read().await.iter() {
-            let Some(saltbox) = &channel.saltbox else { continue };
+            // Try the rotated keys of the channel, newest first, and then
+            // the configured one, which is epoch 0.
+            let mut keys: Vec<(u32, Arc<ChaChaBox>)> = self
+                .channel_keys
+                .keys(name)
+                .await
+                .into_iter()
+                .map(|key| (key.epoch, key.saltbox))
+                .collect();
+            if let Some(saltbox) = &channel.saltbox {
+                keys.push((0, saltbox.clone()));
+            }
 
-            if saltbox::try_decrypt(saltbox, &channel_ciphertext).is_none() {
-                continue
-            };
+            for (epoch, saltbox) in keys {
+                let Some(tag) = saltbox::try_decrypt(&saltbox, &channel_ciphertext) else {
+                    continue
+                };
 
-            let Some(mut nick_dec) = saltbox::try_decrypt(saltbox, &nick_ciphertext) else {
-                warn!(target: "darkirc::irc::server::try_decrypt", "Could not decrypt nick ciphertext for channel: {name}");
-                continue
-            };
+                if parse_channel_tag(&tag) != Some(epoch) {
+                    continue
+                }
 
-            let Some(msg_dec) = saltbox::try_decrypt(saltbox, &msg_ciphertext) else {
-                warn!(target: "darkirc::irc::server::try_decrypt", "Could not decrypt message ciphertext for channel: {name}");
-                continue
-            };
+                if !self.channel_keys.epoch_accepted(name, epoch, timestamp).await {
+                    warn!(target: "darkirc::irc::server::try_decrypt", "Dropping message for {name} under expired key epoch {epoch}");
+                    return false
+                }
 
-            unpad(&mut nick_dec);
+                let Some(mut nick_dec) = saltbox::try_decrypt(&saltbox, &nick_ciphertext) else {
+                    warn!(target: "darkirc::irc::server::try_decrypt", "Could not decrypt nick ciphertext for channel: {name}");
+                    continue
+                };
 
-            privmsg.channel = name.to_string();
-            privmsg.nick = String::from_utf8_lossy(&nick_dec).into();
-            privmsg.msg = String::from_utf8_lossy(&msg_dec).into();
-            debug!("Successfully decrypted message for {name}");
-            return
+                let Some(msg_dec) = saltbox::try_decrypt(&saltbox, &msg_ciphertext) else {
+                    warn!(target: "darkirc::irc::server::try_decrypt", "Could not decrypt message ciphertext for channel: {name}");
+                    continue
+                };
+
+                unpad(&mut nick_dec);
+
+                privmsg.channel = name.to_string();
+                privmsg.nick = String::from_utf8_lossy(&nick_dec).into();
+                privmsg.msg = String::from_utf8_lossy(&msg_dec).into();
+                self.files.receive_offer(privmsg);
+                debug!("Successfully decrypted message for {name}");
+                return true
+            }
         }
 
         for (name, contact) in self.contacts.read().await.iter() {
@@ -540,23 +762,74 @@ impl IrcServer {
 
             // Since everyone encrypts the dummy nick value with their self saltbox,
             // we try to decrypt using our, to identify our messages.
-            let nick = if saltbox::try_decrypt(&contact.self_saltbox, &nick_ciphertext).is_some() {
-                String::from(self_nickname)
-            } else {
-                name.to_string()
-            };
+            let own = saltbox::try_decrypt(&contact.self_saltbox, &nick_ciphertext).is_some();
+            let nick = if own { String::from(self_nickname) } else { name.to_string() };
 
             let Some(msg_dec) = saltbox::try_decrypt(&contact.saltbox, &msg_ciphertext) else {
                 warn!(target: "darkirc::irc::server::try_decrypt", "Could not decrypt message ciphertext for contact: {name}");
                 continue
             };
 
+            let msg = match self.dm.open_payload(contact, &privmsg.msg, &msg_dec, own).await {
+                Ok(DmPayload::Text(msg)) => msg,
+                Ok(DmPayload::Handshake) => return false,
+                Err(e) => {
+                    error!(target: "darkirc::irc::server::try_decrypt", "Failed opening message from {name}: {e}");
+                    UNDECRYPTABLE_MESSAGE.to_string()
+                }
+            };
+
             privmsg.channel = name.to_string();
             privmsg.nick = nick;
-            privmsg.msg = String::from_utf8_lossy(&msg_dec).into();
+            privmsg.msg = msg;
+            self.files.receive_offer(privmsg);
             debug!("Successfully decrypted message from {name}");
-            return
+            return true
         }
+
+        true
+    }
+
+    /// Apply a channel rekey event, if it was signed by the admin of one
+    /// of our channels and we are still a member.
+    async fn handle_channel_rekey(&self, privmsg: &Privmsg, timestamp: u64) -> Result<()> {
+        let Ok(bytes) = bs58::decode(&privmsg.msg).into_vec() else { return Ok(()) };
+        let Ok(rekey) = deserialize_async::<RekeyEvent>(&bytes).await else { return Ok(()) };
+
+        let contacts: Vec<Arc<ChaChaBox>> =
+            self.contacts.read().await.values().map(|c| c.saltbox.clone()).collect();
+
+        let Some((name, secret)) = rekey.open(&contacts) else {
+            // Tell the user if one of their channels was rekeyed without them
+            let channels = self.channels.read().await;
+            let removed = channels.iter().find(|(name, channel)| {
+                channel.admin_public.is_some_and(|admin| rekey.verify(name, &admin))
+            });
+            if let Some((name, _)) = removed {
+                warn!("Channel {name} was rekeyed to epoch {} without us", rekey.epoch);
+            }
+            return Ok(())
+        };
+
+        let Some(admin) = self.channels.read().await.get(&name).and_then(|c| c.admin_public) else {
+            debug!("Ignoring rekey of channel {name} without a configured admin");
+            return Ok(())
+        };
+
+        if !rekey.verify(&name, &admin) {
+            warn!("Ignoring rekey of channel {name} not signed by its admin");
+            return Ok(())
+        }
+
+        if self.channel_keys.keys(&name).await.iter().any(|key| key.epoch == rekey.epoch) {
+            return Ok(())
+        }
+
+        if self.channel_keys.insert(&name, rekey.epoch, timestamp, secret).await? {
+            info!("Channel {name} was rekeyed to epoch {}", rekey.epoch);
+        }
+
+        Ok(())
     }
 }
 
@@ -0,0 +1,494 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2026 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! ChanServ - member management of encrypted channels for DarkIRC.
+//!
+//! Channels with an `admin_secret` in the config are managed by us.
+//! Their members are configured contacts, kept in the sled tree
+//! `darkirc_channel_members`. Every membership change rotates the
+//! channel key, see [`crate::irc::channel_keys`], so added members
+//! can't read older history and removed ones can't read newer one.
+//!
+//! Commands:
+//!
+//! - `INFO <#channel>` - show the key epoch and admin of a channel,
+//!   and its members if we are the admin.
+//! - `ADD <#channel> <contact>` - add a contact to the channel and
+//!   rotate its key.
+//! - `REMOVE <#channel> <contact>` - remove a contact from the channel
+//!   and rotate its key.
+//! - `ROTATE <#channel>` - rotate the channel key for the current
+//!   members.
+//! - `REDACT <#channel> <msgid>` - redact a message of the channel,
+//!   see [`crate::irc::redaction`].
+//! - `HELP` - usage.
+
+use std::{str::SplitAsciiWhitespace, sync::Arc};
+
+use darkfi::{
+    event_graph::{redaction::Redaction, Event},
+    Result,
+};
+use darkfi_serial::{serialize, serialize_async};
+use rand::{rngs::OsRng, RngCore};
+use smol::lock::{Mutex, RwLock};
+
+use super::super::{
+    channel_keys::{RekeyEvent, MAX_CHANNEL_MEMBERS, MSG_TYPE_CHANNEL_REKEY},
+    client::ReplyType,
+    redaction::AdminRedaction,
+    rpl::*,
+};
+use crate::{IrcServer, Privmsg};
+
+const CHANSERV_USAGE: &str = r#"***** ChanServ Help *****
+
+ChanServ manages the members of encrypted channels you are the
+admin of. Members are configured contacts. Every change of members
+rotates the channel key, and the new key is sent only to the
+remaining members.
+
+The following commands are available:
+
+  INFO          Display the key state of a channel.
+  ADD           Add a contact to a channel.
+  REMOVE        Remove a contact from a channel.
+  ROTATE        Rotate the key of a channel.
+  REDACT        Redact a message of a channel.
+
+For more information on a ChanServ command, type:
+/msg ChanServ HELP <command>
+
+***** End of Help *****
+"#;
+
+const CHANSERV_INFO_HELP: &str = r#"***** ChanServ Help: INFO *****
+
+INFO shows the current key epoch and the admin public key of an
+encrypted channel. If you are the channel admin, it also lists the
+channel members.
+
+  INFO <#channel>
+
+***** End of Help *****
+"#;
+
+const CHANSERV_ADD_HELP: &str = r#"***** ChanServ Help: ADD *****
+
+ADD makes a configured contact a member of a channel you are the
+admin of, and rotates the channel key. The new member can read
+messages from now on, but not the ones sent under older keys.
+
+  ADD <#channel> <contact>
+
+***** End of Help *****
+"#;
+
+const CHANSERV_REMOVE_HELP: &str = r#"***** ChanServ Help: REMOVE *****
+
+REMOVE drops a contact from a channel you are the admin of, and
+rotates the channel key. The removed member can't read new messages,
+and their messages are dropped once the rekey had time to propagate.
+
+  REMOVE <#channel> <contact>
+
+***** End of Help *****
+"#;
+
+const CHANSERV_ROTATE_HELP: &str = r#"***** ChanServ Help: ROTATE *****
+
+ROTATE sends a new key to the current members of a channel you are
+the admin of. Use it if a member's key might be compromised, or if
+a previous rekey was not published.
+
+  ROTATE <#channel>
+
+***** End of Help *****
+"#;
+
+const CHANSERV_REDACT_HELP: &str = r#"***** ChanServ Help: REDACT *****
+
+REDACT drops the content of a message sent to a channel you are the
+admin of. The message is identified by its msgid tag. Nodes having
+the channel configured drop the content too, and serve a placeholder
+to syncing peers instead.
+
+  REDACT <#channel> <msgid>
+
+***** End of Help *****
+"#;
+
+/// ChanServ implementation used for encrypted channel management
+pub struct ChanServ {
+    /// Client nickname
+    pub nickname: Arc<RwLock<String>>,
+    /// Pointer to parent `IrcServer`
+    pub server: Arc<IrcServer>,
+    /// Events created by commands, to be published by the client
+    outgoing: Mutex<Vec<(Event, Privmsg)>>,
+}
+
+/// Convenience helper - build a ChanServ NOTICE reply.
+fn notice(nick: &str, body: impl Into<String>) -> ReplyType {
+    ReplyType::Notice(("ChanServ".to_string(), nick.to_string(), body.into()))
+}
+
+/// Convenience helper - build several NOTICE replies from an iterator
+/// of strings, one per line.
+fn notices<I, S>(nick: &str, lines: I) -> Vec<ReplyType>
+where
+    I: IntoIterator<Item = S>,
+    S: Into<String>,
+{
+    lines.into_iter().map(|s| notice(nick, s)).collect()
+}
+
+impl ChanServ {
+    /// Instantiate a new `ChanServ` for a client.
+    pub fn new(nickname: Arc<RwLock<String>>, server: Arc<IrcServer>) -> Self {
+        Self { nickname, server, outgoing: Mutex::new(vec![]) }
+    }
+
+    /// Take the rekey events created by the handled commands
+    pub async fn take_outgoing(&self) -> Vec<(Event, Privmsg)> {
+        std::mem::take(&mut *self.outgoing.lock().await)
+    }
+
+    /// Handle a `ChanServ` query. This is the main command handler.
+    /// Called from `command::handle_cmd_privmsg`.
+    pub async fn handle_query(&self, query: &str) -> Result<Vec<ReplyType>> {
+        let nick = self.nickname.read().await.to_string();
+        let Some((command, mut tokens)) = parse_chanserv_command(query) else {
+            return Ok(vec![ReplyType::Server((
+                ERR_NOTEXTTOSEND,
+                format!("{nick} :No text to send"),
+            ))])
+        };
+
+        match command.to_uppercase().as_str() {
+            "INFO" => self.handle_info(&nick, &mut tokens).await,
+            "ADD" => self.handle_add(&nick, &mut tokens).await,
+            "REMOVE" => self.handle_remove(&nick, &mut tokens).await,
+            "ROTATE" => self.handle_rotate(&nick, &mut tokens).await,
+            "REDACT" => self.handle_redact(&nick, &mut tokens).await,
+            "HELP" => self.handle_help(&nick, &mut tokens).await,
+            _ => self.handle_invalid(&nick).await,
+        }
+    }
+
+    /// Handle the INFO command.
+    ///
+    /// `INFO <#channel>`
+    pub async fn handle_info(
+        &self,
+        nick: &str,
+        tokens: &mut SplitAsciiWhitespace<'_>,
+    ) -> Result<Vec<ReplyType>> {
+        let Some(channel) = tokens.next() else {
+            return Ok(vec![notice(nick, "Usage: INFO <#channel>")])
+        };
+
+        let Some(chan) = self.server.channels.read().await.get(channel).cloned() else {
+            return Ok(vec![notice(nick, format!("No such configured channel: {channel}"))])
+        };
+
+        if chan.saltbox.is_none() && chan.admin_public.is_none() {
+            return Ok(vec![notice(nick, format!("{channel} is not an encrypted channel."))])
+        }
+
+        let epoch = self.server.channel_keys.current(channel).await.map_or(0, |key| key.epoch);
+        let mut lines = vec![format!("Channel {channel}:"), format!("  key epoch = {epoch}")];
+
+        match chan.admin_public {
+            Some(admin) => lines.push(format!("  admin     = {admin}")),
+            None => lines.push("  admin     = none, the key can't be rotated".to_string()),
+        }
+
+        if chan.admin_secret.is_some() {
+            let members = self.server.channel_keys.members(channel).await?;
+            lines.push(format!("  members   = {}", members.join(" ")));
+        }
+
+        Ok(notices(nick, lines))
+    }
+
+    /// Handle the ADD command.
+    ///
+    /// `ADD <#channel> <contact>`
+    pub async fn handle_add(
+        &self,
+        nick: &str,
+        tokens: &mut SplitAsciiWhitespace<'_>,
+    ) -> Result<Vec<ReplyType>> {
+        let (Some(channel), Some(contact)) = (tokens.next(), tokens.next()) else {
+            return Ok(vec![notice(nick, "Usage: ADD <#channel> <contact>")])
+        };
+
+        if !self.server.contacts.read().await.contains_key(contact) {
+            return Ok(vec![notice(nick, format!("No such configured contact: {contact}"))])
+        }
+
+        let mut members = self.server.channel_keys.members(channel).await?;
+        if members.iter().any(|m| m == contact) {
+            return Ok(vec![notice(nick, format!("{contact} is already a member of {channel}"))])
+        }
+
+        if members.len() >= MAX_CHANNEL_MEMBERS {
+            return Ok(vec![notice(
+                nick,
+                format!("{channel} reached the limit of {MAX_CHANNEL_MEMBERS} members"),
+            )])
+        }
+
+        members.push(contact.to_string());
+        self.rotate(nick, channel, members, format!("Added {contact} to {channel}.")).await
+    }
+
+    /// Handle the REMOVE command.
+    ///
+    /// `REMOVE <#channel> <contact>`
+    pub async fn handle_remove(
+        &self,
+        nick: &str,
+        tokens: &mut SplitAsciiWhitespace<'_>,
+    ) -> Result<Vec<ReplyType>> {
+        let (Some(channel), Some(contact)) = (tokens.next(), tokens.next()) else {
+            return Ok(vec![notice(nick, "Usage: REMOVE <#channel> <contact>")])
+        };
+
+        let mut members = self.server.channel_keys.members(channel).await?;
+        let Some(idx) = members.iter().position(|m| m == contact) else {
+            return Ok(vec![notice(nick, format!("{contact} is not a member of {channel}"))])
+        };
+
+        members.remove(idx);
+        self.rotate(nick, channel, members, format!("Removed {contact} from {channel}.")).await
+    }
+
+    /// Handle the ROTATE command.
+    ///
+    /// `ROTATE <#channel>`
+    pub async fn handle_rotate(
+        &self,
+        nick: &str,
+        tokens: &mut SplitAsciiWhitespace<'_>,
+    ) -> Result<Vec<ReplyType>> {
+        let Some(channel) = tokens.next() else {
+            return Ok(vec![notice(nick, "Usage: ROTATE <#channel>")])
+        };
+
+        let members = self.server.channel_keys.members(channel).await?;
+        self.rotate(nick, channel, members, format!("Rotated the key of {channel}.")).await
+    }
+
+    /// Rotate the key of a channel we are the admin of, for the given
+    /// members. On success, the new members are stored and the rekey
+    /// event is queued for publishing.
+    async fn rotate(
+        &self,
+        nick: &str,
+        channel: &str,
+        members: Vec<String>,
+        done: String,
+    ) -> Result<Vec<ReplyType>> {
+        let Some(admin) =
+            self.server.channels.read().await.get(channel).and_then(|c| c.admin_secret)
+        else {
+            return Ok(vec![notice(nick, format!("You are not the admin of {channel}"))])
+        };
+
+        // The rekey must be built on top of the canonical DAG, like
+        // any other event we publish.
+        if !self.server.darkirc.event_graph.is_synced() {
+            return Ok(vec![notice(nick, "The DAG is still syncing, try again later.")])
+        }
+
+        let mut boxes = Vec::with_capacity(members.len());
+        {
+            let contacts = self.server.contacts.read().await;
+            for member in &members {
+                let Some(contact) = contacts.get(member) else {
+                    return Ok(vec![notice(
+                        nick,
+                        format!("Member {member} is not a configured contact anymore"),
+                    )])
+                };
+                boxes.push(contact.saltbox.clone());
+            }
+        }
+
+        let epoch = self.server.channel_keys.current(channel).await.map_or(0, |k| k.epoch) + 1;
+        let secret = crypto_box::SecretKey::generate(&mut OsRng).to_bytes();
+        let rekey = RekeyEvent::new(&admin, channel, epoch, &secret, &boxes);
+
+        // The channel and nick fields are random, so rekeys look like
+        // any other encrypted message.
+        let privmsg = Privmsg {
+            version: 0,
+            msg_type: MSG_TYPE_CHANNEL_REKEY,
+            channel: random_field(),
+            nick: random_field(),
+            msg: bs58::encode(serialize_async(&rekey).await).into_string(),
+        };
+
+        let event_graph = &self.server.darkirc.event_graph;
+        let event = Event::new(serialize_async(&privmsg).await, event_graph).await?;
+
+        self.server.channel_keys.insert(channel, epoch, event.header.timestamp, secret).await?;
+        self.server.channel_keys.set_members(channel, members).await?;
+
+        // The plaintext is empty, so it is not echoed to the client.
+        let plaintext = Privmsg {
+            version: 0,
+            msg_type: MSG_TYPE_CHANNEL_REKEY,
+            channel: channel.to_string(),
+            nick: nick.to_string(),
+            msg: String::new(),
+        };
+        self.outgoing.lock().await.push((event, plaintext));
+
+        Ok(notices(nick, [done, format!("{channel} is now at key epoch {epoch}.")]))
+    }
+
+    /// Handle the REDACT command.
+    ///
+    /// `REDACT <#channel> <msgid>`
+    pub async fn handle_redact(
+        &self,
+        nick: &str,
+        tokens: &mut SplitAsciiWhitespace<'_>,
+    ) -> Result<Vec<ReplyType>> {
+        let (Some(channel), Some(msgid)) = (tokens.next(), tokens.next()) else {
+            return Ok(vec![notice(nick, "Usage: REDACT <#channel> <msgid>")])
+        };
+
+        let Some(admin) =
+            self.server.channels.read().await.get(channel).and_then(|c| c.admin_secret)
+        else {
+            return Ok(vec![notice(nick, format!("You are not the admin of {channel}"))])
+        };
+
+        let Ok(target) = blake3::Hash::from_hex(msgid) else {
+            return Ok(vec![notice(nick, format!("Invalid msgid: {msgid}"))])
+        };
+
+        let event_graph = &self.server.darkirc.event_graph;
+        if !event_graph.is_synced() {
+            return Ok(vec![notice(nick, "The DAG is still syncing, try again later.")])
+        }
+
+        if event_graph.fetch_event_from_dags(&target).await?.is_none() {
+            return Ok(vec![notice(nick, format!("Unknown message {msgid}"))])
+        }
+
+        let auth = AdminRedaction::new(&admin, channel, &target);
+        let redaction = Redaction::new(target, serialize(&auth));
+        let event = Event::new(redaction.to_content(), event_graph).await?;
+
+        // The plaintext is empty, so it is not echoed to the client.
+        let plaintext = Privmsg {
+            version: 0,
+            msg_type: 0,
+            channel: channel.to_string(),
+            nick: nick.to_string(),
+            msg: String::new(),
+        };
+        self.outgoing.lock().await.push((event, plaintext));
+
+        Ok(vec![notice(nick, format!("Redacted {msgid} in {channel}."))])
+    }
+
+    /// Reply to the HELP command.
+    ///
+    /// `HELP` (no args)        -> top-level usage
+    /// `HELP <command_name>`   -> per-command help block
+    pub async fn handle_help(
+        &self,
+        nick: &str,
+        tokens: &mut SplitAsciiWhitespace<'_>,
+    ) -> Result<Vec<ReplyType>> {
+        let body = match tokens.next() {
+            None => CHANSERV_USAGE,
+            Some(sub) => match sub.to_uppercase().as_str() {
+                "INFO" => CHANSERV_INFO_HELP,
+                "ADD" => CHANSERV_ADD_HELP,
+                "REMOVE" => CHANSERV_REMOVE_HELP,
+                "ROTATE" => CHANSERV_ROTATE_HELP,
+                "REDACT" => CHANSERV_REDACT_HELP,
+                "HELP" => CHANSERV_USAGE,
+                _ => {
+                    return Ok(vec![notice(
+                        nick,
+                        format!("No help available for \"{sub}\". Try `HELP`."),
+                    )])
+                }
+            },
+        };
+
+        Ok(notices(nick, body.lines().map(str::to_string)))
+    }
+
+    /// Reply to an invalid command
+    pub async fn handle_invalid(&self, nick: &str) -> Result<Vec<ReplyType>> {
+        Ok(notices(
+            nick,
+            ["Invalid ChanServ command.", "Use /msg ChanServ HELP for a ChanServ command listing."],
+        ))
+    }
+}
+
+/// Random base58 field the size of an encrypted dummy field
+fn random_field() -> String {
+    let mut bytes = [0u8; 64];
+    OsRng.fill_bytes(&mut bytes);
+    bs58::encode(bytes).into_string()
+}
+
+/// Parse a ChanServ PRIVMSG body into the service command and remaining arguments.
+fn parse_chanserv_command(query: &str) -> Option<(&str, SplitAsciiWhitespace<'_>)> {
+    let mut tokens = query.split_ascii_whitespace();
+    tokens.next()?;
+
+    let command = tokens.next()?.strip_prefix(':')?;
+    if command.is_empty() {
+        return None
+    }
+
+    Some((command, tokens))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_chanserv_command;
+
+    #[test]
+    fn parse_chanserv_command_accepts_colon_prefixed_command() {
+        let (command, mut tokens) = parse_chanserv_command("ChanServ :ADD #dev alice").unwrap();
+
+        assert_eq!(command, "ADD");
+        assert_eq!(tokens.next(), Some("#dev"));
+        assert_eq!(tokens.next(), Some("alice"));
+        assert_eq!(tokens.next(), None);
+    }
+
+    #[test]
+    fn parse_chanserv_command_rejects_bare_command() {
+        assert!(parse_chanserv_command("ChanServ ROTATE #dev").is_none());
+        assert!(parse_chanserv_command("ChanServ :").is_none());
+    }
+}
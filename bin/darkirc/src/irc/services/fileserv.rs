@@ -0,0 +1,352 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2026 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! FileServ - file sharing through fud for DarkIRC.
+//!
+//! Files are transferred by the local fud daemon, see
+//! [`crate::irc::files`] for the details.
+//!
+//! Commands:
+//!
+//! - `SHARE <target> <path>` - encrypt and seed a local file, and
+//!   announce it to an encrypted channel or a contact.
+//! - `FETCH <hash>` - download a file announced to us. A NOTICE is
+//!   sent once the download finished or failed.
+//! - `HELP` - usage.
+
+use std::{path::PathBuf, str::SplitAsciiWhitespace, sync::Arc};
+
+use darkfi::{
+    geode::hash_to_string,
+    system::{Publisher, PublisherPtr, StoppableTask, Subscription},
+    Error, Result,
+};
+use smol::lock::{Mutex, RwLock};
+use tracing::error;
+
+use super::super::{client::ReplyType, rpl::*};
+use crate::{IrcServer, Privmsg};
+
+const FILESERV_USAGE: &str = r#"***** FileServ Help *****
+
+FileServ shares files peer-to-peer through fud, which must be
+running on this machine. Files are encrypted, and can only be
+shared to encrypted channels and contacts.
+
+The following commands are available:
+
+  SHARE         Share a file to a channel or a contact.
+  FETCH         Download a file shared with you.
+
+For more information on a FileServ command, type:
+/msg FileServ HELP <command>
+
+***** End of Help *****
+"#;
+
+const FILESERV_SHARE_HELP: &str = r#"***** FileServ Help: SHARE *****
+
+SHARE encrypts a local file, seeds it with fud and announces it to
+an encrypted channel or a contact. Keep darkirc and fud running for
+others to be able to download it.
+
+  SHARE <target> <path>
+
+***** End of Help *****
+"#;
+
+const FILESERV_FETCH_HELP: &str = r#"***** FileServ Help: FETCH *****
+
+FETCH downloads a file shared with you, using the hash from its
+announcement. The file is saved in the downloads directory of the
+darkirc shares datastore. You get a notice once it is done.
+
+  FETCH <hash>
+
+***** End of Help *****
+"#;
+
+/// FileServ implementation used for file sharing
+pub struct FileServ {
+    /// Client nickname
+    pub nickname: Arc<RwLock<String>>,
+    /// Pointer to parent `IrcServer`
+    pub server: Arc<IrcServer>,
+    /// File offers created by commands, to be published by the client
+    outgoing: Mutex<Vec<Privmsg>>,
+    /// Results of finished downloads, to be sent to the client
+    results: PublisherPtr<String>,
+}
+
+/// Convenience helper - build a FileServ NOTICE reply.
+fn notice(nick: &str, body: impl Into<String>) -> ReplyType {
+    ReplyType::Notice(("FileServ".to_string(), nick.to_string(), body.into()))
+}
+
+/// Convenience helper - build several NOTICE replies from an iterator
+/// of strings, one per line.
+fn notices<I, S>(nick: &str, lines: I) -> Vec<ReplyType>
+where
+    I: IntoIterator<Item = S>,
+    S: Into<String>,
+{
+    lines.into_iter().map(|s| notice(nick, s)).collect()
+}
+
+impl FileServ {
+    /// Instantiate a new `FileServ` for a client.
+    pub fn new(nickname: Arc<RwLock<String>>, server: Arc<IrcServer>) -> Self {
+        Self { nickname, server, outgoing: Mutex::new(vec![]), results: Publisher::new() }
+    }
+
+    /// Take the file offers created by the handled commands
+    pub async fn take_outgoing(&self) -> Vec<Privmsg> {
+        std::mem::take(&mut *self.outgoing.lock().await)
+    }
+
+    /// Subscribe to the results of finished downloads
+    pub async fn subscribe_results(&self) -> Subscription<String> {
+        self.results.clone().subscribe().await
+    }
+
+    /// Handle a `FileServ` query. This is the main command handler.
+    /// Called from `command::handle_cmd_privmsg`.
+    pub async fn handle_query(&self, query: &str) -> Result<Vec<ReplyType>> {
+        let nick = self.nickname.read().await.to_string();
+        let Some((command, mut tokens)) = parse_fileserv_command(query) else {
+            return Ok(vec![ReplyType::Server((
+                ERR_NOTEXTTOSEND,
+                format!("{nick} :No text to send"),
+            ))])
+        };
+
+        match command.to_uppercase().as_str() {
+            "SHARE" => self.handle_share(&nick, query).await,
+            "FETCH" => self.handle_fetch(&nick, &mut tokens).await,
+            "HELP" => self.handle_help(&nick, &mut tokens).await,
+            _ => self.handle_invalid(&nick).await,
+        }
+    }
+
+    /// Handle the SHARE command.
+    ///
+    /// `SHARE <target> <path>`
+    pub async fn handle_share(&self, nick: &str, query: &str) -> Result<Vec<ReplyType>> {
+        let Some((target, path)) = parse_share_args(query) else {
+            return Ok(vec![notice(nick, "Usage: SHARE <target> <path>")])
+        };
+
+        // The offer carries the file key, so it must be encrypted
+        if !self.is_encrypted_target(target).await {
+            return Ok(vec![notice(
+                nick,
+                format!("{target} is not an encrypted channel or a contact"),
+            )])
+        }
+
+        // The offer is published like any other message, on top of
+        // the canonical DAG.
+        if !self.server.darkirc.event_graph.is_synced() {
+            return Ok(vec![notice(nick, "The DAG is still syncing, try again later.")])
+        }
+
+        let offer = match self.server.files.share(&PathBuf::from(path)).await {
+            Ok(v) => v,
+            Err(e) => return Ok(vec![notice(nick, format!("Failed sharing {path}: {e}"))]),
+        };
+
+        self.outgoing.lock().await.push(Privmsg {
+            version: 0,
+            msg_type: 0,
+            channel: target.to_string(),
+            nick: nick.to_string(),
+            msg: offer.to_message(),
+        });
+
+        Ok(vec![notice(
+            nick,
+            format!("Shared {} to {target} as {}", offer.name, hash_to_string(&offer.hash)),
+        )])
+    }
+
+    /// Handle the FETCH command.
+    ///
+    /// `FETCH <hash>`
+    pub async fn handle_fetch(
+        &self,
+        nick: &str,
+        tokens: &mut SplitAsciiWhitespace<'_>,
+    ) -> Result<Vec<ReplyType>> {
+        let Some(hash_str) = tokens.next() else {
+            return Ok(vec![notice(nick, "Usage: FETCH <hash>")])
+        };
+
+        let mut hash = [0u8; 32];
+        if !matches!(bs58::decode(hash_str).onto(&mut hash), Ok(32)) {
+            return Ok(vec![notice(nick, format!("Invalid file hash: {hash_str}"))])
+        }
+
+        let Some(offer) = self.server.files.offer(&blake3::Hash::from_bytes(hash))? else {
+            return Ok(vec![notice(nick, format!("No file was shared with hash {hash_str}"))])
+        };
+
+        if offer.size > self.server.files.max_size {
+            return Ok(vec![notice(
+                nick,
+                format!(
+                    "{} is {} bytes, over the limit of {} bytes",
+                    offer.name, offer.size, self.server.files.max_size
+                ),
+            )])
+        }
+
+        // Downloads can take a while, so they run in the background
+        // and report back through a notice.
+        let server = self.server.clone();
+        let results = self.results.clone();
+        let task = StoppableTask::new();
+        task.clone().start(
+            async move {
+                let result = match server.files.fetch(&offer).await {
+                    Ok(path) => format!("Downloaded {} to {}", offer.name, path.display()),
+                    Err(e) => format!("Failed downloading {}: {e}", offer.name),
+                };
+                results.notify(result).await;
+                Ok(())
+            },
+            |res| async {
+                match res {
+                    Ok(()) | Err(Error::DetachedTaskStopped) => { /* Do nothing */ }
+                    Err(e) => error!(target: "darkirc::irc::fileserv", "Download task failed: {e}"),
+                }
+            },
+            Error::DetachedTaskStopped,
+            self.server.files.executor(),
+        );
+
+        Ok(vec![notice(nick, format!("Downloading {} ({} bytes)...", offer.name, offer.size))])
+    }
+
+    /// Reply to the HELP command.
+    ///
+    /// `HELP` (no args)        -> top-level usage
+    /// `HELP <command_name>`   -> per-command help block
+    pub async fn handle_help(
+        &self,
+        nick: &str,
+        tokens: &mut SplitAsciiWhitespace<'_>,
+    ) -> Result<Vec<ReplyType>> {
+        let body = match tokens.next() {
+            None => FILESERV_USAGE,
+            Some(sub) => match sub.to_uppercase().as_str() {
+                "SHARE" => FILESERV_SHARE_HELP,
+                "FETCH" => FILESERV_FETCH_HELP,
+                "HELP" => FILESERV_USAGE,
+                _ => {
+                    return Ok(vec![notice(
+                        nick,
+                        format!("No help available for \"{sub}\". Try `HELP`."),
+                    )])
+                }
+            },
+        };
+
+        Ok(notices(nick, body.lines().map(str::to_string)))
+    }
+
+    /// Reply to an invalid command
+    pub async fn handle_invalid(&self, nick: &str) -> Result<Vec<ReplyType>> {
+        Ok(notices(
+            nick,
+            ["Invalid FileServ command.", "Use /msg FileServ HELP for a FileServ command listing."],
+        ))
+    }
+
+    /// Whether messages to `target` are encrypted
+    async fn is_encrypted_target(&self, target: &str) -> bool {
+        if self.server.contacts.read().await.contains_key(target) {
+            return true
+        }
+
+        let Some(channel) = self.server.channels.read().await.get(target).cloned() else {
+            return false
+        };
+
+        channel.saltbox.is_some() || self.server.channel_keys.current(target).await.is_some()
+    }
+}
+
+/// Parse a FileServ PRIVMSG body into the service command and remaining arguments.
+fn parse_fileserv_command(query: &str) -> Option<(&str, SplitAsciiWhitespace<'_>)> {
+    let mut tokens = query.split_ascii_whitespace();
+    tokens.next()?;
+
+    let command = tokens.next()?.strip_prefix(':')?;
+    if command.is_empty() {
+        return None
+    }
+
+    Some((command, tokens))
+}
+
+/// Parse the arguments of a SHARE query into the target and the path.
+/// The path is the raw rest of the line, so whitespace inside file names
+/// is kept as is.
+fn parse_share_args(query: &str) -> Option<(&str, &str)> {
+    let is_space = |c: char| c.is_ascii_whitespace();
+
+    // Skip the service name and the command
+    let (_, rest) = query.trim_start_matches(is_space).split_once(is_space)?;
+    let (_, rest) = rest.trim_start_matches(is_space).split_once(is_space)?;
+
+    let (target, path) = rest.trim_start_matches(is_space).split_once(is_space)?;
+    let path = path.trim_start_matches(is_space);
+    if path.is_empty() {
+        return None
+    }
+
+    Some((target, path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_fileserv_command, parse_share_args};
+
+    #[test]
+    fn parse_fileserv_command_accepts_colon_prefixed_command() {
+        let (command, mut tokens) =
+            parse_fileserv_command("FileServ :SHARE #dev /tmp/a b.png").unwrap();
+
+        assert_eq!(command, "SHARE");
+        assert_eq!(tokens.next(), Some("#dev"));
+        assert_eq!(tokens.collect::<Vec<_>>().join(" "), "/tmp/a b.png");
+
+        assert_eq!(
+            parse_share_args("FileServ :SHARE #dev /tmp/a  b\tc.png"),
+            Some(("#dev", "/tmp/a  b\tc.png"))
+        );
+        assert_eq!(parse_share_args("FileServ :SHARE #dev"), None);
+        assert_eq!(parse_share_args("FileServ :SHARE #dev   "), None);
+    }
+
+    #[test]
+    fn parse_fileserv_command_rejects_bare_command() {
+        assert!(parse_fileserv_command("FileServ FETCH abc").is_none());
+        assert!(parse_fileserv_command("FileServ :").is_none());
+    }
+}
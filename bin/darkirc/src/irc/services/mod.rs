@@ -18,3 +18,12 @@
 
 /// NickServ implementation, used for account management
 pub mod nickserv;
+
+/// ChanServ implementation, used for encrypted channel management
+pub mod chanserv;
+
+/// FileServ implementation, used for file sharing
+pub mod fileserv;
+
+/// SearchServ implementation, used for searching the message history
+pub mod searchserv;
@@ -0,0 +1,231 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2026 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! SearchServ - local search over the DarkIRC message history.
+//!
+//! Searches the index kept by [`crate::irc::search`].
+//!
+//! Commands:
+//!
+//! - `SEARCH <query>` - list the latest messages matching the query.
+//! - `HELP` - usage.
+
+use std::{str::SplitAsciiWhitespace, sync::Arc};
+
+use darkfi::Result;
+use smol::lock::RwLock;
+
+use super::super::{
+    client::{format_server_time, message_lines, ReplyType},
+    rpl::*,
+    search::{retention_cutoff, SearchQuery},
+};
+use crate::IrcServer;
+
+/// Number of results listed by SEARCH
+const SEARCHSERV_RESULTS: usize = 20;
+
+const SEARCHSERV_USAGE: &str = r#"***** SearchServ Help *****
+
+SearchServ searches the messages this node has seen in public
+channels. Encrypted channels and direct messages are not indexed.
+The search is done locally, and only covers the retained history.
+
+The following commands are available:
+
+  SEARCH        Search the message history.
+
+For more information on a SearchServ command, type:
+/msg SearchServ HELP <command>
+
+***** End of Help *****
+"#;
+
+const SEARCHSERV_SEARCH_HELP: &str = r#"***** SearchServ Help: SEARCH *****
+
+SEARCH lists the latest messages containing all the given words.
+A word ending with * matches any word starting with it.
+
+  SEARCH <words> [in:<channel>] [from:<nick>]
+
+Examples:
+  SEARCH rln proof
+  SEARCH in:#dev from:alice release*
+
+***** End of Help *****
+"#;
+
+/// SearchServ implementation used for searching the message history
+pub struct SearchServ {
+    /// Client nickname
+    pub nickname: Arc<RwLock<String>>,
+    /// Pointer to parent `IrcServer`
+    pub server: Arc<IrcServer>,
+}
+
+/// Convenience helper - build a SearchServ NOTICE reply.
+fn notice(nick: &str, body: impl Into<String>) -> ReplyType {
+    ReplyType::Notice(("SearchServ".to_string(), nick.to_string(), body.into()))
+}
+
+/// Convenience helper - build several NOTICE replies from an iterator
+/// of strings, one per line.
+fn notices<I, S>(nick: &str, lines: I) -> Vec<ReplyType>
+where
+    I: IntoIterator<Item = S>,
+    S: Into<String>,
+{
+    lines.into_iter().map(|s| notice(nick, s)).collect()
+}
+
+impl SearchServ {
+    /// Instantiate a new `SearchServ` for a client.
+    pub fn new(nickname: Arc<RwLock<String>>, server: Arc<IrcServer>) -> Self {
+        Self { nickname, server }
+    }
+
+    /// Handle a `SearchServ` query. This is the main command handler.
+    /// Called from `command::handle_cmd_privmsg`.
+    pub async fn handle_query(&self, query: &str) -> Result<Vec<ReplyType>> {
+        let nick = self.nickname.read().await.to_string();
+        let Some((command, mut tokens)) = parse_searchserv_command(query) else {
+            return Ok(vec![ReplyType::Server((
+                ERR_NOTEXTTOSEND,
+                format!("{nick} :No text to send"),
+            ))])
+        };
+
+        match command.to_uppercase().as_str() {
+            "SEARCH" => self.handle_search(&nick, &mut tokens).await,
+            "HELP" => self.handle_help(&nick, &mut tokens).await,
+            _ => self.handle_invalid(&nick).await,
+        }
+    }
+
+    /// Handle the SEARCH command.
+    ///
+    /// `SEARCH <query>`
+    pub async fn handle_search(
+        &self,
+        nick: &str,
+        tokens: &mut SplitAsciiWhitespace<'_>,
+    ) -> Result<Vec<ReplyType>> {
+        let query_str = tokens.collect::<Vec<_>>().join(" ");
+        let query = SearchQuery::parse(&query_str);
+        if query.is_empty() {
+            return Ok(vec![notice(nick, "Usage: SEARCH <words> [in:<channel>] [from:<nick>]")])
+        }
+
+        let cutoff = retention_cutoff(&self.server.darkirc.event_graph).await;
+        let hits = self.server.darkirc.search.search(&query, cutoff, SEARCHSERV_RESULTS)?;
+        if hits.is_empty() {
+            return Ok(vec![notice(nick, format!("No messages match \"{query_str}\""))])
+        }
+
+        let mut replies = vec![notice(
+            nick,
+            format!("Latest {} message(s) matching \"{query_str}\":", hits.len()),
+        )];
+
+        // Oldest first, like the rest of the history
+        for hit in hits.iter().rev() {
+            let line = message_lines(&hit.msg).join(" ");
+            replies.push(notice(
+                nick,
+                format!(
+                    "[{}] {} <{}> {line}",
+                    format_server_time(hit.timestamp),
+                    hit.channel,
+                    hit.nick
+                ),
+            ));
+        }
+
+        Ok(replies)
+    }
+
+    /// Reply to the HELP command.
+    ///
+    /// `HELP` (no args)        -> top-level usage
+    /// `HELP <command_name>`   -> per-command help block
+    pub async fn handle_help(
+        &self,
+        nick: &str,
+        tokens: &mut SplitAsciiWhitespace<'_>,
+    ) -> Result<Vec<ReplyType>> {
+        let body = match tokens.next() {
+            None => SEARCHSERV_USAGE,
+            Some(sub) => match sub.to_uppercase().as_str() {
+                "SEARCH" => SEARCHSERV_SEARCH_HELP,
+                "HELP" => SEARCHSERV_USAGE,
+                _ => {
+                    return Ok(vec![notice(
+                        nick,
+                        format!("No help available for \"{sub}\". Try `HELP`."),
+                    )])
+                }
+            },
+        };
+
+        Ok(notices(nick, body.lines().map(str::to_string)))
+    }
+
+    /// Reply to an invalid command
+    pub async fn handle_invalid(&self, nick: &str) -> Result<Vec<ReplyType>> {
+        Ok(notices(
+            nick,
+            [
+                "Invalid SearchServ command.",
+                "Use /msg SearchServ HELP for a SearchServ command listing.",
+            ],
+        ))
+    }
+}
+
+/// Parse a SearchServ PRIVMSG body into the service command and remaining arguments.
+fn parse_searchserv_command(query: &str) -> Option<(&str, SplitAsciiWhitespace<'_>)> {
+    let mut tokens = query.split_ascii_whitespace();
+    tokens.next()?;
+
+    let command = tokens.next()?.strip_prefix(':')?;
+    if command.is_empty() {
+        return None
+    }
+
+    Some((command, tokens))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_searchserv_command;
+
+    #[test]
+    fn parse_searchserv_command_accepts_colon_prefixed_command() {
+        let (command, tokens) =
+            parse_searchserv_command("SearchServ :SEARCH in:#dev rln proof").unwrap();
+
+        assert_eq!(command, "SEARCH");
+        assert_eq!(tokens.collect::<Vec<_>>(), vec!["in:#dev", "rln", "proof"]);
+    }
+
+    #[test]
+    fn parse_searchserv_command_rejects_bare_command() {
+        assert!(parse_searchserv_command("SearchServ SEARCH rln").is_none());
+        assert!(parse_searchserv_command("SearchServ :").is_none());
+    }
+}
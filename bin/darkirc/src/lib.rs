@@ -27,7 +27,7 @@ use darkfi_serial::{async_trait, SerialDecodable, SerialEncodable};
 
 /// IRC server and client handler implementation
 pub mod irc;
-use irc::server::IrcServer;
+use irc::{search::SearchIndex, server::IrcServer};
 
 use crate::irc::server::MAX_NICK_LEN;
 
@@ -70,6 +70,8 @@ pub struct DarkIrc {
     gource_sub: JsonSubscriber,
     /// Replay logs (DB) path
     replay_datastore: PathBuf,
+    /// Full-text index of the message history
+    search: SearchIndex,
 }
 
 impl DarkIrc {
@@ -81,6 +83,7 @@ impl DarkIrc {
         deg_sub: JsonSubscriber,
         gource_sub: JsonSubscriber,
         replay_datastore: PathBuf,
+        search: SearchIndex,
     ) -> Self {
         Self {
             p2p,
@@ -91,6 +94,7 @@ impl DarkIrc {
             deg_sub,
             gource_sub,
             replay_datastore,
+            search,
         }
     }
 }
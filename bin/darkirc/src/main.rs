@@ -26,8 +26,10 @@ use std::{
 use darkfi::{
     async_daemonize, cli_desc,
     event_graph::{
-        proto::ProtocolEventGraph, rln::GENESIS_USER_MSG_LIMIT, EventGraph, EventGraphConfig,
-        EventGraphPtr,
+        proto::ProtocolEventGraph,
+        rln::GENESIS_USER_MSG_LIMIT,
+        storage::{migrate_sled_dags, StorageKind},
+        EventGraph, EventGraphConfig, EventGraphPtr,
     },
     net::{session::SESSION_DEFAULT, settings::SettingsOpt, P2p, P2pPtr},
     rpc::{
@@ -49,7 +51,7 @@ use darkfi_serial::serialize;
 use irc2::{
     crypto::{bcrypt::bcrypt_hash_password, rln::RlnIdentity},
     genesis_commits,
-    irc::server::IrcServer,
+    irc::{files::FileShares, search::SearchIndex, server::IrcServer},
     rpc,
     settings::list_configured_contacts,
     DarkIrc,
@@ -185,6 +187,18 @@ struct Args {
     /// Sled cache capacity for the datastore, in MiB
     sled_cache_mb: u64,
 
+    #[structopt(long, default_value = "sled")]
+    /// Storage backend for the rotating DAGs (sled or log)
+    dag_storage: String,
+
+    #[structopt(long, default_value = "~/.local/share/darkfi/darkirc/dag_log")]
+    /// Directory of the log storage backend
+    dag_log_datastore: String,
+
+    #[structopt(long)]
+    /// Move the DAGs of the sled datastore to the configured dag_storage and exit
+    migrate_dag_storage: bool,
+
     #[structopt(long, default_value = "~/.local/share/darkfi/darkirc/zk_keys")]
     /// Datastore path for RLN proving and verifying keys
     zk_key_datastore: String,
@@ -217,6 +231,10 @@ struct Args {
     /// Generate a new encrypted channel NaCl secret and exit
     gen_channel_secret: bool,
 
+    #[structopt(long)]
+    /// Generate a new encrypted channel admin keypair and exit
+    gen_channel_admin_keypair: bool,
+
     #[structopt(long = "get-chacha-pubkey")]
     /// Recover NaCl public key from a secret key
     chacha_secret: Option<String>,
@@ -245,6 +263,18 @@ struct Args {
     /// List configured contacts.
     list_contacts: bool,
 
+    #[structopt(long, default_value = "tcp://127.0.0.1:13336")]
+    /// fud JSON-RPC endpoint, used for file sharing
+    fud_endpoint: Url,
+
+    #[structopt(long, default_value = "~/.local/share/darkfi/darkirc/shares")]
+    /// Datastore path for shared and downloaded files
+    shares_datastore: String,
+
+    #[structopt(long, default_value = "16")]
+    /// Maximum size of shared and downloaded files, in MiB
+    max_file_size_mb: u64,
+
     #[structopt(flatten)]
     /// P2P network settings
     net: SettingsOpt,
@@ -294,6 +324,18 @@ async fn realmain(args: Args, ex: Arc<Executor<'static>>) -> Result<()> {
         return Ok(());
     }
 
+    if args.gen_channel_admin_keypair {
+        let secret = darkfi_sdk::crypto::SecretKey::random(&mut OsRng);
+        let public = darkfi_sdk::crypto::PublicKey::from_secret(secret);
+        println!("Place this in your config file, you will be the channel admin:\n");
+        println!("[channel.\"#yourchannelname\"]");
+        println!("admin_secret = \"{secret}\"");
+        println!("\nAnd give this to the channel members, for their config file:\n");
+        println!("[channel.\"#yourchannelname\"]");
+        println!("admin_public = \"{public}\"");
+        return Ok(());
+    }
+
     if args.gen_rln_identity {
         let identity = RlnIdentity::new(&mut OsRng);
         let nullifier = bs58::encode(identity.nullifier.to_repr()).into_string();
@@ -530,6 +572,35 @@ pub const DARKIRC_GENESIS_COMMITMENTS_REPR: &[[u8; 32]] = &[
     };
     log_memory("after sled open");
 
+    let dag_storage_kind: StorageKind = args.dag_storage.parse()?;
+    let dag_log_datastore = match expand_path(&args.dag_log_datastore) {
+        Ok(v) => v,
+        Err(e) => {
+            error!("Bad DAG log datastore path `{}`: {e}", args.dag_log_datastore);
+            return Err(e);
+        }
+    };
+    let dag_storage = match dag_storage_kind.open(&sled_db, &dag_log_datastore) {
+        Ok(v) => v,
+        Err(e) => {
+            error!("Failed to open {} DAG storage: {e}", args.dag_storage);
+            return Err(e);
+        }
+    };
+
+    if args.migrate_dag_storage {
+        if dag_storage_kind == StorageKind::Sled {
+            error!("Set dag_storage to the backend to migrate the DAGs to");
+            return Err(Error::Custom("DAG storage migration needs a non-sled backend".into()))
+        }
+        let stats = migrate_sled_dags(&sled_db, &dag_storage)?;
+        println!(
+            "Moved {} DAG trees ({} entries) to {} storage",
+            stats.trees, stats.entries, args.dag_storage,
+        );
+        return Ok(())
+    }
+
     let zk_key_db = if let Some(zk_key_datastore) = zk_key_datastore.as_ref() {
         let zk_key_sled_cache_capacity =
             sled_cache_capacity_bytes("zk_key_sled_cache_mb", args.zk_key_sled_cache_mb)?;
@@ -573,28 +644,18 @@ pub const DARKIRC_GENESIS_COMMITMENTS_REPR: &[[u8; 32]] = &[
         },
         max_dags,
     };
-    let event_graph = match if let Some(zk_key_db) = zk_key_db.clone() {
-        EventGraph::new_with_zk_key_db(
-            p2p.clone(),
-            sled_db.clone(),
-            zk_key_db,
-            replay_datastore.clone(),
-            replay_mode,
-            eg_config,
-            ex.clone(),
-        )
-        .await
-    } else {
-        EventGraph::new(
-            p2p.clone(),
-            sled_db.clone(),
-            replay_datastore.clone(),
-            replay_mode,
-            eg_config,
-            ex.clone(),
-        )
-        .await
-    } {
+    let event_graph = match EventGraph::new_with_storage(
+        p2p.clone(),
+        sled_db.clone(),
+        zk_key_db.clone().unwrap_or_else(|| sled_db.clone()),
+        dag_storage,
+        replay_datastore.clone(),
+        replay_mode,
+        eg_config,
+        ex.clone(),
+    )
+    .await
+    {
         Ok(v) => v,
         Err(e) => {
             error!("Event graph failed to start: {e}");
@@ -693,6 +754,13 @@ pub const DARKIRC_GENESIS_COMMITMENTS_REPR: &[[u8; 32]] = &[
 
     info!("Starting JSON-RPC server");
     let rpc_settings: RpcSettings = args.rpc.into();
+    let search = match SearchIndex::new(&sled_db) {
+        Ok(v) => v,
+        Err(e) => {
+            error!("Failed to open the search index: {e}");
+            return Err(e);
+        }
+    };
     let darkirc = Arc::new(DarkIrc::new(
         p2p.clone(),
         sled_db.clone(),
@@ -701,6 +769,7 @@ pub const DARKIRC_GENESIS_COMMITMENTS_REPR: &[[u8; 32]] = &[
         deg_sub,
         gource_sub,
         replay_datastore.clone(),
+        search,
     ));
     let darkirc_ = Arc::clone(&darkirc);
     let rpc_task = StoppableTask::new();
@@ -725,6 +794,28 @@ pub const DARKIRC_GENESIS_COMMITMENTS_REPR: &[[u8; 32]] = &[
             return Err(e);
         }
     };
+    let shares_datastore = match expand_path(&args.shares_datastore) {
+        Ok(v) => v,
+        Err(e) => {
+            error!("Bad shares datastore path `{}`: {e}", args.shares_datastore);
+            return Err(e);
+        }
+    };
+    let files = match FileShares::new(
+        &sled_db,
+        &shares_datastore,
+        args.fud_endpoint,
+        args.max_file_size_mb * 1024 * 1024,
+        ex.clone(),
+    )
+    .await
+    {
+        Ok(v) => Arc::new(v),
+        Err(e) => {
+            error!("Failed to set up shares datastore `{shares_datastore:?}`: {e}");
+            return Err(e);
+        }
+    };
     let irc_server = match IrcServer::new(
         darkirc.clone(),
         args.irc_listen,
@@ -732,6 +823,7 @@ pub const DARKIRC_GENESIS_COMMITMENTS_REPR: &[[u8; 32]] = &[
         args.irc_tls_secret,
         config_path,
         password,
+        files,
     )
     .await
     {
@@ -756,6 +848,20 @@ pub const DARKIRC_GENESIS_COMMITMENTS_REPR: &[[u8; 32]] = &[
         ex.clone(),
     );
 
+    info!("Starting search indexing task");
+    let index_task = StoppableTask::new();
+    index_task.clone().start(
+        irc_server.clone().index_events(),
+        |res| async move {
+            match res {
+                Ok(()) | Err(Error::DetachedTaskStopped) => { /* Do nothing */ }
+                Err(e) => error!("Search indexing task failed: {e}"),
+            }
+        },
+        Error::DetachedTaskStopped,
+        ex.clone(),
+    );
+
     info!("Starting P2P network");
     if let Err(e) = p2p.clone().start().await {
         error!("P2P failed to start: {e}");
@@ -849,12 +955,14 @@ pub const DARKIRC_GENESIS_COMMITMENTS_REPR: &[[u8; 32]] = &[
 
     info!("Stopping IRC server");
     irc_task.stop().await;
+    index_task.stop().await;
     drain_task.stop().await;
     prune_task.stop().await;
 
     info!("Flushing sled database...");
     let flushed_bytes = sled_db.flush_async().await?;
     info!("Flushed {flushed_bytes} bytes");
+    event_graph.flush_storage().await?;
 
     if let Some(zk_key_db) = zk_key_db {
         info!("Flushing RLN key sled database...");
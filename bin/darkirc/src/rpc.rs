@@ -31,10 +31,13 @@ use darkfi::{
 };
 use darkfi_serial::deserialize_async_partial;
 use smol::lock::MutexGuard;
-use tracing::debug;
+use tracing::{debug, error};
 
 use super::DarkIrc;
-use crate::Privmsg;
+use crate::{
+    irc::search::{retention_cutoff, SearchQuery, MAX_SEARCH_RESULTS},
+    Privmsg,
+};
 
 #[async_trait]
 impl RequestHandler<()> for DarkIrc {
@@ -46,6 +49,7 @@ impl RequestHandler<()> for DarkIrc {
             "dnet.switch" => self.dnet_switch(req.id, req.params).await,
             "dnet.subscribe_events" => self.dnet_subscribe_events(req.id, req.params).await,
             "p2p.get_info" => self.p2p_get_info(req.id, req.params).await,
+            "p2p.get_hosts" => self.p2p_get_hosts(req.id, req.params).await,
 
             "deg.switch" => self.deg_switch(req.id, req.params).await,
             "deg.subscribe_events" => self.deg_subscribe_events(req.id, req.params).await,
@@ -54,6 +58,8 @@ impl RequestHandler<()> for DarkIrc {
 
             "gource.subscribe_events" => self.gource_subscribe_events(req.id, req.params).await,
 
+            "search" => self.search(req.id, req.params).await,
+
             _ => JsonError::new(ErrorCode::MethodNotFound, None, req.id).into(),
         }
     }
@@ -210,6 +216,62 @@ impl DarkIrc {
 
         recreate_from_replayer_log(&self.replay_datastore).await
     }
+
+    // RPCAPI:
+    // Search the local message history. Takes a query, as accepted by
+    // SearchServ, and optionally the maximum number of results (at most
+    // 100, 20 by default). Returns the matching messages, newest first.
+    // Our own direct messages have the nick `*`.
+    //
+    // --> {"jsonrpc": "2.0", "method": "search", "params": ["in:#dev rln proof*", 10], "id": 42}
+    // <-- {"jsonrpc": "2.0", "result": [{"id": "...", "timestamp": 1546612406123, "channel": "#dev", "nick": "alice", "msg": "..."}], "id": 42}
+    async fn search(&self, id: i64, params: JsonValue) -> JsonResult {
+        let Some(params) = params.get::<Vec<JsonValue>>() else {
+            return JsonError::new(ErrorCode::InvalidParams, None, id).into()
+        };
+        if params.is_empty() || params.len() > 2 || !params[0].is_string() {
+            return JsonError::new(ErrorCode::InvalidParams, None, id).into()
+        }
+
+        let limit = match params.get(1) {
+            None => 20,
+            Some(limit) => match limit.get::<f64>() {
+                Some(limit) if *limit >= 1.0 && *limit <= MAX_SEARCH_RESULTS as f64 => {
+                    *limit as usize
+                }
+                _ => return JsonError::new(ErrorCode::InvalidParams, None, id).into(),
+            },
+        };
+
+        let query = SearchQuery::parse(params[0].get::<String>().unwrap());
+        if query.is_empty() {
+            return JsonError::new(ErrorCode::InvalidParams, None, id).into()
+        }
+
+        let cutoff = retention_cutoff(&self.event_graph).await;
+        let hits = match self.search.search(&query, cutoff, limit) {
+            Ok(v) => v,
+            Err(e) => {
+                error!(target: "darkirc::rpc", "Failed searching the message history: {e}");
+                return JsonError::new(ErrorCode::InternalError, None, id).into()
+            }
+        };
+
+        let hits = hits
+            .into_iter()
+            .map(|hit| {
+                json_map([
+                    ("id", JsonValue::String(hit.event_id.to_string())),
+                    ("timestamp", JsonValue::Number(hit.timestamp as f64)),
+                    ("channel", JsonValue::String(hit.channel)),
+                    ("nick", JsonValue::String(hit.nick)),
+                    ("msg", JsonValue::String(hit.msg)),
+                ])
+            })
+            .collect();
+
+        JsonResponse::new(JsonValue::Array(hits), id).into()
+    }
 }
 
 impl HandlerP2p for DarkIrc {
@@ -18,6 +18,7 @@
 
 use std::{
     collections::{HashMap, HashSet},
+    str::FromStr,
     sync::Arc,
 };
 
@@ -150,7 +151,10 @@ pub fn parse_configured_contacts(data: &toml::Value) -> Result<HashMap<String, I
         }
 
         info!("Instantiated ChaChaBox for contact \"{name}\"");
-        ret.insert(name.to_string(), IrcContact { saltbox, self_saltbox });
+        ret.insert(
+            name.to_string(),
+            IrcContact { saltbox, self_saltbox, public_key: public, secret_key: my_secret },
+        );
     }
 
     Ok(ret)
@@ -163,6 +167,7 @@ pub fn parse_configured_contacts(data: &toml::Value) -> Result<HashMap<String, I
 /// [channel."#memes"]
 /// secret = "7CkVuFgwTUpJn5Sv67Q3fyEDpa28yrSeL5Hg2GqQ4jfM"
 /// topic = "Dank Memes"
+/// admin_public = "BmZh9Hnyz7HdnXjVTzTXmGVxFH1gE1CWmDWEhKoPWuba"
 /// ```
 pub fn parse_configured_channels(data: &toml::Value) -> Result<HashMap<String, IrcChannel>> {
     let mut ret = HashMap::new();
@@ -172,7 +177,13 @@ pub fn parse_configured_channels(data: &toml::Value) -> Result<HashMap<String, I
     let Some(chans) = chans.as_table() else { return Err(ParseFailed("`channel` not a map")) };
 
     for (name, items) in chans {
-        let mut chan = IrcChannel { topic: String::new(), nicks: HashSet::new(), saltbox: None };
+        let mut chan = IrcChannel {
+            topic: String::new(),
+            nicks: HashSet::new(),
+            saltbox: None,
+            admin_public: None,
+            admin_secret: None,
+        };
 
         if let Some(topic) = items.get("topic") {
             if let Some(topic) = topic.as_str() {
@@ -203,6 +214,37 @@ pub fn parse_configured_channels(data: &toml::Value) -> Result<HashMap<String, I
             }
         }
 
+        if let Some(admin_secret) = items.get("admin_secret") {
+            let Some(admin_secret) = admin_secret.as_str() else {
+                return Err(ParseFailed("Channel admin_secret not a string"))
+            };
+
+            let Ok(admin_secret) = darkfi_sdk::crypto::SecretKey::from_str(admin_secret) else {
+                return Err(ParseFailed("Channel admin_secret not a valid secret key"))
+            };
+
+            chan.admin_public = Some(darkfi_sdk::crypto::PublicKey::from_secret(admin_secret));
+            chan.admin_secret = Some(admin_secret);
+            info!("Configured as admin of channel {name}");
+        }
+
+        if let Some(admin_public) = items.get("admin_public") {
+            let Some(admin_public) = admin_public.as_str() else {
+                return Err(ParseFailed("Channel admin_public not a string"))
+            };
+
+            let Ok(admin_public) = darkfi_sdk::crypto::PublicKey::from_str(admin_public) else {
+                return Err(ParseFailed("Channel admin_public not a valid public key"))
+            };
+
+            if chan.admin_public.is_some_and(|p| p != admin_public) {
+                return Err(ParseFailed("Channel admin_public doesn't match admin_secret"))
+            }
+
+            chan.admin_public = Some(admin_public);
+            info!("Configured admin key for channel {name}");
+        }
+
         info!("Configured channel {name}");
         ret.insert(name.to_string(), chan);
     }
@@ -39,6 +39,9 @@ class Model:
         self.nodes[name]['manual'] = {}
         self.nodes[name]['direct'] = {}
         self.nodes[name]['direct_peer_discovery'] = None
+        self.nodes[name]['node_keys'] = {}
+        self.nodes[name]['bandwidth'] = {}
+        self.nodes[name]['hosts'] = {}
         self.nodes[name]['event'] = {}
         self.nodes[name]['seed'] = {}
         self.nodes[name]['msgs'] = dd(list)
@@ -47,6 +50,9 @@ class Model:
             id = channel['id']
             channel_lookup[id] = channel
 
+        for host in info.get('hosts', []):
+            self.nodes[name]['hosts'][host['url']] = host
+
         for channel in channels:
             if channel['session'] != 'inbound':
                 continue
@@ -191,6 +197,31 @@ class Model:
                 key = (f'{name}', 'direct')
                 event[key] = f'peer discovery: {state} (attempt {attempt})'
                 logging.debug(f'{current_time}  peer_discovery: {state} (attempt {attempt})')
+            case 'peer_authenticated':
+                addr = info['addr']
+                id = info['channel_id']
+                node_key = info['node_key']
+                self.nodes[name]['node_keys'][f'{id}'] = node_key
+                logging.debug(f'{current_time}  authenticated:  addr={addr} node_key={node_key}')
+            case 'bandwidth_update':
+                bandwidth = self.nodes[name]['bandwidth']
+                for stats in info['stats']:
+                    scope = stats['scope']
+                    sent = stats['bytes_sent']
+                    recv = stats['bytes_recv']
+                    # Updates are published every second, so the
+                    # difference with the previous one is the rate
+                    prev = bandwidth.get(scope)
+                    up_rate = sent - prev['bytes_sent'] if prev else 0
+                    down_rate = recv - prev['bytes_recv'] if prev else 0
+                    bandwidth[scope] = {
+                        'bytes_sent': sent,
+                        'bytes_recv': recv,
+                        'up_rate': up_rate,
+                        'down_rate': down_rate,
+                        'upload_limit': stats['upload_limit'],
+                        'download_limit': stats['download_limit'],
+                    }
 
 
     def add_lilith(self, lilith):
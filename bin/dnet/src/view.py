@@ -211,6 +211,8 @@ class View():
         kind = focus_w[0].kind
 
         match kind:
+            case "node":
+                self.add_bandwidth(focus_w[0].name, "global")
             case "outbound" | "direct":
                 key = (focus_w[0].name, kind)
                 info = self.model.nodes.get(focus_w[0].name)
@@ -219,12 +221,17 @@ class View():
                     self.pile.contents.append((
                         urwid.Text(f" {ev}"),
                         self.pile.options()))
+                self.add_bandwidth(focus_w[0].name, kind)
+            case "inbound" | "manual":
+                self.add_bandwidth(focus_w[0].name, kind)
             case "outbound-slot" | "inbound-slot" | \
                     "manual-slot" | "seed-slot" | "direct-slot":
                 addr = focus_w[0].addr
                 name = focus_w[0].name
                 info = self.model.nodes.get(name)
 
+                self.add_host_record(name, addr)
+
                 if info and addr in info['msgs']:
                     msg = info['msgs'].get(addr)
                     for m in msg:
@@ -284,6 +291,51 @@ class View():
                             f"  {host}"),
                             self.pile.options()))
 
+    def add_bandwidth(self, name, scope):
+        info = self.model.nodes.get(name)
+        if not info or 'bandwidth' not in info:
+            return
+        stats = info['bandwidth'].get(scope)
+        if stats is None:
+            return
+
+        def limit(value):
+            return f"{value} B/s" if value else "unlimited"
+
+        lines = [
+            f" bandwidth ({scope}):",
+            f"  up:   {stats['up_rate']} B/s (limit {limit(stats['upload_limit'])}), "
+            f"total {stats['bytes_sent']} B",
+            f"  down: {stats['down_rate']} B/s (limit {limit(stats['download_limit'])}), "
+            f"total {stats['bytes_recv']} B",
+        ]
+        for line in lines:
+            self.pile.contents.append((urwid.Text(line), self.pile.options()))
+
+    def add_host_record(self, name, addr):
+        info = self.model.nodes.get(name)
+        if not info or 'hosts' not in info:
+            return
+        host = info['hosts'].get(addr)
+        if host is None:
+            return
+
+        latency = host['avg_latency_ms']
+        latency = f"{latency} ms" if latency is not None else "unknown"
+        app = f"{host['app_name']} {host['app_version']}" if host['app_name'] else "unknown"
+        transport = host['transport'] or "unknown"
+
+        lines = [
+            f" host ({host['color']}): score {host['score']:.2f}, latency {latency}",
+            f"  app: {app}, transport: {transport}",
+            f"  handshakes: {host['successes']} ok, {host['failures']} failed",
+            f"  uptime: {host['uptime']} s over {host['disconnects']} closed connections",
+        ]
+        if host['last_failure']:
+            lines.append(f"  last failure: {host['last_failure']}")
+        for line in lines:
+            self.pile.contents.append((urwid.Text(line), self.pile.options()))
+
     def update_node_state(self, info):
         if info:
             logging.debug(f"update_node_state(): Returning {NodeState.ON}")
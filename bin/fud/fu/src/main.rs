@@ -70,7 +70,7 @@ struct Args {
 enum Subcmd {
     /// Retrieve provided resource from the fud network
     Get {
-        /// Resource hash
+        /// Resource hash, or `<hash>#<key>` link of an encrypted resource
         hash: String,
         /// Download path (relative or absolute)
         path: Option<String>,
@@ -83,6 +83,15 @@ enum Subcmd {
     Put {
         /// File path or directory path
         path: String,
+        /// Encrypt the file with a random key, only the holders of the
+        /// printed link can decrypt it
+        #[arg(short, long)]
+        encrypt: bool,
+        /// Split the resource using content-defined chunking, so that its
+        /// next versions share most of their chunks with this one. This
+        /// gives it another hash than the default fixed-size chunks.
+        #[arg(short, long, conflicts_with = "encrypt")]
+        cdc: bool,
     },
 
     /// List resources
@@ -114,6 +123,37 @@ enum Subcmd {
         /// Resource hash
         hash: String,
     },
+
+    /// Point one of your pointers to a resource
+    Publish {
+        /// Pointer name
+        name: String,
+        /// Resource hash
+        hash: String,
+    },
+
+    /// Find the resource a pointer points to
+    Resolve {
+        /// Pointer (<publisher>/<name>)
+        pointer: String,
+    },
+
+    /// Automatically download the latest resource of a pointer
+    Follow {
+        /// Pointer (<publisher>/<name>)
+        pointer: String,
+        /// Download directory (relative or absolute)
+        path: Option<String>,
+    },
+
+    /// Stop following a pointer
+    Unfollow {
+        /// Pointer (<publisher>/<name>)
+        pointer: String,
+    },
+
+    /// List the pointers you follow
+    Followed {},
 }
 
 struct Fu {
@@ -132,7 +172,9 @@ impl Fu {
         let publisher = Publisher::new();
         let subscription = Arc::new(publisher.clone().subscribe().await);
         let subscriber_task = StoppableTask::new();
-        let hash_ = hash.clone();
+        // Only keep the hash of `<hash>#<key>` links
+        let hash_ = hash.split('#').next().unwrap_or_default().to_string();
+        let encrypted = hash.contains('#');
         let publisher_ = publisher.clone();
         let rpc_client_ = self.rpc_client.clone();
         subscriber_task.clone().start(
@@ -236,7 +278,7 @@ impl Fu {
         let req = JsonRequest::new(
             "get",
             JsonValue::Array(vec![
-                JsonValue::String(hash_.clone()),
+                JsonValue::String(hash),
                 JsonValue::String(path.unwrap_or_default()),
                 match files {
                     Some(files) => {
@@ -248,7 +290,8 @@ impl Fu {
         );
         // Create a RPC client to send the `get` request
         let rpc_client_getter = RpcClient::new(self.endpoint.clone(), ex.clone()).await?;
-        let _ = rpc_client_getter.request(req).await?;
+        let rep = rpc_client_getter.request(req).await?;
+        let dest_path = rep.get::<String>().unwrap().clone();
 
         loop {
             match subscription.receive().await {
@@ -280,7 +323,13 @@ impl Fu {
                                 .unwrap()
                                 .get::<HashMap<String, JsonValue>>()
                                 .unwrap();
-                            let path = resource_json.get("path").unwrap().get::<String>().unwrap();
+                            // Encrypted resources are decrypted to the path returned by `get`
+                            let path = match encrypted {
+                                true => &dest_path,
+                                false => {
+                                    resource_json.get("path").unwrap().get::<String>().unwrap()
+                                }
+                            };
                             print_progress(info);
                             println!("\nDownload completed:\n{path}");
                             return Ok(());
@@ -324,7 +373,7 @@ impl Fu {
         }
     }
 
-    async fn put(&self, path: String, ex: ExecutorPtr) -> Result<()> {
+    async fn put(&self, path: String, encrypt: bool, cdc: bool, ex: ExecutorPtr) -> Result<()> {
         let publisher = Publisher::new();
         let subscription = Arc::new(publisher.clone().subscribe().await);
         let subscriber_task = StoppableTask::new();
@@ -355,9 +404,20 @@ impl Fu {
         );
 
         let rpc_client_putter = RpcClient::new(self.endpoint.clone(), ex.clone()).await?;
-        let req = JsonRequest::new("put", JsonValue::Array(vec![JsonValue::String(path)]));
+        let method = if encrypt { "put_encrypted" } else { "put" };
+        let params = match encrypt {
+            true => vec![JsonValue::String(path)],
+            false => vec![JsonValue::String(path), JsonValue::Boolean(cdc)],
+        };
+        let req = JsonRequest::new(method, JsonValue::Array(params));
         let rep = rpc_client_putter.request(req).await?;
-        let path_str = rep.get::<String>().unwrap().clone();
+        let (path_str, key) = match encrypt {
+            true => (
+                rep["path"].get::<String>().unwrap().clone(),
+                Some(rep["key"].get::<String>().unwrap().clone()),
+            ),
+            false => (rep.get::<String>().unwrap().clone(), None),
+        };
 
         loop {
             match subscription.receive().await {
@@ -376,7 +436,10 @@ impl Fu {
                     match params.get("event").unwrap().get::<String>().unwrap().as_str() {
                         "insert_completed" => {
                             let id = info.get("hash").unwrap().get::<String>().unwrap().to_string();
-                            println!("{id}");
+                            match &key {
+                                Some(key) => println!("{id}#{key}"),
+                                None => println!("{id}"),
+                            }
                             break Ok(())
                         }
                         "insert_error" => {
@@ -409,6 +472,18 @@ impl Fu {
         let resources: Vec<Resource> = resources_json.into_iter().map(|v| v.into()).collect();
 
         for resource in resources.iter() {
+            // Show the chunks we seed to other nodes next to the status
+            let status = match (&resource.status, resource.seeding_chunks_count) {
+                (_, 0) => resource.status.as_str().to_string(),
+                (ResourceStatus::Seeding, seeding_chunks) => {
+                    format!("seeding {seeding_chunks}/{} chunks", resource.total_chunks_count)
+                }
+                (status, seeding_chunks) => format!(
+                    "{}, seeding {seeding_chunks}/{} chunks",
+                    status.as_str(),
+                    resource.total_chunks_count
+                ),
+            };
             let mut tree: Vec<TreeNode<String>> = vec![
                 TreeNode::kv("ID".to_string(), hash_to_string(&resource.hash)),
                 TreeNode::kvc(
@@ -416,11 +491,7 @@ impl Fu {
                     resource.rtype.as_str().to_string(),
                     type_to_colorspec(&resource.rtype),
                 ),
-                TreeNode::kvc(
-                    "Status".to_string(),
-                    resource.status.as_str().to_string(),
-                    status_to_colorspec(&resource.status),
-                ),
+                TreeNode::kvc("Status".to_string(), status, status_to_colorspec(&resource.status)),
                 TreeNode::kv("Chunks".to_string(), {
                     if let ResourceType::Directory = resource.rtype {
                         format!(
@@ -891,6 +962,70 @@ impl Fu {
         }
         Ok(())
     }
+
+    async fn publish(&self, name: String, hash: String) -> Result<()> {
+        let req = JsonRequest::new(
+            "publish",
+            JsonValue::Array(vec![JsonValue::String(name), JsonValue::String(hash)]),
+        );
+        let rep = self.rpc_client.request(req).await?;
+        let address: String = rep["address"].clone().try_into().unwrap();
+        let sequence: f64 = rep["sequence"].clone().try_into().unwrap();
+        println!("Published {address} (sequence {sequence})");
+        Ok(())
+    }
+
+    async fn resolve(&self, pointer: String) -> Result<()> {
+        let req = JsonRequest::new("resolve", JsonValue::Array(vec![JsonValue::String(pointer)]));
+        let rep = self.rpc_client.request(req).await?;
+        let resource: String = rep["resource"].clone().try_into().unwrap();
+        let sequence: f64 = rep["sequence"].clone().try_into().unwrap();
+        println!("{resource} (sequence {sequence})");
+        Ok(())
+    }
+
+    async fn follow(&self, pointer: String, path: Option<String>) -> Result<()> {
+        let path = match path {
+            Some(path) => JsonValue::String(path),
+            None => JsonValue::Null,
+        };
+        let req =
+            JsonRequest::new("follow", JsonValue::Array(vec![JsonValue::String(pointer), path]));
+        let rep = self.rpc_client.request(req).await?;
+        let address: String = rep["address"].clone().try_into().unwrap();
+        let path: String = rep["path"].clone().try_into().unwrap();
+        println!("Following {address}, downloading to {path}");
+        Ok(())
+    }
+
+    async fn unfollow(&self, pointer: String) -> Result<()> {
+        let req = JsonRequest::new("unfollow", JsonValue::Array(vec![JsonValue::String(pointer)]));
+        self.rpc_client.request(req).await?;
+        Ok(())
+    }
+
+    async fn list_followed(&self) -> Result<()> {
+        let req = JsonRequest::new("list_followed", JsonValue::Array(vec![]));
+        let rep = self.rpc_client.request(req).await?;
+        let followed: Vec<JsonValue> = rep.try_into().unwrap();
+
+        if followed.is_empty() {
+            println!("You don't follow any pointer");
+            return Ok(())
+        }
+
+        for pointer in followed {
+            let address: String = pointer["address"].clone().try_into().unwrap();
+            let path: String = pointer["path"].clone().try_into().unwrap();
+            let resource = match &pointer["resource"] {
+                JsonValue::String(resource) => resource.clone(),
+                _ => "-".to_string(),
+            };
+            println!("{address} -> {resource} ({path})");
+        }
+
+        Ok(())
+    }
 }
 
 fn main() -> Result<()> {
@@ -906,7 +1041,7 @@ fn main() -> Result<()> {
 
             match args.command {
                 Subcmd::Get { hash, path, files } => fu.get(hash, path, files, ex.clone()).await,
-                Subcmd::Put { path } => fu.put(path, ex.clone()).await,
+                Subcmd::Put { path, encrypt, cdc } => fu.put(path, encrypt, cdc, ex.clone()).await,
                 Subcmd::Ls {} => fu.list_resources().await,
                 Subcmd::Watch {} => fu.watch(ex.clone()).await,
                 Subcmd::Rm { hash } => fu.remove(hash).await,
@@ -914,6 +1049,11 @@ fn main() -> Result<()> {
                 Subcmd::Seeders {} => fu.seeders().await,
                 Subcmd::Verify { files } => fu.verify(files).await,
                 Subcmd::Lookup { hash } => fu.lookup(hash, ex.clone()).await,
+                Subcmd::Publish { name, hash } => fu.publish(name, hash).await,
+                Subcmd::Resolve { pointer } => fu.resolve(pointer).await,
+                Subcmd::Follow { pointer, path } => fu.follow(pointer, path).await,
+                Subcmd::Unfollow { pointer } => fu.unfollow(pointer).await,
+                Subcmd::Followed {} => fu.list_followed().await,
             }?;
 
             Ok(())
@@ -0,0 +1,152 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2026 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use darkfi::geode::ChunkedStorage;
+use darkfi_serial::{SerialDecodable, SerialEncodable};
+
+/// Availability of the chunks of a resource, one bit per chunk (in the
+/// order of the resource's chunk hashes).
+/// It is used to tell other nodes which chunks we have.
+#[derive(Clone, Debug, Default, PartialEq, Eq, SerialEncodable, SerialDecodable)]
+pub struct ChunkBitfield {
+    /// Number of chunks in the resource
+    len: u64,
+    /// Bits of the chunks, least significant bit first
+    bits: Vec<u8>,
+}
+
+impl ChunkBitfield {
+    /// Create a bitfield of `len` chunks, none of them available.
+    pub fn new(len: usize) -> Self {
+        Self { len: len as u64, bits: vec![0; len.div_ceil(8)] }
+    }
+
+    /// Create a bitfield of `len` chunks, all of them available.
+    pub fn full(len: usize) -> Self {
+        let mut bitfield = Self::new(len);
+        for index in 0..len {
+            bitfield.set(index, true);
+        }
+        bitfield
+    }
+
+    /// Return the number of chunks.
+    pub fn len(&self) -> usize {
+        self.len as usize
+    }
+
+    /// Return `true` if the bitfield contains no chunk.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Return `true` if the bitfield is well-formed. Bitfields received
+    /// from the network must be checked with this.
+    pub fn is_valid(&self) -> bool {
+        if self.bits.len() as u64 != self.len.div_ceil(8) {
+            return false
+        }
+        // Padding bits of the last byte must be unset
+        match (self.len % 8, self.bits.last()) {
+            (0, _) | (_, None) => true,
+            (used, Some(last)) => last >> used == 0,
+        }
+    }
+
+    /// Return `true` if the chunk at `index` is available.
+    pub fn get(&self, index: usize) -> bool {
+        if index >= self.len() {
+            return false
+        }
+        self.bits[index / 8] & (1 << (index % 8)) != 0
+    }
+
+    /// Set the availability of the chunk at `index`.
+    pub fn set(&mut self, index: usize, available: bool) {
+        if index >= self.len() {
+            return
+        }
+        match available {
+            true => self.bits[index / 8] |= 1 << (index % 8),
+            false => self.bits[index / 8] &= !(1 << (index % 8)),
+        }
+    }
+
+    /// Return the number of available chunks.
+    pub fn count(&self) -> usize {
+        self.bits.iter().map(|byte| byte.count_ones() as usize).sum()
+    }
+
+    /// Return `true` if all the chunks are available.
+    pub fn is_full(&self) -> bool {
+        self.count() == self.len()
+    }
+}
+
+impl From<&ChunkedStorage> for ChunkBitfield {
+    fn from(chunked: &ChunkedStorage) -> Self {
+        let mut bitfield = Self::new(chunked.len());
+        for (index, chunk) in chunked.iter().enumerate() {
+            bitfield.set(index, chunk.available);
+        }
+        bitfield
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bits_are_set_and_counted() {
+        let mut bitfield = ChunkBitfield::new(10);
+        assert!(bitfield.is_valid() && bitfield.count() == 0);
+        bitfield.set(0, true);
+        bitfield.set(9, true);
+        bitfield.set(10, true); // Out of range, ignored
+        assert!(bitfield.get(0) && bitfield.get(9) && !bitfield.get(5) && !bitfield.get(10));
+        assert_eq!(bitfield.count(), 2);
+        bitfield.set(0, false);
+        assert_eq!(bitfield.count(), 1);
+        assert!(bitfield.is_valid());
+
+        assert!(ChunkBitfield::full(10).is_full());
+        assert_eq!(ChunkBitfield::full(10).count(), 10);
+        assert!(ChunkBitfield::default().is_empty() && ChunkBitfield::default().is_valid());
+    }
+
+    #[test]
+    fn malformed_bitfields_are_invalid() {
+        // Too few bytes for the number of chunks
+        assert!(!ChunkBitfield { len: 9, bits: vec![0] }.is_valid());
+        // Padding bit set past the last chunk
+        assert!(!ChunkBitfield { len: 9, bits: vec![0, 0b10] }.is_valid());
+        assert!(ChunkBitfield { len: 9, bits: vec![0xff, 0b1] }.is_valid());
+    }
+
+    #[test]
+    fn bitfield_of_chunked_storage() {
+        let hashes: Vec<_> = (0..3u8).map(|i| blake3::hash(&[i])).collect();
+        let mut chunked = ChunkedStorage::new(&hashes, &[], &[], false);
+        chunked.get_chunk_mut(1).available = true;
+
+        let bitfield = ChunkBitfield::from(&chunked);
+        assert_eq!(bitfield.len(), 3);
+        assert!(!bitfield.get(0) && bitfield.get(1) && !bitfield.get(2));
+    }
+}
@@ -27,7 +27,7 @@ use url::Url;
 use darkfi::{
     dht::{
         event::DhtEvent, impl_dht_node_defaults, Dht, DhtHandler, DhtLookupReply, DhtNode,
-        HostCacheItem,
+        DhtRecord, HostCacheItem,
     },
     geode::hash_to_string,
     net::{
@@ -43,10 +43,12 @@ use darkfi_sdk::crypto::schnorr::{SchnorrPublic, Signature};
 use darkfi_serial::{serialize_async, SerialDecodable, SerialEncodable};
 
 use crate::{
+    bitfield::ChunkBitfield,
+    pointer::FudPointer,
     pow::VerifiableNodeData,
     proto::{
-        FudAnnounce, FudNodesReply, FudNodesRequest, FudPingReply, FudPingRequest, FudSeedersReply,
-        FudSeedersRequest,
+        FudAnnounce, FudNodesReply, FudNodesRequest, FudPingReply, FudPingRequest, FudRecordStore,
+        FudRecordsReply, FudRecordsRequest, FudSeedersReply, FudSeedersRequest,
     },
     util::receive_resource_msg,
     Fud,
@@ -89,7 +91,9 @@ pub struct FudSeeder {
     pub key: blake3::Hash,
     /// Seeder's node data
     pub node: FudNode,
-    /// Seeder's signature of (key || node)
+    /// Chunks of the resource the seeder has, empty if it has all of them
+    pub chunks: ChunkBitfield,
+    /// Seeder's signature of (key || node || chunks)
     pub sig: Signature,
     /// When this [`FudSeeder`] was added to our hash table.
     /// This is not sent to other nodes.
@@ -113,9 +117,19 @@ impl From<FudSeeder> for JsonValue {
 }
 
 impl FudSeeder {
+    /// Returns `true` if the seeder has all the chunks of the resource
+    pub fn is_complete(&self) -> bool {
+        self.chunks.is_empty() || self.chunks.is_full()
+    }
+
     pub async fn verify_signature(&self) -> bool {
         self.node.data.public_key.verify(
-            &[self.key.as_bytes().to_vec(), serialize_async(&self.node).await].concat(),
+            &[
+                self.key.as_bytes().to_vec(),
+                serialize_async(&self.node).await,
+                serialize_async(&self.chunks).await,
+            ]
+            .concat(),
             &self.sig,
         )
     }
@@ -152,6 +166,16 @@ impl DhtHandler for Fud {
         })
     }
 
+    async fn validate_node(&self, node: &FudNode) -> Result<()> {
+        self.pow.write().await.verify_node(&node.data).await
+    }
+
+    async fn validate_record(&self, record: &DhtRecord) -> Result<()> {
+        // The only records are pointers, which must be stored under the
+        // key of their publisher's pointer
+        FudPointer::try_from(record).map(|_| ())
+    }
+
     async fn ping(&self, channel: ChannelPtr) -> Result<FudNode> {
         debug!(target: "fud::DhtHandler::ping()", "Sending ping to {}", channel.display_address());
 
@@ -188,7 +212,7 @@ impl DhtHandler for Fud {
         }
 
         // Verify PoW
-        if let Err(e) = self.pow.write().await.verify_node(&node.data).await {
+        if let Err(e) = self.validate_node(node).await {
             warn!(target: "fud::DhtHandler::ping()", "Received an invalid PoW while pinging {}: {e}", channel.display_address());
             self.dht
                 .event_publisher
@@ -300,6 +324,31 @@ impl DhtHandler for Fud {
         Ok(DhtLookupReply::NodesAndValue(rep.nodes.clone(), rep.seeders.clone()))
     }
 
+    async fn store_record(&self, channel: ChannelPtr, record: &DhtRecord) -> Result<()> {
+        debug!(target: "fud::DhtHandler::store_record()", "Sending record for {} to {}", hash_to_string(&record.key), channel.display_address());
+
+        channel.send(&FudRecordStore { record: record.clone() }).await
+    }
+
+    async fn find_records(
+        &self,
+        channel: ChannelPtr,
+        key: &blake3::Hash,
+    ) -> Result<Vec<DhtRecord>> {
+        debug!(target: "fud::DhtHandler::find_records()", "Fetching records for {} from {}", hash_to_string(key), channel.display_address());
+
+        let msg_subscriber = channel.subscribe_msg::<FudRecordsReply>().await.unwrap();
+
+        let request = FudRecordsRequest { key: *key };
+        channel.send(&request).await?;
+
+        let reply = receive_resource_msg(&msg_subscriber, *key, self.dht().settings.timeout).await;
+
+        msg_subscriber.unsubscribe().await;
+
+        Ok(reply?.records.clone())
+    }
+
     async fn add_value(&self, key: &blake3::Hash, value: &Vec<FudSeeder>) {
         let mut seeders = value.clone();
 
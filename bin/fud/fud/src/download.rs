@@ -17,35 +17,36 @@
  */
 
 use std::{
-    collections::HashSet,
+    collections::{HashMap, HashSet},
     path::{Path, PathBuf},
     time::Instant,
 };
 
-use futures::{future::FutureExt, pin_mut, select};
-use rand::{
-    prelude::{IteratorRandom, SliceRandom},
-    rngs::OsRng,
+use futures::{
+    future::{pending, FutureExt},
+    pin_mut, select,
+    stream::{FuturesUnordered, StreamExt},
 };
+use rand::{prelude::SliceRandom, rngs::OsRng};
+use smol::lock::Mutex;
 use tracing::{error, info, warn};
 
-use darkfi::{
-    dht::{event::DhtEvent, DhtHandler, DhtNode},
-    geode::{hash_to_string, ChunkedStorage},
-    net::ChannelPtr,
-    system::Subscription,
-    Error, Result,
-};
-use darkfi_serial::serialize_async;
-
 use crate::{
+    bitfield::ChunkBitfield,
     event::{self, notify_event, FudEvent},
     proto::{
-        FudChunkNotFound, FudChunkReply, FudChunkRequest, FudDirectoryReply, FudFileReply,
-        FudMetadataNotFound, FudMetadataRequest,
+        FudChunkMapReply, FudChunkMapRequest, FudChunkNotFound, FudChunkReply, FudChunkRequest,
+        FudDirectoryReply, FudFileReply, FudMetadataNotFound, FudMetadataRequest,
     },
     util::{create_all_files, receive_resource_msg},
-    Fud, FudSeeder, ResourceStatus, ResourceType, Scrap,
+    Fud, FudSeeder, ResourceStatus, ResourceType,
+};
+use darkfi::{
+    dht::{event::DhtEvent, DhtHandler, DhtNode},
+    geode::{hash_to_string, ChunkedStorage},
+    net::ChannelPtr,
+    system::{msleep, Subscription},
+    Error, Result,
 };
 
 type FudDhtEvent = DhtEvent<<Fud as DhtHandler>::Node, <Fud as DhtHandler>::Value>;
@@ -110,20 +111,144 @@ macro_rules! seeders_loop {
     };
 }
 
+/// Maximum number of seeders we download chunks from at the same time
+const MAX_CONCURRENT_SEEDERS: usize = 8;
+
+/// Number of chunk requests we keep in flight for each seeder
+const PIPELINE_DEPTH: usize = 4;
+
+/// Milliseconds a seeder waits before selecting chunks again, when all the
+/// chunks it has are already requested from other seeders
+const IDLE_SEEDER_MSECS: u64 = 500;
+
 enum ChunkFetchControl {
     NextChunk,
     NextSeeder,
     Abort,
 }
 
+/// State of the chunks of a resource shared by the seeders we download from
+struct Swarm {
+    /// Index of each chunk hash in the resource
+    chunk_indexes: HashMap<blake3::Hash, usize>,
+    /// Chunks we still need to download
+    missing: HashSet<blake3::Hash>,
+    /// Chunks available on each seeder we are downloading from
+    seeders: HashMap<blake3::Hash, ChunkBitfield>,
+    /// Number of in-flight requests of each chunk
+    requested: HashMap<blake3::Hash, usize>,
+    /// Set once all missing chunks were requested, from then on chunks can
+    /// be requested from several seeders at the same time
+    endgame: bool,
+    /// Set if the download must stop (the resource was removed)
+    aborted: bool,
+}
+
+impl Swarm {
+    fn new(chunked: &ChunkedStorage, missing: &HashSet<blake3::Hash>) -> Self {
+        let mut chunk_indexes = HashMap::new();
+        for (index, chunk) in chunked.iter().enumerate() {
+            chunk_indexes.entry(chunk.hash).or_insert(index);
+        }
+
+        Self {
+            chunk_indexes,
+            missing: missing.clone(),
+            seeders: HashMap::new(),
+            requested: HashMap::new(),
+            endgame: false,
+            aborted: false,
+        }
+    }
+
+    /// Returns `true` if there is nothing left to download
+    fn is_done(&self) -> bool {
+        self.aborted || self.missing.is_empty()
+    }
+
+    /// Returns `true` if `seeder` has any chunk we are missing
+    fn has_missing_chunks(&self, seeder: &blake3::Hash) -> bool {
+        let Some(bitfield) = self.seeders.get(seeder) else { return false };
+        self.missing.iter().any(|chunk| bitfield.get(self.chunk_indexes[chunk]))
+    }
+
+    /// Number of seeders that have the chunk `chunk_hash`
+    fn availability(&self, chunk_hash: &blake3::Hash) -> usize {
+        let index = self.chunk_indexes[chunk_hash];
+        self.seeders.values().filter(|bitfield| bitfield.get(index)).count()
+    }
+
+    /// Select the next chunk to request from `seeder`, excluding the chunks
+    /// in `in_flight` (already requested from this seeder).
+    /// The rarest chunk among the seeders is selected first. Chunks that are
+    /// requested from other seeders are only selected in endgame mode.
+    fn select_chunk(
+        &mut self,
+        seeder: &blake3::Hash,
+        in_flight: &HashMap<blake3::Hash, Instant>,
+    ) -> Option<blake3::Hash> {
+        if !self.endgame && self.missing.iter().all(|chunk| self.requested.contains_key(chunk)) {
+            info!(target: "fud::download::select_chunk()", "All missing chunks are requested, entering endgame mode");
+            self.endgame = true;
+        }
+
+        let bitfield = self.seeders.get(seeder)?;
+        let mut candidates: Vec<_> = self
+            .missing
+            .iter()
+            .filter(|chunk| !in_flight.contains_key(*chunk))
+            .filter(|chunk| self.endgame || !self.requested.contains_key(*chunk))
+            .filter(|chunk| bitfield.get(self.chunk_indexes[*chunk]))
+            .cloned()
+            .collect();
+
+        // Shuffle first so that ties are broken randomly
+        candidates.shuffle(&mut OsRng);
+        let chunk_hash = candidates.into_iter().min_by_key(|chunk| {
+            (self.availability(chunk), self.requested.get(chunk).cloned().unwrap_or(0))
+        })?;
+
+        *self.requested.entry(chunk_hash).or_insert(0) += 1;
+        Some(chunk_hash)
+    }
+
+    /// Cancel one request of `chunk_hash`
+    fn release(&mut self, chunk_hash: &blake3::Hash) {
+        if let Some(count) = self.requested.get_mut(chunk_hash) {
+            *count -= 1;
+            if *count == 0 {
+                self.requested.remove(chunk_hash);
+            }
+        }
+    }
+
+    /// Mark `chunk_hash` as unavailable on `seeder`
+    fn not_found(&mut self, seeder: &blake3::Hash, chunk_hash: &blake3::Hash) {
+        self.release(chunk_hash);
+        if let Some(bitfield) = self.seeders.get_mut(seeder) {
+            bitfield.set(self.chunk_indexes[chunk_hash], false);
+        }
+    }
+
+    /// Mark `chunk_hash` as downloaded
+    fn received(&mut self, chunk_hash: &blake3::Hash) {
+        self.requested.remove(chunk_hash);
+        self.missing.remove(chunk_hash);
+    }
+}
+
 struct ChunkFetchContext<'a> {
     fud: &'a Fud,
     hash: &'a blake3::Hash,
-    chunked: &'a mut ChunkedStorage,
-    chunks: &'a mut HashSet<blake3::Hash>,
+    chunks_count: usize,
+    chunked: Mutex<&'a mut ChunkedStorage>,
+    swarm: Mutex<Swarm>,
 }
 
 /// Fetch `chunks` for `chunked` (file or directory) from seeders in `seeders_sub`.
+/// Chunks are downloaded from up to [`MAX_CONCURRENT_SEEDERS`] seeders at
+/// the same time, and `chunks` only contains the chunks we could not get
+/// when this returns.
 pub async fn fetch_chunks(
     fud: &Fud,
     hash: &blake3::Hash,
@@ -132,117 +257,273 @@ pub async fn fetch_chunks(
     favored_seeder: Option<FudSeeder>,
     chunks: &mut HashSet<blake3::Hash>,
 ) -> Result<()> {
-    let mut ctx = ChunkFetchContext { fud, hash, chunked, chunks };
+    let ctx = ChunkFetchContext {
+        fud,
+        hash,
+        chunks_count: chunked.len(),
+        swarm: Mutex::new(Swarm::new(chunked, chunks)),
+        chunked: Mutex::new(chunked),
+    };
 
-    seeders_loop!(hash, fud, dht_sub, favored_seeder, async |seeder: FudSeeder| -> Result<()> {
-        let (channel, _) = match fud.dht.get_channel(&seeder.node).await {
-            Ok(channel) => channel,
-            Err(e) => {
-                warn!(target: "fud::download::fetch_chunks()", "Could not get a channel for node {}: {e}", hash_to_string(&seeder.node.id()));
-                return Err(e)
+    let mut queried_seeders: HashSet<blake3::Hash> = HashSet::new();
+    let mut pending_seeders: Vec<FudSeeder> = vec![];
+    let mut workers = FuturesUnordered::new();
+    let mut lookup_done = false;
+
+    // We announce ourselves as a seeder while downloading, skip our own node
+    if let Ok(self_node) = fud.node().await {
+        queried_seeders.insert(self_node.id());
+    }
+
+    // Try favored seeder
+    if let Some(seeder) = favored_seeder {
+        queried_seeders.insert(seeder.node.id());
+        pending_seeders.push(seeder);
+    }
+
+    loop {
+        let is_done = ctx.swarm.lock().await.is_done();
+
+        // Start downloading from pending seeders
+        while !is_done && workers.len() < MAX_CONCURRENT_SEEDERS {
+            let Some(seeder) = pending_seeders.pop() else { break };
+            workers.push(fetch_from_seeder(&ctx, seeder));
+        }
+
+        // Stop once all seeders are done, and we either have all the chunks
+        // or there are no more seeders to try
+        if workers.is_empty() && (is_done || (lookup_done && pending_seeders.is_empty())) {
+            break
+        }
+
+        let seeders_recv = async {
+            match is_done || lookup_done {
+                true => pending().await,
+                false => dht_sub.receive().await,
+            }
+        }
+        .fuse();
+        let worker_done = async {
+            match workers.is_empty() {
+                true => pending().await,
+                false => workers.next().await,
             }
-        };
-        let mut chunks_to_query = ctx.chunks.clone();
-        info!(target: "fud::download::fetch_chunks()", "Requesting chunks from seeder {}", hash_to_string(&seeder.node.id()));
-
-        loop {
-            // Loop over chunks
-            match fetch_chunk(&mut ctx, &channel, &seeder, &mut chunks_to_query).await {
-                ChunkFetchControl::NextChunk => continue,
-                ChunkFetchControl::NextSeeder => break,
-                ChunkFetchControl::Abort => {
-                    fud.dht.cleanup_channel(channel).await;
-                    return Ok(())
-                }
-            };
         }
+        .fuse();
 
-        fud.dht.cleanup_channel(channel).await;
+        pin_mut!(seeders_recv, worker_done);
 
-        // Stop when there are no missing chunks
-        if ctx.chunks.is_empty() {
-            return Ok(())
+        // Wait for new seeders from the DHT, or for a seeder to be done
+        let event = select! {
+            event = seeders_recv => event,
+            _ = worker_done => continue,
+        };
+
+        if event.key() != Some(hash) {
+            continue // Ignore this event if it's not about the right key
+        }
+        if let DhtEvent::ValueLookupCompleted { .. } = event {
+            lookup_done = true;
+            continue
+        }
+        if !matches!(event, DhtEvent::ValueFound { .. }) {
+            continue // Ignore this event as it's not a ValueFound
         }
+        let mut seeders: Vec<_> = event.into_value().unwrap().into_iter().collect();
+        seeders.shuffle(&mut OsRng);
+        for seeder in seeders {
+            // Only use a seeder once
+            if queried_seeders.insert(seeder.node.id()) {
+                pending_seeders.push(seeder);
+            }
+        }
+    }
 
-        Err(().into())
-    });
+    drop(workers);
+    *chunks = ctx.swarm.into_inner().missing;
 
     Ok(())
 }
 
-/// Fetch a single chunk and return what should be done next
-async fn fetch_chunk(
-    ctx: &mut ChunkFetchContext<'_>,
+/// Request the bitfield of the chunks `seeder` has using `channel`.
+/// If the seeder does not reply with a valid bitfield, we use the chunks it
+/// announced in the DHT.
+async fn fetch_chunk_map(
+    ctx: &ChunkFetchContext<'_>,
     channel: &ChannelPtr,
     seeder: &FudSeeder,
-    chunks_to_query: &mut HashSet<blake3::Hash>,
-) -> ChunkFetchControl {
-    // Select a chunk to request
-    let mut chunk = None;
-    if let Some(random_chunk) = chunks_to_query.iter().choose(&mut OsRng) {
-        chunk = Some(*random_chunk);
+) -> ChunkBitfield {
+    let msg_subscriber = channel.subscribe_msg::<FudChunkMapReply>().await.unwrap();
+
+    let mut bitfield = None;
+    let send_res = channel.send(&FudChunkMapRequest { resource: *ctx.hash }).await;
+    match send_res {
+        Ok(()) => {
+            match receive_resource_msg(&msg_subscriber, *ctx.hash, ctx.fud.chunk_timeout).await {
+                Ok(reply) => bitfield = Some(reply.bitfield.clone()),
+                Err(e) => {
+                    warn!(target: "fud::download::fetch_chunk_map()", "Error waiting for chunk map reply: {e}");
+                }
+            }
+        }
+        Err(e) => {
+            warn!(target: "fud::download::fetch_chunk_map()", "Error while sending FudChunkMapRequest: {e}");
+        }
     }
-
-    if chunk.is_none() {
-        // No more chunks to request from this seeder
-        return ChunkFetchControl::NextSeeder;
+    msg_subscriber.unsubscribe().await;
+
+    let is_usable =
+        |bitfield: &ChunkBitfield| bitfield.is_valid() && bitfield.len() == ctx.chunks_count;
+    match bitfield {
+        Some(bitfield) if is_usable(&bitfield) => bitfield,
+        _ if !seeder.is_complete() && is_usable(&seeder.chunks) => seeder.chunks.clone(),
+        _ => ChunkBitfield::full(ctx.chunks_count),
     }
+}
 
-    let chunk_hash = chunk.unwrap();
-    chunks_to_query.remove(&chunk_hash);
+/// Fetch chunks from a single seeder until it has no chunk we need, it
+/// fails to reply, or the download is done.
+/// Up to [`PIPELINE_DEPTH`] chunk requests are sent before waiting for
+/// the replies.
+async fn fetch_from_seeder(ctx: &ChunkFetchContext<'_>, seeder: FudSeeder) {
+    let seeder_id = seeder.node.id();
+    let (channel, _) = match ctx.fud.dht.get_channel(&seeder.node).await {
+        Ok(channel) => channel,
+        Err(e) => {
+            warn!(target: "fud::download::fetch_from_seeder()", "Could not get a channel for node {}: {e}", hash_to_string(&seeder_id));
+            return
+        }
+    };
+
+    let bitfield = fetch_chunk_map(ctx, &channel, &seeder).await;
+    info!(target: "fud::download::fetch_from_seeder()", "Requesting chunks from seeder {} ({}/{} chunks available)", hash_to_string(&seeder_id), bitfield.count(), bitfield.len());
+    ctx.swarm.lock().await.seeders.insert(seeder_id, bitfield);
 
-    let start_time = Instant::now();
     let msg_subscriber_chunk = channel.subscribe_msg::<FudChunkReply>().await.unwrap();
     let msg_subscriber_notfound = channel.subscribe_msg::<FudChunkNotFound>().await.unwrap();
 
-    let send_res = channel.send(&FudChunkRequest { resource: *ctx.hash, chunk: chunk_hash }).await;
-    if let Err(e) = send_res {
-        warn!(target: "fud::download::fetch_chunk()", "Error while sending FudChunkRequest: {e}");
-        return ChunkFetchControl::NextSeeder;
-    }
-
-    let chunk_recv =
-        receive_resource_msg(&msg_subscriber_chunk, *ctx.hash, ctx.fud.chunk_timeout).fuse();
-    let notfound_recv =
-        receive_resource_msg(&msg_subscriber_notfound, *ctx.hash, ctx.fud.chunk_timeout).fuse();
+    // Chunks requested from this seeder and the time we requested them
+    let mut in_flight: HashMap<blake3::Hash, Instant> = HashMap::new();
 
-    pin_mut!(chunk_recv, notfound_recv);
+    loop {
+        // Select the chunks to request
+        let mut swarm = ctx.swarm.lock().await;
+        if swarm.is_done() {
+            break
+        }
+        let mut to_request = vec![];
+        while in_flight.len() < PIPELINE_DEPTH {
+            let Some(chunk_hash) = swarm.select_chunk(&seeder_id, &in_flight) else { break };
+            in_flight.insert(chunk_hash, Instant::now());
+            to_request.push(chunk_hash);
+        }
+        let has_missing_chunks = swarm.has_missing_chunks(&seeder_id);
+        drop(swarm);
+
+        // Send the requests
+        let mut send_failed = false;
+        for chunk_hash in to_request {
+            let request = FudChunkRequest { resource: *ctx.hash, chunk: chunk_hash };
+            if let Err(e) = channel.send(&request).await {
+                warn!(target: "fud::download::fetch_from_seeder()", "Error while sending FudChunkRequest: {e}");
+                send_failed = true;
+                break
+            }
+        }
+        if send_failed {
+            break
+        }
 
-    // Wait for a FudChunkReply or FudNotFound
-    select! {
-        chunk_reply = chunk_recv => {
-            msg_subscriber_chunk.unsubscribe().await;
-            msg_subscriber_notfound.unsubscribe().await;
-            if let Err(e) = chunk_reply {
-                warn!(target: "fud::download::fetch_chunk()", "Error waiting for chunk reply: {e}");
-                return ChunkFetchControl::NextSeeder;
+        if in_flight.is_empty() {
+            // This seeder has none of the chunks we are missing
+            if !has_missing_chunks {
+                break
             }
-            let reply = chunk_reply.unwrap();
-            handle_chunk_reply(ctx, &chunk_hash, &reply, seeder, &start_time).await
+            // The chunks this seeder has are requested from other seeders
+            msleep(IDLE_SEEDER_MSECS).await;
+            continue
         }
-        notfound_reply = notfound_recv => {
-            msg_subscriber_chunk.unsubscribe().await;
-            msg_subscriber_notfound.unsubscribe().await;
-            if let Err(e) = notfound_reply {
-                warn!(target: "fud::download::fetch_chunk()", "Error waiting for NOTFOUND reply: {e}");
-                return ChunkFetchControl::NextSeeder;
+
+        let chunk_recv =
+            receive_resource_msg(&msg_subscriber_chunk, *ctx.hash, ctx.fud.chunk_timeout).fuse();
+        let notfound_recv =
+            receive_resource_msg(&msg_subscriber_notfound, *ctx.hash, ctx.fud.chunk_timeout).fuse();
+
+        pin_mut!(chunk_recv, notfound_recv);
+
+        // Wait for a FudChunkReply or FudNotFound
+        let control = select! {
+            chunk_reply = chunk_recv => {
+                if let Err(e) = chunk_reply {
+                    warn!(target: "fud::download::fetch_from_seeder()", "Error waiting for chunk reply: {e}");
+                    break
+                }
+                let reply = chunk_reply.unwrap();
+                let chunk_hash = blake3::hash(&reply.chunk);
+                let Some(start_time) = in_flight.remove(&chunk_hash) else {
+                    continue // We did not request this chunk from this seeder
+                };
+                let control =
+                    handle_chunk_reply(ctx, &chunk_hash, &reply, &seeder, &start_time).await;
+                ctx.swarm.lock().await.release(&chunk_hash);
+                control
+            }
+            notfound_reply = notfound_recv => {
+                if let Err(e) = notfound_reply {
+                    warn!(target: "fud::download::fetch_from_seeder()", "Error waiting for NOTFOUND reply: {e}");
+                    break
+                }
+                let chunk_hash = notfound_reply.unwrap().chunk;
+                if in_flight.remove(&chunk_hash).is_none() {
+                    continue // We did not request this chunk from this seeder
+                }
+                info!(target: "fud::download::fetch_from_seeder()", "Received NOTFOUND {} from seeder {}", hash_to_string(&chunk_hash), hash_to_string(&seeder_id));
+                ctx.swarm.lock().await.not_found(&seeder_id, &chunk_hash);
+                notify_event!(ctx.fud, ChunkNotFound, { hash: *ctx.hash, chunk_hash });
+                ChunkFetchControl::NextChunk
+            }
+        };
+
+        match control {
+            ChunkFetchControl::NextChunk => continue,
+            ChunkFetchControl::NextSeeder => break,
+            ChunkFetchControl::Abort => {
+                ctx.swarm.lock().await.aborted = true;
+                break
             }
-            info!(target: "fud::download::fetch_chunk()", "Received NOTFOUND {} from seeder {}", hash_to_string(&chunk_hash), hash_to_string(&seeder.node.id()));
-            notify_event!(ctx.fud, ChunkNotFound, { hash: *ctx.hash, chunk_hash });
-            ChunkFetchControl::NextChunk
         }
     }
+
+    // Cancel the requests we did not get a reply for
+    let mut swarm = ctx.swarm.lock().await;
+    for chunk_hash in in_flight.keys() {
+        swarm.release(chunk_hash);
+    }
+    swarm.seeders.remove(&seeder_id);
+    drop(swarm);
+
+    msg_subscriber_chunk.unsubscribe().await;
+    msg_subscriber_notfound.unsubscribe().await;
+    ctx.fud.dht.cleanup_channel(channel).await;
 }
 
 /// Processes an incoming chunk
 async fn handle_chunk_reply(
-    ctx: &mut ChunkFetchContext<'_>,
+    ctx: &ChunkFetchContext<'_>,
     chunk_hash: &blake3::Hash,
     reply: &FudChunkReply,
     seeder: &FudSeeder,
     start_time: &Instant,
 ) -> ChunkFetchControl {
-    let write_res = ctx.fud.geode.write_chunk(ctx.chunked, &reply.chunk).await;
+    // Chunks are written one at a time
+    let mut chunked = ctx.chunked.lock().await;
+
+    // In endgame mode, we can receive the same chunk from several seeders
+    if !ctx.swarm.lock().await.missing.contains(chunk_hash) {
+        return ChunkFetchControl::NextChunk;
+    }
+
+    let write_res = ctx.fud.geode.write_chunk(&mut chunked, &reply.chunk).await;
     if let Err(e) = write_res {
         error!(target: "fud::download::handle_chunk_reply()", "Failed inserting chunk {} to Geode: {e}", hash_to_string(chunk_hash));
         return ChunkFetchControl::NextChunk;
@@ -259,23 +540,15 @@ async fn handle_chunk_reply(
     // save the chunk in the scraps.
     if bytes_written < reply.chunk.len() {
         info!(target: "fud::download::handle_chunk_reply()", "Saving chunk {} as a scrap", hash_to_string(chunk_hash));
-        let chunk_written = ctx.fud.geode.get_chunk(ctx.chunked, chunk_hash).await;
-        if let Err(e) = chunk_written {
-            error!(target: "fud::download::handle_chunk_reply()", "Error getting chunk: {e}");
-            return ChunkFetchControl::NextChunk;
-        }
-        let scrap = Scrap {
-            chunk: reply.chunk.clone(),
-            hash_written: blake3::hash(&chunk_written.unwrap()),
-        };
-        if let Err(e) =
-            ctx.fud.scrap_tree.insert(chunk_hash.as_bytes(), serialize_async(&scrap).await)
-        {
+        if let Err(e) = ctx.fud.save_scrap(&mut chunked, chunk_hash, &reply.chunk).await {
             error!(target: "fud::download::handle_chunk_reply()", "Failed to save chunk {} as a scrap: {e}", hash_to_string(chunk_hash));
             return ChunkFetchControl::NextChunk;
         }
     }
 
+    // The chunk was written at all of its positions
+    let chunk_indexes = chunked.get_chunk_indexes(chunk_hash);
+
     // Update the resource
     let mut resources_write = ctx.fud.resources.write().await;
     let resource = resources_write.get_mut(ctx.hash);
@@ -284,12 +557,12 @@ async fn handle_chunk_reply(
     }
     let resource = resource.unwrap();
     resource.status = ResourceStatus::Downloading;
-    resource.total_chunks_downloaded += 1;
+    resource.total_chunks_downloaded += chunk_indexes.len() as u64;
     resource.target_chunks_downloaded += 1;
 
     resource.total_bytes_downloaded += reply.chunk.len() as u64;
     resource.target_bytes_downloaded +=
-        resource.get_selected_bytes(ctx.chunked, chunk_hash, reply.chunk.len()) as u64;
+        resource.get_selected_bytes(&chunked, chunk_hash, reply.chunk.len()) as u64;
     resource.speeds.push(reply.chunk.len() as f64 / start_time.elapsed().as_secs_f64());
     if resource.speeds.len() > 12 {
         resource.speeds = resource.speeds.split_off(resource.speeds.len() - 12); // Only keep the last few speeds
@@ -299,24 +572,47 @@ async fn handle_chunk_reply(
     // `total_bytes_size` (and `target_bytes_size`) again,
     // as `geode.write_chunk()` updated the FileSequence
     // to the exact file size.
-    if let Some(last_chunk) = ctx.chunked.iter().last() {
+    if let Some(last_chunk) = chunked.iter().last() {
         if matches!(resource.rtype, ResourceType::File) && last_chunk.hash == *chunk_hash {
-            resource.total_bytes_size = ctx.chunked.get_fileseq().len();
+            resource.total_bytes_size = chunked.get_fileseq().len();
             resource.target_bytes_size = resource.total_bytes_size;
         }
     }
     let resource = resource.clone();
     drop(resources_write);
 
-    if let Some(chunk_index) = ctx.chunked.get_chunk_index(chunk_hash) {
-        ctx.chunked.get_chunk_mut(chunk_index).available = true;
+    if !chunk_indexes.is_empty() {
+        // Update the cached `ChunkedStorage` in place, including the exact
+        // size of a file `geode.write_chunk()` set from its last chunk
         let mut chunked_storages = ctx.fud.chunked_storages.write().await;
-        chunked_storages.insert(*ctx.hash, ctx.chunked.clone());
+        let mut stored = chunked_storages.get_mut(ctx.hash);
+        for chunk_index in chunk_indexes {
+            chunked.get_chunk_mut(chunk_index).available = true;
+            if let Some(stored) = stored.as_mut() {
+                stored.get_chunk_mut(chunk_index).available = true;
+            }
+        }
+        if let Some(stored) = stored {
+            if !chunked.is_dir() && stored.get_fileseq().len() != chunked.get_fileseq().len() {
+                stored.get_fileseq_mut().set_file_size(0, chunked.get_fileseq().len());
+            }
+        }
         drop(chunked_storages);
     }
 
+    // Announce the chunks we have to the DHT each time their number doubles
+    let should_announce = !chunked.is_complete() &&
+        (resource.seeding_chunks_count == 0 ||
+            resource.total_chunks_downloaded >= 2 * resource.seeding_chunks_count);
+
+    ctx.swarm.lock().await.received(chunk_hash);
+    drop(chunked);
     notify_event!(ctx.fud, ChunkDownloadCompleted, { hash: *ctx.hash, chunk_hash: *chunk_hash, resource });
-    ctx.chunks.remove(chunk_hash);
+
+    if should_announce {
+        ctx.fud.announce_seeder(ctx.hash).await;
+    }
+
     ChunkFetchControl::NextChunk
 }
 
@@ -388,7 +684,7 @@ pub async fn fetch_metadata(
                 let reply = chunk_reply.unwrap();
                 let chunk_hash = blake3::hash(&reply.chunk);
                 // Check that this is the only chunk in the file
-                if !fud.geode.verify_metadata(hash, &[chunk_hash], &[]) {
+                if !fud.geode.verify_metadata(hash, &[chunk_hash], &[], &[]) {
                     warn!(target: "fud::download::fetch_metadata()", "Received a chunk while fetching metadata, but the chunk did not match the file hash");
                     return Err(().into())
                 }
@@ -403,7 +699,7 @@ pub async fn fetch_metadata(
                     return Err(e)
                 }
                 let reply = file_reply.unwrap();
-                if !fud.geode.verify_metadata(hash, &reply.chunk_hashes, &[]) {
+                if !fud.geode.verify_metadata(hash, &reply.chunk_hashes, &reply.chunk_sizes, &[]) {
                     warn!(target: "fud::download::fetch_metadata()", "Received invalid file metadata");
                     return Err(().into())
                 }
@@ -424,7 +720,7 @@ pub async fn fetch_metadata(
                     .map(|(path_str, size)| (PathBuf::from(path_str), size))
                     .collect();
 
-                if !fud.geode.verify_metadata(hash, &reply.chunk_hashes, &files) {
+                if !fud.geode.verify_metadata(hash, &reply.chunk_hashes, &reply.chunk_sizes, &files) {
                     warn!(target: "fud::download::fetch_metadata()", "Received invalid directory metadata");
                     return Err(().into())
                 }
@@ -453,19 +749,27 @@ pub async fn fetch_metadata(
     // At this point the reply content is already verified
     let (seeder, reply) = result.unwrap();
     match reply {
-        MetadataFetchReply::Directory(FudDirectoryReply { files, chunk_hashes, .. }) => {
+        MetadataFetchReply::Directory(FudDirectoryReply {
+            files,
+            chunk_hashes,
+            chunk_sizes,
+            ..
+        }) => {
             // Convert all file paths from String to PathBuf
             let mut files: Vec<_> =
                 files.into_iter().map(|(path_str, size)| (PathBuf::from(path_str), size)).collect();
 
             fud.geode.sort_files(&mut files);
-            if let Err(e) = fud.geode.insert_metadata(hash, &chunk_hashes, &files).await {
+            if let Err(e) =
+                fud.geode.insert_metadata(hash, &chunk_hashes, &chunk_sizes, &files).await
+            {
                 error!(target: "fud::download::fetch_metadata()", "Failed inserting directory {} to Geode: {e}", hash_to_string(hash));
                 return Err(e)
             }
         }
-        MetadataFetchReply::File(FudFileReply { chunk_hashes, .. }) => {
-            if let Err(e) = fud.geode.insert_metadata(hash, &chunk_hashes, &[]).await {
+        MetadataFetchReply::File(FudFileReply { chunk_hashes, chunk_sizes, .. }) => {
+            if let Err(e) = fud.geode.insert_metadata(hash, &chunk_hashes, &chunk_sizes, &[]).await
+            {
                 error!(target: "fud::download::fetch_metadata()", "Failed inserting file {} to Geode: {e}", hash_to_string(hash));
                 return Err(e)
             }
@@ -474,13 +778,14 @@ pub async fn fetch_metadata(
         MetadataFetchReply::Chunk(FudChunkReply { chunk, .. }) => {
             info!(target: "fud::download::fetch_metadata()", "File fits in a single chunk");
             let chunk_hash = blake3::hash(&chunk);
-            if let Err(e) = fud.geode.insert_metadata(hash, &[chunk_hash], &[]).await {
+            if let Err(e) = fud.geode.insert_metadata(hash, &[chunk_hash], &[], &[]).await {
                 error!(target: "fud::download::fetch_metadata()", "Failed inserting file {} to Geode (from single chunk): {e}", hash_to_string(hash));
                 return Err(e)
             }
             create_all_files(&[path.to_path_buf()]).await?;
             let mut chunked_file = ChunkedStorage::new(
                 &[chunk_hash],
+                &[],
                 &[(path.to_path_buf(), chunk.len() as u64)],
                 false,
             );
@@ -493,3 +798,58 @@ pub async fn fetch_metadata(
 
     Ok(seeder)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn seeder(id: u8) -> blake3::Hash {
+        blake3::hash(&[b's', id])
+    }
+
+    #[test]
+    fn swarm_selects_rarest_chunks_then_enters_endgame() {
+        let hashes: Vec<_> = (0..3u8).map(|i| blake3::hash(&[i])).collect();
+        let (a, b, c) = (hashes[0], hashes[1], hashes[2]);
+        let chunked = ChunkedStorage::new(&hashes, &[], &[], false);
+        let mut swarm = Swarm::new(&chunked, &hashes.iter().cloned().collect());
+
+        // a is on one seeder, b on two and c on three
+        let mut has_bc = ChunkBitfield::full(3);
+        has_bc.set(0, false);
+        let mut has_c = has_bc.clone();
+        has_c.set(1, false);
+        swarm.seeders.insert(seeder(1), ChunkBitfield::full(3));
+        swarm.seeders.insert(seeder(2), has_bc);
+        swarm.seeders.insert(seeder(3), has_c);
+        assert_eq!((swarm.availability(&a), swarm.availability(&b)), (1, 2));
+
+        // Rarest first, and no chunk is requested twice before the endgame
+        let mut in_flight = HashMap::new();
+        assert_eq!(swarm.select_chunk(&seeder(1), &in_flight), Some(a));
+        in_flight.insert(a, Instant::now());
+        assert_eq!(swarm.select_chunk(&seeder(1), &in_flight), Some(b));
+        assert_eq!(swarm.select_chunk(&seeder(2), &HashMap::new()), Some(c));
+        assert!(!swarm.endgame);
+
+        // Every missing chunk is requested, c can be requested again
+        assert_eq!(swarm.select_chunk(&seeder(3), &HashMap::new()), Some(c));
+        assert!(swarm.endgame);
+        assert_eq!(swarm.requested[&c], 2);
+        assert_eq!(swarm.select_chunk(&seeder(4), &HashMap::new()), None);
+
+        swarm.received(&c);
+        assert!(!swarm.requested.contains_key(&c) && !swarm.missing.contains(&c));
+
+        // A seeder that doesn't have the chunks it announced is not asked again
+        swarm.not_found(&seeder(1), &a);
+        swarm.not_found(&seeder(1), &b);
+        assert!(!swarm.has_missing_chunks(&seeder(1)));
+        assert!(swarm.has_missing_chunks(&seeder(2)));
+        assert_eq!(swarm.requested.get(&a), None);
+
+        swarm.received(&a);
+        swarm.received(&b);
+        assert!(swarm.is_done());
+    }
+}
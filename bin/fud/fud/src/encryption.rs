@@ -0,0 +1,302 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2026 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Encrypted resources.
+//!
+//! An encrypted resource is a file encrypted with a random
+//! [`ResourceKey`] before being inserted, so seeders only store and
+//! serve ciphertext. The resource is shared with a link containing both
+//! the resource hash and the key: `<hash>#<key>`.
+//!
+//! The file is split into blocks of [`ENCRYPTED_BLOCK_SIZE`] bytes, each
+//! block is encrypted with ChaCha20Poly1305, so that each encrypted block
+//! is exactly one chunk. The nonce of a block is its index, with a flag
+//! set on the last block to detect truncated files.
+//!
+//! Decryption is not streamed: the ciphertext is downloaded like any other
+//! resource, then decrypted once complete and removed.
+
+use std::{fmt, path::Path, str::FromStr};
+
+use chacha20poly1305::{
+    aead::{Aead, KeyInit},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use rand::{rngs::OsRng, RngCore};
+use smol::{
+    fs::{self, File},
+    io::{AsyncReadExt, AsyncWriteExt},
+};
+
+use darkfi::{
+    geode::{hash_to_string, MAX_CHUNK_SIZE},
+    Error, Result,
+};
+use darkfi_serial::{SerialDecodable, SerialEncodable};
+
+/// Size of the authentication tag appended to each encrypted block
+const TAG_SIZE: usize = 16;
+
+/// Size of a plaintext block, so that an encrypted block is one chunk
+pub const ENCRYPTED_BLOCK_SIZE: usize = MAX_CHUNK_SIZE - TAG_SIZE;
+
+/// Key of an encrypted resource
+#[derive(Clone, Copy, PartialEq, Eq, SerialEncodable, SerialDecodable)]
+pub struct ResourceKey([u8; 32]);
+
+impl ResourceKey {
+    /// Generate a new random key.
+    pub fn random() -> Self {
+        let mut key = [0u8; 32];
+        OsRng.fill_bytes(&mut key);
+        Self(key)
+    }
+
+    fn cipher(&self) -> ChaCha20Poly1305 {
+        ChaCha20Poly1305::new(Key::from_slice(&self.0))
+    }
+}
+
+impl fmt::Display for ResourceKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", bs58::encode(self.0).into_string())
+    }
+}
+
+// Never print keys in logs
+impl fmt::Debug for ResourceKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ResourceKey(..)")
+    }
+}
+
+impl FromStr for ResourceKey {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut key = [0u8; 32];
+        match bs58::decode(s).onto(&mut key) {
+            Ok(32) => Ok(Self(key)),
+            _ => Err(Error::Custom(format!("Invalid resource key {s}"))),
+        }
+    }
+}
+
+/// Parse a resource link, either `<hash>` or `<hash>#<key>` for encrypted
+/// resources.
+pub fn parse_link(link: &str) -> Result<(blake3::Hash, Option<ResourceKey>)> {
+    let (hash_str, key) = match link.split_once('#') {
+        Some((hash_str, key)) => (hash_str, Some(ResourceKey::from_str(key)?)),
+        None => (link, None),
+    };
+
+    let mut hash = [0u8; 32];
+    match bs58::decode(hash_str).onto(&mut hash) {
+        Ok(32) => Ok((blake3::Hash::from_bytes(hash), key)),
+        _ => Err(Error::Custom(format!("Invalid resource hash {hash_str}"))),
+    }
+}
+
+/// Return the link of an encrypted resource.
+pub fn link(hash: &blake3::Hash, key: &ResourceKey) -> String {
+    format!("{}#{key}", hash_to_string(hash))
+}
+
+/// Nonce of the block `index`.
+fn block_nonce(index: u64, last: bool) -> [u8; 12] {
+    let mut nonce = [0u8; 12];
+    nonce[..8].copy_from_slice(&index.to_le_bytes());
+    nonce[8] = last as u8;
+    nonce
+}
+
+/// Encrypt the file `src` with `key` and write the result to `dst`.
+pub async fn encrypt_file(key: &ResourceKey, src: &Path, dst: &Path) -> Result<()> {
+    let len = fs::metadata(src).await?.len();
+    let blocks = len.div_ceil(ENCRYPTED_BLOCK_SIZE as u64).max(1);
+    let cipher = key.cipher();
+
+    let mut reader = File::open(src).await?;
+    let mut writer = File::create(dst).await?;
+    let mut buf = vec![0u8; ENCRYPTED_BLOCK_SIZE];
+
+    for index in 0..blocks {
+        let last = index == blocks - 1;
+        let size = match last {
+            true => (len - index * ENCRYPTED_BLOCK_SIZE as u64) as usize,
+            false => ENCRYPTED_BLOCK_SIZE,
+        };
+        reader.read_exact(&mut buf[..size]).await?;
+
+        let Ok(ciphertext) =
+            cipher.encrypt(Nonce::from_slice(&block_nonce(index, last)), &buf[..size])
+        else {
+            return Err(Error::Custom("Failed encrypting file".to_string()))
+        };
+        writer.write_all(&ciphertext).await?;
+    }
+
+    writer.flush().await?;
+    writer.sync_all().await?;
+
+    Ok(())
+}
+
+/// Decrypt the file `src` created by [`encrypt_file`] with `key`, and
+/// write the result to `dst`. `dst` is removed if decryption fails.
+pub async fn decrypt_file(key: &ResourceKey, src: &Path, dst: &Path) -> Result<()> {
+    let res = decrypt_file_inner(key, src, dst).await;
+    if res.is_err() {
+        let _ = fs::remove_file(dst).await;
+    }
+    res
+}
+
+async fn decrypt_file_inner(key: &ResourceKey, src: &Path, dst: &Path) -> Result<()> {
+    let len = fs::metadata(src).await?.len();
+    let blocks = len.div_ceil(MAX_CHUNK_SIZE as u64).max(1);
+    let last_size = len - (blocks - 1) * MAX_CHUNK_SIZE as u64;
+    if last_size < TAG_SIZE as u64 {
+        return Err(Error::Custom("Invalid encrypted file size".to_string()))
+    }
+    let cipher = key.cipher();
+
+    if let Some(parent) = dst.parent() {
+        fs::create_dir_all(parent).await?;
+    }
+    let mut reader = File::open(src).await?;
+    let mut writer = File::create(dst).await?;
+    let mut buf = vec![0u8; MAX_CHUNK_SIZE];
+
+    for index in 0..blocks {
+        let last = index == blocks - 1;
+        let size = match last {
+            true => last_size as usize,
+            false => MAX_CHUNK_SIZE,
+        };
+        reader.read_exact(&mut buf[..size]).await?;
+
+        let Ok(plaintext) =
+            cipher.decrypt(Nonce::from_slice(&block_nonce(index, last)), &buf[..size])
+        else {
+            return Err(Error::Custom("Failed decrypting file, wrong key?".to_string()))
+        };
+        writer.write_all(&plaintext).await?;
+    }
+
+    writer.flush().await?;
+    writer.sync_all().await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::path::PathBuf;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("fud_encryption_{}_{name}", std::process::id()))
+    }
+
+    fn roundtrip(key: &ResourceKey, plaintext: &[u8]) -> (Vec<u8>, Vec<u8>) {
+        let (src, enc, dst) = (temp_path("src"), temp_path("enc"), temp_path("dst"));
+        smol::block_on(async {
+            fs::write(&src, plaintext).await.unwrap();
+            encrypt_file(key, &src, &enc).await.unwrap();
+            decrypt_file(key, &enc, &dst).await.unwrap();
+            let res = (fs::read(&enc).await.unwrap(), fs::read(&dst).await.unwrap());
+            for path in [src, enc, dst] {
+                fs::remove_file(path).await.unwrap();
+            }
+            res
+        })
+    }
+
+    #[test]
+    fn encrypted_files_roundtrip() {
+        let key = ResourceKey::random();
+        for len in [0, 1, ENCRYPTED_BLOCK_SIZE, 2 * ENCRYPTED_BLOCK_SIZE + 5] {
+            let plaintext: Vec<u8> = (0..len).map(|i| i as u8).collect();
+            let (ciphertext, decrypted) = roundtrip(&key, &plaintext);
+
+            // Each block gets a tag, so each full block is exactly one chunk
+            assert_eq!(
+                ciphertext.len(),
+                len.max(1).div_ceil(ENCRYPTED_BLOCK_SIZE) * TAG_SIZE + len
+            );
+            assert_eq!(decrypted, plaintext);
+        }
+    }
+
+    #[test]
+    fn wrong_key_and_truncated_files_are_rejected() {
+        let key = ResourceKey::random();
+        let (src, enc, dst) = (temp_path("bad_src"), temp_path("bad_enc"), temp_path("bad_dst"));
+        let plaintext = vec![7u8; 2 * ENCRYPTED_BLOCK_SIZE + 5];
+
+        smol::block_on(async {
+            fs::write(&src, &plaintext).await.unwrap();
+            encrypt_file(&key, &src, &enc).await.unwrap();
+            let ciphertext = fs::read(&enc).await.unwrap();
+
+            // Wrong key, and `dst` is removed
+            assert!(decrypt_file(&ResourceKey::random(), &enc, &dst).await.is_err());
+            assert!(fs::metadata(&dst).await.is_err());
+
+            // Truncated on a block boundary: the new last block was not
+            // encrypted with the last block flag
+            fs::write(&enc, &ciphertext[..2 * MAX_CHUNK_SIZE]).await.unwrap();
+            assert!(decrypt_file(&key, &enc, &dst).await.is_err());
+            assert!(fs::metadata(&dst).await.is_err());
+
+            // Truncated within the last block
+            fs::write(&enc, &ciphertext[..ciphertext.len() - 1]).await.unwrap();
+            assert!(decrypt_file(&key, &enc, &dst).await.is_err());
+
+            // Shorter than a tag
+            fs::write(&enc, &ciphertext[..TAG_SIZE - 1]).await.unwrap();
+            assert!(decrypt_file(&key, &enc, &dst).await.is_err());
+
+            for path in [src, enc] {
+                fs::remove_file(path).await.unwrap();
+            }
+        });
+    }
+
+    #[test]
+    fn last_block_flag_changes_the_nonce() {
+        assert_ne!(block_nonce(0, false), block_nonce(0, true));
+        assert_ne!(block_nonce(1, false), block_nonce(0, false));
+        assert_eq!(block_nonce(1, true)[..9], [1, 0, 0, 0, 0, 0, 0, 0, 1]);
+    }
+
+    #[test]
+    fn links_roundtrip() {
+        let hash = blake3::hash(b"resource");
+        let key = ResourceKey::random();
+
+        let (parsed_hash, parsed_key) = parse_link(&link(&hash, &key)).unwrap();
+        assert_eq!((parsed_hash, parsed_key), (hash, Some(key)));
+        assert_eq!(parse_link(&hash_to_string(&hash)).unwrap(), (hash, None));
+
+        assert!(parse_link("notahash").is_err());
+        assert!(parse_link(&format!("{}#notakey", hash_to_string(&hash))).is_err());
+    }
+}
@@ -24,7 +24,7 @@ use darkfi::{
     rpc::util::{json_map, json_str},
 };
 
-use crate::{dht::FudSeeder, resource::Resource};
+use crate::{dht::FudSeeder, pointer::FudPointer, resource::Resource};
 
 #[derive(Clone, Debug)]
 pub struct DownloadStarted {
@@ -92,6 +92,12 @@ pub struct SeedersFound {
     pub seeders: Vec<FudSeeder>,
 }
 
+#[derive(Clone, Debug)]
+pub struct PointerUpdated {
+    pub hash: blake3::Hash,
+    pub pointer: FudPointer,
+}
+
 #[derive(Clone, Debug)]
 pub enum FudEvent {
     Ready,
@@ -108,6 +114,7 @@ pub enum FudEvent {
     InsertCompleted(InsertCompleted),
     InsertError(InsertError),
     SeedersFound(SeedersFound),
+    PointerUpdated(PointerUpdated),
 }
 
 impl From<DownloadStarted> for JsonValue {
@@ -215,6 +222,14 @@ impl From<SeedersFound> for JsonValue {
         ])
     }
 }
+impl From<PointerUpdated> for JsonValue {
+    fn from(info: PointerUpdated) -> JsonValue {
+        json_map([
+            ("hash", JsonValue::String(hash_to_string(&info.hash))),
+            ("pointer", info.pointer.into()),
+        ])
+    }
+}
 impl From<FudEvent> for JsonValue {
     fn from(event: FudEvent) -> JsonValue {
         match event {
@@ -259,6 +274,9 @@ impl From<FudEvent> for JsonValue {
             FudEvent::SeedersFound(info) => {
                 json_map([("event", json_str("seeders_found")), ("info", info.into())])
             }
+            FudEvent::PointerUpdated(info) => {
+                json_map([("event", json_str("pointer_updated")), ("info", info.into())])
+            }
         }
     }
 }
@@ -0,0 +1,819 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2026 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Read-only HTTP gateway.
+//!
+//! Serves resources to HTTP clients that don't run fud, at
+//! `/fud/<hash>/<path>`:
+//! - a file resource is served at `/fud/<hash>` (a trailing file name,
+//!   as in `/fud/<hash>/<name>`, is accepted and ignored),
+//! - a file of a directory resource is served at `/fud/<hash>/<file path>`,
+//!   and `/fud/<hash>/` (or any sub directory) returns an HTML listing.
+//!
+//! Resources of the allowlist we don't have are downloaded to the
+//! downloads path when they are requested, and files are streamed as soon
+//! as the chunks they need are available. Without an allowlist, only the
+//! resources we already have (or are downloading) are served, so that
+//! clients can't make us download and seed arbitrary resources.
+//! Single range requests are supported.
+//!
+//! Requests are rate limited per client address, and the number of
+//! connections handled at once is capped.
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    io::SeekFrom,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use smol::{
+    fs::File,
+    io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt},
+    lock::{Mutex, Semaphore},
+};
+use structopt::StructOpt;
+use tracing::{debug, info, warn};
+use url::Url;
+
+use darkfi::{
+    geode::{hash_to_string, ChunkedStorage},
+    net::transport::{Listener, PtListener, PtStream},
+    system::{io_timeout, msleep, ExecutorPtr},
+    Error, Result,
+};
+
+use crate::{
+    resource::{Resource, ResourceStatus},
+    util::FileSelection,
+    Fud,
+};
+
+/// Maximum size of an HTTP request head
+const MAX_REQUEST_HEAD_SIZE: usize = 8192;
+
+/// Time allowed to a client to send its request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Duration of a rate limiting window
+const RATE_LIMIT_WINDOW: Duration = Duration::from_secs(60);
+
+/// Maximum number of connections handled at once, new connections wait
+/// to be accepted
+const MAX_CONNECTIONS: usize = 64;
+
+#[derive(Clone, Debug)]
+pub struct GatewaySettings {
+    /// HTTP gateway listen URL, the gateway is disabled if `None`
+    pub listen: Option<Url>,
+    /// Resources the gateway serves and fetches from the network if we
+    /// don't have them. If empty, all the resources we have are served but
+    /// nothing is fetched.
+    pub allowed_hashes: HashSet<blake3::Hash>,
+    /// Maximum number of requests per minute from a client address
+    /// (0 to disable rate limiting)
+    pub rate_limit: u32,
+    /// Time in seconds we wait for the metadata or a chunk of a resource
+    /// we don't have before giving up
+    pub fetch_timeout: u64,
+}
+
+impl Default for GatewaySettings {
+    fn default() -> Self {
+        Self { listen: None, allowed_hashes: HashSet::new(), rate_limit: 60, fetch_timeout: 60 }
+    }
+}
+
+#[derive(Clone, Debug, serde::Deserialize, structopt::StructOpt, structopt_toml::StructOptToml)]
+#[structopt()]
+#[serde(rename = "gateway")]
+pub struct GatewaySettingsOpt {
+    /// HTTP gateway listen URL (the gateway is disabled if not set)
+    #[structopt(long)]
+    pub gateway_listen: Option<Url>,
+
+    /// Resource hashes the HTTP gateway serves and fetches if missing
+    /// (all local resources and no fetching if empty)
+    #[structopt(long, use_delimiter = true)]
+    pub gateway_allowed_hashes: Vec<String>,
+
+    /// Maximum number of HTTP gateway requests per minute from a client
+    /// address (0 to disable rate limiting)
+    #[structopt(long)]
+    pub gateway_rate_limit: Option<u32>,
+
+    /// Time in seconds the HTTP gateway waits for missing data before
+    /// giving up
+    #[structopt(long)]
+    pub gateway_fetch_timeout: Option<u64>,
+}
+
+impl TryFrom<GatewaySettingsOpt> for GatewaySettings {
+    type Error = Error;
+
+    fn try_from(opt: GatewaySettingsOpt) -> Result<Self> {
+        let def = GatewaySettings::default();
+
+        let mut allowed_hashes = HashSet::new();
+        for hash_str in opt.gateway_allowed_hashes {
+            let mut hash = [0u8; 32];
+            match bs58::decode(&hash_str).onto(&mut hash) {
+                Ok(32) => allowed_hashes.insert(blake3::Hash::from_bytes(hash)),
+                _ => return Err(Error::Custom(format!("Invalid gateway allowed hash {hash_str}"))),
+            };
+        }
+
+        Ok(Self {
+            listen: opt.gateway_listen,
+            allowed_hashes,
+            rate_limit: opt.gateway_rate_limit.unwrap_or(def.rate_limit),
+            fetch_timeout: opt.gateway_fetch_timeout.unwrap_or(def.fetch_timeout),
+        })
+    }
+}
+
+/// Error while handling a request
+enum HttpError {
+    /// Error sent to the client as a response with this status
+    Status(u16, &'static str),
+    /// The response was already started, the connection is closed
+    Aborted,
+}
+
+impl From<std::io::Error> for HttpError {
+    fn from(_: std::io::Error) -> Self {
+        Self::Aborted
+    }
+}
+
+const BAD_REQUEST: HttpError = HttpError::Status(400, "Bad Request");
+const FORBIDDEN: HttpError = HttpError::Status(403, "Forbidden");
+const NOT_FOUND: HttpError = HttpError::Status(404, "Not Found");
+const METHOD_NOT_ALLOWED: HttpError = HttpError::Status(405, "Method Not Allowed");
+const RANGE_NOT_SATISFIABLE: HttpError = HttpError::Status(416, "Range Not Satisfiable");
+const TOO_MANY_REQUESTS: HttpError = HttpError::Status(429, "Too Many Requests");
+const GATEWAY_TIMEOUT: HttpError = HttpError::Status(504, "Gateway Timeout");
+
+type HttpResult<T> = std::result::Result<T, HttpError>;
+
+/// A parsed request
+struct Request {
+    /// `true` for HEAD requests, the body is not sent
+    head: bool,
+    /// Percent-decoded path, without the query string
+    path: String,
+    /// Value of the `Range` header
+    range: Option<String>,
+}
+
+/// Byte range requested by a client
+enum ByteRange {
+    /// The whole file
+    Full,
+    /// Inclusive range of bytes
+    Partial(u64, u64),
+}
+
+pub struct FudGateway {
+    fud: Arc<Fud>,
+    settings: GatewaySettings,
+    /// Start of the current rate limiting window, and number of requests
+    /// of each client address in the window
+    clients: Mutex<(Instant, HashMap<String, u32>)>,
+    /// Chunked storages of the resources we inserted, which are not in
+    /// `fud.chunked_storages`
+    inserted: Mutex<HashMap<blake3::Hash, ChunkedStorage>>,
+    /// Permits of the connections being handled
+    connections: Arc<Semaphore>,
+}
+
+impl FudGateway {
+    pub fn new(fud: Arc<Fud>, settings: GatewaySettings) -> Arc<Self> {
+        Arc::new(Self {
+            fud,
+            settings,
+            clients: Mutex::new((Instant::now(), HashMap::new())),
+            inserted: Mutex::new(HashMap::new()),
+            connections: Arc::new(Semaphore::new(MAX_CONNECTIONS)),
+        })
+    }
+
+    /// Accept HTTP connections on `listen` and serve them.
+    pub async fn listen_and_serve(self: Arc<Self>, listen: Url, ex: ExecutorPtr) -> Result<()> {
+        let listener = Listener::new(listen, None, false).await?.listen().await?;
+
+        loop {
+            let permit = self.connections.acquire_arc().await;
+            let connection = match listener.next().await {
+                Ok(negotiation) => negotiation.await,
+                Err(e) => Err(e),
+            };
+            let (stream, url) = match connection {
+                Ok(v) => v,
+                Err(e) => {
+                    warn!(target: "fud::gateway", "Failed accepting HTTP connection: {e}");
+                    continue
+                }
+            };
+
+            let self_ = self.clone();
+            ex.spawn(async move {
+                self_.handle_connection(stream, url).await;
+                drop(permit);
+            })
+            .detach();
+        }
+    }
+
+    /// Handle a single request on `stream`, then close it.
+    async fn handle_connection(&self, mut stream: Box<dyn PtStream>, peer: Url) {
+        let client = peer.host_str().unwrap_or_default().to_string();
+
+        let res = match io_timeout(REQUEST_TIMEOUT, read_request(&mut stream)).await {
+            Ok(Ok(request)) => {
+                debug!(target: "fud::gateway", "{client} requested {}", request.path);
+                match self.check_rate_limit(&client).await {
+                    true => self.handle_request(&mut stream, &request).await,
+                    false => Err(TOO_MANY_REQUESTS),
+                }
+            }
+            Ok(Err(e)) => Err(e),
+            Err(_) => return,
+        };
+
+        match res {
+            Ok(()) => {}
+            Err(HttpError::Status(status, reason)) => {
+                let body = format!("{status} {reason}\n");
+                let headers = [
+                    ("Content-Type", "text/plain; charset=utf-8".to_string()),
+                    ("Content-Length", body.len().to_string()),
+                ];
+                if write_head(&mut stream, status, reason, &headers).await.is_ok() {
+                    let _ = stream.write_all(body.as_bytes()).await;
+                }
+            }
+            Err(HttpError::Aborted) => {
+                debug!(target: "fud::gateway", "Response to {client} aborted");
+            }
+        }
+
+        let _ = stream.flush().await;
+        let _ = stream.close().await;
+    }
+
+    /// Returns `false` if `client` sent too many requests in the current
+    /// rate limiting window.
+    async fn check_rate_limit(&self, client: &str) -> bool {
+        if self.settings.rate_limit == 0 {
+            return true
+        }
+
+        let mut clients = self.clients.lock().await;
+        let (window_start, counts) = &mut *clients;
+        if window_start.elapsed() >= RATE_LIMIT_WINDOW {
+            *window_start = Instant::now();
+            counts.clear();
+        }
+
+        let count = counts.entry(client.to_string()).or_insert(0);
+        *count += 1;
+        *count <= self.settings.rate_limit
+    }
+
+    async fn handle_request(
+        &self,
+        stream: &mut Box<dyn PtStream>,
+        request: &Request,
+    ) -> HttpResult<()> {
+        // Split `/fud/<hash>/<path>`
+        let Some(rest) = request.path.strip_prefix("/fud/") else { return Err(NOT_FOUND) };
+        let (hash_str, file_path) = match rest.split_once('/') {
+            Some((hash_str, file_path)) => (hash_str, Some(file_path)),
+            None => (rest, None),
+        };
+
+        let mut hash = [0u8; 32];
+        match bs58::decode(hash_str).onto(&mut hash) {
+            Ok(32) => {}
+            _ => return Err(NOT_FOUND),
+        }
+        let hash = blake3::Hash::from_bytes(hash);
+
+        if !self.settings.allowed_hashes.is_empty() && !self.settings.allowed_hashes.contains(&hash)
+        {
+            return Err(FORBIDDEN)
+        }
+
+        // Wait for the metadata
+        let (resource, chunked) =
+            self.wait_chunked(&hash, &FileSelection::Set(HashSet::new()), |_| true).await?;
+
+        if !chunked.is_dir() {
+            return self.serve_file(stream, request, &hash, 0, FileSelection::All, chunked).await
+        }
+
+        // Directory resource: redirect to the listing if there is no
+        // trailing slash, so that relative links work
+        let Some(file_path) = file_path else {
+            return redirect(stream, &format!("/fud/{hash_str}/")).await
+        };
+
+        let files: Vec<(String, u64)> = chunked
+            .get_files()
+            .iter()
+            .filter_map(|(path, size)| {
+                let rel = path.strip_prefix(&resource.path).ok()?;
+                Some((rel.to_string_lossy().to_string(), *size))
+            })
+            .collect();
+
+        // A file of the directory
+        if let Some(file_index) = files.iter().position(|(path, _)| path == file_path) {
+            let selection = FileSelection::Set(HashSet::from([PathBuf::from(file_path)]));
+            return self.serve_file(stream, request, &hash, file_index, selection, chunked).await
+        }
+
+        // A sub directory
+        let prefix = match file_path.is_empty() || file_path.ends_with('/') {
+            true => file_path.to_string(),
+            false => format!("{file_path}/"),
+        };
+        if !files.iter().any(|(path, _)| path.starts_with(&prefix)) {
+            return Err(NOT_FOUND)
+        }
+        if prefix != file_path {
+            return redirect(stream, &format!("/fud/{hash_str}/{}", percent_encode(&prefix))).await
+        }
+
+        let body = directory_listing(&format!("/fud/{hash_str}/{prefix}"), &prefix, &files);
+        let headers = [
+            ("Content-Type", "text/html; charset=utf-8".to_string()),
+            ("Content-Length", body.len().to_string()),
+        ];
+        write_head(stream, 200, "OK", &headers).await?;
+        if !request.head {
+            stream.write_all(body.as_bytes()).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Get the resource `hash` and its chunked storage, with the chunks we
+    /// have marked as available. Returns `None` if we don't have the
+    /// metadata.
+    async fn chunked(&self, hash: &blake3::Hash) -> Option<(Resource, ChunkedStorage)> {
+        let Some(resource) = self.fud.resources.read().await.get(hash).cloned() else {
+            self.inserted.lock().await.remove(hash);
+            return None
+        };
+
+        if let Some(chunked) = self.fud.chunked_storages.read().await.get(hash) {
+            return Some((resource, chunked.clone()))
+        }
+
+        // Resources we inserted are complete, but not in `chunked_storages`
+        let mut inserted = self.inserted.lock().await;
+        if !matches!(resource.status, ResourceStatus::Seeding) {
+            inserted.remove(hash);
+            return None
+        }
+        if let Some(chunked) = inserted.get(hash) {
+            return Some((resource, chunked.clone()))
+        }
+        let mut chunked = self.fud.geode.get(hash, &resource.path).await.ok()?;
+        for chunk in chunked.iter_mut() {
+            chunk.available = true;
+        }
+        if !chunked.is_dir() {
+            let size = smol::fs::metadata(&resource.path).await.ok()?.len();
+            chunked.get_fileseq_mut().set_file_size(0, size);
+        }
+        inserted.insert(*hash, chunked.clone());
+
+        Some((resource, chunked))
+    }
+
+    /// Wait until we have the metadata of `hash` and `ready` returns `true`,
+    /// downloading the `files` of the resource if it is in the allowlist.
+    /// Returns a 404 if the resource is not ready and not being downloaded.
+    async fn wait_chunked(
+        &self,
+        hash: &blake3::Hash,
+        files: &FileSelection,
+        ready: impl Fn(&ChunkedStorage) -> bool,
+    ) -> HttpResult<(Resource, ChunkedStorage)> {
+        let deadline = Instant::now() + Duration::from_secs(self.settings.fetch_timeout);
+        let mut requested = false;
+
+        loop {
+            let current = self.chunked(hash).await;
+            if let Some((resource, chunked)) = &current {
+                if ready(chunked) {
+                    return Ok((resource.clone(), chunked.clone()))
+                }
+            }
+
+            // Start a download, unless one is already running
+            if !requested && !self.fud.fetch_tasks.read().await.contains_key(hash) {
+                // Only resources of the allowlist are fetched
+                if !self.settings.allowed_hashes.contains(hash) {
+                    return Err(NOT_FOUND)
+                }
+                let path = match &current {
+                    Some((resource, _)) => resource.path.clone(),
+                    None => self.fud.downloads_path.join(hash_to_string(hash)),
+                };
+                info!(target: "fud::gateway", "Fetching {} for the HTTP gateway", hash_to_string(hash));
+                if let Err(e) = self.fud.get(hash, &path, files.clone()).await {
+                    warn!(target: "fud::gateway", "Could not fetch {}: {e}", hash_to_string(hash));
+                }
+                requested = true;
+            }
+
+            if Instant::now() >= deadline {
+                return Err(GATEWAY_TIMEOUT)
+            }
+            msleep(500).await;
+        }
+    }
+
+    /// Stream the file `file_index` of `chunked`, waiting for the chunks we
+    /// don't have yet.
+    async fn serve_file(
+        &self,
+        stream: &mut Box<dyn PtStream>,
+        request: &Request,
+        hash: &blake3::Hash,
+        file_index: usize,
+        files: FileSelection,
+        mut chunked: ChunkedStorage,
+    ) -> HttpResult<()> {
+        // We only know the size of a file with fixed-size chunks once we
+        // have its last chunk
+        if !chunked.is_dir() &&
+            !chunked.is_content_defined() &&
+            chunked.iter().last().is_some_and(|chunk| !chunk.available)
+        {
+            let last = chunked.len() - 1;
+            chunked = self.wait_chunked(hash, &files, |c| c.get_chunks()[last].available).await?.1;
+        }
+
+        let (path, size) = chunked.get_files()[file_index].clone();
+        let file_position = chunked.get_fileseq().get_file_position(file_index);
+
+        let range = match &request.range {
+            Some(value) => parse_range(value, size)?,
+            None => ByteRange::Full,
+        };
+        let (start, end) = match range {
+            ByteRange::Full => (0, size),
+            ByteRange::Partial(start, end) => (start, end + 1),
+        };
+
+        let mut headers = vec![
+            ("Content-Type", content_type(&path).to_string()),
+            ("Content-Length", (end - start).to_string()),
+            ("Accept-Ranges", "bytes".to_string()),
+            ("Cache-Control", "public, max-age=31536000, immutable".to_string()),
+            ("Content-Security-Policy", "sandbox".to_string()),
+        ];
+        match range {
+            ByteRange::Full => write_head(stream, 200, "OK", &headers).await?,
+            ByteRange::Partial(..) => {
+                headers.push(("Content-Range", format!("bytes {start}-{}/{size}", end - 1)));
+                write_head(stream, 206, "Partial Content", &headers).await?
+            }
+        }
+
+        if request.head {
+            return Ok(())
+        }
+
+        let mut file: Option<File> = None;
+        let mut buf = vec![];
+        let mut pos = start;
+        while pos < end {
+            // Wait for the chunk containing `pos`
+            let chunk_index = chunked.get_chunk_index_at(file_position + pos);
+            if !chunked.get_chunks()[chunk_index].available {
+                chunked = match self
+                    .wait_chunked(hash, &files, |c| c.get_chunks()[chunk_index].available)
+                    .await
+                {
+                    Ok((_, chunked)) => chunked,
+                    Err(_) => return Err(HttpError::Aborted),
+                };
+            }
+
+            // Send the part of the file that is in this chunk
+            let chunk_end = chunked.get_chunk_position(chunk_index) +
+                chunked.get_chunk_max_size(chunk_index) as u64;
+            let len = (end.min(chunk_end - file_position) - pos) as usize;
+
+            if file.is_none() {
+                file = Some(File::open(&path).await?);
+            }
+            let reader = file.as_mut().unwrap();
+            reader.seek(SeekFrom::Start(pos)).await?;
+            buf.resize(len, 0);
+            reader.read_exact(&mut buf).await?;
+            stream.write_all(&buf).await?;
+
+            pos += len as u64;
+        }
+
+        Ok(())
+    }
+}
+
+/// Read and parse the request head.
+async fn read_request(stream: &mut Box<dyn PtStream>) -> std::io::Result<HttpResult<Request>> {
+    let mut buf = vec![];
+    let mut tmpbuf = [0u8; 1024];
+    loop {
+        let n = stream.read(&mut tmpbuf).await?;
+        if n == 0 {
+            return Err(std::io::ErrorKind::ConnectionAborted.into())
+        }
+        buf.extend_from_slice(&tmpbuf[..n]);
+
+        if buf.windows(4).any(|w| w == b"\r\n\r\n") {
+            break
+        }
+        if buf.len() > MAX_REQUEST_HEAD_SIZE {
+            return Ok(Err(BAD_REQUEST))
+        }
+    }
+
+    let mut headers = [httparse::EMPTY_HEADER; 32];
+    let mut req = httparse::Request::new(&mut headers);
+    match req.parse(&buf) {
+        Ok(httparse::Status::Complete(_)) => {}
+        _ => return Ok(Err(BAD_REQUEST)),
+    }
+
+    let head = match req.method {
+        Some("GET") => false,
+        Some("HEAD") => true,
+        Some(_) => return Ok(Err(METHOD_NOT_ALLOWED)),
+        None => return Ok(Err(BAD_REQUEST)),
+    };
+
+    let Some(path) = req.path else { return Ok(Err(BAD_REQUEST)) };
+    let path = path.split(['?', '#']).next().unwrap_or_default();
+    let Some(path) = percent_decode(path) else { return Ok(Err(BAD_REQUEST)) };
+
+    let range = req
+        .headers
+        .iter()
+        .find(|header| header.name.eq_ignore_ascii_case("range"))
+        .map(|header| String::from_utf8_lossy(header.value).to_string());
+
+    Ok(Ok(Request { head, path, range }))
+}
+
+/// Write the status line and headers of a response.
+async fn write_head(
+    stream: &mut Box<dyn PtStream>,
+    status: u16,
+    reason: &str,
+    headers: &[(&str, String)],
+) -> std::io::Result<()> {
+    let mut head = format!("HTTP/1.1 {status} {reason}\r\n");
+    head.push_str("Server: fud\r\nConnection: close\r\nX-Content-Type-Options: nosniff\r\n");
+    for (name, value) in headers {
+        head.push_str(&format!("{name}: {value}\r\n"));
+    }
+    head.push_str("\r\n");
+    stream.write_all(head.as_bytes()).await
+}
+
+/// Send a permanent redirection to `location`.
+async fn redirect(stream: &mut Box<dyn PtStream>, location: &str) -> HttpResult<()> {
+    let headers = [("Location", location.to_string()), ("Content-Length", "0".to_string())];
+    write_head(stream, 301, "Moved Permanently", &headers).await?;
+    Ok(())
+}
+
+/// Parse the value of a `Range` header for a file of `size` bytes.
+/// Invalid or multiple ranges are ignored, as allowed by RFC 9110.
+fn parse_range(value: &str, size: u64) -> HttpResult<ByteRange> {
+    let Some(range) = value.trim().strip_prefix("bytes=") else { return Ok(ByteRange::Full) };
+    if range.contains(',') {
+        return Ok(ByteRange::Full)
+    }
+    let Some((start, end)) = range.trim().split_once('-') else { return Ok(ByteRange::Full) };
+
+    let (start, end) = match (start.parse::<u64>(), end.parse::<u64>()) {
+        // bytes=<start>-<end>
+        (Ok(start), Ok(end)) if start <= end => (start, end.min(size.saturating_sub(1))),
+        // bytes=<start>-
+        (Ok(start), Err(_)) if end.is_empty() => (start, size.saturating_sub(1)),
+        // bytes=-<suffix length>
+        (Err(_), Ok(suffix)) if start.is_empty() => {
+            if suffix == 0 {
+                return Err(RANGE_NOT_SATISFIABLE)
+            }
+            (size.saturating_sub(suffix), size.saturating_sub(1))
+        }
+        _ => return Ok(ByteRange::Full),
+    };
+
+    if start >= size {
+        return Err(RANGE_NOT_SATISFIABLE)
+    }
+
+    Ok(ByteRange::Partial(start, end))
+}
+
+/// HTML listing of the files and directories in `prefix`.
+/// `files` are (relative path, size) of all the files of the resource.
+fn directory_listing(title: &str, prefix: &str, files: &[(String, u64)]) -> String {
+    // Entry name -> size (None for directories)
+    let mut entries: BTreeMap<String, Option<u64>> = BTreeMap::new();
+    for (path, size) in files {
+        let Some(rel) = path.strip_prefix(prefix) else { continue };
+        match rel.split_once('/') {
+            Some((dir, _)) => entries.insert(format!("{dir}/"), None),
+            None => entries.insert(rel.to_string(), Some(*size)),
+        };
+    }
+
+    let title = html_escape(title);
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"><title>{title}</title></head>\n<body>\n<h1>{title}</h1>\n<ul>\n"
+    );
+    if !prefix.is_empty() {
+        html.push_str("<li><a href=\"../\">../</a></li>\n");
+    }
+    for (name, size) in entries {
+        let href = percent_encode(&name);
+        let name = html_escape(&name);
+        match size {
+            Some(size) => {
+                html.push_str(&format!("<li><a href=\"{href}\">{name}</a> ({size} bytes)</li>\n"))
+            }
+            None => html.push_str(&format!("<li><a href=\"{href}\">{name}</a></li>\n")),
+        }
+    }
+    html.push_str("</ul>\n</body>\n</html>\n");
+
+    html
+}
+
+/// Guess the content type of a file from its extension.
+fn content_type(path: &Path) -> &'static str {
+    let extension = path.extension().map(|ext| ext.to_string_lossy().to_lowercase());
+    match extension.as_deref() {
+        Some("html" | "htm") => "text/html; charset=utf-8",
+        Some("txt" | "md") => "text/plain; charset=utf-8",
+        Some("css") => "text/css; charset=utf-8",
+        Some("js") => "text/javascript; charset=utf-8",
+        Some("json") => "application/json",
+        Some("pdf") => "application/pdf",
+        Some("png") => "image/png",
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("svg") => "image/svg+xml",
+        Some("webp") => "image/webp",
+        Some("mp3") => "audio/mpeg",
+        Some("ogg") => "audio/ogg",
+        Some("mp4") => "video/mp4",
+        Some("webm") => "video/webm",
+        Some("zip") => "application/zip",
+        Some("gz") => "application/gzip",
+        Some("tar") => "application/x-tar",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Decode a percent-encoded URL path. Returns `None` if it is not valid
+/// UTF-8 once decoded.
+fn percent_decode(s: &str) -> Option<String> {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = std::str::from_utf8(bytes.get(i + 1..i + 3)?).ok()?;
+            out.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(out).ok()
+}
+
+/// Percent-encode a URL path, keeping the `/` separators.
+fn percent_encode(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for byte in s.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                out.push(byte as char)
+            }
+            _ => out.push_str(&format!("%{byte:02X}")),
+        }
+    }
+    out
+}
+
+/// Escape text inserted in HTML.
+fn html_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range(value: &str, size: u64) -> Option<(u64, u64)> {
+        match parse_range(value, size) {
+            Ok(ByteRange::Full) => None,
+            Ok(ByteRange::Partial(start, end)) => Some((start, end)),
+            Err(_) => Some((u64::MAX, u64::MAX)),
+        }
+    }
+
+    #[test]
+    fn ranges_are_parsed() {
+        assert_eq!(range("bytes=0-9", 100), Some((0, 9)));
+        assert_eq!(range("bytes=90-200", 100), Some((90, 99)));
+        assert_eq!(range("bytes=10-", 100), Some((10, 99)));
+        assert_eq!(range("bytes=-10", 100), Some((90, 99)));
+        assert_eq!(range("bytes=-200", 100), Some((0, 99)));
+
+        // Ignored
+        assert_eq!(range("items=0-9", 100), None);
+        assert_eq!(range("bytes=0-9,20-29", 100), None);
+        assert_eq!(range("bytes=9-0", 100), None);
+        assert_eq!(range("bytes=a-b", 100), None);
+
+        // Not satisfiable
+        assert!(matches!(parse_range("bytes=100-", 100), Err(HttpError::Status(416, _))));
+        assert!(matches!(parse_range("bytes=-0", 100), Err(HttpError::Status(416, _))));
+        assert!(matches!(parse_range("bytes=0-", 0), Err(HttpError::Status(416, _))));
+    }
+
+    #[test]
+    fn paths_are_percent_decoded_and_encoded() {
+        assert_eq!(percent_decode("/fud/a%20b/%C3%A9").as_deref(), Some("/fud/a b/é"));
+        assert_eq!(percent_decode("/plain").as_deref(), Some("/plain"));
+        assert_eq!(percent_decode("/%2"), None);
+        assert_eq!(percent_decode("/%zz"), None);
+        assert_eq!(percent_decode("/%FF"), None);
+
+        let path = "dir/a b?#%é.txt";
+        assert_eq!(percent_encode(path), "dir/a%20b%3F%23%25%C3%A9.txt");
+        assert_eq!(percent_decode(&percent_encode(path)).as_deref(), Some(path));
+    }
+
+    #[test]
+    fn directory_listings_are_escaped() {
+        let files = vec![
+            ("a.txt".to_string(), 3),
+            ("sub/b.txt".to_string(), 5),
+            ("sub/deep/c.txt".to_string(), 7),
+            ("<x>.html".to_string(), 1),
+        ];
+
+        let root = directory_listing("/fud/h/", "", &files);
+        assert!(root.contains("<li><a href=\"a.txt\">a.txt</a> (3 bytes)</li>"));
+        assert!(root.contains("<li><a href=\"sub/\">sub/</a></li>"));
+        assert!(root.contains("<li><a href=\"%3Cx%3E.html\">&lt;x&gt;.html</a> (1 bytes)</li>"));
+        assert!(!root.contains("b.txt"));
+        assert!(!root.contains("../"));
+
+        let sub = directory_listing("/fud/h/sub/", "sub/", &files);
+        assert!(sub.contains("<li><a href=\"../\">../</a></li>"));
+        assert!(sub.contains("<li><a href=\"b.txt\">b.txt</a> (5 bytes)</li>"));
+        assert!(sub.contains("<li><a href=\"deep/\">deep/</a></li>"));
+        assert!(!sub.contains("a.txt"));
+
+        let title = directory_listing("/fud/h/<script>/", "<script>/", &[]);
+        assert!(title.contains("<title>/fud/h/&lt;script&gt;/</title>"));
+    }
+}
@@ -32,15 +32,16 @@ use smol::{
 use tracing::{error, info, warn};
 
 use darkfi::{
-    dht::{tasks as dht_tasks, Dht, DhtHandler, DhtSettings},
-    geode::{hash_to_string, Chunk, ChunkedStorage, FileSequence, Geode, MAX_CHUNK_SIZE},
+    dht::{tasks as dht_tasks, Dht, DhtHandler, DhtRecord, DhtSettings},
+    geode::{hash_to_string, Chunk, ChunkedStorage, FileSequence, Geode},
     net::P2pPtr,
     system::{ExecutorPtr, PublisherPtr, StoppableTask},
     util::{path::expand_path, time::Timestamp},
     Error, Result,
 };
-use darkfi_sdk::crypto::{schnorr::SchnorrSecret, SecretKey};
-use darkfi_serial::{deserialize_async, serialize_async};
+use darkfi_sdk::crypto::{schnorr::SchnorrSecret, PublicKey, SecretKey};
+use darkfi_serial::{deserialize, deserialize_async, serialize, serialize_async};
+use rand::rngs::OsRng;
 
 /// P2P protocols
 pub mod proto;
@@ -50,6 +51,10 @@ use proto::FudAnnounce;
 pub mod event;
 use event::{notify_event, FudEvent};
 
+/// Chunk availability bitfields
+pub mod bitfield;
+use bitfield::ChunkBitfield;
+
 /// Resource definition
 pub mod resource;
 use resource::{Resource, ResourceStatus, ResourceType};
@@ -58,9 +63,20 @@ use resource::{Resource, ResourceStatus, ResourceType};
 pub mod scrap;
 use scrap::Scrap;
 
+/// Encrypted resources
+pub mod encryption;
+use encryption::ResourceKey;
+
+/// Mutable pointers to resources
+pub mod pointer;
+use pointer::{next_sequence, validate_pointer_name, FollowedPointer, FudPointer};
+
 /// JSON-RPC related methods
 pub mod rpc;
 
+/// HTTP gateway
+pub mod gateway;
+
 /// Background tasks
 pub mod tasks;
 use tasks::start_task;
@@ -96,6 +112,11 @@ use crate::{dht::FudNode, pow::PowSettings};
 const SLED_PATH_TREE: &[u8] = b"_fud_paths";
 const SLED_FILE_SELECTION_TREE: &[u8] = b"_fud_file_selections";
 const SLED_SCRAP_TREE: &[u8] = b"_fud_scraps";
+const SLED_DOWNLOAD_TREE: &[u8] = b"_fud_downloads";
+const SLED_PUBLISHER_TREE: &[u8] = b"_fud_publisher";
+const SLED_PUBLISHED_TREE: &[u8] = b"_fud_published";
+const SLED_FOLLOWED_TREE: &[u8] = b"_fud_followed";
+const SLED_DECRYPTION_TREE: &[u8] = b"_fud_decryptions";
 
 #[derive(Clone, Debug)]
 pub struct FudState {
@@ -111,6 +132,9 @@ pub struct Fud {
     geode: Geode,
     /// Default download directory
     downloads_path: PathBuf,
+    /// Directory containing the ciphertext of the encrypted resources we
+    /// put or download
+    encrypted_path: PathBuf,
     /// Chunk transfer timeout in seconds
     chunk_timeout: u64,
     /// The [`FudPow`] instance
@@ -134,14 +158,32 @@ pub struct Fud {
     /// is not saved to the filesystem in the downloaded files.
     /// "chunk/scrap hash -> chunk content"
     scrap_tree: sled::Tree,
+    /// Sled tree containing the downloads that are not completed yet, used
+    /// to resume them when fud restarts. The chunks we have are found again
+    /// by verifying the files and scraps.
+    /// "resource hash -> (empty)"
+    download_tree: sled::Tree,
+    /// Sled tree containing our publisher secret key, used to sign the
+    /// pointers we publish
+    publisher_tree: sled::Tree,
+    /// Sled tree containing the pointers we published, to republish them.
+    /// "pointer name -> latest DHT record"
+    published_tree: sled::Tree,
+    /// Sled tree containing the pointers we follow.
+    /// "pointer key -> followed pointer"
+    followed_tree: sled::Tree,
+    /// Sled tree containing the encrypted resources we are downloading, to
+    /// decrypt them once the download is completed.
+    /// "resource hash -> (key, decrypted file path)"
+    decryption_tree: sled::Tree,
     /// Get requests sender
     get_tx: channel::Sender<(blake3::Hash, PathBuf, FileSelection)>,
     /// Get requests receiver
     get_rx: channel::Receiver<(blake3::Hash, PathBuf, FileSelection)>,
-    /// Put requests sender
-    put_tx: channel::Sender<PathBuf>,
+    /// Put requests sender, with whether to use content-defined chunking
+    put_tx: channel::Sender<(PathBuf, bool)>,
     /// Put requests receiver
-    put_rx: channel::Receiver<PathBuf>,
+    put_rx: channel::Receiver<(PathBuf, bool)>,
     /// Lookup requests sender
     lookup_tx: channel::Sender<blake3::Hash>,
     /// Lookup requests receiver
@@ -209,12 +251,18 @@ impl Fud {
             state: Arc::new(RwLock::new(None)),
             geode,
             downloads_path,
+            encrypted_path: basedir.join("encrypted"),
             chunk_timeout: settings.chunk_timeout,
             pow: Arc::new(RwLock::new(pow)),
             dht: dht.clone(),
             path_tree: sled_db.open_tree(SLED_PATH_TREE)?,
             file_selection_tree: sled_db.open_tree(SLED_FILE_SELECTION_TREE)?,
             scrap_tree: sled_db.open_tree(SLED_SCRAP_TREE)?,
+            download_tree: sled_db.open_tree(SLED_DOWNLOAD_TREE)?,
+            publisher_tree: sled_db.open_tree(SLED_PUBLISHER_TREE)?,
+            published_tree: sled_db.open_tree(SLED_PUBLISHED_TREE)?,
+            followed_tree: sled_db.open_tree(SLED_FOLLOWED_TREE)?,
+            decryption_tree: sled_db.open_tree(SLED_DECRYPTION_TREE)?,
             resources: Arc::new(RwLock::new(HashMap::new())),
             chunked_storages: Arc::new(RwLock::new(HashMap::new())),
             get_tx,
@@ -266,6 +314,7 @@ impl Fud {
         start_task!(self, "DHT cleanup channels", dht_tasks::cleanup_channels_task::<Fud>, tasks);
         start_task!(self, "DHT add node", dht_tasks::add_node_task::<Fud>, tasks);
         start_task!(self, "DHT refinery", dht_tasks::dht_refinery_task::<Fud>, tasks);
+        start_task!(self, "DHT records", dht_tasks::records_task::<Fud>, tasks);
         start_task!(
             self,
             "DHT disconnect inbounds",
@@ -276,6 +325,7 @@ impl Fud {
         start_task!(self, "verify node", tasks::verify_node_task, tasks);
         start_task!(self, "announce", tasks::announce_seed_task, tasks);
         start_task!(self, "node ID", tasks::node_id_task, tasks);
+        start_task!(self, "follow", tasks::follow_task, tasks);
     }
 
     /// Verify our resources, add ourselves to the seeders (`dht.hash_table`)
@@ -341,6 +391,36 @@ impl Fud {
         info!(target: "fud::init()", "Verifying resources...");
         let resources = self.verify_resources(None).await?;
 
+        // Resume the downloads that were interrupted
+        let resources_read = self.resources.read().await;
+        let mut interrupted_downloads = vec![];
+        for result in self.download_tree.iter() {
+            let Ok((hash, _)) = result else { continue };
+            let hash_bytes: [u8; 32] = match hash.to_vec().try_into() {
+                Ok(v) => v,
+                Err(_) => continue,
+            };
+            let hash = blake3::Hash::from_bytes(hash_bytes);
+            match resources_read.get(&hash) {
+                Some(resource) => interrupted_downloads.push((
+                    hash,
+                    resource.path.clone(),
+                    resource.file_selection.clone(),
+                )),
+                None => {
+                    let _ = self.download_tree.remove(hash.as_bytes());
+                }
+            }
+        }
+        drop(resources_read);
+        for (hash, path, file_selection) in interrupted_downloads {
+            info!(target: "fud::init()", "Resuming download of {}", hash_to_string(&hash));
+            let _ = self.get(&hash, &path, file_selection).await;
+        }
+
+        info!(target: "fud::init()", "Publishing pointers...");
+        self.republish_pointers().await;
+
         let self_node = self.node().await?;
 
         // Stop here if we have no external address
@@ -358,22 +438,35 @@ impl Fud {
 
         info!(target: "fud::init()", "Announcing resources...");
         for resource in resources {
-            if let Ok(seeder) = self.new_seeder(&resource.hash).await {
-                let seeders = vec![seeder];
-                let _ = self
-                    .dht
-                    .announce(
-                        &resource.hash,
-                        &seeders.clone(),
-                        &FudAnnounce { key: resource.hash, seeders },
-                    )
-                    .await;
-            }
+            self.announce_seeder(&resource.hash).await;
         }
 
         Ok(())
     }
 
+    /// Announce our own node as a seeder of a resource to the DHT, with the
+    /// chunks we have if the resource is incomplete.
+    pub async fn announce_seeder(&self, hash: &blake3::Hash) {
+        let seeder = match self.new_seeder(hash).await {
+            Ok(seeder) => seeder,
+            Err(_) => return,
+        };
+
+        let seeders = vec![seeder.clone()];
+        let announce = FudAnnounce { key: *hash, seeders: seeders.clone() };
+        let _ = self.dht.announce(hash, &seeders, &announce).await;
+
+        // Keep track of the number of chunks other nodes know we have
+        let mut resources_write = self.resources.write().await;
+        if let Some(resource) = resources_write.get_mut(hash) {
+            resource.seeding_chunks_count = match seeder.chunks.is_empty() {
+                true => resource.total_chunks_count,
+                false => seeder.chunks.count() as u64,
+            };
+            notify_event!(self, ResourceUpdated, resource);
+        }
+    }
+
     /// Get a copy of the current resources
     pub async fn resources(&self) -> HashMap<blake3::Hash, Resource> {
         let resources = self.resources.read().await;
@@ -413,7 +506,9 @@ impl Fud {
         Ok(None)
     }
 
-    /// Create a new [`dht::FudSeeder`] for own node
+    /// Create a new [`dht::FudSeeder`] for own node.
+    /// If we only have some chunks of the resource, the seeder contains the
+    /// bitfield of the chunks we have.
     pub async fn new_seeder(&self, key: &blake3::Hash) -> Result<FudSeeder> {
         let state = self.state.read().await;
         if state.is_none() {
@@ -423,12 +518,25 @@ impl Fud {
         drop(state);
         let node = self.node().await?;
 
+        let chunked_storages = self.chunked_storages.read().await;
+        let chunks = match chunked_storages.get(key) {
+            Some(chunked) if !chunked.is_complete() => ChunkBitfield::from(chunked),
+            _ => ChunkBitfield::default(),
+        };
+        drop(chunked_storages);
+
         Ok(FudSeeder {
             key: *key,
             node: node.clone(),
-            sig: state_
-                .secret_key
-                .sign(&[key.as_bytes().to_vec(), serialize_async(&node).await].concat()),
+            sig: state_.secret_key.sign(
+                &[
+                    key.as_bytes().to_vec(),
+                    serialize_async(&node).await,
+                    serialize_async(&chunks).await,
+                ]
+                .concat(),
+            ),
+            chunks,
             timestamp: Timestamp::current_time().inner(),
         })
     }
@@ -438,7 +546,8 @@ impl Fud {
     /// If a resource is complete, its status is changed to Seeding.
     /// Takes an optional list of resource hashes.
     /// If no hash is given (None), it verifies all resources.
-    /// Returns the list of verified and uncorrupted/complete seeding resources.
+    /// Returns the list of verified resources we can seed: complete ones,
+    /// and incomplete ones we have at least one chunk of.
     pub async fn verify_resources(
         &self,
         hashes: Option<Vec<blake3::Hash>>,
@@ -557,6 +666,10 @@ impl Fud {
                     target_bytes_downloaded,
                 )
                 .await;
+                // We can still seed the chunks we have
+                if chunked.local_chunks() > 0 {
+                    seeding_resources.push(resource.clone());
+                }
                 continue;
             }
 
@@ -713,6 +826,9 @@ impl Fud {
         // Add path to the sled db
         self.path_tree.insert(hash_bytes, path_bytes)?;
 
+        // Mark the download as not completed in the sled db
+        self.download_tree.insert(hash_bytes, &[])?;
+
         let mut resources_write = self.resources.write().await;
         let merged_files = if let Some(old_resource) = resources_write.get(hash) {
             old_resource.file_selection.merge(files)
@@ -811,7 +927,15 @@ impl Fud {
         }
 
         // Mark locally available chunks as such
-        let verify_res = self.verify_chunks(&resource, &mut chunked, files).await;
+        let mut verify_res = self.verify_chunks(&resource, &mut chunked, files).await;
+
+        // Copy the chunks we are missing but have in other resources
+        if let Ok((total_bytes_downloaded, target_bytes_downloaded)) = &mut verify_res {
+            let (total_bytes_copied, target_bytes_copied) =
+                self.reuse_local_chunks(hash, &resource, &mut chunked, &chunk_hashes, files).await;
+            *total_bytes_downloaded += total_bytes_copied;
+            *target_bytes_downloaded += target_bytes_copied;
+        }
 
         // Insert the chunked storage to fud's cache
         let mut chunked_storages = self.chunked_storages.write().await;
@@ -825,6 +949,11 @@ impl Fud {
         }
         let (total_bytes_downloaded, target_bytes_downloaded) = verify_res.unwrap();
 
+        // Seed the chunks we already have while downloading the others
+        if chunked.local_chunks() > 0 && !chunked.is_complete() {
+            self.announce_seeder(hash).await;
+        }
+
         // Update `total_bytes_size` if the resource is a file
         if let ResourceType::File = resource.rtype {
             update_resource!(hash, { total_bytes_size = chunked.get_fileseq().len() });
@@ -841,9 +970,9 @@ impl Fud {
                 dht_sub.unsubscribe().await;
                 return Err(e.into());
             }
-            if fs_metadata.unwrap().len() > (chunked.len() * MAX_CHUNK_SIZE) as u64 {
+            if fs_metadata.unwrap().len() > chunked.get_max_size() {
                 if let Ok(file) = OpenOptions::new().write(true).create(true).open(path).await {
-                    let _ = file.set_len((chunked.len() * MAX_CHUNK_SIZE) as u64).await;
+                    let _ = file.set_len(chunked.get_max_size()).await;
                 }
             }
         }
@@ -879,15 +1008,28 @@ impl Fud {
                 total_chunks_downloaded = chunked.local_chunks() as u64,
             });
 
-            // Announce the resource if we have all chunks
-            if chunked.is_complete() {
-                if let Ok(seeder) = self.new_seeder(hash).await {
-                    let seeders = vec![seeder];
-                    let self_announce = FudAnnounce { key: *hash, seeders: seeders.clone() };
-                    let _ = self.dht.announce(hash, &seeders, &self_announce).await;
+            // The download does not need to be resumed anymore
+            let _ = self.download_tree.remove(hash_bytes);
+
+            // Decrypt the resource if it is an encrypted resource, its
+            // ciphertext is removed once decrypted so we don't announce it
+            let encrypted = self.decryption_tree.contains_key(hash_bytes)?;
+            if chunked.is_complete() && encrypted {
+                if let Err(e) = self.decrypt_resource(hash, path).await {
+                    error!(target: "fud::fetch_resource()", "Error while decrypting resource: {e}");
+                    notify_event!(self, DownloadError, {
+                        hash: *hash,
+                        error: format!("Decryption failed: {e}"),
+                    });
+                    return Ok(())
                 }
             }
 
+            // Announce the resource if we have all chunks
+            if chunked.is_complete() && !encrypted {
+                self.announce_seeder(hash).await;
+            }
+
             // Send a DownloadCompleted event
             notify_event!(self, DownloadCompleted, resource);
 
@@ -952,6 +1094,122 @@ impl Fud {
         download_completed(&chunked).await
     }
 
+    /// Get the content of a chunk from the scraps, if we have it.
+    async fn get_scrap(&self, chunk_hash: &blake3::Hash) -> Option<Vec<u8>> {
+        let scrap = self.scrap_tree.get(chunk_hash.as_bytes()).ok()??;
+        let scrap: Scrap = deserialize_async(&scrap).await.ok()?;
+        if blake3::hash(&scrap.chunk) != *chunk_hash {
+            return None
+        }
+        Some(scrap.chunk)
+    }
+
+    /// Save a chunk that was not entirely written to the filesystem as a
+    /// scrap.
+    async fn save_scrap(
+        &self,
+        chunked: &mut ChunkedStorage,
+        chunk_hash: &blake3::Hash,
+        chunk: &[u8],
+    ) -> Result<()> {
+        let chunk_written = self.geode.get_chunk(chunked, chunk_hash).await?;
+        let scrap = Scrap { chunk: chunk.to_vec(), hash_written: blake3::hash(&chunk_written) };
+        self.scrap_tree.insert(chunk_hash.as_bytes(), serialize_async(&scrap).await)?;
+        Ok(())
+    }
+
+    /// Copy the missing chunks of `chunked` that are in `chunk_hashes` from
+    /// the other local resources that have them, for example an older
+    /// version of the same file.
+    /// Return the size in bytes of the copied data (copied and
+    /// copied+targeted).
+    async fn reuse_local_chunks(
+        &self,
+        hash: &blake3::Hash,
+        resource: &Resource,
+        chunked: &mut ChunkedStorage,
+        chunk_hashes: &HashSet<blake3::Hash>,
+        file_selection: &FileSelection,
+    ) -> (u64, u64) {
+        let missing_chunks: HashSet<blake3::Hash> = chunked
+            .iter()
+            .filter(|c| !c.available && chunk_hashes.contains(&c.hash))
+            .map(|c| c.hash)
+            .collect();
+        if missing_chunks.is_empty() {
+            return (0, 0)
+        }
+
+        // Find the other resources that have some of the missing chunks
+        let chunked_storages = self.chunked_storages.read().await;
+        let sources: Vec<ChunkedStorage> = chunked_storages
+            .iter()
+            .filter(|(other_hash, _)| *other_hash != hash)
+            .filter(|(_, other)| {
+                other.iter().any(|c| c.available && missing_chunks.contains(&c.hash))
+            })
+            .map(|(_, other)| other.clone())
+            .collect();
+        drop(chunked_storages);
+
+        let mut chunks_count = 0;
+        let mut bytes = (0, 0);
+        for mut source in sources {
+            let source_chunks: HashSet<blake3::Hash> = source
+                .iter()
+                .filter(|c| c.available && missing_chunks.contains(&c.hash))
+                .map(|c| c.hash)
+                .collect();
+
+            for chunk_hash in source_chunks {
+                // Skip the chunks we already copied from another resource
+                let chunk_indexes = chunked.get_chunk_indexes(&chunk_hash);
+                if chunk_indexes.iter().all(|i| chunked.get_chunks()[*i].available) {
+                    continue
+                }
+
+                let chunk = match self.geode.get_chunk(&mut source, &chunk_hash).await {
+                    Ok(chunk) if self.geode.verify_chunk(&chunk_hash, &chunk) => Some(chunk),
+                    _ => self.get_scrap(&chunk_hash).await,
+                };
+                let Some(chunk) = chunk else { continue };
+
+                let bytes_written = match self.geode.write_chunk(chunked, &chunk).await {
+                    Ok((_, bytes_written)) => bytes_written,
+                    Err(e) => {
+                        error!(target: "fud::reuse_local_chunks()", "Failed copying chunk {}: {e}", hash_to_string(&chunk_hash));
+                        continue
+                    }
+                };
+                if bytes_written < chunk.len() {
+                    if let Err(e) = self.save_scrap(chunked, &chunk_hash, &chunk).await {
+                        error!(target: "fud::reuse_local_chunks()", "Failed to save chunk {} as a scrap: {e}", hash_to_string(&chunk_hash));
+                        continue
+                    }
+                }
+
+                for chunk_index in chunk_indexes {
+                    chunked.get_chunk_mut(chunk_index).available = true;
+                    chunked.get_chunk_mut(chunk_index).size = chunk.len();
+                }
+                chunks_count += 1;
+                bytes.0 += chunk.len() as u64;
+                bytes.1 += resource.get_bytes_of_selection(
+                    chunked,
+                    file_selection,
+                    &chunk_hash,
+                    chunk.len(),
+                ) as u64;
+            }
+        }
+
+        if chunks_count > 0 {
+            info!(target: "fud::reuse_local_chunks()", "Copied {chunks_count} chunks of {} from other local resources", hash_to_string(hash));
+        }
+
+        bytes
+    }
+
     async fn write_scraps(
         &self,
         chunked: &mut ChunkedStorage,
@@ -1028,15 +1286,14 @@ impl Fud {
         // Gather all available chunks
         for (chunk_index, chunk) in chunks.iter().enumerate() {
             // Read the chunk using the `FileSequence`
-            let chunk_data =
-                match self.geode.read_chunk(&mut chunked.get_fileseq_mut(), &chunk_index).await {
-                    Ok(c) => c,
-                    Err(Error::Io(ErrorKind::NotFound)) => continue,
-                    Err(e) => {
-                        warn!(target: "fud::verify_chunks()", "Error while verifying chunks: {e}");
-                        break
-                    }
-                };
+            let chunk_data = match self.geode.read_chunk(chunked, &chunk_index).await {
+                Ok(c) => c,
+                Err(Error::Io(ErrorKind::NotFound)) => continue,
+                Err(e) => {
+                    warn!(target: "fud::verify_chunks()", "Error while verifying chunks: {e}");
+                    break
+                }
+            };
 
             // Perform chunk consistency check
             if self.geode.verify_chunk(&chunk.hash, &chunk_data) {
@@ -1082,8 +1339,7 @@ impl Fud {
             }
 
             // Check if the scrap is still written on the filesystem
-            let scrap_chunk =
-                self.geode.read_chunk(&mut chunked.get_fileseq_mut(), &chunk_index).await;
+            let scrap_chunk = self.geode.read_chunk(chunked, &chunk_index).await;
             if scrap_chunk.is_err() {
                 continue;
             }
@@ -1117,13 +1373,13 @@ impl Fud {
         // exact file size if we know the last chunk's size. This is not
         // needed for directories.
         let is_dir = chunked.is_dir();
+        let last_chunk_position = chunked.get_chunk_position(chunked.len().saturating_sub(1));
         if let Some(last_chunk) = chunked.iter_mut().last() {
             if !is_dir && last_chunk.available {
                 if let Some((last_chunk_size, _)) = bytes.get(&last_chunk.hash) {
                     last_chunk.size = *last_chunk_size;
-                    let exact_file_size =
-                        chunked.len() * MAX_CHUNK_SIZE - (MAX_CHUNK_SIZE - last_chunk_size);
-                    chunked.get_fileseq_mut().set_file_size(0, exact_file_size as u64);
+                    let exact_file_size = last_chunk_position + *last_chunk_size as u64;
+                    chunked.get_fileseq_mut().set_file_size(0, exact_file_size);
                 }
             }
         }
@@ -1135,18 +1391,76 @@ impl Fud {
     }
 
     /// Add a resource from the file system.
-    pub async fn put(&self, path: &Path) -> Result<()> {
+    /// With `content_defined` set, the resource is split using
+    /// content-defined chunking, which gives it another hash than the
+    /// same content split in fixed-size chunks.
+    pub async fn put(&self, path: &Path, content_defined: bool) -> Result<()> {
         let put_tasks = self.put_tasks.read().await;
         drop(put_tasks);
 
-        self.put_tx.send(path.to_path_buf()).await?;
+        self.put_tx.send((path.to_path_buf(), content_defined)).await?;
+
+        Ok(())
+    }
+
+    /// Encrypt a file with a new random key and add the ciphertext as a
+    /// resource.
+    /// Returns the path of the ciphertext (the path of the resource we
+    /// insert) and the key.
+    pub async fn put_encrypted(&self, path: &Path) -> Result<(PathBuf, ResourceKey)> {
+        if !fs::metadata(path).await?.is_file() {
+            return Err(Error::Custom("Only files can be encrypted".to_string()))
+        }
+
+        let key = ResourceKey::random();
+        fs::create_dir_all(&self.encrypted_path).await?;
+        let encrypted_path =
+            self.encrypted_path.join(hash_to_string(&blake3::hash(key.to_string().as_bytes())));
+        encryption::encrypt_file(&key, path, &encrypted_path).await?;
+
+        self.put(&encrypted_path, false).await?;
+
+        Ok((encrypted_path, key))
+    }
+
+    /// Start downloading an encrypted resource, it will be decrypted to
+    /// `path` once the download is completed (decryption is not streamed,
+    /// `path` only exists once the whole ciphertext is downloaded).
+    /// The ciphertext is downloaded to `<encrypted path>/<hash>`, and
+    /// removed once decrypted.
+    pub async fn get_encrypted(
+        &self,
+        hash: &blake3::Hash,
+        key: &ResourceKey,
+        path: &Path,
+    ) -> Result<()> {
+        let decryption = (*key, path.to_string_lossy().to_string());
+        self.decryption_tree.insert(hash.as_bytes(), serialize_async(&decryption).await)?;
+
+        let encrypted_path = self.encrypted_path.join(hash_to_string(hash));
+        self.get(hash, &encrypted_path, FileSelection::All).await
+    }
+
+    /// Decrypt the downloaded resource `hash` (at `path`) if we downloaded
+    /// it with [`Fud::get_encrypted()`], then remove the ciphertext and
+    /// stop seeding it.
+    async fn decrypt_resource(&self, hash: &blake3::Hash, path: &Path) -> Result<()> {
+        let Some(bytes) = self.decryption_tree.get(hash.as_bytes())? else { return Ok(()) };
+        let (key, dst): (ResourceKey, String) = deserialize_async(&bytes).await?;
+
+        info!(target: "fud::decrypt_resource()", "Decrypting {} to {dst}", hash_to_string(hash));
+        encryption::decrypt_file(&key, path, Path::new(&dst)).await?;
+
+        // Also removes the pending decryption
+        self.remove(hash).await;
+        fs::remove_file(path).await?;
 
         Ok(())
     }
 
     /// Insert a file or directory from the file system.
     /// Called when `put()` creates a new put task.
-    pub async fn insert_resource(&self, path: &PathBuf) -> Result<()> {
+    pub async fn insert_resource(&self, path: &PathBuf, content_defined: bool) -> Result<()> {
         let self_node = self.node().await?;
 
         if self_node.addresses.is_empty() {
@@ -1171,7 +1485,13 @@ impl Fud {
         // Read the file or directory and create the chunks
         let stream = FileSequence::new(&files, false);
         let total_size = stream.len();
-        let (mut hasher, chunk_hashes) = self.geode.chunk_stream(stream).await?;
+        let (mut hasher, chunk_hashes, chunk_sizes) = match content_defined {
+            true => self.geode.chunk_stream_cdc(stream).await?,
+            false => {
+                let (hasher, chunk_hashes) = self.geode.chunk_stream(stream).await?;
+                (hasher, chunk_hashes, vec![])
+            }
+        };
 
         // Get the relative file paths included in the metadata and hash of directories
         let relative_files = if let ResourceType::Directory = resource_type {
@@ -1196,7 +1516,9 @@ impl Fud {
         let hash = hasher.finalize();
 
         // Create the metadata file in geode
-        if let Err(e) = self.geode.insert_metadata(&hash, &chunk_hashes, &relative_files).await {
+        if let Err(e) =
+            self.geode.insert_metadata(&hash, &chunk_hashes, &chunk_sizes, &relative_files).await
+        {
             error!(target: "fud::put()", "Failed inserting {path:?} to geode: {e}");
             return Err(e)
         }
@@ -1228,17 +1550,14 @@ impl Fud {
                 target_bytes_size: total_size,
                 total_bytes_downloaded: total_size,
                 target_bytes_downloaded: total_size,
+                seeding_chunks_count: 0,
                 speeds: vec![],
             },
         );
         drop(resources_write);
 
         // Announce the new resource
-        if let Ok(seeder) = self.new_seeder(&hash).await {
-            let seeders = vec![seeder];
-            let fud_announce = FudAnnounce { key: hash, seeders: seeders.clone() };
-            let _ = self.dht.announce(&hash, &seeders, &fud_announce).await;
-        }
+        self.announce_seeder(&hash).await;
 
         // Send InsertCompleted event
         notify_event!(self, InsertCompleted, {
@@ -1254,6 +1573,8 @@ impl Fud {
     /// - its metadata in geode
     /// - its path in the sled path tree
     /// - its file selection in the sled file selection tree
+    /// - its download state in the sled download tree
+    /// - its pending decryption in the sled decryption tree
     /// - and any related scrap in the sled scrap tree,
     ///
     /// then sends a `ResourceRemoved` fud event.
@@ -1285,6 +1606,12 @@ impl Fud {
         // Remove the file selection in sled
         let _ = self.file_selection_tree.remove(hash.as_bytes());
 
+        // Remove the download state in sled
+        let _ = self.download_tree.remove(hash.as_bytes());
+
+        // Remove the pending decryption in sled
+        let _ = self.decryption_tree.remove(hash.as_bytes());
+
         // Send a `ResourceRemoved` event
         notify_event!(self, ResourceRemoved, { hash: *hash });
     }
@@ -1305,6 +1632,158 @@ impl Fud {
         }
     }
 
+    /// Get our publisher secret key, generate it if we don't have one yet.
+    fn publisher_secret_key(&self) -> Result<SecretKey> {
+        if let Some(bytes) = self.publisher_tree.get(b"secret_key")? {
+            return Ok(deserialize(&bytes)?)
+        }
+
+        let secret_key = SecretKey::random(&mut OsRng);
+        self.publisher_tree.insert(b"secret_key", serialize(&secret_key))?;
+        Ok(secret_key)
+    }
+
+    /// Get our publisher public key.
+    pub fn publisher(&self) -> Result<PublicKey> {
+        Ok(PublicKey::from_secret(self.publisher_secret_key()?))
+    }
+
+    /// Point our pointer `name` to `resource`, and announce the new record
+    /// to the DHT.
+    pub async fn publish(&self, name: &str, resource: &blake3::Hash) -> Result<FudPointer> {
+        validate_pointer_name(name)?;
+        let secret_key = self.publisher_secret_key()?;
+
+        // Without a record of the pointer (it is new, or our db was lost),
+        // the network may know a more recent one
+        let published = match self.published_tree.get(name.as_bytes())? {
+            Some(bytes) => Some(deserialize_async::<DhtRecord>(&bytes).await?.sequence),
+            None => None,
+        };
+        let network = match published {
+            Some(_) => None,
+            None => self.resolve(&self.publisher()?, name).await.ok().map(|p| p.sequence),
+        };
+        let sequence = next_sequence(published, network);
+
+        let pointer = FudPointer::new(&secret_key, name, sequence, resource);
+        self.published_tree.insert(name.as_bytes(), serialize_async(&pointer.record).await)?;
+        info!(target: "fud::publish()", "Published {} -> {}", pointer.address(), hash_to_string(resource));
+
+        self.dht.put_record(pointer.record.clone()).await?;
+
+        Ok(pointer)
+    }
+
+    /// Find the latest record of the pointer `name` of `publisher`, by
+    /// asking the nodes closest to the pointer key.
+    pub async fn resolve(&self, publisher: &PublicKey, name: &str) -> Result<FudPointer> {
+        validate_pointer_name(name)?;
+        let key = pointer::pointer_key(publisher, name);
+
+        let latest = self
+            .dht
+            .get_records(&key)
+            .await
+            .iter()
+            .filter_map(|record| FudPointer::try_from(record).ok())
+            .filter(|pointer| pointer.publisher == *publisher && pointer.name == name)
+            .max_by_key(|pointer| pointer.sequence);
+
+        latest.ok_or_else(|| Error::Custom(format!("Pointer {publisher}/{name} not found")))
+    }
+
+    /// Follow the pointer `name` of `publisher`: each time it points to a
+    /// new resource, the resource is downloaded to `<path>/<resource hash>`.
+    /// `path` defaults to `<downloads path>/<name>`.
+    pub async fn follow(
+        &self,
+        publisher: &PublicKey,
+        name: &str,
+        path: Option<PathBuf>,
+    ) -> Result<FollowedPointer> {
+        validate_pointer_name(name)?;
+        let path = match path {
+            Some(path) => path,
+            None => self.downloads_path.join(name),
+        };
+
+        let mut followed = FollowedPointer {
+            publisher: *publisher,
+            name: name.to_string(),
+            path: path.to_string_lossy().to_string(),
+            sequence: 0,
+            resource: None,
+        };
+        if let Some(bytes) = self.followed_tree.get(followed.key().as_bytes())? {
+            let existing: FollowedPointer = deserialize_async(&bytes).await?;
+            followed.sequence = existing.sequence;
+            followed.resource = existing.resource;
+        }
+        self.followed_tree.insert(followed.key().as_bytes(), serialize_async(&followed).await)?;
+
+        if let Err(e) = self.update_followed_pointer(&mut followed).await {
+            warn!(target: "fud::follow()", "Could not resolve {publisher}/{name}: {e}");
+        }
+
+        Ok(followed)
+    }
+
+    /// Stop following the pointer `name` of `publisher`. Resources that were
+    /// already downloaded are kept.
+    pub fn unfollow(&self, publisher: &PublicKey, name: &str) -> Result<()> {
+        let key = pointer::pointer_key(publisher, name);
+        if self.followed_tree.remove(key.as_bytes())?.is_none() {
+            return Err(Error::Custom(format!("Not following {publisher}/{name}")))
+        }
+        Ok(())
+    }
+
+    /// Get the pointers we follow.
+    pub async fn followed_pointers(&self) -> Vec<FollowedPointer> {
+        let mut followed = vec![];
+        for (_, bytes) in self.followed_tree.iter().flatten() {
+            if let Ok(pointer) = deserialize_async(&bytes).await {
+                followed.push(pointer);
+            }
+        }
+        followed
+    }
+
+    /// Resolve a followed pointer and start downloading its resource if it
+    /// points to a new one.
+    pub async fn update_followed_pointer(&self, followed: &mut FollowedPointer) -> Result<()> {
+        let pointer = self.resolve(&followed.publisher, &followed.name).await?;
+        if pointer.sequence <= followed.sequence {
+            return Ok(())
+        }
+
+        info!(target: "fud::update_followed_pointer()", "Pointer {} updated to {}", pointer.address(), hash_to_string(&pointer.resource));
+        if followed.resource != Some(pointer.resource) {
+            let path = followed.resource_path(&pointer.resource);
+            self.get(&pointer.resource, &path, FileSelection::All).await?;
+        }
+
+        followed.sequence = pointer.sequence;
+        followed.resource = Some(pointer.resource);
+        self.followed_tree.insert(followed.key().as_bytes(), serialize_async(followed).await)?;
+
+        notify_event!(self, PointerUpdated, { hash: pointer.resource, pointer });
+
+        Ok(())
+    }
+
+    /// Publish the latest record of each pointer we published, the DHT
+    /// then republishes them until they are replaced.
+    pub async fn republish_pointers(&self) {
+        for (_, bytes) in self.published_tree.iter().flatten() {
+            let Ok(record) = deserialize_async::<DhtRecord>(&bytes).await else { continue };
+            if let Err(e) = self.dht.put_record(record).await {
+                warn!(target: "fud::republish_pointers()", "Could not republish a pointer: {e}");
+            }
+        }
+    }
+
     /// Stop all tasks.
     pub async fn stop(&self) {
         info!("Stopping fetch tasks...");
@@ -38,6 +38,7 @@ use darkfi::{
     Error, Result,
 };
 use fud::{
+    gateway::{FudGateway, GatewaySettings},
     proto::ProtocolFud,
     rpc::{management::ManagementRpcInterface, DefaultRpcInterface},
     settings::{parse_management_rpc, Args, CONFIG_FILE, CONFIG_FILE_CONTENTS},
@@ -159,6 +160,25 @@ async fn realmain(args: Args, ex: Arc<Executor<'static>>) -> Result<()> {
         );
     }
 
+    let mut gateway_task = None;
+    let gateway_settings: GatewaySettings = args.gateway.try_into()?;
+    if let Some(gateway_listen) = gateway_settings.listen.clone() {
+        info!(target: "fud", "Starting HTTP gateway on {gateway_listen}");
+        let gateway = FudGateway::new(fud.clone(), gateway_settings);
+        gateway_task = Some(StoppableTask::new());
+        gateway_task.as_ref().unwrap().clone().start(
+            gateway.listen_and_serve(gateway_listen, ex.clone()),
+            |res| async move {
+                match res {
+                    Ok(()) | Err(Error::DetachedTaskStopped) => { /* Do nothing */ }
+                    Err(e) => error!(target: "fud", "Failed starting HTTP gateway: {e}"),
+                }
+            },
+            Error::DetachedTaskStopped,
+            ex.clone(),
+        );
+    }
+
     if let Err(e) = fud.start().await {
         panic!("Error while starting fud: {e}");
     }
@@ -198,6 +218,11 @@ async fn realmain(args: Args, ex: Arc<Executor<'static>>) -> Result<()> {
         management_rpc_task.stop().await;
     }
 
+    if let Some(gateway_task) = gateway_task {
+        info!(target: "fud", "Stopping HTTP gateway...");
+        gateway_task.stop().await;
+    }
+
     info!(target: "fud", "Stopping P2P network...");
     p2p.stop().await;
 
@@ -0,0 +1,261 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2026 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Mutable pointers to resources.
+//!
+//! A pointer is identified by its publisher's public key and a name, and
+//! points to a resource hash. Each time the publisher updates a pointer, it
+//! signs a new record with a higher sequence number, so that nodes can tell
+//! which record is the latest.
+//! Pointer records are [`DhtRecord`]s of the pointer key
+//! `blake3("fud_pointer" || publisher || name)`, whose value is the name
+//! and the resource, so they are stored, republished and expired like any
+//! other DHT record. Sequence numbers start from the current time, so that
+//! a publisher who lost its published records still supersedes them.
+//!
+//! Pointers are written as `<publisher public key>/<name>`.
+
+use std::{path::PathBuf, str::FromStr};
+
+use tinyjson::JsonValue;
+
+use darkfi::{
+    dht::DhtRecord, geode::hash_to_string, rpc::util::json_map, util::time::Timestamp, Error,
+    Result,
+};
+use darkfi_sdk::crypto::{PublicKey, SecretKey};
+use darkfi_serial::{deserialize, serialize, SerialDecodable, SerialEncodable};
+
+/// Maximum length in bytes of a pointer name
+pub const MAX_POINTER_NAME_LEN: usize = 255;
+
+/// TTL in seconds of pointer records, they are republished before they
+/// expire as long as their publisher is online
+pub const POINTER_TTL: u64 = 86400;
+
+/// Compute the DHT key of the pointer `name` of `publisher`.
+pub fn pointer_key(publisher: &PublicKey, name: &str) -> blake3::Hash {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(b"fud_pointer");
+    hasher.update(&publisher.to_bytes());
+    hasher.update(name.as_bytes());
+    hasher.finalize()
+}
+
+/// Parse a pointer written as `<publisher public key>/<name>`.
+pub fn parse_pointer(pointer: &str) -> Result<(PublicKey, String)> {
+    let Some((publisher, name)) = pointer.split_once('/') else {
+        return Err(Error::Custom(format!("Invalid pointer {pointer}, expected <publisher>/<name>")))
+    };
+    let publisher = PublicKey::from_str(publisher)
+        .map_err(|_| Error::Custom(format!("Invalid publisher public key {publisher}")))?;
+    validate_pointer_name(name)?;
+    Ok((publisher, name.to_string()))
+}
+
+/// Check that `name` can be used as a pointer name.
+pub fn validate_pointer_name(name: &str) -> Result<()> {
+    if name.is_empty() || name.len() > MAX_POINTER_NAME_LEN {
+        return Err(Error::Custom(format!(
+            "Pointer names must be between 1 and {MAX_POINTER_NAME_LEN} bytes long"
+        )))
+    }
+    Ok(())
+}
+
+/// Value of the [`DhtRecord`] of a pointer
+#[derive(SerialEncodable, SerialDecodable)]
+struct PointerValue {
+    name: String,
+    resource: blake3::Hash,
+}
+
+/// A pointer record, mapping (publisher, name) to a resource hash.
+#[derive(Debug, Clone)]
+pub struct FudPointer {
+    /// Public key of the publisher
+    pub publisher: PublicKey,
+    /// Name of the pointer, unique for a publisher
+    pub name: String,
+    /// Version of the record, the record with the highest sequence number
+    /// is the latest
+    pub sequence: u64,
+    /// Resource the pointer points to
+    pub resource: blake3::Hash,
+    /// The signed DHT record of the pointer
+    pub record: DhtRecord,
+}
+
+impl FudPointer {
+    /// Create and sign a new pointer record.
+    pub fn new(secret_key: &SecretKey, name: &str, sequence: u64, resource: &blake3::Hash) -> Self {
+        let publisher = PublicKey::from_secret(*secret_key);
+        let value = serialize(&PointerValue { name: name.to_string(), resource: *resource });
+        let record = DhtRecord::new(
+            secret_key,
+            &pointer_key(&publisher, name),
+            sequence,
+            POINTER_TTL,
+            value,
+        );
+        Self { publisher, name: name.to_string(), sequence, resource: *resource, record }
+    }
+
+    /// Return the DHT key of the pointer.
+    pub fn key(&self) -> blake3::Hash {
+        pointer_key(&self.publisher, &self.name)
+    }
+
+    /// Return the pointer as `<publisher public key>/<name>`.
+    pub fn address(&self) -> String {
+        format!("{}/{}", self.publisher, self.name)
+    }
+}
+
+impl TryFrom<&DhtRecord> for FudPointer {
+    type Error = Error;
+
+    /// Parse a pointer record, checking that it is signed by the publisher
+    /// and stored under the key of the pointer.
+    fn try_from(record: &DhtRecord) -> Result<Self> {
+        let value: PointerValue = deserialize(&record.value)?;
+        validate_pointer_name(&value.name)?;
+        if record.key != pointer_key(&record.publisher, &value.name) || !record.verify() {
+            return Err(Error::Custom("Invalid pointer record".to_string()))
+        }
+
+        Ok(Self {
+            publisher: record.publisher,
+            name: value.name,
+            sequence: record.sequence,
+            resource: value.resource,
+            record: record.clone(),
+        })
+    }
+}
+
+impl From<FudPointer> for JsonValue {
+    fn from(pointer: FudPointer) -> JsonValue {
+        json_map([
+            ("address", JsonValue::String(pointer.address())),
+            ("publisher", JsonValue::String(pointer.publisher.to_string())),
+            ("name", JsonValue::String(pointer.name)),
+            ("sequence", JsonValue::Number(pointer.sequence as f64)),
+            ("resource", JsonValue::String(hash_to_string(&pointer.resource))),
+        ])
+    }
+}
+
+/// Sequence number of the next record of a pointer, given the sequence
+/// numbers of the latest record we published and of the latest one the
+/// network has, if any.
+pub fn next_sequence(published: Option<u64>, network: Option<u64>) -> u64 {
+    let latest = published.max(network).unwrap_or(0);
+    (latest + 1).max(Timestamp::current_time().inner())
+}
+
+/// A pointer we follow: each new resource it points to is downloaded to
+/// `path`.
+#[derive(Debug, Clone, SerialEncodable, SerialDecodable)]
+pub struct FollowedPointer {
+    pub publisher: PublicKey,
+    pub name: String,
+    /// Directory where the resources are downloaded, each version is
+    /// downloaded to `<path>/<resource hash>`
+    pub path: String,
+    /// Sequence number of the latest record we know of (0 if none)
+    pub sequence: u64,
+    /// Latest resource the pointer pointed to
+    pub resource: Option<blake3::Hash>,
+}
+
+impl FollowedPointer {
+    /// Return the DHT key of the pointer.
+    pub fn key(&self) -> blake3::Hash {
+        pointer_key(&self.publisher, &self.name)
+    }
+
+    /// Return the path where `resource` is downloaded.
+    pub fn resource_path(&self, resource: &blake3::Hash) -> PathBuf {
+        PathBuf::from(&self.path).join(hash_to_string(resource))
+    }
+}
+
+impl From<FollowedPointer> for JsonValue {
+    fn from(followed: FollowedPointer) -> JsonValue {
+        json_map([
+            ("address", JsonValue::String(format!("{}/{}", followed.publisher, followed.name))),
+            ("path", JsonValue::String(followed.path)),
+            ("sequence", JsonValue::Number(followed.sequence as f64)),
+            (
+                "resource",
+                match followed.resource {
+                    Some(resource) => JsonValue::String(hash_to_string(&resource)),
+                    None => JsonValue::Null,
+                },
+            ),
+        ])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::OsRng;
+
+    use super::*;
+
+    #[test]
+    fn pointer_records_are_verified() {
+        let secret = SecretKey::random(&mut OsRng);
+        let resource = blake3::hash(b"resource");
+
+        let pointer = FudPointer::new(&secret, "site", 2, &resource);
+        let parsed = FudPointer::try_from(&pointer.record).unwrap();
+        assert_eq!(parsed.address(), pointer.address());
+        assert_eq!((parsed.sequence, parsed.resource), (2, resource));
+
+        // A record stored under another key
+        let value = serialize(&PointerValue { name: "site".to_string(), resource });
+        let record = DhtRecord::new(&secret, &blake3::hash(b"key"), 2, POINTER_TTL, value);
+        assert!(FudPointer::try_from(&record).is_err());
+
+        // A record of another publisher's pointer
+        let other = SecretKey::random(&mut OsRng);
+        let value = serialize(&PointerValue { name: "site".to_string(), resource });
+        let record = DhtRecord::new(&other, &pointer.key(), 2, POINTER_TTL, value);
+        assert!(FudPointer::try_from(&record).is_err());
+
+        // A record that isn't a pointer
+        let record = DhtRecord::new(&secret, &pointer.key(), 2, POINTER_TTL, b"junk".to_vec());
+        assert!(FudPointer::try_from(&record).is_err());
+
+        // A tampered record
+        let mut record = pointer.record.clone();
+        record.sequence = 3;
+        assert!(FudPointer::try_from(&record).is_err());
+    }
+
+    #[test]
+    fn sequence_numbers_survive_lost_records() {
+        let now = Timestamp::current_time().inner();
+        assert!(next_sequence(None, None) >= now);
+        assert!(next_sequence(Some(5), None) >= now);
+        assert_eq!(next_sequence(Some(now + 10), None), now + 11);
+        assert_eq!(next_sequence(Some(now + 10), Some(now + 20)), now + 21);
+    }
+}
@@ -22,10 +22,11 @@ use std::{path::StripPrefixError, sync::Arc};
 use tracing::{debug, error, info, warn};
 
 use darkfi::{
-    dht::{event::DhtEvent, DhtHandler},
+    dht::{event::DhtEvent, DhtHandler, DhtRecord},
     geode::hash_to_string,
     impl_p2p_message,
     net::{
+        bandwidth::TrafficPriority,
         metering::{MeteringConfiguration, DEFAULT_METERING_CONFIGURATION},
         session::SESSION_INBOUND,
         ChannelPtr, Message, MessageSubscription, P2pPtr, ProtocolBase, ProtocolBasePtr,
@@ -37,6 +38,7 @@ use darkfi_sdk::crypto::schnorr::{SchnorrSecret, Signature};
 use darkfi_serial::{SerialDecodable, SerialEncodable};
 
 use crate::{
+    bitfield::ChunkBitfield,
     dht::{FudNode, FudSeeder},
     Fud,
 };
@@ -64,8 +66,16 @@ macro_rules! impl_resource_msg {
 pub struct FudFileReply {
     pub resource: blake3::Hash,
     pub chunk_hashes: Vec<blake3::Hash>,
+    pub chunk_sizes: Vec<u64>, // Empty if the chunks are not content-defined
 }
-impl_p2p_message!(FudFileReply, "FudFileReply", 0, 0, DEFAULT_METERING_CONFIGURATION);
+impl_p2p_message!(
+    FudFileReply,
+    "FudFileReply",
+    0,
+    0,
+    DEFAULT_METERING_CONFIGURATION,
+    TrafficPriority::Bulk
+);
 impl_resource_msg!(FudFileReply);
 
 /// Message representing a directory reply from the network
@@ -73,9 +83,17 @@ impl_resource_msg!(FudFileReply);
 pub struct FudDirectoryReply {
     pub resource: blake3::Hash,
     pub chunk_hashes: Vec<blake3::Hash>,
+    pub chunk_sizes: Vec<u64>, // Empty if the chunks are not content-defined
     pub files: Vec<(String, u64)>, // Vec of (file path, file size)
 }
-impl_p2p_message!(FudDirectoryReply, "FudDirectoryReply", 0, 0, DEFAULT_METERING_CONFIGURATION);
+impl_p2p_message!(
+    FudDirectoryReply,
+    "FudDirectoryReply",
+    0,
+    0,
+    DEFAULT_METERING_CONFIGURATION,
+    TrafficPriority::Bulk
+);
 impl_resource_msg!(FudDirectoryReply);
 
 /// Message representing a node announcing a key on the network
@@ -93,7 +111,14 @@ pub struct FudChunkReply {
     // TODO: This should be a chunk-sized array, but then we need padding?
     pub chunk: Vec<u8>,
 }
-impl_p2p_message!(FudChunkReply, "FudChunkReply", 0, 0, DEFAULT_METERING_CONFIGURATION);
+impl_p2p_message!(
+    FudChunkReply,
+    "FudChunkReply",
+    0,
+    0,
+    DEFAULT_METERING_CONFIGURATION,
+    TrafficPriority::Bulk
+);
 impl_resource_msg!(FudChunkReply);
 
 /// Message representing a reply when a metadata is not found
@@ -147,6 +172,24 @@ pub struct FudChunkRequest {
 impl_p2p_message!(FudChunkRequest, "FudChunkRequest", 0, 0, DEFAULT_METERING_CONFIGURATION);
 impl_resource_msg!(FudChunkRequest);
 
+/// Message representing a request for the chunks a node has of a resource
+#[derive(Debug, Clone, SerialEncodable, SerialDecodable)]
+pub struct FudChunkMapRequest {
+    pub resource: blake3::Hash,
+}
+impl_p2p_message!(FudChunkMapRequest, "FudChunkMapRequest", 0, 0, DEFAULT_METERING_CONFIGURATION);
+impl_resource_msg!(FudChunkMapRequest);
+
+/// Message representing the chunks a node has of a resource.
+/// The bitfield is empty if the node does not know which chunks it has.
+#[derive(Debug, Clone, SerialEncodable, SerialDecodable)]
+pub struct FudChunkMapReply {
+    pub resource: blake3::Hash,
+    pub bitfield: ChunkBitfield,
+}
+impl_p2p_message!(FudChunkMapReply, "FudChunkMapReply", 0, 0, DEFAULT_METERING_CONFIGURATION);
+impl_resource_msg!(FudChunkMapReply);
+
 /// Message representing a find nodes request on the network
 #[derive(Debug, Clone, SerialEncodable, SerialDecodable)]
 pub struct FudNodesRequest {
@@ -180,15 +223,41 @@ pub struct FudSeedersReply {
 impl_p2p_message!(FudSeedersReply, "FudSeedersReply", 0, 0, DEFAULT_METERING_CONFIGURATION);
 impl_resource_msg!(FudSeedersReply, key);
 
+/// Message representing a node sending a DHT record to store on the network
+#[derive(Debug, Clone, SerialEncodable, SerialDecodable)]
+pub struct FudRecordStore {
+    pub record: DhtRecord,
+}
+impl_p2p_message!(FudRecordStore, "FudRecordStore", 0, 0, DEFAULT_METERING_CONFIGURATION);
+
+/// Message representing a find DHT records request on the network
+#[derive(Debug, Clone, SerialEncodable, SerialDecodable)]
+pub struct FudRecordsRequest {
+    pub key: blake3::Hash,
+}
+impl_p2p_message!(FudRecordsRequest, "FudRecordsRequest", 0, 0, DEFAULT_METERING_CONFIGURATION);
+
+/// Message representing a find DHT records reply on the network
+#[derive(Debug, Clone, SerialEncodable, SerialDecodable)]
+pub struct FudRecordsReply {
+    pub key: blake3::Hash,
+    pub records: Vec<DhtRecord>,
+}
+impl_p2p_message!(FudRecordsReply, "FudRecordsReply", 0, 0, DEFAULT_METERING_CONFIGURATION);
+impl_resource_msg!(FudRecordsReply, key);
+
 /// P2P protocol implementation for fud.
 pub struct ProtocolFud {
     channel: ChannelPtr,
     ping_request_sub: MessageSubscription<FudPingRequest>,
     find_metadata_request_sub: MessageSubscription<FudMetadataRequest>,
     find_chunk_request_sub: MessageSubscription<FudChunkRequest>,
+    chunk_map_request_sub: MessageSubscription<FudChunkMapRequest>,
     find_nodes_request_sub: MessageSubscription<FudNodesRequest>,
     find_seeders_request_sub: MessageSubscription<FudSeedersRequest>,
     announce_sub: MessageSubscription<FudAnnounce>,
+    record_store_sub: MessageSubscription<FudRecordStore>,
+    records_request_sub: MessageSubscription<FudRecordsRequest>,
     fud: Arc<Fud>,
     jobsman: ProtocolJobsManagerPtr,
 }
@@ -207,6 +276,8 @@ impl ProtocolFud {
         msg_subsystem.add_dispatch::<FudChunkRequest>().await;
         msg_subsystem.add_dispatch::<FudChunkReply>().await;
         msg_subsystem.add_dispatch::<FudChunkNotFound>().await;
+        msg_subsystem.add_dispatch::<FudChunkMapRequest>().await;
+        msg_subsystem.add_dispatch::<FudChunkMapReply>().await;
         msg_subsystem.add_dispatch::<FudFileReply>().await;
         msg_subsystem.add_dispatch::<FudDirectoryReply>().await;
         msg_subsystem.add_dispatch::<FudMetadataNotFound>().await;
@@ -215,22 +286,31 @@ impl ProtocolFud {
         msg_subsystem.add_dispatch::<FudSeedersRequest>().await;
         msg_subsystem.add_dispatch::<FudSeedersReply>().await;
         msg_subsystem.add_dispatch::<FudAnnounce>().await;
+        msg_subsystem.add_dispatch::<FudRecordStore>().await;
+        msg_subsystem.add_dispatch::<FudRecordsRequest>().await;
+        msg_subsystem.add_dispatch::<FudRecordsReply>().await;
 
         let ping_request_sub = channel.subscribe_msg::<FudPingRequest>().await?;
         let find_metadata_request_sub = channel.subscribe_msg::<FudMetadataRequest>().await?;
         let find_chunk_request_sub = channel.subscribe_msg::<FudChunkRequest>().await?;
+        let chunk_map_request_sub = channel.subscribe_msg::<FudChunkMapRequest>().await?;
         let find_nodes_request_sub = channel.subscribe_msg::<FudNodesRequest>().await?;
         let find_seeders_request_sub = channel.subscribe_msg::<FudSeedersRequest>().await?;
         let announce_sub = channel.subscribe_msg::<FudAnnounce>().await?;
+        let record_store_sub = channel.subscribe_msg::<FudRecordStore>().await?;
+        let records_request_sub = channel.subscribe_msg::<FudRecordsRequest>().await?;
 
         Ok(Arc::new(Self {
             channel: channel.clone(),
             ping_request_sub,
             find_metadata_request_sub,
             find_chunk_request_sub,
+            chunk_map_request_sub,
             find_nodes_request_sub,
             find_seeders_request_sub,
             announce_sub,
+            record_store_sub,
+            records_request_sub,
             fud,
             jobsman: ProtocolJobsManager::new("ProtocolFud", channel.clone()),
         }))
@@ -320,7 +400,12 @@ impl ProtocolFud {
             let mut chunked_file = chunked_file.unwrap();
 
             // If it's a file with a single chunk, just reply with the chunk
-            if chunked_file.len() == 1 && !chunked_file.is_dir() {
+            // (the file hash can only be verified from the chunk if it is
+            // not content-defined)
+            if chunked_file.len() == 1 &&
+                !chunked_file.is_dir() &&
+                !chunked_file.is_content_defined()
+            {
                 let chunk_hash = chunked_file.get_chunks()[0].hash;
                 let chunk = self.fud.geode.get_chunk(&mut chunked_file, &chunk_hash).await;
                 if let Ok(chunk) = chunk {
@@ -343,6 +428,7 @@ impl ProtocolFud {
                     let reply = FudFileReply {
                         resource: request.resource,
                         chunk_hashes: chunked_file.get_chunks().iter().map(|c| c.hash).collect(),
+                        chunk_sizes: chunked_file.get_chunk_sizes(),
                     };
                     info!(target: "fud::ProtocolFud::handle_fud_metadata_request()", "Sending file metadata {}", hash_to_string(&request.resource));
                     let _ = self.channel.send(&reply).await;
@@ -364,6 +450,7 @@ impl ProtocolFud {
                     let reply = FudDirectoryReply {
                         resource: request.resource,
                         chunk_hashes: chunked_file.get_chunks().iter().map(|c| c.hash).collect(),
+                        chunk_sizes: chunked_file.get_chunk_sizes(),
                         files: files.unwrap(),
                     };
                     info!(target: "fud::ProtocolFud::handle_fud_metadata_request()", "Sending directory metadata {}", hash_to_string(&request.resource));
@@ -404,13 +491,14 @@ impl ProtocolFud {
                 continue
             }
 
-            let chunk = self.fud.geode.get_chunk(&mut chunked.unwrap(), &request.chunk).await;
-            if let Ok(chunk) = chunk {
-                if !self.fud.geode.verify_chunk(&request.chunk, &chunk) {
-                    // TODO: Run geode GC
-                    notfound().await;
-                    continue
-                }
+            let chunk = match self.fud.geode.get_chunk(&mut chunked.unwrap(), &request.chunk).await
+            {
+                Ok(chunk) if self.fud.geode.verify_chunk(&request.chunk, &chunk) => Some(chunk),
+                // The chunk is not entirely on the filesystem if the resource
+                // is incomplete, but we might have it in the scraps
+                _ => self.fud.get_scrap(&request.chunk).await,
+            };
+            if let Some(chunk) = chunk {
                 let reply = FudChunkReply { resource: request.resource, chunk };
                 info!(target: "fud::ProtocolFud::handle_fud_chunk_request()", "Sending chunk {}", hash_to_string(&request.chunk));
                 let _ = self.channel.send(&reply).await;
@@ -421,6 +509,30 @@ impl ProtocolFud {
         }
     }
 
+    async fn handle_fud_chunk_map_request(self: Arc<Self>) -> Result<()> {
+        debug!(target: "fud::ProtocolFud::handle_fud_chunk_map_request()", "START");
+
+        loop {
+            let request = match self.chunk_map_request_sub.receive().await {
+                Ok(v) => v,
+                Err(Error::ChannelStopped) => continue,
+                Err(_) => continue,
+            };
+            info!(target: "fud::ProtocolFud::handle_fud_chunk_map_request()", "Received CHUNK MAP REQUEST for {}", hash_to_string(&request.resource));
+            self.fud.dht.update_channel(self.channel.info.id).await;
+
+            let chunked_storages = self.fud.chunked_storages.read().await;
+            let bitfield = match chunked_storages.get(&request.resource) {
+                Some(chunked) => ChunkBitfield::from(chunked),
+                None => ChunkBitfield::default(),
+            };
+            drop(chunked_storages);
+
+            let reply = FudChunkMapReply { resource: request.resource, bitfield };
+            let _ = self.channel.send(&reply).await;
+        }
+    }
+
     async fn handle_fud_nodes_request(self: Arc<Self>) -> Result<()> {
         debug!(target: "fud::ProtocolFud::handle_fud_nodes_request()", "START");
 
@@ -527,6 +639,41 @@ impl ProtocolFud {
             self.fud.add_value(&request.key, &seeders).await;
         }
     }
+
+    async fn handle_fud_record_store(self: Arc<Self>) -> Result<()> {
+        debug!(target: "fud::ProtocolFud::handle_fud_record_store()", "START");
+
+        loop {
+            let request = match self.record_store_sub.receive().await {
+                Ok(v) => v,
+                Err(Error::ChannelStopped) => continue,
+                Err(_) => continue,
+            };
+            info!(target: "fud::ProtocolFud::handle_fud_record_store()", "Received STORE RECORD for {}", hash_to_string(&request.record.key));
+            self.fud.dht.update_channel(self.channel.info.id).await;
+
+            if let Err(e) = self.fud.dht.store_record(request.record.clone()).await {
+                debug!(target: "fud::ProtocolFud::handle_fud_record_store()", "Could not store record for {}: {e}", hash_to_string(&request.record.key));
+            }
+        }
+    }
+
+    async fn handle_fud_records_request(self: Arc<Self>) -> Result<()> {
+        debug!(target: "fud::ProtocolFud::handle_fud_records_request()", "START");
+
+        loop {
+            let request = match self.records_request_sub.receive().await {
+                Ok(v) => v,
+                Err(Error::ChannelStopped) => continue,
+                Err(_) => continue,
+            };
+            info!(target: "fud::ProtocolFud::handle_fud_records_request()", "Received FIND RECORDS for {}", hash_to_string(&request.key));
+            self.fud.dht.update_channel(self.channel.info.id).await;
+
+            let records = self.fud.dht.records.get(&request.key).await;
+            let _ = self.channel.send(&FudRecordsReply { key: request.key, records }).await;
+        }
+    }
 }
 
 #[async_trait]
@@ -540,12 +687,21 @@ impl ProtocolBase for ProtocolFud {
             .spawn(self.clone().handle_fud_metadata_request(), executor.clone())
             .await;
         self.jobsman.clone().spawn(self.clone().handle_fud_chunk_request(), executor.clone()).await;
+        self.jobsman
+            .clone()
+            .spawn(self.clone().handle_fud_chunk_map_request(), executor.clone())
+            .await;
         self.jobsman.clone().spawn(self.clone().handle_fud_nodes_request(), executor.clone()).await;
         self.jobsman
             .clone()
             .spawn(self.clone().handle_fud_seeders_request(), executor.clone())
             .await;
         self.jobsman.clone().spawn(self.clone().handle_fud_announce(), executor.clone()).await;
+        self.jobsman.clone().spawn(self.clone().handle_fud_record_store(), executor.clone()).await;
+        self.jobsman
+            .clone()
+            .spawn(self.clone().handle_fud_records_request(), executor.clone())
+            .await;
         debug!(target: "fud::ProtocolFud::start()", "END");
         Ok(())
     }
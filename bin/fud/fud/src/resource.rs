@@ -123,6 +123,9 @@ pub struct Resource {
     /// but only data we want to download on the last fetch request
     pub target_bytes_downloaded: u64,
 
+    /// Number of chunks we announced to other nodes as a seeder
+    pub seeding_chunks_count: u64,
+
     /// Recent speeds in bytes/sec, used to compute the download ETA.
     pub speeds: Vec<f64>,
 }
@@ -150,6 +153,7 @@ impl Resource {
             target_bytes_size: 0,
             total_bytes_downloaded: 0,
             target_bytes_downloaded: 0,
+            seeding_chunks_count: 0,
             speeds: vec![],
         }
     }
@@ -241,7 +245,7 @@ impl Resource {
 
         let files = chunked.get_files();
         let chunk_length = chunk_size;
-        let position = (chunk_index as u64) * (MAX_CHUNK_SIZE as u64);
+        let position = chunked.get_chunk_position(chunk_index);
         let mut total_selected_bytes = 0;
 
         // Find the starting file index based on the position
@@ -319,6 +323,7 @@ impl From<Resource> for JsonValue {
             ("target_bytes_size", JsonValue::Number(rs.target_bytes_size as f64)),
             ("total_bytes_downloaded", JsonValue::Number(rs.total_bytes_downloaded as f64)),
             ("target_bytes_downloaded", JsonValue::Number(rs.target_bytes_downloaded as f64)),
+            ("seeding_chunks_count", JsonValue::Number(rs.seeding_chunks_count as f64)),
             ("speeds", JsonValue::Array(rs.speeds.into_iter().map(JsonValue::Number).collect())),
         ])
     }
@@ -349,6 +354,7 @@ impl From<JsonValue> for Resource {
         let total_bytes_downloaded = *value["total_bytes_downloaded"].get::<f64>().unwrap() as u64;
         let target_bytes_downloaded =
             *value["target_bytes_downloaded"].get::<f64>().unwrap() as u64;
+        let seeding_chunks_count = *value["seeding_chunks_count"].get::<f64>().unwrap() as u64;
 
         let speeds = value["speeds"]
             .get::<Vec<JsonValue>>()
@@ -372,6 +378,7 @@ impl From<JsonValue> for Resource {
             target_bytes_size,
             total_bytes_downloaded,
             target_bytes_downloaded,
+            seeding_chunks_count,
             speeds,
         }
     }
@@ -35,13 +35,14 @@ use darkfi::{
         jsonrpc::{ErrorCode, JsonError, JsonRequest, JsonResponse, JsonResult, JsonSubscriber},
         p2p_method::HandlerP2p,
         server::RequestHandler,
+        util::json_map,
     },
     system::StoppableTaskPtr,
     util::path::expand_path,
     Result,
 };
 
-use crate::{util::FileSelection, Fud};
+use crate::{encryption::parse_link, pointer::parse_pointer, util::FileSelection, Fud};
 
 /// Management JSON-RPC
 pub mod management;
@@ -59,6 +60,7 @@ impl RequestHandler<()> for DefaultRpcInterface {
             "ping" => self.pong(req.id, req.params).await,
 
             "put" => self.put(req.id, req.params).await,
+            "put_encrypted" => self.put_encrypted(req.id, req.params).await,
             "get" => self.get(req.id, req.params).await,
             "subscribe" => self.subscribe(req.id, req.params).await,
             "remove" => self.remove(req.id, req.params).await,
@@ -67,6 +69,11 @@ impl RequestHandler<()> for DefaultRpcInterface {
             "list_seeders" => self.list_seeders(req.id, req.params).await,
             "verify" => self.verify(req.id, req.params).await,
             "lookup" => self.lookup(req.id, req.params).await,
+            "publish" => self.publish(req.id, req.params).await,
+            "resolve" => self.resolve(req.id, req.params).await,
+            "follow" => self.follow(req.id, req.params).await,
+            "unfollow" => self.unfollow(req.id, req.params).await,
+            "list_followed" => self.list_followed(req.id, req.params).await,
 
             _ => JsonError::new(ErrorCode::MethodNotFound, None, req.id).into(),
         }
@@ -90,16 +97,24 @@ impl DefaultRpcInterface {
     }
 
     // RPCAPI:
-    // Put a file/directory onto the network. Takes a local filesystem path as a parameter.
+    // Put a file/directory onto the network. Takes a local filesystem path, and optionally
+    // whether to use content-defined chunking (defaults to `false`) as parameters.
+    // Content-defined chunking lets new versions of a file share most chunks with the old
+    // ones, but gives the resource another hash than fixed-size chunks.
     // Returns the resource hash that serves as a pointer to the file/directory.
     //
-    // --> {"jsonrpc": "2.0", "method": "put", "params": ["/foo.txt"], "id": 42}
+    // --> {"jsonrpc": "2.0", "method": "put", "params": ["/foo.txt", false], "id": 42}
     // <-- {"jsonrpc": "2.0", "result: "df4...3db7", "id": 42}
     async fn put(&self, id: i64, params: JsonValue) -> JsonResult {
         let params = params.get::<Vec<JsonValue>>().unwrap();
-        if params.len() != 1 || !params[0].is_string() {
+        if params.is_empty() ||
+            params.len() > 2 ||
+            !params[0].is_string() ||
+            params.get(1).is_some_and(|p| !p.is_bool())
+        {
             return JsonError::new(ErrorCode::InvalidParams, None, id).into()
         }
+        let content_defined = params.get(1).is_some_and(|p| *p.get::<bool>().unwrap());
 
         let path = params[0].get::<String>().unwrap();
         let path = match expand_path(path.as_str()) {
@@ -109,7 +124,7 @@ impl DefaultRpcInterface {
 
         // A valid path was passed. Let's see if we can read it, and if so,
         // add it to Geode.
-        let res = self.fud.put(&path).await;
+        let res = self.fud.put(&path, content_defined).await;
         if let Err(e) = res {
             return JsonError::new(ErrorCode::InternalError, Some(format!("{e}")), id).into()
         }
@@ -118,39 +133,63 @@ impl DefaultRpcInterface {
     }
 
     // RPCAPI:
-    // Fetch a resource from the network. Takes a hash, path (absolute or relative), and an
-    // optional list of file paths (only used for directories) as parameters.
-    // Returns the path where the resource will be located once downloaded.
+    // Encrypt a file with a random key and put the ciphertext onto the network.
+    // Takes a local filesystem path as a parameter.
+    // Returns the path of the ciphertext (used in the `insert_*` events) and the key.
+    // The resource can then be fetched with `get` using the `<hash>#<key>` link.
     //
-    // --> {"jsonrpc": "2.0", "method": "get", "params": ["1211...abfd", "~/myfile.jpg", null], "id": 42}
-    // <-- {"jsonrpc": "2.0", "result": "/home/user/myfile.jpg", "id": 42}
-    async fn get(&self, id: i64, params: JsonValue) -> JsonResult {
+    // --> {"jsonrpc": "2.0", "method": "put_encrypted", "params": ["/foo.txt"], "id": 42}
+    // <-- {"jsonrpc": "2.0", "result": {"path": "/home/user/.local/share/darkfi/fud/encrypted/9b1...2fa", "key": "Ae3...x7Q"}, "id": 42}
+    async fn put_encrypted(&self, id: i64, params: JsonValue) -> JsonResult {
         let params = params.get::<Vec<JsonValue>>().unwrap();
-        if params.len() != 3 || !params[0].is_string() || !params[1].is_string() {
+        if params.len() != 1 || !params[0].is_string() {
             return JsonError::new(ErrorCode::InvalidParams, None, id).into()
         }
 
-        let mut hash_buf = vec![];
-        match bs58::decode(params[0].get::<String>().unwrap().as_str()).onto(&mut hash_buf) {
-            Ok(_) => {}
+        let path = params[0].get::<String>().unwrap();
+        let path = match expand_path(path.as_str()) {
+            Ok(v) => v,
             Err(_) => return JsonError::new(ErrorCode::InvalidParams, None, id).into(),
+        };
+
+        match self.fud.put_encrypted(&path).await {
+            Ok((encrypted_path, key)) => JsonResponse::new(
+                json_map([
+                    ("path", JsonValue::String(encrypted_path.to_string_lossy().to_string())),
+                    ("key", JsonValue::String(key.to_string())),
+                ]),
+                id,
+            )
+            .into(),
+            Err(e) => JsonError::new(ErrorCode::InternalError, Some(format!("{e}")), id).into(),
         }
+    }
 
-        if hash_buf.len() != 32 {
+    // RPCAPI:
+    // Fetch a resource from the network. Takes a hash (or a `<hash>#<key>` link for encrypted
+    // resources), path (absolute or relative), and an optional list of file paths (only used
+    // for directories) as parameters.
+    // Returns the path where the resource will be located once downloaded (and decrypted).
+    //
+    // --> {"jsonrpc": "2.0", "method": "get", "params": ["1211...abfd", "~/myfile.jpg", null], "id": 42}
+    // <-- {"jsonrpc": "2.0", "result": "/home/user/myfile.jpg", "id": 42}
+    async fn get(&self, id: i64, params: JsonValue) -> JsonResult {
+        let params = params.get::<Vec<JsonValue>>().unwrap();
+        if params.len() != 3 || !params[0].is_string() || !params[1].is_string() {
             return JsonError::new(ErrorCode::InvalidParams, None, id).into()
         }
 
-        let mut hash_buf_arr = [0u8; 32];
-        hash_buf_arr.copy_from_slice(&hash_buf);
-
-        let hash = blake3::Hash::from_bytes(hash_buf_arr);
+        let Ok((hash, key)) = parse_link(params[0].get::<String>().unwrap()) else {
+            return JsonError::new(ErrorCode::InvalidParams, None, id).into()
+        };
         let hash_str = hash_to_string(&hash);
 
         let path = match params[1].get::<String>() {
             Some(path) => match path.is_empty() {
                 true => match self.fud.hash_to_path(&hash).ok().flatten() {
-                    Some(path) => path,
-                    None => self.fud.downloads_path.join(&hash_str),
+                    // The known path of an encrypted resource is its ciphertext
+                    Some(path) if key.is_none() => path,
+                    _ => self.fud.downloads_path.join(&hash_str),
                 },
                 false => match PathBuf::from(path).is_absolute() {
                     true => PathBuf::from(path),
@@ -176,7 +215,11 @@ impl DefaultRpcInterface {
         };
 
         // Start downloading the resource
-        if let Err(e) = self.fud.get(&hash, &path, files).await {
+        let res = match key {
+            Some(key) => self.fud.get_encrypted(&hash, &key, &path).await,
+            None => self.fud.get(&hash, &path, files).await,
+        };
+        if let Err(e) = res {
             return JsonError::new(ErrorCode::InternalError, Some(e.to_string()), id).into()
         }
 
@@ -352,4 +395,121 @@ impl DefaultRpcInterface {
 
         JsonResponse::new(JsonValue::Array(vec![]), id).into()
     }
+
+    // RPCAPI:
+    // Point our pointer `name` to a resource hash, and announce it to the
+    // network. Takes a name and a resource hash as parameters.
+    // Returns the new pointer record.
+    //
+    // --> {"jsonrpc": "2.0", "method": "publish", "params": ["myname", "1211...abfd"], "id": 42}
+    // <-- {"jsonrpc": "2.0", "result": {"address": "8sRw...Xm2P/myname", "publisher": "8sRw...Xm2P", "name": "myname", "sequence": 1, "resource": "1211...abfd"}, "id": 42}
+    async fn publish(&self, id: i64, params: JsonValue) -> JsonResult {
+        let params = params.get::<Vec<JsonValue>>().unwrap();
+        if params.len() != 2 || !params[0].is_string() || !params[1].is_string() {
+            return JsonError::new(ErrorCode::InvalidParams, None, id).into()
+        }
+
+        let name = params[0].get::<String>().unwrap();
+        let mut hash_buf = [0u8; 32];
+        match bs58::decode(params[1].get::<String>().unwrap().as_str()).onto(&mut hash_buf) {
+            Ok(_) => {}
+            Err(_) => return JsonError::new(ErrorCode::InvalidParams, None, id).into(),
+        }
+
+        match self.fud.publish(name, &blake3::Hash::from_bytes(hash_buf)).await {
+            Ok(pointer) => JsonResponse::new(pointer.into(), id).into(),
+            Err(e) => JsonError::new(ErrorCode::InternalError, Some(e.to_string()), id).into(),
+        }
+    }
+
+    // RPCAPI:
+    // Find the latest record of a pointer. Takes a pointer
+    // (`<publisher>/<name>`) as parameter.
+    // Returns the pointer record.
+    //
+    // --> {"jsonrpc": "2.0", "method": "resolve", "params": ["8sRw...Xm2P/myname"], "id": 42}
+    // <-- {"jsonrpc": "2.0", "result": {"address": "8sRw...Xm2P/myname", "publisher": "8sRw...Xm2P", "name": "myname", "sequence": 1, "resource": "1211...abfd"}, "id": 42}
+    async fn resolve(&self, id: i64, params: JsonValue) -> JsonResult {
+        let params = params.get::<Vec<JsonValue>>().unwrap();
+        if params.len() != 1 || !params[0].is_string() {
+            return JsonError::new(ErrorCode::InvalidParams, None, id).into()
+        }
+
+        let Ok((publisher, name)) = parse_pointer(params[0].get::<String>().unwrap()) else {
+            return JsonError::new(ErrorCode::InvalidParams, None, id).into()
+        };
+
+        match self.fud.resolve(&publisher, &name).await {
+            Ok(pointer) => JsonResponse::new(pointer.into(), id).into(),
+            Err(e) => JsonError::new(ErrorCode::InternalError, Some(e.to_string()), id).into(),
+        }
+    }
+
+    // RPCAPI:
+    // Follow a pointer: each new resource it points to is downloaded to
+    // `<path>/<resource hash>`. Takes a pointer (`<publisher>/<name>`) and an
+    // optional path (absolute or relative, defaults to the pointer name) as
+    // parameters.
+    // Returns the followed pointer.
+    //
+    // --> {"jsonrpc": "2.0", "method": "follow", "params": ["8sRw...Xm2P/myname", null], "id": 42}
+    // <-- {"jsonrpc": "2.0", "result": {"address": "8sRw...Xm2P/myname", "path": "/home/user/downloads/myname", "sequence": 1, "resource": "1211...abfd"}, "id": 42}
+    async fn follow(&self, id: i64, params: JsonValue) -> JsonResult {
+        let params = params.get::<Vec<JsonValue>>().unwrap();
+        if params.len() != 2 || !params[0].is_string() {
+            return JsonError::new(ErrorCode::InvalidParams, None, id).into()
+        }
+
+        let Ok((publisher, name)) = parse_pointer(params[0].get::<String>().unwrap()) else {
+            return JsonError::new(ErrorCode::InvalidParams, None, id).into()
+        };
+
+        let path = match &params[1] {
+            JsonValue::String(path) => match PathBuf::from(path).is_absolute() {
+                true => Some(PathBuf::from(path)),
+                false => Some(self.fud.downloads_path.join(path)),
+            },
+            JsonValue::Null => None,
+            _ => return JsonError::new(ErrorCode::InvalidParams, None, id).into(),
+        };
+
+        match self.fud.follow(&publisher, &name, path).await {
+            Ok(followed) => JsonResponse::new(followed.into(), id).into(),
+            Err(e) => JsonError::new(ErrorCode::InternalError, Some(e.to_string()), id).into(),
+        }
+    }
+
+    // RPCAPI:
+    // Stop following a pointer. Takes a pointer (`<publisher>/<name>`) as
+    // parameter.
+    //
+    // --> {"jsonrpc": "2.0", "method": "unfollow", "params": ["8sRw...Xm2P/myname"], "id": 42}
+    // <-- {"jsonrpc": "2.0", "result": [], "id": 42}
+    async fn unfollow(&self, id: i64, params: JsonValue) -> JsonResult {
+        let params = params.get::<Vec<JsonValue>>().unwrap();
+        if params.len() != 1 || !params[0].is_string() {
+            return JsonError::new(ErrorCode::InvalidParams, None, id).into()
+        }
+
+        let Ok((publisher, name)) = parse_pointer(params[0].get::<String>().unwrap()) else {
+            return JsonError::new(ErrorCode::InvalidParams, None, id).into()
+        };
+
+        if let Err(e) = self.fud.unfollow(&publisher, &name) {
+            return JsonError::new(ErrorCode::InternalError, Some(e.to_string()), id).into()
+        }
+
+        JsonResponse::new(JsonValue::Array(vec![]), id).into()
+    }
+
+    // RPCAPI:
+    // Returns the pointers we follow.
+    //
+    // --> {"jsonrpc": "2.0", "method": "list_followed", "params": [], "id": 42}
+    // <-- {"jsonrpc": "2.0", "result": [{"address": "8sRw...Xm2P/myname", "path": "/home/user/downloads/myname", "sequence": 1, "resource": "1211...abfd"}], "id": 42}
+    async fn list_followed(&self, id: i64, _params: JsonValue) -> JsonResult {
+        let followed = self.fud.followed_pointers().await;
+        JsonResponse::new(JsonValue::Array(followed.into_iter().map(|f| f.into()).collect()), id)
+            .into()
+    }
 }
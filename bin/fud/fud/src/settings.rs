@@ -27,7 +27,7 @@ use darkfi::{
     Error, Result,
 };
 
-use crate::pow::PowSettingsOpt;
+use crate::{gateway::GatewaySettingsOpt, pow::PowSettingsOpt};
 
 pub const CONFIG_FILE: &str = "fud_config.toml";
 pub const CONFIG_FILE_CONTENTS: &str = include_str!("../fud_config.toml");
@@ -75,6 +75,10 @@ pub struct Args {
     #[structopt(flatten)]
     /// PoW settings
     pub pow: PowSettingsOpt,
+
+    #[structopt(flatten)]
+    /// HTTP gateway settings
+    pub gateway: GatewaySettingsOpt,
 }
 
 /// Helper function to parse management RPC settings from config file.
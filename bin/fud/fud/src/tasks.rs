@@ -29,7 +29,6 @@ use darkfi::{
 
 use crate::{
     event::{self, notify_event},
-    proto::FudAnnounce,
     resource::ResourceStatus,
     Fud, FudEvent, FudState,
 };
@@ -118,7 +117,7 @@ pub async fn get_task(fud: Arc<Fud>) -> Result<()> {
 /// Triggered when calling the `fud.put()` method.
 pub async fn put_task(fud: Arc<Fud>) -> Result<()> {
     loop {
-        let path = fud.put_rx.recv().await.unwrap();
+        let (path, content_defined) = fud.put_rx.recv().await.unwrap();
 
         // Create the new task
         let mut put_tasks = fud.put_tasks.write().await;
@@ -131,7 +130,7 @@ pub async fn put_task(fud: Arc<Fud>) -> Result<()> {
         let fud_2 = fud.clone();
         let path_ = path.clone();
         task.start(
-            async move { fud_1.insert_resource(&path_).await },
+            async move { fud_1.insert_resource(&path_, content_defined).await },
             move |res| async move {
                 // Remove the task from the `fud.put_tasks` hashmap once it is
                 // stopped (error, manually, or just done).
@@ -222,17 +221,7 @@ pub async fn announce_seed_task(fud: Arc<Fud>) -> Result<()> {
 
         info!(target: "fud::announce_seed_task()", "Announcing files...");
         for resource in seeding_resources {
-            if let Ok(seeder) = fud.new_seeder(&resource.hash).await {
-                let seeders = vec![seeder];
-                let _ = fud
-                    .dht
-                    .announce(
-                        &resource.hash,
-                        &seeders.clone(),
-                        &FudAnnounce { key: resource.hash, seeders },
-                    )
-                    .await;
-            }
+            fud.announce_seeder(&resource.hash).await;
         }
 
         info!(target: "fud::announce_seed_task()", "Pruning seeders...");
@@ -240,6 +229,22 @@ pub async fn announce_seed_task(fud: Arc<Fud>) -> Result<()> {
     }
 }
 
+/// Background task that resolves the pointers we follow and downloads the
+/// resources they point to when they are updated.
+pub async fn follow_task(fud: Arc<Fud>) -> Result<()> {
+    let interval = 600; // TODO: Make a setting
+
+    loop {
+        sleep(interval).await;
+
+        for mut followed in fud.followed_pointers().await {
+            if let Err(e) = fud.update_followed_pointer(&mut followed).await {
+                warn!(target: "fud::follow_task()", "Could not update pointer {}/{}: {e}", followed.publisher, followed.name);
+            }
+        }
+    }
+}
+
 /// Background task that:
 /// 1. Updates the [`crate::bitcoin::BitcoinHashCache`]
 /// 2. Removes old nodes from the DHT
@@ -65,6 +65,27 @@ async def change_task_status(refid, status, server_name, port):
 async def modify_task(refid, changes, server_name, port):
     return await query("modify", [refid, changes], server_name, int(port))
 
+async def set_parent(refid, parent_refid, server_name, port):
+    return await query("set_parent", [refid, parent_refid], server_name, int(port))
+
+async def get_subtasks(refid, server_name, port):
+    return await query("get_subtasks", [refid], server_name, int(port))
+
+async def add_dependency(refid, blocker_refid, server_name, port):
+    return await query("add_dependency", [refid, blocker_refid], server_name, int(port))
+
+async def remove_dependency(refid, blocker_refid, server_name, port):
+    return await query("remove_dependency", [refid, blocker_refid], server_name, int(port))
+
+async def set_recurrence(refid, recurrence, server_name, port):
+    return await query("set_recurrence", [refid, recurrence], server_name, int(port))
+
+async def get_conflicts(refids, server_name, port):
+    return await query("get_conflicts", refids, server_name, int(port))
+
+async def redact(event_id, server_name, port):
+    return await query("redact", [event_id], server_name, int(port))
+
 async def switch_workspace(workspace, server_name, port):
     return await query("switch_ws", [workspace], server_name, int(port))
 
@@ -80,8 +101,9 @@ async def fetch_archive_task(task_refid, month_ts, server_name, port):
 async def add_task_comment(refid, comment, server_name, port):
     return await query("set_comment", [refid, comment], server_name, int(port))
 
-async def export_to(path, server_name, port):
-    return await query("export", [path], server_name, int(port))
+async def export_to(path, server_name, port, fmt=None, month_ts=None):
+    params = [path, fmt, None if month_ts is None else str(month_ts)]
+    return await query("export", params, server_name, int(port))
 
-async def import_from(path, server_name, port):
-    return await query("import", [path], server_name, int(port))
+async def import_from(path, server_name, port, fmt=None):
+    return await query("import", [path, fmt], server_name, int(port))
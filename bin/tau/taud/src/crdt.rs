@@ -0,0 +1,538 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2026 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Merging of concurrently edited copies of a task.
+//!
+//! Every copy of a task carries its whole edit history in `events`, so two
+//! copies merge by taking the union of their events. An event found in only
+//! one copy was made without seeing the new events of the other copy, which
+//! makes the new events of both sides concurrent. Each field then merges
+//! with its own rule:
+//!
+//! * `title`, `rank`, `bounty`, `due` and `state` are last-writer-wins
+//!   registers. Events are ordered like the event graph orders its events:
+//!   by timestamp, with a deterministic tie-break.
+//! * `tags`, `assign` and `project` are observed-remove sets. An element
+//!   removed on one side stays if the other side added it concurrently.
+//! * `desc` is merged line by line against the last common description.
+//!   Lines changed differently on both sides keep the latest edit. Too long
+//!   descriptions are merged as a register instead.
+//!
+//! Fields edited on both sides are kept as [`Conflict`]s so users can check
+//! the outcome. Conflicts are local to the node and never broadcast.
+
+use std::{cmp::Ordering, collections::HashMap};
+
+use darkfi::util::time::Timestamp;
+use tinyjson::JsonValue;
+
+use crate::{
+    error::{TaudError, TaudResult},
+    task_info::{TaskEvent, TaskInfo},
+};
+
+/// Fields merged as last-writer-wins registers.
+const REGISTER_FIELDS: [&str; 5] = ["title", "rank", "bounty", "due", "state"];
+
+/// Largest table of line pairs built to merge a description, bounding
+/// the memory and time a description from a peer can cost.
+const MAX_DIFF_CELLS: usize = 1 << 20;
+
+/// Fields merged as observed-remove sets.
+const SET_FIELDS: [&str; 3] = ["tags", "assign", "project"];
+
+/// A field that was edited on two sides without either seeing the other's edit.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Conflict {
+    pub field: String,
+    /// The concurrent edits, in event order
+    pub events: Vec<TaskEvent>,
+    /// The value the field was merged to
+    pub resolved: String,
+}
+
+impl From<&Conflict> for JsonValue {
+    fn from(conflict: &Conflict) -> JsonValue {
+        let events: Vec<JsonValue> = conflict.events.iter().map(|x| x.clone().into()).collect();
+        JsonValue::Object(HashMap::from([
+            ("field".to_string(), JsonValue::String(conflict.field.clone())),
+            ("events".to_string(), JsonValue::Array(events)),
+            ("resolved".to_string(), JsonValue::String(conflict.resolved.clone())),
+        ]))
+    }
+}
+
+impl TryFrom<&JsonValue> for Conflict {
+    type Error = TaudError;
+
+    fn try_from(value: &JsonValue) -> TaudResult<Conflict> {
+        let invalid = || TaudError::InvalidData("Invalid conflict".into());
+        let string = |map: &HashMap<String, JsonValue>, key: &str| {
+            map.get(key).and_then(|v| v.get::<String>()).cloned().ok_or_else(invalid)
+        };
+
+        let map = value.get::<HashMap<String, JsonValue>>().ok_or_else(invalid)?;
+        let events =
+            map.get("events").and_then(|v| v.get::<Vec<JsonValue>>()).ok_or_else(invalid)?;
+        let events = events
+            .iter()
+            .map(|event| {
+                let event = event.get::<HashMap<String, JsonValue>>().ok_or_else(invalid)?;
+                let timestamp =
+                    string(event, "timestamp")?.parse::<u64>().map_err(|_| invalid())?;
+                Ok(TaskEvent {
+                    action: string(event, "action")?,
+                    author: string(event, "author")?,
+                    content: string(event, "content")?,
+                    timestamp: Timestamp::from_u64(timestamp),
+                })
+            })
+            .collect::<TaudResult<Vec<TaskEvent>>>()?;
+
+        Ok(Conflict { field: string(map, "field")?, events, resolved: string(map, "resolved")? })
+    }
+}
+
+/// Total order of task events: chronological, ties broken by the event data.
+pub fn event_order(a: &TaskEvent, b: &TaskEvent) -> Ordering {
+    a.timestamp
+        .cmp(&b.timestamp)
+        .then_with(|| a.author.cmp(&b.author))
+        .then_with(|| a.action.cmp(&b.action))
+        .then_with(|| a.content.cmp(&b.content))
+}
+
+/// Merge two copies of the same task. The result doesn't depend on which
+/// copy is `local`, apart from the conflicts already recorded in `local`.
+pub fn merge_tasks(local: &TaskInfo, remote: &TaskInfo) -> TaskInfo {
+    let ours: Vec<&TaskEvent> =
+        local.events.iter().filter(|e| !remote.events.contains(e)).collect();
+    let theirs: Vec<&TaskEvent> =
+        remote.events.iter().filter(|e| !local.events.contains(e)).collect();
+
+    let mut merged = local.clone();
+    merged.events.extend(theirs.iter().map(|e| (*e).clone()));
+    merged.events.sort_by(event_order);
+    merged.events.dedup();
+
+    for comment in remote.comments.iter() {
+        if !merged.comments.contains(comment) {
+            merged.comments.push(comment.clone());
+        }
+    }
+    merged.comments.sort_by(|a, b| {
+        a.timestamp
+            .cmp(&b.timestamp)
+            .then_with(|| a.author.cmp(&b.author))
+            .then_with(|| a.content.cmp(&b.content))
+    });
+
+    let latest = |events: &[&TaskEvent], field: &str| -> Option<TaskEvent> {
+        events
+            .iter()
+            .filter(|e| e.action == field)
+            .max_by(|a, b| event_order(a, b))
+            .cloned()
+            .cloned()
+    };
+
+    for field in REGISTER_FIELDS.iter().chain(["desc"].iter()).chain(SET_FIELDS.iter()) {
+        let remote_wins = match (latest(&ours, field), latest(&theirs, field)) {
+            (_, None) => continue,
+            (None, Some(_)) => true,
+            (Some(a), Some(b)) => event_order(&a, &b) == Ordering::Less,
+        };
+        let concurrent = ours.iter().any(|e| e.action == *field);
+
+        match *field {
+            "desc" if concurrent => {
+                let base = local
+                    .events
+                    .iter()
+                    .filter(|e| e.action == "desc" && remote.events.contains(e))
+                    .max_by(|a, b| event_order(a, b))
+                    .map(|e| e.content.as_str())
+                    .unwrap_or("");
+                merged.desc = merge_text(base, &local.desc, &remote.desc, remote_wins);
+            }
+            "tags" | "assign" | "project" => {
+                let (a, b) = (field_set(local, field), field_set(remote, field));
+                let merged_set = merge_set(field, &a, &b, local, remote);
+                set_field_set(&mut merged, field, merged_set);
+            }
+            _ if remote_wins => copy_field(&mut merged, remote, field),
+            _ => {}
+        }
+    }
+
+    // A later edit made with all sides of a conflict in view resolves it.
+    merged.conflicts.retain(|c| {
+        !(theirs.iter().any(|e| e.action == c.field) &&
+            c.events.iter().all(|e| remote.events.contains(e)))
+    });
+
+    for field in REGISTER_FIELDS.iter().chain(["desc"].iter()).chain(SET_FIELDS.iter()) {
+        let mut events: Vec<TaskEvent> = ours
+            .iter()
+            .chain(theirs.iter())
+            .filter(|e| e.action == *field)
+            .map(|e| (*e).clone())
+            .collect();
+        let edited_ours = ours.iter().any(|e| e.action == *field);
+        let edited_theirs = theirs.iter().any(|e| e.action == *field);
+        if !edited_ours || !edited_theirs {
+            continue
+        }
+
+        events.sort_by(event_order);
+        if events.iter().all(|e| e.content == events[0].content) {
+            continue
+        }
+
+        let conflict =
+            Conflict { field: field.to_string(), events, resolved: field_value(&merged, field) };
+        if !merged.conflicts.contains(&conflict) {
+            merged.conflicts.push(conflict);
+        }
+    }
+
+    merged
+}
+
+fn copy_field(dst: &mut TaskInfo, src: &TaskInfo, field: &str) {
+    match field {
+        "title" => dst.title.clone_from(&src.title),
+        "desc" => dst.desc.clone_from(&src.desc),
+        "rank" => dst.rank = src.rank,
+        "bounty" => dst.bounty = src.bounty,
+        "due" => dst.due = src.due,
+        "state" => dst.state.clone_from(&src.state),
+        _ => unreachable!(),
+    }
+}
+
+fn field_value(task: &TaskInfo, field: &str) -> String {
+    let opt = |v: Option<String>| v.unwrap_or_else(|| "None".to_string());
+    match field {
+        "title" => task.title.clone(),
+        "desc" => task.desc.clone(),
+        "rank" => opt(task.rank.map(|r| r.to_string())),
+        "bounty" => opt(task.bounty.map(|b| b.to_string())),
+        "due" => opt(task.due.map(|d| d.inner().to_string())),
+        "state" => task.state.clone(),
+        _ => field_set(task, field).join(", "),
+    }
+}
+
+fn field_set<'a>(task: &'a TaskInfo, field: &str) -> &'a [String] {
+    match field {
+        "tags" => &task.tags,
+        "assign" => &task.assign,
+        "project" => &task.project,
+        _ => unreachable!(),
+    }
+}
+
+fn set_field_set(task: &mut TaskInfo, field: &str, set: Vec<String>) {
+    match field {
+        "tags" => task.tags = set,
+        "assign" => task.assign = set,
+        "project" => task.project = set,
+        _ => unreachable!(),
+    }
+}
+
+/// Elements an event of a set field adds. `tags` events hold `+tag` and
+/// `-tag` items, `assign` events `@name` and `-@name` items, and `project`
+/// events replace the whole set, adding every listed project.
+fn added_by(event: &TaskEvent, field: &str) -> Vec<String> {
+    event
+        .content
+        .split(", ")
+        .filter_map(|item| match field {
+            "tags" => item.strip_prefix('+'),
+            "assign" => item.strip_prefix('@'),
+            _ => Some(item),
+        })
+        .map(String::from)
+        .collect()
+}
+
+/// Observed-remove merge of a set field. An element held by only one side
+/// was either added there or removed on the other side. It stays if one of
+/// its adds is unknown to the other side, since that side can't have
+/// removed what it never saw.
+fn merge_set(
+    field: &str,
+    ours: &[String],
+    theirs: &[String],
+    local: &TaskInfo,
+    remote: &TaskInfo,
+) -> Vec<String> {
+    let unseen_add = |elem: &String, holder: &TaskInfo, other: &TaskInfo| {
+        holder.events.iter().any(|e| {
+            e.action == field && !other.events.contains(e) && added_by(e, field).contains(elem)
+        })
+    };
+
+    let mut merged: Vec<String> = ours
+        .iter()
+        .filter(|x| theirs.contains(x) || unseen_add(x, local, remote))
+        .cloned()
+        .collect();
+
+    for x in theirs.iter() {
+        if !ours.contains(x) && unseen_add(x, remote, local) {
+            merged.push(x.clone());
+        }
+    }
+
+    merged
+}
+
+/// Three-way line merge of `ours` and `theirs` against their common `base`.
+/// Regions changed differently on both sides take the `theirs` version if
+/// `theirs_wins`, and the `ours` version otherwise. Descriptions too long
+/// to diff are taken whole from the winning side.
+pub fn merge_text(base: &str, ours: &str, theirs: &str, theirs_wins: bool) -> String {
+    if ours == theirs || theirs == base {
+        return ours.to_string()
+    }
+    if ours == base {
+        return theirs.to_string()
+    }
+
+    let base: Vec<&str> = base.split('\n').collect();
+    let a: Vec<&str> = ours.split('\n').collect();
+    let b: Vec<&str> = theirs.split('\n').collect();
+    if base.len().saturating_mul(a.len().max(b.len())) > MAX_DIFF_CELLS {
+        return if theirs_wins { theirs.to_string() } else { ours.to_string() }
+    }
+    let ma = lcs_matches(&base, &a);
+    let mb = lcs_matches(&base, &b);
+
+    let mut out: Vec<&str> = vec![];
+    let (mut i, mut ja, mut jb) = (0, 0, 0);
+    loop {
+        // Next base line kept by both sides
+        let anchor = (i..base.len()).find_map(|k| Some((k, ma[k]?, mb[k]?)));
+        let (k, ka, kb) = anchor.unwrap_or((base.len(), a.len(), b.len()));
+
+        let (base_chunk, a_chunk, b_chunk) = (&base[i..k], &a[ja..ka], &b[jb..kb]);
+        if a_chunk == base_chunk {
+            out.extend_from_slice(b_chunk);
+        } else if b_chunk == base_chunk || a_chunk == b_chunk || !theirs_wins {
+            out.extend_from_slice(a_chunk);
+        } else {
+            out.extend_from_slice(b_chunk);
+        }
+
+        let Some((k, ka, kb)) = anchor else { break };
+        out.push(base[k]);
+        (i, ja, jb) = (k + 1, ka + 1, kb + 1);
+    }
+
+    out.join("\n")
+}
+
+/// For each line of `base`, the line of `other` it is matched with in a
+/// longest common subsequence of the two.
+fn lcs_matches(base: &[&str], other: &[&str]) -> Vec<Option<usize>> {
+    let (n, m) = (base.len(), other.len());
+    let mut lens = vec![vec![0usize; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lens[i][j] = if base[i] == other[j] {
+                lens[i + 1][j + 1] + 1
+            } else {
+                lens[i + 1][j].max(lens[i][j + 1])
+            };
+        }
+    }
+
+    let mut matches = vec![None; n];
+    let (mut i, mut j) = (0, 0);
+    while i < n && j < m {
+        if base[i] == other[j] {
+            matches[i] = Some(j);
+            i += 1;
+            j += 1;
+        } else if lens[i + 1][j] >= lens[i][j + 1] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+
+    matches
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use darkfi::util::time::Timestamp;
+
+    fn event(action: &str, author: &str, content: &str, ts: u64) -> TaskEvent {
+        TaskEvent {
+            action: action.into(),
+            author: author.into(),
+            content: content.into(),
+            timestamp: Timestamp::from_u64(ts),
+        }
+    }
+
+    fn base_task() -> TaskInfo {
+        let mut task = TaskInfo::new(
+            "darkfi".to_string(),
+            "title",
+            "line one\nline two\nline three",
+            "alice",
+            None,
+            None,
+            Timestamp::from_u64(100),
+            None,
+        )
+        .unwrap();
+        task.tags = vec!["dev".to_string()];
+        task.events.push(event("tags", "alice", "+dev", 100));
+        task
+    }
+
+    #[test]
+    fn concurrent_edits_to_different_fields_all_survive() {
+        let base = base_task();
+
+        let mut alice = base.clone();
+        alice.set_title("new title");
+        alice.events.push(event("title", "alice", "new title", 200));
+
+        let mut bob = base.clone();
+        bob.assign = vec!["bob".to_string()];
+        bob.events.push(event("assign", "bob", "@bob", 150));
+        bob.desc = "line one\nline two\nline three\nline four".to_string();
+        bob.events.push(event("desc", "bob", &bob.desc, 150));
+
+        let merged = merge_tasks(&alice, &bob);
+        assert_eq!(merged.title, "new title");
+        assert_eq!(merged.assign, vec!["bob".to_string()]);
+        assert_eq!(merged.desc, bob.desc);
+        assert!(merged.conflicts.is_empty());
+        assert_eq!(merged.events.len(), 4);
+
+        // Merging is commutative and idempotent.
+        let other_way = merge_tasks(&bob, &alice);
+        assert_eq!(other_way.title, merged.title);
+        assert_eq!(other_way.events, merged.events);
+        assert_eq!(merge_tasks(&merged, &bob), merged);
+    }
+
+    #[test]
+    fn concurrent_register_edits_are_last_writer_wins_and_flagged() {
+        let base = base_task();
+
+        let mut alice = base.clone();
+        alice.set_title("alice's title");
+        alice.events.push(event("title", "alice", "alice's title", 300));
+
+        let mut bob = base.clone();
+        bob.set_title("bob's title");
+        bob.events.push(event("title", "bob", "bob's title", 200));
+
+        for merged in [merge_tasks(&alice, &bob), merge_tasks(&bob, &alice)] {
+            assert_eq!(merged.title, "alice's title");
+            assert_eq!(merged.conflicts.len(), 1);
+            assert_eq!(merged.conflicts[0].field, "title");
+            assert_eq!(merged.conflicts[0].events.len(), 2);
+            assert_eq!(merged.conflicts[0].resolved, "alice's title");
+        }
+
+        // An edit made after seeing both sides resolves the conflict.
+        let merged = merge_tasks(&bob, &alice);
+        let mut carol = merge_tasks(&alice, &bob);
+        carol.conflicts.clear();
+        carol.set_title("agreed title");
+        carol.events.push(event("title", "carol", "agreed title", 400));
+        let resolved = merge_tasks(&merged, &carol);
+        assert_eq!(resolved.title, "agreed title");
+        assert!(resolved.conflicts.is_empty());
+    }
+
+    #[test]
+    fn concurrent_add_wins_over_remove() {
+        let base = base_task();
+
+        // Alice removes "dev" while Bob, unaware, tags the task "dev" again
+        // along with "bug".
+        let mut alice = base.clone();
+        alice.set_tags(&["-dev".to_string()]);
+        alice.events.push(event("tags", "alice", "-dev", 200));
+
+        let mut bob = base.clone();
+        bob.set_tags(&["+bug".to_string()]);
+        bob.events.push(event("tags", "bob", "+bug", 150));
+        let mut bob_readd = bob.clone();
+        bob_readd.events.push(event("tags", "bob", "+dev", 160));
+
+        // Bob's "dev" add was already seen by Alice, so her remove stands.
+        let merged = merge_tasks(&alice, &bob);
+        assert_eq!(merged.tags, vec!["bug".to_string()]);
+
+        // Bob's concurrent re-add wasn't seen by Alice, so it wins.
+        let merged = merge_tasks(&alice, &bob_readd);
+        assert!(merged.tags.contains(&"dev".to_string()));
+        assert!(merged.tags.contains(&"bug".to_string()));
+    }
+
+    #[test]
+    fn descriptions_merge_line_by_line() {
+        let base = "one\ntwo\nthree\nfour";
+        let ours = "ONE\ntwo\nthree\nfour";
+        let theirs = "one\ntwo\nthree\nFOUR\nfive";
+        assert_eq!(merge_text(base, ours, theirs, true), "ONE\ntwo\nthree\nFOUR\nfive");
+        assert_eq!(merge_text(base, theirs, ours, false), "ONE\ntwo\nthree\nFOUR\nfive");
+
+        // The same line changed on both sides keeps the latest edit.
+        let ours = "one\nTWO\nthree\nfour";
+        let theirs = "one\n2\nthree\nfour";
+        assert_eq!(merge_text(base, ours, theirs, true), "one\n2\nthree\nfour");
+        assert_eq!(merge_text(base, ours, theirs, false), "one\nTWO\nthree\nfour");
+
+        // Descriptions too long to diff keep the winning side whole.
+        let long = "line\n".repeat(2000);
+        let ours = format!("{long}ours");
+        let theirs = format!("theirs\n{long}");
+        assert_eq!(merge_text(&long, &ours, &theirs, true), theirs);
+        assert_eq!(merge_text(&long, &ours, &theirs, false), ours);
+    }
+
+    #[test]
+    fn malformed_conflicts_are_rejected() {
+        let conflict = Conflict {
+            field: "title".to_string(),
+            events: vec![event("title", "alice", "a", 100), event("title", "bob", "b", 200)],
+            resolved: "b".to_string(),
+        };
+        let json: JsonValue = (&conflict).into();
+        assert_eq!(Conflict::try_from(&json).unwrap(), conflict);
+
+        let mut map = json.get::<HashMap<String, JsonValue>>().unwrap().clone();
+        map.insert("events".to_string(), JsonValue::Array(vec![JsonValue::Null]));
+        assert!(Conflict::try_from(&JsonValue::Object(map)).is_err());
+        assert!(Conflict::try_from(&JsonValue::Null).is_err());
+    }
+}
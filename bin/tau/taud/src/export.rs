@@ -0,0 +1,794 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2026 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Export and import of tasks in formats other tools understand, and the
+//! iCalendar feed of tasks with a due date.
+//!
+//! Times are written in UTC. Every format keeps the task attributes and
+//! the parent, dependencies and recurrence of tasks, but todo.txt drops
+//! descriptions and the time of day of due dates.
+
+use std::{collections::HashMap, fmt, fs, path::Path, str::FromStr};
+
+use chrono::{NaiveDate, NaiveDateTime, TimeZone, Utc};
+use tinyjson::JsonValue;
+use tracing::debug;
+
+use darkfi::{util::time::Timestamp, Error};
+
+use crate::{
+    error::{TaudError, TaudResult},
+    month_tasks::MonthTasks,
+    task_info::{State, TaskInfo},
+};
+
+/// Version of the JSON export schema, bumped on incompatible changes.
+pub const JSON_SCHEMA_VERSION: f64 = 1.0;
+
+const TIME_FORMAT: &str = "%Y-%m-%dT%H:%M:%SZ";
+const DATE_FORMAT: &str = "%Y-%m-%d";
+const ICAL_TIME_FORMAT: &str = "%Y%m%dT%H%M%SZ";
+
+/// Columns of the CSV format, also the attributes of the Markdown format.
+const FIELDS: [&str; 15] = [
+    "ref_id",
+    "title",
+    "desc",
+    "state",
+    "tags",
+    "assign",
+    "project",
+    "due",
+    "rank",
+    "bounty",
+    "created_at",
+    "owner",
+    "parent",
+    "blocked_by",
+    "recurrence",
+];
+
+/// Keys of the `key:value` pairs of the todo.txt format.
+const TODO_TXT_KEYS: [&str; 9] =
+    ["tag", "due", "rank", "bounty", "state", "ref", "parent", "blocked_by", "recurrence"];
+
+/// Formats tasks can be exported to and imported from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Csv,
+    Json,
+    Markdown,
+    TodoTxt,
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Format::Csv => write!(f, "csv"),
+            Format::Json => write!(f, "json"),
+            Format::Markdown => write!(f, "markdown"),
+            Format::TodoTxt => write!(f, "todo.txt"),
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let result = match s.to_lowercase().as_str() {
+            "csv" => Format::Csv,
+            "json" => Format::Json,
+            "markdown" | "md" => Format::Markdown,
+            "todo.txt" | "todotxt" => Format::TodoTxt,
+            _ => return Err(Error::ParseFailed("unable to parse export format")),
+        };
+        Ok(result)
+    }
+}
+
+/// Serialize `tasks` in the given format.
+pub fn export_tasks(tasks: &[TaskInfo], format: Format) -> TaudResult<String> {
+    debug!(target: "tau", "export_tasks() format {format}");
+    let exported = match format {
+        Format::Csv => {
+            let mut lines = vec![FIELDS.join(",")];
+            for task in tasks.iter() {
+                let row: Vec<String> =
+                    task_fields(task).iter().map(|(_, v)| csv_field(v)).collect();
+                lines.push(row.join(","));
+            }
+            lines.join("\r\n") + "\r\n"
+        }
+        Format::Json => {
+            let tasks: Vec<JsonValue> = tasks.iter().map(task_to_json).collect();
+            let json = JsonValue::Object(HashMap::from([
+                ("version".to_string(), JsonValue::Number(JSON_SCHEMA_VERSION)),
+                ("tasks".to_string(), JsonValue::Array(tasks)),
+            ]));
+            json.format().map_err(|e| TaudError::JsonError(e.to_string()))?
+        }
+        Format::Markdown => {
+            let mut out = String::from("# Tasks\n");
+            for task in tasks.iter() {
+                let check = if task.state == "stop" { 'x' } else { ' ' };
+                out.push_str(&format!("\n## [{check}] {}\n", task.title));
+                for (field, value) in task_fields(task) {
+                    if !matches!(field, "title" | "desc") && !value.is_empty() {
+                        out.push_str(&format!("- {field}: {value}\n"));
+                    }
+                }
+                if !task.desc.is_empty() {
+                    out.push_str(&format!("\n{}\n", markdown_desc(&task.desc)));
+                }
+            }
+            out
+        }
+        Format::TodoTxt => tasks.iter().map(|t| todo_txt_line(t) + "\n").collect(),
+    };
+
+    Ok(exported)
+}
+
+/// Parse tasks of `workspace` in the given format. Tasks without a
+/// `ref_id` get a new one, and those without an owner belong to `author`.
+pub fn import_tasks(
+    content: &str,
+    format: Format,
+    workspace: &str,
+    author: &str,
+) -> TaudResult<Vec<TaskInfo>> {
+    debug!(target: "tau", "import_tasks() format {format}");
+    match format {
+        Format::Csv => {
+            let mut rows = parse_csv(content)?.into_iter();
+            let Some(header) = rows.next() else { return Ok(vec![]) };
+            rows.filter(|row| row.iter().any(|v| !v.is_empty()))
+                .map(|row| {
+                    let fields: HashMap<&str, String> =
+                        header.iter().map(String::as_str).zip(row).collect();
+                    task_from_fields(&fields, workspace, author)
+                })
+                .collect()
+        }
+        Format::Json => {
+            let json =
+                content.parse::<JsonValue>().map_err(|e| TaudError::JsonError(e.to_string()))?;
+            let Some(json) = json.get::<HashMap<String, JsonValue>>() else {
+                return Err(TaudError::InvalidData("Export is not an object".into()))
+            };
+            if json.get("version").and_then(|v| v.get::<f64>()) != Some(&JSON_SCHEMA_VERSION) {
+                return Err(TaudError::InvalidData("Unsupported JSON export version".into()))
+            }
+            let Some(tasks) = json.get("tasks").and_then(|v| v.get::<Vec<JsonValue>>()) else {
+                return Err(TaudError::InvalidData("Missing \"tasks\" list".into()))
+            };
+            tasks.iter().map(|t| task_from_json(t, workspace, author)).collect()
+        }
+        Format::Markdown => parse_markdown(content)
+            .iter()
+            .map(|fields| task_from_fields(fields, workspace, author))
+            .collect(),
+        Format::TodoTxt => content
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| task_from_todo_txt(line, workspace, author))
+            .collect(),
+    }
+}
+
+/// iCalendar feed of the tasks with a due date, one to-do each.
+pub fn ical_feed(tasks: &[TaskInfo], workspace: &str) -> String {
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        "PRODID:-//dark.fi//taud//EN".to_string(),
+        format!("X-WR-CALNAME:{}", ical_text(workspace)),
+    ];
+
+    for task in tasks.iter() {
+        let Some(due) = task.due else { continue };
+        let modified = task.events.iter().map(|e| e.timestamp).max().unwrap_or(task.created_at);
+        let status = match task.state.as_str() {
+            "stop" => "COMPLETED",
+            "start" => "IN-PROCESS",
+            _ => "NEEDS-ACTION",
+        };
+
+        lines.push("BEGIN:VTODO".to_string());
+        lines.push(format!("UID:{}@taud", task.ref_id));
+        lines.push(format!("DTSTAMP:{}", format_time(modified, ICAL_TIME_FORMAT)));
+        lines.push(format!("CREATED:{}", format_time(task.created_at, ICAL_TIME_FORMAT)));
+        lines.push(format!("DUE:{}", format_time(due, ICAL_TIME_FORMAT)));
+        lines.push(format!("SUMMARY:{}", ical_text(&task.title)));
+        if !task.desc.is_empty() {
+            lines.push(format!("DESCRIPTION:{}", ical_text(&task.desc)));
+        }
+        lines.push(format!("STATUS:{status}"));
+        if !task.tags.is_empty() {
+            let tags: Vec<String> = task.tags.iter().map(|t| ical_text(t)).collect();
+            lines.push(format!("CATEGORIES:{}", tags.join(",")));
+        }
+        lines.push("END:VTODO".to_string());
+    }
+    lines.push("END:VCALENDAR".to_string());
+
+    lines.iter().map(|l| ical_fold(l) + "\r\n").collect()
+}
+
+/// Write the iCalendar feed of the tasks of `workspace` to
+/// `<dir>/<workspace>.ics`, replacing the previous one at once so
+/// subscribed calendars never read a partial feed.
+pub fn save_ical_feed(dataset_path: &Path, workspace: &str, dir: &Path) -> TaudResult<()> {
+    debug!(target: "tau", "save_ical_feed() workspace {workspace}");
+    let tasks = MonthTasks::load_current_tasks(dataset_path, workspace.to_string(), true)?;
+    fs::create_dir_all(dir)?;
+    let path = dir.join(format!("{workspace}.ics"));
+    let tmp_path = dir.join(format!(".{workspace}.ics.tmp"));
+    fs::write(&tmp_path, ical_feed(&tasks, workspace))?;
+    fs::rename(tmp_path, path)?;
+    Ok(())
+}
+
+fn format_time(ts: Timestamp, format: &str) -> String {
+    Utc.timestamp_opt(ts.inner().try_into().unwrap(), 0).unwrap().format(format).to_string()
+}
+
+/// Parse a time written as `2024-01-31T12:00:00Z` or `2024-01-31`.
+fn parse_time(s: &str) -> TaudResult<Timestamp> {
+    let secs = if let Ok(dt) = NaiveDateTime::parse_from_str(s, TIME_FORMAT) {
+        dt.and_utc().timestamp()
+    } else if let Ok(date) = NaiveDate::parse_from_str(s, DATE_FORMAT) {
+        date.and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp()
+    } else {
+        return Err(TaudError::InvalidData(format!("Invalid time \"{s}\"")))
+    };
+
+    match u64::try_from(secs) {
+        Ok(secs) => Ok(Timestamp::from_u64(secs)),
+        Err(_) => Err(TaudError::InvalidData(format!("Invalid time \"{s}\""))),
+    }
+}
+
+fn parse_number(s: &str, field: &str) -> TaudResult<Option<f32>> {
+    if s.is_empty() {
+        return Ok(None)
+    }
+    match s.parse::<f32>() {
+        Ok(n) => Ok(Some(n)),
+        Err(_) => Err(TaudError::InvalidData(format!("Invalid {field} \"{s}\""))),
+    }
+}
+
+/// Attributes of a task as text, in the order of [`FIELDS`].
+/// Lists are separated by spaces and missing values are empty.
+fn task_fields(task: &TaskInfo) -> Vec<(&'static str, String)> {
+    let number = |n: Option<f32>| n.map(|n| n.to_string()).unwrap_or_default();
+    vec![
+        ("ref_id", task.ref_id.clone()),
+        ("title", task.title.clone()),
+        ("desc", task.desc.clone()),
+        ("state", task.state.clone()),
+        ("tags", task.tags.join(" ")),
+        ("assign", task.assign.join(" ")),
+        ("project", task.project.join(" ")),
+        ("due", task.due.map(|d| format_time(d, TIME_FORMAT)).unwrap_or_default()),
+        ("rank", number(task.rank)),
+        ("bounty", number(task.bounty)),
+        ("created_at", format_time(task.created_at, TIME_FORMAT)),
+        ("owner", task.owner.clone()),
+        ("parent", task.parent().unwrap_or_default()),
+        ("blocked_by", task.blocked_by().join(" ")),
+        ("recurrence", task.recurrence().map(|r| r.to_string()).unwrap_or_default()),
+    ]
+}
+
+/// Build a task from attributes as written by [`task_fields`].
+fn task_from_fields(
+    fields: &HashMap<&str, String>,
+    workspace: &str,
+    author: &str,
+) -> TaudResult<TaskInfo> {
+    let get = |field: &str| fields.get(field).map(|v| v.trim()).unwrap_or("");
+    let list =
+        |field: &str| -> Vec<String> { get(field).split_whitespace().map(String::from).collect() };
+
+    let mut task = new_task(get("title"), workspace, author)?;
+    if !get("ref_id").is_empty() {
+        task.ref_id = get("ref_id").to_string();
+    }
+    task.desc = fields.get("desc").cloned().unwrap_or_default();
+    if !get("state").is_empty() {
+        task.state = get("state").parse::<State>()?.to_string();
+    }
+    task.tags = list("tags");
+    task.assign = list("assign");
+    task.project = list("project");
+    if !get("due").is_empty() {
+        task.due = Some(parse_time(get("due"))?);
+    }
+    task.rank = parse_number(get("rank"), "rank")?;
+    task.bounty = parse_number(get("bounty"), "bounty")?;
+    if !get("created_at").is_empty() {
+        task.created_at = parse_time(get("created_at"))?;
+    }
+    if !get("owner").is_empty() {
+        task.owner = get("owner").to_string();
+    }
+
+    if !get("parent").is_empty() {
+        task.set_parent(Some(get("parent")), author);
+    }
+    for blocker in list("blocked_by") {
+        task.add_blocker(&blocker, author);
+    }
+    if !get("recurrence").is_empty() {
+        task.set_recurrence(Some(get("recurrence").parse()?), author);
+    }
+
+    Ok(task)
+}
+
+/// A new open task. Unlike [`TaskInfo::new`], imported tasks may be overdue.
+fn new_task(title: &str, workspace: &str, author: &str) -> TaudResult<TaskInfo> {
+    if title.is_empty() {
+        return Err(TaudError::InvalidData("Task without a title".into()))
+    }
+    TaskInfo::new(
+        workspace.to_string(),
+        title,
+        "",
+        author,
+        None,
+        None,
+        Timestamp::current_time(),
+        None,
+    )
+}
+
+fn task_to_json(task: &TaskInfo) -> JsonValue {
+    let string = |s: &str| JsonValue::String(s.to_string());
+    let list = |l: &[String]| JsonValue::Array(l.iter().map(|s| string(s)).collect());
+    let number = |n: Option<f64>| n.map_or(JsonValue::Null, JsonValue::Number);
+
+    JsonValue::Object(HashMap::from([
+        ("ref_id".to_string(), string(&task.ref_id)),
+        ("title".to_string(), string(&task.title)),
+        ("desc".to_string(), string(&task.desc)),
+        ("state".to_string(), string(&task.state)),
+        ("tags".to_string(), list(&task.tags)),
+        ("assign".to_string(), list(&task.assign)),
+        ("project".to_string(), list(&task.project)),
+        ("due".to_string(), number(task.due.map(|d| d.inner() as f64))),
+        ("rank".to_string(), number(task.rank.map(f64::from))),
+        ("bounty".to_string(), number(task.bounty.map(f64::from))),
+        ("created_at".to_string(), JsonValue::Number(task.created_at.inner() as f64)),
+        ("owner".to_string(), string(&task.owner)),
+        ("parent".to_string(), task.parent().map_or(JsonValue::Null, JsonValue::String)),
+        ("blocked_by".to_string(), list(&task.blocked_by())),
+        (
+            "recurrence".to_string(),
+            task.recurrence().map_or(JsonValue::Null, |r| JsonValue::String(r.to_string())),
+        ),
+    ]))
+}
+
+fn task_from_json(value: &JsonValue, workspace: &str, author: &str) -> TaudResult<TaskInfo> {
+    let Some(obj) = value.get::<HashMap<String, JsonValue>>() else {
+        return Err(TaudError::InvalidData("Task is not an object".into()))
+    };
+    let string = |key: &str| obj.get(key).and_then(|v| v.get::<String>()).cloned();
+    let number = |key: &str| obj.get(key).and_then(|v| v.get::<f64>()).copied();
+    let list = |key: &str| -> Vec<String> {
+        let Some(list) = obj.get(key).and_then(|v| v.get::<Vec<JsonValue>>()) else {
+            return vec![]
+        };
+        list.iter().filter_map(|v| v.get::<String>()).cloned().collect()
+    };
+
+    let mut task = new_task(&string("title").unwrap_or_default(), workspace, author)?;
+    if let Some(ref_id) = string("ref_id") {
+        task.ref_id = ref_id;
+    }
+    task.desc = string("desc").unwrap_or_default();
+    if let Some(state) = string("state") {
+        task.state = state.parse::<State>()?.to_string();
+    }
+    task.tags = list("tags");
+    task.assign = list("assign");
+    task.project = list("project");
+    task.due = number("due").map(|d| Timestamp::from_u64(d as u64));
+    task.rank = number("rank").map(|r| r as f32);
+    task.bounty = number("bounty").map(|b| b as f32);
+    if let Some(created_at) = number("created_at") {
+        task.created_at = Timestamp::from_u64(created_at as u64);
+    }
+    if let Some(owner) = string("owner") {
+        task.owner = owner;
+    }
+
+    if let Some(parent) = string("parent") {
+        task.set_parent(Some(&parent), author);
+    }
+    for blocker in list("blocked_by") {
+        task.add_blocker(&blocker, author);
+    }
+    if let Some(recurrence) = string("recurrence") {
+        task.set_recurrence(Some(recurrence.parse()?), author);
+    }
+
+    Ok(task)
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Rows of RFC 4180 CSV. Quoted fields may hold commas, quotes written
+/// twice and line breaks.
+fn parse_csv(content: &str) -> TaudResult<Vec<Vec<String>>> {
+    let mut rows = vec![];
+    let mut row = vec![];
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = content.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            '"' if quoted => quoted = false,
+            '"' if field.is_empty() => quoted = true,
+            ',' if !quoted => row.push(std::mem::take(&mut field)),
+            '\r' if !quoted && chars.peek() == Some(&'\n') => {}
+            '\n' if !quoted => {
+                row.push(std::mem::take(&mut field));
+                rows.push(std::mem::take(&mut row));
+            }
+            _ => field.push(c),
+        }
+    }
+
+    if quoted {
+        return Err(TaudError::InvalidData("Unterminated quoted CSV field".into()))
+    }
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+
+    Ok(rows)
+}
+
+/// A description in a Markdown export. Lines that would read as a task
+/// heading, and those starting with the backslash escaping them, get a
+/// leading backslash.
+fn markdown_desc(desc: &str) -> String {
+    let escape = |line: &str| {
+        if line.starts_with("## [") || line.starts_with('\\') {
+            format!("\\{line}")
+        } else {
+            line.to_string()
+        }
+    };
+    desc.split('\n').map(escape).collect::<Vec<String>>().join("\n")
+}
+
+/// Attributes of the tasks of a Markdown export. Each task is a `## [ ]`
+/// heading with its title, a list of `- field: value` attributes and its
+/// description after a blank line, escaped by [`markdown_desc`].
+fn parse_markdown(content: &str) -> Vec<HashMap<&'static str, String>> {
+    let mut tasks: Vec<HashMap<&'static str, String>> = vec![];
+    let mut desc: Vec<&str> = vec![];
+    let mut in_attrs = false;
+
+    for line in content.lines().chain(["## [ ] "]) {
+        let heading = line.strip_prefix("## [").and_then(|l| l.split_once("] "));
+        if let Some((check, title)) = heading {
+            if let Some(task) = tasks.last_mut() {
+                task.insert("desc", desc.join("\n").trim_matches('\n').to_string());
+            }
+            desc.clear();
+
+            let state = if check.eq_ignore_ascii_case("x") { "stop" } else { "open" };
+            tasks.push(HashMap::from([("title", title.to_string()), ("state", state.to_string())]));
+            in_attrs = true;
+            continue
+        }
+        let Some(task) = tasks.last_mut() else { continue };
+
+        if in_attrs {
+            let attr = line.strip_prefix("- ").and_then(|l| l.split_once(": "));
+            if let Some((field, value)) = attr {
+                if let Some(field) = FIELDS.iter().find(|f| **f == field) {
+                    task.insert(*field, value.to_string());
+                    continue
+                }
+            }
+            in_attrs = false;
+        }
+        desc.push(line.strip_prefix('\\').unwrap_or(line));
+    }
+    // Drop the sentinel heading ending the last task
+    tasks.pop();
+
+    tasks
+}
+
+/// A task as a todo.txt line: completion mark and date, creation date,
+/// title, `+project` and `@assignee` words, and `key:value` pairs for the
+/// other attributes. Title words that would read as one of these get a
+/// leading backslash.
+fn todo_txt_line(task: &TaskInfo) -> String {
+    let mut words = vec![];
+    if task.state == "stop" {
+        let done = task
+            .events
+            .iter()
+            .rev()
+            .find(|e| e.action == "state" && e.content == "stop")
+            .map_or(task.created_at, |e| e.timestamp);
+        words.push("x".to_string());
+        words.push(format_time(done, DATE_FORMAT));
+    }
+    words.push(format_time(task.created_at, DATE_FORMAT));
+    for (i, word) in task.title.split_whitespace().enumerate() {
+        let escaped = word.starts_with(['+', '@', '\\']) ||
+            word.split_once(':').is_some_and(|(key, _)| TODO_TXT_KEYS.contains(&key)) ||
+            (i == 0 && (word == "x" || NaiveDate::parse_from_str(word, DATE_FORMAT).is_ok()));
+        words.push(if escaped { format!("\\{word}") } else { word.to_string() });
+    }
+    words.extend(task.project.iter().map(|p| format!("+{p}")));
+    words.extend(task.assign.iter().map(|a| format!("@{a}")));
+    words.extend(task.tags.iter().map(|t| format!("tag:{t}")));
+    if let Some(due) = task.due {
+        words.push(format!("due:{}", format_time(due, DATE_FORMAT)));
+    }
+    if let Some(rank) = task.rank {
+        words.push(format!("rank:{rank}"));
+    }
+    if let Some(bounty) = task.bounty {
+        words.push(format!("bounty:{bounty}"));
+    }
+    if task.state == "start" || task.state == "pause" {
+        words.push(format!("state:{}", task.state));
+    }
+    if let Some(parent) = task.parent() {
+        words.push(format!("parent:{parent}"));
+    }
+    words.extend(task.blocked_by().iter().map(|b| format!("blocked_by:{b}")));
+    if let Some(recurrence) = task.recurrence() {
+        words.push(format!("recurrence:{recurrence}"));
+    }
+    words.push(format!("ref:{}", task.ref_id));
+
+    words.join(" ")
+}
+
+fn task_from_todo_txt(line: &str, workspace: &str, author: &str) -> TaudResult<TaskInfo> {
+    let mut words = line.split_whitespace().peekable();
+    let done = words.next_if_eq(&"x").is_some();
+
+    // Priority, then the completion date of done tasks and the creation date
+    words.next_if(|w| w.len() == 3 && w.starts_with('(') && w.ends_with(')'));
+    let mut dates = vec![];
+    while let Some(date) = words.next_if(|w| NaiveDate::parse_from_str(w, DATE_FORMAT).is_ok()) {
+        dates.push(date);
+    }
+    let created_at = if done { dates.get(1) } else { dates.first() };
+
+    let mut title = vec![];
+    let mut fields: HashMap<&str, String> = HashMap::new();
+    let mut push = |field: &'static str, value: &str| {
+        let entry = fields.entry(field).or_default();
+        if !entry.is_empty() {
+            entry.push(' ');
+        }
+        entry.push_str(value);
+    };
+    for word in words {
+        match word.split_once(':') {
+            _ if word.starts_with('\\') => title.push(&word[1..]),
+            _ if word.len() > 1 && word.starts_with('+') => push("project", &word[1..]),
+            _ if word.len() > 1 && word.starts_with('@') => push("assign", &word[1..]),
+            Some(("tag", tag)) => push("tags", tag),
+            Some(("due", due)) => push("due", due),
+            Some(("rank", rank)) => push("rank", rank),
+            Some(("bounty", bounty)) => push("bounty", bounty),
+            Some(("state", state)) => push("state", state),
+            Some(("ref", ref_id)) => push("ref_id", ref_id),
+            Some(("parent", parent)) => push("parent", parent),
+            Some(("blocked_by", blocker)) => push("blocked_by", blocker),
+            Some(("recurrence", recurrence)) => push("recurrence", recurrence),
+            _ => title.push(word),
+        }
+    }
+
+    fields.insert("title", title.join(" "));
+    if done {
+        fields.insert("state", "stop".to_string());
+    }
+    if let Some(created_at) = created_at {
+        fields.insert("created_at", created_at.to_string());
+    }
+
+    task_from_fields(&fields, workspace, author)
+}
+
+/// Escape text for an iCalendar property value.
+fn ical_text(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace("\r\n", "\\n")
+        .replace(['\r', '\n'], "\\n")
+}
+
+/// Fold an iCalendar content line into lines of at most 75 bytes.
+fn ical_fold(line: &str) -> String {
+    let mut folded = String::new();
+    let mut len = 0;
+    for c in line.chars() {
+        if len + c.len_utf8() > 75 {
+            folded.push_str("\r\n ");
+            len = 1;
+        }
+        folded.push(c);
+        len += c.len_utf8();
+    }
+    folded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn task(title: &str, desc: &str) -> TaskInfo {
+        let mut task = new_task(title, "darkfi", "alice").unwrap();
+        task.desc = desc.to_string();
+        task.created_at = Timestamp::from_u64(1_706_702_400); // 2024-01-31T12:00:00
+        task
+    }
+
+    fn sample_tasks() -> Vec<TaskInfo> {
+        let mut first = task("Write, \"quote\" and test", "line one\n\nline three");
+        first.tags = vec!["dev".to_string(), "bug".to_string()];
+        first.assign = vec!["bob".to_string()];
+        first.project = vec!["tau".to_string()];
+        first.due = Some(Timestamp::from_u64(1_709_208_000)); // 2024-02-29T12:00:00
+        first.rank = Some(1.5);
+        first.state = "start".to_string();
+
+        let mut second = task("Ship it", "");
+        second.bounty = Some(20.0);
+        second.state = "stop".to_string();
+
+        vec![first, second]
+    }
+
+    #[test]
+    fn tasks_round_trip_through_every_format() {
+        let tasks = sample_tasks();
+
+        for format in [Format::Csv, Format::Json, Format::Markdown] {
+            let exported = export_tasks(&tasks, format).unwrap();
+            let imported = import_tasks(&exported, format, "darkfi", "carol").unwrap();
+            assert_eq!(imported, tasks, "{format}");
+        }
+
+        // todo.txt keeps no descriptions and only the day of due dates.
+        let exported = export_tasks(&tasks, Format::TodoTxt).unwrap();
+        let imported = import_tasks(&exported, Format::TodoTxt, "darkfi", "alice").unwrap();
+        assert_eq!(imported.len(), 2);
+        assert_eq!(imported[0].title, tasks[0].title);
+        assert_eq!(imported[0].ref_id, tasks[0].ref_id);
+        assert_eq!(imported[0].tags, tasks[0].tags);
+        assert_eq!(imported[0].assign, tasks[0].assign);
+        assert_eq!(imported[0].project, tasks[0].project);
+        assert_eq!(imported[0].state, "start");
+        assert_eq!(imported[0].due, Some(parse_time("2024-02-29").unwrap()));
+        assert_eq!(imported[0].created_at, parse_time("2024-01-31").unwrap());
+        assert_eq!(imported[1].state, "stop");
+        assert_eq!(imported[1].bounty, Some(20.0));
+    }
+
+    #[test]
+    fn exports_keep_relations() {
+        let mut tasks = sample_tasks();
+        let parent = tasks[1].ref_id.clone();
+        tasks[0].set_parent(Some(&parent), "alice");
+        tasks[0].add_blocker(&parent, "alice");
+        tasks[0].add_blocker("other", "alice");
+        tasks[0].set_recurrence(Some("weekly".parse().unwrap()), "alice");
+
+        for format in [Format::Csv, Format::Json, Format::Markdown, Format::TodoTxt] {
+            let exported = export_tasks(&tasks, format).unwrap();
+            let imported = import_tasks(&exported, format, "darkfi", "carol").unwrap();
+            assert_eq!(imported[0].parent(), Some(parent.clone()), "{format}");
+            assert_eq!(imported[0].blocked_by(), tasks[0].blocked_by(), "{format}");
+            assert_eq!(imported[0].recurrence(), tasks[0].recurrence(), "{format}");
+            assert!(imported[1].parent().is_none() && imported[1].blocked_by().is_empty());
+        }
+
+        let exported = export_tasks(&tasks, Format::Json).unwrap();
+        let mut json: JsonValue = exported.parse().unwrap();
+        if let JsonValue::Object(obj) = &mut json {
+            obj.insert("version".to_string(), JsonValue::Number(2.0));
+        }
+        let wrong_version = json.stringify().unwrap();
+        assert!(import_tasks(&wrong_version, Format::Json, "darkfi", "carol").is_err());
+    }
+
+    #[test]
+    fn foreign_todo_txt_lines_are_imported() {
+        let todo = "(A) 2024-03-01 Call Mom +Family @phone due:2024-03-02 https://example.com\n\
+                    x 2024-03-05 2024-03-01 Pay rent\n";
+        let tasks = import_tasks(todo, Format::TodoTxt, "darkfi", "alice").unwrap();
+        assert_eq!(tasks[0].title, "Call Mom https://example.com");
+        assert_eq!(tasks[0].project, vec!["Family".to_string()]);
+        assert_eq!(tasks[0].assign, vec!["phone".to_string()]);
+        assert_eq!(tasks[0].owner, "alice");
+        assert_eq!(tasks[0].state, "open");
+        assert_eq!(tasks[1].state, "stop");
+        assert_eq!(tasks[1].created_at, parse_time("2024-03-01").unwrap());
+    }
+
+    #[test]
+    fn markup_in_titles_and_descriptions_is_escaped() {
+        let mut tasks = sample_tasks();
+        tasks[0].title = "2024-05-01 review +1 @home due:soon \\o/ x 12:30".to_string();
+        tasks[0].desc = "intro\n## [ ] not a task\n\\ kept\n- rank: 3".to_string();
+        tasks[1].title = "x marks the spot".to_string();
+
+        let exported = export_tasks(&tasks, Format::TodoTxt).unwrap();
+        let imported = import_tasks(&exported, Format::TodoTxt, "darkfi", "alice").unwrap();
+        assert_eq!(imported[0].title, tasks[0].title);
+        assert_eq!(imported[0].project, tasks[0].project);
+        assert_eq!(imported[0].due, Some(parse_time("2024-02-29").unwrap()));
+        assert_eq!(imported[1].title, tasks[1].title);
+        assert_eq!(imported[1].state, "stop");
+
+        let exported = export_tasks(&tasks, Format::Markdown).unwrap();
+        let imported = import_tasks(&exported, Format::Markdown, "darkfi", "alice").unwrap();
+        assert_eq!(imported, tasks);
+    }
+
+    #[test]
+    fn ical_feed_lists_due_tasks() {
+        let mut tasks = sample_tasks();
+        tasks[0].title = "A very long title, long enough to need folding across lines".repeat(2);
+        tasks[0].desc = "line one\r\n\rline three".to_string();
+        let feed = ical_feed(&tasks, "darkfi");
+
+        assert!(feed.starts_with("BEGIN:VCALENDAR\r\n"));
+        assert!(feed.ends_with("END:VCALENDAR\r\n"));
+        assert_eq!(feed.matches("BEGIN:VTODO").count(), 1);
+        assert!(feed.contains(&format!("UID:{}@taud\r\n", tasks[0].ref_id)));
+        assert!(feed.contains("DUE:20240229T120000Z\r\n"));
+        assert!(feed.contains("STATUS:IN-PROCESS\r\n"));
+        assert!(feed.contains("DESCRIPTION:line one\\n\\nline three\r\n"));
+        assert!(feed.contains("SUMMARY:A very long title\\, long enough"));
+        assert!(feed.split("\r\n").all(|line| line.len() <= 75));
+    }
+}
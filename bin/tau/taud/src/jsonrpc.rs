@@ -18,7 +18,7 @@
 
 use std::{
     collections::{BTreeMap, HashMap, HashSet},
-    fs::create_dir_all,
+    fs::{create_dir_all, read_to_string, write},
     path::PathBuf,
     sync::Arc,
 };
@@ -29,7 +29,7 @@ use tinyjson::JsonValue;
 use tracing::{debug, info, warn};
 
 use darkfi::{
-    event_graph::EventGraphPtr,
+    event_graph::{proto::EventPut, Event, EventGraphPtr},
     net,
     rpc::{
         jsonrpc::{ErrorCode, JsonError, JsonRequest, JsonResult, JsonSubscriber},
@@ -43,12 +43,13 @@ use darkfi::{
 
 use taud::{
     error::{to_json_result, TaudError, TaudResult},
+    export::{export_tasks, import_tasks, Format},
     month_tasks::MonthTasks,
-    task_info::{Comment, TaskInfo},
+    task_info::{is_ancestor, is_blocked_by, subtasks, Comment, Recurrence, TaskInfo},
     util::set_event,
 };
 
-use crate::Workspace;
+use crate::{sign_redaction, Workspace};
 
 pub struct JsonRpcInterface {
     dataset_path: PathBuf,
@@ -74,6 +75,13 @@ impl RequestHandler<()> for JsonRpcInterface {
             "set_state" => self.set_state(req.params).await,
             "set_comment" => self.set_comment(req.params).await,
             "get_task_by_ref_id" => self.get_task_by_ref_id(req.params).await,
+            "set_parent" => self.set_parent(req.params).await,
+            "get_subtasks" => self.get_subtasks(req.params).await,
+            "add_dependency" => self.add_dependency(req.params).await,
+            "remove_dependency" => self.remove_dependency(req.params).await,
+            "set_recurrence" => self.set_recurrence(req.params).await,
+            "get_conflicts" => self.get_conflicts(req.params).await,
+            "redact" => self.redact(req.params).await,
             "switch_ws" => self.switch_ws(req.params).await,
             "get_ws" => self.get_ws(req.params).await,
             "export" => self.export_to(req.params).await,
@@ -90,6 +98,8 @@ impl RequestHandler<()> for JsonRpcInterface {
             "eventgraph.get_info" => return self.eg_get_info(req.id, req.params).await,
 
             "p2p.get_info" => return self.p2p_get_info(req.id, req.params).await,
+            "p2p.get_hosts" => return self.p2p_get_hosts(req.id, req.params).await,
+
             _ => return JsonError::new(ErrorCode::MethodNotFound, None, req.id).into(),
         };
 
@@ -422,6 +432,8 @@ impl JsonRpcInterface {
 
     // RPCAPI:
     // Set state for a task and returns `true` upon success.
+    // A task can't be stopped while one of the tasks it is blocked by is
+    // still active. Stopping a recurring task adds its next occurrence.
     // --> {"jsonrpc": "2.0", "method": "set_state", "params": [task_id, state], "id": 1}
     // <-- {"jsonrpc": "2.0", "result": true, "id": 1}
     async fn set_state(&self, params: JsonValue) -> TaudResult<JsonValue> {
@@ -443,13 +455,202 @@ impl JsonRpcInterface {
         }
 
         let mut task: TaskInfo =
-            self.load_task_by_ref_id(params[0].get::<String>().unwrap(), ws)?;
+            self.load_task_by_ref_id(params[0].get::<String>().unwrap(), ws.clone())?;
 
+        let mut next = None;
         if states.contains(&state.as_str()) {
+            if state == "stop" {
+                let active = MonthTasks::load_current_tasks(&self.dataset_path, ws, false)?;
+                let blockers: Vec<String> = task
+                    .blocked_by()
+                    .into_iter()
+                    .filter(|b| active.iter().any(|t| t.ref_id == *b))
+                    .collect();
+                if !blockers.is_empty() {
+                    return Err(TaudError::InvalidData(format!(
+                        "Task is blocked by {}",
+                        blockers.join(", ")
+                    )))
+                }
+            }
+
             task.set_state(state);
             set_event(&mut task, "state", &self.nickname, state);
+
+            if state == "stop" {
+                next = task.spawn_next(&self.nickname)?;
+            }
+        }
+
+        self.notify_queue_sender.send(task).await.map_err(Error::from)?;
+        if let Some(next) = next {
+            info!(target: "tau", "Recurring task spawned {}", next.ref_id);
+            self.notify_queue_sender.send(next).await.map_err(Error::from)?;
+        }
+
+        Ok(JsonValue::Boolean(true))
+    }
+
+    // RPCAPI:
+    // Make a task a subtask of another one, or a top-level task again with
+    // a `null` parent. Returns `true` upon success.
+    // --> {"jsonrpc": "2.0", "method": "set_parent", "params": [task_id, parent_id], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": true, "id": 1}
+    async fn set_parent(&self, params: JsonValue) -> TaudResult<JsonValue> {
+        let params = params.get::<Vec<JsonValue>>().unwrap();
+        debug!(target: "tau", "JsonRpc::set_parent() params {params:?}");
+
+        if params.len() != 2 ||
+            !params[0].is_string() ||
+            !(params[1].is_string() || params[1].is_null())
+        {
+            return Err(TaudError::InvalidData("len of params should be 2".into()))
+        }
+
+        let ws = self.workspace.lock().await.clone();
+        if self.workspaces.get(&ws).unwrap().write_key.is_none() {
+            info!("You don't have write access!");
+            return Ok(JsonValue::Boolean(false))
+        }
+
+        let ref_id = params[0].get::<String>().unwrap();
+        let parent = params[1].get::<String>();
+        let mut task: TaskInfo = self.load_task_by_ref_id(ref_id, ws.clone())?;
+
+        if let Some(parent) = parent {
+            let tasks = MonthTasks::load_current_tasks(&self.dataset_path, ws, true)?;
+            if !tasks.iter().any(|t| t.ref_id == *parent) {
+                return Err(TaudError::InvalidId)
+            }
+            if is_ancestor(&tasks, parent, ref_id) {
+                return Err(TaudError::InvalidData("Parent would create a cycle".into()))
+            }
+        }
+
+        task.set_parent(parent.map(String::as_str), &self.nickname);
+        self.notify_queue_sender.send(task).await.map_err(Error::from)?;
+
+        Ok(JsonValue::Boolean(true))
+    }
+
+    // RPCAPI:
+    // Get the subtasks of a task.
+    // --> {"jsonrpc": "2.0", "method": "get_subtasks", "params": [task_id], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": [task, ...], "id": 1}
+    async fn get_subtasks(&self, params: JsonValue) -> TaudResult<JsonValue> {
+        let params = params.get::<Vec<JsonValue>>().unwrap();
+        debug!(target: "tau", "JsonRpc::get_subtasks() params {params:?}");
+
+        if params.len() != 1 || !params[0].is_string() {
+            return Err(TaudError::InvalidData("len of params should be 1".into()))
+        }
+
+        let ws = self.workspace.lock().await.clone();
+        let tasks = MonthTasks::load_current_tasks(&self.dataset_path, ws, true)?;
+        let subtasks: Vec<JsonValue> = subtasks(&tasks, params[0].get::<String>().unwrap())
+            .into_iter()
+            .map(|t| t.into())
+            .collect();
+
+        Ok(JsonValue::Array(subtasks))
+    }
+
+    // RPCAPI:
+    // Mark a task as blocked by another one and returns `true` upon success.
+    // Dependencies that would form a cycle are refused.
+    // --> {"jsonrpc": "2.0", "method": "add_dependency", "params": [task_id, blocker_id], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": true, "id": 1}
+    async fn add_dependency(&self, params: JsonValue) -> TaudResult<JsonValue> {
+        let params = params.get::<Vec<JsonValue>>().unwrap();
+        debug!(target: "tau", "JsonRpc::add_dependency() params {params:?}");
+
+        if params.len() != 2 || !params[0].is_string() || !params[1].is_string() {
+            return Err(TaudError::InvalidData("len of params should be 2".into()))
         }
 
+        let ws = self.workspace.lock().await.clone();
+        if self.workspaces.get(&ws).unwrap().write_key.is_none() {
+            info!("You don't have write access!");
+            return Ok(JsonValue::Boolean(false))
+        }
+
+        let ref_id = params[0].get::<String>().unwrap();
+        let blocker = params[1].get::<String>().unwrap();
+        let mut task: TaskInfo = self.load_task_by_ref_id(ref_id, ws.clone())?;
+
+        let tasks = MonthTasks::load_current_tasks(&self.dataset_path, ws, true)?;
+        if !tasks.iter().any(|t| t.ref_id == *blocker) {
+            return Err(TaudError::InvalidId)
+        }
+        if ref_id == blocker || is_blocked_by(&tasks, blocker, ref_id) {
+            return Err(TaudError::InvalidData("Dependency would create a cycle".into()))
+        }
+
+        task.add_blocker(blocker, &self.nickname);
+        self.notify_queue_sender.send(task).await.map_err(Error::from)?;
+
+        Ok(JsonValue::Boolean(true))
+    }
+
+    // RPCAPI:
+    // Remove a "blocked by" dependency and returns `true` upon success.
+    // --> {"jsonrpc": "2.0", "method": "remove_dependency", "params": [task_id, blocker_id], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": true, "id": 1}
+    async fn remove_dependency(&self, params: JsonValue) -> TaudResult<JsonValue> {
+        let params = params.get::<Vec<JsonValue>>().unwrap();
+        debug!(target: "tau", "JsonRpc::remove_dependency() params {params:?}");
+
+        if params.len() != 2 || !params[0].is_string() || !params[1].is_string() {
+            return Err(TaudError::InvalidData("len of params should be 2".into()))
+        }
+
+        let ws = self.workspace.lock().await.clone();
+        if self.workspaces.get(&ws).unwrap().write_key.is_none() {
+            info!("You don't have write access!");
+            return Ok(JsonValue::Boolean(false))
+        }
+
+        let mut task: TaskInfo =
+            self.load_task_by_ref_id(params[0].get::<String>().unwrap(), ws)?;
+        task.remove_blocker(params[1].get::<String>().unwrap(), &self.nickname);
+        self.notify_queue_sender.send(task).await.map_err(Error::from)?;
+
+        Ok(JsonValue::Boolean(true))
+    }
+
+    // RPCAPI:
+    // Set how often a task recurs: "daily", "weekly", "monthly", or `null`
+    // to stop it recurring. Returns `true` upon success.
+    // --> {"jsonrpc": "2.0", "method": "set_recurrence", "params": [task_id, "weekly"], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": true, "id": 1}
+    async fn set_recurrence(&self, params: JsonValue) -> TaudResult<JsonValue> {
+        let params = params.get::<Vec<JsonValue>>().unwrap();
+        debug!(target: "tau", "JsonRpc::set_recurrence() params {params:?}");
+
+        if params.len() != 2 ||
+            !params[0].is_string() ||
+            !(params[1].is_string() || params[1].is_null())
+        {
+            return Err(TaudError::InvalidData("len of params should be 2".into()))
+        }
+
+        let recurrence = match params[1].get::<String>() {
+            Some(r) => Some(
+                r.parse::<Recurrence>()
+                    .map_err(|_| TaudError::InvalidData("Invalid recurrence".into()))?,
+            ),
+            None => None,
+        };
+
+        let ws = self.workspace.lock().await.clone();
+        if self.workspaces.get(&ws).unwrap().write_key.is_none() {
+            info!("You don't have write access!");
+            return Ok(JsonValue::Boolean(false))
+        }
+
+        let mut task: TaskInfo =
+            self.load_task_by_ref_id(params[0].get::<String>().unwrap(), ws)?;
+        task.set_recurrence(recurrence, &self.nickname);
         self.notify_queue_sender.send(task).await.map_err(Error::from)?;
 
         Ok(JsonValue::Boolean(true))
@@ -505,6 +706,43 @@ impl JsonRpcInterface {
         Ok(task)
     }
 
+    // RPCAPI:
+    // Get the fields of active tasks that were edited concurrently by several
+    // peers, with the competing edits and the value each field was merged to.
+    // An empty list of params returns the conflicts of every task, otherwise
+    // only those of the given task. A later edit of a field clears its conflict.
+    // --> {"jsonrpc": "2.0", "method": "get_conflicts", "params": [task_id], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": [{"ref_id": task_id, "title": title, "conflicts": [conflict, ...]}, ...], "id": 1}
+    async fn get_conflicts(&self, params: JsonValue) -> TaudResult<JsonValue> {
+        let params = params.get::<Vec<JsonValue>>().unwrap();
+        debug!(target: "tau", "JsonRpc::get_conflicts() params {params:?}");
+
+        if params.len() > 1 || (params.len() == 1 && !params[0].is_string()) {
+            return Err(TaudError::InvalidData("len of params should be 0 or 1".into()))
+        }
+
+        let ws = self.workspace.lock().await.clone();
+        let tasks = match params.first() {
+            Some(ref_id) => vec![self.load_task_by_ref_id(ref_id.get::<String>().unwrap(), ws)?],
+            None => MonthTasks::load_current_tasks(&self.dataset_path, ws, false)?,
+        };
+
+        let conflicts: Vec<JsonValue> = tasks
+            .iter()
+            .filter(|t| !t.conflicts.is_empty())
+            .map(|t| {
+                let conflicts = t.conflicts.iter().map(|c| c.into()).collect();
+                JsonValue::Object(HashMap::from([
+                    ("ref_id".to_string(), JsonValue::String(t.ref_id.clone())),
+                    ("title".to_string(), JsonValue::String(t.title.clone())),
+                    ("conflicts".to_string(), JsonValue::Array(conflicts)),
+                ]))
+            })
+            .collect();
+
+        Ok(JsonValue::Array(conflicts))
+    }
+
     // RPCAPI:
     // Get all tasks.
     // --> {"jsonrpc": "2.0", "method": "fetch_deactive_tasks", "params": [task_id], "id": 1}
@@ -569,6 +807,44 @@ impl JsonRpcInterface {
         Ok(task)
     }
 
+    // RPCAPI:
+    // Redact a task update event, signed with the write key of the current
+    // workspace. Nodes having the workspace configured drop the event
+    // content, and serve a placeholder to syncing peers instead.
+    // --> {"jsonrpc": "2.0", "method": "redact", "params": [event_id], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": "true", "id": 1}
+    async fn redact(&self, params: JsonValue) -> TaudResult<JsonValue> {
+        let params = params.get::<Vec<JsonValue>>().unwrap();
+        debug!(target: "tau", "JsonRpc::redact() params {params:?}");
+
+        if params.len() != 1 || !params[0].is_string() {
+            return Err(TaudError::InvalidData("len of params should be 1".into()))
+        }
+
+        let Ok(target) = blake3::Hash::from_hex(params[0].get::<String>().unwrap()) else {
+            return Err(TaudError::InvalidData("Invalid event id".into()))
+        };
+
+        if self.event_graph.fetch_event_from_dags(&target).await?.is_none() {
+            warn!("Event {target} is not in the DAG");
+            return Ok(JsonValue::Boolean(false))
+        }
+
+        let ws = self.workspace.lock().await.clone();
+        let redaction = sign_redaction(target, &self.workspaces[&ws])?;
+        let event = Event::new(redaction.to_content(), &self.event_graph).await?;
+
+        let current_genesis = self.event_graph.current_genesis.read().await;
+        let dag_name = current_genesis.header.timestamp.to_string();
+        drop(current_genesis);
+
+        // Taud runs EventGraph with RLN disabled, so the blob is empty.
+        self.event_graph.insert_signal_with_blob(&event, &[], &dag_name).await?;
+        self.p2p.broadcast(&EventPut(event, vec![])).await?;
+
+        Ok(JsonValue::Boolean(true))
+    }
+
     // RPCAPI:
     // Switch tasks workspace.
     // --> {"jsonrpc": "2.0", "method": "switch_ws", "params": [workspace], "id": 1}
@@ -610,30 +886,58 @@ impl JsonRpcInterface {
     }
 
     // RPCAPI:
-    // Export tasks.
-    // --> {"jsonrpc": "2.0", "method": "export_to", "params": [path], "id": 1}
+    // Export tasks. With only a path, the tasks of the current workspace are
+    // saved in taud's own format under `path/exported_tasks`. Given a format
+    // (csv, json, markdown or todo.txt), they are written to the file at
+    // `path` instead, or to `path/exported_tasks` for a `null` format.
+    // Giving a month timestamp exports the tasks archived that month.
+    // --> {"jsonrpc": "2.0", "method": "export_to", "params": [path, format, month], "id": 1}
     // <-- {"jsonrpc": "2.0", "result": "true", "id": 1}
     async fn export_to(&self, params: JsonValue) -> TaudResult<JsonValue> {
         let params = params.get::<Vec<JsonValue>>().unwrap();
         debug!(target: "tau", "JsonRpc::export_to() params {params:?}");
 
-        if params.len() != 1 {
-            return Err(TaudError::InvalidData("len of params should be 1".into()))
+        if params.is_empty() || params.len() > 3 {
+            return Err(TaudError::InvalidData("len of params should be 1 to 3".into()))
         }
 
         if !params[0].is_string() {
             return Err(TaudError::InvalidData("Invalid path".into()))
         }
 
-        // mkdir datastore_path if not exists
+        let format = Self::parse_format(params.get(1))?;
+
+        let month = match params.get(2) {
+            None | Some(JsonValue::Null) => None,
+            Some(JsonValue::String(u64_str)) => match u64_str.parse::<u64>() {
+                Ok(v) => Some(Timestamp::from_u64(v)),
+                Err(e) => return Err(TaudError::InvalidData(e.to_string())),
+            },
+            Some(_) => return Err(TaudError::InvalidData("Invalid month".into())),
+        };
+
+        let ws = self.workspace.lock().await.clone();
+        let tasks = match month {
+            Some(month) => MonthTasks::load_stop_tasks(&self.dataset_path, ws, Some(&month))?,
+            None => MonthTasks::load_current_tasks(&self.dataset_path, ws, true)?,
+        };
+
         let path = params[0].get::<String>().unwrap();
-        let path = expand_path(path)?.join("exported_tasks");
+        let path = expand_path(path)?;
+
+        if let Some(format) = format {
+            if let Some(parent) = path.parent() {
+                create_dir_all(parent).map_err(Error::from)?;
+            }
+            write(&path, export_tasks(&tasks, format)?).map_err(Error::from)?;
+            return Ok(JsonValue::Boolean(true))
+        }
+
+        // mkdir datastore_path if not exists
+        let path = path.join("exported_tasks");
         create_dir_all(path.join("month")).map_err(Error::from)?;
         create_dir_all(path.join("task")).map_err(Error::from)?;
 
-        let ws = self.workspace.lock().await.clone();
-        let tasks = MonthTasks::load_current_tasks(&self.dataset_path, ws, true)?;
-
         for task in tasks {
             task.save(&path)?;
         }
@@ -642,37 +946,49 @@ impl JsonRpcInterface {
     }
 
     // RPCAPI:
-    // Import tasks.
-    // --> {"jsonrpc": "2.0", "method": "import_from", "params": [path], "id": 1}
+    // Import tasks. With only a path, the tasks saved in taud's own format
+    // under `path/exported_tasks` are added to the current workspace. Given
+    // a format (csv, json, markdown or todo.txt), they are read from the
+    // file at `path` instead. Tasks that already exist are skipped.
+    // --> {"jsonrpc": "2.0", "method": "import_from", "params": [path, format], "id": 1}
     // <-- {"jsonrpc": "2.0", "result": "true", "id": 1}
     async fn import_from(&self, params: JsonValue) -> TaudResult<JsonValue> {
         let params = params.get::<Vec<JsonValue>>().unwrap();
         debug!(target: "tau", "JsonRpc::import_from() params {params:?}");
 
-        if params.len() != 1 {
-            return Err(TaudError::InvalidData("len of params should be 1".into()))
+        if params.is_empty() || params.len() > 2 {
+            return Err(TaudError::InvalidData("len of params should be 1 or 2".into()))
         }
 
         if !params[0].is_string() {
             return Err(TaudError::InvalidData("Invalid path".into()))
         }
 
+        let format = Self::parse_format(params.get(1))?;
+
         let path = params[0].get::<String>().unwrap();
-        let path = expand_path(path)?.join("exported_tasks");
+        let path = expand_path(path)?;
         let ws = self.workspace.lock().await.clone();
         if self.workspaces.get(&ws).unwrap().write_key.is_none() {
             info!("You don't have write access!");
             return Ok(JsonValue::Boolean(false))
         }
 
-        let imported_tasks = MonthTasks::load_current_tasks(&path, ws.clone(), true)?;
+        let imported_tasks = match format {
+            Some(format) => {
+                let content = read_to_string(&path).map_err(Error::from)?;
+                import_tasks(&content, format, &ws, &self.nickname)?
+            }
+            None => MonthTasks::load_current_tasks(&path.join("exported_tasks"), ws.clone(), true)?,
+        };
+
+        let existing: Vec<String> = MonthTasks::load_current_tasks(&self.dataset_path, ws, false)?
+            .into_iter()
+            .map(|t| t.ref_id)
+            .collect();
 
         for task in imported_tasks {
-            if MonthTasks::load_current_tasks(&self.dataset_path, ws.clone(), false)?
-                .into_iter()
-                .map(|t| t.ref_id)
-                .any(|x| x == task.ref_id)
-            {
+            if existing.contains(&task.ref_id) {
                 continue
             }
 
@@ -681,6 +997,15 @@ impl JsonRpcInterface {
         Ok(JsonValue::Boolean(true))
     }
 
+    /// Export format parameter, `None` for taud's own format.
+    fn parse_format(param: Option<&JsonValue>) -> TaudResult<Option<Format>> {
+        match param {
+            None | Some(JsonValue::Null) => Ok(None),
+            Some(JsonValue::String(format)) => Ok(Some(format.parse()?)),
+            Some(_) => Err(TaudError::InvalidData("Invalid format".into())),
+        }
+    }
+
     fn load_task_by_ref_id(&self, task_ref_id: &str, ws: String) -> TaudResult<TaskInfo> {
         let tasks = MonthTasks::load_current_tasks(&self.dataset_path, ws, false)?;
         let task = tasks.into_iter().find(|t| (t.get_ref_id()) == task_ref_id);
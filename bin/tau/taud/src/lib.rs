@@ -16,7 +16,9 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
+pub mod crdt;
 pub mod error;
+pub mod export;
 pub mod month_tasks;
 pub mod task_info;
 pub mod util;
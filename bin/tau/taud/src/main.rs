@@ -47,7 +47,9 @@ use darkfi::{
     async_daemonize,
     event_graph::{
         proto::{EventPut, ProtocolEventGraph},
-        Event, EventGraph, EventGraphConfig, EventGraphPtr,
+        redaction::{Redaction, RedactionPolicy},
+        storage::{migrate_sled_dags, StorageKind},
+        Event, EventGraph, EventGraphConfig, EventGraphPtr, Header,
     },
     net::{session::SESSION_DEFAULT, P2p, P2pPtr},
     rpc::{
@@ -93,8 +95,11 @@ mod jsonrpc;
 mod settings;
 
 use taud::{
+    crdt::merge_tasks,
     error::{TaudError, TaudResult},
-    task_info::{TaskEvent, TaskInfo},
+    export::save_ical_feed,
+    month_tasks::MonthTasks,
+    task_info::{break_cycles, TaskEvent, TaskInfo},
     util::pipe_write,
 };
 
@@ -160,6 +165,48 @@ fn encrypt_sign_task(task: &TaskInfo, workspace: &Workspace) -> TaudResult<Encry
     Ok(EncryptedTask { payload })
 }
 
+/// Domain separator of redaction signatures
+const REDACTION_SIGNATURE_DOMAIN: &[u8] = b"taud-redaction-v1";
+
+/// Message signed with a workspace write key to redact `target`
+fn redaction_message(target: &blake3::Hash) -> Vec<u8> {
+    [REDACTION_SIGNATURE_DOMAIN, target.as_bytes()].concat()
+}
+
+/// Build the redaction of `target`, signed with the workspace write key
+fn sign_redaction(target: blake3::Hash, workspace: &Workspace) -> TaudResult<Redaction> {
+    let Some(write_key) = &workspace.write_key else {
+        return Err(TaudError::InvalidData("You don't have write access".to_string()))
+    };
+
+    let signature = write_key.sign(&redaction_message(&target));
+    Ok(Redaction::new(target, serialize(&signature)))
+}
+
+/// Redaction policy applying the redactions signed by the write key
+/// of any configured workspace. Only the event id is signed, so the
+/// redactions can be checked from the event header.
+struct WorkspaceWritersPolicy {
+    writers: Vec<PublicKey>,
+}
+
+impl RedactionPolicy for WorkspaceWritersPolicy {
+    fn authorize(&self, target: &Event, redaction_event: &Event, redaction: &Redaction) -> bool {
+        self.authorize_header(&target.header, redaction_event, redaction)
+    }
+
+    fn authorize_header(
+        &self,
+        target: &Header,
+        _redaction_event: &Event,
+        redaction: &Redaction,
+    ) -> bool {
+        let Ok(signature) = deserialize::<Signature>(&redaction.auth) else { return false };
+        let message = redaction_message(&target.id());
+        self.writers.iter().any(|writer| writer.verify(&message, &signature))
+    }
+}
+
 fn try_decrypt_task(
     encrypt_task: &EncryptedTask,
     chacha_box: &ChaChaBox,
@@ -369,6 +416,11 @@ async fn start_sync_loop(
                 }
                 mark_seen(sled_db.clone(), seen.clone(), &event_id).await?;
 
+                // Redactions are applied by the event graph
+                if Redaction::from_event(&task_event).is_some() {
+                    continue
+                }
+
                 // Try to deserialize the `Event`'s content into a `EncryptedTask`
                 let enc_task: EncryptedTask = match deserialize_async_partial(task_event.content()).await {
                     Ok((v, _)) => v,
@@ -412,13 +464,37 @@ async fn on_receive_task(
         task.workspace.clone_from(ws_name);
         let datastore_path = expand_path(&settings.datastore)?;
 
+        // Edits made concurrently on another copy of the task are merged
+        // field by field instead of being overwritten.
+        let loaded = TaskInfo::load(&task.ref_id, &datastore_path).ok();
+        if let Some(loaded) = &loaded {
+            task = merge_tasks(loaded, &task);
+            for conflict in task.conflicts.iter().filter(|c| !loaded.conflicts.contains(c)) {
+                info!(target: "taud", "Concurrent edits of {} in task {}", conflict.field, task.ref_id);
+            }
+        }
+
+        // Merged edits of different tasks can close a dependency or
+        // parent cycle, which is broken at its latest edge.
+        let mut tasks = MonthTasks::load_current_tasks(&datastore_path, ws_name.clone(), true)?;
+        let ref_id = task.ref_id.clone();
+        tasks.retain(|t| t.ref_id != ref_id);
+        tasks.push(task);
+        for i in break_cycles(&mut tasks, &ref_id) {
+            info!(target: "taud", "Dropped an edge closing a cycle in task {}", tasks[i].ref_id);
+            if tasks[i].ref_id != ref_id {
+                tasks[i].save(&datastore_path)?;
+            }
+        }
+        let task = tasks.pop().unwrap();
+
         // Push a notification to a fifo if set
         if settings.piped {
             // if we can't load the task then it's a new task.
             // otherwise it's a modification.
-            match TaskInfo::load(&task.ref_id, &datastore_path) {
-                Ok(loaded_task) => {
-                    let loaded_events = loaded_task.events;
+            match &loaded {
+                Some(loaded_task) => {
+                    let loaded_events = &loaded_task.events;
                     let mut events = task.events.clone();
                     events.retain(|ev| !loaded_events.contains(ev));
 
@@ -430,7 +506,7 @@ async fn on_receive_task(
                     let json: JsonValue = (&task_clone).into();
                     pipe_write.write_all(json.stringify().unwrap().as_bytes())?;
                 }
-                Err(_) => {
+                None => {
                     let file = settings.pipe_path.clone();
                     let mut pipe_write = pipe_write(file)?;
                     let mut task_clone = task.clone();
@@ -448,6 +524,12 @@ async fn on_receive_task(
         }
 
         task.save(&datastore_path)?;
+
+        if let Some(ical_feed) = &settings.ical_feed {
+            if let Err(e) = save_ical_feed(&datastore_path, ws_name, &expand_path(ical_feed)?) {
+                error!(target: "taud", "Failed writing the iCalendar feed of {ws_name}: {e}");
+            }
+        }
     }
     Ok(())
 }
@@ -553,6 +635,23 @@ async fn realmain(settings: Args, executor: Arc<smol::Executor<'static>>) -> Res
     info!(target: "taud", "Instantiating event DAG");
     let sled_db = sled::open(datastore)?;
 
+    let dag_storage_kind: StorageKind = settings.dag_storage.parse()?;
+    let dag_log_datastore = expand_path(&settings.dag_log_datastore)?;
+    let dag_storage = dag_storage_kind.open(&sled_db, &dag_log_datastore)?;
+
+    if settings.migrate_dag_storage {
+        if dag_storage_kind == StorageKind::Sled {
+            error!(target: "taud", "Set dag_storage to the backend to migrate the DAGs to");
+            return Err(Error::Custom("DAG storage migration needs a non-sled backend".into()))
+        }
+        let stats = migrate_sled_dags(&sled_db, &dag_storage)?;
+        println!(
+            "Moved {} DAG trees ({} entries) to {} storage",
+            stats.trees, stats.entries, settings.dag_storage,
+        );
+        return Ok(())
+    }
+
     let p2p_settings: darkfi::net::Settings =
         (env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"), settings.net.clone()).try_into()?;
     let comms_timeout = p2p_settings.outbound_connect_timeout_max();
@@ -572,9 +671,11 @@ async fn realmain(settings: Args, executor: Arc<smol::Executor<'static>>) -> Res
         pregenerated_identity_commitments: Vec::new(),
         max_dags: Some(TAUD_MAX_DAGS),
     };
-    let event_graph = match EventGraph::new(
+    let event_graph = match EventGraph::new_with_storage(
         p2p.clone(),
         sled_db.clone(),
+        sled_db.clone(),
+        dag_storage,
         replay_datastore.clone(),
         replay_mode,
         eg_config,
@@ -589,6 +690,12 @@ async fn realmain(settings: Args, executor: Arc<smol::Executor<'static>>) -> Res
         }
     };
 
+    // Redactions signed by a workspace write key drop the task update
+    // they apply to. This must be set before syncing, so placeholders
+    // served by peers can be checked.
+    let writers = workspaces.values().map(|ws| ws.write_pubkey).collect();
+    event_graph.set_redaction_policy(Arc::new(WorkspaceWritersPolicy { writers })).await;
+
     info!(target: "taud", "Registering EventGraph P2P protocol");
     let event_graph_ = Arc::clone(&event_graph);
     let registry = p2p.protocol_registry();
@@ -652,6 +759,16 @@ async fn realmain(settings: Args, executor: Arc<smol::Executor<'static>>) -> Res
         on_receive_task(&enc_task, &workspaces, &settings).await.unwrap();
     }
 
+    // Feeds are otherwise only written when a task changes
+    if let Some(ical_feed) = &settings.ical_feed {
+        let ical_feed = expand_path(ical_feed)?;
+        for ws_name in workspaces.keys() {
+            if let Err(e) = save_ical_feed(&datastore_path, ws_name, &ical_feed) {
+                error!(target: "taud", "Failed writing the iCalendar feed of {ws_name}: {e}");
+            }
+        }
+    }
+
     ////////////////////
     // Listner
     ////////////////////
@@ -777,6 +894,7 @@ async fn realmain(settings: Args, executor: Arc<smol::Executor<'static>>) -> Res
     info!(target: "taud", "Flushing sled database...");
     let flushed_bytes = sled_db.flush_async().await?;
     info!(target: "taud", "Flushed {flushed_bytes} bytes");
+    event_graph.flush_storage().await?;
 
     info!(target: "taud", "Shut down successfully");
     Ok(())
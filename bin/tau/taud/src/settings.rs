@@ -37,6 +37,18 @@ pub struct Args {
     /// Sets Datastore Path
     pub datastore: String,
 
+    #[structopt(long, default_value = "sled")]
+    /// Storage backend for the rotating DAGs (sled or log)
+    pub dag_storage: String,
+
+    #[structopt(long, default_value = "~/.local/share/darkfi/taud_dag_log")]
+    /// Directory of the log storage backend
+    pub dag_log_datastore: String,
+
+    #[structopt(long)]
+    /// Move the DAGs of the sled datastore to the configured dag_storage and exit
+    pub migrate_dag_storage: bool,
+
     #[structopt(long, default_value = "~/.local/share/darkfi/replayed_taud_db")]
     /// Replay logs (DB) path
     pub replay_datastore: String,
@@ -94,6 +106,10 @@ pub struct Args {
     // Whether to pipe notifications or not
     pub piped: bool,
 
+    #[structopt(long)]
+    /// Directory to keep an iCalendar feed of each workspace's due tasks in
+    pub ical_feed: Option<String>,
+
     #[structopt(long)]
     // Whether to sync headers only or full sync
     pub fast_mode: bool,
@@ -17,7 +17,7 @@
  */
 
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     fmt,
     path::{Path, PathBuf},
     str::FromStr,
@@ -30,15 +30,16 @@ use tracing::debug;
 use darkfi::{
     util::{
         file::{load_json_file, save_json_file},
-        time::Timestamp,
+        time::{DateTime, Timestamp},
     },
     Error,
 };
 
 use crate::{
+    crdt::{event_order, Conflict},
     error::{TaudError, TaudResult},
     month_tasks::MonthTasks,
-    util::gen_id,
+    util::{gen_id, set_event},
 };
 
 pub enum State {
@@ -86,6 +87,93 @@ impl FromStr for State {
     }
 }
 
+/// How often a recurring task comes back once it is completed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Recurrence {
+    Daily,
+    Weekly,
+    Monthly,
+}
+
+impl fmt::Display for Recurrence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Recurrence::Daily => write!(f, "daily"),
+            Recurrence::Weekly => write!(f, "weekly"),
+            Recurrence::Monthly => write!(f, "monthly"),
+        }
+    }
+}
+
+impl FromStr for Recurrence {
+    type Err = Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let result = match s.to_lowercase().as_str() {
+            "daily" => Recurrence::Daily,
+            "weekly" => Recurrence::Weekly,
+            "monthly" => Recurrence::Monthly,
+            _ => return Err(Error::ParseFailed("unable to parse recurrence")),
+        };
+        Ok(result)
+    }
+}
+
+const SECS_IN_DAY: u64 = 86_400;
+
+fn days_in_month(year: u32, month: u32) -> u64 {
+    match month {
+        2 if year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400)) => {
+            29
+        }
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+impl Recurrence {
+    /// The same time of day one period after `ts`. A monthly recurrence
+    /// on a day the next month doesn't have falls on its last day.
+    pub fn next_after(&self, ts: Timestamp) -> Timestamp {
+        self.next_on_day(ts, DateTime::from_timestamp(ts.inner(), 0).day)
+    }
+
+    /// [`Recurrence::next_after`], with a monthly recurrence anchored on
+    /// `day` of the month. It falls on the last day of the months that
+    /// don't have it, and comes back to it in the following ones.
+    pub fn next_on_day(&self, ts: Timestamp, day: u32) -> Timestamp {
+        let secs = ts.inner();
+        match self {
+            Recurrence::Daily => Timestamp::from_u64(secs + SECS_IN_DAY),
+            Recurrence::Weekly => Timestamp::from_u64(secs + 7 * SECS_IN_DAY),
+            Recurrence::Monthly => {
+                let date = DateTime::from_timestamp(secs, 0);
+                let (year, month) =
+                    if date.month == 12 { (date.year + 1, 1) } else { (date.year, date.month + 1) };
+                let next_day = u64::from(day).min(days_in_month(year, month));
+                let days = days_in_month(date.year, date.month) - u64::from(date.day) + next_day;
+                Timestamp::from_u64(secs + days * SECS_IN_DAY)
+            }
+        }
+    }
+}
+
+/// Day of the month a monthly recurrence of a task due at `due` is
+/// anchored on: `anchor` if `due` was clamped from it to the end of a
+/// shorter month, otherwise the day of `due`.
+fn anchor_day(due: Timestamp, anchor: Option<u32>) -> u32 {
+    let date = DateTime::from_timestamp(due.inner(), 0);
+    match anchor {
+        Some(anchor)
+            if anchor > date.day && u64::from(date.day) == days_in_month(date.year, date.month) =>
+        {
+            anchor
+        }
+        _ => date.day,
+    }
+}
+
 #[derive(Clone, Debug, SerialEncodable, SerialDecodable, PartialEq, Eq)]
 pub struct TaskEvent {
     pub action: String,
@@ -144,9 +232,9 @@ impl From<&JsonValue> for TaskEvent {
 
 #[derive(Clone, Debug, SerialDecodable, SerialEncodable, PartialEq, Eq)]
 pub struct Comment {
-    content: String,
-    author: String,
-    timestamp: Timestamp,
+    pub content: String,
+    pub author: String,
+    pub timestamp: Timestamp,
 }
 
 impl std::fmt::Display for Comment {
@@ -205,6 +293,9 @@ pub struct TaskInfo {
     pub bounty: Option<f32>,
     pub events: Vec<TaskEvent>,
     pub comments: Vec<Comment>,
+    /// Fields concurrently edited by several peers. Local to this node.
+    #[skip_serialize]
+    pub conflicts: Vec<Conflict>,
 }
 
 impl From<&TaskInfo> for JsonValue {
@@ -244,6 +335,18 @@ impl From<&TaskInfo> for JsonValue {
         let state = JsonValue::String(task.state.clone());
         let events: Vec<JsonValue> = task.events.iter().map(|x| x.clone().into()).collect();
         let comments: Vec<JsonValue> = task.comments.iter().map(|x| x.clone().into()).collect();
+        let conflicts: Vec<JsonValue> = task.conflicts.iter().map(|x| x.into()).collect();
+
+        let parent = match task.parent() {
+            Some(parent) => JsonValue::String(parent),
+            None => JsonValue::Null,
+        };
+        let blocked_by: Vec<JsonValue> =
+            task.blocked_by().into_iter().map(JsonValue::String).collect();
+        let recurrence = match task.recurrence() {
+            Some(recurrence) => JsonValue::String(recurrence.to_string()),
+            None => JsonValue::Null,
+        };
 
         JsonValue::Object(HashMap::from([
             ("ref_id".to_string(), ref_id),
@@ -261,6 +364,10 @@ impl From<&TaskInfo> for JsonValue {
             ("bounty".to_string(), bounty),
             ("events".to_string(), JsonValue::Array(events)),
             ("comments".to_string(), JsonValue::Array(comments)),
+            ("parent".to_string(), parent),
+            ("blocked_by".to_string(), JsonValue::Array(blocked_by)),
+            ("recurrence".to_string(), recurrence),
+            ("conflicts".to_string(), JsonValue::Array(conflicts)),
         ]))
     }
 }
@@ -306,6 +413,16 @@ impl From<JsonValue> for TaskInfo {
         let events: Vec<TaskEvent> = events.iter().map(|x| x.into()).collect();
         let comments: Vec<Comment> = comments.iter().map(|x| (*x).clone().into()).collect();
 
+        // Tasks saved before conflicts were tracked have none, and
+        // malformed ones are dropped since they are only informative.
+        let conflicts: Vec<Conflict> = value
+            .get::<HashMap<String, JsonValue>>()
+            .unwrap()
+            .get("conflicts")
+            .and_then(|conflicts| conflicts.get::<Vec<JsonValue>>())
+            .map(|conflicts| conflicts.iter().filter_map(|x| x.try_into().ok()).collect())
+            .unwrap_or_default();
+
         TaskInfo {
             ref_id: value["ref_id"].get::<String>().unwrap().clone(),
             workspace: value["workspace"].get::<String>().unwrap().clone(),
@@ -322,6 +439,7 @@ impl From<JsonValue> for TaskInfo {
             bounty,
             events,
             comments,
+            conflicts,
         }
     }
 }
@@ -363,6 +481,7 @@ impl TaskInfo {
             bounty,
             comments: vec![],
             events: vec![],
+            conflicts: vec![],
         })
     }
 
@@ -484,4 +603,394 @@ impl TaskInfo {
         }
         self.state = state.to_string();
     }
+
+    /// Content of the latest event with the given action, if any.
+    fn last_event(&self, action: &str) -> Option<&str> {
+        self.events.iter().rev().find(|ev| ev.action == action).map(|ev| ev.content.as_str())
+    }
+
+    /// Parent task of this subtask, from its latest `parent` event.
+    pub fn parent(&self) -> Option<String> {
+        match self.last_event("parent") {
+            None | Some("None") => None,
+            Some(parent) => Some(parent.to_string()),
+        }
+    }
+
+    /// Tasks this one is blocked by, from its `block` and `unblock` events.
+    pub fn blocked_by(&self) -> Vec<String> {
+        let mut blocked_by: Vec<String> = vec![];
+        for ev in self.events.iter() {
+            match ev.action.as_str() {
+                "block" if !blocked_by.contains(&ev.content) => blocked_by.push(ev.content.clone()),
+                "unblock" => blocked_by.retain(|b| *b != ev.content),
+                _ => {}
+            }
+        }
+        blocked_by
+    }
+
+    /// Recurrence rule, from the latest `recurrence` event.
+    pub fn recurrence(&self) -> Option<Recurrence> {
+        self.last_event("recurrence").and_then(|r| r.parse().ok())
+    }
+
+    pub fn set_parent(&mut self, parent: Option<&str>, author: &str) {
+        debug!(target: "tau", "TaskInfo::set_parent()");
+        set_event(self, "parent", author, parent.unwrap_or("None"));
+    }
+
+    pub fn add_blocker(&mut self, blocker: &str, author: &str) {
+        debug!(target: "tau", "TaskInfo::add_blocker()");
+        if !self.blocked_by().iter().any(|b| b == blocker) {
+            set_event(self, "block", author, blocker);
+        }
+    }
+
+    pub fn remove_blocker(&mut self, blocker: &str, author: &str) {
+        debug!(target: "tau", "TaskInfo::remove_blocker()");
+        if self.blocked_by().iter().any(|b| b == blocker) {
+            set_event(self, "unblock", author, blocker);
+        }
+    }
+
+    pub fn set_recurrence(&mut self, recurrence: Option<Recurrence>, author: &str) {
+        debug!(target: "tau", "TaskInfo::set_recurrence()");
+        let content = match recurrence {
+            Some(recurrence) => recurrence.to_string(),
+            None => "None".to_string(),
+        };
+        set_event(self, "recurrence", author, &content);
+    }
+
+    /// The next occurrence of a completed recurring task, or `None` if the
+    /// task doesn't recur or its next occurrence was already created.
+    ///
+    /// The new task keeps the attributes and parent of this one, and its due
+    /// time moves forward by whole periods until it is in the future. Both
+    /// tasks record the other's `ref_id`, in `spawned` and `recur` events.
+    pub fn spawn_next(&mut self, author: &str) -> TaudResult<Option<TaskInfo>> {
+        debug!(target: "tau", "TaskInfo::spawn_next()");
+        let Some(recurrence) = self.recurrence() else { return Ok(None) };
+        if self.last_event("spawned").is_some() {
+            return Ok(None)
+        }
+
+        // Monthly tasks due on a day some months don't have keep coming
+        // back to it, through the `recur_day` event of each occurrence.
+        let now = Timestamp::current_time();
+        let day = self
+            .due
+            .map(|due| anchor_day(due, self.last_event("recur_day").and_then(|d| d.parse().ok())));
+        let due = self.due.zip(day).map(|(mut due, day)| {
+            while due <= now {
+                due = recurrence.next_on_day(due, day);
+            }
+            due
+        });
+
+        let mut next = TaskInfo::new(
+            self.workspace.clone(),
+            &self.title,
+            &self.desc,
+            author,
+            due,
+            self.rank,
+            now,
+            self.bounty,
+        )?;
+        next.tags.clone_from(&self.tags);
+        next.assign.clone_from(&self.assign);
+        next.project.clone_from(&self.project);
+        if let Some(parent) = self.parent() {
+            next.set_parent(Some(&parent), author);
+        }
+        next.set_recurrence(Some(recurrence), author);
+        if let Some(day) = day {
+            set_event(&mut next, "recur_day", author, &day.to_string());
+        }
+        set_event(&mut next, "recur", author, &self.ref_id);
+        set_event(self, "spawned", author, &next.ref_id);
+
+        Ok(Some(next))
+    }
+}
+
+/// Whether `to` can be reached from `from` through "blocked by" relations.
+pub fn is_blocked_by(tasks: &[TaskInfo], from: &str, to: &str) -> bool {
+    let blockers: HashMap<&str, Vec<String>> =
+        tasks.iter().map(|t| (t.ref_id.as_str(), t.blocked_by())).collect();
+
+    let mut visited = HashSet::new();
+    let mut stack = vec![from.to_string()];
+    while let Some(ref_id) = stack.pop() {
+        if !visited.insert(ref_id.clone()) {
+            continue
+        }
+        for blocker in blockers.get(ref_id.as_str()).into_iter().flatten() {
+            if blocker == to {
+                return true
+            }
+            stack.push(blocker.clone());
+        }
+    }
+    false
+}
+
+/// Whether `ancestor` is `task` or one of its parents, transitively.
+pub fn is_ancestor(tasks: &[TaskInfo], task: &str, ancestor: &str) -> bool {
+    let parents: HashMap<&str, String> =
+        tasks.iter().filter_map(|t| t.parent().map(|p| (t.ref_id.as_str(), p))).collect();
+
+    let mut current = task.to_string();
+    for _ in 0..=parents.len() {
+        if current == ancestor {
+            return true
+        }
+        match parents.get(current.as_str()) {
+            Some(parent) => current.clone_from(parent),
+            None => return false,
+        }
+    }
+    // Only reachable through an existing cycle.
+    true
+}
+
+/// Drop the edges closing "blocked by" or parent cycles through `ref_id`.
+///
+/// Local edits creating a cycle are refused, but concurrent edits of
+/// different tasks can still close one once merged. The edge set last in
+/// the cycle is dropped, so every node breaks it the same way whichever
+/// edit it received first. Returns the indexes of the modified tasks.
+pub fn break_cycles(tasks: &mut [TaskInfo], ref_id: &str) -> Vec<usize> {
+    let mut modified = vec![];
+
+    while let Some(cycle) = blocker_cycle(tasks, ref_id) {
+        let latest = cycle
+            .into_iter()
+            .filter_map(|(i, blocker)| {
+                tasks[i]
+                    .events
+                    .iter()
+                    .filter(|ev| ev.action == "block" && ev.content == blocker)
+                    .max_by(|a, b| event_order(a, b))
+                    .map(|ev| (i, ev.clone()))
+            })
+            .max_by(|a, b| event_order(&a.1, &b.1));
+        let Some((i, ev)) = latest else { break };
+        tasks[i].remove_blocker(&ev.content, &ev.author);
+        modified.push(i);
+    }
+
+    if let Some(cycle) = parent_cycle(tasks, ref_id) {
+        let latest = cycle
+            .into_iter()
+            .filter_map(|i| {
+                tasks[i]
+                    .events
+                    .iter()
+                    .rev()
+                    .find(|ev| ev.action == "parent")
+                    .map(|ev| (i, ev.clone()))
+            })
+            .max_by(|a, b| event_order(&a.1, &b.1));
+        if let Some((i, ev)) = latest {
+            tasks[i].set_parent(None, &ev.author);
+            modified.push(i);
+        }
+    }
+
+    modified.sort_unstable();
+    modified.dedup();
+    modified
+}
+
+/// A "blocked by" cycle through `ref_id`, as (blocked task index, blocker) edges.
+fn blocker_cycle(tasks: &[TaskInfo], ref_id: &str) -> Option<Vec<(usize, String)>> {
+    let index: HashMap<&str, usize> =
+        tasks.iter().enumerate().map(|(i, t)| (t.ref_id.as_str(), i)).collect();
+
+    // Index of the task each visited blocker was first reached from
+    let mut reached_from: HashMap<String, usize> = HashMap::new();
+    let mut visited = HashSet::new();
+    let mut stack = vec![ref_id.to_string()];
+    while let Some(current) = stack.pop() {
+        if !visited.insert(current.clone()) {
+            continue
+        }
+        let Some(&i) = index.get(current.as_str()) else { continue };
+        for blocker in tasks[i].blocked_by() {
+            if blocker == ref_id {
+                let mut cycle = vec![(i, blocker)];
+                let mut node = current;
+                while node != ref_id {
+                    let from = reached_from[&node];
+                    cycle.push((from, node));
+                    node = tasks[from].ref_id.clone();
+                }
+                return Some(cycle)
+            }
+            if !visited.contains(&blocker) {
+                reached_from.entry(blocker.clone()).or_insert(i);
+                stack.push(blocker);
+            }
+        }
+    }
+    None
+}
+
+/// Indexes of the tasks in a parent cycle through `ref_id`.
+fn parent_cycle(tasks: &[TaskInfo], ref_id: &str) -> Option<Vec<usize>> {
+    let index: HashMap<&str, usize> =
+        tasks.iter().enumerate().map(|(i, t)| (t.ref_id.as_str(), i)).collect();
+
+    let mut cycle = vec![];
+    let mut current = ref_id.to_string();
+    for _ in 0..tasks.len() {
+        let &i = index.get(current.as_str())?;
+        cycle.push(i);
+        current = tasks[i].parent()?;
+        if current == ref_id {
+            return Some(cycle)
+        }
+    }
+    None
+}
+
+/// Subtasks of `parent` among `tasks`.
+pub fn subtasks<'a>(tasks: &'a [TaskInfo], parent: &str) -> Vec<&'a TaskInfo> {
+    tasks.iter().filter(|t| t.parent().as_deref() == Some(parent)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn task(title: &str) -> TaskInfo {
+        TaskInfo::new(
+            "darkfi".to_string(),
+            title,
+            "desc",
+            "NICKNAME",
+            None,
+            None,
+            Timestamp::current_time(),
+            None,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn relations_are_read_from_events() {
+        let mut t = task("t");
+        assert!(t.parent().is_none() && t.blocked_by().is_empty() && t.recurrence().is_none());
+
+        t.set_parent(Some("p1"), "a");
+        t.set_parent(Some("p2"), "a");
+        assert_eq!(t.parent().as_deref(), Some("p2"));
+        t.set_parent(None, "a");
+        assert!(t.parent().is_none());
+
+        t.add_blocker("b1", "a");
+        t.add_blocker("b2", "a");
+        t.add_blocker("b1", "a");
+        t.remove_blocker("b1", "a");
+        assert_eq!(t.blocked_by(), vec!["b2".to_string()]);
+
+        t.set_recurrence(Some(Recurrence::Weekly), "a");
+        assert_eq!(t.recurrence(), Some(Recurrence::Weekly));
+
+        // The relations survive a JSON round trip through the events.
+        let json: JsonValue = (&t).into();
+        let loaded: TaskInfo = json.into();
+        assert_eq!(loaded, t);
+        assert_eq!(loaded.blocked_by(), vec!["b2".to_string()]);
+    }
+
+    #[test]
+    fn dependency_and_parent_cycles_are_detected() {
+        let a = task("a");
+        let mut b = task("b");
+        let mut c = task("c");
+        b.add_blocker(&a.ref_id, "x");
+        c.add_blocker(&b.ref_id, "x");
+        b.set_parent(Some(&a.ref_id), "x");
+        c.set_parent(Some(&b.ref_id), "x");
+        let tasks = vec![a.clone(), b, c.clone()];
+
+        // a blocked by c would close a -> c -> b -> a.
+        assert!(is_blocked_by(&tasks, &c.ref_id, &a.ref_id));
+        assert!(!is_blocked_by(&tasks, &a.ref_id, &c.ref_id));
+
+        // a can't become a subtask of its own grandchild.
+        assert!(is_ancestor(&tasks, &c.ref_id, &a.ref_id));
+        assert!(!is_ancestor(&tasks, &a.ref_id, &c.ref_id));
+        assert_eq!(subtasks(&tasks, &a.ref_id).len(), 1);
+    }
+
+    #[test]
+    fn merged_cycles_are_broken_at_the_latest_edge() {
+        let mut a = task("a");
+        let mut b = task("b");
+        let mut c = task("c");
+        c.add_blocker(&b.ref_id, "x");
+        b.add_blocker(&a.ref_id, "x");
+        a.set_parent(Some(&b.ref_id), "x");
+        // Concurrent edits on another node, merged in later.
+        a.add_blocker(&c.ref_id, "y");
+        b.set_parent(Some(&a.ref_id), "y");
+
+        let mut tasks = vec![a, b, c];
+        let ref_id = tasks[0].ref_id.clone();
+        assert_eq!(break_cycles(&mut tasks, &ref_id), vec![0, 1]);
+        assert!(tasks[0].blocked_by().is_empty());
+        assert_eq!(tasks[1].blocked_by(), vec![ref_id.clone()]);
+        assert_eq!(tasks[0].parent(), Some(tasks[1].ref_id.clone()));
+        assert!(tasks[1].parent().is_none());
+
+        // Nothing is left to break.
+        assert!(break_cycles(&mut tasks, &ref_id).is_empty());
+    }
+
+    #[test]
+    fn completed_recurring_task_spawns_once() {
+        let due = Timestamp::current_time().inner() + 3600;
+        let mut t = task("standup");
+        t.set_due(Some(Timestamp::from_u64(due)));
+        t.tags.push("sprint".to_string());
+        t.set_parent(Some("epic"), "a");
+        assert!(t.spawn_next("a").unwrap().is_none());
+
+        t.set_recurrence(Some(Recurrence::Daily), "a");
+        let next = t.spawn_next("b").unwrap().unwrap();
+        assert_eq!(next.due, Some(Timestamp::from_u64(due)));
+        assert_eq!(next.tags, t.tags);
+        assert_eq!(next.parent().as_deref(), Some("epic"));
+        assert_eq!(next.recurrence(), Some(Recurrence::Daily));
+        assert_eq!(next.last_event("recur"), Some(t.ref_id.as_str()));
+        assert_eq!(t.last_event("spawned"), Some(next.ref_id.as_str()));
+        assert!(t.spawn_next("b").unwrap().is_none());
+    }
+
+    #[test]
+    fn recurrence_periods() {
+        // 2024-01-31T12:00:00
+        let jan31 = Timestamp::from_u64(1_706_702_400);
+        assert_eq!(Recurrence::Daily.next_after(jan31).inner(), 1_706_702_400 + SECS_IN_DAY);
+        assert_eq!(Recurrence::Weekly.next_after(jan31).inner(), 1_706_702_400 + 7 * SECS_IN_DAY);
+        // Leap February ends on the 29th, and March comes back to the 31st.
+        let feb29 = Recurrence::Monthly.next_after(jan31);
+        assert_eq!(DateTime::from_timestamp(feb29.inner(), 0).to_string(), "2024-02-29T12:00:00");
+        assert_eq!(anchor_day(feb29, Some(31)), 31);
+        let mar31 = Recurrence::Monthly.next_on_day(feb29, 31);
+        assert_eq!(DateTime::from_timestamp(mar31.inner(), 0).to_string(), "2024-03-31T12:00:00");
+        let apr30 = Recurrence::Monthly.next_on_day(mar31, 31);
+        assert_eq!(DateTime::from_timestamp(apr30.inner(), 0).to_string(), "2024-04-30T12:00:00");
+        // A due date moved off the end of the month drops the anchor.
+        assert_eq!(anchor_day(Timestamp::from_u64(1_734_264_000), Some(31)), 15);
+        // December rolls over the year.
+        let dec15 = Timestamp::from_u64(1_734_264_000); // 2024-12-15T12:00:00
+        let jan15 = Recurrence::Monthly.next_after(dec15);
+        assert_eq!(DateTime::from_timestamp(jan15.inner(), 0).to_string(), "2025-01-15T12:00:00");
+    }
 }
@@ -42,6 +42,7 @@ impl RequestHandler<()> for Dchat {
             "recv" => self.recv(req.id).await,
             "ping" => self.pong(req.id, req.params).await,
             "p2p.get_info" => self.p2p_get_info(req.id, req.params).await,
+            "p2p.get_hosts" => self.p2p_get_hosts(req.id, req.params).await,
             "dnet.switch" => self.dnet_switch(req.id, req.params).await,
             "dnet.subscribe_events" => self.dnet_subscribe_events(req.id, req.params).await,
             _ => JsonError::new(ErrorCode::MethodNotFound, None, req.id).into(),
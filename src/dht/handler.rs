@@ -24,7 +24,7 @@ use std::{
 
 use async_trait::async_trait;
 
-use super::{Dht, DhtLookupReply, DhtNode};
+use super::{Dht, DhtLookupReply, DhtNode, DhtRecord};
 use crate::{net::ChannelPtr, Result};
 
 /// Trait for application-specific behaviors over a [`Dht`]
@@ -39,6 +39,16 @@ pub trait DhtHandler: Send + Sync + Sized {
     /// Get our own node
     async fn node(&self) -> Result<Self::Node>;
 
+    /// Check that a node's identity is valid (e.g. that its id is backed by
+    /// a valid proof of work). Invalid nodes are never added to our buckets
+    /// and are dropped from lookup replies.
+    async fn validate_node(&self, node: &Self::Node) -> Result<()>;
+
+    /// Check that a record sent by another node is valid for the app (e.g.
+    /// that its key belongs to its publisher). Invalid records are refused
+    /// before they count towards any storage quota.
+    async fn validate_record(&self, record: &DhtRecord) -> Result<()>;
+
     /// Send PING request, which is used to know the node data of a peer
     /// (and most importantly, its ID/key in the DHT keyspace)
     async fn ping(&self, channel: ChannelPtr) -> Result<Self::Node>;
@@ -61,6 +71,13 @@ pub trait DhtHandler: Send + Sync + Sized {
         key: &blake3::Hash,
     ) -> Result<DhtLookupReply<Self::Node, Self::Value>>;
 
+    /// Send STORE RECORD request to instruct a peer to store a [`DhtRecord`]
+    async fn store_record(&self, channel: ChannelPtr, record: &DhtRecord) -> Result<()>;
+
+    /// Send FIND RECORDS request to a peer to get the records it stores for `key`
+    async fn find_records(&self, channel: ChannelPtr, key: &blake3::Hash)
+        -> Result<Vec<DhtRecord>>;
+
     /// Add a value to our hash table
     async fn add_value(&self, key: &blake3::Hash, value: &Self::Value);
 
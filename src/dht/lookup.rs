@@ -0,0 +1,402 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2026 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Iterative lookup over disjoint paths, as in S/Kademlia.
+//!
+//! The initial nodes are split between [`DhtSettings::disjoint_paths`]
+//! paths that run in parallel, each path being a regular Kademlia iterative
+//! lookup. A node is visited by at most one path, so a set of malicious
+//! nodes has to be on every path to censor a key.
+
+use std::collections::HashSet;
+
+use futures::{future::join_all, stream::FuturesUnordered};
+use num_bigint::BigUint;
+use smol::{
+    lock::{Mutex, Semaphore},
+    stream::StreamExt,
+};
+use tracing::warn;
+
+use crate::{
+    dht::{DhtLookupReply, DhtNode, DhtSettings},
+    Result,
+};
+
+/// Get the distance between `key_1` and `key_2`
+pub fn distance(key_1: &blake3::Hash, key_2: &blake3::Hash) -> BigUint {
+    let bytes1 = key_1.as_bytes();
+    let bytes2 = key_2.as_bytes();
+
+    let mut result_bytes = [0u8; 32];
+    for i in 0..32 {
+        result_bytes[i] = bytes1[i] ^ bytes2[i];
+    }
+
+    BigUint::from_bytes_be(&result_bytes)
+}
+
+/// Sort `nodes` by distance from `key`
+pub fn sort_by_distance<N: DhtNode>(nodes: &mut [N], key: &blake3::Hash) {
+    nodes.sort_by_cached_key(|node| distance(key, &node.id()));
+}
+
+/// State shared by the paths of a lookup
+struct LookupState {
+    /// Limits the number of parallel requests of all paths
+    semaphore: Semaphore,
+    /// Nodes already visited or to be visited by a path
+    claimed: Mutex<HashSet<blake3::Hash>>,
+}
+
+/// Find the `k` nodes closest to `key`, and the values they return,
+/// starting from `initial_nodes`. `query` sends a FIND NODES or FIND VALUE
+/// request to a node.
+/// The result contains the `k` closest nodes found by all the paths.
+pub async fn disjoint_lookup<N, V, F>(
+    key: &blake3::Hash,
+    self_id: Option<blake3::Hash>,
+    initial_nodes: Vec<N>,
+    settings: &DhtSettings,
+    query: F,
+) -> (Vec<N>, Vec<V>)
+where
+    N: DhtNode,
+    F: AsyncFn(N) -> Result<DhtLookupReply<N, V>>,
+{
+    let state = LookupState {
+        semaphore: Semaphore::new(settings.concurrency),
+        claimed: Mutex::new(initial_nodes.iter().map(|node| node.id()).chain(self_id).collect()),
+    };
+
+    // Split the initial nodes between the paths: they are sorted by
+    // distance, so each path gets some of the closest ones
+    let n_paths = settings.disjoint_paths.clamp(1, initial_nodes.len().max(1));
+    let mut paths: Vec<Vec<N>> = (0..n_paths).map(|_| vec![]).collect();
+    for (i, node) in initial_nodes.into_iter().enumerate() {
+        paths[i % n_paths].push(node);
+    }
+
+    let results =
+        join_all(paths.into_iter().map(|nodes| lookup_path(key, nodes, settings, &state, &query)))
+            .await;
+
+    let mut seen = HashSet::new();
+    let mut nodes = vec![];
+    let mut values = vec![];
+    for (path_nodes, path_values) in results {
+        nodes.extend(path_nodes.into_iter().filter(|node| seen.insert(node.id())));
+        values.extend(path_values);
+    }
+    sort_by_distance(&mut nodes, key);
+    nodes.truncate(settings.k);
+
+    (nodes, values)
+}
+
+/// Kademlia iterative lookup, only visiting the nodes that are not claimed
+/// by another path.
+async fn lookup_path<N, V, F>(
+    key: &blake3::Hash,
+    mut nodes_to_visit: Vec<N>,
+    settings: &DhtSettings,
+    state: &LookupState,
+    query: &F,
+) -> (Vec<N>, Vec<V>)
+where
+    N: DhtNode,
+    F: AsyncFn(N) -> Result<DhtLookupReply<N, V>>,
+{
+    let (k, a) = (settings.k, settings.alpha);
+    let mut result = Vec::new();
+    let mut values = Vec::new();
+    let mut futures = FuturesUnordered::new();
+    let mut consecutive_stalls = 0;
+
+    let visit = async |node: N| {
+        let _permit = state.semaphore.acquire().await;
+        let res = query(node.clone()).await;
+        (node, res)
+    };
+
+    // Start up to `alpha` requests
+    let spawn_futures = |nodes_to_visit: &mut Vec<N>, futures: &mut FuturesUnordered<_>| {
+        for _ in 0..a {
+            if nodes_to_visit.is_empty() {
+                break
+            }
+            futures.push(visit(nodes_to_visit.remove(0)));
+        }
+    };
+
+    // Initial futures
+    spawn_futures(&mut nodes_to_visit, &mut futures);
+
+    // Process lookup responses
+    while let Some((queried_node, res)) = futures.next().await {
+        let reply = match res {
+            Ok(reply) => reply,
+            Err(e) => {
+                warn!(target: "dht::lookup", "[DHT] [LOOKUP] Error in lookup: {e}");
+
+                // Start the next requests if there are no more running but
+                // we still have nodes to visit
+                if futures.is_empty() {
+                    spawn_futures(&mut nodes_to_visit, &mut futures);
+                }
+                continue
+            }
+        };
+
+        let (nodes, value) = match reply {
+            DhtLookupReply::Nodes(nodes) => (Some(nodes), None),
+            DhtLookupReply::Value(value) => (None, Some(value)),
+            DhtLookupReply::NodesAndValue(nodes, value) => (Some(nodes), Some(value)),
+        };
+
+        if let Some(value) = value {
+            values.push(value);
+        }
+
+        // Add the nodes that no path visited yet to the nodes to visit
+        if let Some(nodes) = nodes {
+            let mut claimed = state.claimed.lock().await;
+            nodes_to_visit.extend(nodes.into_iter().filter(|node| claimed.insert(node.id())));
+            drop(claimed);
+            sort_by_distance(&mut nodes_to_visit, key);
+        }
+
+        result.push(queried_node);
+        sort_by_distance(&mut result, key);
+
+        // Early termination logic:
+        // The closest node to visit must be further than the `k`-th closest
+        // queried node, 3 consecutive times
+        if result.len() >= k &&
+            result.get(k - 1).zip(nodes_to_visit.first()).is_some_and(|(furthest, next)| {
+                distance(key, &furthest.id()) < distance(key, &next.id())
+            })
+        {
+            consecutive_stalls += 1;
+            if consecutive_stalls >= 3 {
+                break
+            }
+        } else {
+            consecutive_stalls = 0;
+        }
+
+        // Start the next requests
+        spawn_futures(&mut nodes_to_visit, &mut futures);
+    }
+
+    result.truncate(k);
+    (result, values)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashMap, HashSet};
+
+    use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+    use url::Url;
+
+    use super::{disjoint_lookup, sort_by_distance};
+    use crate::{
+        dht::{DhtLookupReply, DhtNode, DhtSettings},
+        Error, Result,
+    };
+
+    #[derive(Clone, Debug)]
+    struct SimNode {
+        id: blake3::Hash,
+    }
+    crate::impl_dht_node_defaults!(SimNode);
+
+    impl DhtNode for SimNode {
+        fn id(&self) -> blake3::Hash {
+            self.id
+        }
+        fn addresses(&self) -> Vec<Url> {
+            vec![]
+        }
+    }
+
+    /// A simulated DHT network with adversarial nodes colluding to censor
+    /// keys: they never return the value, and reply with sybil nodes, with
+    /// ids right next to the key, that only know each other.
+    /// Sybil ids are free to create, they fail node validation.
+    struct SimNetwork {
+        nodes: Vec<SimNode>,
+        adversarial: HashSet<blake3::Hash>,
+        /// Node id -> routing table of the node
+        tables: HashMap<blake3::Hash, Vec<SimNode>>,
+        k: usize,
+    }
+
+    impl SimNetwork {
+        fn new(rng: &mut StdRng, n_nodes: usize, adversarial_ratio: f64, k: usize) -> Self {
+            let nodes: Vec<SimNode> =
+                (0..n_nodes).map(|_| SimNode { id: blake3::Hash::from_bytes(rng.gen()) }).collect();
+
+            let adversarial =
+                nodes.iter().filter(|_| rng.gen_bool(adversarial_ratio)).map(|n| n.id).collect();
+
+            // Kademlia routing tables: up to `k` random nodes per bucket
+            let mut tables = HashMap::new();
+            for node in &nodes {
+                let mut others: Vec<&SimNode> = nodes.iter().filter(|n| n.id != node.id).collect();
+                others.shuffle(rng);
+                let mut buckets: HashMap<u64, usize> = HashMap::new();
+                let mut table = vec![];
+                for other in others {
+                    let count =
+                        buckets.entry(super::distance(&node.id, &other.id).bits()).or_default();
+                    if *count < k {
+                        *count += 1;
+                        table.push(other.clone());
+                    }
+                }
+                tables.insert(node.id, table);
+            }
+
+            Self { nodes, adversarial, tables, k }
+        }
+
+        /// The `k` nodes of `nodes` closest to `key`
+        fn closest(&self, nodes: &[SimNode], key: &blake3::Hash) -> Vec<SimNode> {
+            let mut nodes = nodes.to_vec();
+            sort_by_distance(&mut nodes, key);
+            nodes.truncate(self.k);
+            nodes
+        }
+
+        /// Lookup a random key from a random honest node, returns `None` if
+        /// all the nodes storing the key are adversarial, or whether the
+        /// value was found.
+        async fn lookup(
+            &self,
+            rng: &mut StdRng,
+            settings: &DhtSettings,
+            validate: bool,
+        ) -> Option<bool> {
+            let key = blake3::Hash::from_bytes(rng.gen());
+
+            // Honest nodes among the `k` closest to `key` store the value
+            let holders: HashSet<blake3::Hash> = self
+                .closest(&self.nodes, &key)
+                .into_iter()
+                .map(|n| n.id)
+                .filter(|id| !self.adversarial.contains(id))
+                .collect();
+            if holders.is_empty() {
+                return None
+            }
+
+            // Sybils share the first bytes of the key
+            let sybils: Vec<SimNode> = (0..self.k)
+                .map(|_| {
+                    let mut id = *key.as_bytes();
+                    rng.fill(&mut id[8..]);
+                    SimNode { id: blake3::Hash::from_bytes(id) }
+                })
+                .collect();
+
+            let query = async |node: SimNode| -> Result<DhtLookupReply<SimNode, ()>> {
+                let mut reply = match self.tables.get(&node.id) {
+                    Some(_) if self.adversarial.contains(&node.id) => sybils.clone(),
+                    Some(table) => self.closest(table, &key),
+                    None if sybils.contains(&node) => sybils.clone(),
+                    None => return Err(Error::Custom("Unknown node".to_string())),
+                };
+                // Adversarial nodes reply faster than honest ones
+                if !self.adversarial.contains(&node.id) && !sybils.contains(&node) {
+                    for _ in 0..10 {
+                        smol::future::yield_now().await;
+                    }
+                }
+                // Node validation: only nodes of the network are valid
+                if validate {
+                    reply.retain(|n| self.tables.contains_key(&n.id));
+                }
+                match holders.contains(&node.id) {
+                    true => Ok(DhtLookupReply::NodesAndValue(reply, ())),
+                    false => Ok(DhtLookupReply::Nodes(reply)),
+                }
+            };
+
+            let origin = loop {
+                let node = self.nodes.choose(rng).unwrap();
+                if !self.adversarial.contains(&node.id) {
+                    break node
+                }
+            };
+            let initial_nodes = self.closest(&self.tables[&origin.id], &key);
+            let (_, values) =
+                disjoint_lookup(&key, Some(origin.id), initial_nodes, settings, query).await;
+            Some(!values.is_empty())
+        }
+
+        /// Run lookups of 100 random keys, returns the percentage of
+        /// successful ones.
+        fn success_rate(&self, disjoint_paths: usize, validate: bool) -> usize {
+            let settings = DhtSettings { k: self.k, disjoint_paths, ..Default::default() };
+            let mut rng = StdRng::seed_from_u64(1);
+            let (mut lookups, mut successes) = (0, 0);
+            smol::block_on(async {
+                while lookups < 100 {
+                    if let Some(success) = self.lookup(&mut rng, &settings, validate).await {
+                        lookups += 1;
+                        successes += success as usize;
+                    }
+                }
+            });
+            successes
+        }
+    }
+
+    #[test]
+    fn test_dht_lookup_without_adversarial_nodes() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let network = SimNetwork::new(&mut rng, 500, 0.0, 8);
+
+        assert_eq!(network.success_rate(1, false), 100);
+        assert_eq!(network.success_rate(4, false), 100);
+    }
+
+    #[test]
+    fn test_dht_disjoint_lookups_survive_adversarial_nodes() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let network = SimNetwork::new(&mut rng, 500, 0.3, 8);
+
+        // Without node validation, a single path is captured by the sybils
+        // as soon as it queries an adversarial node
+        let single_path = network.success_rate(1, false);
+        let disjoint_paths = network.success_rate(4, false);
+        assert!(single_path < 80, "{single_path}% of single path lookups succeeded");
+        assert!(disjoint_paths >= 95, "{disjoint_paths}% of disjoint lookups succeeded");
+    }
+
+    #[test]
+    fn test_dht_node_validation_rejects_sybils() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let network = SimNetwork::new(&mut rng, 500, 0.3, 8);
+
+        assert!(network.success_rate(1, true) >= 95);
+        assert!(network.success_rate(4, true) >= 95);
+    }
+}
@@ -25,12 +25,10 @@ use std::{
     sync::{Arc, Weak},
 };
 
-use futures::stream::FuturesUnordered;
 use num_bigint::BigUint;
 use smol::{
     channel,
-    lock::{Mutex, RwLock, Semaphore},
-    stream::StreamExt,
+    lock::{Mutex, RwLock},
 };
 use tracing::{info, warn};
 use url::Url;
@@ -47,6 +45,7 @@ use crate::{
     util::time::Timestamp,
     Error, Result,
 };
+use darkfi_sdk::crypto::PublicKey;
 
 pub mod settings;
 pub use settings::{DhtSettings, DhtSettingsOpt};
@@ -58,6 +57,11 @@ pub mod tasks;
 
 pub mod event;
 
+pub mod store;
+pub use store::{DhtRecord, DhtRecordStore};
+
+pub mod lookup;
+
 pub trait DhtNode: Debug + Clone + Send + Sync + PartialEq + Eq + Hash {
     fn id(&self) -> blake3::Hash;
     fn addresses(&self) -> Vec<Url>;
@@ -154,8 +158,12 @@ pub struct Dht<H: DhtHandler> {
     pub bootstrapped: Arc<RwLock<bool>>,
     /// Vec of buckets
     pub buckets: Arc<RwLock<Vec<DhtBucket<H::Node>>>>,
+    /// Sibling list: the nodes closest to our own node id, sorted by distance
+    pub siblings: Arc<RwLock<Vec<H::Node>>>,
     /// Our local hash table, storing a part of the full DHT keys/values
     pub hash_table: DhtHashTable<H::Value>,
+    /// Signed records we store or publish
+    pub records: DhtRecordStore,
     /// Number of buckets
     pub n_buckets: usize,
     /// Channel ID -> ChannelCacheItem
@@ -196,7 +204,9 @@ impl<H: DhtHandler> Dht<H> {
         Self {
             handler: RwLock::new(Weak::new()),
             buckets: Arc::new(RwLock::new(buckets)),
+            siblings: Arc::new(RwLock::new(vec![])),
             hash_table: Arc::new(RwLock::new(HashMap::new())),
+            records: DhtRecordStore::new(),
             n_buckets: 256,
             bootstrapped: Arc::new(RwLock::new(false)),
             channel_cache: Arc::new(RwLock::new(HashMap::new())),
@@ -249,11 +259,7 @@ impl<H: DhtHandler> Dht<H> {
 
     /// Sort `nodes` by distance from `key`
     pub fn sort_by_distance(&self, nodes: &mut [H::Node], key: &blake3::Hash) {
-        nodes.sort_by(|a, b| {
-            let distance_a = BigUint::from_bytes_be(&self.distance(key, &a.id()));
-            let distance_b = BigUint::from_bytes_be(&self.distance(key, &b.id()));
-            distance_a.cmp(&distance_b)
-        });
+        lookup::sort_by_distance(nodes, key);
     }
 
     /// `key` -> bucket index
@@ -290,6 +296,13 @@ impl<H: DhtHandler> Dht<H> {
                 neighbors.extend(bucket.nodes.iter().cloned());
             }
         }
+        drop(buckets);
+
+        // Siblings that did not fit in their bucket
+        let mut seen: HashSet<_> = neighbors.iter().map(|node| node.id()).collect();
+        let siblings = self.siblings.read().await;
+        neighbors.extend(siblings.iter().filter(|node| seen.insert(node.id())).cloned());
+        drop(siblings);
 
         self.sort_by_distance(&mut neighbors, key);
 
@@ -298,6 +311,19 @@ impl<H: DhtHandler> Dht<H> {
         neighbors
     }
 
+    /// Returns `true` if our node is among the `k` closest nodes we know to
+    /// `key`, so that we are one of the nodes expected to store its values.
+    pub async fn is_close_to(&self, key: &blake3::Hash) -> bool {
+        let Ok(self_node) = self.handler().await.node().await else { return false };
+        let neighbors = self.find_neighbors(key, self.settings.k).await;
+        match neighbors.last() {
+            Some(furthest) if neighbors.len() >= self.settings.k => {
+                self.distance(&self_node.id(), key) <= self.distance(&furthest.id(), key)
+            }
+            _ => true,
+        }
+    }
+
     /// Channel ID -> [`DhtNode`]
     pub async fn get_node_from_channel(&self, channel_id: u32) -> Option<H::Node> {
         let channel_cache_lock = self.channel_cache.clone();
@@ -320,7 +346,9 @@ impl<H: DhtHandler> Dht<H> {
         }
 
         *self.buckets.write().await = buckets;
+        self.siblings.write().await.clear();
         *self.hash_table.write().await = HashMap::new();
+        self.records.clear().await;
     }
 
     /// Add `value` to our hash table and send `message` for a `key` to the closest nodes found
@@ -394,6 +422,15 @@ impl<H: DhtHandler> Dht<H> {
                 let _ = self.handler().await.store(channel.clone(), key, value).await;
             }
         }
+
+        // Same for the records
+        for record in self.records.get_all().await {
+            let node_distance = BigUint::from_bytes_be(&self.distance(&record.key, &node.id()));
+            let self_distance = BigUint::from_bytes_be(&self.distance(&record.key, &self_id));
+            if node_distance <= self_distance {
+                let _ = self.handler().await.store_record(channel.clone(), &record).await;
+            }
+        }
     }
 
     /// Move a node to the tail in its bucket,
@@ -418,6 +455,21 @@ impl<H: DhtHandler> Dht<H> {
         let mut buckets = buckets_lock.write().await;
         let bucket = &mut buckets[bucket_index];
         bucket.nodes.retain(|node| node.id() != *node_id);
+        drop(buckets);
+        self.siblings.write().await.retain(|node| node.id() != *node_id);
+    }
+
+    /// Add `node` to the sibling list if it is one of the
+    /// [`DhtSettings::siblings`] nodes closest to `self_node_id`.
+    pub async fn add_sibling(&self, self_node_id: &blake3::Hash, node: &H::Node) {
+        let mut siblings = self.siblings.write().await;
+        if let Some(sibling) = siblings.iter_mut().find(|n| n.id() == node.id()) {
+            *sibling = node.clone();
+            return
+        }
+        siblings.push(node.clone());
+        self.sort_by_distance(&mut siblings, self_node_id);
+        siblings.truncate(self.settings.siblings);
     }
 
     /// Send a DHT ping to `channel` using the handler's ping method.
@@ -449,7 +501,58 @@ impl<H: DhtHandler> Dht<H> {
         ping_result
     }
 
+    /// Check the nodes of a lookup reply from `queried_node`, dropping the
+    /// invalid ones and our own node, and notify the found nodes and value.
+    async fn check_lookup_reply(
+        &self,
+        key: &blake3::Hash,
+        queried_node: &H::Node,
+        self_id: Option<blake3::Hash>,
+        reply: DhtLookupReply<H::Node, H::Value>,
+    ) -> DhtLookupReply<H::Node, H::Value> {
+        let handler = self.handler().await;
+        let check_nodes = async |nodes: Vec<H::Node>| {
+            let mut valid_nodes = vec![];
+            for node in nodes {
+                if Some(node.id()) == self_id {
+                    continue
+                }
+                if let Err(e) = handler.validate_node(&node).await {
+                    warn!(target: "dht::lookup", "[DHT] [LOOKUP] Node {} returned an invalid node {}: {e}", H::key_to_string(&queried_node.id()), H::key_to_string(&node.id()));
+                    continue
+                }
+                valid_nodes.push(node);
+            }
+            if !valid_nodes.is_empty() {
+                info!(target: "dht::lookup", "[DHT] [LOOKUP] Found {} nodes from {}", valid_nodes.len(), H::key_to_string(&queried_node.id()));
+                self.event_publisher
+                    .notify(DhtEvent::NodesFound { key: *key, nodes: valid_nodes.clone() })
+                    .await;
+            }
+            valid_nodes
+        };
+        let notify_value = async |value: &H::Value| {
+            info!(target: "dht::lookup", "[DHT] [LOOKUP] Found value for {} from {}", H::key_to_string(key), H::key_to_string(&queried_node.id()));
+            self.event_publisher
+                .notify(DhtEvent::ValueFound { key: *key, value: value.clone() })
+                .await;
+        };
+
+        match reply {
+            DhtLookupReply::Nodes(nodes) => DhtLookupReply::Nodes(check_nodes(nodes).await),
+            DhtLookupReply::Value(value) => {
+                notify_value(&value).await;
+                DhtLookupReply::Value(value)
+            }
+            DhtLookupReply::NodesAndValue(nodes, value) => {
+                notify_value(&value).await;
+                DhtLookupReply::NodesAndValue(check_nodes(nodes).await, value)
+            }
+        }
+    }
+
     /// Lookup algorithm for both nodes lookup and value lookup.
+    /// See [`lookup::disjoint_lookup()`].
     async fn lookup(
         &self,
         key: blake3::Hash,
@@ -457,165 +560,62 @@ impl<H: DhtHandler> Dht<H> {
     ) -> (Vec<H::Node>, Vec<H::Value>) {
         let net_settings = self.p2p.settings().read_arc().await.clone();
         let external_addrs = self.p2p.hosts().external_addrs().await;
-
-        let (k, a) = (self.settings.k, self.settings.alpha);
-        let semaphore = Arc::new(Semaphore::new(self.settings.concurrency));
-        let queried_addrs = Arc::new(Mutex::new(HashSet::new()));
-        let mut seen_nodes = HashSet::new();
-        let mut nodes_to_visit = self.find_neighbors(&key, k).await;
-        let mut result = Vec::new();
-        let mut futures = FuturesUnordered::new();
-        let mut consecutive_stalls = 0;
-
-        let mut values = Vec::new();
-
-        let distance_check = |(furthest, next): (&H::Node, &H::Node)| {
-            BigUint::from_bytes_be(&self.distance(&key, &furthest.id())) <
-                BigUint::from_bytes_be(&self.distance(&key, &next.id()))
-        };
+        let self_id = self.handler().await.node().await.ok().map(|node| node.id());
+        let queried_addrs = Mutex::new(HashSet::new());
 
         // Create a channel if necessary and send a FIND NODES or FIND VALUE
-        // request to `addr`
-        let lookup = async |node: H::Node, key, addrs: Vec<Url>| {
-            let _permit = semaphore.acquire().await;
+        // request to `node`
+        let query = async |node: H::Node| -> Result<DhtLookupReply<H::Node, H::Value>> {
+            let addrs = dialable_node_addresses(&node.addresses(), &net_settings, &external_addrs);
 
             // Try all valid addresses for the node
             let mut last_err = None;
             for addr in addrs {
-                let mut queried_addrs_set = queried_addrs.lock().await;
                 // Skip if this address has already been queried
-                if queried_addrs_set.contains(&addr) {
-                    continue;
+                if !queried_addrs.lock().await.insert(addr.clone()) {
+                    continue
                 }
-                queried_addrs_set.insert(addr.clone());
-                drop(queried_addrs_set);
 
                 // Try to create or find an existing channel
-                let channel = self.create_channel(&addr).await.map(|(ch, _)| ch);
-
-                if let Err(e) = channel {
-                    last_err = Some(e);
-                    continue
-                }
-                let channel = channel.unwrap();
+                let channel = match self.create_channel(&addr).await {
+                    Ok((channel, _)) => channel,
+                    Err(e) => {
+                        last_err = Some(e);
+                        continue
+                    }
+                };
 
                 let handler = self.handler().await;
                 let res = match &lookup_type {
                     DhtLookupType::Nodes => {
-                        info!(target: "dht::lookup", "[DHT] [LOOKUP] Querying node {} for nodes lookup of key {}", H::key_to_string(&node.id()), H::key_to_string(key));
-                        handler.find_nodes(channel.clone(), key).await.map(DhtLookupReply::Nodes)
+                        info!(target: "dht::lookup", "[DHT] [LOOKUP] Querying node {} for nodes lookup of key {}", H::key_to_string(&node.id()), H::key_to_string(&key));
+                        handler.find_nodes(channel.clone(), &key).await.map(DhtLookupReply::Nodes)
                     }
                     DhtLookupType::Value => {
-                        info!(target: "dht::lookup", "[DHT] [LOOKUP] Querying node {} for value lookup of key {}", H::key_to_string(&node.id()), H::key_to_string(key));
-                        handler.find_value(channel.clone(), key).await
+                        info!(target: "dht::lookup", "[DHT] [LOOKUP] Querying node {} for value lookup of key {}", H::key_to_string(&node.id()), H::key_to_string(&key));
+                        handler.find_value(channel.clone(), &key).await
                     }
                 };
 
                 self.cleanup_channel(channel).await;
-                if res.is_ok() {
-                    return (node, res)
-                }
-                last_err = res.err();
-            }
-            if let Some(e) = last_err {
-                return (node, Err(e))
-            }
-
-            (node, Err(Error::Custom("All node's addresses failed".to_string())))
-        };
-
-        // Spawn up to `alpha` futures for lookup()
-        let spawn_futures = async |nodes_to_visit: &mut Vec<H::Node>,
-                                   futures: &mut FuturesUnordered<_>| {
-            for _ in 0..a {
-                if !nodes_to_visit.is_empty() {
-                    let node = nodes_to_visit.remove(0);
-                    let valid_addrs =
-                        dialable_node_addresses(&node.addresses(), &net_settings, &external_addrs);
-                    if !valid_addrs.is_empty() {
-                        futures.push(Box::pin(lookup(node, &key, valid_addrs)));
-                    }
-                }
-            }
-        };
-
-        // Initial futures
-        spawn_futures(&mut nodes_to_visit, &mut futures).await;
-
-        // Process lookup responses
-        while let Some((queried_node, res)) = futures.next().await {
-            if let Err(e) = res {
-                warn!(target: "dht::lookup", "[DHT] [LOOKUP] Error in lookup: {e}");
-
-                // Spawn next `alpha` futures if there are no more futures but
-                // we still have nodes to visit
-                if futures.is_empty() {
-                    spawn_futures(&mut nodes_to_visit, &mut futures).await;
-                }
-
-                continue;
-            }
-
-            let (nodes, value) = match res.unwrap() {
-                DhtLookupReply::Nodes(nodes) => (Some(nodes), None),
-                DhtLookupReply::Value(value) => (None, Some(value)),
-                DhtLookupReply::NodesAndValue(nodes, value) => (Some(nodes), Some(value)),
-            };
-
-            // Send the value we found to the publisher
-            if let Some(value) = value {
-                info!(target: "dht::lookup", "[DHT] [LOOKUP] Found value for {} from {}", H::key_to_string(&key), H::key_to_string(&queried_node.id()));
-                values.push(value.clone());
-                self.event_publisher.notify(DhtEvent::ValueFound { key, value }).await;
-            }
-
-            // Update nodes_to_visit
-            if let Some(mut nodes) = nodes {
-                if !nodes.is_empty() {
-                    info!(target: "dht::lookup", "[DHT] [LOOKUP] Found {} nodes from {}", nodes.len(), H::key_to_string(&queried_node.id()));
-
-                    self.event_publisher
-                        .notify(DhtEvent::NodesFound { key, nodes: nodes.clone() })
-                        .await;
-
-                    // Remove our own node and duplicates
-                    if let Ok(self_node) = self.handler().await.node().await {
-                        let self_id = self_node.id();
-                        nodes.retain(|node: &H::Node| {
-                            node.id() != self_id && seen_nodes.insert(node.id())
-                        });
+                match res {
+                    Ok(reply) => {
+                        return Ok(self.check_lookup_reply(&key, &node, self_id, reply).await)
                     }
-
-                    // Add new nodes to the list of nodes to visit
-                    nodes_to_visit.extend(nodes.clone());
-                    self.sort_by_distance(&mut nodes_to_visit, &key);
+                    Err(e) => last_err = Some(e),
                 }
             }
 
-            result.push(queried_node);
-            self.sort_by_distance(&mut result, &key);
-
-            // Early termination logic:
-            // The closest node to visit must be further than the furthest
-            // queried node, 3 consecutive times
-            if result.len() >= k &&
-                result.last().zip(nodes_to_visit.first()).is_some_and(distance_check)
-            {
-                consecutive_stalls += 1;
-                if consecutive_stalls >= 3 {
-                    break;
-                }
-            } else {
-                consecutive_stalls = 0;
-            }
+            Err(last_err
+                .unwrap_or_else(|| Error::Custom("All node's addresses failed".to_string())))
+        };
 
-            // Spawn next `alpha` futures
-            spawn_futures(&mut nodes_to_visit, &mut futures).await;
-        }
+        let initial_nodes = self.find_neighbors(&key, self.settings.k).await;
+        let (nodes, values) =
+            lookup::disjoint_lookup(&key, self_id, initial_nodes, &self.settings, query).await;
 
         info!(target: "dht::lookup", "[DHT] [LOOKUP] Lookup for {} completed", H::key_to_string(&key));
 
-        let nodes: Vec<_> = result.into_iter().take(k).collect();
         (nodes, values)
     }
 
@@ -653,6 +653,85 @@ impl<H: DhtHandler> Dht<H> {
         (nodes, values)
     }
 
+    /// Store `record` locally and send it to the closest nodes. The record
+    /// is republished until [`Dht::unpublish_record()`] is called.
+    pub async fn put_record(&self, record: DhtRecord) -> Result<()> {
+        let node_id = self.handler().await.node().await?.id();
+        self.records.insert(record.clone(), &node_id, &self.settings).await?;
+        self.records.publish(record.clone()).await;
+        self.replicate_record(&record).await;
+        Ok(())
+    }
+
+    /// Store a record sent by another node, if it is valid and we are one
+    /// of the nodes closest to its key.
+    pub async fn store_record(&self, record: DhtRecord) -> Result<()> {
+        let handler = self.handler().await;
+        handler.validate_record(&record).await?;
+        if !self.is_close_to(&record.key).await {
+            return Err(Error::Custom("We are not close to the DHT record key".to_string()))
+        }
+        let node_id = handler.node().await?.id();
+        self.records.insert(record, &node_id, &self.settings).await
+    }
+
+    /// Stop republishing the record of `publisher` for `key`.
+    pub async fn unpublish_record(&self, key: &blake3::Hash, publisher: &PublicKey) {
+        self.records.unpublish(key, publisher).await;
+    }
+
+    /// Send `record` to the nodes closest to its key.
+    pub async fn replicate_record(&self, record: &DhtRecord) {
+        let nodes = self.lookup_nodes(&record.key).await;
+        info!(target: "dht::replicate_record", "[DHT] Sending record for {} to {} nodes", H::key_to_string(&record.key), nodes.len());
+
+        let handler = self.handler().await;
+        for node in nodes {
+            if let Ok((channel, _)) = self.get_channel(&node).await {
+                let _ = handler.store_record(channel.clone(), record).await;
+                self.cleanup_channel(channel).await;
+            }
+        }
+    }
+
+    /// Find the records of `key`: our own and the ones stored by the nodes
+    /// closest to `key`. Only the latest valid record of each publisher is
+    /// returned.
+    pub async fn get_records(&self, key: &blake3::Hash) -> Vec<DhtRecord> {
+        let mut records: HashMap<[u8; 32], DhtRecord> = HashMap::new();
+        let mut add_record = |record: DhtRecord| {
+            let publisher = record.publisher.to_bytes();
+            if records.get(&publisher).is_some_and(|r| r.sequence >= record.sequence) {
+                return
+            }
+            records.insert(publisher, record);
+        };
+
+        for record in self.records.get(key).await {
+            add_record(record);
+        }
+
+        let handler = self.handler().await;
+        for node in self.lookup_nodes(key).await {
+            let Ok((channel, _)) = self.get_channel(&node).await else { continue };
+            let res = handler.find_records(channel.clone(), key).await;
+            self.cleanup_channel(channel).await;
+
+            let Ok(found) = res else { continue };
+            for record in found {
+                if record.key != *key ||
+                    record.value.len() > self.settings.record_max_size ||
+                    !record.verify()
+                {
+                    continue
+                }
+                add_record(record);
+            }
+        }
+
+        records.into_values().collect()
+    }
+
     /// Update a channel's `last_used` field in the channel cache.
     pub async fn update_channel(&self, channel_id: u32) {
         let channel_cache_lock = self.channel_cache.clone();
@@ -783,43 +862,4 @@ impl<H: DhtHandler> Dht<H> {
 }
 
 #[cfg(test)]
-mod tests {
-    use super::{dialable_node_addresses, NetSettings};
-    use url::Url;
-
-    #[test]
-    fn test_dht_traversal_keeps_mixed_address_canonical() {
-        let settings = NetSettings {
-            active_profiles: vec!["tor+tls".to_string()],
-            mixed_profiles: vec!["tcp+tls".to_string()],
-            ..Default::default()
-        };
-        let canonical = Url::parse("tcp+tls://mixed.example:28880").unwrap();
-        let derived = Url::parse("tor+tls://mixed.example:28880").unwrap();
-
-        let addresses = dialable_node_addresses(std::slice::from_ref(&canonical), &settings, &[]);
-
-        assert_eq!(addresses, [canonical]);
-        assert!(!addresses.contains(&derived));
-    }
-
-    #[test]
-    fn test_dht_bootstrap_filters_undialable_and_external_addresses() {
-        let settings = NetSettings {
-            active_profiles: vec!["tor".to_string()],
-            mixed_profiles: vec!["tcp".to_string()],
-            ..Default::default()
-        };
-        let mixed = Url::parse("tcp://mixed.example:28880").unwrap();
-        let incompatible = Url::parse("tcp+tls://incompatible.example:28880").unwrap();
-        let external = Url::parse("tor://self.example:28880").unwrap();
-
-        let addresses = dialable_node_addresses(
-            &[mixed.clone(), incompatible, external.clone()],
-            &settings,
-            &[external],
-        );
-
-        assert_eq!(addresses, [mixed]);
-    }
-}
+mod tests;
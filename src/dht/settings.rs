@@ -26,15 +26,49 @@ pub struct DhtSettings {
     pub alpha: usize,
     /// Maximum number of parallel lookup requests
     pub concurrency: usize,
+    /// Number of disjoint paths of a lookup
+    pub disjoint_paths: usize,
+    /// Number of nodes closest to our own node id we keep in the sibling list
+    pub siblings: usize,
     /// Timeout in seconds
     pub timeout: u64,
     /// Timeout in seconds for inbound connections
     pub inbound_timeout: u64,
+    /// Maximum TTL in seconds of the records we store
+    pub record_max_ttl: u64,
+    /// Maximum size in bytes of a record value
+    pub record_max_size: usize,
+    /// Maximum number of records we store
+    pub records_max: usize,
+    /// Maximum number of records we store from a single publisher
+    pub records_max_per_publisher: usize,
+    /// Maximum number of records we store for a single key
+    pub records_max_per_key: usize,
+    /// Interval in seconds at which we republish our own records
+    pub record_republish_interval: u64,
+    /// Interval in seconds at which we send the records we store to the
+    /// nodes closest to their key
+    pub record_replicate_interval: u64,
 }
 
 impl Default for DhtSettings {
     fn default() -> Self {
-        Self { k: 16, alpha: 4, concurrency: 10, timeout: 5, inbound_timeout: 30 }
+        Self {
+            k: 16,
+            alpha: 4,
+            concurrency: 10,
+            disjoint_paths: 4,
+            siblings: 32,
+            timeout: 5,
+            inbound_timeout: 30,
+            record_max_ttl: 86400,
+            record_max_size: 4096,
+            records_max: 10000,
+            records_max_per_publisher: 100,
+            records_max_per_key: 20,
+            record_republish_interval: 3600,
+            record_replicate_interval: 3600,
+        }
     }
 }
 
@@ -54,6 +88,15 @@ pub struct DhtSettingsOpt {
     #[structopt(long)]
     pub dht_concurrency: Option<usize>,
 
+    /// Number of disjoint paths of a DHT lookup
+    #[structopt(long)]
+    pub dht_disjoint_paths: Option<usize>,
+
+    /// Number of nodes closest to our own node id we keep in the DHT
+    /// sibling list
+    #[structopt(long)]
+    pub dht_siblings: Option<usize>,
+
     /// Timeout in seconds
     #[structopt(long)]
     pub dht_timeout: Option<u64>,
@@ -61,6 +104,35 @@ pub struct DhtSettingsOpt {
     /// Timeout in seconds for inbound connections
     #[structopt(long)]
     pub dht_inbound_timeout: Option<u64>,
+
+    /// Maximum TTL in seconds of the DHT records we store
+    #[structopt(long)]
+    pub dht_record_max_ttl: Option<u64>,
+
+    /// Maximum size in bytes of a DHT record value
+    #[structopt(long)]
+    pub dht_record_max_size: Option<usize>,
+
+    /// Maximum number of DHT records we store
+    #[structopt(long)]
+    pub dht_records_max: Option<usize>,
+
+    /// Maximum number of DHT records we store from a single publisher
+    #[structopt(long)]
+    pub dht_records_max_per_publisher: Option<usize>,
+
+    /// Maximum number of DHT records we store for a single key
+    #[structopt(long)]
+    pub dht_records_max_per_key: Option<usize>,
+
+    /// Interval in seconds at which we republish our own DHT records
+    #[structopt(long)]
+    pub dht_record_republish_interval: Option<u64>,
+
+    /// Interval in seconds at which we send the DHT records we store to the
+    /// nodes closest to their key
+    #[structopt(long)]
+    pub dht_record_replicate_interval: Option<u64>,
 }
 
 impl From<DhtSettingsOpt> for DhtSettings {
@@ -71,8 +143,23 @@ impl From<DhtSettingsOpt> for DhtSettings {
             k: opt.dht_k.unwrap_or(def.k),
             alpha: opt.dht_alpha.unwrap_or(def.alpha),
             concurrency: opt.dht_concurrency.unwrap_or(def.concurrency),
+            disjoint_paths: opt.dht_disjoint_paths.unwrap_or(def.disjoint_paths),
+            siblings: opt.dht_siblings.unwrap_or(def.siblings),
             timeout: opt.dht_timeout.unwrap_or(def.timeout),
             inbound_timeout: opt.dht_inbound_timeout.unwrap_or(def.inbound_timeout),
+            record_max_ttl: opt.dht_record_max_ttl.unwrap_or(def.record_max_ttl),
+            record_max_size: opt.dht_record_max_size.unwrap_or(def.record_max_size),
+            records_max: opt.dht_records_max.unwrap_or(def.records_max),
+            records_max_per_publisher: opt
+                .dht_records_max_per_publisher
+                .unwrap_or(def.records_max_per_publisher),
+            records_max_per_key: opt.dht_records_max_per_key.unwrap_or(def.records_max_per_key),
+            record_republish_interval: opt
+                .dht_record_republish_interval
+                .unwrap_or(def.record_republish_interval),
+            record_replicate_interval: opt
+                .dht_record_replicate_interval
+                .unwrap_or(def.record_replicate_interval),
         }
     }
 }
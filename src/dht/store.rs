@@ -0,0 +1,460 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2026 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Generic record storage over a [`crate::dht::Dht`].
+//!
+//! A [`DhtRecord`] is a value stored under a DHT key, signed by its
+//! publisher. A key can hold one record per publisher, and a publisher
+//! replaces its record by signing a new one with a higher sequence number.
+//!
+//! Records are stored by the `k` nodes closest to their key, and expire
+//! after their TTL (capped by [`DhtSettings::record_max_ttl`]) unless
+//! they are republished by their publisher. Nodes storing a record send it
+//! again to the closest nodes periodically and when new nodes join, so
+//! that records survive churn.
+//!
+//! Publisher keys cost nothing to create, so the per-publisher quota alone
+//! does not protect the storage. A key holds at most
+//! [`DhtSettings::records_max_per_key`] records, and once the storage is
+//! full a new record replaces the one whose key is the furthest from our
+//! node id, if its own key is closer. Filling the storage of a node then
+//! requires finding keys closer to it than the records it already stores.
+
+use std::collections::HashMap;
+
+use smol::lock::RwLock;
+
+use crate::{
+    dht::{lookup::distance, DhtSettings},
+    util::time::Timestamp,
+    Error, Result,
+};
+use darkfi_sdk::crypto::{
+    schnorr::{SchnorrPublic, SchnorrSecret, Signature},
+    PublicKey, SecretKey,
+};
+use darkfi_serial::{SerialDecodable, SerialEncodable};
+
+/// A signed value stored in the DHT
+#[derive(Clone, Debug, SerialEncodable, SerialDecodable)]
+pub struct DhtRecord {
+    /// DHT key of the record
+    pub key: blake3::Hash,
+    /// Public key of the publisher
+    pub publisher: PublicKey,
+    /// Version of the record, the record with the highest sequence number
+    /// is the latest
+    pub sequence: u64,
+    /// Time in seconds the record should be stored for
+    pub ttl: u64,
+    /// Application-specific value
+    pub value: Vec<u8>,
+    /// Publisher's signature of (key || sequence || ttl || value)
+    pub sig: Signature,
+}
+
+impl DhtRecord {
+    /// Create and sign a new record.
+    pub fn new(
+        secret_key: &SecretKey,
+        key: &blake3::Hash,
+        sequence: u64,
+        ttl: u64,
+        value: Vec<u8>,
+    ) -> Self {
+        let sig = secret_key.sign(&Self::signed_data(key, sequence, ttl, &value));
+        Self {
+            key: *key,
+            publisher: PublicKey::from_secret(*secret_key),
+            sequence,
+            ttl,
+            value,
+            sig,
+        }
+    }
+
+    fn signed_data(key: &blake3::Hash, sequence: u64, ttl: u64, value: &[u8]) -> Vec<u8> {
+        [
+            b"dht_record".as_slice(),
+            key.as_bytes(),
+            &sequence.to_le_bytes(),
+            &ttl.to_le_bytes(),
+            value,
+        ]
+        .concat()
+    }
+
+    /// Returns `true` if the record is signed by its publisher.
+    pub fn verify(&self) -> bool {
+        self.publisher
+            .verify(&Self::signed_data(&self.key, self.sequence, self.ttl, &self.value), &self.sig)
+    }
+}
+
+/// A record in a [`DhtRecordStore`]
+#[derive(Clone, Debug)]
+struct StoredRecord {
+    record: DhtRecord,
+    /// When the record expires
+    expires: u64,
+    /// Last time we sent the record to the nodes closest to its key
+    replicated: u64,
+}
+
+/// Records stored by a node: the records it is responsible for, and the
+/// records it published.
+pub struct DhtRecordStore {
+    /// Key -> records of this key, at most one per publisher
+    records: RwLock<HashMap<blake3::Hash, Vec<StoredRecord>>>,
+    /// (Key, publisher) -> record we published and last time we sent it
+    published: RwLock<HashMap<(blake3::Hash, [u8; 32]), (DhtRecord, u64)>>,
+}
+
+impl Default for DhtRecordStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DhtRecordStore {
+    pub fn new() -> Self {
+        Self { records: RwLock::new(HashMap::new()), published: RwLock::new(HashMap::new()) }
+    }
+
+    /// Verify and store `record`, enforcing the storage quotas of `settings`.
+    /// If we already have a record of the same publisher for this key, it is
+    /// replaced if `record` is newer, or its expiry is refreshed if it is
+    /// the same version (if the publisher signed two values with the same
+    /// sequence number, the first one we received is kept).
+    /// `node_id` is our own node id, the storage keeps the records closest
+    /// to it when it is full.
+    pub async fn insert(
+        &self,
+        record: DhtRecord,
+        node_id: &blake3::Hash,
+        settings: &DhtSettings,
+    ) -> Result<()> {
+        if record.value.len() > settings.record_max_size {
+            return Err(Error::Custom(format!(
+                "DHT record is too large ({} bytes, max {})",
+                record.value.len(),
+                settings.record_max_size
+            )))
+        }
+        if !record.verify() {
+            return Err(Error::InvalidSignature)
+        }
+
+        let now = Timestamp::current_time().inner();
+        let expires = now + record.ttl.min(settings.record_max_ttl);
+        let mut records = self.records.write().await;
+
+        // Replace or refresh the record of the same publisher
+        if let Some(stored) = records.get_mut(&record.key).and_then(|key_records| {
+            key_records.iter_mut().find(|s| s.record.publisher == record.publisher)
+        }) {
+            if record.sequence < stored.record.sequence {
+                return Err(Error::Custom("DHT record is older than the stored one".to_string()))
+            }
+            if record.sequence > stored.record.sequence {
+                stored.record = record;
+            }
+            stored.expires = expires;
+            return Ok(())
+        }
+
+        // Expired records don't count in the quotas
+        prune_expired(&mut records, now);
+
+        // Storage quotas
+        if records.get(&record.key).is_some_and(|r| r.len() >= settings.records_max_per_key) {
+            return Err(Error::Custom("Too many DHT records for this key".to_string()))
+        }
+        let mut total = 0;
+        let mut publisher_total = 0;
+        for stored in records.values().flatten() {
+            total += 1;
+            if stored.record.publisher == record.publisher {
+                publisher_total += 1;
+            }
+        }
+        if publisher_total >= settings.records_max_per_publisher {
+            return Err(Error::Custom("Too many DHT records from this publisher".to_string()))
+        }
+        if total >= settings.records_max {
+            // Replace a record of the furthest key, if it is further than
+            // the key of `record`
+            let furthest = records.keys().max_by_key(|key| distance(node_id, key)).copied();
+            let Some(furthest) =
+                furthest.filter(|key| distance(node_id, key) > distance(node_id, &record.key))
+            else {
+                return Err(Error::Custom("DHT record storage is full".to_string()))
+            };
+            let key_records = records.get_mut(&furthest).unwrap();
+            key_records.pop();
+            if key_records.is_empty() {
+                records.remove(&furthest);
+            }
+        }
+
+        records.entry(record.key).or_default().push(StoredRecord {
+            record,
+            expires,
+            replicated: now,
+        });
+
+        Ok(())
+    }
+
+    /// Get the records of `key` that did not expire.
+    pub async fn get(&self, key: &blake3::Hash) -> Vec<DhtRecord> {
+        let now = Timestamp::current_time().inner();
+        let records = self.records.read().await;
+        let Some(key_records) = records.get(key) else { return vec![] };
+        key_records.iter().filter(|s| s.expires > now).map(|s| s.record.clone()).collect()
+    }
+
+    /// Get all the records that did not expire.
+    pub async fn get_all(&self) -> Vec<DhtRecord> {
+        let now = Timestamp::current_time().inner();
+        let records = self.records.read().await;
+        records.values().flatten().filter(|s| s.expires > now).map(|s| s.record.clone()).collect()
+    }
+
+    /// Remove expired records. Returns the number of records removed.
+    pub async fn prune(&self) -> usize {
+        let now = Timestamp::current_time().inner();
+        prune_expired(&mut *self.records.write().await, now)
+    }
+
+    /// Get the stored records that were not sent to the closest nodes in the
+    /// last `interval` seconds, and mark them as sent.
+    pub async fn due_for_replication(&self, interval: u64) -> Vec<DhtRecord> {
+        let now = Timestamp::current_time().inner();
+        let mut records = self.records.write().await;
+        let mut due = vec![];
+        for stored in records.values_mut().flatten() {
+            if stored.expires > now && stored.replicated + interval <= now {
+                stored.replicated = now;
+                due.push(stored.record.clone());
+            }
+        }
+        due
+    }
+
+    /// Add `record` to the records we publish, replacing the previous
+    /// version of it.
+    pub async fn publish(&self, record: DhtRecord) {
+        let now = Timestamp::current_time().inner();
+        let id = (record.key, record.publisher.to_bytes());
+        self.published.write().await.insert(id, (record, now));
+    }
+
+    /// Stop republishing the record of `publisher` for `key`. Nodes storing
+    /// it drop it once it expires.
+    pub async fn unpublish(&self, key: &blake3::Hash, publisher: &PublicKey) -> Option<DhtRecord> {
+        let id = (*key, publisher.to_bytes());
+        self.published.write().await.remove(&id).map(|(record, _)| record)
+    }
+
+    /// Get the records we publish.
+    pub async fn get_published(&self) -> Vec<DhtRecord> {
+        self.published.read().await.values().map(|(record, _)| record.clone()).collect()
+    }
+
+    /// Get the records we publish that were not sent in the last `interval`
+    /// seconds, and mark them as sent.
+    pub async fn due_for_republish(&self, interval: u64) -> Vec<DhtRecord> {
+        let now = Timestamp::current_time().inner();
+        let mut published = self.published.write().await;
+        let mut due = vec![];
+        for (record, last_published) in published.values_mut() {
+            if *last_published + interval <= now {
+                *last_published = now;
+                due.push(record.clone());
+            }
+        }
+        due
+    }
+
+    /// Remove all the stored records (published records are kept).
+    pub async fn clear(&self) {
+        self.records.write().await.clear();
+    }
+}
+
+/// Remove the records that expired at `now`. Returns the number of records
+/// removed.
+fn prune_expired(records: &mut HashMap<blake3::Hash, Vec<StoredRecord>>, now: u64) -> usize {
+    let mut removed = 0;
+    records.retain(|_, key_records| {
+        let len = key_records.len();
+        key_records.retain(|s| s.expires > now);
+        removed += len - key_records.len();
+        !key_records.is_empty()
+    });
+    removed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DhtRecord, DhtRecordStore};
+    use crate::dht::{lookup::distance, DhtSettings};
+    use darkfi_sdk::crypto::SecretKey;
+    use rand::rngs::OsRng;
+
+    #[test]
+    fn test_dht_record_store_versions_and_quotas() {
+        smol::block_on(async {
+            let settings =
+                DhtSettings { records_max: 3, records_max_per_publisher: 2, ..Default::default() };
+            let store = DhtRecordStore::new();
+            let alice = SecretKey::random(&mut OsRng);
+            let bob = SecretKey::random(&mut OsRng);
+            let carol = SecretKey::random(&mut OsRng);
+            let key = blake3::hash(b"key");
+            // Our node id is `key`, so records of `key` are the closest to us
+            let node_id = key;
+
+            // A newer record replaces the previous one, an older one is rejected
+            store
+                .insert(DhtRecord::new(&alice, &key, 1, 60, b"a1".to_vec()), &node_id, &settings)
+                .await
+                .unwrap();
+            store
+                .insert(DhtRecord::new(&alice, &key, 2, 60, b"a2".to_vec()), &node_id, &settings)
+                .await
+                .unwrap();
+            assert!(store
+                .insert(DhtRecord::new(&alice, &key, 1, 60, b"a1".to_vec()), &node_id, &settings)
+                .await
+                .is_err());
+
+            // Another value with the same sequence number does not replace
+            // the first one
+            store
+                .insert(DhtRecord::new(&alice, &key, 2, 60, b"a3".to_vec()), &node_id, &settings)
+                .await
+                .unwrap();
+            let records = store.get(&key).await;
+            assert_eq!(records.len(), 1);
+            assert_eq!(records[0].value, b"a2");
+
+            // Tampered records are rejected
+            let mut record = DhtRecord::new(&bob, &key, 1, 60, b"b1".to_vec());
+            record.value = b"b2".to_vec();
+            assert!(store.insert(record, &node_id, &settings).await.is_err());
+
+            // Per-publisher quota
+            let key2 = blake3::hash(b"key2");
+            let key3 = blake3::hash(b"key3");
+            store
+                .insert(DhtRecord::new(&alice, &key2, 1, 60, vec![]), &node_id, &settings)
+                .await
+                .unwrap();
+            assert!(store
+                .insert(DhtRecord::new(&alice, &key3, 1, 60, vec![]), &node_id, &settings)
+                .await
+                .is_err());
+
+            // Once the storage is full, a record of a closer key replaces
+            // the record of the furthest key
+            store
+                .insert(DhtRecord::new(&bob, &key3, 1, 60, vec![]), &node_id, &settings)
+                .await
+                .unwrap();
+            let (closer, further) = match distance(&node_id, &key2) < distance(&node_id, &key3) {
+                true => (key2, key3),
+                false => (key3, key2),
+            };
+            store
+                .insert(DhtRecord::new(&carol, &key, 1, 60, vec![]), &node_id, &settings)
+                .await
+                .unwrap();
+            assert_eq!(store.get(&key).await.len(), 2);
+            assert_eq!(store.get(&closer).await.len(), 1);
+            assert!(store.get(&further).await.is_empty());
+
+            // A record of a further key is rejected
+            let key4 = (0u8..)
+                .map(|i| blake3::hash(&[i]))
+                .find(|k| distance(&node_id, k) > distance(&node_id, &closer))
+                .unwrap();
+            let dave = SecretKey::random(&mut OsRng);
+            assert!(store
+                .insert(DhtRecord::new(&dave, &key4, 1, 60, vec![]), &node_id, &settings)
+                .await
+                .is_err());
+
+            // Oversized values are rejected
+            let value = vec![0u8; settings.record_max_size + 1];
+            assert!(store
+                .insert(DhtRecord::new(&dave, &key, 1, 60, value), &node_id, &settings)
+                .await
+                .is_err());
+        });
+    }
+
+    #[test]
+    fn test_dht_record_store_key_quota() {
+        smol::block_on(async {
+            let settings = DhtSettings { records_max_per_key: 2, ..Default::default() };
+            let store = DhtRecordStore::new();
+            let key = blake3::hash(b"key");
+            let node_id = blake3::hash(b"node");
+
+            for _ in 0..2 {
+                let secret = SecretKey::random(&mut OsRng);
+                let record = DhtRecord::new(&secret, &key, 1, 60, vec![]);
+                store.insert(record, &node_id, &settings).await.unwrap();
+            }
+
+            // New publishers can't add more records to this key
+            let secret = SecretKey::random(&mut OsRng);
+            let record = DhtRecord::new(&secret, &key, 1, 60, vec![]);
+            assert!(store.insert(record, &node_id, &settings).await.is_err());
+        });
+    }
+
+    #[test]
+    fn test_dht_record_store_expiry() {
+        smol::block_on(async {
+            let settings = DhtSettings { records_max: 1, ..Default::default() };
+            let store = DhtRecordStore::new();
+            let secret = SecretKey::random(&mut OsRng);
+            let key = blake3::hash(b"key");
+            let node_id = blake3::hash(b"node");
+
+            store
+                .insert(DhtRecord::new(&secret, &key, 1, 0, vec![]), &node_id, &settings)
+                .await
+                .unwrap();
+            assert!(store.get(&key).await.is_empty());
+
+            // Expired records don't count in the quotas
+            let key2 = blake3::hash(b"key2");
+            store
+                .insert(DhtRecord::new(&secret, &key2, 1, 0, vec![]), &node_id, &settings)
+                .await
+                .unwrap();
+
+            assert_eq!(store.prune().await, 1);
+            assert!(store.get_all().await.is_empty());
+        });
+    }
+}
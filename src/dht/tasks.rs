@@ -187,6 +187,13 @@ pub async fn add_node_task<H: DhtHandler>(handler: Arc<H>) -> Result<()> {
         }
         let self_node = self_node.unwrap();
 
+        // Do not add a node with an invalid identity
+        if let Err(e) = handler.validate_node(&node).await {
+            warn!(target: "dht::tasks::add_node_task", "[DHT] Not adding invalid node {}: {e}", H::key_to_string(&node.id()));
+            dht.cleanup_channel(channel).await;
+            continue;
+        }
+
         let bucket_index = dht.get_bucket_index(&self_node.id(), &node.id()).await;
         let buckets_lock = dht.buckets.clone();
         let mut buckets = buckets_lock.write().await;
@@ -211,6 +218,8 @@ pub async fn add_node_task<H: DhtHandler>(handler: Arc<H>) -> Result<()> {
             continue;
         }
 
+        dht.add_sibling(&self_node.id(), &node).await;
+
         // We already have this node, move it to the tail of the bucket
         if let Some(node_index) = bucket.nodes.iter().position(|n| n.id() == node.id()) {
             bucket.nodes.remove(node_index);
@@ -234,9 +243,10 @@ pub async fn add_node_task<H: DhtHandler>(handler: Arc<H>) -> Result<()> {
             }
 
             // Ping was not successful, remove the least recently seen node and add the new node
-            bucket.nodes.remove(0);
+            let removed = bucket.nodes.remove(0);
             bucket.nodes.push(node.clone());
             drop(buckets);
+            dht.siblings.write().await.retain(|n| n.id() != removed.id());
             dht.on_new_node(&node.clone(), channel.clone()).await;
             dht.cleanup_channel(channel).await;
             continue;
@@ -315,3 +325,36 @@ pub async fn cleanup_channels_task<H: DhtHandler>(handler: Arc<H>) -> Result<()>
         }
     }
 }
+
+/// Maintain the DHT records: remove expired records, republish our own
+/// records and send the records we store to the nodes closest to their key,
+/// as they may have changed since we received them.
+pub async fn records_task<H: DhtHandler>(handler: Arc<H>) -> Result<()> {
+    let interval = 60; // TODO: Make a setting
+    let dht = handler.dht();
+
+    loop {
+        sleep(interval).await;
+
+        let removed = dht.records.prune().await;
+        if removed > 0 {
+            info!(target: "dht::records_task", "Removed {removed} expired records");
+        }
+
+        if !dht.is_bootstrapped().await {
+            continue
+        }
+
+        let Ok(self_node) = handler.node().await else { continue };
+        for record in dht.records.due_for_republish(dht.settings.record_republish_interval).await {
+            // Refresh our local copy too
+            let _ = dht.records.insert(record.clone(), &self_node.id(), &dht.settings).await;
+            dht.replicate_record(&record).await;
+        }
+
+        for record in dht.records.due_for_replication(dht.settings.record_replicate_interval).await
+        {
+            dht.replicate_record(&record).await;
+        }
+    }
+}
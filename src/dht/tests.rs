@@ -0,0 +1,536 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2026 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use url::Url;
+
+use super::{dialable_node_addresses, NetSettings};
+
+#[test]
+fn test_dht_traversal_keeps_mixed_address_canonical() {
+    let settings = NetSettings {
+        active_profiles: vec!["tor+tls".to_string()],
+        mixed_profiles: vec!["tcp+tls".to_string()],
+        ..Default::default()
+    };
+    let canonical = Url::parse("tcp+tls://mixed.example:28880").unwrap();
+    let derived = Url::parse("tor+tls://mixed.example:28880").unwrap();
+
+    let addresses = dialable_node_addresses(std::slice::from_ref(&canonical), &settings, &[]);
+
+    assert_eq!(addresses, [canonical]);
+    assert!(!addresses.contains(&derived));
+}
+
+#[test]
+fn test_dht_bootstrap_filters_undialable_and_external_addresses() {
+    let settings = NetSettings {
+        active_profiles: vec!["tor".to_string()],
+        mixed_profiles: vec!["tcp".to_string()],
+        ..Default::default()
+    };
+    let mixed = Url::parse("tcp://mixed.example:28880").unwrap();
+    let incompatible = Url::parse("tcp+tls://incompatible.example:28880").unwrap();
+    let external = Url::parse("tor://self.example:28880").unwrap();
+
+    let addresses = dialable_node_addresses(
+        &[mixed.clone(), incompatible, external.clone()],
+        &settings,
+        &[external],
+    );
+
+    assert_eq!(addresses, [mixed]);
+}
+
+/// A DHT network running over the in-memory transport, with a minimal
+/// [`DhtHandler`] speaking the same ping / find nodes / find value / store
+/// flow as fud.
+#[cfg(feature = "p2p-memory")]
+mod network {
+    use std::{collections::HashMap, sync::Arc, time::Duration};
+
+    use async_trait::async_trait;
+    use darkfi_sdk::crypto::{PublicKey, SecretKey};
+    use darkfi_serial::{SerialDecodable, SerialEncodable};
+    use rand::rngs::OsRng;
+    use smol::{channel, future, Executor, Task, Timer};
+    use url::Url;
+
+    use crate::{
+        dht::{
+            event::DhtEvent, impl_dht_node_defaults, lookup, tasks as dht_tasks, Dht, DhtHandler,
+            DhtLookupReply, DhtNode, DhtRecord, DhtSettings,
+        },
+        impl_p2p_message,
+        net::{
+            metering::{MeteringConfiguration, DEFAULT_METERING_CONFIGURATION},
+            session::{SESSION_DIRECT, SESSION_INBOUND, SESSION_MANUAL},
+            settings::NetworkProfile,
+            ChannelPtr, Message, MessageSubscription, P2p, P2pPtr, ProtocolBase, ProtocolBasePtr,
+            ProtocolJobsManager, ProtocolJobsManagerPtr, Settings,
+        },
+        system::timeout::timeout,
+        Error, Result,
+    };
+
+    const N_NODES: usize = 16;
+
+    /// Key of the test records, bound to their publisher like fud pointers
+    fn record_key(publisher: &PublicKey, value: &[u8]) -> blake3::Hash {
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(&publisher.to_bytes());
+        hasher.update(value);
+        hasher.finalize()
+    }
+
+    #[derive(Debug, Clone, SerialEncodable, SerialDecodable)]
+    struct TestNode {
+        id: blake3::Hash,
+        addresses: Vec<Url>,
+    }
+
+    impl DhtNode for TestNode {
+        fn id(&self) -> blake3::Hash {
+            self.id
+        }
+
+        fn addresses(&self) -> Vec<Url> {
+            self.addresses.clone()
+        }
+    }
+    impl_dht_node_defaults!(TestNode);
+
+    #[derive(Debug, Clone, SerialEncodable, SerialDecodable)]
+    struct TestPingRequest {
+        random: u64,
+    }
+    impl_p2p_message!(TestPingRequest, "TestDhtPingRequest", 0, 0, DEFAULT_METERING_CONFIGURATION);
+
+    #[derive(Debug, Clone, SerialEncodable, SerialDecodable)]
+    struct TestPingReply {
+        node: TestNode,
+    }
+    impl_p2p_message!(TestPingReply, "TestDhtPingReply", 0, 0, DEFAULT_METERING_CONFIGURATION);
+
+    #[derive(Debug, Clone, SerialEncodable, SerialDecodable)]
+    struct TestFindRequest {
+        key: blake3::Hash,
+    }
+    impl_p2p_message!(TestFindRequest, "TestDhtFindRequest", 0, 0, DEFAULT_METERING_CONFIGURATION);
+
+    #[derive(Debug, Clone, SerialEncodable, SerialDecodable)]
+    struct TestFindReply {
+        key: blake3::Hash,
+        nodes: Vec<TestNode>,
+        value: Option<Vec<u8>>,
+    }
+    impl_p2p_message!(TestFindReply, "TestDhtFindReply", 0, 0, DEFAULT_METERING_CONFIGURATION);
+
+    #[derive(Debug, Clone, SerialEncodable, SerialDecodable)]
+    struct TestStore {
+        key: blake3::Hash,
+        value: Vec<u8>,
+    }
+    impl_p2p_message!(TestStore, "TestDhtStore", 0, 0, DEFAULT_METERING_CONFIGURATION);
+
+    struct TestHandler {
+        node: TestNode,
+        dht: Arc<Dht<TestHandler>>,
+    }
+
+    impl TestHandler {
+        /// Send a FIND request to `channel` and wait for the reply for `key`
+        async fn find(&self, channel: ChannelPtr, key: &blake3::Hash) -> Result<TestFindReply> {
+            let sub = channel.subscribe_msg::<TestFindReply>().await?;
+            channel.send(&TestFindRequest { key: *key }).await?;
+            let reply = loop {
+                match sub.receive_with_timeout(self.dht.settings.timeout).await {
+                    Ok(reply) if reply.key == *key => break Ok(reply),
+                    Ok(_) => continue,
+                    Err(e) => break Err(e),
+                }
+            };
+            sub.unsubscribe().await;
+            Ok((*reply?).clone())
+        }
+    }
+
+    #[async_trait]
+    impl DhtHandler for TestHandler {
+        type Value = Vec<u8>;
+        type Node = TestNode;
+
+        fn dht(&self) -> Arc<Dht<Self>> {
+            self.dht.clone()
+        }
+
+        async fn node(&self) -> Result<TestNode> {
+            Ok(self.node.clone())
+        }
+
+        async fn validate_node(&self, _node: &TestNode) -> Result<()> {
+            Ok(())
+        }
+
+        async fn validate_record(&self, record: &DhtRecord) -> Result<()> {
+            if record.key != record_key(&record.publisher, &record.value) {
+                return Err(Error::Custom("Record key doesn't belong to its publisher".to_string()))
+            }
+            Ok(())
+        }
+
+        async fn ping(&self, channel: ChannelPtr) -> Result<TestNode> {
+            let sub = channel.subscribe_msg::<TestPingReply>().await?;
+            channel.send(&TestPingRequest { random: 0 }).await?;
+            let reply = sub.receive_with_timeout(self.dht.settings.timeout).await;
+            sub.unsubscribe().await;
+            let node = reply?.node.clone();
+
+            self.dht
+                .event_publisher
+                .notify(DhtEvent::PingReceived { from: channel.clone(), result: Ok(node.id()) })
+                .await;
+
+            if channel.session_type_id() & (SESSION_DIRECT | SESSION_MANUAL) != 0 {
+                // Wait for the other node to ping us, then add it to our buckets
+                let pinged = self.dht.wait_fully_pinged(channel.info.id);
+                if timeout(Duration::from_secs(10), pinged).await.is_err() {
+                    self.dht.cleanup_channel(channel).await;
+                    return Err(Error::ConnectTimeout)
+                }
+                self.dht.update_node(&node, channel.clone()).await;
+            } else if channel.session_type_id() & SESSION_INBOUND != 0 {
+                // Check that the node is reachable, which adds it to our buckets
+                let dht = self.dht.clone();
+                let node = node.clone();
+                self.dht
+                    .executor
+                    .spawn(async move {
+                        if let Ok((channel, _)) = dht.create_channel_to_node(&node).await {
+                            dht.cleanup_channel(channel).await;
+                        }
+                    })
+                    .detach();
+            }
+
+            self.dht.add_channel_to_cache(channel.info.id, &node).await;
+            Ok(node)
+        }
+
+        async fn store(
+            &self,
+            channel: ChannelPtr,
+            key: &blake3::Hash,
+            value: &Vec<u8>,
+        ) -> Result<()> {
+            channel.send(&TestStore { key: *key, value: value.clone() }).await
+        }
+
+        async fn find_nodes(
+            &self,
+            channel: ChannelPtr,
+            key: &blake3::Hash,
+        ) -> Result<Vec<TestNode>> {
+            Ok(self.find(channel, key).await?.nodes)
+        }
+
+        async fn find_value(
+            &self,
+            channel: ChannelPtr,
+            key: &blake3::Hash,
+        ) -> Result<DhtLookupReply<TestNode, Vec<u8>>> {
+            let reply = self.find(channel, key).await?;
+            Ok(match reply.value {
+                Some(value) => DhtLookupReply::NodesAndValue(reply.nodes, value),
+                None => DhtLookupReply::Nodes(reply.nodes),
+            })
+        }
+
+        async fn store_record(&self, _channel: ChannelPtr, _record: &DhtRecord) -> Result<()> {
+            Ok(())
+        }
+
+        async fn find_records(
+            &self,
+            _channel: ChannelPtr,
+            _key: &blake3::Hash,
+        ) -> Result<Vec<DhtRecord>> {
+            Ok(vec![])
+        }
+
+        async fn add_value(&self, key: &blake3::Hash, value: &Vec<u8>) {
+            self.dht.hash_table.write().await.insert(*key, value.clone());
+        }
+
+        fn key_to_string(key: &blake3::Hash) -> String {
+            key.to_hex().to_string()
+        }
+    }
+
+    struct ProtocolTestDht {
+        channel: ChannelPtr,
+        ping_sub: MessageSubscription<TestPingRequest>,
+        find_sub: MessageSubscription<TestFindRequest>,
+        store_sub: MessageSubscription<TestStore>,
+        handler: Arc<TestHandler>,
+        jobsman: ProtocolJobsManagerPtr,
+    }
+
+    impl ProtocolTestDht {
+        async fn init(handler: Arc<TestHandler>, channel: ChannelPtr) -> Result<ProtocolBasePtr> {
+            let msg_subsystem = channel.message_subsystem();
+            msg_subsystem.add_dispatch::<TestPingRequest>().await;
+            msg_subsystem.add_dispatch::<TestPingReply>().await;
+            msg_subsystem.add_dispatch::<TestFindRequest>().await;
+            msg_subsystem.add_dispatch::<TestFindReply>().await;
+            msg_subsystem.add_dispatch::<TestStore>().await;
+
+            Ok(Arc::new(Self {
+                channel: channel.clone(),
+                ping_sub: channel.subscribe_msg().await?,
+                find_sub: channel.subscribe_msg().await?,
+                store_sub: channel.subscribe_msg().await?,
+                handler,
+                jobsman: ProtocolJobsManager::new("ProtocolTestDht", channel.clone()),
+            }))
+        }
+
+        async fn handle_ping(self: Arc<Self>) -> Result<()> {
+            loop {
+                if self.ping_sub.receive().await.is_err() {
+                    continue
+                }
+                let dht = self.handler.dht();
+                dht.update_channel(self.channel.info.id).await;
+
+                let result =
+                    self.channel.send(&TestPingReply { node: self.handler.node.clone() }).await;
+                dht.event_publisher
+                    .notify(DhtEvent::PingSent { to: self.channel.clone(), result })
+                    .await;
+
+                // Ping the peer if this is an inbound connection
+                if self.channel.session_type_id() & SESSION_INBOUND != 0 {
+                    let _ = dht.ping(self.channel.clone()).await;
+                }
+            }
+        }
+
+        async fn handle_find(self: Arc<Self>) -> Result<()> {
+            loop {
+                let Ok(request) = self.find_sub.receive().await else { continue };
+                let dht = self.handler.dht();
+                dht.update_channel(self.channel.info.id).await;
+
+                let reply = TestFindReply {
+                    key: request.key,
+                    nodes: dht.find_neighbors(&request.key, dht.settings.k).await,
+                    value: dht.hash_table.read().await.get(&request.key).cloned(),
+                };
+                let _ = self.channel.send(&reply).await;
+            }
+        }
+
+        async fn handle_store(self: Arc<Self>) -> Result<()> {
+            loop {
+                let Ok(request) = self.store_sub.receive().await else { continue };
+                self.handler.add_value(&request.key, &request.value).await;
+            }
+        }
+    }
+
+    #[async_trait]
+    impl ProtocolBase for ProtocolTestDht {
+        async fn start(self: Arc<Self>, executor: Arc<Executor<'_>>) -> Result<()> {
+            self.jobsman.clone().start(executor.clone()).await?;
+            self.jobsman.clone().spawn(self.clone().handle_ping(), executor.clone()).await;
+            self.jobsman.clone().spawn(self.clone().handle_find(), executor.clone()).await;
+            self.jobsman.clone().spawn(self.clone().handle_store(), executor.clone()).await;
+            Ok(())
+        }
+
+        fn name(&self) -> &'static str {
+            "ProtocolTestDht"
+        }
+    }
+
+    /// A running DHT node and its background tasks
+    struct TestDhtNode {
+        handler: Arc<TestHandler>,
+        p2p: P2pPtr,
+        _tasks: Vec<Task<Result<()>>>,
+    }
+
+    async fn spawn_node(host: &str, peers: Vec<Url>, ex: Arc<Executor<'static>>) -> TestDhtNode {
+        let addr = Url::parse(&format!("mem://{host}.dht:1")).unwrap();
+        let mut profiles = HashMap::new();
+        profiles.insert(
+            "mem".to_string(),
+            NetworkProfile { outbound_connect_timeout: 2, ..Default::default() },
+        );
+        let settings = Settings {
+            localnet: true,
+            inbound_addrs: vec![addr.clone()],
+            external_addrs: vec![addr.clone()],
+            outbound_connections: 0,
+            inbound_connections: usize::MAX,
+            peers,
+            node_id: host.to_string(),
+            active_profiles: vec!["mem".to_string()],
+            profiles,
+            ..Default::default()
+        };
+        let p2p = P2p::new(settings, ex.clone()).await.unwrap();
+
+        let dht_settings =
+            DhtSettings { k: 4, alpha: 2, disjoint_paths: 2, timeout: 2, ..Default::default() };
+        let dht = Arc::new(Dht::new(&dht_settings, p2p.clone(), ex.clone()).await);
+        let node = TestNode { id: blake3::hash(host.as_bytes()), addresses: vec![addr] };
+        let handler = Arc::new(TestHandler { node, dht: dht.clone() });
+        *dht.handler.write().await = Arc::downgrade(&handler);
+
+        let handler_ = handler.clone();
+        p2p.protocol_registry()
+            .register(SESSION_DIRECT | SESSION_INBOUND | SESSION_MANUAL, move |channel, _| {
+                let handler = handler_.clone();
+                async move { ProtocolTestDht::init(handler, channel).await.unwrap() }
+            })
+            .await;
+
+        let tasks = vec![
+            ex.spawn(dht_tasks::events_task(handler.clone())),
+            ex.spawn(dht_tasks::channel_task(handler.clone())),
+            ex.spawn(dht_tasks::add_node_task(handler.clone())),
+        ];
+        p2p.clone().start().await.unwrap();
+
+        TestDhtNode { handler, p2p, _tasks: tasks }
+    }
+
+    async fn dht_memory_network(ex: Arc<Executor<'static>>) {
+        let bootstrap_addr = Url::parse("mem://dhtnode0.dht:1").unwrap();
+        let mut nodes = vec![spawn_node("dhtnode0", vec![], ex.clone()).await];
+        for i in 1..N_NODES {
+            let node = spawn_node(&format!("dhtnode{i}"), vec![bootstrap_addr.clone()], ex.clone());
+            nodes.push(node.await);
+        }
+
+        // Every node must learn about at least `k` other nodes
+        timeout(Duration::from_secs(60), async {
+            loop {
+                let mut converged = true;
+                for node in &nodes {
+                    let dht = node.handler.dht();
+                    let known = dht.find_neighbors(&node.handler.node.id, N_NODES).await;
+                    converged &= known.len() >= dht.settings.k;
+                }
+                if converged {
+                    break
+                }
+                Timer::after(Duration::from_millis(100)).await;
+            }
+        })
+        .await
+        .expect("DHT did not bootstrap");
+
+        // Lookups find the `k` closest nodes of the whole network
+        for (i, key) in [b"key0", b"key1", b"key2"].iter().enumerate() {
+            let key = blake3::hash(*key);
+            let searcher = &nodes[(i * 5 + 3) % N_NODES].handler;
+            let mut expected: Vec<_> = nodes
+                .iter()
+                .map(|node| node.handler.node.clone())
+                .filter(|node| node.id != searcher.node.id)
+                .collect();
+            lookup::sort_by_distance(&mut expected, &key);
+            expected.truncate(searcher.dht.settings.k);
+
+            let found = searcher.dht.lookup_nodes(&key).await;
+            assert_eq!(found, expected);
+        }
+
+        // A value announced by a node is found by another one
+        let key = blake3::hash(b"value key");
+        let value = b"value".to_vec();
+        let announcer = &nodes[5].handler;
+        let message = TestStore { key, value: value.clone() };
+        announcer.dht.announce(&key, &value, &message).await.unwrap();
+        // Wait for the stores to be processed
+        Timer::after(Duration::from_millis(500)).await;
+        let (_, values) = nodes[11].handler.dht.lookup_value(&key).await;
+        assert!(values.contains(&value));
+
+        for node in nodes {
+            node.p2p.stop().await;
+        }
+    }
+
+    async fn dht_record_validation(ex: Arc<Executor<'static>>) {
+        let node = spawn_node("recordnode", vec![], ex).await;
+        let dht = node.handler.dht();
+
+        let secret = SecretKey::random(&mut OsRng);
+        let value = b"site".to_vec();
+        let key = record_key(&PublicKey::from_secret(secret), &value);
+
+        // Other publishers can't fill the record quota of the key
+        for _ in 0..dht.settings.records_max_per_key {
+            let attacker = SecretKey::random(&mut OsRng);
+            let record = DhtRecord::new(&attacker, &key, 1, 3600, value.clone());
+            assert!(dht.store_record(record).await.is_err());
+        }
+        assert!(dht.records.get(&key).await.is_empty());
+
+        let record = DhtRecord::new(&secret, &key, 1, 3600, value);
+        dht.store_record(record).await.unwrap();
+        let stored = dht.records.get(&key).await;
+        assert_eq!(stored.len(), 1);
+        assert_eq!(stored[0].publisher, PublicKey::from_secret(secret));
+
+        node.p2p.stop().await;
+    }
+
+    #[test]
+    fn test_dht_record_validation() {
+        let ex = Arc::new(Executor::new());
+        let (signal, shutdown) = channel::unbounded::<()>();
+
+        easy_parallel::Parallel::new()
+            .each(0..2, |_| future::block_on(ex.run(shutdown.recv())))
+            .finish(|| {
+                future::block_on(async {
+                    dht_record_validation(ex.clone()).await;
+                    drop(signal);
+                })
+            });
+    }
+
+    #[test]
+    fn test_dht_memory_network() {
+        let ex = Arc::new(Executor::new());
+        let (signal, shutdown) = channel::unbounded::<()>();
+
+        easy_parallel::Parallel::new()
+            .each(0..4, |_| future::block_on(ex.run(shutdown.recv())))
+            .finish(|| {
+                future::block_on(async {
+                    dht_memory_network(ex.clone()).await;
+                    drop(signal);
+                })
+            });
+    }
+}
@@ -205,6 +205,12 @@ pub enum Error {
     #[error("Invalid state transition: current_state={0}, end_state={1}")]
     HostStateBlocked(String, String),
 
+    #[error("Invalid node identity signature")]
+    InvalidNodeIdentity,
+
+    #[error("Peer node key rejected")]
+    NodeKeyRejected,
+
     #[cfg(feature = "upnp-igd")]
     #[error(transparent)]
     UpnpError(#[from] oxy_upnp_igd::Error),
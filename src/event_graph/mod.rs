@@ -26,7 +26,7 @@ use std::{
     str::FromStr,
     sync::{
         atomic::{AtomicBool, Ordering},
-        Arc,
+        Arc, OnceLock,
     },
 };
 
@@ -38,7 +38,7 @@ use smol::{
     lock::{OnceCell, RwLock},
     Executor,
 };
-use tracing::{error, info, warn};
+use tracing::{debug, error, info, warn};
 use url::Url;
 
 use crate::{
@@ -53,10 +53,10 @@ pub use event::{display_order, Event, Header};
 
 pub mod proto;
 use proto::{
-    cap_layer_tips, count_layer_tips, EventRep, EventReq, HeaderRep, HeaderReq, RangeCursor,
-    RangeRep, RangeReq, StaticPut, SyncDirection, TipRep, TipReq, MAX_EVENT_REP_EVENTS,
-    MAX_EVENT_REQ_IDS, MAX_HEADER_REP_HEADERS, MAX_HEADER_REQ_TIPS, MAX_RANGE_PAGE_SIZE,
-    MAX_TIP_REP_TIPS,
+    cap_layer_tips, count_layer_tips, recon_initiate, recon_process, EventRep, EventReq, HeaderRep,
+    HeaderReq, RangeCursor, RangeRep, RangeReq, ReconRep, ReconReq, StaticPut, SyncDirection,
+    TipRep, TipReq, MAX_EVENT_REP_EVENTS, MAX_EVENT_REQ_IDS, MAX_HEADER_REP_HEADERS,
+    MAX_HEADER_REQ_TIPS, MAX_RANGE_PAGE_SIZE, MAX_RECON_ROUNDS, MAX_TIP_REP_TIPS,
 };
 
 pub mod rln;
@@ -71,6 +71,14 @@ use util::{
 pub mod deg;
 use deg::DegEvent;
 
+pub mod storage;
+use storage::{Storage, StorageOverlay, StorageTree};
+
+pub mod redaction;
+use redaction::{
+    is_redacted_body, placeholder_redaction, redacted_placeholder, Redaction, RedactionPolicy,
+};
+
 #[cfg(test)]
 mod tests;
 
@@ -218,6 +226,9 @@ fn validate_pregenerated_identity_commitments(
 pub struct TimeIndex {
     index: BTreeMap<u64, Vec<blake3::Hash>>,
     count: usize,
+    /// [`TimeIndex::cursors`], built once per change of the index and
+    /// shared by the reconciliation rounds of every peer
+    cursors: OnceLock<Arc<Vec<RangeCursor>>>,
 }
 
 impl TimeIndex {
@@ -225,7 +236,7 @@ impl TimeIndex {
         Self::default()
     }
 
-    pub async fn from_header_dag(tree: &sled::Tree) -> Result<Self> {
+    pub async fn from_header_dag(tree: &StorageTree) -> Result<Self> {
         let mut idx = Self::new();
         for item in tree.iter() {
             let (id, hdr) = item?;
@@ -245,6 +256,7 @@ impl TimeIndex {
         ids.push(id);
         ids.sort_by_key(hash_order_key);
         self.count += 1;
+        self.cursors = OnceLock::new();
     }
 
     pub fn newest(&self, n: usize) -> Vec<blake3::Hash> {
@@ -296,6 +308,19 @@ impl TimeIndex {
         out
     }
 
+    /// Every indexed event as a cursor, ordered by `(timestamp, event_id)`.
+    pub fn cursors(&self) -> Arc<Vec<RangeCursor>> {
+        self.cursors
+            .get_or_init(|| {
+                let mut out = Vec::with_capacity(self.count);
+                for (ts, ids) in &self.index {
+                    out.extend(ids.iter().map(|id| RangeCursor { timestamp: *ts, event_id: *id }));
+                }
+                Arc::new(out)
+            })
+            .clone()
+    }
+
     fn rev(&self, start: u64, n: usize) -> Vec<blake3::Hash> {
         let mut out = Vec::with_capacity(n);
         for (_, ids) in self.index.range(..=start).rev() {
@@ -333,8 +358,8 @@ impl TimeIndex {
 
 /// All per-DAG state: trees, tips, and the timestamp index.
 pub struct DagSlot {
-    pub header_tree: sled::Tree,
-    pub main_tree: sled::Tree,
+    pub header_tree: StorageTree,
+    pub main_tree: StorageTree,
     pub tips: LayerUTips,
     pub time_index: TimeIndex,
 }
@@ -342,7 +367,7 @@ pub struct DagSlot {
 /// Full-scan tip computation.
 /// Compute unreferenced tips - events that exist in the DAG but are
 /// not referenced as a parent by any other event - grouped by layer.
-pub(crate) async fn compute_unreferenced_tips(dag: &sled::Tree) -> Result<LayerUTips> {
+pub(crate) async fn compute_unreferenced_tips(dag: &StorageTree) -> Result<LayerUTips> {
     let mut candidates: HashMap<blake3::Hash, u64> = HashMap::new();
     let mut referenced: HashSet<blake3::Hash> = HashSet::new();
 
@@ -394,7 +419,7 @@ fn select_parents_from_tips(tips: &LayerUTips) -> (u64, [blake3::Hash; N_EVENT_P
 
 /// Storage layer for all rotating DAGs.
 pub struct DagStore {
-    db: sled::Db,
+    storage: Storage,
     dags: BTreeMap<u64, DagSlot>,
 }
 
@@ -408,30 +433,30 @@ impl DagStore {
     /// * **Archive mode** (`max_dags = None`): discover *all*
     ///   existing DAG trees in sled and load them, plus ensure the
     ///   recent window exists. Nothing is ever dropped.
-    pub async fn new(sled_db: sled::Db, config: &EventGraphConfig) -> Result<Self> {
+    pub async fn new(storage: impl Into<Storage>, config: &EventGraphConfig) -> Result<Self> {
         config.validate()?;
+        let storage = storage.into();
         let mut dags = BTreeMap::new();
 
         if config.hours_rotation == 0 {
             let genesis = generate_genesis(config)?;
-            dags.insert(genesis.header.timestamp, Self::create_slot(&sled_db, &genesis).await?);
-            return Ok(Self { db: sled_db, dags })
+            dags.insert(genesis.header.timestamp, Self::create_slot(&storage, &genesis).await?);
+            return Ok(Self { storage, dags })
         }
 
         // Determine how many recent DAGs to create/ensure exist.
         let window = config.max_dags.unwrap_or(24);
 
         // In archive mode, first discover and load any existing DAG
-        // trees that are already in sled from previous runs.
+        // trees that are already in storage from previous runs.
         //
         // A DAG is stored across two trees: `<timestamp>` for events
         // and `headers_<timestamp>` for headers. We walk every tree
-        // name in sled and pick out the ones whose name is a valid u64
-        // timestamp.
+        // name in storage and pick out the ones whose name is a valid
+        // u64 timestamp.
         if config.max_dags.is_none() {
-            for name in sled_db.tree_names() {
-                let name_str = String::from_utf8_lossy(&name);
-                if let Ok(ts) = name_str.parse::<u64>() {
+            for name in storage.tree_names()? {
+                if let Ok(ts) = name.parse::<u64>() {
                     // Reconstruct the genesis for this timestamp
                     let hdr = Header {
                         timestamp: ts,
@@ -440,7 +465,7 @@ impl DagStore {
                         content_hash: blake3::hash(&config.genesis_contents),
                     };
                     let genesis = Event { header: hdr, content: config.genesis_contents.clone() };
-                    let slot = Self::create_slot(&sled_db, &genesis).await?;
+                    let slot = Self::create_slot(&storage, &genesis).await?;
                     dags.insert(ts, slot);
                 }
             }
@@ -461,21 +486,21 @@ impl DagStore {
                 content_hash: blake3::hash(&config.genesis_contents),
             };
             let genesis = Event { header: hdr, content: config.genesis_contents.clone() };
-            dags.insert(ts, Self::create_slot(&sled_db, &genesis).await?);
+            dags.insert(ts, Self::create_slot(&storage, &genesis).await?);
         }
 
-        Ok(Self { db: sled_db, dags })
+        Ok(Self { storage, dags })
     }
 
-    async fn create_slot(db: &sled::Db, genesis: &Event) -> Result<DagSlot> {
+    async fn create_slot(storage: &Storage, genesis: &Event) -> Result<DagSlot> {
         let name = genesis.header.timestamp.to_string();
-        let ht = db.open_tree(format!("headers_{name}"))?;
-        let mt = db.open_tree(&name)?;
+        let ht = storage.open_tree(&format!("headers_{name}"))?;
+        let mt = storage.open_tree(&name)?;
         for (tree, data) in
             [(&ht, serialize_async(&genesis.header).await), (&mt, serialize_async(genesis).await)]
         {
             if tree.is_empty() {
-                let mut ov = SledTreeOverlay::new(tree);
+                let mut ov = StorageOverlay::new(tree);
                 ov.insert(genesis.id().as_bytes(), &data)?;
                 if let Some(b) = ov.aggregate() {
                     tree.apply_batch(b)?;
@@ -502,11 +527,11 @@ impl DagStore {
                 let Some((_, old)) = self.dags.pop_first() else {
                     return Err(Error::Custom("event graph DAG store is empty".into()))
                 };
-                self.db.drop_tree(old.header_tree.name())?;
-                self.db.drop_tree(old.main_tree.name())?;
+                self.storage.drop_tree(&old.header_tree.name())?;
+                self.storage.drop_tree(&old.main_tree.name())?;
             }
         }
-        let slot = Self::create_slot(&self.db, genesis).await?;
+        let slot = Self::create_slot(&self.storage, genesis).await?;
         self.dags.insert(genesis.header.timestamp, slot);
         Ok(())
     }
@@ -519,8 +544,8 @@ impl DagStore {
         self.dags.get_mut(ts)
     }
 
-    pub fn get_header_tree(&self, dag_name: &str) -> Result<sled::Tree> {
-        Ok(self.db.open_tree(format!("headers_{dag_name}"))?)
+    pub fn get_header_tree(&self, dag_name: &str) -> Result<StorageTree> {
+        self.storage.open_tree(&format!("headers_{dag_name}"))
     }
 
     pub fn dag_timestamps(&self) -> Vec<u64> {
@@ -554,6 +579,20 @@ pub struct RangeSyncPage {
     pub exhausted: bool,
 }
 
+/// Result of one [`EventGraph::dag_sync_reconcile`] call.
+#[derive(Clone, Debug, Default)]
+pub struct ReconSyncStats {
+    /// Peers the reconciliation completed with.
+    pub peers: usize,
+    /// Reconciliation round trips, summed over peers.
+    pub rounds: usize,
+    /// Events we were missing, fetched and inserted.
+    pub fetched: usize,
+    /// Advertised events no peer served, or that could not be linked
+    /// to the DAG without those.
+    pub dropped: usize,
+}
+
 /// Match an `EventRep` against the exact IDs requested for one sync chunk.
 ///
 /// The response may be partial, but every returned event must be unique and
@@ -687,7 +726,15 @@ pub struct EventGraph {
     /// by `handle_event_req` to forward blobs to syncing peers.
     /// Pruned by `dag_prune` when the corresponding rotating DAG
     /// rolls out of the retention window.
-    pub(crate) dag_blobs: sled::Tree,
+    pub(crate) dag_blobs: StorageTree,
+    /// Applied redactions: `target_id -> redaction event`. The body of
+    /// every target is stored empty, see [`redaction`].
+    pub(crate) dag_redacted: sled::Tree,
+    /// Redactions waiting for their target body or for a policy:
+    /// `(target_id, redaction_id) -> redaction event`.
+    pub(crate) dag_pending_redactions: sled::Tree,
+    /// Application check deciding which redactions are applied
+    redaction_policy: RwLock<Option<Arc<dyn RedactionPolicy>>>,
     /// Verified range-sync bodies waiting for older parent bodies before they
     /// can be durably committed to the rotating DAG body tree.
     lazy_pending: RwLock<HashMap<u64, HashMap<blake3::Hash, PendingLazyEvent>>>,
@@ -737,6 +784,8 @@ pub struct EventGraph {
     pub deg_enabled: AtomicBool,
     deg_publisher: PublisherPtr<DegEvent>,
     pub sled_db: sled::Db,
+    /// Backend holding the rotating DAGs and their blobs, see [`storage`]
+    storage: Storage,
     pub zk_keys: Option<Arc<ZkKeys>>,
     pub identity_state: Option<RwLock<IdentityState>>,
     pub rln_state: Option<RwLock<RlnState>>,
@@ -783,6 +832,24 @@ impl EventGraph {
         replay_mode: bool,
         config: EventGraphConfig,
         ex: Arc<Executor<'_>>,
+    ) -> Result<EventGraphPtr> {
+        let storage = Storage::from(sled_db.clone());
+        Self::new_with_storage(p2p, sled_db, zk_key_db, storage, datastore, replay_mode, config, ex)
+            .await
+    }
+
+    /// Create a new Event Graph keeping the rotating DAGs in `storage`.
+    /// The static DAG and RLN state stay in `sled_db`.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn new_with_storage(
+        p2p: P2pPtr,
+        sled_db: sled::Db,
+        zk_key_db: sled::Db,
+        storage: Storage,
+        datastore: PathBuf,
+        replay_mode: bool,
+        config: EventGraphConfig,
+        ex: Arc<Executor<'_>>,
     ) -> Result<EventGraphPtr> {
         config.validate()?;
         let zk_keys = if config.rln_enabled {
@@ -793,7 +860,17 @@ impl EventGraph {
             info!(target: "event_graph::new", "[EVENTGRAPH] RLN disabled; skipping key initialization");
             None
         };
-        Self::with_optional_zk_keys(p2p, sled_db, datastore, replay_mode, config, zk_keys, ex).await
+        Self::with_optional_zk_keys(
+            p2p,
+            sled_db,
+            storage,
+            datastore,
+            replay_mode,
+            config,
+            zk_keys,
+            ex,
+        )
+        .await
     }
 
     /// Same as [`Self::new`] but accepts a pre-built [`ZkKeys`].
@@ -812,13 +889,25 @@ impl EventGraph {
         zk_keys: Arc<ZkKeys>,
         ex: Arc<Executor<'_>>,
     ) -> Result<EventGraphPtr> {
-        Self::with_optional_zk_keys(p2p, sled_db, datastore, replay_mode, config, Some(zk_keys), ex)
-            .await
+        let storage = Storage::from(sled_db.clone());
+        Self::with_optional_zk_keys(
+            p2p,
+            sled_db,
+            storage,
+            datastore,
+            replay_mode,
+            config,
+            Some(zk_keys),
+            ex,
+        )
+        .await
     }
 
+    #[allow(clippy::too_many_arguments)]
     async fn with_optional_zk_keys(
         p2p: P2pPtr,
         sled_db: sled::Db,
+        storage: Storage,
         datastore: PathBuf,
         replay_mode: bool,
         config: EventGraphConfig,
@@ -847,10 +936,12 @@ impl EventGraph {
             } else {
                 (Vec::new(), HashSet::new())
             };
-        let dag_store = DagStore::new(sled_db.clone(), &config).await?;
+        let dag_store = DagStore::new(storage.clone(), &config).await?;
         let static_dag = Self::static_new(&sled_db, &config).await?;
         let static_dag_blobs = sled_db.open_tree("static-dag-blobs")?;
-        let dag_blobs = sled_db.open_tree("dag-blobs")?;
+        let dag_blobs = storage.open_tree("dag-blobs")?;
+        let dag_redacted = sled_db.open_tree("dag-redacted")?;
+        let dag_pending_redactions = sled_db.open_tree("dag-pending-redactions")?;
 
         // Historical-roots side-tables. See the design comment on
         // `EventGraph::apply_rln_static_event` for the full rationale.
@@ -874,10 +965,14 @@ impl EventGraph {
         let self_ = Arc::new(Self {
             p2p,
             sled_db: sled_db.clone(),
+            storage,
             dag_store: RwLock::new(dag_store),
             static_dag,
             static_dag_blobs,
             dag_blobs,
+            dag_redacted,
+            dag_pending_redactions,
+            redaction_policy: RwLock::new(None),
             lazy_pending: RwLock::new(HashMap::new()),
             rln_historical_roots_ordered,
             rln_historical_roots_by_value,
@@ -1160,8 +1255,145 @@ impl EventGraph {
     /// Use this when the application wants the complete historical
     /// content (e.g. an archive node, or a node rebuilding local state
     /// from the full event stream).
+    ///
+    /// Tries [`Self::dag_sync_reconcile`] first, and falls back to the
+    /// tip-based sync when no peer completes a reconciliation.
     pub async fn dag_sync(&self, dag_ts: u64) -> Result<()> {
-        self.sync_impl(dag_ts, true).await
+        match self.dag_sync_reconcile(dag_ts).await {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                debug!(
+                    target: "event_graph::sync",
+                    "[DAG_SYNC] reconciliation of dag {dag_ts} failed ({e}), syncing from tips",
+                );
+                self.sync_impl(dag_ts, true).await
+            }
+        }
+    }
+
+    /// Full sync of one DAG by set reconciliation with every connected peer.
+    ///
+    /// Each peer is sent [`ReconReq`] rounds over our time index until we
+    /// know which events it has that we don't. This takes a number of round
+    /// trips logarithmic in the DAG size, a few more than the two of the tip
+    /// sync, but only the differing IDs are sent whatever our tips, and no
+    /// quorum on the tips is needed. The missing events are then fetched with
+    /// `EventReq`, and inserted through the usual header validation and RLN
+    /// verification. Events a peer advertises but nobody serves are dropped
+    /// with the events depending on them, instead of failing the
+    /// reconciliation with the honest peers.
+    pub async fn dag_sync_reconcile(&self, dag_ts: u64) -> Result<ReconSyncStats> {
+        let dag_name = dag_ts.to_string();
+        let channels = self.p2p.hosts().peers();
+        if channels.is_empty() {
+            return Err(Error::DagSyncFailed)
+        }
+        let timeout = self.p2p.settings().read().await.outbound_connect_timeout_max();
+
+        let items = {
+            let store = self.dag_store.read().await;
+            let slot = store.get_slot(&dag_ts).ok_or(Error::DagSyncFailed)?;
+            slot.time_index.cursors()
+        };
+
+        let mut futs = FuturesUnordered::new();
+        for ch in channels.iter() {
+            futs.push(request_recon(ch.clone(), dag_name.clone(), &items, timeout));
+        }
+
+        let mut stats = ReconSyncStats::default();
+        let mut wanted = vec![];
+        let mut needed = HashSet::new();
+        while let Some((result, peer)) = futs.next().await {
+            match result {
+                Ok((need, rounds)) => {
+                    stats.peers += 1;
+                    stats.rounds += rounds;
+                    needed.extend(need.iter().copied());
+                    wanted.push((peer, need));
+                }
+                Err(e) => {
+                    debug!(
+                        target: "event_graph::sync",
+                        "[DAG_SYNC] reconciliation with {} failed: {e}",
+                        peer.display_address(),
+                    );
+                }
+            }
+        }
+        drop(futs);
+        if stats.peers == 0 {
+            return Err(Error::DagSyncFailed)
+        }
+
+        // Every missing ID is asked from the peers advertising it in
+        // turn, until one serves it.
+        let mut received: HashMap<blake3::Hash, (Event, Vec<u8>)> = HashMap::new();
+        for (peer, need) in &wanted {
+            let need: Vec<blake3::Hash> =
+                need.iter().filter(|id| !received.contains_key(id)).copied().collect();
+            for (cid, chunk) in need.chunks(MAX_EVENT_REQ_IDS).enumerate() {
+                let (Ok((events, blobs)), _, _) =
+                    request_event(peer.clone(), chunk.to_vec(), cid, timeout).await
+                else {
+                    break
+                };
+                let Ok((events, blobs, _)) = filter_requested_event_rep(chunk, events, blobs)
+                else {
+                    break
+                };
+                for (event, blob) in events.into_iter().zip(blobs) {
+                    received.insert(event.id(), (event, blob));
+                }
+            }
+        }
+        // A peer advertising IDs nobody serves only loses us those, and
+        // the events we can't link to the DAG without them.
+        for (peer, need) in &wanted {
+            let unserved = need.iter().filter(|id| !received.contains_key(id)).count();
+            if unserved > 0 {
+                warn!(
+                    target: "event_graph::sync",
+                    "[DAG_SYNC] {} advertised {unserved} events nobody served",
+                    peer.display_address(),
+                );
+            }
+        }
+        stats.dropped = needed.len() - received.len();
+
+        let mut fetched: Vec<(Event, Vec<u8>)> = received.into_values().collect();
+        fetched.sort_by_key(|(event, _)| event.header.layer);
+        {
+            let store = self.dag_store.read().await;
+            let slot = store.get_slot(&dag_ts).ok_or(Error::DagSyncFailed)?;
+            let mut linked = HashSet::new();
+            let before = fetched.len();
+            fetched.retain(|(event, _)| {
+                let parents_known = event.header.parents.iter().all(|p| {
+                    *p == NULL_ID ||
+                        linked.contains(p) ||
+                        slot.header_tree.contains_key(p.as_bytes()).unwrap_or(false)
+                });
+                if parents_known {
+                    linked.insert(event.id());
+                }
+                parents_known
+            });
+            stats.dropped += before - fetched.len();
+        }
+
+        if !fetched.is_empty() {
+            let (events, blobs): (Vec<Event>, Vec<Vec<u8>>) = fetched.into_iter().unzip();
+            self.header_dag_insert(events.iter().map(|e| e.header.clone()).collect(), &dag_name)
+                .await?;
+            self.dag_insert_with_blobs(&events, &blobs, &dag_name).await?;
+            stats.fetched = events.len();
+        }
+
+        // Headers we already had may still be missing their bodies.
+        self.fetch_missing_events(dag_ts, &dag_name, timeout).await?;
+
+        Ok(stats)
     }
 
     async fn sync_impl(&self, dag_ts: u64, fetch_content: bool) -> Result<()> {
@@ -1296,10 +1528,20 @@ impl EventGraph {
                     };
 
                     match filter_requested_event_rep(requested, events, blobs) {
-                        Ok((matched_events, matched_blobs, missing)) => {
-                            let matched = matched_events.len();
+                        Ok((matched_events, matched_blobs, mut missing)) => {
+                            let mut matched = 0;
                             for (event, blob) in matched_events.into_iter().zip(matched_blobs) {
                                 let event_id = event.id();
+                                // Placeholders whose redaction we can't
+                                // authorize are asked to another peer.
+                                if placeholder_redaction(&event).is_some() &&
+                                    !self.placeholder_authorized(&event).await
+                                {
+                                    missing.push(event_id);
+                                    continue
+                                }
+
+                                matched += 1;
                                 if received.insert(event_id, (event, blob)).is_none() {
                                     count += 1;
                                 }
@@ -1842,6 +2084,44 @@ impl EventGraph {
         Ok(out)
     }
 
+    /// Fetch a page of events past the exclusive `cursor`, crossing DAG
+    /// boundaries transparently. Unlike [`Self::fetch_page()`], events
+    /// sharing a timestamp with the cursor are neither skipped nor
+    /// returned twice. Returns the events and the cursor to continue from.
+    pub async fn fetch_page_at(
+        &self,
+        cursor: RangeCursor,
+        dir: SyncDirection,
+        limit: usize,
+    ) -> Result<(Vec<Event>, RangeCursor)> {
+        let limit = limit.min(MAX_RANGE_PAGE_SIZE);
+        let mut out = vec![];
+        let mut next_cursor = cursor;
+        let store = self.dag_store.read().await;
+        let slots: Vec<_> = match dir {
+            SyncDirection::Forward => store.dags.iter().collect(),
+            SyncDirection::Backward => store.dags.iter().rev().collect(),
+        };
+        for (_, slot) in slots {
+            if out.len() >= limit {
+                break
+            }
+            let rem = limit - out.len();
+            let ids = match dir {
+                SyncDirection::Forward => slot.time_index.after_cursor(cursor, rem),
+                SyncDirection::Backward => slot.time_index.before_cursor(cursor, rem),
+            };
+            for id in ids {
+                if let Some(bytes) = slot.main_tree.get(id.as_bytes())? {
+                    let event: Event = deserialize_async(&bytes).await?;
+                    next_cursor = RangeCursor { timestamp: event.header.timestamp, event_id: id };
+                    out.push(event);
+                }
+            }
+        }
+        Ok((out, next_cursor))
+    }
+
     /// Fetch a DAG-scoped page with aligned RLN blobs for peer range sync.
     ///
     /// Non-genesis events without a stored blob are skipped because a requester
@@ -1884,6 +2164,22 @@ impl EventGraph {
             if event.header.parents == NULL_PARENTS {
                 continue
             }
+
+            // Redacted events are served as placeholders, without a blob
+            if event.id() == id && is_redacted_body(&event) {
+                match self.servable_body(event).await? {
+                    Some(placeholder) => {
+                        events.push(placeholder);
+                        blobs.push(Vec::new());
+                    }
+                    None => warn!(
+                        target: "event_graph::range",
+                        "[EVENTGRAPH] refusing to serve redacted range event {id} without redaction",
+                    ),
+                }
+                continue
+            }
+
             if event.id() != id || !event.content_matches_header() {
                 warn!(
                     target: "event_graph::range",
@@ -1967,10 +2263,21 @@ impl EventGraph {
                 if !already_have && !slot.header_tree.contains_key(event_id.as_bytes())? {
                     return Err(Error::DagSyncFailed)
                 }
-                if !event.dag_validate(&slot.header_tree, &self.config, dag_ts).await? {
+                let is_placeholder = placeholder_redaction(&event).is_some();
+                let valid = if is_placeholder {
+                    self.validate_placeholder(&store, &event, &slot.header_tree, dag_ts).await?
+                } else {
+                    event.dag_validate(&slot.header_tree, &self.config, dag_ts).await?
+                };
+                if !valid {
                     return Err(Error::DagSyncFailed)
                 }
-                if self.rln_enabled() && !already_have && !already_pending && blob.is_empty() {
+                if self.rln_enabled() &&
+                    !already_have &&
+                    !already_pending &&
+                    !is_placeholder &&
+                    blob.is_empty()
+                {
                     return Err(Error::DagSyncFailed)
                 }
 
@@ -1986,7 +2293,7 @@ impl EventGraph {
                 continue
             }
 
-            if !self.rln_enabled() {
+            if !self.rln_enabled() || placeholder_redaction(&event).is_some() {
                 newly_pending.push(PendingLazyEvent { event: event.clone(), blob });
                 accepted_events.push(event);
                 continue
@@ -2117,11 +2424,21 @@ impl EventGraph {
             if store.dags.len() >= limit {
                 if let Some((_, oldest)) = store.dags.iter().next() {
                     for item in oldest.main_tree.iter() {
-                        let (eid, _) = match item {
+                        let (eid, body) = match item {
                             Ok(v) => v,
                             Err(_) => continue,
                         };
                         let _ = self.dag_blobs.remove(&eid);
+                        let _ = self.dag_redacted.remove(&eid);
+
+                        // Pending redactions leave with the redaction
+                        // event, whether their target showed up or not.
+                        let Ok(event) = deserialize_async::<Event>(&body).await else { continue };
+                        if let Some(redaction) = Redaction::from_event(&event) {
+                            let mut key = redaction.target.as_bytes().to_vec();
+                            key.extend_from_slice(&eid);
+                            let _ = self.dag_pending_redactions.remove(key);
+                        }
                     }
                 }
             }
@@ -2225,12 +2542,16 @@ impl EventGraph {
         blob: &[u8],
         dag_name: &str,
     ) -> Result<Vec<blake3::Hash>> {
-        if self.rln_enabled() && event.header.parents != NULL_PARENTS && blob.is_empty() {
+        // Redacted placeholders come without a blob, their proof is gone
+        let needs_blob = self.rln_enabled() &&
+            event.header.parents != NULL_PARENTS &&
+            placeholder_redaction(event).is_none();
+        if needs_blob && blob.is_empty() {
             return Err(Error::Custom("verified signal event blob must not be empty".into()))
         }
 
         self.header_dag_insert(vec![event.header.clone()], dag_name).await?;
-        if self.rln_enabled() && event.header.parents != NULL_PARENTS {
+        if needs_blob {
             self.dag_blob_store(&event.id(), blob)?;
         }
         self.dag_insert(std::slice::from_ref(event), dag_name).await
@@ -2286,11 +2607,12 @@ impl EventGraph {
         // and re-running it for an already-seen event would trip its
         // duplicate-share check.
         let dag_ts = u64::from_str(dag_name)?;
-        let (already_have, structurally_valid): (Vec<bool>, Vec<bool>) = {
+        let (already_have, structurally_valid, placeholders) = {
             let store = self.dag_store.read().await;
             let slot = store.get_slot(&dag_ts);
             let mut already_have = Vec::with_capacity(events.len());
             let mut structurally_valid = Vec::with_capacity(events.len());
+            let mut placeholders = Vec::with_capacity(events.len());
 
             for ev in events {
                 let eid = ev.id();
@@ -2300,6 +2622,12 @@ impl EventGraph {
                 };
                 already_have.push(have);
 
+                // Bodies of redacted events, served by peers which
+                // dropped the content.
+                let placeholder = placeholder_redaction(ev);
+                let is_placeholder = placeholder.is_some();
+                placeholders.push(placeholder);
+
                 if have || ev.header.parents == NULL_PARENTS {
                     structurally_valid.push(true);
                     continue
@@ -2315,12 +2643,20 @@ impl EventGraph {
                     continue
                 }
 
+                if is_placeholder {
+                    structurally_valid.push(
+                        self.validate_placeholder(&store, ev, &slot.header_tree, dag_ts).await?,
+                    );
+                    continue
+                }
+
                 structurally_valid
                     .push(ev.dag_validate(&slot.header_tree, &self.config, dag_ts).await?);
             }
 
-            (already_have, structurally_valid)
+            (already_have, structurally_valid, placeholders)
         };
+        let policy = self.redaction_policy.read().await.clone();
 
         let mut candidates: Vec<usize> = (0..events.len()).collect();
         sort_event_indices(events, &mut candidates);
@@ -2364,6 +2700,14 @@ impl EventGraph {
                 continue
             }
 
+            // The proof of a redacted event is dropped along with its
+            // content. The placeholder was checked against the header DAG.
+            if placeholders[i].is_some() {
+                accepted.push(i);
+                accepted_body_ids.insert(eid);
+                continue
+            }
+
             if !self.rln_enabled() {
                 accepted.push(i);
                 accepted_body_ids.insert(eid);
@@ -2425,8 +2769,9 @@ impl EventGraph {
 
         let mut ids = Vec::with_capacity(accepted.len());
         let mut committed_indices = Vec::with_capacity(accepted.len());
-        let mut overlay = SledTreeOverlay::new(&slot.main_tree);
+        let mut overlay = StorageOverlay::new(&slot.main_tree);
         let mut staged_body_ids = HashSet::with_capacity(accepted.len());
+        let mut redacted = Vec::new();
 
         'commit: for &i in &accepted {
             let ev = &events[i];
@@ -2441,7 +2786,13 @@ impl EventGraph {
             if !slot.header_tree.contains_key(eid.as_bytes())? {
                 continue
             }
-            if !ev.dag_validate(&slot.header_tree, &self.config, dag_ts).await? {
+            let valid = match placeholders[i] {
+                Some(_) => {
+                    ev.header.validate(&slot.header_tree, &self.config, dag_ts, None).await?
+                }
+                None => ev.dag_validate(&slot.header_tree, &self.config, dag_ts).await?,
+            };
+            if !valid {
                 return Err(Error::EventIsInvalid)
             }
             for pid in ev.header.parents.iter().filter(|pid| **pid != NULL_ID) {
@@ -2455,6 +2806,23 @@ impl EventGraph {
                 }
             }
 
+            // Redacted events are stored with an empty body, either
+            // because a peer served them redacted, or because we hold
+            // an authorized redaction which arrived first.
+            let redaction = match &placeholders[i] {
+                Some(redaction_event) => Some(redaction_event.clone()),
+                None => self.authorized_pending_redaction(policy.as_ref(), ev)?,
+            };
+            if let Some(redaction_event) = redaction {
+                let body = Event { header: ev.header.clone(), content: vec![] };
+                overlay.insert(eid.as_bytes(), &serialize_async(&body).await)?;
+                staged_body_ids.insert(eid);
+                redacted.push((eid, redaction_event));
+                ids.push(eid);
+                committed_indices.push(i);
+                continue
+            }
+
             let se = serialize_async(ev).await;
             overlay.insert(eid.as_bytes(), &se)?;
             staged_body_ids.insert(eid);
@@ -2481,11 +2849,16 @@ impl EventGraph {
         }
 
         if let Some(b) = overlay.aggregate() {
-            slot.main_tree.apply_batch(b)?;
+            slot.main_tree.apply_batch_unblocked(b).await?;
         } else {
             return Ok(vec![])
         }
 
+        for (eid, redaction_event) in &redacted {
+            self.dag_redacted.insert(eid.as_bytes(), serialize_async(redaction_event).await)?;
+            self.remove_pending_redactions(eid)?;
+        }
+
         for &i in &committed_indices {
             let ev = &events[i];
             let eid = ev.id();
@@ -2504,6 +2877,22 @@ impl EventGraph {
             }
             slot.tips.retain(|_, t| !t.is_empty());
             slot.tips.entry(ev.header.layer).or_default().insert(eid);
+        }
+
+        // Apply the committed redactions before publishing them, so
+        // subscribers find their targets already dropped.
+        for &i in &committed_indices {
+            if let Some(redaction) = Redaction::from_event(&events[i]) {
+                self.apply_redaction(&store, policy.as_ref(), &events[i], &redaction).await?;
+            }
+        }
+
+        for &i in &committed_indices {
+            let ev = &events[i];
+            let eid = ev.id();
+            if ev.header.parents == NULL_PARENTS || redacted.iter().any(|(id, _)| *id == eid) {
+                continue
+            }
             self.event_pub.notify(ev.clone()).await;
         }
 
@@ -2568,7 +2957,7 @@ impl EventGraph {
 
         let mut store = self.dag_store.write().await;
         let slot = store.get_slot_mut(&dag_ts).ok_or(Error::DagSyncFailed)?;
-        let mut overlay = SledTreeOverlay::new(&slot.header_tree);
+        let mut overlay = StorageOverlay::new(&slot.header_tree);
         let mut staged_headers = Vec::new();
         let mut hdrs = headers;
         hdrs.sort_by_key(|h| h.layer);
@@ -2608,7 +2997,7 @@ impl EventGraph {
         }
 
         if let Some(b) = overlay.aggregate() {
-            slot.header_tree.apply_batch(b)?;
+            slot.header_tree.apply_batch_unblocked(b).await?;
             for (timestamp, hid) in staged_headers {
                 slot.time_index.insert(timestamp, hid);
             }
@@ -2649,7 +3038,7 @@ impl EventGraph {
     pub(crate) async fn get_next_layer_with_parents_static(
         &self,
     ) -> Result<(u64, [blake3::Hash; N_EVENT_PARENTS])> {
-        let tips = compute_unreferenced_tips(&self.static_dag).await?;
+        let tips = compute_unreferenced_tips(&StorageTree::from(self.static_dag.clone())).await?;
         Ok(select_parents_from_tips(&tips))
     }
 
@@ -2727,7 +3116,7 @@ impl EventGraph {
         &self,
         visited: &mut HashSet<blake3::Hash>,
         hdr: Header,
-        tree: &sled::Tree,
+        tree: &StorageTree,
     ) -> Result<()> {
         let mut stack = VecDeque::new();
         stack.push_back(hdr);
@@ -2837,7 +3226,7 @@ impl EventGraph {
         })
     }
     pub async fn static_unreferenced_tips(&self) -> Result<LayerUTips> {
-        compute_unreferenced_tips(&self.static_dag).await
+        compute_unreferenced_tips(&StorageTree::from(self.static_dag.clone())).await
     }
 
     /// Audit static-DAG blob coverage and repair deterministic guard blobs.
@@ -2929,6 +3318,200 @@ impl EventGraph {
         Ok(self.static_dag_blobs.get(eid.as_bytes())?.map(|ivec| ivec.to_vec()))
     }
 
+    /// Set the application check deciding which redactions are applied.
+    /// Redactions committed before are not reconsidered, except those
+    /// still waiting for their target.
+    pub async fn set_redaction_policy(&self, policy: Arc<dyn RedactionPolicy>) {
+        *self.redaction_policy.write().await = Some(policy);
+    }
+
+    /// Whether the content of a rotating-DAG event was redacted
+    pub fn is_redacted(&self, eid: &blake3::Hash) -> Result<bool> {
+        Ok(self.dag_redacted.contains_key(eid.as_bytes())?)
+    }
+
+    /// Get the redaction event applied to a rotating-DAG event, if any
+    pub async fn redaction_of(&self, eid: &blake3::Hash) -> Result<Option<Event>> {
+        match self.dag_redacted.get(eid.as_bytes())? {
+            Some(bytes) => Ok(Some(deserialize_async(&bytes).await?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Build the body to serve to peers for a stored event. Redacted
+    /// events are served as a placeholder holding their redaction.
+    /// Returns `None` if a redacted event lost its redaction record.
+    pub(crate) async fn servable_body(&self, event: Event) -> Result<Option<Event>> {
+        if !is_redacted_body(&event) {
+            return Ok(Some(event))
+        }
+
+        let Some(redaction_event) = self.redaction_of(&event.id()).await? else { return Ok(None) };
+        Ok(Some(redacted_placeholder(&event.header, &redaction_event)))
+    }
+
+    /// Whether the redaction held by a placeholder served by a peer is
+    /// authorized by our policy from the target header alone. If not,
+    /// the content has to be fetched from another peer.
+    pub(crate) async fn placeholder_authorized(&self, event: &Event) -> bool {
+        let Some(redaction_event) = placeholder_redaction(event) else { return false };
+        let Some(redaction) = Redaction::from_event(&redaction_event) else { return false };
+        let Some(policy) = self.redaction_policy.read().await.clone() else { return false };
+        policy.authorize_header(&event.header, &redaction_event, &redaction)
+    }
+
+    /// Whether an event received live may be stored and relayed. Only
+    /// redactions are checked: they must be authorized by our policy,
+    /// against the target content if we have it, or its header.
+    /// Redactions of unknown events are refused.
+    pub(crate) async fn redaction_admissible(&self, event: &Event) -> Result<bool> {
+        let Some(redaction) = Redaction::from_event(event) else { return Ok(true) };
+        let Some(policy) = self.redaction_policy.read().await.clone() else { return Ok(false) };
+
+        let store = self.dag_store.read().await;
+        for slot in store.dags.values() {
+            if let Some(bytes) = slot.main_tree.get(redaction.target.as_bytes())? {
+                let target: Event = deserialize_async(&bytes).await?;
+                if is_redacted_body(&target) || Redaction::from_event(&target).is_some() {
+                    return Ok(false)
+                }
+                return Ok(policy.authorize(&target, event, &redaction))
+            }
+
+            if let Some(bytes) = slot.header_tree.get(redaction.target.as_bytes())? {
+                let target: Header = deserialize_async(&bytes).await?;
+                return Ok(policy.authorize_header(&target, event, &redaction))
+            }
+        }
+
+        Ok(false)
+    }
+
+    /// Check a redacted placeholder served by a peer. Its header must
+    /// validate like any other, the redaction it holds must be in one
+    /// of our header DAGs, and our policy must authorize it from the
+    /// header, see [`Self::placeholder_authorized`].
+    async fn validate_placeholder(
+        &self,
+        store: &DagStore,
+        event: &Event,
+        header_dag: &StorageTree,
+        dag_ts: u64,
+    ) -> Result<bool> {
+        if !self.placeholder_authorized(event).await {
+            return Ok(false)
+        }
+
+        let Some(redaction_event) = placeholder_redaction(event) else { return Ok(false) };
+        let redaction_id = redaction_event.id();
+
+        let mut known = false;
+        for slot in store.dags.values() {
+            if slot.header_tree.contains_key(redaction_id.as_bytes())? {
+                known = true;
+                break
+            }
+        }
+        if !known {
+            return Ok(false)
+        }
+
+        event.header.validate(header_dag, &self.config, dag_ts, None).await
+    }
+
+    /// Apply a committed redaction. The target body is emptied and its
+    /// blob dropped if the policy authorizes it. Redactions whose target
+    /// we don't have yet are kept until it shows up.
+    async fn apply_redaction(
+        &self,
+        store: &DagStore,
+        policy: Option<&Arc<dyn RedactionPolicy>>,
+        redaction_event: &Event,
+        redaction: &Redaction,
+    ) -> Result<()> {
+        let target_id = redaction.target;
+
+        let mut found = None;
+        for slot in store.dags.values() {
+            if let Some(bytes) = slot.main_tree.get(target_id.as_bytes())? {
+                found = Some((slot, deserialize_async::<Event>(&bytes).await?));
+                break
+            }
+        }
+
+        let Some((slot, target)) = found else {
+            let mut key = target_id.as_bytes().to_vec();
+            key.extend_from_slice(redaction_event.id().as_bytes());
+            self.dag_pending_redactions.insert(key, serialize_async(redaction_event).await)?;
+            return Ok(())
+        };
+
+        // Redactions themselves can't be redacted, the placeholders
+        // of their targets depend on them.
+        if is_redacted_body(&target) || Redaction::from_event(&target).is_some() {
+            return Ok(())
+        }
+
+        let Some(policy) = policy else { return Ok(()) };
+        if !policy.authorize(&target, redaction_event, redaction) {
+            warn!(
+                target: "event_graph::redaction",
+                "[EVENTGRAPH] redaction {} of {target_id} was not authorized",
+                redaction_event.id(),
+            );
+            return Ok(())
+        }
+
+        let body = Event { header: target.header.clone(), content: vec![] };
+        slot.main_tree.insert(target_id.as_bytes(), serialize_async(&body).await)?;
+        self.dag_blobs.remove(target_id.as_bytes())?;
+        self.dag_redacted.insert(target_id.as_bytes(), serialize_async(redaction_event).await)?;
+        self.remove_pending_redactions(&target_id)?;
+        info!(
+            target: "event_graph::redaction",
+            "[EVENTGRAPH] redacted {target_id} with {}",
+            redaction_event.id(),
+        );
+        Ok(())
+    }
+
+    /// Find an authorized redaction waiting for `target`
+    fn authorized_pending_redaction(
+        &self,
+        policy: Option<&Arc<dyn RedactionPolicy>>,
+        target: &Event,
+    ) -> Result<Option<Event>> {
+        let Some(policy) = policy else { return Ok(None) };
+        if Redaction::from_event(target).is_some() {
+            return Ok(None)
+        }
+
+        for item in self.dag_pending_redactions.scan_prefix(target.id().as_bytes()) {
+            let (_, bytes) = item?;
+            let Ok(redaction_event) = darkfi_serial::deserialize::<Event>(&bytes) else { continue };
+            let Some(redaction) = Redaction::from_event(&redaction_event) else { continue };
+            if policy.authorize(target, &redaction_event, &redaction) {
+                return Ok(Some(redaction_event))
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Drop the redactions waiting for `target`
+    fn remove_pending_redactions(&self, target: &blake3::Hash) -> Result<()> {
+        for item in self.dag_pending_redactions.scan_prefix(target.as_bytes()).keys() {
+            self.dag_pending_redactions.remove(item?)?;
+        }
+        Ok(())
+    }
+
+    /// Make the rotating DAGs durable. Apps call this on shutdown
+    /// alongside flushing their sled database.
+    pub async fn flush_storage(&self) -> Result<()> {
+        self.storage.flush_async().await
+    }
+
     /// Persist the original RLN signal blob for a rotating-DAG event.
     ///
     /// Mirror of [`Self::static_blob_store`] but for rotating-DAG
@@ -2950,7 +3533,7 @@ impl EventGraph {
     /// (see `dag_blobs_prune`). Older-than-window events therefore
     /// don't accumulate blobs in this side-table.
     pub fn dag_blob_fetch(&self, eid: &blake3::Hash) -> Result<Option<Vec<u8>>> {
-        Ok(self.dag_blobs.get(eid.as_bytes())?.map(|ivec| ivec.to_vec()))
+        self.dag_blobs.get(eid.as_bytes())
     }
 
     /// Apply a static-DAG event (registration or slash) to the
@@ -3794,6 +4377,51 @@ async fn request_range(
     }
 }
 
+/// Run set reconciliation rounds with one peer, returning the IDs of the
+/// events it has and we don't, and the number of round trips it took.
+async fn request_recon(
+    peer: Arc<Channel>,
+    dag_name: String,
+    items: &[RangeCursor],
+    timeout: u64,
+) -> (Result<(HashSet<blake3::Hash>, usize)>, Arc<Channel>) {
+    let sub = match peer.subscribe_msg::<ReconRep>().await {
+        Ok(s) => s,
+        Err(e) => return (Err(e), peer),
+    };
+
+    let mut ranges = recon_initiate(items);
+    let mut need = HashSet::new();
+    let mut rounds = 0;
+    let result = loop {
+        if rounds >= MAX_RECON_ROUNDS {
+            break Err(Error::DagSyncFailed)
+        }
+        rounds += 1;
+
+        if let Err(e) = peer.send(&ReconReq { dag_name: dag_name.clone(), ranges }).await {
+            break Err(e)
+        }
+        let rep = match sub.receive_with_timeout(timeout).await {
+            Ok(r) => r,
+            Err(_) => break Err(Error::EventNotFound("recon timeout".into())),
+        };
+        let step = match recon_process(items, &rep.0, true) {
+            Ok(s) => s,
+            Err(e) => break Err(e),
+        };
+
+        need.extend(step.need.iter().copied());
+        if step.is_done() {
+            break Ok((need, rounds))
+        }
+        ranges = step.ranges;
+    };
+
+    sub.unsubscribe().await;
+    (result, peer)
+}
+
 async fn request_event(
     peer: Arc<Channel>,
     ids: Vec<blake3::Hash>,
@@ -23,6 +23,7 @@
 //! requests, and bidirectional range queries.
 
 use std::{
+    cmp::Ordering as CmpOrdering,
     collections::{BTreeMap, HashSet, VecDeque},
     str::FromStr,
     sync::{
@@ -41,7 +42,8 @@ use tracing::{debug, error, warn};
 
 use super::{
     event::Header,
-    filter_requested_event_rep,
+    filter_requested_event_rep, range_cursor_cmp,
+    redaction::is_redacted_body,
     rln::{self, prepare_slash_proof_request, sss_recover, RLNNode, RlnProver, SlashBlob},
     Event, EventGraphPtr, LayerUTips, NULL_ID, NULL_PARENTS,
 };
@@ -123,6 +125,17 @@ pub const MAX_HEADER_REP_HEADERS: usize = 4096;
 pub const MAX_TIP_REP_TIPS: usize = 1024;
 /// Maximum events served for one paginated range request.
 pub const MAX_RANGE_PAGE_SIZE: usize = 100;
+/// Maximum ranges in one `ReconReq` or `ReconRep`.
+pub const MAX_RECON_RANGES: usize = 256;
+/// Ranges holding at most this many events are sent as ID lists
+/// instead of fingerprints.
+pub const RECON_ID_LIST_THRESHOLD: usize = 16;
+/// Number of sub-ranges a mismatching range is split into.
+pub const RECON_BRANCH_FACTOR: usize = 16;
+/// Maximum reconciliation round trips with a single peer.
+pub const MAX_RECON_ROUNDS: usize = 64;
+/// Length of a range fingerprint in bytes.
+pub const RECON_FINGERPRINT_LEN: usize = 16;
 
 pub(crate) fn count_layer_tips(tips: &LayerUTips) -> usize {
     tips.values().map(HashSet::len).sum()
@@ -182,6 +195,200 @@ pub(crate) fn filter_parent_event_rep(
     Ok(resolved)
 }
 
+/// Fingerprint of a set of events: a hash of the sum of their IDs modulo
+/// 2^256 and of their count. Being a sum, it doesn't depend on the order
+/// the IDs are added in.
+pub fn recon_fingerprint(items: &[RangeCursor]) -> [u8; RECON_FINGERPRINT_LEN] {
+    let mut sum = [0u8; blake3::OUT_LEN];
+    for item in items {
+        let mut carry = 0u16;
+        for (acc, byte) in sum.iter_mut().zip(item.event_id.as_bytes()) {
+            let v = u16::from(*acc) + u16::from(*byte) + carry;
+            *acc = v as u8;
+            carry = v >> 8;
+        }
+    }
+
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(&sum);
+    hasher.update(&(items.len() as u64).to_le_bytes());
+    let mut fingerprint = [0u8; RECON_FINGERPRINT_LEN];
+    fingerprint.copy_from_slice(&hasher.finalize().as_bytes()[..RECON_FINGERPRINT_LEN]);
+    fingerprint
+}
+
+/// Describe `items`, the local events below `upper`, either as one ID
+/// list or as [`RECON_BRANCH_FACTOR`] fingerprinted sub-ranges.
+fn recon_split(items: &[RangeCursor], upper: RangeCursor, out: &mut Vec<ReconRange>) {
+    if items.len() <= RECON_ID_LIST_THRESHOLD {
+        let ids = items.iter().map(|c| c.event_id).collect();
+        out.push(ReconRange { upper, mode: ReconMode::IdList(ids) });
+        return
+    }
+
+    let per_bucket = items.len().div_ceil(RECON_BRANCH_FACTOR);
+    for (i, bucket) in items.chunks(per_bucket).enumerate() {
+        let bucket_upper = items.get((i + 1) * per_bucket).copied().unwrap_or(upper);
+        out.push(ReconRange {
+            upper: bucket_upper,
+            mode: ReconMode::Fingerprint(recon_fingerprint(bucket)),
+        });
+    }
+}
+
+/// Describe `items[lo..hi]`, the local events below `upper`, as ID lists
+/// of at most [`RECON_ID_LIST_THRESHOLD`] events. If they don't all fit
+/// in the message, the last range is a fingerprint of every event from
+/// there up to the newest cursor, and `false` is returned.
+fn recon_list(
+    items: &[RangeCursor],
+    lo: usize,
+    hi: usize,
+    upper: RangeCursor,
+    out: &mut Vec<ReconRange>,
+) -> bool {
+    let mut start = lo;
+    loop {
+        let end = (start + RECON_ID_LIST_THRESHOLD).min(hi);
+        let list_upper = if end == hi { upper } else { items[end] };
+        let ids = items[start..end].iter().map(|c| c.event_id).collect();
+        out.push(ReconRange { upper: list_upper, mode: ReconMode::IdList(ids) });
+        if end == hi {
+            return true
+        }
+
+        start = end;
+        if out.len() + RECON_BRANCH_FACTOR >= MAX_RECON_RANGES {
+            out.push(ReconRange {
+                upper: RangeCursor::newest(),
+                mode: ReconMode::Fingerprint(recon_fingerprint(&items[start..])),
+            });
+            return false
+        }
+    }
+}
+
+/// Append a skipped range, merging it with a preceding one.
+fn recon_push_skip(out: &mut Vec<ReconRange>, upper: RangeCursor) {
+    if let Some(last) = out.last_mut() {
+        if matches!(last.mode, ReconMode::Skip) {
+            last.upper = upper;
+            return
+        }
+    }
+    out.push(ReconRange { upper, mode: ReconMode::Skip });
+}
+
+/// First message of a reconciliation over `items`, sorted by
+/// `(timestamp, event_id)`.
+pub fn recon_initiate(items: &[RangeCursor]) -> Vec<ReconRange> {
+    let mut out = vec![];
+    recon_split(items, RangeCursor::newest(), &mut out);
+    out
+}
+
+/// Outcome of processing one reconciliation message.
+#[derive(Debug, Default)]
+pub struct ReconStep {
+    /// Ranges to send back to the peer.
+    pub ranges: Vec<ReconRange>,
+    /// Events the peer has and we don't.
+    pub need: Vec<blake3::Hash>,
+    /// Events we have and the peer doesn't.
+    pub have: Vec<blake3::Hash>,
+}
+
+impl ReconStep {
+    /// Whether both sides agree on every range, ending the exchange.
+    pub fn is_done(&self) -> bool {
+        self.ranges.iter().all(|r| matches!(r.mode, ReconMode::Skip))
+    }
+}
+
+/// Process the ranges received from a peer against our `items`, sorted
+/// by `(timestamp, event_id)`.
+///
+/// Matching fingerprints are skipped and mismatching ones are split
+/// further. An ID list tells us exactly what differs in its range: the
+/// initiator records it and skips the range, while the responder answers
+/// with its own IDs for the range (or splits it, if it holds too many).
+/// Ranges must be in increasing order and cover everything up to
+/// [`RangeCursor::newest`], otherwise the message is rejected. The reply
+/// never holds more than [`MAX_RECON_RANGES`] ranges.
+pub fn recon_process(
+    items: &[RangeCursor],
+    ranges: &[ReconRange],
+    initiator: bool,
+) -> Result<ReconStep> {
+    if ranges.is_empty() || ranges.len() > MAX_RECON_RANGES {
+        return Err(Error::DagSyncFailed)
+    }
+
+    let newest = RangeCursor::newest();
+    if range_cursor_cmp(ranges[ranges.len() - 1].upper, newest) != CmpOrdering::Equal {
+        return Err(Error::DagSyncFailed)
+    }
+
+    let mut step = ReconStep::default();
+    let mut lower = RangeCursor::oldest();
+    let mut lo = 0;
+    for (i, range) in ranges.iter().enumerate() {
+        if i > 0 && range_cursor_cmp(range.upper, lower) != CmpOrdering::Greater {
+            return Err(Error::DagSyncFailed)
+        }
+
+        // Without room left to split a range, the rest of the message is
+        // answered with a single fingerprint up to the newest cursor, which
+        // the peer splits in the next round.
+        if step.ranges.len() + RECON_BRANCH_FACTOR >= MAX_RECON_RANGES {
+            step.ranges.push(ReconRange {
+                upper: newest,
+                mode: ReconMode::Fingerprint(recon_fingerprint(&items[lo..])),
+            });
+            break
+        }
+
+        let hi = lo +
+            items[lo..]
+                .partition_point(|c| range_cursor_cmp(*c, range.upper) == CmpOrdering::Less);
+        let ours = &items[lo..hi];
+
+        match &range.mode {
+            ReconMode::Skip => recon_push_skip(&mut step.ranges, range.upper),
+
+            ReconMode::Fingerprint(fingerprint) => {
+                if recon_fingerprint(ours) == *fingerprint {
+                    recon_push_skip(&mut step.ranges, range.upper);
+                } else {
+                    recon_split(ours, range.upper, &mut step.ranges);
+                }
+            }
+
+            ReconMode::IdList(theirs) => {
+                if theirs.len() > RECON_ID_LIST_THRESHOLD {
+                    return Err(Error::DagSyncFailed)
+                }
+
+                let theirs: HashSet<blake3::Hash> = theirs.iter().copied().collect();
+                let local: HashSet<blake3::Hash> = ours.iter().map(|c| c.event_id).collect();
+                step.need.extend(theirs.difference(&local));
+                step.have.extend(ours.iter().map(|c| c.event_id).filter(|id| !theirs.contains(id)));
+
+                if initiator || theirs == local {
+                    recon_push_skip(&mut step.ranges, range.upper);
+                } else if !recon_list(items, lo, hi, range.upper, &mut step.ranges) {
+                    break
+                }
+            }
+        }
+
+        lower = range.upper;
+        lo = hi;
+    }
+
+    Ok(step)
+}
+
 struct MovingWindow {
     times: VecDeque<NanoTimestamp>,
     expiry_time: NanoTimestamp,
@@ -304,6 +511,51 @@ impl RangeCursor {
     pub fn oldest() -> Self {
         Self { timestamp: 0, event_id: blake3::Hash::from_bytes([0; 32]) }
     }
+
+    /// Cursor sorting before every event at `timestamp`.
+    pub fn timestamp_start(timestamp: u64) -> Self {
+        Self { timestamp, event_id: blake3::Hash::from_bytes([0; 32]) }
+    }
+
+    /// Cursor sorting after every event at `timestamp`.
+    pub fn timestamp_end(timestamp: u64) -> Self {
+        Self { timestamp, event_id: blake3::Hash::from_bytes([0xff; 32]) }
+    }
+
+    /// Cursor sorting right after this one, so that loading backwards
+    /// from it includes the event this cursor points to.
+    pub fn successor(self) -> Self {
+        let mut id = *self.event_id.as_bytes();
+        for byte in id.iter_mut().rev() {
+            let (next, overflow) = byte.overflowing_add(1);
+            *byte = next;
+            if !overflow {
+                return Self { timestamp: self.timestamp, event_id: blake3::Hash::from_bytes(id) }
+            }
+        }
+        Self::timestamp_start(self.timestamp.saturating_add(1))
+    }
+}
+
+impl PartialEq for RangeCursor {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == CmpOrdering::Equal
+    }
+}
+
+impl Eq for RangeCursor {}
+
+impl PartialOrd for RangeCursor {
+    fn partial_cmp(&self, other: &Self) -> Option<CmpOrdering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Cursors are ordered by `(timestamp, event_id)`, like the time index.
+impl Ord for RangeCursor {
+    fn cmp(&self, other: &Self) -> CmpOrdering {
+        range_cursor_cmp(*self, *other)
+    }
 }
 
 /// Bidirectional content pagination request.
@@ -337,6 +589,52 @@ impl_p2p_message!(RangeReq, "EventGraph::RangeReq", 0, 0, DEFAULT_METERING_CONFI
 pub struct RangeRep(pub Vec<Event>, pub Vec<Vec<u8>>, pub RangeCursor, pub bool);
 impl_p2p_message!(RangeRep, "EventGraph::RangeRep", 0, 0, DEFAULT_METERING_CONFIGURATION);
 
+/// How the sender describes one range of a reconciliation message.
+#[derive(Clone, Debug, SerialEncodable, SerialDecodable)]
+pub enum ReconMode {
+    /// Nothing left to reconcile in this range.
+    Skip,
+    /// Fingerprint of the sender's events in this range.
+    Fingerprint([u8; RECON_FINGERPRINT_LEN]),
+    /// Every event ID the sender has in this range.
+    IdList(Vec<blake3::Hash>),
+}
+
+/// One range of a reconciliation message.
+///
+/// Ranges are contiguous: the lower bound of a range is the exclusive
+/// upper bound of the previous one, or [`RangeCursor::oldest`] for the
+/// first. The last range ends at [`RangeCursor::newest`].
+#[derive(Clone, Debug, SerialEncodable, SerialDecodable)]
+pub struct ReconRange {
+    /// Exclusive upper bound of the range.
+    pub upper: RangeCursor,
+    /// Description of the sender's events in the range.
+    pub mode: ReconMode,
+}
+
+/// Set reconciliation request over the events of one rotating DAG,
+/// ordered by `(timestamp, event_id)` as in its time index.
+///
+/// The initiator and the responder exchange fingerprints of ranges,
+/// splitting the ones that differ, until the differing ranges are small
+/// enough to be sent as ID lists. Two peers converge in a number of
+/// round trips logarithmic in the DAG size, however far behind one of
+/// them is. The missing events are then fetched with [`EventReq`].
+#[derive(Clone, SerialEncodable, SerialDecodable)]
+pub struct ReconReq {
+    /// Which DAG to reconcile (genesis timestamp as string).
+    pub dag_name: String,
+    /// Ranges covering the whole DAG.
+    pub ranges: Vec<ReconRange>,
+}
+impl_p2p_message!(ReconReq, "EventGraph::ReconReq", 0, 0, DEFAULT_METERING_CONFIGURATION);
+
+/// Reply to a [`ReconReq`] with the responder's ranges.
+#[derive(Clone, SerialEncodable, SerialDecodable)]
+pub struct ReconRep(pub Vec<ReconRange>);
+impl_p2p_message!(ReconRep, "EventGraph::ReconRep", 0, 0, DEFAULT_METERING_CONFIGURATION);
+
 /// Per-connection protocol handler for the Event Graph.
 ///
 /// One instance is created for each peer connection. It subscribes
@@ -352,6 +650,7 @@ impl_p2p_message!(RangeRep, "EventGraph::RangeRep", 0, 0, DEFAULT_METERING_CONFI
 /// * `handle_tip_req` - serving our unreferenced tips.
 /// * `handle_range_req` - serving bidirectional paginated content
 ///   (the primary mechanism for lazy content fetching).
+/// * `handle_recon_req` - answering set reconciliation rounds.
 /// * `broadcast_rate_limiter` - rate-limiting outbound event
 ///   relay through a bounded channel with adaptive sleep.
 ///
@@ -373,6 +672,8 @@ pub struct ProtocolEventGraph {
     _tip_rep_sub: MessageSubscription<TipRep>,
     range_req_sub: MessageSubscription<RangeReq>,
     _range_rep_sub: MessageSubscription<RangeRep>,
+    recon_req_sub: MessageSubscription<ReconReq>,
+    _recon_rep_sub: MessageSubscription<ReconRep>,
     malicious_count: AtomicUsize,
     jobsman: ProtocolJobsManagerPtr,
     broadcaster_push: smol::channel::Sender<EventPut>,
@@ -389,6 +690,7 @@ impl ProtocolBase for ProtocolEventGraph {
         self.jobsman.clone().spawn(self.clone().handle_header_req(), ex.clone()).await;
         self.jobsman.clone().spawn(self.clone().handle_tip_req(), ex.clone()).await;
         self.jobsman.clone().spawn(self.clone().handle_range_req(), ex.clone()).await;
+        self.jobsman.clone().spawn(self.clone().handle_recon_req(), ex.clone()).await;
         self.jobsman.clone().spawn(self.clone().broadcast_rate_limiter(), ex.clone()).await;
         Ok(())
     }
@@ -413,6 +715,8 @@ impl ProtocolEventGraph {
         msg_subsystem.add_dispatch::<TipRep>().await;
         msg_subsystem.add_dispatch::<RangeReq>().await;
         msg_subsystem.add_dispatch::<RangeRep>().await;
+        msg_subsystem.add_dispatch::<ReconReq>().await;
+        msg_subsystem.add_dispatch::<ReconRep>().await;
 
         let (push, pull) = smol::channel::bounded(BROADCASTER_CAPACITY);
 
@@ -430,6 +734,8 @@ impl ProtocolEventGraph {
             _tip_rep_sub: channel.subscribe_msg().await?,
             range_req_sub: channel.subscribe_msg().await?,
             _range_rep_sub: channel.subscribe_msg().await?,
+            recon_req_sub: channel.subscribe_msg().await?,
+            _recon_rep_sub: channel.subscribe_msg().await?,
             malicious_count: AtomicUsize::new(0),
             jobsman: ProtocolJobsManager::new("ProtocolEventGraph", channel),
             broadcaster_push: push,
@@ -577,6 +883,27 @@ impl ProtocolEventGraph {
                 continue
             }
 
+            // Redactions our policy doesn't authorize are neither stored
+            // nor relayed. Nodes with another policy may still send them,
+            // so this is not a strike.
+            match self.event_graph.redaction_admissible(&event).await {
+                Ok(true) => {}
+                Ok(false) => {
+                    debug!(
+                        target: "event_graph::protocol",
+                        "[EVENTGRAPH] Dropping unauthorized redaction {eid}",
+                    );
+                    continue
+                }
+                Err(e) => {
+                    error!(
+                        target: "event_graph::protocol",
+                        "[EVENTGRAPH] Failed checking redaction {eid}: {e}",
+                    );
+                    continue
+                }
+            }
+
             if self.event_graph.rln_enabled() &&
                 event.header.parents != NULL_PARENTS &&
                 self.verify_rln_signal(&event, &blob).await
@@ -939,6 +1266,23 @@ impl ProtocolEventGraph {
                     continue
                 }
                 if let Some(ev) = self.event_graph.fetch_event_from_dags(id).await? {
+                    // Redacted events are served as placeholders, their
+                    // blob was dropped along with the content.
+                    if !in_static && is_redacted_body(&ev) {
+                        match self.event_graph.servable_body(ev).await? {
+                            Some(placeholder) => {
+                                events.push(placeholder);
+                                blobs.push(Vec::new());
+                            }
+                            None => warn!(
+                                target: "event_graph::handle_event_req",
+                                "[EVENTGRAPH] declining to serve redacted event {id} without \
+                                its redaction",
+                            ),
+                        }
+                        continue
+                    }
+
                     let blob = if !self.event_graph.rln_enabled() {
                         Vec::new()
                     } else if in_static {
@@ -1100,6 +1444,52 @@ impl ProtocolEventGraph {
         }
     }
 
+    /// Answer one round of a set reconciliation over a rotating DAG.
+    async fn handle_recon_req(self: Arc<Self>) -> Result<()> {
+        loop {
+            let req = match self.recon_req_sub.receive().await {
+                Ok(v) => v,
+                Err(_) => continue,
+            };
+            if !self.event_graph.is_synced() {
+                continue
+            }
+            let dag_ts = match u64::from_str(&req.dag_name) {
+                Ok(v) => v,
+                Err(_) => continue,
+            };
+
+            let items = {
+                let store = self.event_graph.dag_store.read().await;
+                match store.get_slot(&dag_ts) {
+                    Some(slot) => slot.time_index.cursors(),
+                    None => continue,
+                }
+            };
+
+            let step = match recon_process(&items, &req.ranges, false) {
+                Ok(v) => v,
+                Err(_) => {
+                    self.clone().strike().await?;
+                    continue
+                }
+            };
+
+            // The peer follows up with EventReq for the IDs we list,
+            // so they are registered like the headers of a HeaderRep.
+            {
+                let mut b = self.event_graph.broadcasted_ids.write().await;
+                for range in &step.ranges {
+                    if let ReconMode::IdList(ids) = &range.mode {
+                        b.extend(ids.iter().copied());
+                    }
+                }
+            }
+
+            self.channel.send(&ReconRep(step.ranges)).await?;
+        }
+    }
+
     async fn broadcast_rate_limiter(self: Arc<Self>) -> Result<()> {
         let mut rl = MovingWindow::new(RATELIMIT_EXPIRY_TIME);
         loop {
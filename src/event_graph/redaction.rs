@@ -0,0 +1,144 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2026 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Redaction events.
+//!
+//! A redaction is a regular rotating-DAG event whose content is
+//! [`REDACTION_PREFIX`] followed by a serialized [`Redaction`]. It
+//! references an earlier event, and carries opaque authorization
+//! bytes for the application to check, e.g. a signature over the
+//! target id made with a key found in the target content.
+//!
+//! RLN signals are unlinkable by design, so the event graph cannot
+//! tell whether two events come from the same identity. Instead,
+//! applications register a [`RedactionPolicy`] which gets the target
+//! and the redaction, and decides. Redactions received live which the
+//! policy refuses, or any redaction when there is no policy, are not
+//! stored nor relayed. Nodes syncing a DAG still take them from peers
+//! which accepted them, as later events may build on them, but never
+//! apply them.
+//!
+//! Applying a redaction empties the stored body of the target and
+//! drops its RLN blob. The header stays in the header DAG, and the
+//! empty body still counts as committed, so descendants keep
+//! validating. Peers syncing a redacted event receive a placeholder
+//! body holding the redaction event, see [`redacted_placeholder`].
+//! The content is gone, so they only accept the placeholder if the
+//! redaction is in their header DAG and their policy authorizes it
+//! from the target header alone, see
+//! [`RedactionPolicy::authorize_header`]. Otherwise they fetch the
+//! content from a peer which still has it, and apply the redaction
+//! themselves.
+//!
+//! Redactions are published to subscribers like other events, once
+//! applied, so applications can hide content they already displayed.
+
+use darkfi_serial::{async_trait, deserialize, serialize, SerialDecodable, SerialEncodable};
+
+use super::{Event, Header, NULL_PARENTS};
+
+/// Content prefix of redaction events
+pub const REDACTION_PREFIX: &[u8] = b"darkfi:event_graph:redaction:v1:";
+
+/// Content prefix of the placeholder bodies served for redacted events
+pub const REDACTED_PREFIX: &[u8] = b"darkfi:event_graph:redacted:v1:";
+
+/// Request to drop the content of an earlier event
+#[derive(Clone, Debug, PartialEq, Eq, SerialEncodable, SerialDecodable)]
+pub struct Redaction {
+    /// ID of the redacted event
+    pub target: blake3::Hash,
+    /// Application-defined authorization, checked by the
+    /// [`RedactionPolicy`] of each node
+    pub auth: Vec<u8>,
+}
+
+impl Redaction {
+    pub fn new(target: blake3::Hash, auth: Vec<u8>) -> Self {
+        Self { target, auth }
+    }
+
+    /// Event content carrying this redaction
+    pub fn to_content(&self) -> Vec<u8> {
+        let mut content = REDACTION_PREFIX.to_vec();
+        content.extend_from_slice(&serialize(self));
+        content
+    }
+
+    /// Decode the redaction carried by event content, if any
+    pub fn from_content(content: &[u8]) -> Option<Self> {
+        deserialize(content.strip_prefix(REDACTION_PREFIX)?).ok()
+    }
+
+    /// Decode the redaction carried by a rotating-DAG event, if any
+    pub fn from_event(event: &Event) -> Option<Self> {
+        if event.header.parents == NULL_PARENTS {
+            return None
+        }
+
+        Self::from_content(&event.content)
+    }
+}
+
+/// Application check deciding which redactions are applied
+pub trait RedactionPolicy: Send + Sync {
+    /// Whether `redaction`, the content of `redaction_event`, may drop
+    /// the content of `target`.
+    fn authorize(&self, target: &Event, redaction_event: &Event, redaction: &Redaction) -> bool;
+
+    /// Whether `redaction` may drop the content of the event with the
+    /// `target` header, without knowing that content. Used for the
+    /// placeholders served by peers, and for live redactions whose
+    /// target body we don't have. Policies whose authorization depends
+    /// on the target content keep the default, which refuses.
+    fn authorize_header(
+        &self,
+        _target: &Header,
+        _redaction_event: &Event,
+        _redaction: &Redaction,
+    ) -> bool {
+        false
+    }
+}
+
+/// Build the body served to peers for a redacted event: its header,
+/// with the redaction event as content.
+pub fn redacted_placeholder(header: &Header, redaction_event: &Event) -> Event {
+    let mut content = REDACTED_PREFIX.to_vec();
+    content.extend_from_slice(&serialize(redaction_event));
+    Event { header: header.clone(), content }
+}
+
+/// Decode a placeholder body built by [`redacted_placeholder`].
+/// Returns the redaction event if it is well-formed and redacts
+/// `event`. The caller must still check the redaction event belongs
+/// to the DAG.
+pub fn placeholder_redaction(event: &Event) -> Option<Event> {
+    let redaction_event: Event = deserialize(event.content.strip_prefix(REDACTED_PREFIX)?).ok()?;
+    if !redaction_event.content_matches_header() {
+        return None
+    }
+
+    let redaction = Redaction::from_event(&redaction_event)?;
+    (redaction.target == event.id()).then_some(redaction_event)
+}
+
+/// Whether a stored body is that of a redacted event
+pub fn is_redacted_body(event: &Event) -> bool {
+    event.content.is_empty() && event.header.parents != NULL_PARENTS
+}
@@ -0,0 +1,1049 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2026 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Storage backends for the rotating DAGs.
+//!
+//! Every rotating DAG is stored as two key-value trees, `headers_<ts>`
+//! for headers and `<ts>` for event bodies, and RLN blobs live in the
+//! `dag-blobs` tree. The trees are opened through a [`StorageBackend`]
+//! so applications can pick where they go:
+//!
+//! * [`SledStorage`] keeps them in the sled database, next to the
+//!   static DAG and the RLN state. This is the default.
+//! * [`LogStorage`] keeps one append-only log file per tree, and
+//!   rewrites it once most of it is overwritten or removed data.
+//!   Rotating DAGs are written once and dropped whole, which suits an
+//!   append-only layout better than a B-tree.
+//! * [`MemoryStorage`] keeps them in memory, for tests.
+//!
+//! The static DAG, RLN state and other side-tables always stay in
+//! sled. [`migrate_sled_dags`] moves the rotating DAGs of an existing
+//! sled database to another backend.
+
+use std::{
+    collections::BTreeMap,
+    fs::{self, File, OpenOptions},
+    io::{Read, Seek, SeekFrom, Write},
+    ops::{Bound, Deref},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+};
+
+use parking_lot::Mutex;
+use sled_overlay::sled;
+use tracing::{error, info, warn};
+
+use crate::{Error, Result};
+
+/// Key-value pairs yielded by [`StorageTree::iter`], in key order
+pub type StorageIter = Box<dyn Iterator<Item = Result<(Vec<u8>, Vec<u8>)>> + Send>;
+
+/// Writes applied atomically to a tree with [`StorageTree::apply_batch`]
+#[derive(Clone, Debug, Default)]
+pub struct StorageBatch {
+    ops: Vec<(Vec<u8>, Option<Vec<u8>>)>,
+}
+
+impl StorageBatch {
+    pub fn insert(&mut self, key: impl AsRef<[u8]>, value: impl AsRef<[u8]>) {
+        self.ops.push((key.as_ref().to_vec(), Some(value.as_ref().to_vec())));
+    }
+
+    pub fn remove(&mut self, key: impl AsRef<[u8]>) {
+        self.ops.push((key.as_ref().to_vec(), None));
+    }
+
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+}
+
+/// A tree implementation of a [`StorageBackend`]
+pub trait StorageTreeBackend: Send + Sync {
+    fn name(&self) -> &str;
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>>;
+    fn contains_key(&self, key: &[u8]) -> Result<bool> {
+        Ok(self.get(key)?.is_some())
+    }
+    fn len(&self) -> usize;
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// Iterate over the tree in key order
+    fn iter(&self) -> StorageIter;
+    /// Apply all writes of `batch`, or none of them
+    fn apply_batch(&self, batch: StorageBatch) -> Result<()>;
+}
+
+/// Persistence of the rotating-DAG trees
+pub trait StorageBackend: Send + Sync {
+    /// Open a tree, creating it if it doesn't exist
+    fn open_tree(&self, name: &str) -> Result<StorageTree>;
+    /// Remove a tree and its contents. Returns false if it didn't exist.
+    fn drop_tree(&self, name: &str) -> Result<bool>;
+    /// Names of the existing trees
+    fn tree_names(&self) -> Result<Vec<String>>;
+    /// Make all applied writes durable. Blocks until done, use
+    /// [`Storage::flush_async`] from async code.
+    fn flush(&self) -> Result<()>;
+}
+
+/// Handle to a tree opened from a [`StorageBackend`]
+#[derive(Clone)]
+pub struct StorageTree(Arc<dyn StorageTreeBackend>);
+
+impl StorageTree {
+    pub fn new(tree: Arc<dyn StorageTreeBackend>) -> Self {
+        Self(tree)
+    }
+
+    pub fn name(&self) -> String {
+        self.0.name().to_string()
+    }
+
+    pub fn get(&self, key: impl AsRef<[u8]>) -> Result<Option<Vec<u8>>> {
+        self.0.get(key.as_ref())
+    }
+
+    pub fn contains_key(&self, key: impl AsRef<[u8]>) -> Result<bool> {
+        self.0.contains_key(key.as_ref())
+    }
+
+    pub fn insert(&self, key: impl AsRef<[u8]>, value: impl AsRef<[u8]>) -> Result<()> {
+        let mut batch = StorageBatch::default();
+        batch.insert(key, value);
+        self.0.apply_batch(batch)
+    }
+
+    pub fn remove(&self, key: impl AsRef<[u8]>) -> Result<()> {
+        let mut batch = StorageBatch::default();
+        batch.remove(key);
+        self.0.apply_batch(batch)
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn iter(&self) -> StorageIter {
+        self.0.iter()
+    }
+
+    pub fn apply_batch(&self, batch: StorageBatch) -> Result<()> {
+        if batch.is_empty() {
+            return Ok(())
+        }
+        self.0.apply_batch(batch)
+    }
+
+    /// [`StorageTree::apply_batch`] run on the blocking thread pool,
+    /// for writes made from async code
+    pub async fn apply_batch_unblocked(&self, batch: StorageBatch) -> Result<()> {
+        if batch.is_empty() {
+            return Ok(())
+        }
+        let tree = self.0.clone();
+        smol::unblock(move || tree.apply_batch(batch)).await
+    }
+}
+
+impl From<sled::Tree> for StorageTree {
+    fn from(tree: sled::Tree) -> Self {
+        let name = String::from_utf8_lossy(&tree.name()).into_owned();
+        Self(Arc::new(SledTree { name, tree }))
+    }
+}
+
+/// Staged writes on top of a [`StorageTree`], read back before they
+/// are applied. Mirrors `SledTreeOverlay` for the storage backends.
+pub struct StorageOverlay<'a> {
+    tree: &'a StorageTree,
+    staged: BTreeMap<Vec<u8>, Option<Vec<u8>>>,
+}
+
+impl<'a> StorageOverlay<'a> {
+    pub fn new(tree: &'a StorageTree) -> Self {
+        Self { tree, staged: BTreeMap::new() }
+    }
+
+    pub fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        match self.staged.get(key) {
+            Some(value) => Ok(value.clone()),
+            None => self.tree.get(key),
+        }
+    }
+
+    pub fn insert(&mut self, key: &[u8], value: &[u8]) -> Result<()> {
+        self.staged.insert(key.to_vec(), Some(value.to_vec()));
+        Ok(())
+    }
+
+    pub fn remove(&mut self, key: &[u8]) -> Result<()> {
+        self.staged.insert(key.to_vec(), None);
+        Ok(())
+    }
+
+    /// Batch of the staged writes, `None` if there are none
+    pub fn aggregate(&self) -> Option<StorageBatch> {
+        if self.staged.is_empty() {
+            return None
+        }
+
+        let ops = self.staged.iter().map(|(k, v)| (k.clone(), v.clone())).collect();
+        Some(StorageBatch { ops })
+    }
+}
+
+/// Shared handle to the [`StorageBackend`] used by an event graph
+#[derive(Clone)]
+pub struct Storage(Arc<dyn StorageBackend>);
+
+impl Storage {
+    pub fn new(backend: Arc<dyn StorageBackend>) -> Self {
+        Self(backend)
+    }
+
+    /// Volatile storage, for tests
+    pub fn memory() -> Self {
+        Self(Arc::new(MemoryStorage::default()))
+    }
+
+    /// Make all applied writes durable on the blocking thread pool, so
+    /// that waiting for disk syncs and running compactions doesn't stall
+    /// the executor.
+    pub async fn flush_async(&self) -> Result<()> {
+        let backend = self.0.clone();
+        smol::unblock(move || backend.flush()).await
+    }
+}
+
+impl Deref for Storage {
+    type Target = dyn StorageBackend;
+
+    fn deref(&self) -> &Self::Target {
+        self.0.as_ref()
+    }
+}
+
+impl From<sled::Db> for Storage {
+    fn from(db: sled::Db) -> Self {
+        Self(Arc::new(SledStorage::new(db)))
+    }
+}
+
+/// Storage backends selectable from app configuration
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StorageKind {
+    Sled,
+    Log,
+    Memory,
+}
+
+impl StorageKind {
+    /// Open the backend. `sled_db` is used by [`StorageKind::Sled`],
+    /// `log_path` by [`StorageKind::Log`].
+    pub fn open(&self, sled_db: &sled::Db, log_path: &Path) -> Result<Storage> {
+        match self {
+            Self::Sled => Ok(Storage::from(sled_db.clone())),
+            Self::Log => Ok(Storage::new(Arc::new(LogStorage::open(log_path)?))),
+            Self::Memory => Ok(Storage::memory()),
+        }
+    }
+}
+
+impl FromStr for StorageKind {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "sled" => Ok(Self::Sled),
+            "log" => Ok(Self::Log),
+            "memory" => Ok(Self::Memory),
+            _ => Err(Error::Custom(format!("unknown event graph storage backend \"{s}\""))),
+        }
+    }
+}
+
+/// Rotating DAGs stored in the sled database
+pub struct SledStorage {
+    db: sled::Db,
+}
+
+impl SledStorage {
+    pub fn new(db: sled::Db) -> Self {
+        Self { db }
+    }
+}
+
+impl StorageBackend for SledStorage {
+    fn open_tree(&self, name: &str) -> Result<StorageTree> {
+        Ok(StorageTree::from(self.db.open_tree(name)?))
+    }
+
+    fn drop_tree(&self, name: &str) -> Result<bool> {
+        Ok(self.db.drop_tree(name)?)
+    }
+
+    fn tree_names(&self) -> Result<Vec<String>> {
+        Ok(self.db.tree_names().iter().map(|n| String::from_utf8_lossy(n).into_owned()).collect())
+    }
+
+    fn flush(&self) -> Result<()> {
+        self.db.flush()?;
+        Ok(())
+    }
+}
+
+struct SledTree {
+    name: String,
+    tree: sled::Tree,
+}
+
+impl StorageTreeBackend for SledTree {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        Ok(self.tree.get(key)?.map(|v| v.to_vec()))
+    }
+
+    fn contains_key(&self, key: &[u8]) -> Result<bool> {
+        Ok(self.tree.contains_key(key)?)
+    }
+
+    fn len(&self) -> usize {
+        self.tree.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    fn iter(&self) -> StorageIter {
+        Box::new(self.tree.iter().map(|item| {
+            let (k, v) = item?;
+            Ok((k.to_vec(), v.to_vec()))
+        }))
+    }
+
+    fn apply_batch(&self, batch: StorageBatch) -> Result<()> {
+        let mut sled_batch = sled::Batch::default();
+        for (key, value) in batch.ops {
+            match value {
+                Some(value) => sled_batch.insert(key, value),
+                None => sled_batch.remove(key),
+            }
+        }
+        self.tree.apply_batch(sled_batch)?;
+        Ok(())
+    }
+}
+
+/// Rotating DAGs kept in memory
+#[derive(Default)]
+pub struct MemoryStorage {
+    trees: Mutex<BTreeMap<String, Arc<MemoryTree>>>,
+}
+
+impl StorageBackend for MemoryStorage {
+    fn open_tree(&self, name: &str) -> Result<StorageTree> {
+        let mut trees = self.trees.lock();
+        let tree = trees
+            .entry(name.to_string())
+            .or_insert_with(|| {
+                Arc::new(MemoryTree { name: name.to_string(), map: Mutex::new(BTreeMap::new()) })
+            })
+            .clone();
+        Ok(StorageTree::new(tree))
+    }
+
+    fn drop_tree(&self, name: &str) -> Result<bool> {
+        Ok(self.trees.lock().remove(name).is_some())
+    }
+
+    fn tree_names(&self) -> Result<Vec<String>> {
+        Ok(self.trees.lock().keys().cloned().collect())
+    }
+
+    fn flush(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+struct MemoryTree {
+    name: String,
+    map: Mutex<BTreeMap<Vec<u8>, Vec<u8>>>,
+}
+
+impl StorageTreeBackend for MemoryTree {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        Ok(self.map.lock().get(key).cloned())
+    }
+
+    fn len(&self) -> usize {
+        self.map.lock().len()
+    }
+
+    fn iter(&self) -> StorageIter {
+        let items: Vec<Result<(Vec<u8>, Vec<u8>)>> =
+            self.map.lock().iter().map(|(k, v)| Ok((k.clone(), v.clone()))).collect();
+        Box::new(items.into_iter())
+    }
+
+    fn apply_batch(&self, batch: StorageBatch) -> Result<()> {
+        let mut map = self.map.lock();
+        for (key, value) in batch.ops {
+            match value {
+                Some(value) => map.insert(key, value),
+                None => map.remove(&key),
+            };
+        }
+        Ok(())
+    }
+}
+
+/// File extension of [`LogStorage`] tree logs
+const LOG_EXTENSION: &str = "log";
+
+/// File extension of logs being rewritten by a compaction
+const COMPACT_EXTENSION: &str = "compact";
+
+/// Frame header: payload length (u32 LE) and truncated payload hash
+const FRAME_HEADER_LEN: u64 = 4 + FRAME_CHECKSUM_LEN as u64;
+
+/// Bytes of the payload blake3 hash kept in a frame header
+const FRAME_CHECKSUM_LEN: usize = 8;
+
+/// Record tags within a frame payload
+const OP_INSERT: u8 = 0;
+const OP_REMOVE: u8 = 1;
+
+/// Logs smaller than this are never compacted
+const COMPACTION_MIN_BYTES: u64 = 4 * 1024 * 1024;
+
+/// Payload size of the frames written by a compaction
+const COMPACTION_FRAME_BYTES: usize = 1024 * 1024;
+
+/// Rotating DAGs stored as append-only logs, one file per tree.
+///
+/// Each batch is appended as a single frame, a length and checksum
+/// followed by its insert and remove records, so it is applied whole
+/// or not at all: a failed append is cut off right away, and a torn
+/// frame left by a crash when the tree is opened again. The latest
+/// value of every key is indexed in memory. A log is rewritten with
+/// only its live records once it is more than twice their size, on the
+/// blocking thread pool while appends go on.
+pub struct LogStorage {
+    path: PathBuf,
+    trees: Mutex<BTreeMap<String, Arc<LogTree>>>,
+}
+
+impl LogStorage {
+    /// Open a log directory, creating it if needed
+    pub fn open(path: &Path) -> Result<Self> {
+        fs::create_dir_all(path)?;
+
+        // Leftovers of an interrupted compaction. The original log is
+        // only replaced once the rewrite is complete.
+        for entry in fs::read_dir(path)? {
+            let entry_path = entry?.path();
+            if entry_path.extension().is_some_and(|ext| ext == COMPACT_EXTENSION) {
+                fs::remove_file(&entry_path)?;
+            }
+        }
+
+        Ok(Self { path: path.to_path_buf(), trees: Mutex::new(BTreeMap::new()) })
+    }
+
+    fn tree_path(&self, name: &str) -> PathBuf {
+        let file_name: String = name.bytes().map(|b| format!("{b:02x}")).collect();
+        self.path.join(file_name).with_extension(LOG_EXTENSION)
+    }
+}
+
+impl StorageBackend for LogStorage {
+    fn open_tree(&self, name: &str) -> Result<StorageTree> {
+        let mut trees = self.trees.lock();
+        if let Some(tree) = trees.get(name) {
+            return Ok(StorageTree::new(tree.clone()))
+        }
+
+        let tree = Arc::new(LogTree::open(name, self.tree_path(name))?);
+        trees.insert(name.to_string(), tree.clone());
+        Ok(StorageTree::new(tree))
+    }
+
+    fn drop_tree(&self, name: &str) -> Result<bool> {
+        // A running compaction must not recreate the log
+        if let Some(tree) = self.trees.lock().remove(name) {
+            tree.log.lock().dropped = true;
+        }
+        let path = self.tree_path(name);
+        if !path.exists() {
+            return Ok(false)
+        }
+
+        fs::remove_file(path)?;
+        Ok(true)
+    }
+
+    fn tree_names(&self) -> Result<Vec<String>> {
+        let mut names = vec![];
+        for entry in fs::read_dir(&self.path)? {
+            let path = entry?.path();
+            if !path.extension().is_some_and(|ext| ext == LOG_EXTENSION) {
+                continue
+            }
+            let Some(stem) = path.file_stem().and_then(|s| s.to_str()) else { continue };
+            if let Some(name) = decode_tree_name(stem) {
+                names.push(name);
+            }
+        }
+        names.sort();
+        Ok(names)
+    }
+
+    fn flush(&self) -> Result<()> {
+        let trees: Vec<_> = self.trees.lock().values().cloned().collect();
+        for tree in trees {
+            let compaction = tree.compaction.lock().take();
+            // Flushing blocks, async callers go through `Storage::flush_async`
+            if let Some(compaction) = compaction {
+                smol::block_on(compaction);
+            }
+            tree.log.lock().file.sync_data()?;
+        }
+        Ok(())
+    }
+}
+
+fn decode_tree_name(stem: &str) -> Option<String> {
+    if stem.len() % 2 != 0 {
+        return None
+    }
+
+    let bytes: Option<Vec<u8>> = (0..stem.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(stem.get(i..i + 2)?, 16).ok())
+        .collect();
+    String::from_utf8(bytes?).ok()
+}
+
+/// Position of a value within a log file
+#[derive(Clone, Copy)]
+struct ValueRef {
+    offset: u64,
+    len: u32,
+}
+
+struct LogTree {
+    name: String,
+    path: PathBuf,
+    log: Arc<Mutex<LogFile>>,
+    /// Running compaction, if any
+    compaction: Mutex<Option<smol::Task<()>>>,
+}
+
+struct LogFile {
+    file: File,
+    index: BTreeMap<Vec<u8>, ValueRef>,
+    /// Size of the log file
+    size: u64,
+    /// Size the live records would take in a compacted log
+    live: u64,
+    /// Bumped when a compaction replaces the log file, which moves
+    /// every value
+    generation: u64,
+    /// Set once the tree is dropped
+    dropped: bool,
+}
+
+/// Size of an insert record in a frame payload
+fn record_len(key_len: usize, value_len: usize) -> u64 {
+    (1 + 4 + key_len + 4 + value_len) as u64
+}
+
+fn frame_checksum(payload: &[u8]) -> [u8; FRAME_CHECKSUM_LEN] {
+    let mut checksum = [0u8; FRAME_CHECKSUM_LEN];
+    checksum.copy_from_slice(&blake3::hash(payload).as_bytes()[..FRAME_CHECKSUM_LEN]);
+    checksum
+}
+
+fn encode_frame(payload: &[u8]) -> Result<Vec<u8>> {
+    let len = u32::try_from(payload.len())
+        .map_err(|_| Error::Custom("event graph storage batch too large".into()))?;
+    let mut frame = Vec::with_capacity(FRAME_HEADER_LEN as usize + payload.len());
+    frame.extend_from_slice(&len.to_le_bytes());
+    frame.extend_from_slice(&frame_checksum(payload));
+    frame.extend_from_slice(payload);
+    Ok(frame)
+}
+
+fn encode_len(out: &mut Vec<u8>, len: usize) -> Result<()> {
+    let len = u32::try_from(len)
+        .map_err(|_| Error::Custom("event graph storage record too large".into()))?;
+    out.extend_from_slice(&len.to_le_bytes());
+    Ok(())
+}
+
+/// Reader over the records of a frame payload
+struct Records<'a> {
+    payload: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Records<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        let bytes = self.payload.get(self.pos..self.pos.checked_add(len)?)?;
+        self.pos += len;
+        Some(bytes)
+    }
+
+    fn take_len(&mut self) -> Option<usize> {
+        Some(u32::from_le_bytes(self.take(4)?.try_into().ok()?) as usize)
+    }
+
+    /// Next record as (key, value position within the payload), or an
+    /// error if the payload is malformed
+    #[allow(clippy::type_complexity)]
+    fn next_record(&mut self) -> Option<Result<(&'a [u8], Option<(usize, usize)>)>> {
+        if self.pos == self.payload.len() {
+            return None
+        }
+
+        let malformed = || Error::Custom("malformed event graph storage record".into());
+        let Some(tag) = self.take(1) else { return Some(Err(malformed())) };
+        let Some(key) = self.take_len().and_then(|len| self.take(len)) else {
+            return Some(Err(malformed()))
+        };
+
+        match tag[0] {
+            OP_INSERT => {
+                let Some(len) = self.take_len() else { return Some(Err(malformed())) };
+                let start = self.pos;
+                if self.take(len).is_none() {
+                    return Some(Err(malformed()))
+                }
+                Some(Ok((key, Some((start, len)))))
+            }
+            OP_REMOVE => Some(Ok((key, None))),
+            _ => Some(Err(malformed())),
+        }
+    }
+}
+
+impl LogTree {
+    fn open(name: &str, path: PathBuf) -> Result<Self> {
+        let mut file = OpenOptions::new().read(true).append(true).create(true).open(&path)?;
+        let mut data = vec![];
+        file.read_to_end(&mut data)?;
+
+        let mut index: BTreeMap<Vec<u8>, ValueRef> = BTreeMap::new();
+        let mut live = 0u64;
+        let mut pos = 0usize;
+        while let Some(header) = data.get(pos..pos + FRAME_HEADER_LEN as usize) {
+            let len = u32::from_le_bytes(header[..4].try_into().unwrap()) as usize;
+            let payload_start = pos + FRAME_HEADER_LEN as usize;
+            let Some(payload) = data.get(payload_start..payload_start + len) else { break };
+            if header[4..] != frame_checksum(payload) {
+                break
+            }
+
+            // Validate the whole frame before applying any of it
+            let mut records = Records { payload, pos: 0 };
+            let mut ops = vec![];
+            while let Some(record) = records.next_record() {
+                ops.push(record?);
+            }
+
+            for (key, value) in ops {
+                if let Some(old) = index.remove(key) {
+                    live -= record_len(key.len(), old.len as usize);
+                }
+                if let Some((start, len)) = value {
+                    let offset = (payload_start + start) as u64;
+                    index.insert(key.to_vec(), ValueRef { offset, len: len as u32 });
+                    live += record_len(key.len(), len);
+                }
+            }
+
+            pos = payload_start + len;
+        }
+
+        // Cut off a torn frame left by an interrupted write
+        if pos < data.len() {
+            warn!(
+                target: "event_graph::storage",
+                "[EVENTGRAPH] truncating {} trailing bytes of {path:?}",
+                data.len() - pos,
+            );
+            file.set_len(pos as u64)?;
+            file.sync_data()?;
+        }
+
+        let log = LogFile { file, index, size: pos as u64, live, generation: 0, dropped: false };
+        Ok(Self {
+            name: name.to_string(),
+            path,
+            log: Arc::new(Mutex::new(log)),
+            compaction: Mutex::new(None),
+        })
+    }
+
+    /// Start a compaction on the blocking thread pool, unless one is
+    /// already running
+    fn start_compaction(&self) {
+        let mut compaction = self.compaction.lock();
+        if compaction.as_ref().is_some_and(|task| !task.is_finished()) {
+            return
+        }
+
+        let (name, path, log) = (self.name.clone(), self.path.clone(), self.log.clone());
+        *compaction = Some(smol::unblock(move || {
+            if let Err(e) = compact(&name, &path, &log) {
+                error!(
+                    target: "event_graph::storage",
+                    "[EVENTGRAPH] compaction of {name} failed: {e}",
+                );
+                let _ = fs::remove_file(path.with_extension(COMPACT_EXTENSION));
+            }
+        }));
+    }
+}
+
+/// Rewrite a log with only its live records.
+///
+/// The records live when the compaction starts are copied without
+/// holding the log lock. The frames appended meanwhile are then copied
+/// as they are, and the log replaced, under the lock.
+fn compact(name: &str, path: &Path, log: &Mutex<LogFile>) -> Result<()> {
+    let (entries, snapshot_size) = {
+        let log = log.lock();
+        let entries: Vec<_> = log.index.iter().map(|(k, v)| (k.clone(), *v)).collect();
+        (entries, log.size)
+    };
+
+    // The log is only replaced by a compaction, so this handle reads
+    // the snapshot values even if the tree is dropped meanwhile.
+    let mut file = File::open(path)?;
+    let compact_path = path.with_extension(COMPACT_EXTENSION);
+    let mut out = File::create(&compact_path)?;
+    let mut index = BTreeMap::new();
+    let mut size = 0u64;
+    let mut payload = vec![];
+    let mut pending = vec![];
+
+    for (n, (key, value_ref)) in entries.iter().enumerate() {
+        let value = read_value(&mut file, *value_ref)?;
+        payload.push(OP_INSERT);
+        encode_len(&mut payload, key.len())?;
+        payload.extend_from_slice(key);
+        encode_len(&mut payload, value.len())?;
+        pending.push((key.clone(), payload.len() as u64, value.len() as u32));
+        payload.extend_from_slice(&value);
+
+        if payload.len() >= COMPACTION_FRAME_BYTES || n + 1 == entries.len() {
+            out.write_all(&encode_frame(&payload)?)?;
+            for (key, start, len) in pending.drain(..) {
+                index.insert(key, ValueRef { offset: size + FRAME_HEADER_LEN + start, len });
+            }
+            size += FRAME_HEADER_LEN + payload.len() as u64;
+            payload.clear();
+        }
+    }
+
+    let mut log = log.lock();
+    if log.dropped {
+        drop(out);
+        fs::remove_file(&compact_path)?;
+        return Ok(())
+    }
+
+    // Appended frames are whole, failed appends are cut off, so the
+    // tail is copied as is and its values keep their frame offsets.
+    let mut tail = vec![0u8; (log.size - snapshot_size) as usize];
+    file.seek(SeekFrom::Start(snapshot_size))?;
+    file.read_exact(&mut tail)?;
+    out.write_all(&tail)?;
+    out.sync_all()?;
+    drop(out);
+
+    // Values still at their snapshot position were copied, the others
+    // were appended since.
+    for (key, value_ref) in log.index.iter_mut() {
+        if value_ref.offset >= snapshot_size {
+            value_ref.offset = value_ref.offset - snapshot_size + size;
+        } else if let Some(moved) = index.get(key) {
+            *value_ref = *moved;
+        }
+    }
+
+    fs::rename(&compact_path, path)?;
+    let before = log.size;
+    log.file = OpenOptions::new().read(true).append(true).open(path)?;
+    log.size = size + tail.len() as u64;
+    log.generation += 1;
+    info!(
+        target: "event_graph::storage",
+        "[EVENTGRAPH] compacted {name} from {before} to {} bytes", log.size,
+    );
+    Ok(())
+}
+
+fn read_value(file: &mut File, value_ref: ValueRef) -> Result<Vec<u8>> {
+    let mut value = vec![0u8; value_ref.len as usize];
+    file.seek(SeekFrom::Start(value_ref.offset))?;
+    file.read_exact(&mut value)?;
+    Ok(value)
+}
+
+impl StorageTreeBackend for LogTree {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        let mut log = self.log.lock();
+        let Some(value_ref) = log.index.get(key).copied() else { return Ok(None) };
+        Ok(Some(read_value(&mut log.file, value_ref)?))
+    }
+
+    fn contains_key(&self, key: &[u8]) -> Result<bool> {
+        Ok(self.log.lock().index.contains_key(key))
+    }
+
+    fn len(&self) -> usize {
+        self.log.lock().index.len()
+    }
+
+    fn iter(&self) -> StorageIter {
+        Box::new(LogIter {
+            path: self.path.clone(),
+            log: self.log.clone(),
+            file: None,
+            last: None,
+            done: false,
+        })
+    }
+
+    fn apply_batch(&self, batch: StorageBatch) -> Result<()> {
+        let mut payload = vec![];
+        let mut values = Vec::with_capacity(batch.ops.len());
+        for (key, value) in &batch.ops {
+            match value {
+                Some(value) => {
+                    payload.push(OP_INSERT);
+                    encode_len(&mut payload, key.len())?;
+                    payload.extend_from_slice(key);
+                    encode_len(&mut payload, value.len())?;
+                    values.push(Some((payload.len() as u64, value.len() as u32)));
+                    payload.extend_from_slice(value);
+                }
+                None => {
+                    payload.push(OP_REMOVE);
+                    encode_len(&mut payload, key.len())?;
+                    payload.extend_from_slice(key);
+                    values.push(None);
+                }
+            }
+        }
+        let frame = encode_frame(&payload)?;
+
+        let mut log = self.log.lock();
+        if let Err(e) = log.file.write_all(&frame) {
+            // Later frames would land after the torn bytes, and be cut
+            // off with them when the log is opened again
+            let size = log.size;
+            if let Err(e) = log.file.set_len(size) {
+                error!(
+                    target: "event_graph::storage",
+                    "[EVENTGRAPH] failed truncating {:?} after a failed write: {e}", self.path,
+                );
+            }
+            return Err(e.into())
+        }
+        let payload_offset = log.size + FRAME_HEADER_LEN;
+        log.size += frame.len() as u64;
+
+        for ((key, _), value) in batch.ops.into_iter().zip(values) {
+            if let Some(old) = log.index.remove(&key) {
+                log.live -= record_len(key.len(), old.len as usize);
+            }
+            if let Some((start, len)) = value {
+                log.live += record_len(key.len(), len as usize);
+                log.index.insert(key, ValueRef { offset: payload_offset + start, len });
+            }
+        }
+
+        if log.size > COMPACTION_MIN_BYTES && log.size > 2 * log.live {
+            drop(log);
+            self.start_compaction();
+        }
+
+        Ok(())
+    }
+}
+
+/// Streaming iterator over a [`LogTree`]. Each step looks up the key
+/// following the last one, so the log lock is not held while reading,
+/// and writes made meanwhile may or may not be seen, like sled.
+struct LogIter {
+    path: PathBuf,
+    log: Arc<Mutex<LogFile>>,
+    /// Read handle, and the log generation it was opened at
+    file: Option<(u64, File)>,
+    last: Option<Vec<u8>>,
+    done: bool,
+}
+
+impl LogIter {
+    fn next_entry(&mut self) -> Result<Option<(Vec<u8>, Vec<u8>)>> {
+        let (key, value_ref) = {
+            let log = self.log.lock();
+            let lower = match &self.last {
+                Some(last) => Bound::Excluded(last.as_slice()),
+                None => Bound::Unbounded,
+            };
+            let Some((key, value_ref)) =
+                log.index.range::<[u8], _>((lower, Bound::Unbounded)).next()
+            else {
+                return Ok(None)
+            };
+
+            // Reopened under the lock, so the handle matches the offsets
+            if !matches!(&self.file, Some((generation, _)) if *generation == log.generation) {
+                self.file = Some((log.generation, File::open(&self.path)?));
+            }
+            (key.clone(), *value_ref)
+        };
+
+        let value = read_value(&mut self.file.as_mut().unwrap().1, value_ref)?;
+        self.last = Some(key.clone());
+        Ok(Some((key, value)))
+    }
+}
+
+impl Iterator for LogIter {
+    type Item = Result<(Vec<u8>, Vec<u8>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None
+        }
+
+        let item = self.next_entry().transpose();
+        self.done = !matches!(item, Some(Ok(_)));
+        item
+    }
+}
+
+/// Counters reported by [`migrate_sled_dags`]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MigrationStats {
+    /// Trees moved
+    pub trees: usize,
+    /// Key-value pairs copied
+    pub entries: usize,
+}
+
+/// Entries copied per batch by [`migrate_sled_dags`]
+const MIGRATION_BATCH_LEN: usize = 10_000;
+
+/// Whether a sled tree holds rotating-DAG data handled by a [`StorageBackend`]
+fn is_dag_tree(name: &str) -> bool {
+    name == "dag-blobs" ||
+        name.parse::<u64>().is_ok() ||
+        name.strip_prefix("headers_").is_some_and(|ts| ts.parse::<u64>().is_ok())
+}
+
+/// Move the rotating DAGs and their RLN blobs from a sled database to
+/// another backend. Every tree is copied and flushed before the sled
+/// trees are dropped, so an interrupted migration can be run again.
+/// Fails without changes if a destination tree holds keys which are
+/// not in sled.
+pub fn migrate_sled_dags(src: &sled::Db, dst: &Storage) -> Result<MigrationStats> {
+    let names: Vec<String> = src
+        .tree_names()
+        .iter()
+        .map(|n| String::from_utf8_lossy(n).into_owned())
+        .filter(|n| is_dag_tree(n))
+        .collect();
+
+    for name in &names {
+        let src_tree = src.open_tree(name)?;
+        if src_tree.is_empty() {
+            continue
+        }
+        for item in dst.open_tree(name)?.iter() {
+            let (key, _) = item?;
+            if !src_tree.contains_key(&key)? {
+                return Err(Error::Custom(format!(
+                    "event graph storage tree {name} already holds other data"
+                )))
+            }
+        }
+    }
+
+    let mut stats = MigrationStats::default();
+    for name in &names {
+        let src_tree = src.open_tree(name)?;
+        if src_tree.is_empty() {
+            continue
+        }
+        let dst_tree = dst.open_tree(name)?;
+
+        let mut batch = StorageBatch::default();
+        for item in src_tree.iter() {
+            let (key, value) = item?;
+            batch.insert(key, value);
+            stats.entries += 1;
+            if batch.len() >= MIGRATION_BATCH_LEN {
+                dst_tree.apply_batch(std::mem::take(&mut batch))?;
+            }
+        }
+        dst_tree.apply_batch(batch)?;
+        stats.trees += 1;
+    }
+    dst.flush()?;
+
+    for name in &names {
+        src.drop_tree(name)?;
+    }
+    src.flush()?;
+
+    info!(
+        target: "event_graph::storage",
+        "[EVENTGRAPH] migrated {} trees with {} entries out of sled",
+        stats.trees, stats.entries,
+    );
+    Ok(stats)
+}
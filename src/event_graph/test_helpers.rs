@@ -31,7 +31,10 @@ use url::Url;
 
 use crate::{
     error::Result,
-    event_graph::{proto::ProtocolEventGraph, Event, EventGraph, EventGraphConfig, EventGraphPtr},
+    event_graph::{
+        proto::ProtocolEventGraph, storage::Storage, Event, EventGraph, EventGraphConfig,
+        EventGraphPtr,
+    },
     net::{session::SESSION_DEFAULT, settings::NetworkProfile, P2p, Settings},
 };
 
@@ -141,6 +144,28 @@ pub async fn make_eg_with_config_and_db(
     }
 }
 
+/// Construct an RLN-disabled [`EventGraph`] keeping its rotating DAGs in `storage`.
+pub async fn make_eg_with_storage(
+    config: EventGraphConfig,
+    sled_db: sled::Db,
+    storage: Storage,
+) -> EventGraphPtr {
+    let ex = Arc::new(Executor::new());
+    let p2p = P2p::new(Settings::default(), ex.clone()).await.unwrap();
+    EventGraph::new_with_storage(
+        p2p,
+        sled_db.clone(),
+        sled_db,
+        storage,
+        "/tmp".into(),
+        false,
+        config,
+        ex,
+    )
+    .await
+    .unwrap()
+}
+
 /// Number of nodes a `make_network` call brings up.
 pub const N_NODES: usize = 5;
 
@@ -155,6 +180,22 @@ fn alloc_port_base() -> u16 {
     NEXT.fetch_add(N_NODES as u16, Ordering::SeqCst)
 }
 
+/// Transport the test network runs on. With the in-memory transport
+/// available, nodes don't need real sockets.
+#[cfg(feature = "p2p-memory")]
+const TEST_SCHEME: &str = "mem";
+#[cfg(not(feature = "p2p-memory"))]
+const TEST_SCHEME: &str = "tcp";
+
+/// Address of the test node listening on `port`.
+fn node_url(port: u16) -> Url {
+    #[cfg(feature = "p2p-memory")]
+    let url = format!("mem://node{port}.eventgraph:{port}");
+    #[cfg(not(feature = "p2p-memory"))]
+    let url = format!("tcp://127.0.0.1:{port}");
+    Url::parse(&url).unwrap()
+}
+
 /// Spawn one `EventGraph` node on a local port, peered with the
 /// given `peer_offsets` (relative to `port_base`).
 async fn spawn_node(
@@ -165,15 +206,11 @@ async fn spawn_node(
 ) -> EventGraphPtr {
     let mut profiles = HashMap::new();
     profiles.insert(
-        "tcp".to_string(),
+        TEST_SCHEME.to_string(),
         NetworkProfile { outbound_connect_timeout: 2, ..Default::default() },
     );
-    let inbound =
-        vec![Url::parse(&format!("tcp://127.0.0.1:{}", port_base + port_offset as u16)).unwrap()];
-    let peers: Vec<_> = peer_offsets
-        .iter()
-        .map(|p| Url::parse(&format!("tcp://127.0.0.1:{}", port_base + *p as u16)).unwrap())
-        .collect();
+    let inbound = vec![node_url(port_base + port_offset as u16)];
+    let peers: Vec<_> = peer_offsets.iter().map(|p| node_url(port_base + *p as u16)).collect();
 
     let settings = Settings {
         localnet: true,
@@ -181,7 +218,7 @@ async fn spawn_node(
         outbound_connections: 0,
         inbound_connections: usize::MAX,
         peers,
-        active_profiles: vec!["tcp".to_string()],
+        active_profiles: vec![TEST_SCHEME.to_string()],
         profiles,
         ..Default::default()
     };
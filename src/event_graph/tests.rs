@@ -18,12 +18,14 @@
 
 use std::{
     collections::{BTreeMap, HashMap, HashSet},
+    future::Future,
     slice,
     sync::Arc,
     time::{Duration, UNIX_EPOCH},
 };
 
 use darkfi_serial::{deserialize_async, serialize_async};
+use futures::FutureExt;
 use sled_overlay::sled;
 use smol::Executor;
 
@@ -34,18 +36,26 @@ use crate::{
         event::Header,
         filter_requested_event_rep, merge_static_sync_event_rep,
         proto::{
-            cap_layer_tips, count_layer_tips, filter_parent_event_rep, EventPut, RangeCursor,
-            RangeRep, RangeReq, SyncDirection, MAX_HEADER_REP_HEADERS, MAX_RANGE_PAGE_SIZE,
+            cap_layer_tips, count_layer_tips, filter_parent_event_rep, recon_initiate,
+            recon_process, EventPut, RangeCursor, RangeRep, RangeReq, ReconMode, ReconRange,
+            SyncDirection, MAX_HEADER_REP_HEADERS, MAX_RANGE_PAGE_SIZE, MAX_RECON_RANGES,
+            RECON_ID_LIST_THRESHOLD,
         },
+        redaction::{placeholder_redaction, redacted_placeholder, Redaction, RedactionPolicy},
         rln::epoch_of,
+        storage::{
+            migrate_sled_dags, LogStorage, MigrationStats, Storage, StorageBatch, StorageTree,
+        },
         test_helpers::{
             archive_config, bounded_dag_store_config, init_logger, make_eg, make_eg_with_config,
-            make_network, run_multi_node_test, shutdown_network, test_config, TestIdentity,
+            make_eg_with_config_and_db, make_eg_with_storage, make_network, run_multi_node_test,
+            shutdown_network, test_config, TestIdentity,
         },
         util::{millis_until_next_rotation, next_hour_timestamp, next_rotation_timestamp},
         DagStore, Event, EventGraphConfig, EventGraphPtr, LayerUTips, TimeIndex, NULL_ID,
         NULL_PARENTS, N_EVENT_PARENTS,
     },
+    net::dnet::DnetEvent,
     system::{sleep, timeout::timeout},
 };
 
@@ -452,6 +462,143 @@ fn evgr_dag_store_rejects_corrupt_event_tree_on_open() {
     })
 }
 
+fn temp_log_dir(name: &str) -> std::path::PathBuf {
+    std::env::temp_dir().join(format!("darkfi_evgr_{name}_{}", rand::random::<u32>()))
+}
+
+#[test]
+fn evgr_storage_backends_behave_alike() {
+    let log_dir = temp_log_dir("backends");
+    let backends = [
+        Storage::from(sled::Config::new().temporary(true).open().unwrap()),
+        Storage::memory(),
+        Storage::new(Arc::new(LogStorage::open(&log_dir).unwrap())),
+    ];
+
+    for storage in backends {
+        let tree = storage.open_tree("headers_42").unwrap();
+        assert!(tree.is_empty());
+        tree.insert(b"b", b"2").unwrap();
+        tree.insert(b"a", b"1").unwrap();
+        tree.insert(b"b", b"3").unwrap();
+
+        let mut batch = StorageBatch::default();
+        batch.insert(b"c", b"4");
+        batch.remove(b"a");
+        tree.apply_batch(batch).unwrap();
+
+        assert_eq!(tree.get(b"a").unwrap(), None);
+        assert_eq!(tree.get(b"b").unwrap(), Some(b"3".to_vec()));
+        assert!(tree.contains_key(b"c").unwrap());
+        let items: Vec<_> = tree.iter().map(|item| item.unwrap()).collect();
+        assert_eq!(items, vec![(b"b".to_vec(), b"3".to_vec()), (b"c".to_vec(), b"4".to_vec())]);
+
+        // Handles to the same tree share its contents
+        assert_eq!(storage.open_tree("headers_42").unwrap().len(), 2);
+        assert!(storage.tree_names().unwrap().contains(&"headers_42".to_string()));
+        assert!(storage.drop_tree("headers_42").unwrap());
+        assert!(storage.open_tree("headers_42").unwrap().is_empty());
+    }
+
+    let _ = std::fs::remove_dir_all(log_dir);
+}
+
+#[test]
+fn evgr_log_storage_recovers_torn_writes_and_compacts() {
+    let log_dir = temp_log_dir("log_storage");
+    let value = vec![7u8; 64 * 1024];
+    {
+        let storage = LogStorage::open(&log_dir).unwrap();
+        let tree = storage.open_tree("1704067200000").unwrap();
+        tree.insert(b"kept", b"value").unwrap();
+
+        // Overwriting one key grows the log past the compaction
+        // threshold, and the rewrite only keeps the latest values.
+        for _ in 0..100 {
+            tree.insert(b"overwritten", &value).unwrap();
+        }
+        storage.flush().unwrap();
+    }
+
+    let log_path = std::fs::read_dir(&log_dir).unwrap().next().unwrap().unwrap().path();
+    assert!(std::fs::metadata(&log_path).unwrap().len() < 50 * value.len() as u64);
+
+    // A torn frame at the end of the log is cut off on reopen
+    let mut file = std::fs::OpenOptions::new().append(true).open(&log_path).unwrap();
+    std::io::Write::write_all(&mut file, &[9, 0, 0, 0, 1, 2]).unwrap();
+    drop(file);
+
+    let storage = LogStorage::open(&log_dir).unwrap();
+    assert_eq!(storage.tree_names().unwrap(), vec!["1704067200000".to_string()]);
+    let tree = storage.open_tree("1704067200000").unwrap();
+    assert_eq!(tree.len(), 2);
+    assert_eq!(tree.get(b"kept").unwrap(), Some(b"value".to_vec()));
+    assert_eq!(tree.get(b"overwritten").unwrap(), Some(value));
+
+    tree.insert(b"after", b"reopen").unwrap();
+    drop(tree);
+    drop(storage);
+    let storage = LogStorage::open(&log_dir).unwrap();
+    assert_eq!(storage.open_tree("1704067200000").unwrap().len(), 3);
+
+    let _ = std::fs::remove_dir_all(log_dir);
+}
+
+#[test]
+fn evgr_log_storage_iterates_across_compaction() {
+    let log_dir = temp_log_dir("log_storage_iter");
+    let storage = LogStorage::open(&log_dir).unwrap();
+    let tree = storage.open_tree("1704067200000").unwrap();
+    for key in 0u8..10 {
+        tree.insert([key], [key]).unwrap();
+    }
+
+    // The iterator reads values as it goes, so a compaction replacing
+    // the log in between doesn't break it.
+    let mut iter = tree.iter();
+    assert_eq!(iter.next().unwrap().unwrap(), (vec![0], vec![0]));
+    let value = vec![7u8; 64 * 1024];
+    for _ in 0..100 {
+        tree.insert(b"overwritten", &value).unwrap();
+    }
+    tree.remove([5]).unwrap();
+    storage.flush().unwrap();
+
+    let rest: Vec<_> = iter.map(|item| item.unwrap().0).collect();
+    let mut expected: Vec<Vec<u8>> = (1u8..10).filter(|k| *k != 5).map(|k| vec![k]).collect();
+    expected.push(b"overwritten".to_vec());
+    assert_eq!(rest, expected);
+
+    let _ = std::fs::remove_dir_all(log_dir);
+}
+
+#[test]
+fn evgr_migrate_sled_dags_moves_history_to_another_backend() {
+    smol::block_on(async {
+        let config = EventGraphConfig { rln_enabled: false, ..test_config() };
+        let sled_db = sled::Config::new().temporary(true).open().unwrap();
+        let eg = make_eg_with_config_and_db(config.clone(), sled_db.clone()).await;
+        let dag_name = eg.current_genesis.read().await.header.timestamp.to_string();
+
+        let event = Event::new(b"migrated".to_vec(), &eg).await.unwrap();
+        eg.header_dag_insert(vec![event.header.clone()], &dag_name).await.unwrap();
+        eg.dag_insert(slice::from_ref(&event), &dag_name).await.unwrap();
+        drop(eg);
+
+        let storage = Storage::memory();
+        let stats = migrate_sled_dags(&sled_db, &storage).unwrap();
+        assert_eq!(stats.trees, 2);
+        assert_eq!(stats.entries, 4);
+        assert!(!sled_db.tree_names().iter().any(|n| n.as_ref() == dag_name.as_bytes()));
+
+        // Running it again finds nothing left to move
+        assert_eq!(migrate_sled_dags(&sled_db, &storage).unwrap(), MigrationStats::default());
+
+        let eg = make_eg_with_storage(config, sled_db, storage).await;
+        assert_eq!(eg.fetch_event_from_dags(&event.id()).await.unwrap(), Some(event));
+    })
+}
+
 #[test]
 fn evgr_rotating_event_creation_rejects_missing_current_dag_slot() {
     smol::block_on(async {
@@ -826,7 +973,7 @@ fn evgr_header_insert_rejects_duplicate_parents() {
 fn evgr_header_validate_rejects_layer_overflow_parent() {
     smol::block_on(async {
         let db = sled::Config::new().temporary(true).open().unwrap();
-        let tree = db.open_tree("headers").unwrap();
+        let tree = StorageTree::from(db.open_tree("headers").unwrap());
         let timestamp = UNIX_EPOCH.elapsed().unwrap().as_millis() as u64;
         let parent = Header {
             timestamp,
@@ -855,7 +1002,7 @@ fn evgr_header_validate_uses_target_slot_bounds() {
         const HOUR_MS: u64 = 3_600_000;
 
         let db = sled::Config::new().temporary(true).open().unwrap();
-        let tree = db.open_tree("headers").unwrap();
+        let tree = StorageTree::from(db.open_tree("headers").unwrap());
         let dag_ts = 1_704_067_200_000;
         let drift = crate::event_graph::EVENT_TIME_DRIFT;
         let config = EventGraphConfig { hours_rotation: 6, ..test_config() };
@@ -896,7 +1043,7 @@ fn evgr_header_validate_ignores_future_initial_genesis() {
         const HOUR_MS: u64 = 3_600_000;
 
         let db = sled::Config::new().temporary(true).open().unwrap();
-        let tree = db.open_tree("headers").unwrap();
+        let tree = StorageTree::from(db.open_tree("headers").unwrap());
         let now = UNIX_EPOCH.elapsed().unwrap().as_millis() as u64;
         let dag_ts = now.saturating_sub(HOUR_MS);
         let config =
@@ -926,7 +1073,7 @@ fn evgr_header_validate_ignores_future_initial_genesis() {
 fn evgr_header_validate_no_rotation_rejects_far_future() {
     smol::block_on(async {
         let db = sled::Config::new().temporary(true).open().unwrap();
-        let tree = db.open_tree("headers").unwrap();
+        let tree = StorageTree::from(db.open_tree("headers").unwrap());
         let config = test_config();
         let dag_ts = config.initial_genesis;
         let genesis = Header {
@@ -1147,6 +1294,430 @@ fn evgr_fetch_page_with_blobs_keeps_same_timestamp_cursor_position() {
     })
 }
 
+#[test]
+fn evgr_fetch_page_at_keeps_same_timestamp_cursor_position() {
+    smol::block_on(async {
+        let eg = make_eg().await;
+        let dag_name = eg.current_genesis.read().await.header.timestamp.to_string();
+        let ts = UNIX_EPOCH.elapsed().unwrap().as_millis() as u64;
+        let mut events = vec![];
+
+        for i in 0..3_u8 {
+            let event =
+                Event::with_timestamp(ts, vec![b's', b'a', b'm', b'e', i], &eg).await.unwrap();
+            eg.header_dag_insert(vec![event.header.clone()], &dag_name).await.unwrap();
+            eg.dag_insert(slice::from_ref(&event), &dag_name).await.unwrap();
+            events.push(event);
+        }
+
+        events.sort_by(|a, b| b.id().as_bytes().cmp(a.id().as_bytes()));
+
+        let (first, cursor) =
+            eg.fetch_page_at(RangeCursor::newest(), SyncDirection::Backward, 2).await.unwrap();
+        assert_eq!(
+            first.iter().map(Event::id).collect::<Vec<_>>(),
+            events.iter().take(2).map(Event::id).collect::<Vec<_>>()
+        );
+        assert_eq!(cursor.event_id, events[1].id());
+
+        // The rest of the timestamp bucket, then the genesis event
+        let (second, _) = eg.fetch_page_at(cursor, SyncDirection::Backward, 2).await.unwrap();
+        assert_eq!(second[0].id(), events[2].id());
+        assert_eq!(second[1].header.parents, NULL_PARENTS);
+
+        // A successor cursor includes the event it points to
+        let (page, _) =
+            eg.fetch_page_at(cursor.successor(), SyncDirection::Backward, 1).await.unwrap();
+        assert_eq!(page[0].id(), events[1].id());
+
+        // Timestamp cursors include or exclude the whole bucket
+        let (page, _) = eg
+            .fetch_page_at(RangeCursor::timestamp_end(ts), SyncDirection::Backward, 3)
+            .await
+            .unwrap();
+        assert_eq!(page.len(), 3);
+        assert!(page.iter().all(|event| event.header.timestamp == ts));
+        let (page, _) = eg
+            .fetch_page_at(RangeCursor::timestamp_start(ts), SyncDirection::Forward, 3)
+            .await
+            .unwrap();
+        assert_eq!(page.len(), 3);
+        let (page, _) = eg
+            .fetch_page_at(RangeCursor::timestamp_end(ts), SyncDirection::Forward, 3)
+            .await
+            .unwrap();
+        assert!(page.is_empty());
+    })
+}
+
+/// Redaction policy accepting redactions carrying the expected auth
+/// bytes, which can be checked without the target content
+struct AuthBytesPolicy(Vec<u8>);
+
+impl RedactionPolicy for AuthBytesPolicy {
+    fn authorize(&self, _target: &Event, _redaction_event: &Event, redaction: &Redaction) -> bool {
+        redaction.auth == self.0
+    }
+
+    fn authorize_header(
+        &self,
+        _target: &Header,
+        _redaction_event: &Event,
+        redaction: &Redaction,
+    ) -> bool {
+        redaction.auth == self.0
+    }
+}
+
+/// Redaction policy accepting redactions whose auth bytes are the
+/// target content, so it needs the content
+struct AuthContentPolicy;
+
+impl RedactionPolicy for AuthContentPolicy {
+    fn authorize(&self, target: &Event, _redaction_event: &Event, redaction: &Redaction) -> bool {
+        redaction.auth == target.content
+    }
+}
+
+/// A chain of `n` events starting at `start`, one millisecond apart,
+/// each referencing the previous one.
+fn recon_chain(start: u64, n: u64) -> Vec<(RangeCursor, blake3::Hash)> {
+    let mut parent = NULL_ID;
+    let mut out = vec![];
+    for i in start..start + n {
+        let id = blake3::hash(&i.to_le_bytes());
+        out.push((RangeCursor { timestamp: i, event_id: id }, parent));
+        parent = id;
+    }
+    out
+}
+
+fn recon_sorted(items: &[(RangeCursor, blake3::Hash)]) -> Vec<RangeCursor> {
+    let mut out: Vec<RangeCursor> = items.iter().map(|(c, _)| *c).collect();
+    out.sort_by(|a, b| {
+        (a.timestamp, a.event_id.as_bytes()).cmp(&(b.timestamp, b.event_id.as_bytes()))
+    });
+    out
+}
+
+/// Run a reconciliation between `local` (initiator) and `remote`,
+/// returning the IDs `local` needs, the IDs it has that `remote`
+/// doesn't, and the number of messages exchanged.
+fn simulate_recon(
+    local: &[RangeCursor],
+    remote: &[RangeCursor],
+) -> (HashSet<blake3::Hash>, HashSet<blake3::Hash>, usize) {
+    let mut need = HashSet::new();
+    let mut have = HashSet::new();
+    let mut messages = 0;
+    let mut ranges = recon_initiate(local);
+    loop {
+        assert!(ranges.len() <= MAX_RECON_RANGES);
+        let rep = recon_process(remote, &ranges, false).unwrap();
+        assert!(rep.ranges.len() <= MAX_RECON_RANGES);
+        messages += 2;
+        let step = recon_process(local, &rep.ranges, true).unwrap();
+        need.extend(step.need.iter().copied());
+        have.extend(step.have.iter().copied());
+        if step.is_done() {
+            return (need, have, messages)
+        }
+        ranges = step.ranges;
+        assert!(messages < 200, "reconciliation did not converge");
+    }
+}
+
+#[test]
+fn evgr_recon_converges_in_logarithmic_rounds() {
+    // A node that was offline for part of the hour: it has the first
+    // 4000 events of a chain and a few of its own, the peer has 600 more.
+    let shared = recon_chain(1_000, 4_000);
+    let offline = recon_chain(5_000, 600);
+    let own = recon_chain(9_000, 3);
+
+    let local_items: Vec<_> = shared.iter().chain(own.iter()).copied().collect();
+    let remote_items: Vec<_> = shared.iter().chain(offline.iter()).copied().collect();
+    let local = recon_sorted(&local_items);
+    let remote = recon_sorted(&remote_items);
+
+    let (need, have, messages) = simulate_recon(&local, &remote);
+    assert_eq!(need, offline.iter().map(|(c, _)| c.event_id).collect());
+    assert_eq!(have, own.iter().map(|(c, _)| c.event_id).collect());
+
+    // log16(4600) is about 3, so a handful of round trips.
+    assert!(messages <= 12, "reconciliation took {messages} messages");
+
+    // Identical sets agree in a single round trip.
+    let (need, have, messages) = simulate_recon(&remote, &remote);
+    assert!(need.is_empty() && have.is_empty());
+    assert_eq!(messages, 2);
+
+    // An empty node learns everything, in both roles.
+    let (need, _, _) = simulate_recon(&[], &remote);
+    assert_eq!(need.len(), remote.len());
+    let (_, have, _) = simulate_recon(&remote, &[]);
+    assert_eq!(have.len(), remote.len());
+}
+
+#[test]
+fn evgr_recon_differences_scattered_over_the_dag() {
+    let all = recon_chain(0, 3_000);
+    let local_items: Vec<_> = all.iter().filter(|(c, _)| c.timestamp % 97 != 0).copied().collect();
+    let remote_items: Vec<_> =
+        all.iter().filter(|(c, _)| c.timestamp % 101 != 0).copied().collect();
+    let local = recon_sorted(&local_items);
+    let remote = recon_sorted(&remote_items);
+
+    let (need, have, _) = simulate_recon(&local, &remote);
+    let expected_need: HashSet<_> = all
+        .iter()
+        .filter(|(c, _)| c.timestamp % 97 == 0 && c.timestamp % 101 != 0)
+        .map(|(c, _)| c.event_id)
+        .collect();
+    let expected_have: HashSet<_> = all
+        .iter()
+        .filter(|(c, _)| c.timestamp % 101 == 0 && c.timestamp % 97 != 0)
+        .map(|(c, _)| c.event_id)
+        .collect();
+    assert_eq!(need, expected_need);
+    assert_eq!(have, expected_have);
+}
+
+#[test]
+fn evgr_recon_rejects_malformed_ranges() {
+    let items = recon_sorted(&recon_chain(0, 100));
+    let mid = items[50];
+
+    // Must end at the newest cursor.
+    let short = vec![ReconRange { upper: mid, mode: ReconMode::Skip }];
+    assert!(recon_process(&items, &short, false).is_err());
+    assert!(recon_process(&items, &[], false).is_err());
+
+    // Bounds must increase.
+    let unordered = vec![
+        ReconRange { upper: mid, mode: ReconMode::Skip },
+        ReconRange { upper: items[10], mode: ReconMode::Skip },
+        ReconRange { upper: RangeCursor::newest(), mode: ReconMode::Skip },
+    ];
+    assert!(recon_process(&items, &unordered, false).is_err());
+
+    // ID lists are bounded.
+    let ids = items.iter().take(RECON_ID_LIST_THRESHOLD + 1).map(|c| c.event_id).collect();
+    let long = vec![ReconRange { upper: RangeCursor::newest(), mode: ReconMode::IdList(ids) }];
+    assert!(recon_process(&items, &long, false).is_err());
+
+    // So is the number of ranges.
+    let many: Vec<ReconRange> = (0..MAX_RECON_RANGES as u64)
+        .map(|i| ReconRange {
+            upper: RangeCursor { timestamp: i + 1, event_id: NULL_ID },
+            mode: ReconMode::Skip,
+        })
+        .chain(std::iter::once(ReconRange { upper: RangeCursor::newest(), mode: ReconMode::Skip }))
+        .collect();
+    assert!(recon_process(&items, &many, false).is_err());
+}
+
+#[test]
+fn evgr_time_index_cursors_are_ordered() {
+    let items = recon_chain(0, 50);
+    let mut idx = TimeIndex::new();
+    for (c, _) in items.iter().rev() {
+        idx.insert(c.timestamp / 5, c.event_id);
+    }
+    let cursors = idx.cursors();
+    assert_eq!(cursors.len(), 50);
+    for pair in cursors.windows(2) {
+        assert!(
+            (pair[0].timestamp, pair[0].event_id.as_bytes()) <
+                (pair[1].timestamp, pair[1].event_id.as_bytes())
+        );
+    }
+}
+
+#[test]
+fn evgr_authorized_redaction_drops_content_and_serves_placeholder() {
+    smol::block_on(async {
+        let eg = make_eg().await;
+        eg.set_redaction_policy(Arc::new(AuthBytesPolicy(b"ok".to_vec()))).await;
+        let dag_ts = eg.current_genesis.read().await.header.timestamp;
+        let dag_name = dag_ts.to_string();
+        let sub = eg.event_pub.clone().subscribe().await;
+
+        let target = Event::new(b"oops".to_vec(), &eg).await.unwrap();
+        eg.header_dag_insert(vec![target.header.clone()], &dag_name).await.unwrap();
+        eg.dag_insert(slice::from_ref(&target), &dag_name).await.unwrap();
+        assert_eq!(sub.receive().await.id(), target.id());
+
+        let content = Redaction::new(target.id(), b"ok".to_vec()).to_content();
+        let redaction = Event::new(content, &eg).await.unwrap();
+        eg.header_dag_insert(vec![redaction.header.clone()], &dag_name).await.unwrap();
+        eg.dag_insert(slice::from_ref(&redaction), &dag_name).await.unwrap();
+
+        // Subscribers learn about the redaction once it was applied
+        let Ok(notified) = timeout(Duration::from_secs(1), sub.receive()).await else {
+            panic!("Redaction notification not received");
+        };
+        assert_eq!(notified.id(), redaction.id());
+        assert!(eg.is_redacted(&target.id()).unwrap());
+        let stored = eg.fetch_event_from_dags(&target.id()).await.unwrap().unwrap();
+        assert_eq!(stored.header, target.header);
+        assert!(stored.content.is_empty());
+
+        // Peers are served a placeholder holding the redaction, which
+        // another node accepts in place of the content.
+        let (events, blobs, _, _) = eg
+            .fetch_page_with_blobs(&dag_name, RangeCursor::oldest(), SyncDirection::Forward, 10)
+            .await
+            .unwrap();
+        let placeholder = events.iter().find(|ev| ev.id() == target.id()).unwrap().clone();
+        assert_eq!(placeholder_redaction(&placeholder), Some(redaction.clone()));
+        assert!(blobs[0].is_empty());
+
+        let peer = make_eg().await;
+        peer.set_redaction_policy(Arc::new(AuthBytesPolicy(b"ok".to_vec()))).await;
+        peer.header_dag_insert(vec![target.header.clone(), redaction.header.clone()], &dag_name)
+            .await
+            .unwrap();
+        let ids = peer.dag_insert(slice::from_ref(&placeholder), &dag_name).await.unwrap();
+        assert_eq!(ids, vec![target.id()]);
+        assert!(peer.is_redacted(&target.id()).unwrap());
+        assert!(peer
+            .fetch_event_from_dags(&target.id())
+            .await
+            .unwrap()
+            .unwrap()
+            .content
+            .is_empty());
+    })
+}
+
+#[test]
+fn evgr_unauthorized_redaction_keeps_content() {
+    smol::block_on(async {
+        for policy in [None, Some(Arc::new(AuthBytesPolicy(b"ok".to_vec())))] {
+            let eg = make_eg().await;
+            if let Some(policy) = policy {
+                eg.set_redaction_policy(policy).await;
+            }
+            let dag_name = eg.current_genesis.read().await.header.timestamp.to_string();
+
+            let target = Event::new(b"keep me".to_vec(), &eg).await.unwrap();
+            eg.header_dag_insert(vec![target.header.clone()], &dag_name).await.unwrap();
+            eg.dag_insert(slice::from_ref(&target), &dag_name).await.unwrap();
+
+            let content = Redaction::new(target.id(), b"forged".to_vec()).to_content();
+            let redaction = Event::new(content, &eg).await.unwrap();
+            eg.header_dag_insert(vec![redaction.header.clone()], &dag_name).await.unwrap();
+            let ids = eg.dag_insert(slice::from_ref(&redaction), &dag_name).await.unwrap();
+
+            // The redaction itself is a regular event
+            assert_eq!(ids, vec![redaction.id()]);
+            assert!(!eg.is_redacted(&target.id()).unwrap());
+            let stored = eg.fetch_event_from_dags(&target.id()).await.unwrap().unwrap();
+            assert_eq!(stored, target);
+        }
+    })
+}
+
+#[test]
+fn evgr_placeholder_needs_header_authorization() {
+    smol::block_on(async {
+        let eg = make_eg().await;
+        eg.set_redaction_policy(Arc::new(AuthContentPolicy)).await;
+        let dag_name = eg.current_genesis.read().await.header.timestamp.to_string();
+
+        let target = Event::new(b"secret".to_vec(), &eg).await.unwrap();
+        eg.header_dag_insert(vec![target.header.clone()], &dag_name).await.unwrap();
+        eg.dag_insert(slice::from_ref(&target), &dag_name).await.unwrap();
+
+        let content = Redaction::new(target.id(), b"secret".to_vec()).to_content();
+        let redaction = Event::new(content, &eg).await.unwrap();
+        eg.header_dag_insert(vec![redaction.header.clone()], &dag_name).await.unwrap();
+        eg.dag_insert(slice::from_ref(&redaction), &dag_name).await.unwrap();
+        assert!(eg.is_redacted(&target.id()).unwrap());
+        let placeholder = redacted_placeholder(&target.header, &redaction);
+
+        // Without the content, neither a content policy nor no policy
+        // at all can tell whether the redaction is authorized, so the
+        // body must come from a peer which still has it.
+        for policy in [None, Some(Arc::new(AuthContentPolicy))] {
+            let peer = make_eg().await;
+            if let Some(policy) = policy {
+                peer.set_redaction_policy(policy).await;
+            }
+            peer.header_dag_insert(
+                vec![target.header.clone(), redaction.header.clone()],
+                &dag_name,
+            )
+            .await
+            .unwrap();
+
+            assert!(!peer.placeholder_authorized(&placeholder).await);
+            let ids = peer.dag_insert(slice::from_ref(&placeholder), &dag_name).await.unwrap();
+            assert!(ids.is_empty());
+            assert!(peer.fetch_event_from_dags(&target.id()).await.unwrap().is_none());
+        }
+    })
+}
+
+#[test]
+fn evgr_unauthorized_live_redactions_are_not_admitted() {
+    smol::block_on(async {
+        let eg = make_eg().await;
+        let dag_name = eg.current_genesis.read().await.header.timestamp.to_string();
+
+        let target = Event::new(b"target".to_vec(), &eg).await.unwrap();
+        eg.header_dag_insert(vec![target.header.clone()], &dag_name).await.unwrap();
+        eg.dag_insert(slice::from_ref(&target), &dag_name).await.unwrap();
+
+        let redaction = |auth: &[u8], target: blake3::Hash| {
+            let content = Redaction::new(target, auth.to_vec()).to_content();
+            let eg = eg.clone();
+            async move { Event::new(content, &eg).await.unwrap() }
+        };
+
+        // Regular events always are, redactions need a policy
+        assert!(eg.redaction_admissible(&target).await.unwrap());
+        assert!(!eg.redaction_admissible(&redaction(b"ok", target.id()).await).await.unwrap());
+
+        eg.set_redaction_policy(Arc::new(AuthBytesPolicy(b"ok".to_vec()))).await;
+        assert!(eg.redaction_admissible(&redaction(b"ok", target.id()).await).await.unwrap());
+        assert!(!eg.redaction_admissible(&redaction(b"forged", target.id()).await).await.unwrap());
+
+        // Redactions of events we don't know can't be checked
+        let unknown = blake3::hash(b"unknown");
+        assert!(!eg.redaction_admissible(&redaction(b"ok", unknown).await).await.unwrap());
+
+        // With only the target header, the policy decides from it
+        let other = Event::new(b"other".to_vec(), &eg).await.unwrap();
+        eg.header_dag_insert(vec![other.header.clone()], &dag_name).await.unwrap();
+        assert!(eg.redaction_admissible(&redaction(b"ok", other.id()).await).await.unwrap());
+        eg.set_redaction_policy(Arc::new(AuthContentPolicy)).await;
+        assert!(!eg.redaction_admissible(&redaction(b"other", other.id()).await).await.unwrap());
+        assert!(eg.redaction_admissible(&redaction(b"target", target.id()).await).await.unwrap());
+    })
+}
+
+#[test]
+fn evgr_placeholder_without_known_redaction_is_rejected() {
+    smol::block_on(async {
+        let eg = make_eg().await;
+        eg.set_redaction_policy(Arc::new(AuthBytesPolicy(vec![]))).await;
+        let dag_name = eg.current_genesis.read().await.header.timestamp.to_string();
+
+        let target = Event::new(b"target".to_vec(), &eg).await.unwrap();
+        let content = Redaction::new(target.id(), vec![]).to_content();
+        let redaction = Event::new(content, &eg).await.unwrap();
+        let placeholder = redacted_placeholder(&target.header, &redaction);
+
+        // Only the target header is known, so the redaction can't be
+        // checked against the DAG.
+        eg.header_dag_insert(vec![target.header.clone()], &dag_name).await.unwrap();
+        assert!(eg.dag_insert(slice::from_ref(&placeholder), &dag_name).await.unwrap().is_empty());
+        assert!(eg.fetch_event_from_dags(&target.id()).await.unwrap().is_none());
+    })
+}
+
 #[test]
 fn evgr_multi_node_range_req_returns_blob_aligned_backward_page() {
     init_logger();
@@ -1225,6 +1796,159 @@ async fn range_req_returns_blob_aligned_backward_page(ex: Arc<Executor<'static>>
     shutdown_network(&nodes).await;
 }
 
+#[test]
+fn evgr_multi_node_dag_sync_reconcile_fetches_missing_events() {
+    init_logger();
+    run_multi_node_test(dag_sync_reconcile_fetches_missing_events);
+}
+async fn dag_sync_reconcile_fetches_missing_events(ex: Arc<Executor<'static>>) {
+    let nodes = make_network(ex).await;
+
+    let mut alice = TestIdentity::new();
+    for eg in &nodes {
+        alice.register_directly(eg).await.unwrap();
+    }
+
+    let dag_ts = nodes[0].current_genesis.read().await.header.timestamp;
+    let dag_name = dag_ts.to_string();
+    let base = UNIX_EPOCH.elapsed().unwrap().as_millis() as u64;
+    let mut events = vec![];
+
+    for i in 0..3_u8 {
+        let event = Event::with_timestamp(
+            base + u64::from(i),
+            vec![b'r', b'e', b'c', b'o', b'n', i],
+            &nodes[0],
+        )
+        .await
+        .unwrap();
+        let message_id = alice.next_message_id(event.header.timestamp).expect("budget");
+        let blob_struct = alice.create_signal(&event, message_id, &nodes[0]).await.unwrap();
+        let blob = serialize_async(&blob_struct).await;
+
+        for eg in nodes.iter().take(4) {
+            eg.insert_signal_with_blob(&event, &blob, &dag_name).await.unwrap();
+        }
+        events.push(event);
+    }
+
+    let stats = nodes[4].dag_sync_reconcile(dag_ts).await.unwrap();
+    assert!(stats.peers > 0);
+    assert_eq!(stats.fetched, events.len());
+    assert!(stats.rounds <= 2 * stats.peers);
+    for event in &events {
+        assert!(nodes[4].fetch_event_from_dags(&event.id()).await.unwrap().is_some());
+    }
+
+    // Nothing left to fetch on a second run.
+    let stats = nodes[4].dag_sync_reconcile(dag_ts).await.unwrap();
+    assert_eq!(stats.fetched, 0);
+    assert_eq!(stats.rounds, stats.peers);
+
+    shutdown_network(&nodes).await;
+}
+
+/// Sync requests `eg` sends to its peers while `sync` runs, counted
+/// from its dnet events
+async fn count_sync_requests<T>(eg: &EventGraphPtr, sync: impl Future<Output = T>) -> (T, usize) {
+    const SYNC_REQUESTS: [&str; 4] = [
+        "EventGraph::TipReq",
+        "EventGraph::HeaderReq",
+        "EventGraph::EventReq",
+        "EventGraph::ReconReq",
+    ];
+
+    let dnet = eg.p2p.dnet_subscribe().await;
+    eg.p2p.dnet_enable();
+    let out = sync.await;
+    eg.p2p.dnet_disable();
+
+    let mut requests = 0;
+    while let Some(event) = dnet.receive().now_or_never() {
+        if let DnetEvent::SendMessage(info) = event {
+            if SYNC_REQUESTS.contains(&info.cmd.as_str()) {
+                requests += 1;
+            }
+        }
+    }
+    (out, requests)
+}
+
+/// Publish a chain of `count` events to every node but the last one
+async fn publish_behind_last_node(
+    nodes: &[EventGraphPtr],
+    alice: &mut TestIdentity,
+    base: u64,
+    count: u8,
+) -> Vec<Event> {
+    let dag_name = nodes[0].current_genesis.read().await.header.timestamp.to_string();
+    let mut events = vec![];
+    for i in 0..count {
+        let event =
+            Event::with_timestamp(base + u64::from(i), vec![b'l', b'a', b'g', i], &nodes[0])
+                .await
+                .unwrap();
+        let message_id = alice.next_message_id(event.header.timestamp).expect("budget");
+        let blob_struct = alice.create_signal(&event, message_id, &nodes[0]).await.unwrap();
+        let blob = serialize_async(&blob_struct).await;
+
+        for eg in nodes.iter().take(nodes.len() - 1) {
+            eg.insert_signal_with_blob(&event, &blob, &dag_name).await.unwrap();
+        }
+        events.push(event);
+    }
+    events
+}
+
+#[test]
+fn evgr_multi_node_recon_requests_compared_to_tip_sync() {
+    init_logger();
+    run_multi_node_test(recon_requests_compared_to_tip_sync);
+}
+async fn recon_requests_compared_to_tip_sync(ex: Arc<Executor<'static>>) {
+    let nodes = make_network(ex).await;
+    let behind = nodes.last().unwrap();
+
+    let mut alice = TestIdentity::new();
+    for eg in &nodes {
+        alice.register_directly(eg).await.unwrap();
+    }
+
+    // The last node misses the same number of events twice, and
+    // catches up with the tip sync then with the reconciliation.
+    let dag_ts = nodes[0].current_genesis.read().await.header.timestamp;
+    let base = UNIX_EPOCH.elapsed().unwrap().as_millis() as u64;
+    let events = publish_behind_last_node(&nodes, &mut alice, base, 20).await;
+    let (synced, tip_requests) = count_sync_requests(behind, behind.sync_impl(dag_ts, true)).await;
+    synced.unwrap();
+    for event in &events {
+        assert!(behind.fetch_event_from_dags(&event.id()).await.unwrap().is_some());
+    }
+
+    let events = publish_behind_last_node(&nodes, &mut alice, base + 100, 20).await;
+    let (stats, recon_requests) =
+        count_sync_requests(behind, behind.dag_sync_reconcile(dag_ts)).await;
+    let stats = stats.unwrap();
+    assert_eq!(stats.fetched, events.len());
+    assert_eq!(stats.dropped, 0);
+    for event in &events {
+        assert!(behind.fetch_event_from_dags(&event.id()).await.unwrap().is_some());
+    }
+
+    // The tip sync asks every peer for its tips and the headers past
+    // ours, then the bodies in chunks of 20. The reconciliation takes
+    // a few rounds per peer over the whole DAG, then one request for
+    // the bodies, so it is on par while the node has no events of its
+    // own, and doesn't depend on the tips the peers agree on.
+    assert!(stats.rounds <= 3 * stats.peers, "{} rounds with {} peers", stats.rounds, stats.peers);
+    assert!(
+        recon_requests <= tip_requests + stats.peers,
+        "reconciliation took {recon_requests} requests, tip sync {tip_requests}",
+    );
+
+    shutdown_network(&nodes).await;
+}
+
 #[test]
 fn evgr_multi_node_dag_sync_range_drains_pending_backward_page() {
     init_logger();
@@ -0,0 +1,173 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2026 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Content-defined chunking, based on FastCDC.
+//!
+//! A rolling "gear" hash is computed over the data, and a chunk ends where
+//! the hash matches a mask. Chunk boundaries only depend on the bytes
+//! around them, so inserting or removing bytes in a file only changes the
+//! chunks around the modification.
+//!
+//! Normalized chunking is used: a stricter mask is used before
+//! [`CDC_AVG_CHUNK_SIZE`] and a looser one after it, so that chunk sizes
+//! stay close to the average. Chunks are never smaller than
+//! [`CDC_MIN_CHUNK_SIZE`] (except the last one) and never bigger than
+//! [`MAX_CHUNK_SIZE`].
+//!
+//! The gear table and the masks are part of the resource format, changing
+//! them changes the chunks (and hashes) of all content-defined resources.
+//! The hash of content-defined resources starts with [`CDC_HASH_DOMAIN`],
+//! whose version must be bumped along with them.
+
+use super::MAX_CHUNK_SIZE;
+
+/// Domain separator of the hash of content-defined resources, versioning
+/// the chunking parameters
+pub const CDC_HASH_DOMAIN: &[u8] = b"geode-cdc-v1";
+
+/// Minimum size of a content-defined chunk (64 KiB)
+pub const CDC_MIN_CHUNK_SIZE: usize = 65_536;
+
+/// Average size of a content-defined chunk (128 KiB)
+pub const CDC_AVG_CHUNK_SIZE: usize = 131_072;
+
+/// Mask used before reaching the average chunk size (19 bits)
+const MASK_S: u64 = !0 << (64 - 19);
+
+/// Mask used after reaching the average chunk size (15 bits)
+const MASK_L: u64 = !0 << (64 - 15);
+
+/// Gear table: 256 pseudorandom values, generated with SplitMix64 from a
+/// fixed seed.
+const GEAR: [u64; 256] = gear_table();
+
+const fn gear_table() -> [u64; 256] {
+    let mut table = [0u64; 256];
+    let mut state: u64 = 0x6765_6f64_6563_6463;
+    let mut i = 0;
+    while i < 256 {
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        table[i] = z ^ (z >> 31);
+        i += 1;
+    }
+    table
+}
+
+/// Returns the size of the first chunk of `data`.
+/// `data` must contain at least [`MAX_CHUNK_SIZE`] bytes, unless it is the
+/// end of the stream.
+pub fn cut_point(data: &[u8]) -> usize {
+    if data.len() <= CDC_MIN_CHUNK_SIZE {
+        return data.len()
+    }
+
+    let max_size = data.len().min(MAX_CHUNK_SIZE);
+    let avg_size = max_size.min(CDC_AVG_CHUNK_SIZE);
+
+    let mut hash: u64 = 0;
+    let mut i = CDC_MIN_CHUNK_SIZE;
+
+    while i < avg_size {
+        hash = (hash << 1).wrapping_add(GEAR[data[i] as usize]);
+        if hash & MASK_S == 0 {
+            return i + 1
+        }
+        i += 1;
+    }
+
+    while i < max_size {
+        hash = (hash << 1).wrapping_add(GEAR[data[i] as usize]);
+        if hash & MASK_L == 0 {
+            return i + 1
+        }
+        i += 1;
+    }
+
+    max_size
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Deterministic pseudorandom bytes
+    fn random_bytes(len: usize, seed: u64) -> Vec<u8> {
+        let mut state = seed;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect()
+    }
+
+    fn chunk_sizes(mut data: &[u8]) -> Vec<usize> {
+        let mut sizes = vec![];
+        while !data.is_empty() {
+            let size = cut_point(data);
+            sizes.push(size);
+            data = &data[size..];
+        }
+        sizes
+    }
+
+    #[test]
+    fn chunk_size_bounds() {
+        let data = random_bytes(8 * 1024 * 1024, 42);
+        let sizes = chunk_sizes(&data);
+
+        assert_eq!(sizes.iter().sum::<usize>(), data.len());
+        for size in &sizes[..sizes.len() - 1] {
+            assert!(*size >= CDC_MIN_CHUNK_SIZE);
+            assert!(*size <= MAX_CHUNK_SIZE);
+        }
+
+        // Small inputs are a single chunk
+        assert_eq!(chunk_sizes(&data[..1000]), vec![1000]);
+        assert!(chunk_sizes(&[]).is_empty());
+    }
+
+    #[test]
+    fn insertion_keeps_later_chunks() {
+        let data = random_bytes(4 * 1024 * 1024, 1337);
+        let mut modified = data.clone();
+        modified.splice(100..100, [0xff; 17]);
+
+        let hashes = |data: &[u8]| -> Vec<blake3::Hash> {
+            let mut offset = 0;
+            chunk_sizes(data)
+                .into_iter()
+                .map(|size| {
+                    offset += size;
+                    blake3::hash(&data[offset - size..offset])
+                })
+                .collect()
+        };
+        let original = hashes(&data);
+        let modified = hashes(&modified);
+
+        // Only the first chunks are different
+        let common = original.iter().filter(|hash| modified.contains(hash)).count();
+        assert!(common >= original.len() - 2);
+    }
+}
@@ -55,18 +55,41 @@ pub struct ChunkedStorage {
     /// Set to `true` if this ChunkedStorage is the representation of a
     /// directory.
     is_dir: bool,
+    /// Byte position of each chunk, only set if the chunks were created with
+    /// content-defined chunking (chunks are `MAX_CHUNK_SIZE` sized otherwise).
+    positions: Vec<u64>,
 }
 
 impl ChunkedStorage {
-    pub fn new(hashes: &[blake3::Hash], files: &[(PathBuf, u64)], is_dir: bool) -> Self {
-        Self {
-            chunks: hashes
-                .iter()
-                .map(|x| Chunk { hash: *x, available: false, size: MAX_CHUNK_SIZE })
-                .collect(),
-            fileseq: FileSequence::new(files, is_dir),
-            is_dir,
-        }
+    /// Create a new `ChunkedStorage`.
+    /// `sizes` contains the size of each chunk if the chunks were created
+    /// with content-defined chunking, it is empty otherwise.
+    pub fn new(
+        hashes: &[blake3::Hash],
+        sizes: &[u64],
+        files: &[(PathBuf, u64)],
+        is_dir: bool,
+    ) -> Self {
+        let chunks = hashes
+            .iter()
+            .enumerate()
+            .map(|(i, x)| Chunk {
+                hash: *x,
+                available: false,
+                size: sizes.get(i).map(|s| *s as usize).unwrap_or(MAX_CHUNK_SIZE),
+            })
+            .collect();
+
+        let positions = sizes
+            .iter()
+            .scan(0u64, |position, size| {
+                let chunk_position = *position;
+                *position += size;
+                Some(chunk_position)
+            })
+            .collect();
+
+        Self { chunks, fileseq: FileSequence::new(files, is_dir), is_dir, positions }
     }
 
     /// Check whether we have all the chunks available locally.
@@ -118,6 +141,65 @@ impl ChunkedStorage {
         None
     }
 
+    /// Return the indexes of all the chunks with hash `hash`. There can be
+    /// more than one if the same content appears several times.
+    pub fn get_chunk_indexes(&self, hash: &blake3::Hash) -> Vec<usize> {
+        self.chunks
+            .iter()
+            .enumerate()
+            .filter(|(_, chunk)| chunk.hash == *hash)
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    /// Return `true` if the chunks were created with content-defined chunking.
+    pub fn is_content_defined(&self) -> bool {
+        !self.positions.is_empty()
+    }
+
+    /// Return the chunk sizes of a content-defined `ChunkedStorage`, or an
+    /// empty Vec if the chunks are fixed-size.
+    pub fn get_chunk_sizes(&self) -> Vec<u64> {
+        match self.is_content_defined() {
+            true => self.chunks.iter().map(|c| c.size as u64).collect(),
+            false => vec![],
+        }
+    }
+
+    /// Return the byte position of the chunk at `index`.
+    pub fn get_chunk_position(&self, index: usize) -> u64 {
+        match self.positions.get(index) {
+            Some(position) => *position,
+            None => (index as u64) * (MAX_CHUNK_SIZE as u64),
+        }
+    }
+
+    /// Return the maximum size of the chunk at `index`. This is the exact
+    /// chunk size for content-defined chunks.
+    pub fn get_chunk_max_size(&self, index: usize) -> usize {
+        match self.is_content_defined() {
+            true => self.chunks.get(index).map(|c| c.size).unwrap_or(0),
+            false => MAX_CHUNK_SIZE,
+        }
+    }
+
+    /// Return the maximum size in bytes of all the chunks. This is the exact
+    /// size for content-defined chunks.
+    pub fn get_max_size(&self) -> u64 {
+        match self.is_content_defined() {
+            true => self.chunks.iter().map(|c| c.size as u64).sum(),
+            false => (self.chunks.len() * MAX_CHUNK_SIZE) as u64,
+        }
+    }
+
+    /// Return the index of the chunk containing the byte at `position`.
+    pub fn get_chunk_index_at(&self, position: u64) -> usize {
+        match self.is_content_defined() {
+            true => self.positions.partition_point(|p| *p <= position).saturating_sub(1),
+            false => (position / MAX_CHUNK_SIZE as u64) as usize,
+        }
+    }
+
     /// Return the list of files from the `reader`.
     pub fn get_files(&self) -> &Vec<(PathBuf, u64)> {
         self.fileseq.get_files()
@@ -151,10 +233,10 @@ impl ChunkedStorage {
 
         let end_pos = start_pos + files[file_index].1;
 
-        let start_index = (start_pos as f64 / MAX_CHUNK_SIZE as f64).floor();
-        let end_index = (end_pos as f64 / MAX_CHUNK_SIZE as f64).floor();
+        let start_index = self.get_chunk_index_at(start_pos);
+        let end_index = self.get_chunk_index_at(end_pos);
 
-        let chunk_indexes: Vec<usize> = (start_index as usize..=end_index as usize).collect();
+        let chunk_indexes: Vec<usize> = (start_index..=end_index).collect();
 
         chunk_indexes
             .iter()
@@ -25,7 +25,8 @@
 //! clean things up.
 //!
 //! The hash of a file is the BLAKE3 hash of hashed chunks in the correct
-//! order.
+//! order. With content-defined chunking, it is the hash of
+//! [`cdc::CDC_HASH_DOMAIN`] followed by the chunk hashes, each with its size.
 //! The hash of a directory is the BLAKE3 hash of hashed chunks in the correct
 //! order and the ordered list of (file path, file sizes).
 //! All hashes (file, directory, chunk) are 32 bytes long, and are encoded in
@@ -45,6 +46,15 @@
 //! To get the chunks you split the full file into `MAX_CHUNK_SIZE` sized
 //! slices, the last chunk is the only one that can be smaller than that.
 //!
+//! Alternatively, resources can be split using content-defined chunking
+//! (see [`cdc`]), where chunk boundaries depend on the content, so that
+//! two versions of a file share most of their chunks. Chunks then have a
+//! variable size (at most `MAX_CHUNK_SIZE`), which is stored in the
+//! metadata next to each chunk hash: `<chunk hash>:<chunk size>`.
+//! The chunking is chosen per resource: the same content has different
+//! hashes with fixed-size and content-defined chunks, so whoever computes
+//! a resource hash must use the chunking it was inserted with.
+//!
 //! It might look like the following:
 //! ```
 //! /files/B9fFKaEYphw2oH5PDbeL1TTAcSzL6ax84p8SjBKzuYzX
@@ -75,13 +85,17 @@
 //!
 //! The full file is not copied, and individual chunks are not stored by
 //! geode. Additionally it does not keep track of the full files path.
+//! A chunk that appears several times in a resource is written at all of its
+//! positions when it is received. Shared chunks thus only save transfers:
+//! a chunk is fetched once and can be copied from another local resource,
+//! but every file still holds all of its bytes.
 
 use std::{
     collections::HashSet,
     path::{Path, PathBuf},
 };
 
-use futures::{AsyncRead, AsyncSeek};
+use futures::AsyncRead;
 use smol::{
     fs::{self, File},
     io::{
@@ -94,6 +108,8 @@ use tracing::{debug, info, warn};
 
 use crate::{Error, Result};
 
+pub mod cdc;
+
 mod chunked_storage;
 pub use chunked_storage::{Chunk, ChunkedStorage};
 
@@ -139,11 +155,15 @@ impl Geode {
 
     /// Attempt to read chunk hashes and files metadata from a given metadata path.
     /// This works for both file metadata and directory metadata.
-    /// Returns (chunk hashes, [(file path, file size)]).
-    async fn read_metadata(path: &PathBuf) -> Result<(Vec<blake3::Hash>, Vec<(PathBuf, u64)>)> {
+    /// Returns (chunk hashes, chunk sizes, [(file path, file size)]).
+    /// Chunk sizes are empty if the chunks are not content-defined.
+    async fn read_metadata(
+        path: &PathBuf,
+    ) -> Result<(Vec<blake3::Hash>, Vec<u64>, Vec<(PathBuf, u64)>)> {
         debug!(target: "geode::read_dir_metadata", "Reading chunks from {path:?} (dir)");
 
         let mut chunk_hashes = vec![];
+        let mut chunk_sizes = vec![];
         let mut files = vec![];
 
         let fd = File::open(path).await?;
@@ -179,21 +199,33 @@ impl Geode {
                 files.push((file_path, file_size));
             } else if parts.len() == 1 {
                 // Chunk
-                let chunk_hash_str = parts[0].trim();
+                let (chunk_hash_str, chunk_size) = match parts[0].trim().split_once(':') {
+                    Some((hash_str, size_str)) => (hash_str, Some(size_str.parse::<u64>()?)),
+                    None => (parts[0].trim(), None),
+                };
                 if chunk_hash_str.is_empty() {
                     break; // Stop reading chunk hashes on empty line
                 }
+
                 let mut hash_buf = [0u8; 32];
                 bs58::decode(chunk_hash_str).onto(&mut hash_buf)?;
                 let chunk_hash = blake3::Hash::from_bytes(hash_buf);
                 chunk_hashes.push(chunk_hash);
+                if let Some(chunk_size) = chunk_size {
+                    chunk_sizes.push(chunk_size);
+                }
             } else {
                 // Invalid format
                 return Err(Error::Custom("Invalid directory metadata format".to_string()));
             }
         }
 
-        Ok((chunk_hashes, files))
+        // Either all chunks have a size or none of them
+        if !chunk_sizes.is_empty() && chunk_sizes.len() != chunk_hashes.len() {
+            return Err(Error::Custom("Invalid chunk sizes in metadata".to_string()))
+        }
+
+        Ok((chunk_hashes, chunk_sizes, files))
     }
 
     /// Perform garbage collection over the filesystem hierarchy.
@@ -272,15 +304,64 @@ impl Geode {
         Ok((hasher, chunk_hashes))
     }
 
+    /// Chunk a stream using content-defined chunking.
+    /// Returns a hasher (containing the chunk hashes and sizes), the list of
+    /// chunk hashes, and the list of chunk sizes.
+    pub async fn chunk_stream_cdc(
+        &self,
+        mut stream: impl AsyncRead + Unpin,
+    ) -> Result<(blake3::Hasher, Vec<blake3::Hash>, Vec<u64>)> {
+        let mut hasher = blake3::Hasher::new();
+        let mut chunk_hashes = vec![];
+        let mut chunk_sizes = vec![];
+
+        // `buf[..len]` contains the data that is not chunked yet
+        let mut buf = vec![0u8; MAX_CHUNK_SIZE];
+        let mut len = 0;
+
+        loop {
+            len += read_until_filled(&mut stream, &mut buf[len..]).await?.len();
+            if len == 0 {
+                break
+            }
+
+            let chunk_size = cdc::cut_point(&buf[..len]);
+            let chunk_hash = blake3::hash(&buf[..chunk_size]);
+            chunk_hashes.push(chunk_hash);
+            chunk_sizes.push(chunk_size as u64);
+
+            buf.copy_within(chunk_size..len, 0);
+            len -= chunk_size;
+        }
+
+        self.hash_chunks_metadata(&mut hasher, &chunk_hashes, &chunk_sizes);
+
+        Ok((hasher, chunk_hashes, chunk_sizes))
+    }
+
     /// Sorts files by their PathBuf.
     pub fn sort_files(&self, files: &mut [(PathBuf, u64)]) {
         files.sort_by(|(a, _), (b, _)| a.to_string_lossy().cmp(&b.to_string_lossy()));
     }
 
     /// Add chunk hashes to `hasher`.
-    pub fn hash_chunks_metadata(&self, hasher: &mut blake3::Hasher, chunk_hashes: &[blake3::Hash]) {
-        for chunk in chunk_hashes {
+    /// `chunk_sizes` is empty if the chunks are not content-defined,
+    /// otherwise [`cdc::CDC_HASH_DOMAIN`] comes first and each chunk size is
+    /// added after its hash.
+    pub fn hash_chunks_metadata(
+        &self,
+        hasher: &mut blake3::Hasher,
+        chunk_hashes: &[blake3::Hash],
+        chunk_sizes: &[u64],
+    ) {
+        if !chunk_sizes.is_empty() {
+            hasher.update(cdc::CDC_HASH_DOMAIN);
+        }
+        for (i, chunk) in chunk_hashes.iter().enumerate() {
             hasher.update(chunk.as_bytes());
+            if let Some(size) = chunk_sizes.get(i) {
+                hasher.update(&size.to_le_bytes());
+            }
         }
     }
 
@@ -300,17 +381,19 @@ impl Geode {
     /// Create and insert file or directory metadata into Geode.
     /// Always overwrites any existing file.
     /// Verifies that the metadata is valid.
+    /// The `chunk_sizes` slice is empty if the chunks are not content-defined.
     /// The `relative_files` slice is empty for files.
     pub async fn insert_metadata(
         &self,
         hash: &blake3::Hash,
         chunk_hashes: &[blake3::Hash],
+        chunk_sizes: &[u64],
         relative_files: &[(PathBuf, u64)],
     ) -> Result<()> {
         info!(target: "geode::insert_metadata", "[Geode] Inserting metadata");
 
         // Verify the metadata
-        if !self.verify_metadata(hash, chunk_hashes, relative_files) {
+        if !self.verify_metadata(hash, chunk_hashes, chunk_sizes, relative_files) {
             return Err(Error::GeodeNeedsGc)
         }
 
@@ -322,8 +405,12 @@ impl Geode {
         file_path.push(hash_to_string(hash).as_str());
         let mut file_fd = File::create(&file_path).await?;
 
-        for ch in chunk_hashes {
-            file_fd.write(format!("{}\n", hash_to_string(ch).as_str()).as_bytes()).await?;
+        for (i, ch) in chunk_hashes.iter().enumerate() {
+            let line = match chunk_sizes.get(i) {
+                Some(size) => format!("{}:{size}\n", hash_to_string(ch)),
+                None => format!("{}\n", hash_to_string(ch)),
+            };
+            file_fd.write(line.as_bytes()).await?;
         }
         for file in relative_files {
             file_fd.write(format!("{} {}\n", file.0.to_string_lossy(), file.1).as_bytes()).await?;
@@ -335,8 +422,10 @@ impl Geode {
 
     /// Write a single chunk given a stream.
     /// The file must be inserted into Geode before calling this method.
-    /// Always overwrites any existing chunk. Returns the chunk hash and
-    /// the number of bytes written to the file system.
+    /// Always overwrites any existing chunk. The chunk is written at every
+    /// position it appears in. Returns the chunk hash and the number of
+    /// bytes written to the file system (the smallest one if the chunk was
+    /// written at several positions).
     pub async fn write_chunk(
         &self,
         chunked: &mut ChunkedStorage,
@@ -353,38 +442,48 @@ impl Geode {
         // Get the chunk hash from the content
         let chunk_hash = blake3::hash(chunk_slice);
 
-        // Get the chunk index in the file/directory from the chunk hash
-        let chunk_index = match chunked.iter().position(|c| c.hash == chunk_hash) {
-            Some(index) => index,
-            None => {
+        // Get the chunk indexes in the file/directory from the chunk hash
+        let chunk_indexes = chunked.get_chunk_indexes(&chunk_hash);
+        if chunk_indexes.is_empty() {
+            return Err(Error::GeodeNeedsGc);
+        }
+
+        let mut bytes_written = chunk_slice.len();
+        for chunk_index in chunk_indexes {
+            // Content-defined chunks must have the size from the metadata
+            if chunked.is_content_defined() &&
+                chunk_slice.len() != chunked.get_chunk_max_size(chunk_index)
+            {
                 return Err(Error::GeodeNeedsGc);
             }
-        };
 
-        // Compute byte position from the chunk index and the chunk size
-        let position = (chunk_index as u64) * (MAX_CHUNK_SIZE as u64);
-
-        // Seek to the correct position
-        let fileseq = &mut chunked.get_fileseq_mut();
-        fileseq.seek(SeekFrom::Start(position)).await?;
-
-        // This will write the chunk, and truncate files if `chunked` is a directory.
-        let bytes_written = fileseq.write(chunk_slice).await?;
-
-        // If it's the last chunk of a file (and it's *not* a directory),
-        // truncate the file to the correct length.
-        // This is because contrary to directories, we do not know the exact
-        // file size from its metadata, we only know the number of chunks.
-        // Therefore we only know the exact size once we know the size of the
-        // last chunk.
-        // We also update the `FileSequence` to the exact size.
-        if !chunked.is_dir() && chunk_index == chunked.len() - 1 {
-            let exact_file_size =
-                chunked.len() * MAX_CHUNK_SIZE - (MAX_CHUNK_SIZE - chunk_slice.len());
-            if let Some(file) = &chunked.get_fileseq_mut().get_current_file() {
-                let _ = file.set_len(exact_file_size as u64);
+            // Get the byte position of the chunk
+            let position = chunked.get_chunk_position(chunk_index);
+
+            // Seek to the correct position
+            let fileseq = &mut chunked.get_fileseq_mut();
+            fileseq.seek(SeekFrom::Start(position)).await?;
+
+            // This will write the chunk, and truncate files if `chunked` is a directory.
+            bytes_written = bytes_written.min(fileseq.write(chunk_slice).await?);
+
+            // If it's the last chunk of a file (and it's *not* a directory nor
+            // content-defined), truncate the file to the correct length.
+            // This is because contrary to directories, we do not know the exact
+            // file size from its metadata, we only know the number of chunks.
+            // Therefore we only know the exact size once we know the size of the
+            // last chunk.
+            // We also update the `FileSequence` to the exact size.
+            if !chunked.is_dir() &&
+                !chunked.is_content_defined() &&
+                chunk_index == chunked.len() - 1
+            {
+                let exact_file_size = position + chunk_slice.len() as u64;
+                if let Some(file) = &chunked.get_fileseq_mut().get_current_file() {
+                    let _ = file.set_len(exact_file_size);
+                }
+                chunked.get_fileseq_mut().set_file_size(0, exact_file_size);
             }
-            chunked.get_fileseq_mut().set_file_size(0, exact_file_size as u64);
         }
 
         Ok((chunk_hash, bytes_written))
@@ -401,8 +500,10 @@ impl Geode {
         let metadata_paths = [self.files_path.join(&hash_str), self.dirs_path.join(&hash_str)];
         for metadata_path in metadata_paths {
             match Self::read_metadata(&metadata_path).await {
-                Ok((chunk_hashes, files)) => {
-                    return self.create_chunked_storage(hash, path, &chunk_hashes, &files).await
+                Ok((chunk_hashes, chunk_sizes, files)) => {
+                    return self
+                        .create_chunked_storage(hash, path, &chunk_hashes, &chunk_sizes, &files)
+                        .await
                 }
                 Err(e) => {
                     if !matches!(e, Error::Io(ErrorKind::NotFound)) {
@@ -422,24 +523,33 @@ impl Geode {
         hash: &blake3::Hash,
         path: &Path,
         chunk_hashes: &[blake3::Hash],
+        chunk_sizes: &[u64],               // Only used by content-defined chunks
         relative_files: &[(PathBuf, u64)], // Only used by directories
     ) -> Result<ChunkedStorage> {
         // Make sure the file or directory is valid
-        if !self.verify_metadata(hash, chunk_hashes, relative_files) {
+        if !self.verify_metadata(hash, chunk_hashes, chunk_sizes, relative_files) {
             return Err(Error::GeodeNeedsGc);
         }
 
         let chunked = if relative_files.is_empty() {
             // File
-            let file_size = (chunk_hashes.len() * MAX_CHUNK_SIZE) as u64; // Upper bound, not actual file size
-            ChunkedStorage::new(chunk_hashes, &[(path.to_path_buf(), file_size)], false)
+            let file_size = match chunk_sizes.is_empty() {
+                true => (chunk_hashes.len() * MAX_CHUNK_SIZE) as u64, // Upper bound, not actual file size
+                false => chunk_sizes.iter().sum(),
+            };
+            ChunkedStorage::new(
+                chunk_hashes,
+                chunk_sizes,
+                &[(path.to_path_buf(), file_size)],
+                false,
+            )
         } else {
             // Directory
             let files: Vec<_> = relative_files
                 .iter()
                 .map(|(file_path, size)| (path.join(file_path), *size))
                 .collect();
-            ChunkedStorage::new(chunk_hashes, &files, true)
+            ChunkedStorage::new(chunk_hashes, chunk_sizes, &files, true)
         };
 
         Ok(chunked)
@@ -462,35 +572,55 @@ impl Geode {
         };
 
         // Read the file to get the chunk content
-        let chunk = self.read_chunk(&mut chunked.get_fileseq_mut(), &chunk_index).await?;
+        let chunk = self.read_chunk(chunked, &chunk_index).await?;
 
         Ok(chunk)
     }
 
-    /// Read the file at `file_path` to get its chunk with index `chunk_index`.
+    /// Read the files of `chunked` to get its chunk with index `chunk_index`.
     /// Returns the chunk content in a Vec.
     pub async fn read_chunk(
         &self,
-        mut stream: impl AsyncRead + Unpin + AsyncSeek,
+        chunked: &mut ChunkedStorage,
         chunk_index: &usize,
     ) -> Result<Vec<u8>> {
-        let position = (*chunk_index as u64) * (MAX_CHUNK_SIZE as u64);
-        let mut buf = vec![0u8; MAX_CHUNK_SIZE];
+        let position = chunked.get_chunk_position(*chunk_index);
+        let mut buf = vec![0u8; chunked.get_chunk_max_size(*chunk_index)];
+        let stream = chunked.get_fileseq_mut();
         stream.seek(SeekFrom::Start(position)).await?;
         let bytes_read = stream.read(&mut buf).await?;
         Ok(buf[..bytes_read].to_vec())
     }
 
-    /// Verifies that the file hash matches the chunk hashes.
+    /// Verifies that the file hash matches the chunk hashes (and the chunk
+    /// sizes if the chunks are content-defined).
     pub fn verify_metadata(
         &self,
         hash: &blake3::Hash,
         chunk_hashes: &[blake3::Hash],
+        chunk_sizes: &[u64],
         files: &[(PathBuf, u64)],
     ) -> bool {
         info!(target: "geode::verify_metadata", "[Geode] Verifying metadata for {}", hash_to_string(hash));
+
+        // Content-defined chunks must all have a valid size, and must cover
+        // all the files of a directory
+        if !chunk_sizes.is_empty() {
+            if chunk_sizes.len() != chunk_hashes.len() ||
+                chunk_sizes.iter().any(|size| *size == 0 || *size > MAX_CHUNK_SIZE as u64)
+            {
+                return false
+            }
+            if !files.is_empty() &&
+                chunk_sizes.iter().sum::<u64>() !=
+                    files.iter().map(|(_, size)| size).sum::<u64>()
+            {
+                return false
+            }
+        }
+
         let mut hasher = blake3::Hasher::new();
-        self.hash_chunks_metadata(&mut hasher, chunk_hashes);
+        self.hash_chunks_metadata(&mut hasher, chunk_hashes, chunk_sizes);
         self.hash_files_metadata(&mut hasher, files);
         *hash == hasher.finalize()
     }
@@ -0,0 +1,415 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2026 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Bandwidth limits and traffic shaping.
+//!
+//! Unlike [`metering`](super::metering), which protects against peers
+//! spamming a single message type, this module caps the total amount of
+//! traffic a node uploads and downloads. Limits are configured globally
+//! and per session type, and each one is enforced with a token bucket.
+//!
+//! Outgoing messages carry a [`TrafficPriority`] so that consensus and
+//! sync traffic is not starved by bulk transfers: high priority messages
+//! are never delayed (but are still accounted for), normal messages wait
+//! for their turn, and bulk messages additionally yield to any normal
+//! message waiting on the same bucket. Downloads are shaped by delaying
+//! the read of the next message, which pushes back on the sender through
+//! the transport's flow control.
+//!
+//! High priority must be kept for small control and consensus messages,
+//! as it bypasses the limit. Limits are applied again on
+//! [`P2p::reload()`](super::P2p::reload).
+
+use std::{
+    io,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+use parking_lot::Mutex;
+use smol::{
+    io::{AsyncRead, AsyncWrite},
+    Timer,
+};
+
+use super::{
+    session::{SessionBitFlag, SESSION_DIRECT, SESSION_INBOUND, SESSION_MANUAL, SESSION_OUTBOUND},
+    settings::{BandwidthLimit, Settings},
+    transport::PtStream,
+};
+
+/// Session types which can have their own bandwidth limits, along with
+/// the name used to configure them.
+pub const LIMITED_SESSIONS: [(SessionBitFlag, &str); 4] = [
+    (SESSION_INBOUND, "inbound"),
+    (SESSION_OUTBOUND, "outbound"),
+    (SESSION_MANUAL, "manual"),
+    (SESSION_DIRECT, "direct"),
+];
+
+/// Priority class of outgoing traffic.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum TrafficPriority {
+    /// Bulk data transfers, sent only when nothing else is waiting
+    Bulk,
+    /// Regular protocol traffic
+    Normal,
+    /// Small control and consensus messages, never delayed
+    High,
+}
+
+/// Token bucket refilled at `rate` bytes per second, holding at most
+/// one second worth of tokens.
+///
+/// Reservations are taken upfront and may put the bucket in debt, in
+/// which case the caller waits until the debt is paid off. This keeps
+/// messages larger than the bucket size from blocking forever.
+struct TokenBucket {
+    rate: f64,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    fn new(rate: u64) -> Self {
+        Self { rate: rate as f64, tokens: rate as f64, last_refill: Instant::now() }
+    }
+
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.rate);
+        self.last_refill = now;
+    }
+
+    /// Time until the bucket is out of debt
+    fn debt_delay(&self) -> Duration {
+        if self.tokens >= 0.0 {
+            return Duration::ZERO
+        }
+        Duration::from_secs_f64(-self.tokens / self.rate)
+    }
+
+    /// Take `bytes` tokens and return how long the caller should wait
+    fn reserve(&mut self, bytes: usize) -> Duration {
+        self.refill();
+        self.tokens -= bytes as f64;
+        self.debt_delay()
+    }
+}
+
+/// Rate limiter for a single traffic direction.
+pub struct RateLimiter {
+    /// `None` when unlimited
+    bucket: Mutex<Option<TokenBucket>>,
+    /// Configured limit in bytes per second, 0 when unlimited
+    limit: AtomicU64,
+    /// Number of normal priority reservations currently waiting
+    normal_waiting: AtomicUsize,
+    /// Total bytes that went through this limiter
+    total: AtomicU64,
+}
+
+impl RateLimiter {
+    /// Create a new limiter. `None` or `Some(0)` means unlimited.
+    pub fn new(limit: Option<u64>) -> Self {
+        let limit = limit.unwrap_or(0);
+        let bucket = (limit > 0).then(|| TokenBucket::new(limit));
+        Self {
+            bucket: Mutex::new(bucket),
+            limit: AtomicU64::new(limit),
+            normal_waiting: AtomicUsize::new(0),
+            total: AtomicU64::new(0),
+        }
+    }
+
+    /// Configured limit in bytes per second, 0 when unlimited
+    pub fn limit(&self) -> u64 {
+        self.limit.load(Ordering::Relaxed)
+    }
+
+    /// Change the limit. `None` or `Some(0)` means unlimited. Pending
+    /// debt is carried over so a lowered limit is honoured right away.
+    pub fn set_limit(&self, limit: Option<u64>) {
+        let limit = limit.unwrap_or(0);
+        if self.limit.swap(limit, Ordering::Relaxed) == limit {
+            return
+        }
+
+        let mut bucket = self.bucket.lock();
+        if limit == 0 {
+            *bucket = None;
+            return
+        }
+
+        match bucket.as_mut() {
+            Some(bucket) => {
+                bucket.refill();
+                bucket.rate = limit as f64;
+                bucket.tokens = bucket.tokens.min(bucket.rate);
+            }
+            None => *bucket = Some(TokenBucket::new(limit)),
+        }
+    }
+
+    /// Total bytes accounted by this limiter
+    pub fn total(&self) -> u64 {
+        self.total.load(Ordering::Relaxed)
+    }
+
+    /// Account for `bytes` of traffic, waiting as long as needed to stay
+    /// within the limit for the given priority.
+    pub async fn acquire(&self, bytes: usize, priority: TrafficPriority) {
+        self.total.fetch_add(bytes as u64, Ordering::Relaxed);
+        match priority {
+            TrafficPriority::High => {
+                if let Some(bucket) = self.bucket.lock().as_mut() {
+                    bucket.reserve(bytes);
+                }
+            }
+
+            TrafficPriority::Normal => {
+                let Some(delay) = self.bucket.lock().as_mut().map(|b| b.reserve(bytes)) else {
+                    return
+                };
+                if !delay.is_zero() {
+                    let _waiting = WaitingGuard::new(&self.normal_waiting);
+                    Timer::after(delay).await;
+                }
+            }
+
+            TrafficPriority::Bulk => loop {
+                // Only reserve once the bucket is out of debt and no
+                // normal traffic is queued up behind it.
+                let (reserved, delay) = {
+                    let mut bucket = self.bucket.lock();
+                    let Some(bucket) = bucket.as_mut() else { return };
+                    bucket.refill();
+                    let delay = bucket.debt_delay();
+                    if delay.is_zero() && self.normal_waiting.load(Ordering::SeqCst) == 0 {
+                        (true, bucket.reserve(bytes))
+                    } else {
+                        (false, delay.max(Duration::from_millis(10)))
+                    }
+                };
+
+                Timer::after(delay).await;
+                if reserved {
+                    return
+                }
+            },
+        }
+    }
+}
+
+/// Keeps a waiter counted for as long as it's alive, so that a cancelled
+/// send doesn't leave bulk traffic blocked behind it.
+struct WaitingGuard<'a>(&'a AtomicUsize);
+
+impl<'a> WaitingGuard<'a> {
+    fn new(counter: &'a AtomicUsize) -> Self {
+        counter.fetch_add(1, Ordering::SeqCst);
+        Self(counter)
+    }
+}
+
+impl Drop for WaitingGuard<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Upload and download limiters of a single scope.
+pub struct DirectionalLimiter {
+    pub upload: RateLimiter,
+    pub download: RateLimiter,
+}
+
+impl DirectionalLimiter {
+    fn new(limit: &BandwidthLimit) -> Self {
+        Self {
+            upload: RateLimiter::new(limit.upload_limit),
+            download: RateLimiter::new(limit.download_limit),
+        }
+    }
+
+    fn stats(&self, scope: &str) -> BandwidthStats {
+        BandwidthStats {
+            scope: scope.to_string(),
+            upload_limit: self.upload.limit(),
+            download_limit: self.download.limit(),
+            bytes_sent: self.upload.total(),
+            bytes_recv: self.download.total(),
+        }
+    }
+}
+
+/// Bandwidth usage snapshot of a limiter scope
+#[derive(Clone, Debug)]
+pub struct BandwidthStats {
+    /// `global` or the session name
+    pub scope: String,
+    /// Upload limit in bytes per second, 0 when unlimited
+    pub upload_limit: u64,
+    /// Download limit in bytes per second, 0 when unlimited
+    pub download_limit: u64,
+    /// Total bytes sent
+    pub bytes_sent: u64,
+    /// Total bytes received
+    pub bytes_recv: u64,
+}
+
+/// Global and per-session bandwidth limiters of a P2P instance
+pub struct BandwidthManager {
+    global: DirectionalLimiter,
+    sessions: Vec<(SessionBitFlag, &'static str, DirectionalLimiter)>,
+}
+
+impl BandwidthManager {
+    /// Build the limiters from the configured settings
+    pub fn new(settings: &Settings) -> Self {
+        let global = DirectionalLimiter::new(&BandwidthLimit {
+            upload_limit: settings.upload_limit,
+            download_limit: settings.download_limit,
+        });
+
+        let sessions = LIMITED_SESSIONS
+            .iter()
+            .map(|(flag, name)| {
+                let limit = settings.session_bandwidth.get(*name).cloned().unwrap_or_default();
+                (*flag, *name, DirectionalLimiter::new(&limit))
+            })
+            .collect();
+
+        Self { global, sessions }
+    }
+
+    /// Apply the limits of reloaded settings to the running limiters.
+    /// Traffic totals are kept.
+    pub fn reload(&self, settings: &Settings) {
+        self.global.upload.set_limit(settings.upload_limit);
+        self.global.download.set_limit(settings.download_limit);
+
+        for (_, name, limiter) in &self.sessions {
+            let limit = settings.session_bandwidth.get(*name).cloned().unwrap_or_default();
+            limiter.upload.set_limit(limit.upload_limit);
+            limiter.download.set_limit(limit.download_limit);
+        }
+    }
+
+    fn session(&self, session: SessionBitFlag) -> Option<&DirectionalLimiter> {
+        self.sessions.iter().find(|(flag, _, _)| *flag == session).map(|(_, _, limiter)| limiter)
+    }
+
+    /// Wait until `bytes` can be sent on a channel of the given session.
+    pub async fn throttle_upload(
+        &self,
+        session: SessionBitFlag,
+        bytes: usize,
+        priority: TrafficPriority,
+    ) {
+        if let Some(limiter) = self.session(session) {
+            limiter.upload.acquire(bytes, priority).await;
+        }
+        self.global.upload.acquire(bytes, priority).await;
+    }
+
+    /// Account `bytes` received on a channel of the given session and
+    /// wait before reading more if a download limit is exceeded.
+    pub async fn throttle_download(&self, session: SessionBitFlag, bytes: usize) {
+        if let Some(limiter) = self.session(session) {
+            limiter.download.acquire(bytes, TrafficPriority::Normal).await;
+        }
+        self.global.download.acquire(bytes, TrafficPriority::Normal).await;
+    }
+
+    /// Snapshot of the global and per-session traffic
+    pub fn stats(&self) -> Vec<BandwidthStats> {
+        let mut stats = vec![self.global.stats("global")];
+        for (_, name, limiter) in &self.sessions {
+            stats.push(limiter.stats(name));
+        }
+        stats
+    }
+}
+
+/// Bytes transferred over a single channel
+#[derive(Debug, Default)]
+pub struct ChannelTraffic {
+    pub bytes_sent: AtomicU64,
+    pub bytes_recv: AtomicU64,
+}
+
+/// Transport stream wrapper counting the bytes going through it
+pub(super) struct CountingStream {
+    inner: Box<dyn PtStream>,
+    traffic: Arc<ChannelTraffic>,
+}
+
+impl CountingStream {
+    pub(super) fn new(inner: Box<dyn PtStream>, traffic: Arc<ChannelTraffic>) -> Self {
+        Self { inner, traffic }
+    }
+}
+
+impl AsyncRead for CountingStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let poll = Pin::new(&mut self.inner).poll_read(cx, buf);
+        if let Poll::Ready(Ok(n)) = poll {
+            self.traffic.bytes_recv.fetch_add(n as u64, Ordering::Relaxed);
+        }
+        poll
+    }
+}
+
+impl AsyncWrite for CountingStream {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let poll = Pin::new(&mut self.inner).poll_write(cx, buf);
+        if let Poll::Ready(Ok(n)) = poll {
+            self.traffic.bytes_sent.fetch_add(n as u64, Ordering::Relaxed);
+        }
+        poll
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_close(cx)
+    }
+}
+
+impl PtStream for CountingStream {
+    fn channel_binding(&self) -> Option<[u8; 32]> {
+        self.inner.channel_binding()
+    }
+}
@@ -20,7 +20,10 @@ use std::{
     collections::HashMap,
     fmt,
     sync::{
-        atomic::{AtomicBool, Ordering::SeqCst},
+        atomic::{
+            AtomicBool,
+            Ordering::{Relaxed, SeqCst},
+        },
         Arc,
     },
     time::{Duration, UNIX_EPOCH},
@@ -39,8 +42,10 @@ use tracing::{debug, trace};
 use url::Url;
 
 use super::{
+    bandwidth::{ChannelTraffic, CountingStream},
     dnet::{self, dnetev, DnetEvent},
     hosts::{HostColor, HostsPtr},
+    identity::NodeKey,
     message,
     message::{SerializedMessage, VersionMessage, MAX_COMMAND_LENGTH},
     message_publisher::{MessageSubscription, MessageSubsystem},
@@ -112,11 +117,19 @@ pub struct Channel {
     /// Some if the version exchange has already occurred, None
     /// otherwise.
     pub version: OnceCell<Arc<VersionMessage>>,
+    /// Authenticated node key of the peer. Some if the peer proved
+    /// ownership of its node identity during the handshake.
+    peer_key: OnceCell<NodeKey>,
+    /// Keying material of the transport's encrypted session, if any.
+    /// Signed in the node identity handshake.
+    channel_binding: Option<[u8; 32]>,
     /// Channel debug info
     pub info: ChannelInfo,
     /// Map holding a `MeteringQueue` for each [`crate::net::Message`]
     /// to perform rate limiting of propagation towards the stream.
     metering_map: AsyncMutex<HashMap<String, MeteringQueue>>,
+    /// Bytes sent and received over the transport stream
+    pub traffic: Arc<ChannelTraffic>,
 }
 
 impl Channel {
@@ -130,6 +143,9 @@ impl Channel {
         session: SessionWeakPtr,
         transport_mixed: bool,
     ) -> Arc<Self> {
+        let traffic = Arc::new(ChannelTraffic::default());
+        let channel_binding = stream.channel_binding();
+        let stream: Box<dyn PtStream> = Box::new(CountingStream::new(stream, traffic.clone()));
         let (reader, writer) = io::split(stream);
         let reader = AsyncMutex::new(reader);
         let writer = AsyncMutex::new(writer);
@@ -153,8 +169,11 @@ impl Channel {
             started: AtomicBool::new(false),
             session,
             version: OnceCell::new(),
+            peer_key: OnceCell::new(),
+            channel_binding,
             info,
             metering_map,
+            traffic,
         })
     }
 
@@ -166,6 +185,7 @@ impl Channel {
         subsystem.add_dispatch::<message::PongMessage>().await;
         subsystem.add_dispatch::<message::GetAddrsMessage>().await;
         subsystem.add_dispatch::<message::AddrsMessage>().await;
+        subsystem.add_dispatch::<message::NodeIdentityMessage>().await;
     }
 
     /// Starts the channel. Runs a receive loop to start receiving messages
@@ -338,6 +358,12 @@ impl Channel {
             msleep(sleep_time).await;
         }
 
+        // Wait for our turn within the configured bandwidth limits
+        self.p2p()
+            .bandwidth()
+            .throttle_upload(self.session_type_id(), message.wire_size(), message.priority)
+            .await;
+
         // Check if the channel is stopped, so we can abort
         if self.is_stopped() {
             return Err(Error::ChannelStopped)
@@ -502,6 +528,10 @@ impl Channel {
         // Acquire reader lock
         let reader = &mut *self.reader.lock().await;
 
+        // Bytes received up to the previous message, used to account
+        // each message against the download limits.
+        let mut bytes_recv = self.traffic.bytes_recv.load(Relaxed);
+
         // Run loop
         loop {
             let command = match self.read_command(reader).await {
@@ -580,6 +610,12 @@ impl Channel {
                 }
                 Err(_) => unreachable!("You added a new error in notify()"),
             }
+
+            // Delay reading the next message if we are over the download limits
+            let received = self.traffic.bytes_recv.load(Relaxed);
+            let bytes = (received - bytes_recv) as usize;
+            bytes_recv = received;
+            self.p2p().bandwidth().throttle_download(self.session_type_id(), bytes).await;
         }
     }
 
@@ -681,6 +717,23 @@ impl Channel {
         self.version.get().unwrap().clone()
     }
 
+    /// Set the authenticated node key of the peer. Called by
+    /// `ProtocolVersion` after verifying the node identity.
+    pub(crate) async fn set_peer_key(&self, node_key: NodeKey) {
+        self.peer_key.set(node_key).await.unwrap();
+    }
+
+    /// Returns the authenticated node key of the peer, if it has one.
+    pub fn peer_key(&self) -> Option<NodeKey> {
+        self.peer_key.get().copied()
+    }
+
+    /// Returns the keying material binding this channel to the
+    /// transport's encrypted session, if it has one.
+    pub(crate) fn channel_binding(&self) -> Option<[u8; 32]> {
+        self.channel_binding
+    }
+
     /// Returns the inner [`MessageSubsystem`] reference
     pub fn message_subsystem(&self) -> &MessageSubsystem {
         &self.message_subsystem
@@ -156,6 +156,7 @@ impl Connector {
         let settings = self.settings.read().await;
         let datastore = settings.p2p_datastore.clone();
         let i2p_socks5_proxy = settings.i2p_socks5_proxy.clone();
+        let inbound_addrs = settings.inbound_addrs.clone();
 
         let endpoints = HostContainer::resolve_dial_endpoints(
             url,
@@ -194,13 +195,18 @@ impl Connector {
             let datastore = datastore.clone();
             let i2p_socks5_proxy = i2p_socks5_proxy.clone();
             let canonical = canonical.clone();
+            // Originate from our own inbound address on the same transport, if any
+            let local_addr =
+                inbound_addrs.iter().find(|addr| addr.scheme() == endpoint.scheme()).cloned();
             async move {
                 verbose!(
                     target: "net::connector::connect",
                     "[P2P] Connecting {}",
                     route_description(&canonical, &endpoint),
                 );
-                let dialer = Dialer::new(endpoint, datastore, Some(i2p_socks5_proxy), true).await?;
+                let dialer = Dialer::new(endpoint, datastore, Some(i2p_socks5_proxy), true)
+                    .await?
+                    .with_local_addr(local_addr);
                 dialer.dial(Some(timeout)).await
             }
         })
@@ -18,7 +18,7 @@
 
 use url::Url;
 
-use super::channel::ChannelInfo;
+use super::{bandwidth::BandwidthStats, channel::ChannelInfo, identity::NodeKey};
 use crate::util::time::NanoTimestamp;
 
 macro_rules! dnetev {
@@ -103,6 +103,18 @@ pub struct DirectDisconnected {
 
 pub type DirectPeerDiscovery = OutboundPeerDiscovery;
 
+#[derive(Clone, Debug)]
+pub struct PeerAuthenticated {
+    pub addr: Url,
+    pub channel_id: u32,
+    pub node_key: NodeKey,
+}
+
+#[derive(Clone, Debug)]
+pub struct BandwidthUpdate {
+    pub stats: Vec<BandwidthStats>,
+}
+
 #[derive(Clone, Debug)]
 pub enum DnetEvent {
     SendMessage(MessageInfo),
@@ -118,4 +130,6 @@ pub enum DnetEvent {
     DirectConnected(DirectConnected),
     DirectDisconnected(DirectDisconnected),
     DirectPeerDiscovery(DirectPeerDiscovery),
+    PeerAuthenticated(PeerAuthenticated),
+    BandwidthUpdate(BandwidthUpdate),
 }
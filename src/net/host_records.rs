@@ -0,0 +1,542 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2026 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Persistent connection history of known hosts.
+//!
+//! The hostlists only remember when a host was last seen. `HostRecords`
+//! keeps track of what happened every time we dialed a host: how often
+//! the handshake succeeded or failed and why, the average ping latency,
+//! how long connections stayed up, the transport the host was reached
+//! over and the app version the host advertised. The records are saved next
+//! to the hostlist (`<hostlist>.records`) so they survive restarts.
+//!
+//! Each record yields a score in `(0, 1]` which the outbound session uses
+//! to weight its selection of known hosts. Selection stays randomized so
+//! that hosts with a short history still get picked from time to time.
+
+use std::{
+    collections::HashMap,
+    fs,
+    fs::File,
+    time::{Duration, UNIX_EPOCH},
+};
+
+use parking_lot::RwLock;
+use rand::{rngs::OsRng, Rng};
+use url::Url;
+
+use crate::{
+    util::{
+        file::{load_file, save_file},
+        logger::verbose,
+        path::expand_path,
+    },
+    Result,
+};
+
+/// Maximum number of records kept. The least recently seen hosts are
+/// evicted first.
+pub const HOST_RECORDS_MAX_LEN: usize = 10000;
+
+/// Eviction goes down to this many records, so that the records don't
+/// get sorted again on every subsequent insertion.
+const HOST_RECORDS_TRIM_LEN: usize = 9000;
+
+/// Records of hosts we haven't seen for this long are dropped on load.
+const HOST_RECORD_MAX_AGE: u64 = 30 * 86400;
+
+/// Weight of a new ping sample in the latency moving average
+const LATENCY_SMOOTHING: f64 = 0.2;
+
+/// Failure reasons are truncated to this many characters
+const FAILURE_REASON_MAX_LEN: usize = 128;
+
+/// Lowest possible score, so that no known host is excluded entirely
+const MIN_SCORE: f64 = 0.01;
+
+/// Average connection uptime in seconds at which a host counts as
+/// half-way stable
+const STABLE_UPTIME: f64 = 600.0;
+
+/// Connection history of a single host.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct HostRecord {
+    /// When we first dialed this host
+    pub first_seen: u64,
+    /// When we last dialed or pinged this host
+    pub last_seen: u64,
+    /// When we last completed a handshake with this host, 0 if never
+    pub last_connected: u64,
+    /// Number of successful handshakes
+    pub successes: u64,
+    /// Number of failed connection attempts
+    pub failures: u64,
+    /// Failed attempts since the last successful handshake
+    pub consecutive_failures: u32,
+    /// Reason of the last failed attempt
+    pub last_failure: Option<String>,
+    /// Moving average of the ping round trip time in milliseconds
+    pub avg_latency_ms: Option<u64>,
+    /// App name advertised in the version message
+    pub app_name: Option<String>,
+    /// App version advertised in the version message
+    pub app_version: Option<String>,
+    /// Total number of seconds we stayed connected to this host
+    pub uptime: u64,
+    /// Number of connections to this host that have since closed
+    pub disconnects: u64,
+    /// Transport scheme the host was last reached over
+    pub transport: Option<String>,
+}
+
+impl HostRecord {
+    fn new(now: u64) -> Self {
+        Self { first_seen: now, last_seen: now, ..Default::default() }
+    }
+
+    /// Score of this host in `(0, 1]`, higher is better.
+    ///
+    /// Combines the handshake success ratio (smoothed so that a single
+    /// attempt doesn't dominate), the ping latency, the average uptime of
+    /// closed connections, and a penalty halving the score for every
+    /// consecutive failure.
+    pub fn score(&self) -> f64 {
+        let attempts = (self.successes + self.failures) as f64;
+        let reliability = (self.successes as f64 + 1.0) / (attempts + 2.0);
+
+        let latency = match self.avg_latency_ms {
+            Some(ms) => 1000.0 / (1000.0 + ms as f64),
+            None => 0.5,
+        };
+
+        let stability = match self.disconnects {
+            0 => 0.5,
+            n => {
+                let avg_uptime = self.uptime as f64 / n as f64;
+                avg_uptime / (avg_uptime + STABLE_UPTIME)
+            }
+        };
+
+        let streak = 0.5f64.powi(self.consecutive_failures.min(16) as i32);
+
+        (reliability * (0.5 + 0.5 * latency) * (0.5 + 0.5 * stability) * streak).max(MIN_SCORE)
+    }
+
+    /// Serialize into a TSV line, without the trailing newline
+    fn to_tsv(&self, addr: &Url) -> String {
+        let opt = |s: &Option<String>| s.as_deref().map(sanitize).unwrap_or_default();
+        format!(
+            "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+            addr,
+            self.first_seen,
+            self.last_seen,
+            self.last_connected,
+            self.successes,
+            self.failures,
+            self.consecutive_failures,
+            self.avg_latency_ms.map(|ms| ms.to_string()).unwrap_or_default(),
+            opt(&self.app_name),
+            opt(&self.app_version),
+            opt(&self.last_failure),
+            self.uptime,
+            self.disconnects,
+            opt(&self.transport),
+        )
+    }
+
+    /// Parse a TSV line written by [`HostRecord::to_tsv`]. Lines written
+    /// before uptime and transports were tracked have 11 fields.
+    fn from_tsv(line: &str) -> Option<(Url, Self)> {
+        let parts: Vec<&str> = line.split('\t').collect();
+        if parts.len() != 11 && parts.len() != 14 {
+            return None
+        }
+
+        let opt = |s: &str| (!s.is_empty()).then(|| s.to_string());
+
+        let addr = Url::parse(parts[0]).ok()?;
+        let record = Self {
+            first_seen: parts[1].parse().ok()?,
+            last_seen: parts[2].parse().ok()?,
+            last_connected: parts[3].parse().ok()?,
+            successes: parts[4].parse().ok()?,
+            failures: parts[5].parse().ok()?,
+            consecutive_failures: parts[6].parse().ok()?,
+            avg_latency_ms: match parts[7] {
+                "" => None,
+                ms => Some(ms.parse().ok()?),
+            },
+            app_name: opt(parts[8]),
+            app_version: opt(parts[9]),
+            last_failure: opt(parts[10]),
+            uptime: parts.get(11).map_or(Some(0), |s| s.parse().ok())?,
+            disconnects: parts.get(12).map_or(Some(0), |s| s.parse().ok())?,
+            transport: parts.get(13).and_then(|s| opt(s)),
+        };
+
+        Some((addr, record))
+    }
+}
+
+/// Strip characters that would break the TSV format and bound the length
+fn sanitize(s: &str) -> String {
+    s.chars().map(|c| if c.is_control() { ' ' } else { c }).take(FAILURE_REASON_MAX_LEN).collect()
+}
+
+/// Connection history of all the hosts we dialed.
+#[derive(Default)]
+pub struct HostRecords {
+    records: RwLock<HashMap<Url, HostRecord>>,
+}
+
+impl HostRecords {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the record of a host
+    pub fn get(&self, addr: &Url) -> Option<HostRecord> {
+        self.records.read().get(addr).cloned()
+    }
+
+    /// Get all records
+    pub fn fetch_all(&self) -> Vec<(Url, HostRecord)> {
+        self.records.read().iter().map(|(addr, record)| (addr.clone(), record.clone())).collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.records.read().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.read().is_empty()
+    }
+
+    /// Score of a host. Hosts without a record get the score of a
+    /// fresh record.
+    pub fn score(&self, addr: &Url) -> f64 {
+        match self.records.read().get(addr) {
+            Some(record) => record.score(),
+            None => HostRecord::default().score(),
+        }
+    }
+
+    /// Apply `f` to the record of `addr`, creating it if needed
+    fn update(&self, addr: &Url, f: impl FnOnce(&mut HostRecord, u64)) {
+        let now = UNIX_EPOCH.elapsed().unwrap().as_secs();
+        let mut records = self.records.write();
+
+        let record = records.entry(addr.clone()).or_insert_with(|| HostRecord::new(now));
+        record.last_seen = now;
+        f(record, now);
+
+        if records.len() > HOST_RECORDS_MAX_LEN {
+            Self::trim(&mut records, HOST_RECORDS_TRIM_LEN);
+        }
+    }
+
+    /// Record a successful handshake along with the transport scheme the
+    /// host was reached over and the app it advertised
+    pub fn record_success(&self, addr: &Url, transport: &str, app_name: &str, app_version: &str) {
+        self.update(addr, |record, now| {
+            record.last_connected = now;
+            record.successes += 1;
+            record.consecutive_failures = 0;
+            record.transport = Some(sanitize(transport));
+            record.app_name = Some(sanitize(app_name));
+            record.app_version = Some(sanitize(app_version));
+        });
+    }
+
+    /// Record a failed connection attempt
+    pub fn record_failure(&self, addr: &Url, reason: &str) {
+        self.update(addr, |record, _| {
+            record.failures += 1;
+            record.consecutive_failures = record.consecutive_failures.saturating_add(1);
+            record.last_failure = Some(sanitize(reason));
+        });
+    }
+
+    /// Record how long a connection to the host stayed up once it closes.
+    /// Only hosts we hold a record for are tracked.
+    pub fn record_disconnect(&self, addr: &Url, uptime: Duration) {
+        if let Some(record) = self.records.write().get_mut(addr) {
+            record.uptime = record.uptime.saturating_add(uptime.as_secs());
+            record.disconnects += 1;
+        }
+    }
+
+    /// Record a ping round trip time
+    pub fn record_latency(&self, addr: &Url, latency: Duration) {
+        let sample = latency.as_millis() as f64;
+        self.update(addr, |record, _| {
+            let avg = match record.avg_latency_ms {
+                Some(avg) => avg as f64 * (1.0 - LATENCY_SMOOTHING) + sample * LATENCY_SMOOTHING,
+                None => sample,
+            };
+            record.avg_latency_ms = Some(avg.round() as u64);
+        });
+    }
+
+    /// Randomly select up to `n` of the given hosts, weighted by their
+    /// score.
+    ///
+    /// Uses weighted sampling without replacement: every host draws a key
+    /// `u^(1/score)` with `u` uniform in `(0, 1)`, and the `n` highest keys
+    /// are selected.
+    pub fn weighted_sample(&self, hosts: Vec<(Url, u64)>, n: usize) -> Vec<(Url, u64)> {
+        let records = self.records.read();
+        let mut keyed: Vec<(f64, (Url, u64))> = hosts
+            .into_iter()
+            .map(|host| {
+                let score = match records.get(&host.0) {
+                    Some(record) => record.score(),
+                    None => HostRecord::default().score(),
+                };
+                let u: f64 = OsRng.gen_range(f64::EPSILON..1.0);
+                (u.powf(1.0 / score), host)
+            })
+            .collect();
+        drop(records);
+
+        keyed.sort_unstable_by(|a, b| b.0.total_cmp(&a.0));
+        keyed.into_iter().take(n).map(|(_, host)| host).collect()
+    }
+
+    /// Evict the least recently seen records until at most `max_len` remain
+    fn trim(records: &mut HashMap<Url, HostRecord>, max_len: usize) {
+        if records.len() <= max_len {
+            return
+        }
+
+        let mut by_age: Vec<(Url, u64)> =
+            records.iter().map(|(addr, record)| (addr.clone(), record.last_seen)).collect();
+        by_age.sort_unstable_by_key(|(_, last_seen)| *last_seen);
+
+        let remove_count = records.len() - max_len;
+        for (addr, _) in by_age.into_iter().take(remove_count) {
+            records.remove(&addr);
+        }
+    }
+
+    /// Path of the records file belonging to a hostlist
+    pub fn path_for(hostlist: &str) -> String {
+        format!("{hostlist}.records")
+    }
+
+    pub fn load_all(&self, path: &str) -> Result<()> {
+        let path = expand_path(path)?;
+
+        if !path.exists() {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            File::create(path.clone())?;
+        }
+
+        let contents = match load_file(&path) {
+            Ok(c) => c,
+            Err(e) => {
+                verbose!(target: "net::host_records::load_all", "[P2P] Failed retrieving host records: {e}");
+                return Ok(())
+            }
+        };
+
+        let now = UNIX_EPOCH.elapsed().unwrap().as_secs();
+        let mut records = self.records.write();
+
+        for line in contents.lines() {
+            let Some((addr, record)) = HostRecord::from_tsv(line) else { continue };
+            if now.saturating_sub(record.last_seen) > HOST_RECORD_MAX_AGE {
+                continue
+            }
+            records.insert(addr, record);
+        }
+
+        Self::trim(&mut records, HOST_RECORDS_MAX_LEN);
+
+        Ok(())
+    }
+
+    pub fn save_all(&self, path: &str) -> Result<()> {
+        let path = expand_path(path)?;
+        let records = self.records.read();
+
+        let mut tsv = String::new();
+        for (addr, record) in records.iter() {
+            tsv.push_str(&record.to_tsv(addr));
+            tsv.push('\n');
+        }
+
+        if !tsv.is_empty() {
+            verbose!(target: "net::host_records::save_all", "[P2P] Saving host records to: {path:?}");
+            if let Err(e) = save_file(&path, &tsv) {
+                verbose!(target: "net::host_records::save_all", "[P2P] Failed saving host records: {e}");
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url(host: &str) -> Url {
+        Url::parse(&format!("tcp://{host}:123")).unwrap()
+    }
+
+    #[test]
+    fn test_record_history() {
+        let records = HostRecords::new();
+        let addr = url("alice.com");
+
+        records.record_failure(&addr, "Connection refused");
+        records.record_failure(&addr, "Connection refused");
+        let record = records.get(&addr).unwrap();
+        assert_eq!(record.failures, 2);
+        assert_eq!(record.consecutive_failures, 2);
+        assert_eq!(record.last_connected, 0);
+        assert_eq!(record.last_failure.as_deref(), Some("Connection refused"));
+
+        records.record_success(&addr, "tcp+tls", "darkirc", "0.5.0");
+        let record = records.get(&addr).unwrap();
+        assert_eq!(record.successes, 1);
+        assert_eq!(record.consecutive_failures, 0);
+        assert!(record.last_connected > 0);
+        assert_eq!(record.app_name.as_deref(), Some("darkirc"));
+        assert_eq!(record.app_version.as_deref(), Some("0.5.0"));
+        assert_eq!(record.transport.as_deref(), Some("tcp+tls"));
+
+        records.record_disconnect(&addr, Duration::from_secs(300));
+        records.record_disconnect(&addr, Duration::from_secs(100));
+        let record = records.get(&addr).unwrap();
+        assert_eq!(record.uptime, 400);
+        assert_eq!(record.disconnects, 2);
+
+        records.record_latency(&addr, Duration::from_millis(100));
+        assert_eq!(records.get(&addr).unwrap().avg_latency_ms, Some(100));
+        records.record_latency(&addr, Duration::from_millis(200));
+        assert_eq!(records.get(&addr).unwrap().avg_latency_ms, Some(120));
+    }
+
+    #[test]
+    fn test_score_ordering() {
+        let records = HostRecords::new();
+        let (good, slow, flaky) = (url("good.com"), url("slow.com"), url("flaky.com"));
+
+        for _ in 0..10 {
+            records.record_success(&good, "tcp+tls", "app", "1.0.0");
+            records.record_success(&slow, "tcp+tls", "app", "1.0.0");
+        }
+        records.record_latency(&good, Duration::from_millis(50));
+        records.record_latency(&slow, Duration::from_millis(3000));
+
+        records.record_success(&flaky, "tcp+tls", "app", "1.0.0");
+        for _ in 0..3 {
+            records.record_failure(&flaky, "timeout");
+        }
+
+        let unknown = records.score(&url("unknown.com"));
+        assert!(records.score(&good) > records.score(&slow));
+        assert!(records.score(&slow) > unknown);
+        assert!(unknown > records.score(&flaky));
+        assert!(records.score(&flaky) >= MIN_SCORE);
+
+        // Hosts whose connections stay up score higher than hosts that
+        // keep dropping us
+        let (stable, unstable) = (url("stable.com"), url("unstable.com"));
+        for _ in 0..10 {
+            records.record_success(&stable, "tcp+tls", "app", "1.0.0");
+            records.record_disconnect(&stable, Duration::from_secs(3600));
+            records.record_success(&unstable, "tcp+tls", "app", "1.0.0");
+            records.record_disconnect(&unstable, Duration::from_secs(5));
+        }
+        records.record_latency(&stable, Duration::from_millis(50));
+        records.record_latency(&unstable, Duration::from_millis(50));
+        assert!(records.score(&stable) > records.score(&good));
+        assert!(records.score(&good) > records.score(&unstable));
+    }
+
+    #[test]
+    fn test_weighted_sample() {
+        let records = HostRecords::new();
+        let good = url("good.com");
+        let bad = url("bad.com");
+
+        for _ in 0..20 {
+            records.record_success(&good, "tcp+tls", "app", "1.0.0");
+            records.record_failure(&bad, "refused");
+        }
+
+        let hosts = vec![(good.clone(), 1), (bad.clone(), 2)];
+        assert_eq!(records.weighted_sample(hosts.clone(), 5).len(), 2);
+
+        let mut picked_good = 0;
+        for _ in 0..100 {
+            let sample = records.weighted_sample(hosts.clone(), 1);
+            assert_eq!(sample.len(), 1);
+            if sample[0].0 == good {
+                picked_good += 1;
+            }
+        }
+        assert!(picked_good > 90);
+    }
+
+    #[test]
+    fn test_tsv_roundtrip() {
+        let records = HostRecords::new();
+        let addr = url("alice.com");
+        records.record_success(&addr, "tcp+tls", "darkirc", "0.5.0");
+        records.record_failure(&addr, "bad\tchars\nhere");
+        records.record_disconnect(&addr, Duration::from_secs(42));
+
+        let record = records.get(&addr).unwrap();
+        let line = record.to_tsv(&addr);
+        let (parsed_addr, parsed) = HostRecord::from_tsv(&line).unwrap();
+        assert_eq!(parsed_addr, addr);
+        assert_eq!(parsed, record);
+        assert_eq!(parsed.last_failure.as_deref(), Some("bad chars here"));
+        assert_eq!(parsed.avg_latency_ms, None);
+        assert_eq!(parsed.uptime, 42);
+        assert_eq!(parsed.transport.as_deref(), Some("tcp+tls"));
+
+        // Lines saved before uptime and transports were tracked still load
+        let legacy = line.rsplitn(4, '\t').last().unwrap();
+        let (_, parsed) = HostRecord::from_tsv(legacy).unwrap();
+        assert_eq!(parsed.successes, record.successes);
+        assert_eq!((parsed.uptime, parsed.disconnects, parsed.transport), (0, 0, None));
+
+        // Hostlist lines are not records
+        assert!(HostRecord::from_tsv("gold\ttcp://alice.com:123\t1720000000").is_none());
+    }
+
+    #[test]
+    fn test_trim_evicts_oldest() {
+        let mut map = HashMap::new();
+        for i in 0..10u64 {
+            let record = HostRecord { last_seen: i, ..Default::default() };
+            map.insert(url(&format!("host{i}.com")), record);
+        }
+
+        HostRecords::trim(&mut map, 5);
+        assert_eq!(map.len(), 5);
+        assert!(map.values().all(|record| record.last_seen >= 5));
+    }
+}
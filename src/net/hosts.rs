@@ -34,6 +34,18 @@
 //! - `Black`: Hostile hosts, blocked for the program duration.
 //! - `Dark`: Hosts with unsupported transports. Shared with peers but not used locally.
 //!   Cleared daily to avoid propagating stale entries.
+//!
+//! # Netgroups
+//!
+//! Addresses are bucketed by `NetGroup` (IPv4 /16, IPv6 /32, domain).
+//! The greylist holds a bounded number of entries per netgroup, refinery
+//! feelers pick a netgroup before a host, and outbound connections per
+//! netgroup are limited, so that an attacker controlling a single network
+//! can't eclipse a node.
+//!
+//! Onion, i2p and nym addresses cost nothing to generate, so each one is
+//! its own netgroup. The greylist instead caps the number of entries per
+//! overlay transport, and feelers treat each transport as one netgroup.
 
 use parking_lot::{Mutex, RwLock};
 use rand::{prelude::IteratorRandom, rngs::OsRng, Rng};
@@ -53,7 +65,8 @@ use tracing::debug;
 use url::{Host, Url};
 
 use super::{
-    session::{SESSION_REFINE, SESSION_SEED},
+    host_records::HostRecords,
+    session::{SESSION_INBOUND, SESSION_OUTBOUND, SESSION_REFINE, SESSION_SEED},
     settings::Settings,
     ChannelPtr,
 };
@@ -74,6 +87,126 @@ use crate::{
 pub const SHAREABLE_SCHEMES: [&str; 9] =
     ["tor", "tls", "tcp", "nym", "i2p", "tor+tls", "nym+tls", "tcp+tls", "i2p+tls"];
 
+/// Check whether addresses with the given scheme may be exchanged through
+/// peer discovery. The in-memory transport is shareable when enabled, so
+/// simulated networks can bootstrap over seeds.
+pub fn is_shareable_scheme(scheme: &str) -> bool {
+    #[cfg(feature = "p2p-memory")]
+    if scheme == "mem" {
+        return true
+    }
+
+    SHAREABLE_SCHEMES.contains(&scheme)
+}
+
+/// Network group of a host address.
+///
+/// Addresses in the same netgroup are likely to be controlled by the same
+/// operator, so we bound how many of them we store and connect to. This
+/// makes it expensive for an attacker to eclipse a node, as they need
+/// addresses from many different networks rather than many addresses.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum NetGroup {
+    /// IPv4 /16 prefix
+    Ipv4([u8; 2]),
+    /// IPv6 /32 prefix
+    Ipv6([u16; 2]),
+    /// A single Tor onion service
+    Onion(String),
+    /// A single I2P destination
+    I2p(String),
+    /// A single Nym address
+    Nym(String),
+    /// Domain names, grouped by their last two labels
+    Domain(String),
+}
+
+impl NetGroup {
+    /// Compute the netgroup of an address. Returns `None` for local and
+    /// private addresses, which aren't subject to netgroup limits.
+    pub fn of(url: &Url) -> Option<Self> {
+        if url.scheme().starts_with("nym") {
+            return url.host_str().map(|host| NetGroup::Nym(host.to_string()))
+        }
+
+        match url.host()? {
+            Host::Ipv4(ip) => Self::of_ipv4(ip),
+            Host::Ipv6(ip) => match ip.to_ipv4_mapped() {
+                Some(ip) => Self::of_ipv4(ip),
+                None => {
+                    if !ip.unstable_is_global() {
+                        return None
+                    }
+                    let segments = ip.segments();
+                    Some(NetGroup::Ipv6([segments[0], segments[1]]))
+                }
+            },
+            Host::Domain(domain) => {
+                if LOCAL_HOST_STRS.contains(&domain) {
+                    return None
+                }
+                if domain.ends_with(".onion") {
+                    return Some(NetGroup::Onion(domain.to_string()))
+                }
+                if domain.ends_with(".i2p") {
+                    return Some(NetGroup::I2p(domain.to_string()))
+                }
+
+                let labels: Vec<&str> = domain.rsplitn(3, '.').collect();
+                let group = match labels.as_slice() {
+                    [tld, name, ..] => format!("{name}.{tld}"),
+                    _ => domain.to_string(),
+                };
+                Some(NetGroup::Domain(group))
+            }
+        }
+    }
+
+    fn of_ipv4(ip: Ipv4Addr) -> Option<Self> {
+        if !ip.unstable_is_global() {
+            return None
+        }
+        let octets = ip.octets();
+        Some(NetGroup::Ipv4([octets[0], octets[1]]))
+    }
+
+    /// Overlay transport of the address, if it's an onion, i2p or nym one
+    pub fn overlay(&self) -> Option<&'static str> {
+        match self {
+            NetGroup::Onion(_) => Some("tor"),
+            NetGroup::I2p(_) => Some("i2p"),
+            NetGroup::Nym(_) => Some("nym"),
+            _ => None,
+        }
+    }
+}
+
+/// Bucket of addresses sharing a greylist cap: the netgroup of the
+/// address, or its whole transport for overlay addresses.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+enum HostBucket {
+    NetGroup(NetGroup),
+    Overlay(&'static str),
+}
+
+impl HostBucket {
+    fn of(url: &Url) -> Option<Self> {
+        let group = NetGroup::of(url)?;
+        match group.overlay() {
+            Some(transport) => Some(HostBucket::Overlay(transport)),
+            None => Some(HostBucket::NetGroup(group)),
+        }
+    }
+
+    /// Maximum number of greylist entries in this bucket
+    fn max_len(&self) -> usize {
+        match self {
+            HostBucket::NetGroup(_) => GREYLIST_NETGROUP_MAX_LEN,
+            HostBucket::Overlay(_) => GREYLIST_OVERLAY_MAX_LEN,
+        }
+    }
+}
+
 pub const LOCAL_HOST_STRS: [&str; 2] = ["localhost", "localhost.localdomain"];
 
 const WHITELIST_MAX_LEN: usize = 5000;
@@ -81,6 +214,14 @@ const GREYLIST_MAX_LEN: usize = 2000;
 const DARKLIST_MAX_LEN: usize = 1000;
 const BLACKLIST_MAX_LEN: usize = 10000;
 
+/// Maximum number of greylist entries from the same netgroup. Addresses
+/// we learn from peers can't crowd out other networks beyond this.
+pub const GREYLIST_NETGROUP_MAX_LEN: usize = 32;
+
+/// Maximum number of greylist entries from the same overlay transport
+/// (tor, i2p, nym), whose addresses are each their own netgroup.
+pub const GREYLIST_OVERLAY_MAX_LEN: usize = 500;
+
 /// How long a host can remain in Free state before being pruned from the registry.
 /// 24 hours is appropriate for long-running daemons.
 const REGISTRY_PRUNE_AGE_SECS: u64 = 86400;
@@ -213,6 +354,11 @@ impl HostColor {
         }
     }
 
+    /// Whether the number of entries per [`HostBucket`] is capped
+    fn is_bucketed(self) -> bool {
+        self == HostColor::Grey
+    }
+
     fn name(self) -> &'static str {
         match self {
             HostColor::Grey => "grey",
@@ -266,14 +412,59 @@ impl HostContainer {
     }
 
     /// Store, sort by last_seen (descending), and enforce max size.
-    pub fn store_and_trim(&self, color: HostColor, addr: Url, last_seen: u64) {
+    ///
+    /// On lists with per-netgroup limits, a new address from a full
+    /// netgroup replaces the oldest entry of that netgroup, or is dropped
+    /// if it's older than all of them, in which case this returns `false`.
+    pub fn store_and_trim(&self, color: HostColor, addr: Url, last_seen: u64) -> bool {
+        self.store_and_trim_many(color, vec![(addr, last_seen)]) == 1
+    }
+
+    /// Store a batch of addresses like [`Self::store_and_trim()`], sorting
+    /// and bucketing the list once. Returns the number of addresses stored.
+    pub fn store_and_trim_many(&self, color: HostColor, addrs: Vec<(Url, u64)>) -> usize {
         let mut lists = self.lists.write();
         let list = &mut lists[color as usize];
 
-        if let Some(entry) = list.iter_mut().find(|(u, _)| *u == addr) {
-            entry.1 = last_seen;
-        } else {
-            list.push((addr, last_seen));
+        // Indices of the entries of each bucket. Evicted entries are
+        // overwritten in place, so the indices stay valid.
+        let mut buckets: HashMap<HostBucket, Vec<usize>> = HashMap::new();
+        if color.is_bucketed() {
+            for (i, (url, _)) in list.iter().enumerate() {
+                if let Some(bucket) = HostBucket::of(url) {
+                    buckets.entry(bucket).or_default().push(i);
+                }
+            }
+        }
+
+        let mut stored = 0;
+        for (addr, last_seen) in addrs {
+            if let Some(entry) = list.iter_mut().find(|(u, _)| *u == addr) {
+                entry.1 = last_seen;
+                stored += 1;
+                continue
+            }
+
+            let bucket = if color.is_bucketed() { HostBucket::of(&addr) } else { None };
+            let Some(bucket) = bucket else {
+                list.push((addr, last_seen));
+                stored += 1;
+                continue
+            };
+
+            let max = bucket.max_len();
+            let members = buckets.entry(bucket).or_default();
+            if members.len() >= max {
+                let oldest = *members.iter().min_by_key(|i| list[**i].1).unwrap();
+                if list[oldest].1 >= last_seen {
+                    continue
+                }
+                list[oldest] = (addr, last_seen);
+            } else {
+                members.push(list.len());
+                list.push((addr, last_seen));
+            }
+            stored += 1;
         }
 
         list.sort_by_key(|e| std::cmp::Reverse(e.1));
@@ -281,6 +472,8 @@ impl HostContainer {
         if let Some(max) = color.max_len() {
             list.truncate(max);
         }
+
+        stored
     }
 
     /// Remove an address from a hostlist if it exists.
@@ -382,6 +575,24 @@ impl HostContainer {
         Some(hosts[idx].clone())
     }
 
+    /// Get a random host matching the given transport schemes, picking a
+    /// random netgroup first so that netgroups with many entries are not
+    /// more likely to be selected. Overlay transports count as a single
+    /// netgroup each.
+    pub fn fetch_random_netgroup_with_schemes(
+        &self,
+        color: HostColor,
+        schemes: &[String],
+    ) -> Option<(Url, u64)> {
+        let mut groups: HashMap<Option<HostBucket>, Vec<(Url, u64)>> = HashMap::new();
+        for host in self.fetch_with_schemes(color, schemes, None) {
+            groups.entry(HostBucket::of(&host.0)).or_default().push(host);
+        }
+
+        let hosts = groups.into_values().choose(&mut OsRng)?;
+        hosts.into_iter().choose(&mut OsRng)
+    }
+
     /// Get up to n random hosts.
     pub fn fetch_n_random(&self, color: HostColor, n: usize) -> Vec<(Url, u64)> {
         if n == 0 {
@@ -635,7 +846,7 @@ impl HostContainer {
         for scheme in
             Self::dialable_schemes(transports, mixed_transports, tor_socks5_proxy, nym_socks5_proxy)
         {
-            if SHAREABLE_SCHEMES.contains(&scheme.as_str()) && !schemes.contains(&scheme) {
+            if is_shareable_scheme(&scheme) && !schemes.contains(&scheme) {
                 schemes.push(scheme);
             }
         }
@@ -703,6 +914,8 @@ pub struct Hosts {
     registry: Mutex<HashMap<Url, HostState>>,
     /// Hostlists and associated methods
     pub container: HostContainer,
+    /// Connection history of the hosts we dialed
+    pub records: HostRecords,
     /// Publisher listening for store updates
     store_publisher: PublisherPtr<usize>,
     /// Publisher for notifications of new channels
@@ -713,6 +926,8 @@ pub struct Hosts {
     pub(crate) last_connection: Mutex<Instant>,
     /// Marker for IPv6 availability
     pub(crate) ipv6_available: AtomicBool,
+    /// Outbound peers of the previous run, reconnected to first
+    anchors: Mutex<Vec<Url>>,
     /// Auto self discovered addresses. Used for filtering self connections.
     auto_self_addrs: Mutex<RingBuffer<Ipv6Addr, 20>>,
     /// Pointer to configured P2P settings
@@ -725,11 +940,13 @@ impl Hosts {
         Arc::new(Self {
             registry: Mutex::new(HashMap::new()),
             container: HostContainer::new(),
+            records: HostRecords::new(),
             store_publisher: Publisher::new(),
             channel_publisher: Publisher::new(),
             disconnect_publisher: Publisher::new(),
             last_connection: Mutex::new(Instant::now()),
             ipv6_available: AtomicBool::new(true),
+            anchors: Mutex::new(Vec::new()),
             auto_self_addrs: Mutex::new(RingBuffer::new()),
             settings,
         })
@@ -924,11 +1141,22 @@ impl Hosts {
             return
         }
 
-        if let Err(e) = self.try_register(address, HostState::Connected(channel.clone())) {
+        if let Err(e) = self.try_register(address.clone(), HostState::Connected(channel.clone())) {
             verbose!(target: "net::hosts::register_channel", "[P2P] Error registering channel: {e:?}");
             return
         }
 
+        // Inbound addresses are ephemeral, only keep history of hosts we dialed
+        if channel.session_type_id() & SESSION_INBOUND == 0 {
+            let version = channel.get_version();
+            self.records.record_success(
+                &address,
+                channel.info.connect_addr.scheme(),
+                &version.app_name,
+                &version.version.to_string(),
+            );
+        }
+
         self.channel_publisher.notify(Ok(channel)).await;
         *self.last_connection.lock() = Instant::now();
     }
@@ -936,16 +1164,15 @@ impl Hosts {
     /// Insert addresses into the greylist after filtering.
     pub(crate) async fn insert(&self, color: HostColor, addrs: &[(Url, u64)]) {
         let filtered = self.filter_addresses(addrs).await;
-        let mut count = 0;
 
-        for (addr, last_seen) in filtered {
-            if self.try_register(addr.clone(), HostState::Insert).is_err() {
-                continue;
-            }
+        let registered: Vec<(Url, u64)> = filtered
+            .into_iter()
+            .filter(|(addr, _)| self.try_register(addr.clone(), HostState::Insert).is_ok())
+            .collect();
 
-            self.container.store_and_trim(color, addr.clone(), last_seen);
-            let _ = self.unregister(&addr);
-            count += 1;
+        let count = self.container.store_and_trim_many(color, registered.clone());
+        for (addr, _) in &registered {
+            let _ = self.unregister(addr);
         }
 
         if count > 0 {
@@ -954,9 +1181,13 @@ impl Hosts {
     }
 
     /// Find a connectable address from the given hosts.
+    ///
+    /// Hosts from a netgroup that already has `outbound_netgroup_limit`
+    /// outbound connections (established or pending) are skipped.
     pub(crate) async fn check_addrs(&self, hosts: Vec<(Url, u64)>) -> Option<(Url, u64)> {
         let settings = self.settings.read().await;
         let seeds = &settings.seeds;
+        let netgroup_limit = settings.outbound_netgroup_limit;
         let external = self.external_addrs().await;
 
         for (host, last_seen) in hosts {
@@ -964,6 +1195,18 @@ impl Hosts {
                 continue;
             }
 
+            if netgroup_limit > 0 {
+                if let Some(group) = NetGroup::of(&host) {
+                    if self.outbound_netgroup_count(&group) >= netgroup_limit {
+                        debug!(
+                            target: "net::hosts::check_addrs",
+                            "Skipping {host}: netgroup {group:?} reached the outbound limit",
+                        );
+                        continue
+                    }
+                }
+            }
+
             if self.try_register(host.clone(), HostState::Connect).is_ok() {
                 return Some((host, last_seen))
             }
@@ -972,6 +1215,75 @@ impl Hosts {
         None
     }
 
+    /// Number of outbound connections, established or pending, to hosts
+    /// of the given netgroup.
+    pub fn outbound_netgroup_count(&self, group: &NetGroup) -> usize {
+        self.registry
+            .lock()
+            .iter()
+            .filter(|(_, state)| match state {
+                HostState::Connect => true,
+                HostState::Connected(c) => c.session_type_id() & SESSION_OUTBOUND != 0,
+                _ => false,
+            })
+            .filter(|(url, _)| NetGroup::of(url).as_ref() == Some(group))
+            .count()
+    }
+
+    /// Path of the anchors file belonging to a hostlist
+    pub fn anchors_path_for(hostlist: &str) -> String {
+        format!("{hostlist}.anchors")
+    }
+
+    /// Load the anchors saved by the previous run. Outbound slots try
+    /// to reconnect to them before anything else, so that an attacker
+    /// can't take over all of our slots across restarts. The file is
+    /// removed once loaded, so a faulty anchor isn't retried forever.
+    pub fn load_anchors(&self, path: &str) -> Result<()> {
+        let path = expand_path(path)?;
+        if !path.exists() {
+            return Ok(())
+        }
+
+        let contents = load_file(&path)?;
+        let anchors: Vec<Url> =
+            contents.lines().filter_map(|line| Url::parse(line.trim()).ok()).collect();
+        debug!(target: "net::hosts::load_anchors", "Loaded {} anchors", anchors.len());
+        *self.anchors.lock() = anchors;
+
+        fs::remove_file(path)?;
+        Ok(())
+    }
+
+    /// Save up to `count` of our current outbound peers as anchors for
+    /// the next run, preferring the ones with the best connection history.
+    pub fn save_anchors(&self, path: &str, count: usize) -> Result<()> {
+        let path = expand_path(path)?;
+
+        let mut peers: Vec<Url> = self
+            .peers()
+            .into_iter()
+            .filter(|c| c.session_type_id() & SESSION_OUTBOUND != 0)
+            .map(|c| c.address().clone())
+            .collect();
+        peers.sort_by(|a, b| self.records.score(b).total_cmp(&self.records.score(a)));
+        peers.truncate(count);
+
+        if peers.is_empty() {
+            return Ok(())
+        }
+
+        let contents: String = peers.iter().map(|url| format!("{url}\n")).collect();
+        verbose!(target: "net::hosts::save_anchors", "[P2P] Saving {} anchors to: {path:?}", peers.len());
+        save_file(&path, &contents)?;
+        Ok(())
+    }
+
+    /// Take the next anchor to reconnect to, if any remain
+    pub(crate) fn take_anchor(&self) -> Option<Url> {
+        self.anchors.lock().pop()
+    }
+
     /// Move a host to the greylist.
     pub async fn greylist_host(&self, addr: &Url, last_seen: u64) -> Result<()> {
         self.move_host(addr, last_seen, HostColor::Grey).await?;
@@ -1137,6 +1449,9 @@ impl Hosts {
             #[cfg(feature = "p2p-quic")]
             "quic" => true,
 
+            #[cfg(feature = "p2p-memory")]
+            "mem" => true,
+
             _ => false,
         }
     }
@@ -1500,6 +1815,100 @@ mod tests {
         assert!(hosts.is_blacklisted(&with_port));
     }
 
+    #[test]
+    fn test_netgroup() {
+        let group = |url: &str| NetGroup::of(&Url::parse(url).unwrap());
+
+        assert_eq!(group("tcp://45.67.1.2:8340"), Some(NetGroup::Ipv4([45, 67])));
+        assert_eq!(group("tcp://45.67.1.2:8340"), group("tcp+tls://45.67.200.9:1"));
+        assert_ne!(group("tcp://45.67.1.2:8340"), group("tcp://45.68.1.2:8340"));
+        assert_eq!(group("tcp://[2001:db9:1::1]:8340"), Some(NetGroup::Ipv6([0x2001, 0xdb9])));
+        assert_eq!(group("tcp://[::ffff:45.67.1.2]:8340"), Some(NetGroup::Ipv4([45, 67])));
+        assert_eq!(group("tcp://seed.dark.fi:8340"), Some(NetGroup::Domain("dark.fi".into())));
+        assert_eq!(group("tor://abcdef.onion:8340"), Some(NetGroup::Onion("abcdef.onion".into())));
+        assert_eq!(group("i2p://xyz.b32.i2p:8340"), Some(NetGroup::I2p("xyz.b32.i2p".into())));
+        assert_ne!(group("tor://abcdef.onion:8340"), group("tor://abcxyz.onion:8340"));
+        assert_eq!(group("tor://abcdef.onion:8340").unwrap().overlay(), Some("tor"));
+
+        // Local addresses are not grouped
+        assert_eq!(group("tcp://127.0.0.1:8340"), None);
+        assert_eq!(group("tcp://192.168.1.1:8340"), None);
+        assert_eq!(group("tcp://localhost:8340"), None);
+    }
+
+    #[test]
+    fn test_greylist_netgroup_limit() {
+        let container = HostContainer::new();
+
+        for i in 0..GREYLIST_NETGROUP_MAX_LEN as u64 {
+            let url = Url::parse(&format!("tcp://45.67.0.{i}:8340")).unwrap();
+            assert!(container.store_and_trim(HostColor::Grey, url, 100 + i));
+        }
+
+        // Older than every entry of the full netgroup
+        let old = Url::parse("tcp://45.67.1.1:8340").unwrap();
+        assert!(!container.store_and_trim(HostColor::Grey, old.clone(), 1));
+        assert!(!container.contains(HostColor::Grey, &old));
+
+        // Newer entries replace the oldest one
+        let new = Url::parse("tcp://45.67.1.2:8340").unwrap();
+        assert!(container.store_and_trim(HostColor::Grey, new.clone(), 1000));
+        assert!(container.contains(HostColor::Grey, &new));
+        assert!(!container.contains(HostColor::Grey, &Url::parse("tcp://45.67.0.0:8340").unwrap()));
+        assert_eq!(container.fetch_all(HostColor::Grey).len(), GREYLIST_NETGROUP_MAX_LEN);
+
+        // Other netgroups are unaffected
+        let other = Url::parse("tcp://80.1.1.1:8340").unwrap();
+        assert!(container.store_and_trim(HostColor::Grey, other.clone(), 1));
+        assert!(container.contains(HostColor::Grey, &other));
+    }
+
+    #[test]
+    fn test_greylist_overlay_limit() {
+        let container = HostContainer::new();
+
+        // Every onion is its own netgroup, but they share a transport cap
+        let onions: Vec<(Url, u64)> = (0..GREYLIST_OVERLAY_MAX_LEN as u64 + 10)
+            .map(|i| (Url::parse(&format!("tor://onion{i}.onion:8340")).unwrap(), 100 + i))
+            .collect();
+        let stored = container.store_and_trim_many(HostColor::Grey, onions);
+        assert_eq!(stored, GREYLIST_OVERLAY_MAX_LEN + 10);
+        assert_eq!(container.fetch_all(HostColor::Grey).len(), GREYLIST_OVERLAY_MAX_LEN);
+
+        // The oldest ones were replaced
+        let oldest = Url::parse("tor://onion0.onion:8340").unwrap();
+        assert!(!container.contains(HostColor::Grey, &oldest));
+        let newest = format!("tor://onion{}.onion:8340", GREYLIST_OVERLAY_MAX_LEN + 9);
+        assert!(container.contains(HostColor::Grey, &Url::parse(&newest).unwrap()));
+
+        // Other transports are unaffected
+        let i2p = Url::parse("i2p://xyz.b32.i2p:8340").unwrap();
+        let tcp = Url::parse("tcp://80.1.1.1:8340").unwrap();
+        assert!(container.store_and_trim(HostColor::Grey, i2p.clone(), 1));
+        assert!(container.store_and_trim(HostColor::Grey, tcp.clone(), 1));
+        assert!(container.contains(HostColor::Grey, &i2p));
+        assert!(container.contains(HostColor::Grey, &tcp));
+    }
+
+    #[test]
+    fn test_anchors_are_loaded_once() {
+        let hosts = make_hosts();
+        let path = std::env::temp_dir().join(format!("darkfi_anchors_{}", rand::random::<u32>()));
+        let path = path.to_str().unwrap();
+
+        let anchor = Url::parse("tcp://80.1.1.1:8340").unwrap();
+        save_file(&expand_path(path).unwrap(), &format!("{anchor}\nnot a url\n")).unwrap();
+
+        hosts.load_anchors(path).unwrap();
+        assert_eq!(hosts.take_anchor(), Some(anchor));
+        assert_eq!(hosts.take_anchor(), None);
+
+        // The file is consumed
+        assert!(!expand_path(path).unwrap().exists());
+        hosts.load_anchors(path).unwrap();
+        assert_eq!(hosts.take_anchor(), None);
+    }
+
     #[test]
     fn test_refresh() {
         let container = HostContainer::new();
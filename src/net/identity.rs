@@ -0,0 +1,200 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2026 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Long-term node identities.
+//!
+//! The transports only provide ephemeral TLS certificates, so they can't
+//! be used to recognize a peer across connections. A node can optionally
+//! be configured with a persistent ed25519 keypair. When both ends of a
+//! channel advertise the [`NODE_IDENTITY_FEATURE`] in their version
+//! messages, each side signs a transcript of the version exchange with
+//! its node key and sends it over in a [`NodeIdentityMessage`]. The
+//! signature binds the key to this specific handshake, so it can't be
+//! replayed on another channel.
+//!
+//! On TLS and QUIC transports the transcript also includes keying
+//! material exported from the session, which is the same on both ends of
+//! a connection. A man in the middle terminating the encrypted sessions
+//! on both sides can't forward the identity proof, as each of its
+//! sessions exports different material. Plaintext transports (e.g. Tor
+//! onion services, which are already authenticated by their address)
+//! have no such binding.
+//!
+//! [`NodeIdentityMessage`]: super::message::NodeIdentityMessage
+
+use std::{fmt, fs, str::FromStr};
+
+use darkfi_serial::{serialize_async, SerialDecodable, SerialEncodable};
+use ed25519_compact::{KeyPair, PublicKey, Seed, Signature};
+
+use super::message::VersionMessage;
+use crate::{
+    util::{
+        file::{load_file, save_file},
+        path::expand_path,
+    },
+    Error, Result,
+};
+
+#[cfg(target_family = "unix")]
+use std::os::unix::fs::PermissionsExt;
+
+/// Feature name advertised in `VersionMessage::features` by nodes
+/// which have a node identity configured.
+pub const NODE_IDENTITY_FEATURE: &str = "node_identity";
+
+/// Version of the node identity handshake
+pub const NODE_IDENTITY_VERSION: u32 = 2;
+
+/// Domain separator for the signed handshake transcript
+const TRANSCRIPT_DOMAIN: &[u8] = b"DarkFi:P2P:NodeIdentity:v2";
+
+/// Public part of a node identity, used to pin or filter peers.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, SerialEncodable, SerialDecodable)]
+pub struct NodeKey(pub [u8; 32]);
+
+impl NodeKey {
+    /// Verify an ed25519 `signature` over `message` made by this key.
+    pub fn verify(&self, message: &[u8], signature: &[u8; 64]) -> bool {
+        let public_key = PublicKey::new(self.0);
+        public_key.verify(message, &Signature::new(*signature)).is_ok()
+    }
+}
+
+impl fmt::Display for NodeKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", bs58::encode(self.0).into_string())
+    }
+}
+
+impl FromStr for NodeKey {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let Ok(bytes) = bs58::decode(s).into_vec() else {
+            return Err(Error::ParseFailed("Invalid base58 node key"))
+        };
+
+        let Ok(bytes) = <[u8; 32]>::try_from(bytes) else {
+            return Err(Error::ParseFailed("Invalid node key length"))
+        };
+
+        if PublicKey::from_slice(&bytes).is_err() {
+            return Err(Error::ParseFailed("Invalid ed25519 node key"))
+        }
+
+        Ok(Self(bytes))
+    }
+}
+
+/// Persistent ed25519 keypair identifying this node on the P2P network.
+#[derive(Clone)]
+pub struct NodeIdentity {
+    keypair: KeyPair,
+}
+
+impl NodeIdentity {
+    /// Generate a new random node identity.
+    pub fn generate() -> Self {
+        Self { keypair: KeyPair::generate() }
+    }
+
+    /// Deterministically create a node identity from a 32 byte seed.
+    pub fn from_seed(seed: [u8; 32]) -> Self {
+        Self { keypair: KeyPair::from_seed(Seed::new(seed)) }
+    }
+
+    /// Load the node identity seed from the given path. If the file does
+    /// not exist, a new identity is generated and written there.
+    pub fn load_or_create(path: &str) -> Result<Self> {
+        let path = expand_path(path)?;
+
+        if path.exists() {
+            let contents = load_file(&path)?;
+            let Ok(bytes) = bs58::decode(contents.trim()).into_vec() else {
+                return Err(Error::ParseFailed("Invalid base58 node identity"))
+            };
+            let Ok(seed) = <[u8; 32]>::try_from(bytes) else {
+                return Err(Error::ParseFailed("Invalid node identity length"))
+            };
+            return Ok(Self::from_seed(seed))
+        }
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let identity = Self::generate();
+        let seed = identity.keypair.sk.seed();
+        save_file(&path, &bs58::encode(*seed).into_string())?;
+        // Windows only has readonly so don't worry about it
+        #[cfg(target_family = "unix")]
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600))?;
+
+        Ok(identity)
+    }
+
+    /// Public node key of this identity
+    pub fn node_key(&self) -> NodeKey {
+        NodeKey(*self.keypair.pk)
+    }
+
+    /// Sign the handshake transcript of a version exchange.
+    /// `sent` is the version message we sent, `received` is the
+    /// one we got from the peer, and `binding` is the channel binding
+    /// of the transport session.
+    pub(in crate::net) async fn sign_handshake(
+        &self,
+        sent: &VersionMessage,
+        received: &VersionMessage,
+        binding: Option<[u8; 32]>,
+    ) -> [u8; 64] {
+        let transcript = handshake_transcript(&self.node_key(), sent, received, binding).await;
+        *self.keypair.sk.sign(transcript, None)
+    }
+}
+
+impl fmt::Debug for NodeIdentity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "NodeIdentity({})", self.node_key())
+    }
+}
+
+/// Build the transcript signed by `signer` over the version exchange.
+/// `signer_sent` is the version message the signer sent,
+/// `signer_received` is the one it received from its peer, and
+/// `binding` is the channel binding of the transport session.
+pub(in crate::net) async fn handshake_transcript(
+    signer: &NodeKey,
+    signer_sent: &VersionMessage,
+    signer_received: &VersionMessage,
+    binding: Option<[u8; 32]>,
+) -> Vec<u8> {
+    let mut transcript = TRANSCRIPT_DOMAIN.to_vec();
+    transcript.extend_from_slice(&signer.0);
+    transcript.extend_from_slice(&serialize_async(signer_sent).await);
+    transcript.extend_from_slice(&serialize_async(signer_received).await);
+    match binding {
+        Some(binding) => {
+            transcript.push(1);
+            transcript.extend_from_slice(&binding);
+        }
+        None => transcript.push(0),
+    }
+    transcript
+}
@@ -20,10 +20,14 @@ use std::net::Ipv6Addr;
 
 use darkfi_serial::{
     async_trait, serialize_async, AsyncDecodable, AsyncEncodable, SerialDecodable, SerialEncodable,
+    VarInt,
 };
 use url::{Host, Url};
 
-use crate::{net::metering::MeteringConfiguration, util::time::NanoTimestamp};
+use crate::{
+    net::{bandwidth::TrafficPriority, identity::NodeKey, metering::MeteringConfiguration},
+    util::time::NanoTimestamp,
+};
 
 /// Generic message template.
 pub trait Message: 'static + Send + Sync + AsyncDecodable + AsyncEncodable {
@@ -37,17 +41,33 @@ pub trait Message: 'static + Send + Sync + AsyncDecodable + AsyncEncodable {
     /// Message metering configuration for rate limit.
     /// Use `MeteringConfiguration::default()` for no limit.
     const METERING_CONFIGURATION: MeteringConfiguration;
+    /// Priority class used when bandwidth limits are configured.
+    const PRIORITY: TrafficPriority = TrafficPriority::Normal;
 }
 
 /// Generic serialized message template.
 pub struct SerializedMessage {
     pub command: String,
     pub payload: Vec<u8>,
+    pub priority: TrafficPriority,
 }
 
 impl SerializedMessage {
     pub async fn new<M: Message>(message: &M) -> Self {
-        Self { command: M::NAME.to_string(), payload: serialize_async(message).await }
+        Self {
+            command: M::NAME.to_string(),
+            payload: serialize_async(message).await,
+            priority: M::PRIORITY,
+        }
+    }
+
+    /// Number of bytes this message takes on the wire, including the
+    /// magic bytes and the length prefixes.
+    pub fn wire_size(&self) -> usize {
+        4 + VarInt(self.command.len() as u64).length() +
+            self.command.len() +
+            VarInt(self.payload.len() as u64).length() +
+            self.payload.len()
     }
 }
 
@@ -61,6 +81,15 @@ macro_rules! impl_p2p_message {
             const METERING_CONFIGURATION: MeteringConfiguration = $mc;
         }
     };
+    ($st:ty, $nm:expr, $mb:expr, $ms:expr, $mc:expr, $pr:expr) => {
+        impl Message for $st {
+            const NAME: &'static str = $nm;
+            const MAX_BYTES: u64 = $mb;
+            const METERING_SCORE: u64 = $ms;
+            const METERING_CONFIGURATION: MeteringConfiguration = $mc;
+            const PRIORITY: $crate::net::bandwidth::TrafficPriority = $pr;
+        }
+    };
 }
 
 /// Maximum command (message name) length in bytes.
@@ -84,14 +113,28 @@ pub const PING_PONG_MAX_BYTES: u64 = 2;
 pub struct PingMessage {
     pub nonce: u16,
 }
-impl_p2p_message!(PingMessage, "ping", PING_PONG_MAX_BYTES, 1, PING_PONG_METERING_CONFIGURATION);
+impl_p2p_message!(
+    PingMessage,
+    "ping",
+    PING_PONG_MAX_BYTES,
+    1,
+    PING_PONG_METERING_CONFIGURATION,
+    TrafficPriority::High
+);
 
 /// Inbound keepalive message.
 #[derive(Debug, Copy, Clone, SerialEncodable, SerialDecodable)]
 pub struct PongMessage {
     pub nonce: u16,
 }
-impl_p2p_message!(PongMessage, "pong", PING_PONG_MAX_BYTES, 1, PING_PONG_METERING_CONFIGURATION);
+impl_p2p_message!(
+    PongMessage,
+    "pong",
+    PING_PONG_MAX_BYTES,
+    1,
+    PING_PONG_METERING_CONFIGURATION,
+    TrafficPriority::High
+);
 
 /// Requests address of outbound connection.
 #[derive(Debug, Clone, SerialEncodable, SerialDecodable)]
@@ -184,7 +227,14 @@ pub const VERSION_METERING_CONFIGURATION: MeteringConfiguration = MeteringConfig
 /// * features = 1 (vec_len) + (32 (service_name) + 4 (service_version)) * 10 = 361 (10 features is an estimate)
 pub const VERSION_MAX_BYTES: u64 = 2043;
 
-impl_p2p_message!(VersionMessage, "version", VERSION_MAX_BYTES, 1, VERSION_METERING_CONFIGURATION);
+impl_p2p_message!(
+    VersionMessage,
+    "version",
+    VERSION_MAX_BYTES,
+    1,
+    VERSION_METERING_CONFIGURATION,
+    TrafficPriority::High
+);
 
 impl VersionMessage {
     pub(in crate::net) fn get_ipv6_addr(&self) -> Option<Ipv6Addr> {
@@ -218,4 +268,41 @@ pub const VERACK_METERING_CONFIGURATION: MeteringConfiguration = MeteringConfigu
 /// Prerelease and build strings are variable length but shouldn't be larger than 102 bytes.
 pub const VERACK_MAX_BYTES: u64 = 128;
 
-impl_p2p_message!(VerackMessage, "verack", VERACK_MAX_BYTES, 1, VERACK_METERING_CONFIGURATION);
+impl_p2p_message!(
+    VerackMessage,
+    "verack",
+    VERACK_MAX_BYTES,
+    1,
+    VERACK_METERING_CONFIGURATION,
+    TrafficPriority::High
+);
+
+/// Proves ownership of the sender's long-term node key.
+/// Sent after the version exchange when both sides advertise the
+/// node identity feature.
+#[derive(Debug, Clone, SerialEncodable, SerialDecodable)]
+pub struct NodeIdentityMessage {
+    /// Public node key of the sender
+    pub node_key: NodeKey,
+    /// Signature over the version exchange transcript
+    pub signature: [u8; 64],
+}
+pub const NODE_IDENTITY_METERING_CONFIGURATION: MeteringConfiguration = MeteringConfiguration {
+    threshold: 4,
+    sleep_step: 1000,
+    expiry_time: NanoTimestamp::from_secs(10),
+};
+
+/// NodeIdentity message fields size:
+/// * node_key = 32
+/// * signature = 64
+pub const NODE_IDENTITY_MAX_BYTES: u64 = 96;
+
+impl_p2p_message!(
+    NodeIdentityMessage,
+    "nodeidentity",
+    NODE_IDENTITY_MAX_BYTES,
+    1,
+    NODE_IDENTITY_METERING_CONFIGURATION,
+    TrafficPriority::High
+);
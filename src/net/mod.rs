@@ -67,6 +67,10 @@ pub mod upnp;
 /// hosts store until it finds ones to connect to.
 pub mod hosts;
 
+/// Persistent connection history of known hosts, used to score them
+/// when selecting outbound connections.
+pub mod host_records;
+
 /// Async channel that handles the sending of messages across the network.
 /// Public interface is used to create new channels, to stop and start a
 /// channel, and to send messages.
@@ -123,6 +127,11 @@ pub mod acceptor;
 /// Used to establish an outbound connection.
 pub mod connector;
 
+/// Persistent node identities used to authenticate peers across
+/// connections, independently of the transport.
+pub mod identity;
+pub use identity::{NodeIdentity, NodeKey};
+
 /// Network configuration settings. This holds the configured P2P instance
 /// behaviour and is controlled by clients of this API.
 pub mod settings;
@@ -135,3 +144,6 @@ pub mod dnet;
 
 /// Metering related definitions.
 pub mod metering;
+
+/// Bandwidth limits and traffic shaping of channel I/O.
+pub mod bandwidth;
@@ -23,6 +23,7 @@ use std::{
         atomic::{AtomicBool, AtomicUsize, Ordering},
         Arc, Weak,
     },
+    time::Duration,
 };
 
 use futures::{stream::FuturesUnordered, TryFutureExt};
@@ -33,9 +34,11 @@ use tracing::{debug, error, info};
 use url::Url;
 
 use super::{
+    bandwidth::BandwidthManager,
     channel::{Channel, ChannelPtr},
-    dnet::DnetEvent,
+    dnet::{self, DnetEvent},
     hosts::{Hosts, HostsPtr},
+    identity::NodeIdentity,
     message::{Message, SerializedMessage},
     protocol::{protocol_registry::ProtocolRegistry, register_default_protocols},
     session::{
@@ -178,6 +181,12 @@ pub struct P2p {
     channels: Mutex<HashMap<u32, Weak<Channel>>>,
     /// Bounded set of detached broadcast tasks owned by this P2P instance.
     broadcast_tasks: Arc<BroadcastTasks>,
+    /// Long-term node identity, if configured
+    identity: Option<NodeIdentity>,
+    /// Global and per-session bandwidth limiters
+    bandwidth: BandwidthManager,
+    /// Task periodically publishing bandwidth stats over dnet
+    bandwidth_stats_task: StoppableTaskPtr,
 }
 
 impl P2p {
@@ -199,9 +208,27 @@ impl P2p {
             fs::set_permissions(&datastore, PermissionsExt::from_mode(0o700)).await?;
         }
 
+        // Load or create the node identity
+        let identity = match settings.node_key {
+            Some(ref path) => {
+                let identity = NodeIdentity::load_or_create(path)?;
+                info!(target: "net::p2p::new", "[P2P] Node key: {}", identity.node_key());
+                Some(identity)
+            }
+            None => None,
+        };
+
+        if identity.is_none() && !settings.allowed_node_keys.is_empty() {
+            return Err(Error::ConfigError(
+                "allowed_node_keys requires node_key to be configured".to_string(),
+            ))
+        }
+
         // Register a CryptoProvider for rustls
         let _ = CryptoProvider::install_default(ring::default_provider());
 
+        let bandwidth = BandwidthManager::new(&settings);
+
         // Wrap the Settings into an Arc<RwLock>
         let settings = Arc::new(AsyncRwLock::new(settings));
 
@@ -221,6 +248,9 @@ impl P2p {
             stopping: AtomicBool::new(false),
             channels: Mutex::new(HashMap::new()),
             broadcast_tasks: BroadcastTasks::new(),
+            identity,
+            bandwidth,
+            bandwidth_stats_task: StoppableTask::new(),
         });
 
         register_default_protocols(self_.clone()).await;
@@ -250,15 +280,29 @@ impl P2p {
         // activate yet- they wait for a call to notify().
         self.session_seedsync().start().await;
 
+        // Start the refine session. This loads the hostlist and the
+        // anchors, so it must run before the outbound session starts.
+        self.session_refine().start().await;
+
         // Start the outbound session
         self.session_outbound().start().await;
 
-        // Start the refine session
-        self.session_refine().start().await;
-
         // Start the direct session
         self.session_direct().start().await;
 
+        // Start publishing bandwidth stats to dnet subscribers
+        let p2p = Arc::downgrade(&self);
+        self.bandwidth_stats_task.clone().start(
+            async move {
+                Self::bandwidth_stats_loop(p2p).await;
+                Ok(())
+            },
+            // Ignore stop handler
+            |_| async {},
+            Error::NetworkServiceStopped,
+            self.executor.clone(),
+        );
+
         info!(target: "net::p2p::start", "[P2P] P2P subsystem started successfully");
         Ok(())
     }
@@ -288,6 +332,7 @@ impl P2p {
         self.session_outbound().stop().await;
         self.session_refine().stop().await;
         self.session_direct().stop().await;
+        self.bandwidth_stats_task.stop().await;
 
         let channels = self.tracked_channels();
         let stops = FuturesUnordered::new();
@@ -424,10 +469,16 @@ impl P2p {
         self.session_refine().reload().await;
         self.session_seedsync().reload().await;
         self.session_direct().reload().await;
+        self.bandwidth.reload(&*self.settings.read().await);
 
         debug!(target: "net::p2p::reload", "P2P settings reloaded successfully");
     }
 
+    /// Return a reference to the configured node identity, if any
+    pub fn node_identity(&self) -> Option<&NodeIdentity> {
+        self.identity.as_ref()
+    }
+
     /// Return an atomic pointer to the list of hosts
     pub fn hosts(&self) -> HostsPtr {
         self.hosts.clone()
@@ -495,6 +546,27 @@ impl P2p {
         self.dnet_publisher.notify(event).await;
     }
 
+    /// Return a reference to the bandwidth limiters
+    pub fn bandwidth(&self) -> &BandwidthManager {
+        &self.bandwidth
+    }
+
+    /// Publish a bandwidth usage snapshot every second while dnet is
+    /// enabled. Exits once the P2P instance is dropped.
+    async fn bandwidth_stats_loop(weak: Weak<Self>) {
+        loop {
+            smol::Timer::after(Duration::from_secs(1)).await;
+
+            let Some(p2p) = weak.upgrade() else { return };
+            if !p2p.dnet_enabled.load(Ordering::SeqCst) {
+                continue
+            }
+
+            let event = dnet::BandwidthUpdate { stats: p2p.bandwidth.stats() };
+            p2p.dnet_notify(DnetEvent::BandwidthUpdate(event)).await;
+        }
+    }
+
     /// Grab the channel pointer of provided channel ID, if it exists.
     pub fn get_channel(&self, id: u32) -> Option<ChannelPtr> {
         self.hosts.get_channel(id)
@@ -25,7 +25,7 @@ use url::Url;
 use super::{
     super::{
         channel::ChannelPtr,
-        hosts::{HostColor, HostContainer, HostsPtr, SHAREABLE_SCHEMES},
+        hosts::{is_shareable_scheme, HostColor, HostContainer, HostsPtr},
         message::{AddrsMessage, GetAddrsMessage},
         message_publisher::MessageSubscription,
         p2p::P2pPtr,
@@ -84,9 +84,7 @@ fn select_addrs(container: &HostContainer, request: &GetAddrsMessage) -> Vec<(Ur
     // Ignore private or unknown endpoint schemes and collapse duplicate preferences.
     let mut requested_transports = vec![];
     for transport in &request.transports {
-        if SHAREABLE_SCHEMES.contains(&transport.as_str()) &&
-            !requested_transports.contains(transport)
-        {
+        if is_shareable_scheme(transport) && !requested_transports.contains(transport) {
             requested_transports.push(transport.clone());
         }
     }
@@ -123,7 +121,7 @@ fn select_addrs(container: &HostContainer, request: &GetAddrsMessage) -> Vec<(Ur
     addrs.append(&mut container.fetch_n_random(HostColor::Dark, remain));
 
     // Dark entries are untrusted and can contain private endpoint schemes.
-    addrs.retain(|addr| SHAREABLE_SCHEMES.contains(&addr.0.scheme()));
+    addrs.retain(|addr| is_shareable_scheme(addr.0.scheme()));
     addrs
 }
 
@@ -310,11 +308,13 @@ impl ProtocolBase for ProtocolAddress {
 mod tests {
     use darkfi_serial::serialize;
     use smol::lock::RwLock as AsyncRwLock;
-    use std::sync::Arc;
+    use std::{sync::Arc, time::UNIX_EPOCH};
     use url::Url;
 
     use crate::net::{
-        hosts::{HostColor, HostContainer, Hosts, SHAREABLE_SCHEMES},
+        hosts::{
+            HostColor, HostContainer, Hosts, NetGroup, GREYLIST_NETGROUP_MAX_LEN, SHAREABLE_SCHEMES,
+        },
         message::GET_ADDRS_MAX_BYTES,
         Settings,
     };
@@ -351,4 +351,70 @@ mod tests {
 
         assert_eq!(response, [(mixed, 2), (fallback, 1)]);
     }
+
+    #[test]
+    fn test_addrs_flood_is_bucketed_by_netgroup() {
+        smol::block_on(async {
+            let settings =
+                Settings { active_profiles: vec!["tcp".to_string()], ..Default::default() };
+            let hosts = Hosts::new(Arc::new(AsyncRwLock::new(settings)));
+            let now = UNIX_EPOCH.elapsed().unwrap().as_secs();
+
+            // Honest peers, each in its own /16
+            let honest: Vec<(Url, u64)> = (0..20)
+                .map(|i| (Url::parse(&format!("tcp://{}.1.1.1:8340", 80 + i)).unwrap(), now))
+                .collect();
+            hosts.insert(HostColor::Grey, &honest).await;
+
+            // An attacker owning a single /16 floods us with fresh addresses,
+            // the same way ProtocolAddress appends received AddrsMessages.
+            for round in 0..10u64 {
+                let addrs: Vec<(Url, u64)> = (0..100)
+                    .map(|i| {
+                        let url = format!("tcp://45.67.{round}.{i}:8340");
+                        (Url::parse(&url).unwrap(), now + round + 1)
+                    })
+                    .collect();
+                hosts.insert(HostColor::Grey, &addrs).await;
+            }
+
+            let attacker = NetGroup::of(&Url::parse("tcp://45.67.0.1:8340").unwrap()).unwrap();
+            let grey = hosts.container.fetch_all(HostColor::Grey);
+            let attacker_count =
+                grey.iter().filter(|(url, _)| NetGroup::of(url) == Some(attacker.clone())).count();
+
+            // The attacker is capped, and can't evict the honest peers
+            assert_eq!(attacker_count, GREYLIST_NETGROUP_MAX_LEN);
+            for (addr, _) in &honest {
+                assert!(hosts.container.contains(HostColor::Grey, addr));
+            }
+
+            // Feelers pick a netgroup first, so the attacker only gets its share
+            let schemes = vec!["tcp".to_string()];
+            let mut attacker_picks = 0;
+            for _ in 0..1000 {
+                let (url, _) = hosts
+                    .container
+                    .fetch_random_netgroup_with_schemes(HostColor::Grey, &schemes)
+                    .unwrap();
+                if NetGroup::of(&url) == Some(attacker.clone()) {
+                    attacker_picks += 1;
+                }
+            }
+            assert!(attacker_picks < 150, "attacker picked {attacker_picks} times");
+
+            // Outbound slots can only use a couple of connections on the attacker
+            let attacker_addrs: Vec<(Url, u64)> = grey
+                .iter()
+                .filter(|(url, _)| NetGroup::of(url) == Some(attacker.clone()))
+                .cloned()
+                .collect();
+            let limit = Settings::default().outbound_netgroup_limit;
+            for _ in 0..limit {
+                assert!(hosts.check_addrs(attacker_addrs.clone()).await.is_some());
+            }
+            assert!(hosts.check_addrs(attacker_addrs).await.is_none());
+            assert!(hosts.check_addrs(honest).await.is_some());
+        });
+    }
 }
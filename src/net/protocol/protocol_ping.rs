@@ -32,6 +32,7 @@ use super::{
         message::{PingMessage, PongMessage},
         message_publisher::MessageSubscription,
         p2p::P2pPtr,
+        session::SESSION_INBOUND,
         settings::Settings,
     },
     protocol_base::{ProtocolBase, ProtocolBasePtr},
@@ -136,13 +137,19 @@ impl ProtocolPing {
                 return Err(Error::ChannelStopped)
             }
 
+            let latency = timer.elapsed();
             debug!(
                 target: "net::protocol_ping::run_ping_pong",
                 "Received Pong from {}: {:?}",
                 self.channel.display_address(),
-                timer.elapsed(),
+                latency,
             );
 
+            // Inbound addresses are ephemeral, so only record dialed hosts
+            if self.channel.session_type_id() & SESSION_INBOUND == 0 {
+                self.channel.hosts().records.record_latency(self.channel.address(), latency);
+            }
+
             // Sleep until next heartbeat
             sleep(channel_heartbeat_interval).await;
         }
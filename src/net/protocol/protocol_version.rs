@@ -20,7 +20,10 @@ use futures::{
     future::{join_all, select, Either},
     pin_mut,
 };
-use smol::{lock::RwLock as AsyncRwLock, Executor, Timer};
+use smol::{
+    lock::{OnceCell, RwLock as AsyncRwLock},
+    Executor, Timer,
+};
 use std::{
     sync::Arc,
     time::{Duration, UNIX_EPOCH},
@@ -29,7 +32,9 @@ use tracing::debug;
 
 use super::super::{
     channel::ChannelPtr,
-    message::{VerackMessage, VersionMessage},
+    dnet::{self, dnetev, DnetEvent},
+    identity::{handshake_transcript, NODE_IDENTITY_FEATURE, NODE_IDENTITY_VERSION},
+    message::{NodeIdentityMessage, VerackMessage, VersionMessage},
     message_publisher::MessageSubscription,
     settings::Settings,
 };
@@ -45,6 +50,9 @@ pub struct ProtocolVersion {
     channel: ChannelPtr,
     version_sub: MessageSubscription<VersionMessage>,
     verack_sub: MessageSubscription<VerackMessage>,
+    identity_sub: MessageSubscription<NodeIdentityMessage>,
+    /// The version message we sent, kept for the node identity transcript
+    sent_version: OnceCell<VersionMessage>,
     settings: Arc<AsyncRwLock<Settings>>,
 }
 
@@ -62,12 +70,26 @@ impl ProtocolVersion {
         let verack_sub =
             channel.subscribe_msg::<VerackMessage>().await.expect("Missing verack dispatcher!");
 
-        Arc::new(Self { channel, version_sub, verack_sub, settings })
+        // Creates a node identity subscription
+        let identity_sub = channel
+            .subscribe_msg::<NodeIdentityMessage>()
+            .await
+            .expect("Missing node identity dispatcher!");
+
+        Arc::new(Self {
+            channel,
+            version_sub,
+            verack_sub,
+            identity_sub,
+            sent_version: OnceCell::new(),
+            settings,
+        })
     }
 
     /// Start version information exchange. Start the timer. Send version
     /// info and wait for version ack. Wait for version info and send
-    /// version ack.
+    /// version ack. Then authenticate the peer node identity, if both
+    /// sides have one.
     pub async fn run(self: Arc<Self>, executor: Arc<Executor<'_>>) -> Result<()> {
         debug!(target: "net::protocol_version::run", "START => address={}", self.channel.display_address());
         let channel_handshake_timeout =
@@ -140,6 +162,14 @@ impl ProtocolVersion {
             return Err(e.clone())
         }
 
+        if let Err(e) = self.clone().authenticate_peer().await {
+            verbose!(
+                target: "net::protocol_version::exchange_versions",
+                "authenticate_peer() failed: {e}"
+            );
+            return Err(e)
+        }
+
         debug!(
             target: "net::protocol_version::exchange_versions",
             "END => address={}", self.channel.display_address(),
@@ -163,6 +193,14 @@ impl ProtocolVersion {
 
         let external_addrs = self.channel.hosts().external_addrs().await;
 
+        /* NOTE: `features` is a list of enabled features in the
+        format Vec<(service, version)>. In the future, Protocols will
+        add their own data to this field when they are attached.*/
+        let mut features = vec![];
+        if self.channel.p2p().node_identity().is_some() {
+            features.push((NODE_IDENTITY_FEATURE.to_string(), NODE_IDENTITY_VERSION));
+        }
+
         let version = VersionMessage {
             node_id,
             app_name: app_name.clone(),
@@ -171,12 +209,10 @@ impl ProtocolVersion {
             connect_recv_addr: self.channel.connect_addr().clone(),
             resolve_recv_addr: self.channel.resolve_addr(),
             ext_send_addr: external_addrs,
-            /* NOTE: `features` is a list of enabled features in the
-            format Vec<(service, version)>. In the future, Protocols will
-            add their own data to this field when they are attached.*/
-            features: vec![],
+            features,
         };
         self.channel.send(&version).await?;
+        self.sent_version.set(version).await.unwrap();
 
         // Wait for verack
         let verack_msg = self.verack_sub.receive().await?;
@@ -249,4 +285,82 @@ impl ProtocolVersion {
         );
         Ok(())
     }
+
+    /// Exchange node identities when both peers advertise them, then
+    /// check the peer against the configured node key filters.
+    ///
+    /// Each side signs the transcript of the version exchange and the
+    /// channel binding of the encrypted session with its node key, so
+    /// the signature can't be replayed on a different channel or relayed
+    /// by a man in the middle terminating the TLS sessions.
+    async fn authenticate_peer(self: Arc<Self>) -> Result<()> {
+        debug!(
+            target: "net::protocol_version::authenticate_peer",
+            "START => address={}", self.channel.display_address(),
+        );
+
+        let p2p = self.channel.p2p();
+        let recv_version = self.channel.get_version();
+        let peer_has_identity = recv_version.features.iter().any(|(name, version)| {
+            name == NODE_IDENTITY_FEATURE && *version == NODE_IDENTITY_VERSION
+        });
+
+        let mut peer_key = None;
+        if let (Some(identity), true) = (p2p.node_identity(), peer_has_identity) {
+            let sent_version = self.sent_version.get().unwrap();
+
+            let binding = self.channel.channel_binding();
+            let signature = identity.sign_handshake(sent_version, &recv_version, binding).await;
+            let identity_msg = NodeIdentityMessage { node_key: identity.node_key(), signature };
+            self.channel.send(&identity_msg).await?;
+
+            // The peer signed the transcript from its own point of view
+            let peer_identity = self.identity_sub.receive().await?;
+            let transcript =
+                handshake_transcript(&peer_identity.node_key, &recv_version, sent_version, binding)
+                    .await;
+
+            if !peer_identity.node_key.verify(&transcript, &peer_identity.signature) {
+                verbose!(
+                    target: "net::protocol_version::authenticate_peer",
+                    "[P2P] Invalid node identity from {}. Disconnecting...",
+                    self.channel.display_address(),
+                );
+                return Err(Error::InvalidNodeIdentity)
+            }
+
+            peer_key = Some(peer_identity.node_key);
+        }
+
+        if !self.settings.read().await.node_key_allowed(peer_key.as_ref()) {
+            verbose!(
+                target: "net::protocol_version::authenticate_peer",
+                "[P2P] Node key of {} is not allowed. Disconnecting...",
+                self.channel.display_address(),
+            );
+            return Err(Error::NodeKeyRejected)
+        }
+
+        if let Some(node_key) = peer_key {
+            verbose!(
+                target: "net::protocol_version::authenticate_peer",
+                "[P2P] Authenticated peer {} as {node_key}",
+                self.channel.display_address(),
+            );
+
+            self.channel.set_peer_key(node_key).await;
+
+            dnetev!(self.channel, PeerAuthenticated, {
+                addr: self.channel.display_address().clone(),
+                channel_id: self.channel.info.id,
+                node_key,
+            });
+        }
+
+        debug!(
+            target: "net::protocol_version::authenticate_peer",
+            "END => address={}", self.channel.display_address(),
+        );
+        Ok(())
+    }
 }
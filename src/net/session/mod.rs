@@ -18,7 +18,7 @@
 
 use std::{
     sync::{Arc, Weak},
-    time::UNIX_EPOCH,
+    time::{Duration, UNIX_EPOCH},
 };
 
 use async_trait::async_trait;
@@ -101,6 +101,13 @@ pub async fn remove_sub_on_stop(
         channel.display_address()
     );
 
+    // Keep track of how long hosts we dialed stay connected
+    if type_id & SESSION_INBOUND == 0 {
+        let uptime =
+            UNIX_EPOCH.elapsed().unwrap().as_secs().saturating_sub(channel.info.start_time);
+        hosts.records.record_disconnect(addr, Duration::from_secs(uptime));
+    }
+
     // Downgrade to greylist if this is a outbound session.
     if type_id & (SESSION_OUTBOUND | SESSION_DIRECT) != 0 {
         debug!(
@@ -265,6 +265,8 @@ impl Slot {
     /// All slots use this same ordered vector, and check_addrs coordinates
     /// which slot gets which address via try_register.
     ///
+    /// Anchors saved by the previous run are tried before any of these.
+    ///
     /// Selecting from the greylist for some % of the slots is necessary
     /// and healthy since we require the network retains some unreliable
     /// connections. A network that purely favors uptime over unreliable
@@ -289,6 +291,18 @@ impl Slot {
         // Drop Settings read lock
         drop(settings);
 
+        // Reconnect to the anchors of the previous run before anything else
+        while let Some(anchor) = hosts.take_anchor() {
+            if !transports.contains(&anchor.scheme().to_string()) {
+                continue
+            }
+            let Some(last_seen) = hosts.fetch_last_seen(&anchor) else { continue };
+            if let Some(addr) = hosts.check_addrs(vec![(anchor, last_seen)]).await {
+                debug!(target: "net::outbound_session::fetch_addrs", "[P2P] Reconnecting to anchor {}", addr.0);
+                return Some(addr)
+            }
+        }
+
         let mut addrs = Vec::with_capacity(outbound_connections);
 
         // Known peers (gold + white) up to known_peer_percent.
@@ -298,18 +312,19 @@ impl Slot {
         let bounded_percent = if disable_greys { 100 } else { known_peer_percent.min(80) };
         let known_count = (bounded_percent * outbound_connections) / 100;
 
-        // Add gold up to known_count
-        for addr in container.fetch_n_random_with_schemes(HostColor::Gold, &transports, known_count)
-        {
+        // Add gold up to known_count. Known hosts are weighted by their
+        // connection history, so reliable and fast peers are preferred
+        // while every host keeps a chance of being selected.
+        let gold = container.fetch_with_schemes(HostColor::Gold, &transports, None);
+        for addr in hosts.records.weighted_sample(gold, known_count) {
             addrs.push(addr);
         }
         trace!(target: "net::outbound_session::fetch_addrs", "[P2P] fetch_addrs: collected {} gold addrs", addrs.len());
 
         // Add white to fill remaining known slots
         let remaining_known = known_count - addrs.len();
-        for addr in
-            container.fetch_n_random_with_schemes(HostColor::White, &transports, remaining_known)
-        {
+        let white = container.fetch_with_schemes(HostColor::White, &transports, None);
+        for addr in hosts.records.weighted_sample(white, remaining_known) {
             addrs.push(addr);
         }
         trace!(target: "net::outbound_session::fetch_addrs", "[P2P] fetch_addrs: collected {} white addrs (total: {})", remaining_known, addrs.len());
@@ -450,6 +465,8 @@ impl Slot {
                     channel.display_address()
                 );
 
+                self.p2p().hosts().records.record_failure(channel.address(), &err.to_string());
+
                 // Peer disconnected during the registry process. We'll downgrade this peer now.
                 if let Err(e) = self
                     .p2p()
@@ -505,6 +522,8 @@ impl Slot {
                     return Err(Error::ConnectFailed(message));
                 }
 
+                self.p2p().hosts().records.record_failure(&addr, &err.to_string());
+
                 // At this point we failed to connect. We'll downgrade this peer now.
                 self.p2p().hosts().move_host(&addr, last_seen, HostColor::Grey).await?;
 
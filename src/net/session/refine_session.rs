@@ -44,7 +44,8 @@ use super::super::p2p::{P2p, P2pPtr};
 use crate::{
     net::{
         connector::Connector,
-        hosts::{HostColor, HostContainer},
+        host_records::HostRecords,
+        hosts::{HostColor, HostContainer, Hosts},
         protocol::ProtocolVersion,
         session::{Session, SessionBitFlag, SESSION_REFINE},
     },
@@ -79,6 +80,24 @@ impl RefineSession {
                     verbose!(target: "net::refine_session::start", "Error loading hosts {e}");
                 }
             }
+
+            match self.p2p().hosts().records.load_all(&HostRecords::path_for(hostlist)) {
+                Ok(()) => {
+                    debug!(target: "net::refine_session::start", "Load host records successful!");
+                }
+                Err(e) => {
+                    verbose!(target: "net::refine_session::start", "Error loading host records {e}");
+                }
+            }
+
+            match self.p2p().hosts().load_anchors(&Hosts::anchors_path_for(hostlist)) {
+                Ok(()) => {
+                    debug!(target: "net::refine_session::start", "Load anchors successful!");
+                }
+                Err(e) => {
+                    verbose!(target: "net::refine_session::start", "Error loading anchors {e}");
+                }
+            }
         }
 
         match self.p2p().hosts().import_blacklist().await {
@@ -100,7 +119,21 @@ impl RefineSession {
         debug!(target: "net::refine_session", "Stopping refinery process");
         self.refinery.clone().stop().await;
 
-        if let Some(ref hostlist) = self.p2p().settings().read().await.hostlist {
+        let settings = self.p2p().settings().read_arc().await;
+        let anchor_connections = settings.anchor_connections;
+        if let Some(ref hostlist) = settings.hostlist {
+            // The outbound session is stopped by now, but its channels are
+            // still registered until the P2P instance drains them.
+            let anchors = Hosts::anchors_path_for(hostlist);
+            match self.p2p().hosts().save_anchors(&anchors, anchor_connections) {
+                Ok(()) => {
+                    debug!(target: "net::refine_session::stop", "Save anchors successful!");
+                }
+                Err(e) => {
+                    verbose!(target: "net::refine_session::stop", "Error saving anchors {e}");
+                }
+            }
+
             match self.p2p().hosts().container.save_all(hostlist) {
                 Ok(()) => {
                     debug!(target: "net::refine_session::stop", "Save hosts successful!");
@@ -109,6 +142,15 @@ impl RefineSession {
                     verbose!(target: "net::refine_session::stop", "Error saving hosts {e}");
                 }
             }
+
+            match self.p2p().hosts().records.save_all(&HostRecords::path_for(hostlist)) {
+                Ok(()) => {
+                    debug!(target: "net::refine_session::stop", "Save host records successful!");
+                }
+                Err(e) => {
+                    verbose!(target: "net::refine_session::stop", "Error saving host records {e}");
+                }
+            }
         }
     }
 
@@ -279,8 +321,13 @@ impl GreylistRefinery {
                 continue
             }
 
-            // Only attempt to refine peers that match our transports.
-            match hosts.container.fetch_random_with_schemes(HostColor::Grey, &dialable_schemes) {
+            // Only attempt to refine peers that match our transports. Feelers pick a
+            // netgroup first, so a flood of addresses from a few networks can't take
+            // over the whitelist.
+            match hosts
+                .container
+                .fetch_random_netgroup_with_schemes(HostColor::Grey, &dialable_schemes)
+            {
                 Some((url, _last_seen)) => {
                     if !hosts.refinable(&url) {
                         debug!(target: "net::refinery", "Unable to refine addr={}", url);
@@ -289,6 +336,7 @@ impl GreylistRefinery {
 
                     if !self.session().handshake_node(url.clone(), self.p2p().clone()).await {
                         hosts.container.remove(HostColor::Grey, &url);
+                        hosts.records.record_failure(&url, "Refinery handshake failed");
 
                         debug!(
                             target: "net::refinery",
@@ -21,6 +21,7 @@ use std::collections::HashMap;
 use structopt::StructOpt;
 use url::Url;
 
+use super::{bandwidth::LIMITED_SESSIONS, identity::NodeKey};
 use crate::error::{Error, Result};
 
 type BlacklistEntry = (String, Vec<String>, Vec<u16>);
@@ -116,6 +117,12 @@ pub struct Settings {
     /// If true, disable greylist connections entirely.
     /// When set, known_peer_percent is effectively 100%.
     pub disable_greys: bool,
+    /// Maximum number of outbound connections to hosts of the same
+    /// netgroup (IPv4 /16, IPv6 /32, ...). 0 means unlimited.
+    pub outbound_netgroup_limit: usize,
+    /// Number of outbound peers saved on shutdown and reconnected to
+    /// first on the next start.
+    pub anchor_connections: usize,
     /// Number of seconds with no connections after which refinery
     /// process is paused.
     pub time_with_no_connections: u64,
@@ -129,6 +136,22 @@ pub struct Settings {
     pub ban_policy: BanPolicy,
     /// Mapping of transport/scheme to Network Profile
     pub profiles: HashMap<String, NetworkProfile>,
+    /// Path to the long-term node identity key. When set, the node
+    /// authenticates itself to peers which also have an identity.
+    /// A new key is generated if the file does not exist.
+    pub node_key: Option<String>,
+    /// If not empty, only peers authenticating with one of these node
+    /// keys are allowed to connect. Requires `node_key` to be set.
+    pub allowed_node_keys: Vec<NodeKey>,
+    /// Peers authenticating with one of these node keys are rejected
+    pub denied_node_keys: Vec<NodeKey>,
+    /// Global upload limit in bytes per second (unlimited if `None`)
+    pub upload_limit: Option<u64>,
+    /// Global download limit in bytes per second (unlimited if `None`)
+    pub download_limit: Option<u64>,
+    /// Per-session bandwidth limits, keyed by session name
+    /// (`inbound`, `outbound`, `manual` or `direct`)
+    pub session_bandwidth: HashMap<String, BandwidthLimit>,
 }
 
 impl Default for Settings {
@@ -162,10 +185,18 @@ impl Default for Settings {
             greylist_refinery_interval: 15,
             known_peer_percent: 70,
             disable_greys: false,
+            outbound_netgroup_limit: 2,
+            anchor_connections: 2,
             time_with_no_connections: 30,
             blacklist: vec![],
             ban_policy: BanPolicy::Strict,
             profiles: HashMap::new(),
+            node_key: None,
+            allowed_node_keys: vec![],
+            denied_node_keys: vec![],
+            upload_limit: None,
+            download_limit: None,
+            session_bandwidth: HashMap::new(),
         }
     }
 }
@@ -193,6 +224,28 @@ impl Settings {
     pub fn channel_handshake_timeout(&self, profile: &str) -> u64 {
         self.profiles.get(profile).unwrap_or(&NetworkProfile::default()).channel_handshake_timeout
     }
+
+    /// Check a peer against the configured node key filters.
+    /// `node_key` is `None` when the peer did not authenticate.
+    pub fn node_key_allowed(&self, node_key: Option<&NodeKey>) -> bool {
+        let Some(node_key) = node_key else { return self.allowed_node_keys.is_empty() };
+
+        if self.denied_node_keys.contains(node_key) {
+            return false
+        }
+
+        self.allowed_node_keys.is_empty() || self.allowed_node_keys.contains(node_key)
+    }
+}
+
+/// Upload and download limits of a session type, in bytes per second.
+/// A missing or zero limit means unlimited.
+#[derive(Clone, Debug, Default, PartialEq, serde::Deserialize)]
+pub struct BandwidthLimit {
+    #[serde(default)]
+    pub upload_limit: Option<u64>,
+    #[serde(default)]
+    pub download_limit: Option<u64>,
 }
 
 /// Distinguishes distinct P2P networks
@@ -337,6 +390,16 @@ pub struct SettingsOpt {
     #[structopt(long)]
     pub disable_greys: bool,
 
+    /// Maximum outbound connections to hosts of the same netgroup (0 for unlimited)
+    #[serde(default)]
+    #[structopt(long)]
+    pub outbound_netgroup_limit: Option<usize>,
+
+    /// Number of outbound peers to reconnect to first after a restart
+    #[serde(default)]
+    #[structopt(long)]
+    pub anchor_connections: Option<usize>,
+
     /// Number of seconds with no connections after which refinery
     /// process is paused.
     #[structopt(skip)]
@@ -360,6 +423,35 @@ pub struct SettingsOpt {
     #[serde(default)]
     #[structopt(skip)]
     pub profiles: HashMap<String, NetworkProfileOpt>,
+
+    /// Path to the long-term node identity key (generated if missing)
+    #[serde(default)]
+    #[structopt(long)]
+    pub node_key: Option<String>,
+
+    /// Only allow peers authenticating with one of these node keys
+    #[serde(default)]
+    #[structopt(skip)]
+    pub allowed_node_keys: Vec<String>,
+
+    /// Reject peers authenticating with one of these node keys
+    #[serde(default)]
+    #[structopt(skip)]
+    pub denied_node_keys: Vec<String>,
+
+    /// Global upload limit in bytes per second
+    #[structopt(long)]
+    pub upload_limit: Option<u64>,
+
+    /// Global download limit in bytes per second
+    #[structopt(long)]
+    pub download_limit: Option<u64>,
+
+    /// Bandwidth limits for each session type
+    /// (inbound, outbound, manual, direct)
+    #[serde(default)]
+    #[structopt(skip)]
+    pub session_bandwidth: HashMap<String, BandwidthLimit>,
 }
 
 impl TryFrom<(&str, &str, SettingsOpt)> for Settings {
@@ -399,6 +491,24 @@ impl TryFrom<(&str, &str, SettingsOpt)> for Settings {
             })
             .collect();
 
+        let parse_node_keys = |keys: Vec<String>| -> Result<Vec<NodeKey>> {
+            keys.iter()
+                .map(|key| {
+                    key.parse().map_err(|_| Error::ConfigError(format!("Invalid node key '{key}'")))
+                })
+                .collect()
+        };
+        let allowed_node_keys = parse_node_keys(opt.allowed_node_keys)?;
+        let denied_node_keys = parse_node_keys(opt.denied_node_keys)?;
+
+        for name in opt.session_bandwidth.keys() {
+            if !LIMITED_SESSIONS.iter().any(|(_, session)| session == name) {
+                return Err(Error::ConfigError(format!(
+                    "Unknown session '{name}' in net.session_bandwidth"
+                )))
+            }
+        }
+
         Ok(Self {
             node_id: opt.node_id,
             inbound_addrs,
@@ -430,12 +540,22 @@ impl TryFrom<(&str, &str, SettingsOpt)> for Settings {
                 .unwrap_or(def.greylist_refinery_interval),
             known_peer_percent: opt.known_peer_percent.unwrap_or(def.known_peer_percent),
             disable_greys: opt.disable_greys,
+            outbound_netgroup_limit: opt
+                .outbound_netgroup_limit
+                .unwrap_or(def.outbound_netgroup_limit),
+            anchor_connections: opt.anchor_connections.unwrap_or(def.anchor_connections),
             time_with_no_connections: opt
                 .time_with_no_connections
                 .unwrap_or(def.time_with_no_connections),
             blacklist: opt.blacklist,
             ban_policy: opt.ban_policy,
             profiles,
+            node_key: opt.node_key,
+            allowed_node_keys,
+            denied_node_keys,
+            upload_limit: opt.upload_limit,
+            download_limit: opt.download_limit,
+            session_bandwidth: opt.session_bandwidth,
         })
     }
 }
@@ -512,7 +632,7 @@ impl NetworkProfile {
 
     /// Creates [`NetworkProfile`] from [`NetworkProfileOpt`] based on the profile
     fn from_with_profile(opt: NetworkProfileOpt, profile: &str) -> Self {
-        let def = if ["tcp", "tcp+tls", "quic"].contains(&profile) {
+        let def = if ["tcp", "tcp+tls", "quic", "mem"].contains(&profile) {
             NetworkProfile::default()
         } else {
             NetworkProfile::tor_default()
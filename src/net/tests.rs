@@ -40,7 +40,7 @@ use crate::{
         p2p::MAX_CONCURRENT_BROADCASTS,
         settings::NetworkProfile,
         transport::Dialer,
-        P2p, Settings,
+        NodeIdentity, P2p, Settings,
     },
     system::{sleep, timeout::timeout},
     util::logger::{setup_test_logger, Level},
@@ -926,3 +926,384 @@ async fn p2p_broadcasts_are_bounded_and_drained_real(ex: Arc<Executor<'static>>)
 
     server.stop().await;
 }
+
+#[test]
+fn p2p_node_identity_authenticates_peers() {
+    test_body!(p2p_node_identity_authenticates_peers_real, 2);
+}
+
+async fn p2p_node_identity_authenticates_peers_real(ex: Arc<Executor<'static>>) {
+    let tmp = std::env::temp_dir().join(format!("darkfi_net_identity_{}", rand::random::<u32>()));
+    let server_key_path = tmp.join("server_node_key").to_str().unwrap().to_string();
+    let client_key_path = tmp.join("client_node_key").to_str().unwrap().to_string();
+    let server_key = NodeIdentity::load_or_create(&server_key_path).unwrap().node_key();
+    let client_key = NodeIdentity::load_or_create(&client_key_path).unwrap().node_key();
+
+    // Loading again must return the same identity
+    assert_eq!(NodeIdentity::load_or_create(&client_key_path).unwrap().node_key(), client_key);
+
+    // First the server only allows the client key, then it denies it
+    for allowed in [true, false] {
+        let port = get_random_available_port();
+        let listen_url = Url::parse(&format!("tcp://127.0.0.1:{port}")).unwrap();
+        let (allowed_node_keys, denied_node_keys) =
+            if allowed { (vec![client_key], vec![]) } else { (vec![], vec![client_key]) };
+
+        let server_settings = Settings {
+            localnet: true,
+            inbound_addrs: vec![listen_url.clone()],
+            inbound_connections: 8,
+            outbound_connections: 0,
+            active_profiles: vec!["tcp".to_string()],
+            node_key: Some(server_key_path.clone()),
+            allowed_node_keys,
+            denied_node_keys,
+            ..Default::default()
+        };
+        let client_settings = Settings {
+            localnet: true,
+            peers: vec![listen_url],
+            inbound_connections: 0,
+            outbound_connections: 0,
+            active_profiles: vec!["tcp".to_string()],
+            node_key: Some(client_key_path.clone()),
+            ..Default::default()
+        };
+
+        let server = P2p::new(server_settings, ex.clone()).await.unwrap();
+        let client = P2p::new(client_settings, ex.clone()).await.unwrap();
+        server.clone().start().await.unwrap();
+        client.clone().start().await.unwrap();
+
+        if allowed {
+            timeout(Duration::from_secs(5), async {
+                while server.hosts().channels().is_empty() || client.hosts().channels().is_empty() {
+                    Timer::after(Duration::from_millis(10)).await;
+                }
+            })
+            .await
+            .expect("authenticated connection was not established");
+
+            let server_channel = server.hosts().channels().first().unwrap().clone();
+            let client_channel = client.hosts().channels().first().unwrap().clone();
+            assert_eq!(server_channel.peer_key(), Some(client_key));
+            assert_eq!(client_channel.peer_key(), Some(server_key));
+        } else {
+            // The client may keep retrying, but the server must never
+            // register a channel with a denied peer.
+            sleep(2).await;
+            assert!(server.hosts().channels().is_empty());
+        }
+
+        client.stop().await;
+        server.stop().await;
+    }
+
+    // An allowlist without a configured node identity is a config error
+    let settings = Settings { allowed_node_keys: vec![client_key], ..Default::default() };
+    assert!(P2p::new(settings, ex).await.is_err());
+
+    let _ = std::fs::remove_dir_all(tmp);
+}
+
+#[cfg(feature = "p2p-memory")]
+fn memory_settings(addr: &Url, seeds: Vec<Url>, peers: Vec<Url>, outbound: usize) -> Settings {
+    let mut profiles = HashMap::new();
+    profiles.insert(
+        "mem".to_string(),
+        NetworkProfile {
+            outbound_connect_timeout: 1,
+            channel_handshake_timeout: 2,
+            channel_heartbeat_interval: 1,
+        },
+    );
+
+    Settings {
+        localnet: true,
+        inbound_addrs: vec![addr.clone()],
+        external_addrs: vec![addr.clone()],
+        outbound_connections: outbound,
+        outbound_peer_discovery_cooloff_time: 1,
+        inbound_connections: usize::MAX,
+        greylist_refinery_interval: 2,
+        seeds,
+        peers,
+        node_id: addr.host_str().unwrap().to_string(),
+        active_profiles: vec!["mem".to_string()],
+        profiles,
+        ..Default::default()
+    }
+}
+
+#[test]
+#[cfg(feature = "p2p-memory")]
+fn p2p_memory_transport_network() {
+    test_body!(p2p_memory_transport_network_real, 4);
+}
+
+#[cfg(feature = "p2p-memory")]
+async fn p2p_memory_transport_network_real(ex: Arc<Executor<'static>>) {
+    use super::transport::memory::{memory_network, LinkConfig};
+
+    const N_NODES: usize = 30;
+
+    let network = memory_network("net");
+    network.seed(42);
+
+    // Dialing an unbound endpoint is refused right away
+    let dialer = Dialer::new(Url::parse("mem://nowhere.net:1").unwrap(), None, None, true);
+    let err = dialer.await.unwrap().dial(None).await.err().unwrap();
+    assert_eq!(err.kind(), std::io::ErrorKind::ConnectionRefused);
+
+    let seed_addr = Url::parse("mem://netseed.net:1").unwrap();
+    let seed = P2p::new(memory_settings(&seed_addr, vec![], vec![], 0), ex.clone()).await.unwrap();
+    seed.clone().start().await.unwrap();
+
+    let mut nodes = vec![];
+    for i in 0..N_NODES {
+        let host = format!("netnode{i}.net");
+        network.set_link(
+            &host,
+            LinkConfig {
+                latency: Duration::from_millis(5),
+                jitter: Duration::from_millis(10),
+                loss: 0.01,
+                retransmit_delay: Duration::from_millis(50),
+            },
+        );
+
+        let addr = Url::parse(&format!("mem://{host}:1")).unwrap();
+        let settings = memory_settings(&addr, vec![seed_addr.clone()], vec![], 2);
+        let p2p = P2p::new(settings, ex.clone()).await.unwrap();
+        p2p.clone().start().await.unwrap();
+        nodes.push(p2p);
+    }
+
+    // Every node should discover peers over the seed and connect to them
+    timeout(Duration::from_secs(60), async {
+        while nodes.iter().any(|node| node.hosts().channels().is_empty()) {
+            Timer::after(Duration::from_millis(100)).await;
+        }
+    })
+    .await
+    .expect("simulated network did not converge");
+
+    for node in &nodes {
+        for channel in node.hosts().channels() {
+            assert_eq!(channel.address().scheme(), "mem");
+        }
+    }
+
+    for node in nodes {
+        node.stop().await;
+    }
+    seed.stop().await;
+}
+
+#[test]
+#[cfg(feature = "p2p-memory")]
+fn p2p_memory_transport_partition() {
+    test_body!(p2p_memory_transport_partition_real, 2);
+}
+
+#[cfg(feature = "p2p-memory")]
+async fn p2p_memory_transport_partition_real(ex: Arc<Executor<'static>>) {
+    use super::transport::memory::memory_network;
+
+    let network = memory_network("partition");
+    let server_addr = Url::parse("mem://server.partition:1").unwrap();
+    let client_addr = Url::parse("mem://client.partition:1").unwrap();
+
+    let server =
+        P2p::new(memory_settings(&server_addr, vec![], vec![], 0), ex.clone()).await.unwrap();
+    let client = P2p::new(memory_settings(&client_addr, vec![], vec![server_addr], 0), ex.clone())
+        .await
+        .unwrap();
+    server.clone().start().await.unwrap();
+    client.clone().start().await.unwrap();
+
+    let wait_connected = |connected: bool| {
+        let server = server.clone();
+        let client = client.clone();
+        async move {
+            timeout(Duration::from_secs(15), async {
+                while server.hosts().channels().is_empty() == connected ||
+                    client.hosts().channels().is_empty() == connected
+                {
+                    Timer::after(Duration::from_millis(50)).await;
+                }
+            })
+            .await
+        }
+    };
+
+    wait_connected(true).await.expect("initial connection was not established");
+
+    // The heartbeat must notice the dead route and drop the channel
+    network.partition(&[&["server.partition"], &["client.partition"]]);
+    wait_connected(false).await.expect("partitioned channel was not dropped");
+
+    // No new connection can be made while the partition holds
+    sleep(2).await;
+    assert!(server.hosts().channels().is_empty());
+
+    // The manual session reconnects once the partition heals
+    network.heal(&["server.partition", "client.partition"]);
+    wait_connected(true).await.expect("connection was not re-established after healing");
+
+    client.stop().await;
+    server.stop().await;
+}
+
+#[test]
+#[cfg(feature = "p2p-memory")]
+fn p2p_memory_transport_full_loss() {
+    use smol::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::transport::{
+        memory::{memory_network, LinkConfig},
+        Dialer, Listener,
+    };
+
+    future::block_on(async {
+        let network = memory_network("loss");
+        let addr = Url::parse("mem://server.loss:1").unwrap();
+        let listener = Listener::new(addr.clone(), None, false).await.unwrap();
+        let listener = listener.listen().await.unwrap();
+        let dialer = Dialer::new(addr, None, None, true).await.unwrap();
+
+        let mut client = dialer.dial(Some(Duration::from_secs(1))).await.unwrap();
+        let (mut server, _) = listener.next().await.unwrap().await.unwrap();
+        client.write_all(b"ping").await.unwrap();
+        let mut buf = [0u8; 4];
+        server.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"ping");
+
+        // Nothing gets through a link losing every segment. Writes don't
+        // block, the connection just goes dead.
+        network.set_link("server.loss", LinkConfig { loss: 1.0, ..Default::default() });
+        timeout(Duration::from_secs(1), client.write_all(b"ping")).await.unwrap().unwrap();
+        assert!(timeout(Duration::from_millis(200), server.read_exact(&mut buf)).await.is_err());
+
+        // New connections can't be established either
+        let err = dialer.dial(Some(Duration::from_secs(1))).await.err().unwrap();
+        assert_eq!(err.kind(), std::io::ErrorKind::TimedOut);
+
+        network.set_link("server.loss", LinkConfig::default());
+    });
+}
+
+#[test]
+#[cfg(feature = "p2p-memory")]
+fn p2p_memory_transport_deterministic() {
+    use smol::io::AsyncWriteExt;
+
+    use super::transport::{
+        memory::{memory_network, LinkConfig, Transmission},
+        Dialer, Listener,
+    };
+
+    // Connect lossy clients to a jittery server, send a few segments on
+    // each connection and return what the network recorded
+    async fn scenario(seed: u64) -> Vec<Transmission> {
+        let network = memory_network("determinism");
+        network.seed(seed);
+        network.start_log();
+
+        let server = Url::parse("mem://server.determinism:1").unwrap();
+        let link = LinkConfig {
+            jitter: Duration::from_millis(5),
+            loss: 0.3,
+            retransmit_delay: Duration::from_millis(1),
+            ..Default::default()
+        };
+        network.set_link(server.host_str().unwrap(), link);
+        let listener = Listener::new(server.clone(), None, false).await.unwrap();
+        let listener = listener.listen().await.unwrap();
+
+        let mut clients = vec![];
+        for i in 0..8 {
+            let local = Url::parse(&format!("mem://client{i}.determinism:1")).unwrap();
+            let link = LinkConfig {
+                loss: if i % 2 == 0 { 0.9 } else { 0.1 },
+                retransmit_delay: Duration::from_millis(1),
+                ..Default::default()
+            };
+            network.set_link(local.host_str().unwrap(), link);
+
+            let dialer = Dialer::new(server.clone(), None, None, true).await.unwrap();
+            if let Ok(stream) = dialer.with_local_addr(Some(local)).dial(None).await {
+                clients.push(stream);
+            }
+        }
+
+        for round in 0..10u8 {
+            for client in clients.iter_mut() {
+                client.write_all(&[round; 16]).await.unwrap();
+            }
+        }
+
+        drop(listener);
+        network.take_log()
+    }
+
+    future::block_on(async {
+        let log = scenario(7).await;
+        assert!(log.iter().any(|t| t.delay.is_none()));
+        assert!(log.iter().any(|t| t.len > 0 && t.delay.is_some()));
+
+        // The same seed gives the same delays and drops, so the same
+        // delivery order
+        assert_eq!(scenario(7).await, log);
+        assert_ne!(scenario(8).await, log);
+    });
+}
+
+#[test]
+fn p2p_bandwidth_token_bucket() {
+    use super::bandwidth::{RateLimiter, TrafficPriority};
+
+    future::block_on(async {
+        // Unlimited limiters only account traffic
+        let limiter = RateLimiter::new(None);
+        limiter.acquire(1_000_000, TrafficPriority::Bulk).await;
+        assert_eq!(limiter.total(), 1_000_000);
+
+        // The first second worth of tokens is available upfront, the
+        // rest has to wait for the bucket to refill.
+        let limiter = RateLimiter::new(Some(10_000));
+        let start = std::time::Instant::now();
+        for _ in 0..3 {
+            limiter.acquire(10_000, TrafficPriority::Normal).await;
+        }
+        assert!(start.elapsed() >= Duration::from_millis(1900));
+
+        // High priority traffic is never delayed, even in debt
+        let start = std::time::Instant::now();
+        limiter.acquire(10_000, TrafficPriority::High).await;
+        limiter.acquire(10_000, TrafficPriority::High).await;
+        assert!(start.elapsed() < Duration::from_millis(100));
+
+        // Bulk traffic waits until the debt is paid off
+        let start = std::time::Instant::now();
+        limiter.acquire(1, TrafficPriority::Bulk).await;
+        assert!(start.elapsed() >= Duration::from_millis(1900));
+        assert_eq!(limiter.total(), 50_001);
+
+        // Limits can be changed at runtime, keeping the totals
+        limiter.set_limit(None);
+        assert_eq!(limiter.limit(), 0);
+        let start = std::time::Instant::now();
+        limiter.acquire(100_000, TrafficPriority::Normal).await;
+        assert!(start.elapsed() < Duration::from_millis(100));
+
+        limiter.set_limit(Some(10_000));
+        assert_eq!(limiter.limit(), 10_000);
+        let start = std::time::Instant::now();
+        for _ in 0..2 {
+            limiter.acquire(10_000, TrafficPriority::Normal).await;
+        }
+        assert!(start.elapsed() >= Duration::from_millis(900));
+        assert_eq!(limiter.total(), 170_001);
+    });
+}
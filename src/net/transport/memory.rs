@@ -0,0 +1,656 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2026 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! In-process simulated network transport.
+//!
+//! Endpoints are addressed as `mem://<host>:<port>` and only exist inside
+//! the current process, so many nodes can be spawned in a single test
+//! without touching the OS network stack.
+//!
+//! Hosts are grouped in independent networks named after the part of the
+//! host following its first dot: `mem://alice.sync:1` is the host
+//! `alice.sync` of the network `sync`, and hosts without a dot belong to
+//! the default network `""`. Tests running in parallel use their own
+//! network, so that seeding, link settings and partitions of one test
+//! don't affect the others. A host can only reach hosts of its network.
+//!
+//! Each [`MemoryNetwork`] models a star topology: every host has an access link with its own
+//! [`LinkConfig`] (latency, jitter and loss), and a connection between two
+//! hosts combines both links. Hosts can be split into partitions, which
+//! makes connections between them hang like a dead route would, so the
+//! P2P heartbeat has to detect it.
+//!
+//! Streams stay reliable and ordered like TCP. A lost segment is modelled
+//! as a retransmission delay rather than missing bytes, and a lost
+//! connection attempt fails the dial. A segment that is still lost after
+//! [`MAX_RETRANSMITS`] retransmissions (e.g. on a link with a loss of 1)
+//! kills the connection like a dead route.
+//!
+//! Random decisions are drawn from RNGs derived from the network seed,
+//! one per connection attempt and per direction of a connection, keyed by
+//! the host pair and the number of previous connections between them.
+//! The delays of a connection therefore only depend on the seed and on
+//! its own write sequence, not on how the executor interleaves traffic
+//! of other connections. Delivery of a connection's data is scheduled in
+//! order of the simulated arrival time. There is no virtual clock: delays
+//! are waited for in real time, so only the random decisions (delays and
+//! drops) are reproducible, which [`MemoryNetwork::start_log`] allows to
+//! check. Running the nodes on a single-threaded executor makes the write
+//! sequences reproducible.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    future::Future,
+    hash::{DefaultHasher, Hash, Hasher},
+    io,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, OnceLock, Weak,
+    },
+    task::{Context, Poll, Waker},
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use parking_lot::Mutex;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use smol::{
+    channel,
+    io::{AsyncRead, AsyncWrite},
+    lock::OnceCell,
+    Timer,
+};
+use tracing::debug;
+use url::Url;
+
+use super::{PtListener, PtNegotiation, PtStream};
+
+/// Host name used for dialers which don't have a local `mem://` address
+const ANONYMOUS_HOST: &str = "anonymous";
+
+/// First port handed out for ephemeral (port 0) listeners and dialers
+const EPHEMERAL_PORT_START: u16 = 49152;
+
+/// Default seed of the network RNGs
+const DEFAULT_SEED: u64 = 0xdf;
+
+/// Number of times a lost segment is retransmitted before the connection
+/// is considered dead
+pub const MAX_RETRANSMITS: u32 = 15;
+
+/// Characteristics of a host's access link to the simulated network.
+#[derive(Clone, Debug)]
+pub struct LinkConfig {
+    /// One-way latency added to every delivered segment
+    pub latency: Duration,
+    /// Maximum random latency added on top of `latency`
+    pub jitter: Duration,
+    /// Probability in `[0, 1]` that a segment or connection attempt is lost
+    pub loss: f64,
+    /// Delay before a lost segment is retransmitted
+    pub retransmit_delay: Duration,
+}
+
+impl Default for LinkConfig {
+    fn default() -> Self {
+        Self {
+            latency: Duration::ZERO,
+            jitter: Duration::ZERO,
+            loss: 0.0,
+            retransmit_delay: Duration::from_millis(200),
+        }
+    }
+}
+
+/// A segment or connection handshake recorded by
+/// [`MemoryNetwork::start_log`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Transmission {
+    /// Sending host
+    pub from: String,
+    /// Receiving host
+    pub to: String,
+    /// Length of the segment, 0 for a connection handshake
+    pub len: usize,
+    /// Delay before delivery, `None` if it never got through
+    pub delay: Option<Duration>,
+}
+
+struct NetworkState {
+    /// Seed the RNGs are derived from
+    seed: u64,
+    /// Number of RNGs derived so far for each `(from, to)` host pair
+    rng_counters: HashMap<(String, String), u64>,
+    /// Bound listeners, keyed by `(host, port)`
+    listeners: HashMap<(String, u16), channel::Sender<(MemoryStream, Url)>>,
+    /// Next port to hand out for ephemeral binds
+    next_port: u16,
+    /// Access link configuration of each host
+    links: HashMap<String, LinkConfig>,
+    /// Partition index of each partitioned host
+    partitions: HashMap<String, usize>,
+    /// Open connections, used to sever them on partition
+    connections: Vec<Weak<Connection>>,
+    /// Transmissions recorded since [`MemoryNetwork::start_log`]
+    log: Option<Vec<Transmission>>,
+}
+
+impl NetworkState {
+    fn link(&self, host: &str) -> LinkConfig {
+        self.links.get(host).cloned().unwrap_or_default()
+    }
+
+    fn is_partitioned(&self, a: &str, b: &str) -> bool {
+        match (self.partitions.get(a), self.partitions.get(b)) {
+            (Some(a), Some(b)) => a != b,
+            _ => false,
+        }
+    }
+
+    /// Derive a new RNG for random decisions about traffic sent from
+    /// `from` to `to`. The `n`-th RNG of a host pair is the same on every
+    /// run with the same seed.
+    fn pair_rng(&mut self, from: &str, to: &str) -> StdRng {
+        let counter = self.rng_counters.entry((from.to_string(), to.to_string())).or_default();
+        let mut hasher = DefaultHasher::new();
+        (self.seed, from, to, *counter).hash(&mut hasher);
+        *counter += 1;
+        StdRng::seed_from_u64(hasher.finish())
+    }
+
+    fn record(&mut self, from: &str, to: &str, len: usize, delay: Option<Duration>) {
+        if let Some(log) = &mut self.log {
+            log.push(Transmission { from: from.to_string(), to: to.to_string(), len, delay });
+        }
+    }
+
+    fn alloc_port(&mut self, host: &str) -> u16 {
+        loop {
+            let port = self.next_port;
+            self.next_port = self.next_port.checked_add(1).unwrap_or(EPHEMERAL_PORT_START);
+            if !self.listeners.contains_key(&(host.to_string(), port)) {
+                return port
+            }
+        }
+    }
+}
+
+/// Draw the delay of a single segment sent over the links `a` and `b`.
+/// Returns `None` if the segment is lost more than [`MAX_RETRANSMITS`]
+/// times.
+fn segment_delay(a: &LinkConfig, b: &LinkConfig, rng: &mut StdRng) -> Option<Duration> {
+    let mut delay = a.latency + b.latency;
+
+    let jitter = a.jitter + b.jitter;
+    if !jitter.is_zero() {
+        delay += jitter.mul_f64(rng.gen::<f64>());
+    }
+
+    let success = (1.0 - a.loss.clamp(0.0, 1.0)) * (1.0 - b.loss.clamp(0.0, 1.0));
+    if success >= 1.0 {
+        return Some(delay)
+    }
+    if success <= 0.0 {
+        return None
+    }
+
+    // Number of lost transmissions before one gets through, drawn from
+    // a geometric distribution
+    let losses = ((1.0 - rng.gen::<f64>()).ln() / (1.0 - success).ln()).floor();
+    if losses > MAX_RETRANSMITS as f64 {
+        return None
+    }
+
+    Some(delay + a.retransmit_delay.max(b.retransmit_delay) * losses as u32)
+}
+
+/// Simulated network carrying the `mem://` connections of its hosts.
+pub struct MemoryNetwork {
+    state: Mutex<NetworkState>,
+}
+
+/// Returns the [`MemoryNetwork`] named `name`, creating it if needed.
+pub fn memory_network(name: &str) -> Arc<MemoryNetwork> {
+    static NETWORKS: OnceLock<Mutex<HashMap<String, Arc<MemoryNetwork>>>> = OnceLock::new();
+
+    NETWORKS
+        .get_or_init(|| Mutex::new(HashMap::new()))
+        .lock()
+        .entry(name.to_string())
+        .or_insert_with(|| {
+            Arc::new(MemoryNetwork {
+                state: Mutex::new(NetworkState {
+                    seed: DEFAULT_SEED,
+                    rng_counters: HashMap::new(),
+                    listeners: HashMap::new(),
+                    next_port: EPHEMERAL_PORT_START,
+                    links: HashMap::new(),
+                    partitions: HashMap::new(),
+                    connections: vec![],
+                    log: None,
+                }),
+            })
+        })
+        .clone()
+}
+
+/// Returns the [`MemoryNetwork`] `host` belongs to.
+fn host_network(host: &str) -> Arc<MemoryNetwork> {
+    memory_network(host.split_once('.').map(|(_, network)| network).unwrap_or_default())
+}
+
+impl MemoryNetwork {
+    /// Reseed the RNGs driving latency jitter and loss decisions.
+    pub fn seed(&self, seed: u64) {
+        let mut state = self.state.lock();
+        state.seed = seed;
+        state.rng_counters.clear();
+    }
+
+    /// Configure the access link of `host`.
+    pub fn set_link(&self, host: &str, config: LinkConfig) {
+        self.state.lock().links.insert(host.to_string(), config);
+    }
+
+    /// Split the given hosts into partitions. Hosts in different groups
+    /// can no longer reach each other, and their open connections hang.
+    /// Hosts not listed in any group are unaffected.
+    pub fn partition(&self, groups: &[&[&str]]) {
+        let mut state = self.state.lock();
+        for (index, group) in groups.iter().enumerate() {
+            for host in group.iter() {
+                state.partitions.insert(host.to_string(), index);
+            }
+        }
+
+        let connections = std::mem::take(&mut state.connections);
+        for conn in connections {
+            let Some(conn) = conn.upgrade() else { continue };
+            if state.is_partitioned(&conn.hosts[0], &conn.hosts[1]) {
+                debug!(
+                    target: "net::memory::partition",
+                    "Severing {} <-> {}", conn.hosts[0], conn.hosts[1],
+                );
+                conn.severed.store(true, Ordering::SeqCst);
+            }
+            state.connections.push(Arc::downgrade(&conn));
+        }
+    }
+
+    /// Remove the given hosts from their partitions. Connections severed
+    /// by the partition stay dead, new ones can be established.
+    pub fn heal(&self, hosts: &[&str]) {
+        let mut state = self.state.lock();
+        for host in hosts {
+            state.partitions.remove(*host);
+        }
+    }
+
+    /// Start recording the transmissions of the network, discarding the
+    /// ones recorded so far.
+    pub fn start_log(&self) {
+        self.state.lock().log = Some(vec![]);
+    }
+
+    /// Stop recording and return the transmissions recorded since
+    /// [`MemoryNetwork::start_log`], in the order they were sent.
+    pub fn take_log(&self) -> Vec<Transmission> {
+        self.state.lock().log.take().unwrap_or_default()
+    }
+
+    /// Bind a listener on `host:port`. Port 0 picks a free port.
+    fn bind(self: &Arc<Self>, host: &str, port: u16) -> io::Result<(MemoryAcceptor, u16)> {
+        let mut state = self.state.lock();
+        let port = if port == 0 { state.alloc_port(host) } else { port };
+
+        let key = (host.to_string(), port);
+        if state.listeners.contains_key(&key) {
+            return Err(io::Error::from(io::ErrorKind::AddrInUse))
+        }
+
+        let (sender, receiver) = channel::unbounded();
+        state.listeners.insert(key.clone(), sender);
+        Ok((MemoryAcceptor { network: self.clone(), addr: key, incoming: receiver }, port))
+    }
+
+    fn unbind(&self, addr: &(String, u16)) {
+        self.state.lock().listeners.remove(addr);
+    }
+
+    /// Open a connection from `local` to the listener at `host:port`.
+    async fn connect(
+        self: &Arc<Self>,
+        local: &str,
+        host: &str,
+        port: u16,
+        timeout: Option<Duration>,
+    ) -> io::Result<MemoryStream> {
+        let rtt = {
+            let mut state = self.state.lock();
+            if !state.listeners.contains_key(&(host.to_string(), port)) {
+                return Err(io::Error::from(io::ErrorKind::ConnectionRefused))
+            }
+
+            if state.is_partitioned(local, host) {
+                return Err(io::Error::from_raw_os_error(libc::EHOSTUNREACH))
+            }
+
+            // The handshake is lost if either direction doesn't get through
+            let (local_link, host_link) = (state.link(local), state.link(host));
+            let mut rng = state.pair_rng(local, host);
+            let syn = segment_delay(&local_link, &host_link, &mut rng);
+            let ack = segment_delay(&host_link, &local_link, &mut rng);
+            state.record(local, host, 0, syn.zip(ack).map(|(syn, ack)| syn + ack));
+            let (Some(syn), Some(ack)) = (syn, ack) else {
+                return Err(io::Error::from(io::ErrorKind::TimedOut))
+            };
+            syn + ack
+        };
+
+        if let Some(timeout) = timeout {
+            if rtt > timeout {
+                Timer::after(timeout).await;
+                return Err(io::Error::from(io::ErrorKind::TimedOut))
+            }
+        }
+
+        // Simulate the connection handshake
+        if !rtt.is_zero() {
+            Timer::after(rtt).await;
+        }
+
+        let mut state = self.state.lock();
+        let Some(listener) = state.listeners.get(&(host.to_string(), port)).cloned() else {
+            return Err(io::Error::from(io::ErrorKind::ConnectionRefused))
+        };
+
+        let local_port = state.alloc_port(local);
+        let conn = Arc::new(Connection {
+            network: self.clone(),
+            hosts: [local.to_string(), host.to_string()],
+            severed: AtomicBool::new(false),
+            pipes: [
+                Mutex::new(Pipe::new(state.pair_rng(host, local))),
+                Mutex::new(Pipe::new(state.pair_rng(local, host))),
+            ],
+        });
+        state.connections.retain(|conn| conn.strong_count() > 0);
+        state.connections.push(Arc::downgrade(&conn));
+        drop(state);
+
+        let peer_url = Url::parse(&format!("mem://{local}:{local_port}")).unwrap();
+        let accepted = MemoryStream::new(conn.clone(), 1);
+        if listener.try_send((accepted, peer_url)).is_err() {
+            return Err(io::Error::from(io::ErrorKind::ConnectionRefused))
+        }
+
+        Ok(MemoryStream::new(conn, 0))
+    }
+}
+
+/// One direction of a connection
+struct Pipe {
+    /// Segments in flight with their delivery time
+    segments: VecDeque<(Instant, Vec<u8>)>,
+    /// Read offset into the front segment
+    offset: usize,
+    /// Delivery time of the last queued segment, to keep ordering
+    last_delivery: Option<Instant>,
+    /// Set when either end is gone
+    closed: bool,
+    /// Waker of a reader waiting for data
+    waker: Option<Waker>,
+    /// RNG drawing the delays of the segments sent through this pipe
+    rng: StdRng,
+}
+
+impl Pipe {
+    fn new(rng: StdRng) -> Self {
+        Self {
+            segments: VecDeque::new(),
+            offset: 0,
+            last_delivery: None,
+            closed: false,
+            waker: None,
+            rng,
+        }
+    }
+
+    fn close(&mut self) {
+        self.closed = true;
+        if let Some(waker) = self.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+struct Connection {
+    /// Network carrying the connection
+    network: Arc<MemoryNetwork>,
+    /// Dialer and acceptor host names
+    hosts: [String; 2],
+    /// Set when a partition cut this connection
+    severed: AtomicBool,
+    /// `pipes[i]` carries data towards side `i`
+    pipes: [Mutex<Pipe>; 2],
+}
+
+/// One end of an in-memory connection
+pub struct MemoryStream {
+    conn: Arc<Connection>,
+    /// 0 for the dialing side, 1 for the accepting side
+    side: usize,
+    /// Timer waiting for the next segment delivery
+    timer: Option<Timer>,
+}
+
+impl MemoryStream {
+    fn new(conn: Arc<Connection>, side: usize) -> Self {
+        Self { conn, side, timer: None }
+    }
+}
+
+impl AsyncRead for MemoryStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        // A severed connection behaves like a dead route
+        if self.conn.severed.load(Ordering::SeqCst) {
+            return Poll::Pending
+        }
+
+        loop {
+            let conn = self.conn.clone();
+            let mut pipe = conn.pipes[self.side].lock();
+
+            let Some((deliver_at, _)) = pipe.segments.front() else {
+                if pipe.closed {
+                    return Poll::Ready(Ok(0))
+                }
+                pipe.waker = Some(cx.waker().clone());
+                return Poll::Pending
+            };
+
+            let deliver_at = *deliver_at;
+            if deliver_at <= Instant::now() {
+                let offset = pipe.offset;
+                let segment = &pipe.segments.front().unwrap().1;
+                let n = buf.len().min(segment.len() - offset);
+                buf[..n].copy_from_slice(&segment[offset..offset + n]);
+
+                if offset + n == segment.len() {
+                    pipe.segments.pop_front();
+                    pipe.offset = 0;
+                } else {
+                    pipe.offset += n;
+                }
+
+                return Poll::Ready(Ok(n))
+            }
+            drop(pipe);
+
+            // Wait until the front segment arrives
+            let timer = self.timer.get_or_insert_with(|| Timer::at(deliver_at));
+            timer.set_at(deliver_at);
+            if Pin::new(timer).poll(cx).is_pending() {
+                return Poll::Pending
+            }
+        }
+    }
+}
+
+impl AsyncWrite for MemoryStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        // Writes into a severed connection are silently dropped
+        if self.conn.severed.load(Ordering::SeqCst) || buf.is_empty() {
+            return Poll::Ready(Ok(buf.len()))
+        }
+
+        let from = &self.conn.hosts[self.side];
+        let to = &self.conn.hosts[1 - self.side];
+        let mut state = self.conn.network.state.lock();
+        let (from_link, to_link) = (state.link(from), state.link(to));
+
+        let mut pipe = self.conn.pipes[1 - self.side].lock();
+        if pipe.closed {
+            return Poll::Ready(Err(io::Error::from(io::ErrorKind::BrokenPipe)))
+        }
+
+        let delay = segment_delay(&from_link, &to_link, &mut pipe.rng);
+        state.record(from, to, buf.len(), delay);
+        drop(state);
+
+        // The segment never got through: the route is dead, so the
+        // connection hangs like a severed one
+        let Some(delay) = delay else {
+            drop(pipe);
+            debug!(target: "net::memory::poll_write", "Segment lost {from} -> {to}, dropping link");
+            self.conn.severed.store(true, Ordering::SeqCst);
+            return Poll::Ready(Ok(buf.len()))
+        };
+
+        // Segments are delivered in order, never before the previous one
+        let mut deliver_at = Instant::now() + delay;
+        if let Some(last_delivery) = pipe.last_delivery {
+            deliver_at = deliver_at.max(last_delivery);
+        }
+        pipe.last_delivery = Some(deliver_at);
+        pipe.segments.push_back((deliver_at, buf.to_vec()));
+
+        if let Some(waker) = pipe.waker.take() {
+            waker.wake();
+        }
+
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.conn.pipes[1 - self.side].lock().close();
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl Drop for MemoryStream {
+    fn drop(&mut self) {
+        self.conn.pipes[0].lock().close();
+        self.conn.pipes[1].lock().close();
+    }
+}
+
+/// Memory Dialer implementation
+#[derive(Debug, Clone)]
+pub struct MemoryDialer;
+
+impl MemoryDialer {
+    /// Instantiate a new [`MemoryDialer`] object
+    pub(crate) async fn new() -> io::Result<Self> {
+        Ok(Self {})
+    }
+
+    /// Internal dial function. `local_addr` is the `mem://` address the
+    /// connection originates from, used to apply link settings and
+    /// partitions.
+    pub(crate) async fn do_dial(
+        &self,
+        host: &str,
+        port: u16,
+        local_addr: Option<&Url>,
+        timeout: Option<Duration>,
+    ) -> io::Result<MemoryStream> {
+        let local = local_addr.and_then(|addr| addr.host_str()).unwrap_or(ANONYMOUS_HOST);
+        debug!(target: "net::memory::do_dial", "Dialing mem://{host}:{port} from {local}...");
+        host_network(host).connect(local, host, port, timeout).await
+    }
+}
+
+/// Memory Listener implementation
+#[derive(Debug, Clone)]
+pub struct MemoryListener {
+    /// When the user puts port 0, the bound port is stored here
+    pub port: Arc<OnceCell<u16>>,
+}
+
+impl MemoryListener {
+    /// Instantiate a new [`MemoryListener`] object
+    pub(crate) async fn new() -> io::Result<Self> {
+        Ok(Self { port: Arc::new(OnceCell::new()) })
+    }
+
+    /// Internal listen function
+    pub(crate) async fn do_listen(&self, host: &str, port: u16) -> io::Result<MemoryAcceptor> {
+        let (acceptor, port) = host_network(host).bind(host, port)?;
+        let _ = self.port.set(port).await;
+        debug!(target: "net::memory::do_listen", "Listening on mem://{host}:{port}");
+        Ok(acceptor)
+    }
+}
+
+/// Accepts incoming connections of a bound [`MemoryListener`]
+pub struct MemoryAcceptor {
+    network: Arc<MemoryNetwork>,
+    addr: (String, u16),
+    incoming: channel::Receiver<(MemoryStream, Url)>,
+}
+
+impl Drop for MemoryAcceptor {
+    fn drop(&mut self) {
+        self.network.unbind(&self.addr);
+    }
+}
+
+#[async_trait]
+impl PtListener for MemoryAcceptor {
+    async fn next(&self) -> io::Result<PtNegotiation> {
+        let Ok((stream, url)) = self.incoming.recv().await else {
+            return Err(io::Error::from(io::ErrorKind::ConnectionAborted))
+        };
+
+        Ok(Box::pin(async move { Ok((Box::new(stream) as Box<dyn PtStream>, url)) }))
+    }
+}
@@ -53,6 +53,10 @@ pub(crate) mod unix;
 #[cfg(feature = "p2p-quic")]
 pub(crate) mod quic;
 
+/// In-process simulated transport
+#[cfg(feature = "p2p-memory")]
+pub mod memory;
+
 /// Dialer variants
 #[derive(Debug, Clone)]
 pub enum DialerVariant {
@@ -92,6 +96,10 @@ pub enum DialerVariant {
 
     /// QUIC (with built-in TLS)
     Quic(quic::QuicDialer),
+
+    /// In-process simulated network
+    #[cfg(feature = "p2p-memory")]
+    Memory(memory::MemoryDialer),
 }
 
 /// Listener variants
@@ -114,6 +122,10 @@ pub enum ListenerVariant {
     #[cfg(feature = "p2p-quic")]
     /// QUIC (with built-in TLS)
     Quic(quic::QuicListener),
+
+    #[cfg(feature = "p2p-memory")]
+    /// In-process simulated network
+    Memory(memory::MemoryListener),
 }
 
 /// A dialer that is able to transparently operate over arbitrary transports.
@@ -124,6 +136,8 @@ pub struct Dialer {
     variant: DialerVariant,
     /// Marker if TLS client certificate should be provided for this instance
     provide_tls_client_cert: bool,
+    /// Local address the connection originates from, if known
+    local_addr: Option<Url>,
 }
 
 macro_rules! enforce_hostport {
@@ -161,7 +175,7 @@ impl Dialer {
                 enforce_hostport!(endpoint);
                 let variant = tcp::TcpDialer::new(None).await?;
                 let variant = DialerVariant::Tcp(variant);
-                Ok(Self { endpoint, variant, provide_tls_client_cert })
+                Ok(Self { endpoint, variant, provide_tls_client_cert, local_addr: None })
             }
 
             "tcp+tls" => {
@@ -169,7 +183,7 @@ impl Dialer {
                 enforce_hostport!(endpoint);
                 let variant = tcp::TcpDialer::new(None).await?;
                 let variant = DialerVariant::TcpTls(variant);
-                Ok(Self { endpoint, variant, provide_tls_client_cert })
+                Ok(Self { endpoint, variant, provide_tls_client_cert, local_addr: None })
             }
 
             #[cfg(feature = "p2p-tor")]
@@ -178,7 +192,7 @@ impl Dialer {
                 enforce_hostport!(endpoint);
                 let variant = tor::TorDialer::new(datastore).await?;
                 let variant = DialerVariant::Tor(variant);
-                Ok(Self { endpoint, variant, provide_tls_client_cert })
+                Ok(Self { endpoint, variant, provide_tls_client_cert, local_addr: None })
             }
 
             #[cfg(feature = "p2p-tor")]
@@ -187,7 +201,7 @@ impl Dialer {
                 enforce_hostport!(endpoint);
                 let variant = tor::TorDialer::new(datastore).await?;
                 let variant = DialerVariant::TorTls(variant);
-                Ok(Self { endpoint, variant, provide_tls_client_cert })
+                Ok(Self { endpoint, variant, provide_tls_client_cert, local_addr: None })
             }
 
             #[cfg(feature = "p2p-nym")]
@@ -196,7 +210,7 @@ impl Dialer {
                 enforce_hostport!(endpoint);
                 let variant = nym::NymDialer::new().await?;
                 let variant = DialerVariant::Nym(variant);
-                Ok(Self { endpoint, variant, provide_tls_client_cert })
+                Ok(Self { endpoint, variant, provide_tls_client_cert, local_addr: None })
             }
 
             #[cfg(feature = "p2p-nym")]
@@ -205,7 +219,7 @@ impl Dialer {
                 enforce_hostport!(endpoint);
                 let variant = nym::NymDialer::new().await?;
                 let variant = DialerVariant::NymTls(variant);
-                Ok(Self { endpoint, variant, provide_tls_client_cert })
+                Ok(Self { endpoint, variant, provide_tls_client_cert, local_addr: None })
             }
 
             #[cfg(feature = "p2p-unix")]
@@ -214,7 +228,7 @@ impl Dialer {
                 enforce_abspath!(endpoint);
                 let variant = unix::UnixDialer::new().await?;
                 let variant = DialerVariant::Unix(variant);
-                Ok(Self { endpoint, variant, provide_tls_client_cert })
+                Ok(Self { endpoint, variant, provide_tls_client_cert, local_addr: None })
             }
 
             #[cfg(feature = "p2p-socks5")]
@@ -223,7 +237,7 @@ impl Dialer {
                 enforce_hostport!(endpoint);
                 let variant = socks5::Socks5Dialer::new(&endpoint).await?;
                 let variant = DialerVariant::Socks5(variant);
-                Ok(Self { endpoint, variant, provide_tls_client_cert })
+                Ok(Self { endpoint, variant, provide_tls_client_cert, local_addr: None })
             }
 
             #[cfg(feature = "p2p-socks5")]
@@ -232,7 +246,7 @@ impl Dialer {
                 enforce_hostport!(endpoint);
                 let variant = socks5::Socks5Dialer::new(&endpoint).await?;
                 let variant = DialerVariant::Socks5Tls(variant);
-                Ok(Self { endpoint, variant, provide_tls_client_cert })
+                Ok(Self { endpoint, variant, provide_tls_client_cert, local_addr: None })
             }
 
             #[cfg(feature = "p2p-i2p")]
@@ -243,7 +257,7 @@ impl Dialer {
                 url.set_path(&format!("{}:{}", endpoint.host().unwrap(), endpoint.port().unwrap()));
                 let variant = socks5::Socks5Dialer::new(&url).await?;
                 let variant = DialerVariant::Socks5(variant);
-                Ok(Self { endpoint, variant, provide_tls_client_cert })
+                Ok(Self { endpoint, variant, provide_tls_client_cert, local_addr: None })
             }
 
             #[cfg(feature = "p2p-i2p")]
@@ -255,7 +269,7 @@ impl Dialer {
                 url.set_scheme("socks5+tls").unwrap();
                 let variant = socks5::Socks5Dialer::new(&url).await?;
                 let variant = DialerVariant::Socks5Tls(variant);
-                Ok(Self { endpoint, variant, provide_tls_client_cert })
+                Ok(Self { endpoint, variant, provide_tls_client_cert, local_addr: None })
             }
 
             #[cfg(feature = "p2p-quic")]
@@ -264,7 +278,16 @@ impl Dialer {
                 enforce_hostport!(endpoint);
                 let variant = quic::QuicDialer::new().await?;
                 let variant = DialerVariant::Quic(variant);
-                Ok(Self { endpoint, variant, provide_tls_client_cert })
+                Ok(Self { endpoint, variant, provide_tls_client_cert, local_addr: None })
+            }
+
+            #[cfg(feature = "p2p-memory")]
+            "mem" => {
+                // Build an in-memory dialer
+                enforce_hostport!(endpoint);
+                let variant = memory::MemoryDialer::new().await?;
+                let variant = DialerVariant::Memory(variant);
+                Ok(Self { endpoint, variant, provide_tls_client_cert, local_addr: None })
             }
 
             x => {
@@ -354,9 +377,25 @@ impl Dialer {
                 let stream = dialer.do_dial(sockaddr[0], timeout).await?;
                 Ok(Box::new(stream))
             }
+
+            #[cfg(feature = "p2p-memory")]
+            DialerVariant::Memory(dialer) => {
+                let host = self.endpoint.host_str().unwrap();
+                let port = self.endpoint.port().unwrap();
+                let local_addr = self.local_addr.as_ref();
+                let stream = dialer.do_dial(host, port, local_addr, timeout).await?;
+                Ok(Box::new(stream))
+            }
         }
     }
 
+    /// Set the local address the connection originates from. Transports
+    /// which can't bind outgoing connections ignore it.
+    pub fn with_local_addr(mut self, local_addr: Option<Url>) -> Self {
+        self.local_addr = local_addr;
+        self
+    }
+
     /// Return a reference to the `Dialer` endpoint
     pub fn endpoint(&self) -> &Url {
         &self.endpoint
@@ -423,6 +462,14 @@ impl Listener {
                 Ok(Self { endpoint, variant, require_tls_client_cert })
             }
 
+            #[cfg(feature = "p2p-memory")]
+            "mem" => {
+                enforce_hostport!(endpoint);
+                let variant = memory::MemoryListener::new().await?;
+                let variant = ListenerVariant::Memory(variant);
+                Ok(Self { endpoint, variant, require_tls_client_cert })
+            }
+
             x => {
                 verbose!("[P2P] Requested unsupported transport: {x}");
                 Err(io::Error::from_raw_os_error(libc::ENETUNREACH))
@@ -471,6 +518,14 @@ impl Listener {
                 let l = listener.do_listen(sockaddr[0]).await?;
                 Ok(Box::new(l))
             }
+
+            #[cfg(feature = "p2p-memory")]
+            ListenerVariant::Memory(listener) => {
+                let host = self.endpoint.host_str().unwrap();
+                let port = self.endpoint.port().unwrap();
+                let l = listener.do_listen(host, port).await?;
+                Ok(Box::new(l))
+            }
         }
     }
 
@@ -513,30 +568,70 @@ impl Listener {
                 endpoint
             }
 
+            #[cfg(feature = "p2p-memory")]
+            ListenerVariant::Memory(listener) => {
+                let mut endpoint = self.endpoint.clone();
+                let port = self.endpoint.port().unwrap();
+
+                if port == 0 {
+                    if let Some(actual_port) = listener.port.get() {
+                        endpoint.set_port(Some(*actual_port)).unwrap();
+                    }
+                }
+
+                endpoint
+            }
+
             #[allow(unreachable_patterns)]
             _ => self.endpoint.clone(),
         }
     }
 }
 
+/// Label of the keying material exported from TLS and QUIC sessions
+/// to bind the node identity handshake to the session.
+pub(crate) const CHANNEL_BINDING_LABEL: &[u8] = b"EXPORTER-DarkFi-channel-binding";
+
 /// Wrapper trait for async streams
-pub trait PtStream: AsyncRead + AsyncWrite + Unpin + Send {}
+pub trait PtStream: AsyncRead + AsyncWrite + Unpin + Send {
+    /// Value identifying the encrypted session of this stream, which is
+    /// the same on both ends of the connection. `None` for transports
+    /// without an encrypted session.
+    fn channel_binding(&self) -> Option<[u8; 32]> {
+        None
+    }
+}
 
 impl PtStream for smol::net::TcpStream {}
 
-impl PtStream for futures_rustls::TlsStream<smol::net::TcpStream> {}
+impl PtStream for futures_rustls::TlsStream<smol::net::TcpStream> {
+    fn channel_binding(&self) -> Option<[u8; 32]> {
+        tls::channel_binding(self)
+    }
+}
 
 #[cfg(feature = "p2p-tor")]
 impl PtStream for arti_client::DataStream {}
 
 #[cfg(feature = "p2p-tor")]
-impl PtStream for futures_rustls::TlsStream<arti_client::DataStream> {}
+impl PtStream for futures_rustls::TlsStream<arti_client::DataStream> {
+    fn channel_binding(&self) -> Option<[u8; 32]> {
+        tls::channel_binding(self)
+    }
+}
 
 #[cfg(feature = "p2p-unix")]
 impl PtStream for smol::net::unix::UnixStream {}
 
 #[cfg(feature = "p2p-quic")]
-impl PtStream for quic::QuicStream {}
+impl PtStream for quic::QuicStream {
+    fn channel_binding(&self) -> Option<[u8; 32]> {
+        self.binding()
+    }
+}
+
+#[cfg(feature = "p2p-memory")]
+impl PtStream for memory::MemoryStream {}
 
 /// A transport negotiation produced after accepting an underlying connection.
 pub type PtNegotiation =
@@ -34,7 +34,8 @@ use futures::{
 use futures_rustls::rustls::{self, version::TLS13};
 use quinn_smol::{
     crypto::rustls::{QuicClientConfig, QuicServerConfig},
-    ClientConfig, Endpoint, RecvStream, SendStream, ServerConfig, TransportConfig, VarInt,
+    ClientConfig, Connection, Endpoint, RecvStream, SendStream, ServerConfig, TransportConfig,
+    VarInt,
 };
 use smol::{
     io::{AsyncRead, AsyncWrite},
@@ -223,11 +224,23 @@ fn create_server_config() -> io::Result<ServerConfig> {
 pub struct QuicStream {
     send: SendStream,
     recv: RecvStream,
+    /// Keying material exported from the QUIC session
+    binding: Option<[u8; 32]>,
 }
 
 impl QuicStream {
-    fn new(send: SendStream, recv: RecvStream) -> Self {
-        Self { send, recv }
+    fn new(connection: &Connection, send: SendStream, recv: RecvStream) -> Self {
+        let mut binding = [0u8; 32];
+        let binding = connection
+            .export_keying_material(&mut binding, super::CHANNEL_BINDING_LABEL, &[])
+            .ok()
+            .map(|_| binding);
+        Self { send, recv, binding }
+    }
+
+    /// Keying material binding the node identity handshake to this session
+    pub(super) fn binding(&self) -> Option<[u8; 32]> {
+        self.binding
     }
 }
 
@@ -313,7 +326,7 @@ impl QuicDialer {
                 .await
                 .map_err(|e| io::Error::other(format!("QUIC stream error: {e}")))?;
 
-            Ok(QuicStream::new(send, recv))
+            Ok(QuicStream::new(&connection, send, recv))
         };
 
         match timeout {
@@ -402,7 +415,7 @@ impl PtListener for QuicListenerIntern {
                 io::Error::new(io::ErrorKind::InvalidData, format!("Invalid peer address: {e}"))
             })?;
 
-            Ok((Box::new(QuicStream::new(send, recv)) as Box<dyn PtStream>, url))
+            Ok((Box::new(QuicStream::new(&connection, send, recv)) as Box<dyn PtStream>, url))
         }))
     }
 }
@@ -40,6 +40,21 @@ use crate::util::logger::verbose;
 /// The DNS name used for certificate validation across all transports
 pub(crate) const TLS_DNS_NAME: &str = "dark.fi";
 
+/// Export keying material from the TLS session of `stream`, used to bind
+/// the node identity handshake to this session.
+pub(crate) fn channel_binding<IO>(stream: &TlsStream<IO>) -> Option<[u8; 32]> {
+    let label = super::CHANNEL_BINDING_LABEL;
+    let exported = match stream {
+        TlsStream::Client(stream) => {
+            stream.get_ref().1.export_keying_material([0u8; 32], label, None)
+        }
+        TlsStream::Server(stream) => {
+            stream.get_ref().1.export_keying_material([0u8; 32], label, None)
+        }
+    };
+    exported.ok()
+}
+
 /// Validate certificate DNSName.
 fn validate_dnsname(cert: &X509Certificate) -> std::result::Result<(), rustls::Error> {
     #[rustfmt::skip]
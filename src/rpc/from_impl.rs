@@ -123,6 +123,37 @@ impl From<net::dnet::DirectDisconnected> for JsonValue {
     }
 }
 
+#[cfg(feature = "net")]
+impl From<net::dnet::PeerAuthenticated> for JsonValue {
+    fn from(info: net::dnet::PeerAuthenticated) -> JsonValue {
+        json_map([
+            ("addr", JsonStr(info.addr.to_string())),
+            ("channel_id", JsonNum(info.channel_id.into())),
+            ("node_key", JsonStr(info.node_key.to_string())),
+        ])
+    }
+}
+
+#[cfg(feature = "net")]
+impl From<net::bandwidth::BandwidthStats> for JsonValue {
+    fn from(stats: net::bandwidth::BandwidthStats) -> JsonValue {
+        json_map([
+            ("scope", JsonStr(stats.scope)),
+            ("upload_limit", JsonNum(stats.upload_limit as f64)),
+            ("download_limit", JsonNum(stats.download_limit as f64)),
+            ("bytes_sent", JsonNum(stats.bytes_sent as f64)),
+            ("bytes_recv", JsonNum(stats.bytes_recv as f64)),
+        ])
+    }
+}
+
+#[cfg(feature = "net")]
+impl From<net::dnet::BandwidthUpdate> for JsonValue {
+    fn from(info: net::dnet::BandwidthUpdate) -> JsonValue {
+        json_map([("stats", JsonArray(info.stats.into_iter().map(|s| s.into()).collect()))])
+    }
+}
+
 #[cfg(feature = "net")]
 impl From<net::dnet::DnetEvent> for JsonValue {
     fn from(event: net::dnet::DnetEvent) -> JsonValue {
@@ -166,6 +197,12 @@ impl From<net::dnet::DnetEvent> for JsonValue {
             net::dnet::DnetEvent::DirectPeerDiscovery(info) => {
                 json_map([("event", json_str("direct_peer_discovery")), ("info", info.into())])
             }
+            net::dnet::DnetEvent::PeerAuthenticated(info) => {
+                json_map([("event", json_str("peer_authenticated")), ("info", info.into())])
+            }
+            net::dnet::DnetEvent::BandwidthUpdate(info) => {
+                json_map([("event", json_str("bandwidth_update")), ("info", info.into())])
+            }
         }
     }
 }
@@ -16,13 +16,15 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
+use std::sync::atomic::Ordering::Relaxed;
+
 use async_trait::async_trait;
 
 use super::{
     jsonrpc::{JsonResponse, JsonResult},
     util::*,
 };
-use crate::net;
+use crate::{net, net::hosts::HostColor};
 
 #[async_trait]
 pub trait HandlerP2p: Sync + Send {
@@ -44,6 +46,8 @@ pub trait HandlerP2p: Sync + Send {
                 ("url", JsonStr(channel.display_address().to_string())),
                 ("session", json_str(session)),
                 ("id", JsonNum(channel.info.id.into())),
+                ("bytes_sent", JsonNum(channel.traffic.bytes_sent.load(Relaxed) as f64)),
+                ("bytes_recv", JsonNum(channel.traffic.bytes_recv.load(Relaxed) as f64)),
             ]));
         }
 
@@ -52,10 +56,60 @@ pub trait HandlerP2p: Sync + Send {
             slots.push(JsonNum(channel_id.into()));
         }
 
-        let result =
-            json_map([("channels", JsonArray(channels)), ("outbound_slots", JsonArray(slots))]);
+        let bandwidth =
+            self.p2p().bandwidth().stats().into_iter().map(|stats| stats.into()).collect();
+
+        let result = json_map([
+            ("channels", JsonArray(channels)),
+            ("outbound_slots", JsonArray(slots)),
+            ("bandwidth", JsonArray(bandwidth)),
+        ]);
         JsonResponse::new(result, id).into()
     }
 
+    async fn p2p_get_hosts(&self, id: i64, _params: JsonValue) -> JsonResult {
+        let hosts = self.p2p().hosts();
+
+        let mut records = Vec::new();
+        for (url, record) in hosts.records.fetch_all() {
+            let color = [
+                (HostColor::Gold, "gold"),
+                (HostColor::White, "white"),
+                (HostColor::Grey, "grey"),
+                (HostColor::Dark, "dark"),
+                (HostColor::Black, "black"),
+            ]
+            .into_iter()
+            .find(|(color, _)| hosts.container.contains(*color, &url))
+            .map_or("none", |(_, name)| name);
+
+            let opt_str = |s: Option<String>| s.map_or(JsonValue::Null, JsonStr);
+
+            records.push(json_map([
+                ("url", JsonStr(url.to_string())),
+                ("color", json_str(color)),
+                ("score", JsonNum(record.score())),
+                ("first_seen", JsonNum(record.first_seen as f64)),
+                ("last_seen", JsonNum(record.last_seen as f64)),
+                ("last_connected", JsonNum(record.last_connected as f64)),
+                ("successes", JsonNum(record.successes as f64)),
+                ("failures", JsonNum(record.failures as f64)),
+                ("consecutive_failures", JsonNum(record.consecutive_failures.into())),
+                ("last_failure", opt_str(record.last_failure)),
+                (
+                    "avg_latency_ms",
+                    record.avg_latency_ms.map_or(JsonValue::Null, |ms| JsonNum(ms as f64)),
+                ),
+                ("app_name", opt_str(record.app_name)),
+                ("app_version", opt_str(record.app_version)),
+                ("uptime", JsonNum(record.uptime as f64)),
+                ("disconnects", JsonNum(record.disconnects as f64)),
+                ("transport", opt_str(record.transport)),
+            ]));
+        }
+
+        JsonResponse::new(JsonArray(records), id).into()
+    }
+
     fn p2p(&self) -> net::P2pPtr;
 }